@@ -117,6 +117,7 @@ pub trait ContainerApi: Send + Sync {
         stdout: bool,
         stderr: bool,
         since: i32,
+        until: i32,
         timestamps: bool,
         tail: &str,
     ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send>;
@@ -819,6 +820,7 @@ where
         stdout: bool,
         stderr: bool,
         since: i32,
+        until: i32,
         timestamps: bool,
         tail: &str,
     ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send> {
@@ -831,6 +833,7 @@ where
             .append_pair("stdout", &stdout.to_string())
             .append_pair("stderr", &stderr.to_string())
             .append_pair("since", &since.to_string())
+            .append_pair("until", &until.to_string())
             .append_pair("timestamps", &timestamps.to_string())
             .append_pair("tail", &tail.to_string())
             .finish();
@@ -83,6 +83,9 @@ pub enum ErrorKind {
     #[fail(display = "Invalid or unsupported certificate issuer.")]
     InvalidIssuer,
 
+    #[fail(display = "Invalid log severity {:?}", _0)]
+    InvalidLogSeverity(String),
+
     #[fail(display = "Invalid log tail {:?}", _0)]
     InvalidLogTail(String),
 
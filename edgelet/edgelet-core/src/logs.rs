@@ -6,10 +6,13 @@ use std::io;
 use bytes::{Buf, BufMut, Bytes, BytesMut, IntoBuf};
 use futures::prelude::*;
 use futures::try_ready;
+use regex::Regex;
 use tokio::codec::length_delimited;
 use tokio::codec::FramedRead;
 use tokio::io::AsyncRead;
 
+use crate::module::{LogOptions, LogSeverity};
+
 /// Logs parser
 /// Logs are emitted with a simple header to specify stdout or stderr
 ///
@@ -34,6 +37,36 @@ pub enum LogChunk {
     Unknown(Bytes),
 }
 
+impl LogChunk {
+    pub fn payload(&self) -> &Bytes {
+        match self {
+            LogChunk::Stdin(b)
+            | LogChunk::Stdout(b)
+            | LogChunk::Stderr(b)
+            | LogChunk::Unknown(b) => b,
+        }
+    }
+
+    /// Encodes the chunk as a frame with the header described above.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn into_frame(self) -> Bytes {
+        let (stream_type, payload) = match self {
+            LogChunk::Stdin(b) => (0, b),
+            LogChunk::Stdout(b) => (1, b),
+            LogChunk::Stderr(b) => (2, b),
+            LogChunk::Unknown(b) => (3, b),
+        };
+
+        let mut frame = BytesMut::with_capacity(8 + payload.len());
+        frame.put_u8(stream_type);
+        frame.put_slice(&[0, 0, 0]);
+        // the length of a frame read by LogDecode always fits into the header
+        frame.put_u32_be(payload.len() as u32);
+        frame.put_slice(&payload);
+        frame.freeze()
+    }
+}
+
 pub struct LogDecode<T: AsyncRead> {
     inner: FramedRead<T, length_delimited::LengthDelimitedCodec>,
 }
@@ -169,6 +202,83 @@ where
 {
 }
 
+/// Drops the frames of a log stream which do not match the regex or the
+/// severity of a [`LogOptions`], for runtimes whose container engine cannot
+/// filter logs itself. The remaining frames are passed on unchanged.
+pub struct FilteredLogs<S, C>
+where
+    C: AsRef<[u8]>,
+    S: Stream<Item = C, Error = io::Error>,
+{
+    inner: LogDecode<Chunked<S, C>>,
+    regex: Option<Regex>,
+    severity: Option<LogSeverity>,
+}
+
+impl<S, C> FilteredLogs<S, C>
+where
+    C: AsRef<[u8]>,
+    S: Stream<Item = C, Error = io::Error>,
+{
+    pub fn new(inner: S, options: &LogOptions) -> Self {
+        FilteredLogs {
+            inner: LogDecode::new(Chunked::new(inner)),
+            regex: options.regex().cloned(),
+            severity: options.severity(),
+        }
+    }
+
+    fn is_match(&self, chunk: &LogChunk) -> bool {
+        let line = String::from_utf8_lossy(chunk.payload());
+        let severe_enough = match (self.severity, line_severity(&line)) {
+            (Some(min), Some(severity)) => severity <= min,
+            _ => true,
+        };
+        severe_enough
+            && self
+                .regex
+                .as_ref()
+                .map_or(true, |regex| regex.is_match(&line))
+    }
+}
+
+impl<S, C> Stream for FilteredLogs<S, C>
+where
+    C: AsRef<[u8]>,
+    S: Stream<Item = C, Error = io::Error>,
+{
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            match try_ready!(self.inner.poll()) {
+                Some(chunk) => {
+                    if self.is_match(&chunk) {
+                        return Ok(Async::Ready(Some(chunk.into_frame())));
+                    }
+                }
+                None => return Ok(Async::Ready(None)),
+            }
+        }
+    }
+}
+
+/// Reads the syslog severity prefix of a log line, e.g. `<6>`, which may
+/// follow the timestamp added by the container engine.
+fn line_severity(line: &str) -> Option<LogSeverity> {
+    fn prefix(line: &str) -> Option<LogSeverity> {
+        let line = line.trim_start().as_bytes();
+        if line.len() >= 3 && line[0] == b'<' && line[2] == b'>' {
+            LogSeverity::from_level(line[1].wrapping_sub(b'0'))
+        } else {
+            None
+        }
+    }
+
+    prefix(line).or_else(|| line.splitn(2, ' ').nth(1).and_then(prefix))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -219,4 +329,47 @@ mod tests {
         }
         assert_eq!(b"Roses are red violets are blue", read_buffer);
     }
+
+    fn frame(chunk: LogChunk) -> Vec<u8> {
+        chunk.into_frame().to_vec()
+    }
+
+    #[test]
+    fn filter_by_regex_and_severity() {
+        let chunks = vec![
+            frame(LogChunk::Stdout(Bytes::from(
+                "<6> 2019-01-01 [INF] - Starting module",
+            ))),
+            frame(LogChunk::Stderr(Bytes::from(
+                "<3> 2019-01-01 [ERR] - Could not connect",
+            ))),
+            frame(LogChunk::Stdout(Bytes::from(
+                "2019-01-01T00:00:00.000Z <4> 2019-01-01 [WRN] - Retrying connect",
+            ))),
+            frame(LogChunk::Stdout(Bytes::from("unprefixed connect"))),
+        ];
+        let stream = iter_ok::<Vec<Vec<u8>>, io::Error>(chunks.clone());
+        let options = LogOptions::new()
+            .with_regex(Some(Regex::new("connect").unwrap()))
+            .with_severity(Some(LogSeverity::Warning));
+
+        let filtered: Vec<Bytes> = FilteredLogs::new(stream, &options)
+            .collect()
+            .wait()
+            .unwrap();
+        let expected: Vec<Bytes> = chunks[1..].iter().map(|c| Bytes::from(&c[..])).collect();
+
+        assert_eq!(expected, filtered);
+    }
+
+    #[test]
+    fn line_severity_skips_timestamp() {
+        assert_eq!(Some(LogSeverity::Error), line_severity("<3> failed"));
+        assert_eq!(
+            Some(LogSeverity::Debug),
+            line_severity("2019-09-27T16:00:00.000000000Z <7> details")
+        );
+        assert_eq!(None, line_severity("plain text"));
+        assert_eq!(None, line_severity("<9> out of range"));
+    }
 }
@@ -11,6 +11,7 @@ use std::time::Duration;
 use chrono::prelude::*;
 use failure::{Fail, ResultExt};
 use futures::{Future, Stream};
+use regex::Regex;
 use serde_json;
 
 use edgelet_utils::{ensure_not_empty_with_context, serialize_ordered};
@@ -282,11 +283,80 @@ impl ToString for LogTail {
     }
 }
 
-#[derive(Debug, Default)]
+/// Syslog severity of a log line, from the most to the least severe.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum LogSeverity {
+    Emergency,
+    Alert,
+    Critical,
+    Error,
+    Warning,
+    Notice,
+    Informational,
+    Debug,
+}
+
+impl LogSeverity {
+    pub fn from_level(level: u8) -> Option<Self> {
+        let severity = match level {
+            0 => LogSeverity::Emergency,
+            1 => LogSeverity::Alert,
+            2 => LogSeverity::Critical,
+            3 => LogSeverity::Error,
+            4 => LogSeverity::Warning,
+            5 => LogSeverity::Notice,
+            6 => LogSeverity::Informational,
+            7 => LogSeverity::Debug,
+            _ => return None,
+        };
+        Some(severity)
+    }
+
+    pub fn level(self) -> u8 {
+        self as u8
+    }
+}
+
+impl FromStr for LogSeverity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let severity = match s.to_lowercase().as_str() {
+            "emerg" | "emergency" => LogSeverity::Emergency,
+            "alert" => LogSeverity::Alert,
+            "crit" | "critical" => LogSeverity::Critical,
+            "err" | "error" => LogSeverity::Error,
+            "warn" | "warning" => LogSeverity::Warning,
+            "notice" => LogSeverity::Notice,
+            "info" | "informational" => LogSeverity::Informational,
+            "debug" => LogSeverity::Debug,
+            level => level
+                .parse::<u8>()
+                .ok()
+                .and_then(LogSeverity::from_level)
+                .ok_or_else(|| ErrorKind::InvalidLogSeverity(s.to_string()))?,
+        };
+        Ok(severity)
+    }
+}
+
+impl ToString for LogSeverity {
+    fn to_string(&self) -> String {
+        self.level().to_string()
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct LogOptions {
     follow: bool,
     tail: LogTail,
     since: i32,
+    until: Option<i32>,
+    timestamps: bool,
+    stdout: bool,
+    stderr: bool,
+    regex: Option<Regex>,
+    severity: Option<LogSeverity>,
 }
 
 impl LogOptions {
@@ -295,6 +365,12 @@ impl LogOptions {
             follow: false,
             tail: LogTail::All,
             since: 0,
+            until: None,
+            timestamps: false,
+            stdout: true,
+            stderr: true,
+            regex: None,
+            severity: None,
         }
     }
 
@@ -313,6 +389,39 @@ impl LogOptions {
         self
     }
 
+    pub fn with_until(mut self, until: Option<i32>) -> Self {
+        self.until = until;
+        self
+    }
+
+    pub fn with_timestamps(mut self, timestamps: bool) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
+    pub fn with_stdout(mut self, stdout: bool) -> Self {
+        self.stdout = stdout;
+        self
+    }
+
+    pub fn with_stderr(mut self, stderr: bool) -> Self {
+        self.stderr = stderr;
+        self
+    }
+
+    /// Only returns log lines matching `regex`.
+    pub fn with_regex(mut self, regex: Option<Regex>) -> Self {
+        self.regex = regex;
+        self
+    }
+
+    /// Only returns log lines at least as severe as `severity`. Lines without
+    /// a syslog severity prefix such as `<6>` are always returned.
+    pub fn with_severity(mut self, severity: Option<LogSeverity>) -> Self {
+        self.severity = severity;
+        self
+    }
+
     pub fn follow(&self) -> bool {
         self.follow
     }
@@ -324,6 +433,42 @@ impl LogOptions {
     pub fn since(&self) -> i32 {
         self.since
     }
+
+    pub fn until(&self) -> Option<i32> {
+        self.until
+    }
+
+    pub fn timestamps(&self) -> bool {
+        self.timestamps
+    }
+
+    pub fn stdout(&self) -> bool {
+        self.stdout
+    }
+
+    pub fn stderr(&self) -> bool {
+        self.stderr
+    }
+
+    pub fn regex(&self) -> Option<&Regex> {
+        self.regex.as_ref()
+    }
+
+    pub fn severity(&self) -> Option<LogSeverity> {
+        self.severity
+    }
+
+    /// Whether log lines have to be filtered by the runtime, since the
+    /// container engine cannot filter them.
+    pub fn is_filtered(&self) -> bool {
+        self.regex.is_some() || self.severity.is_some()
+    }
+}
+
+impl Default for LogOptions {
+    fn default() -> Self {
+        LogOptions::new()
+    }
 }
 
 pub trait Module {
@@ -410,6 +555,34 @@ impl SystemResources {
             docker_stats,
         }
     }
+
+    pub fn host_uptime(&self) -> u64 {
+        self.host_uptime
+    }
+
+    pub fn process_uptime(&self) -> u64 {
+        self.process_uptime
+    }
+
+    pub fn used_cpu(&self) -> f64 {
+        self.used_cpu
+    }
+
+    pub fn used_ram(&self) -> u64 {
+        self.used_ram
+    }
+
+    pub fn total_ram(&self) -> u64 {
+        self.total_ram
+    }
+
+    pub fn disks(&self) -> &[DiskInfo] {
+        &self.disks
+    }
+
+    pub fn docker_stats(&self) -> &str {
+        &self.docker_stats
+    }
 }
 
 #[derive(Debug, serde_derive::Serialize)]
@@ -437,6 +610,26 @@ impl DiskInfo {
             file_type,
         }
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn available_space(&self) -> u64 {
+        self.available_space
+    }
+
+    pub fn total_space(&self) -> u64 {
+        self.total_space
+    }
+
+    pub fn file_system(&self) -> &str {
+        &self.file_system
+    }
+
+    pub fn file_type(&self) -> &str {
+        &self.file_type
+    }
 }
 
 #[derive(Debug)]
@@ -761,4 +954,31 @@ mod tests {
             current_value_architecture_type
         );
     }
+
+    #[test]
+    fn log_severity_parses_names_and_levels() {
+        assert_eq!(
+            LogSeverity::Warning,
+            LogSeverity::from_str("warning").unwrap()
+        );
+        assert_eq!(LogSeverity::Error, LogSeverity::from_str("ERR").unwrap());
+        assert_eq!(
+            LogSeverity::Informational,
+            LogSeverity::from_str("6").unwrap()
+        );
+        assert_eq!("3", LogSeverity::Error.to_string());
+        assert!(LogSeverity::Error < LogSeverity::Warning);
+    }
+
+    #[test]
+    fn log_severity_rejects_unknown_levels() {
+        for severity in &["8", "-1", "verbose", ""] {
+            let err = LogSeverity::from_str(severity).unwrap_err();
+            if let ErrorKind::InvalidLogSeverity(s) = err.kind() {
+                assert_eq!(severity, s);
+            } else {
+                panic!("Expected `InvalidLogSeverity` but got {:?}", err);
+            }
+        }
+    }
 }
@@ -1,6 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::collections::HashMap;
+use std::io;
 use std::ops::Deref;
 use std::time::Duration;
 
@@ -19,9 +20,10 @@ use docker::apis::client::APIClient;
 use docker::apis::configuration::Configuration;
 use docker::models::{ContainerCreateBody, InlineResponse200, Ipam, NetworkConfig};
 use edgelet_core::{
-    AuthId, Authenticator, GetTrustBundle, Ipam as CoreIpam, LogOptions, MakeModuleRuntime,
-    MobyNetwork, Module, ModuleId, ModuleRegistry, ModuleRuntime, ModuleRuntimeState, ModuleSpec,
-    RegistryOperation, RuntimeOperation, SystemInfo as CoreSystemInfo, SystemResources, UrlExt,
+    AuthId, Authenticator, FilteredLogs, GetTrustBundle, Ipam as CoreIpam, LogOptions,
+    MakeModuleRuntime, MobyNetwork, Module, ModuleId, ModuleRegistry, ModuleRuntime,
+    ModuleRuntimeState, ModuleSpec, RegistryOperation, RuntimeOperation,
+    SystemInfo as CoreSystemInfo, SystemResources, UrlExt,
 };
 use edgelet_http::{Pid, UrlConnector};
 use edgelet_utils::{ensure_not_empty_with_context, log_failure};
@@ -792,21 +794,32 @@ impl ModuleRuntime for DockerModuleRuntime {
         let id = id.to_string();
 
         let tail = &options.tail().to_string();
+        // docker cannot filter log lines, so they are filtered while streamed
+        let filter = if options.is_filtered() {
+            Some(options.clone())
+        } else {
+            None
+        };
         let result = self
             .client
             .container_api()
             .container_logs(
                 &id,
                 options.follow(),
-                true,
-                true,
+                options.stdout(),
+                options.stderr(),
                 options.since(),
-                false,
+                options.until().unwrap_or(0),
+                options.timestamps(),
                 tail,
             )
             .then(|result| match result {
                 Ok(logs) => {
                     info!("Successfully got logs for module {}", id);
+                    let logs = match filter {
+                        Some(options) => filter_logs(logs, &options),
+                        None => logs,
+                    };
                     Ok(Logs(id, logs))
                 }
                 Err(err) => {
@@ -870,6 +883,11 @@ fn init_client(docker_url: &Url) -> Result<DockerClient<UrlConnector>> {
     Ok(DockerClient::new(APIClient::new(configuration)))
 }
 
+fn filter_logs(logs: Body, options: &LogOptions) -> Body {
+    let logs = logs.map_err(|err| io::Error::new(io::ErrorKind::Other, err));
+    Body::wrap_stream(FilteredLogs::new(logs, options))
+}
+
 #[derive(Debug)]
 pub struct Logs(String, Body);
 
@@ -26,7 +26,7 @@ use docker::models::{
 };
 
 use edgelet_core::{
-    GetTrustBundle, ImagePullPolicy, LogOptions, LogTail, MakeModuleRuntime, Module,
+    GetTrustBundle, ImagePullPolicy, LogOptions, LogSeverity, LogTail, MakeModuleRuntime, Module,
     ModuleRegistry, ModuleRuntime, ModuleSpec, RegistryOperation, RuntimeOperation,
 };
 use edgelet_docker::{DockerConfig, DockerModuleRuntime, Settings};
@@ -1233,6 +1233,76 @@ fn container_logs_succeeds() {
     runtime.block_on(assert).unwrap();
 }
 
+fn severity_logs_body() -> Vec<u8> {
+    let mut body = vec![0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x11];
+    body.extend_from_slice(b"<6> Roses are red");
+    body.extend_from_slice(&[0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x14]);
+    body.extend_from_slice(b"<3> violets are blue");
+    body
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn container_logs_window_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::GET);
+    assert_eq!(req.uri().path(), "/containers/mod1/logs");
+
+    let query_map: HashMap<String, String> = parse_query(req.uri().query().unwrap().as_bytes())
+        .into_owned()
+        .collect();
+    assert_eq!("false", query_map["stdout"]);
+    assert_eq!("true", query_map["stderr"]);
+    assert_eq!("100000", query_map["since"]);
+    assert_eq!("200000", query_map["until"]);
+    assert_eq!("true", query_map["timestamps"]);
+
+    Box::new(future::ok(Response::new(severity_logs_body().into())))
+}
+
+#[test]
+fn container_logs_with_window_and_severity_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/mod1/logs" => container_logs_window_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| {
+            let options = LogOptions::new()
+                .with_since(100_000)
+                .with_until(Some(200_000))
+                .with_timestamps(true)
+                .with_stdout(false)
+                .with_severity(Some(LogSeverity::Warning));
+
+            runtime.logs("mod1", &options)
+        });
+
+    // only the error line passes the severity filter
+    let expected_body = &severity_logs_body()[25..];
+
+    let assert = task.and_then(Stream::concat2).and_then(|b| {
+        assert_eq!(expected_body, b.as_ref());
+        Ok(())
+    });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(assert).unwrap();
+}
+
 #[test]
 fn image_remove_with_white_space_name_fails() {
     let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
@@ -1,5 +1,6 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::convert::TryFrom;
 use std::fmt;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -13,6 +14,8 @@ use hyper::{Body, Chunk as HyperChunk, Client};
 use management::apis::client::APIClient;
 use management::apis::configuration::Configuration;
 use management::models::{Config, ModuleDetails as HttpModuleDetails};
+use regex::Regex;
+use serde::{Serialize, Serializer};
 use serde_json;
 use url::Url;
 
@@ -80,6 +83,15 @@ impl fmt::Display for ModuleConfig {
     }
 }
 
+impl Serialize for ModuleConfig {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.1.serialize(serializer)
+    }
+}
+
 impl Module for ModuleDetails {
     type Config = ModuleConfig;
     type Error = Error;
@@ -129,6 +141,10 @@ fn runtime_status(details: &HttpModuleDetails) -> Result<ModuleRuntimeState, Err
     Ok(state)
 }
 
+fn to_unsigned(value: i64) -> u64 {
+    u64::try_from(value).unwrap_or_default()
+}
+
 impl ModuleRegistry for ModuleClient {
     type Error = Error;
     type PullFuture = FutureResult<(), Self::Error>;
@@ -172,8 +188,26 @@ impl ModuleRuntime for ModuleClient {
         unimplemented!()
     }
 
-    fn get(&self, _id: &str) -> Self::GetFuture {
-        unimplemented!()
+    fn get(&self, id: &str) -> Self::GetFuture {
+        let id = id.to_string();
+
+        let module = self
+            .client
+            .module_api()
+            .get_module(&API_VERSION.to_string(), &id)
+            .then(|result| match result {
+                Ok(details) => {
+                    let state = runtime_status(&details)?;
+                    let type_ = details.type_().clone();
+                    let config = details.config().clone();
+                    Ok((ModuleDetails(details, ModuleConfig(type_, config)), state))
+                }
+                Err(err) => Err(Error::from_mgmt_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(id)),
+                )),
+            });
+        Box::new(module)
     }
 
     fn start(&self, id: &str) -> Self::StartFuture {
@@ -250,11 +284,56 @@ impl ModuleRuntime for ModuleClient {
     }
 
     fn system_info(&self) -> Self::SystemInfoFuture {
-        unimplemented!()
+        let system_info = self
+            .client
+            .system_information_api()
+            .get_system_info(&API_VERSION.to_string())
+            .map(|info| CoreSystemInfo::new(info.os_type().clone(), info.architecture().clone()))
+            .map_err(|err| {
+                Error::from_mgmt_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::SystemInfo),
+                )
+            });
+        Box::new(system_info)
     }
 
     fn system_resources(&self) -> Self::SystemResourcesFuture {
-        unimplemented!()
+        let system_resources = self
+            .client
+            .system_information_api()
+            .get_system_resources(&API_VERSION.to_string())
+            .map(|resources| {
+                let disks = resources
+                    .disks()
+                    .iter()
+                    .map(|disk| {
+                        DiskInfo::new(
+                            disk.name().clone(),
+                            to_unsigned(disk.available_space()),
+                            to_unsigned(disk.total_space()),
+                            disk.file_system().clone(),
+                            disk.file_type().clone(),
+                        )
+                    })
+                    .collect();
+                SystemResources::new(
+                    to_unsigned(resources.host_uptime()),
+                    to_unsigned(resources.process_uptime()),
+                    resources.used_cpu(),
+                    to_unsigned(resources.used_ram()),
+                    to_unsigned(resources.total_ram()),
+                    disks,
+                    resources.docker_stats().clone(),
+                )
+            })
+            .map_err(|err| {
+                Error::from_mgmt_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::SystemResources),
+                )
+            });
+        Box::new(system_resources)
     }
 
     fn list(&self) -> Self::ListFuture {
@@ -312,6 +391,7 @@ impl ModuleRuntime for ModuleClient {
         let id = id.to_string();
 
         let tail = &options.tail().to_string();
+        let severity = options.severity().map(|severity| severity.to_string());
         let result = self
             .client
             .module_api()
@@ -321,6 +401,12 @@ impl ModuleRuntime for ModuleClient {
                 options.follow(),
                 tail,
                 options.since(),
+                options.until(),
+                options.timestamps(),
+                options.stdout(),
+                options.stderr(),
+                options.regex().map(Regex::as_str),
+                severity.as_ref().map(AsRef::as_ref),
             )
             .then(|logs| match logs {
                 Ok(logs) => Ok(Logs(id, logs)),
@@ -1,20 +1,205 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use futures::{future, Future};
-use hyper::{Body, Request, Response};
+use failure::{Fail, ResultExt};
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde::Serialize;
+use serde_json;
 
+use edgelet_core::{Module, ModuleRuntime, RuntimeOperation};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 
-pub struct GetModule;
+use super::core_to_details;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
 
-impl Handler<Parameters> for GetModule {
+pub struct GetModule<M> {
+    runtime: M,
+}
+
+impl<M> GetModule<M> {
+    pub fn new(runtime: M) -> Self {
+        GetModule { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for GetModule<M>
+where
+    M: 'static + ModuleRuntime + Send,
+    <M::Module as Module>::Config: Serialize,
+{
     fn handle(
         &self,
         _req: Request<Body>,
-        _params: Parameters,
+        params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
-        let response = Response::new(Body::default());
-        Box::new(future::ok(response))
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                self.runtime.get(&name).then(|result| match result {
+                    Ok((module, state)) => Ok((name, module, state)),
+                    Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
+                        RuntimeOperation::GetModule(name),
+                    )))),
+                })
+            })
+            .into_future()
+            .flatten()
+            .and_then(|(name, module, state)| -> Result<_, Error> {
+                let details = core_to_details(
+                    &module,
+                    &state,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(name.clone())),
+                )?;
+                let b = serde_json::to_string(&details).with_context(|_| {
+                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(name.clone()))
+                })?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                    .body(b.into())
+                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(
+                        name,
+                    )))?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::prelude::*;
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState, ModuleStatus};
+    use edgelet_http::route::Parameters;
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+    use futures::Stream;
+    use management::models::{ErrorResponse, ModuleDetails};
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    #[test]
+    fn success() {
+        // arrange
+        let state = ModuleRuntimeState::default()
+            .with_status(ModuleStatus::Running)
+            .with_started_at(Some(Utc.ymd(2018, 4, 13).and_hms_milli(14, 20, 0, 1)));
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> =
+            TestModule::new("test-module".to_string(), config, Ok(state));
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let handler = GetModule::new(runtime);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "test-module".to_string())]);
+        let request = Request::get("http://localhost/modules/test-module")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let module: ModuleDetails = serde_json::from_slice(&b).unwrap();
+                assert_eq!("test-module", module.name());
+                assert_eq!("test", module.type_());
+
+                let config: TestConfig = serde_json::from_value(
+                    serde_json::to_value(module.config().settings()).unwrap(),
+                )
+                .unwrap();
+                assert_eq!("microsoft/test-image", config.image());
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn get_bad_params() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let handler = GetModule::new(runtime);
+        let request = Request::get("http://localhost/modules/test-module")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[test]
+    fn get_failed() {
+        // arrange
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Err(Error::General));
+        let handler = GetModule::new(runtime);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "test-module".to_string())]);
+        let request = Request::get("http://localhost/modules/test-module")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                assert_eq!(
+                    "Could not get module test-module\n\tcaused by: General error",
+                    error.message()
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
     }
 }
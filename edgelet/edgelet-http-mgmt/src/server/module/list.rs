@@ -8,11 +8,12 @@ use log::debug;
 use serde::Serialize;
 use serde_json;
 
-use edgelet_core::{Module, ModuleRuntime, ModuleRuntimeState, RuntimeOperation};
+use edgelet_core::{Module, ModuleRuntime, RuntimeOperation};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 use management::models::*;
 
+use super::core_to_details;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
@@ -46,7 +47,13 @@ where
                 let details: Result<_, Error> = result
                     .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?
                     .into_iter()
-                    .map(|(module, state)| core_to_details(&module, &state))
+                    .map(|(module, state)| {
+                        core_to_details(
+                            &module,
+                            &state,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::ListModules),
+                        )
+                    })
                     .collect();
                 let body = ModuleList::new(details?);
                 let b = serde_json::to_string(&body)
@@ -65,37 +72,6 @@ where
     }
 }
 
-fn core_to_details<M>(module: &M, state: &ModuleRuntimeState) -> Result<ModuleDetails, Error>
-where
-    M: 'static + Module + Send,
-    M::Config: Serialize,
-{
-    let settings = serde_json::to_value(module.config())
-        .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
-    let config = Config::new(settings).with_env(vec![]);
-    let mut runtime_status = RuntimeStatus::new(state.status().to_string());
-    if let Some(description) = state.status_description() {
-        runtime_status.set_description(description.to_string());
-    }
-    let mut status = Status::new(runtime_status);
-    if let Some(started_at) = state.started_at() {
-        status.set_start_time(started_at.to_rfc3339());
-    }
-    if let Some(code) = state.exit_code() {
-        if let Some(finished_at) = state.finished_at() {
-            status.set_exit_status(ExitStatus::new(finished_at.to_rfc3339(), code.to_string()));
-        }
-    }
-
-    Ok(ModuleDetails::new(
-        "id".to_string(),
-        module.name().to_string(),
-        module.type_().to_string(),
-        config,
-        status,
-    ))
-}
-
 #[cfg(test)]
 mod tests {
     use chrono::prelude::*;
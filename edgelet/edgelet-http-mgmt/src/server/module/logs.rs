@@ -3,9 +3,12 @@
 use failure::ResultExt;
 use futures::{future, Future, IntoFuture};
 use hyper::{Body, Request, Response, StatusCode};
+use regex::Regex;
 use url::form_urlencoded;
 
-use edgelet_core::{parse_since, LogOptions, LogTail, ModuleRuntime, RuntimeOperation};
+use edgelet_core::{
+    parse_since, LogOptions, LogSeverity, LogTail, ModuleRuntime, RuntimeOperation,
+};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 
@@ -84,10 +87,49 @@ fn parse_options(query: &str) -> Result<LogOptions, Error> {
         .find(|&(ref key, _)| key == "since")
         .map_or_else(|| Ok(0), |(_, val)| parse_since(val))
         .context(ErrorKind::MalformedRequestParameter("since"))?;
+    let until = parse
+        .iter()
+        .find(|&(ref key, _)| key == "until")
+        .map(|(_, val)| parse_since(val))
+        .transpose()
+        .context(ErrorKind::MalformedRequestParameter("until"))?;
+    let timestamps = parse
+        .iter()
+        .find(|&(ref key, _)| key == "timestamps")
+        .map_or_else(|| Ok(false), |(_, val)| val.parse::<bool>())
+        .context(ErrorKind::MalformedRequestParameter("timestamps"))?;
+    let stdout = parse
+        .iter()
+        .find(|&(ref key, _)| key == "stdout")
+        .map_or_else(|| Ok(true), |(_, val)| val.parse::<bool>())
+        .context(ErrorKind::MalformedRequestParameter("stdout"))?;
+    let stderr = parse
+        .iter()
+        .find(|&(ref key, _)| key == "stderr")
+        .map_or_else(|| Ok(true), |(_, val)| val.parse::<bool>())
+        .context(ErrorKind::MalformedRequestParameter("stderr"))?;
+    let regex = parse
+        .iter()
+        .find(|&(ref key, _)| key == "regex")
+        .map(|(_, val)| Regex::new(val))
+        .transpose()
+        .context(ErrorKind::MalformedRequestParameter("regex"))?;
+    let severity = parse
+        .iter()
+        .find(|&(ref key, _)| key == "severity")
+        .map(|(_, val)| val.parse::<LogSeverity>())
+        .transpose()
+        .context(ErrorKind::MalformedRequestParameter("severity"))?;
     let options = LogOptions::new()
         .with_follow(follow)
         .with_tail(tail)
-        .with_since(since);
+        .with_since(since)
+        .with_until(until)
+        .with_timestamps(timestamps)
+        .with_stdout(stdout)
+        .with_stderr(stderr)
+        .with_regex(regex)
+        .with_severity(severity);
     Ok(options)
 }
 
@@ -113,6 +155,19 @@ mod tests {
         assert_eq!(1_551_885_923, options.since());
     }
 
+    #[test]
+    fn correct_logoptions_window_and_filter() {
+        let query = "since=1551885923&until=1551889523&timestamps=true&stdout=false&regex=conn%5Ba-z%5D%2B&severity=warning";
+        let options = parse_options(&query).unwrap();
+        assert_eq!(1_551_885_923, options.since());
+        assert_eq!(Some(1_551_889_523), options.until());
+        assert_eq!(true, options.timestamps());
+        assert_eq!(false, options.stdout());
+        assert_eq!(true, options.stderr());
+        assert_eq!("conn[a-z]+", options.regex().unwrap().as_str());
+        assert_eq!(Some(LogSeverity::Warning), options.severity());
+    }
+
     #[test]
     fn logoption_defaults() {
         let query = "";
@@ -120,6 +175,12 @@ mod tests {
         assert_eq!(LogTail::default(), *options.tail());
         assert_eq!(false, options.follow());
         assert_eq!(0, options.since());
+        assert_eq!(None, options.until());
+        assert_eq!(false, options.timestamps());
+        assert_eq!(true, options.stdout());
+        assert_eq!(true, options.stderr());
+        assert!(options.regex().is_none());
+        assert_eq!(None, options.severity());
     }
 
     #[test]
@@ -155,6 +216,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn logoption_until_error() {
+        let query = "since=15&until=15abc";
+        let options = parse_options(&query);
+        assert!(options.is_err());
+        assert_eq!(
+            "The request parameter `until` is malformed",
+            options.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn logoption_regex_error() {
+        let query = "regex=%5Bunclosed";
+        let options = parse_options(&query);
+        assert!(options.is_err());
+        assert_eq!(
+            "The request parameter `regex` is malformed",
+            options.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn logoption_severity_error() {
+        let query = "severity=verbose";
+        let options = parse_options(&query);
+        assert!(options.is_err());
+        assert_eq!(
+            "The request parameter `severity` is malformed",
+            options.err().unwrap().to_string()
+        );
+    }
+
     #[test]
     fn test_success() {
         let state = ModuleRuntimeState::default()
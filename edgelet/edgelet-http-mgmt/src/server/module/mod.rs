@@ -8,7 +8,8 @@ use serde::Serialize;
 use serde_json;
 
 use edgelet_core::{
-    ImagePullPolicy, Module, ModuleRuntime, ModuleSpec as CoreModuleSpec, ModuleStatus,
+    ImagePullPolicy, Module, ModuleRuntime, ModuleRuntimeState, ModuleSpec as CoreModuleSpec,
+    ModuleStatus,
 };
 use management::models::*;
 
@@ -93,6 +94,43 @@ fn spec_to_details(spec: &ModuleSpec, module_status: ModuleStatus) -> ModuleDeta
     ModuleDetails::new(id, name, type_, config, status)
 }
 
+fn core_to_details<M>(
+    module: &M,
+    state: &ModuleRuntimeState,
+    context: ErrorKind,
+) -> Result<ModuleDetails, Error>
+where
+    M: 'static + Module + Send,
+    M::Config: Serialize,
+{
+    let settings = match serde_json::to_value(module.config()) {
+        Ok(settings) => settings,
+        Err(err) => return Err(Error::from(err.context(context))),
+    };
+    let config = Config::new(settings).with_env(vec![]);
+    let mut runtime_status = RuntimeStatus::new(state.status().to_string());
+    if let Some(description) = state.status_description() {
+        runtime_status.set_description(description.to_string());
+    }
+    let mut status = Status::new(runtime_status);
+    if let Some(started_at) = state.started_at() {
+        status.set_start_time(started_at.to_rfc3339());
+    }
+    if let Some(code) = state.exit_code() {
+        if let Some(finished_at) = state.finished_at() {
+            status.set_exit_status(ExitStatus::new(finished_at.to_rfc3339(), code.to_string()));
+        }
+    }
+
+    Ok(ModuleDetails::new(
+        "id".to_string(),
+        module.name().to_string(),
+        module.type_().to_string(),
+        config,
+        status,
+    ))
+}
+
 #[cfg(test)]
 pub mod tests {
     use failure::Fail;
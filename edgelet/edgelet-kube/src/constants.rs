@@ -6,6 +6,8 @@ pub const EDGE_MODULE_LABEL: &str = "net.azure-devices.edge.module";
 
 pub const EDGE_ORIGINAL_MODULEID: &str = "net.azure-devices.edge.original-moduleid";
 
+pub const EDGE_RESTARTED_AT: &str = "net.azure-devices.edge.restarted-at";
+
 pub const EDGE_DEVICE_LABEL: &str = "net.azure-devices.edge.deviceid";
 
 pub const EDGE_HUBNAME_LABEL: &str = "net.azure-devices.edge.hub";
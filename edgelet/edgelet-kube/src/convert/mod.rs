@@ -8,9 +8,10 @@ mod to_docker;
 mod to_k8s;
 
 pub use named_secret::NamedSecret;
-pub use to_docker::pod_to_module;
+pub use to_docker::{pod_to_module, pod_to_state};
 pub use to_k8s::{
-    spec_to_deployment, spec_to_role_binding, spec_to_service_account, trust_bundle_to_config_map,
+    spec_to_deployment, spec_to_persistent_volume_claims, spec_to_role_binding, spec_to_service,
+    spec_to_service_account, trust_bundle_to_config_map,
 };
 
 pub fn sanitize_dns_value(name: &str) -> Result<String> {
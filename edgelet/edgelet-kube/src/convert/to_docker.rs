@@ -5,6 +5,7 @@ use k8s_openapi::api::core::v1 as api_core;
 use log::debug;
 
 use docker::models::ContainerCreateBody;
+use edgelet_core::{ModuleRuntimeState, ModuleStatus};
 use edgelet_docker::DockerConfig;
 
 use crate::constants::*;
@@ -63,6 +64,79 @@ pub fn pod_to_module(pod: &api_core::Pod) -> Option<Result<KubeModule>> {
         })
 }
 
+/// Reads the state of the module container of a pod created by IoT Edge, falling back
+/// to the phase of the pod while the container has not been created yet.
+pub fn pod_to_state(pod: &api_core::Pod) -> ModuleRuntimeState {
+    let status = pod.status.as_ref();
+    let container_status = pod
+        .metadata
+        .as_ref()
+        .and_then(|meta| meta.labels.as_ref())
+        .and_then(|labels| labels.get(EDGE_MODULE_LABEL))
+        .and_then(|module| {
+            status
+                .and_then(|status| status.container_statuses.as_ref())
+                .and_then(|statuses| statuses.iter().find(|status| status.name == *module))
+        });
+
+    if let Some(container_status) = container_status {
+        container_status_to_state(container_status)
+    } else {
+        let module_status = match status.and_then(|status| status.phase.as_deref()) {
+            Some("Succeeded") => ModuleStatus::Stopped,
+            Some("Failed") => ModuleStatus::Failed,
+            _ => ModuleStatus::Unknown,
+        };
+        ModuleRuntimeState::default()
+            .with_status(module_status)
+            .with_status_description(status.and_then(|status| status.message.clone()))
+    }
+}
+
+fn container_status_to_state(container_status: &api_core::ContainerStatus) -> ModuleRuntimeState {
+    let state =
+        ModuleRuntimeState::default().with_image_id(Some(container_status.image_id.clone()));
+    let current = container_status.state.as_ref();
+
+    if let Some(running) = current.and_then(|current| current.running.as_ref()) {
+        state
+            .with_status(ModuleStatus::Running)
+            .with_started_at(running.started_at.as_ref().map(|time| time.0))
+    } else if let Some(terminated) = current.and_then(|current| current.terminated.as_ref()) {
+        let module_status = if terminated.exit_code == 0 {
+            ModuleStatus::Stopped
+        } else {
+            ModuleStatus::Failed
+        };
+        state
+            .with_status(module_status)
+            .with_exit_code(Some(terminated.exit_code.into()))
+            .with_status_description(
+                terminated
+                    .message
+                    .clone()
+                    .or_else(|| terminated.reason.clone()),
+            )
+            .with_started_at(terminated.started_at.as_ref().map(|time| time.0))
+            .with_finished_at(terminated.finished_at.as_ref().map(|time| time.0))
+    } else if let Some(waiting) = current.and_then(|current| current.waiting.as_ref()) {
+        // A container waits both to be created and to be restarted after failing.
+        let module_status = match waiting.reason.as_deref() {
+            Some("CrashLoopBackOff")
+            | Some("CreateContainerConfigError")
+            | Some("ErrImagePull")
+            | Some("ImagePullBackOff")
+            | Some("InvalidImageName") => ModuleStatus::Failed,
+            _ => ModuleStatus::Unknown,
+        };
+        state
+            .with_status(module_status)
+            .with_status_description(waiting.message.clone().or_else(|| waiting.reason.clone()))
+    } else {
+        state.with_status(ModuleStatus::Unknown)
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -240,4 +314,141 @@ mod tests {
         assert!(result.is_some());
         assert!(result.unwrap().is_err());
     }
+
+    const POD_RUNNING: &str = r###"
+    {
+        "kind": "Pod",
+        "metadata" :
+        {
+            "name" : "edgehub",
+            "labels" : {
+                "net.azure-devices.edge.module":"edgehub"
+            }
+        },
+        "status" :
+        {
+            "phase": "Running",
+            "containerStatuses" : [
+                {
+                    "name": "proxy",
+                    "image": "proxy:latest",
+                    "imageID": "docker-pullable://proxy@sha256:01",
+                    "ready": true,
+                    "restartCount": 0,
+                    "state": { "terminated": { "exitCode": 1 } }
+                },
+                {
+                    "name": "edgehub",
+                    "image": "correct_image",
+                    "imageID": "docker-pullable://correct_image@sha256:02",
+                    "ready": true,
+                    "restartCount": 0,
+                    "state": { "running": { "startedAt": "2019-11-20T22:30:37Z" } }
+                }
+            ]
+        }
+    }
+    "###;
+    #[test]
+    fn pod_running_state() {
+        let pod: api_core::Pod = serde_json::from_str(POD_RUNNING).unwrap();
+        let state = pod_to_state(&pod);
+        assert_eq!(state.status(), &ModuleStatus::Running);
+        assert_eq!(
+            state.image_id(),
+            Some("docker-pullable://correct_image@sha256:02")
+        );
+        assert_eq!(
+            state.started_at().map(ToString::to_string),
+            Some("2019-11-20 22:30:37 UTC".to_string())
+        );
+    }
+
+    const POD_CRASHING: &str = r###"
+    {
+        "kind": "Pod",
+        "metadata" :
+        {
+            "name" : "edgehub",
+            "labels" : {
+                "net.azure-devices.edge.module":"edgehub"
+            }
+        },
+        "status" :
+        {
+            "phase": "Running",
+            "containerStatuses" : [
+                {
+                    "name": "edgehub",
+                    "image": "correct_image",
+                    "imageID": "docker-pullable://correct_image@sha256:02",
+                    "ready": false,
+                    "restartCount": 3,
+                    "state": { "waiting": { "reason": "CrashLoopBackOff" } }
+                }
+            ]
+        }
+    }
+    "###;
+    #[test]
+    fn pod_crashing_state() {
+        let pod: api_core::Pod = serde_json::from_str(POD_CRASHING).unwrap();
+        let state = pod_to_state(&pod);
+        assert_eq!(state.status(), &ModuleStatus::Failed);
+        assert_eq!(state.status_description(), Some("CrashLoopBackOff"));
+    }
+
+    const POD_TERMINATED: &str = r###"
+    {
+        "kind": "Pod",
+        "metadata" :
+        {
+            "name" : "edgehub",
+            "labels" : {
+                "net.azure-devices.edge.module":"edgehub"
+            }
+        },
+        "status" :
+        {
+            "phase": "Succeeded",
+            "containerStatuses" : [
+                {
+                    "name": "edgehub",
+                    "image": "correct_image",
+                    "imageID": "docker-pullable://correct_image@sha256:02",
+                    "ready": false,
+                    "restartCount": 0,
+                    "state": {
+                        "terminated": {
+                            "exitCode": 0,
+                            "reason": "Completed",
+                            "startedAt": "2019-11-20T22:30:37Z",
+                            "finishedAt": "2019-11-20T22:40:37Z"
+                        }
+                    }
+                }
+            ]
+        }
+    }
+    "###;
+    #[test]
+    fn pod_terminated_state() {
+        let pod: api_core::Pod = serde_json::from_str(POD_TERMINATED).unwrap();
+        let state = pod_to_state(&pod);
+        assert_eq!(state.status(), &ModuleStatus::Stopped);
+        assert_eq!(state.exit_code(), Some(0));
+        assert_eq!(state.status_description(), Some("Completed"));
+        assert!(state.finished_at().is_some());
+    }
+
+    #[test]
+    fn pod_pending_state() {
+        let pod: api_core::Pod = serde_json::from_str(
+            r#"{"kind": "Pod", "status": {"phase": "Pending", "message": "Unschedulable"}}"#,
+        )
+        .unwrap();
+        let state = pod_to_state(&pod);
+        assert_eq!(state.status(), &ModuleStatus::Unknown);
+        assert_eq!(state.status_description(), Some("Unschedulable"));
+    }
 }
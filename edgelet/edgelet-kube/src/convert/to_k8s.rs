@@ -10,7 +10,9 @@ use failure::ResultExt;
 use k8s_openapi::api::apps::v1 as api_apps;
 use k8s_openapi::api::core::v1 as api_core;
 use k8s_openapi::api::rbac::v1 as api_rbac;
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1 as api_meta;
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
 use log::warn;
 
 use crate::constants::env::*;
@@ -153,11 +155,23 @@ fn spec_to_podspec(
         .and_then(|hc| hc.binds())
     {
         // Binds in Docker options are "source:target:options"
-        // We will convert these to a Host Path Volume Source.
+        // We will convert these to a Host Path Volume Source, or to a named volume
+        // when the source is a volume name rather than an absolute path.
         for bind in binds.iter() {
             let bind_elements = bind.split(':').collect::<Vec<&str>>();
             let element_count = bind_elements.len();
-            if element_count >= 2 {
+            if element_count >= 2 && !is_host_path(bind_elements[0]) {
+                let volume_name = sanitize_dns_value(bind_elements[0])?;
+                let volume_mount = api_core::VolumeMount {
+                    mount_path: bind_elements[1].to_string(),
+                    name: volume_name.clone(),
+                    read_only: Some(element_count > 2 && bind_elements[2].contains("ro")),
+                    ..api_core::VolumeMount::default()
+                };
+
+                push_named_volume(&mut volumes, settings, volume_name);
+                volume_mounts.push(volume_mount);
+            } else if element_count >= 2 {
                 // If we have a valid bind mount, create a Volume with the
                 // bind source as a host path.
                 let bind_name = sanitize_dns_value(bind_elements[0])?;
@@ -228,18 +242,13 @@ fn spec_to_podspec(
                     if let (Some(source), Some(target)) = (mount.source(), mount.target()) {
                         let volume_name = sanitize_dns_value(source)?;
 
-                        let volume = api_core::Volume {
-                            name: volume_name.clone(),
-                            empty_dir: Some(api_core::EmptyDirVolumeSource::default()),
-                            ..api_core::Volume::default()
-                        };
                         let volume_mount = api_core::VolumeMount {
                             mount_path: target.to_string(),
-                            name: volume_name,
+                            name: volume_name.clone(),
                             read_only: mount.read_only().cloned(),
                             ..api_core::VolumeMount::default()
                         };
-                        volumes.push(volume);
+                        push_named_volume(&mut volumes, settings, volume_name);
                         volume_mounts.push(volume_mount);
                     } else {
                         warn!("Volume mount did not contain a source and target");
@@ -301,6 +310,65 @@ fn spec_to_podspec(
     })
 }
 
+/// Docker treats the source of a bind as a host path when it is absolute, and as a volume name otherwise.
+fn is_host_path(source: &str) -> bool {
+    source.starts_with('/')
+}
+
+/// Backs a named volume with the claim of the same name when a storage class is configured,
+/// or with an `emptyDir` volume otherwise. A volume mounted several times is declared once.
+fn push_named_volume(
+    volumes: &mut Vec<api_core::Volume>,
+    settings: &Settings,
+    volume_name: String,
+) {
+    if volumes.iter().any(|volume| volume.name == volume_name) {
+        return;
+    }
+
+    let volume = if settings.storage_class_name().is_some() {
+        api_core::Volume {
+            name: volume_name.clone(),
+            persistent_volume_claim: Some(api_core::PersistentVolumeClaimVolumeSource {
+                claim_name: volume_name,
+                read_only: None,
+            }),
+            ..api_core::Volume::default()
+        }
+    } else {
+        api_core::Volume {
+            name: volume_name,
+            empty_dir: Some(api_core::EmptyDirVolumeSource::default()),
+            ..api_core::Volume::default()
+        }
+    };
+    volumes.push(volume);
+}
+
+/// Lists the sources of the named volumes a module mounts with either binds or mounts.
+fn named_volume_sources(spec: &ModuleSpec<DockerConfig>) -> Vec<&str> {
+    let host_config = spec.config().create_options().host_config();
+    let binds = host_config
+        .and_then(HostConfig::binds)
+        .into_iter()
+        .flatten()
+        .filter_map(|bind| {
+            let mut bind_elements = bind.split(':');
+            match (bind_elements.next(), bind_elements.next()) {
+                (Some(source), Some(_)) if !is_host_path(source) => Some(source),
+                _ => None,
+            }
+        });
+    let mounts = host_config
+        .and_then(HostConfig::mounts)
+        .into_iter()
+        .flatten()
+        .filter(|mount| mount._type() == Some("volume"))
+        .filter_map(|mount| mount.source().filter(|_| mount.target().is_some()));
+
+    binds.chain(mounts).collect()
+}
+
 fn env<V: Into<String>>(key: &str, value: V) -> api_core::EnvVar {
     api_core::EnvVar {
         name: key.to_string(),
@@ -486,6 +554,189 @@ pub fn spec_to_role_binding(
     Ok((role_binding_name, role_binding))
 }
 
+/// Converts the port bindings of a module into a Service selecting its pod,
+/// or `None` if the module binds no ports.
+pub fn spec_to_service(
+    settings: &Settings,
+    spec: &ModuleSpec<DockerConfig>,
+    module_owner: &KubeModuleOwner,
+) -> Result<(String, Option<api_core::Service>)> {
+    let module_label_value = sanitize_dns_value(spec.name())?;
+    let device_label_value =
+        sanitize_label_value(settings.device_id().ok_or(ErrorKind::MissingDeviceId)?);
+    let hubname_label = sanitize_label_value(
+        settings
+            .iot_hub_hostname()
+            .ok_or(ErrorKind::MissingHubName)?,
+    );
+
+    let service_name = module_label_value.clone();
+
+    // Port bindings in Docker options are keyed by "port[/protocol]" of the container,
+    // with the host ports to publish them on.
+    let mut ports = Vec::new();
+    if let Some(port_bindings) = spec
+        .config()
+        .create_options()
+        .host_config()
+        .and_then(HostConfig::port_bindings)
+    {
+        for (container_port, bindings) in port_bindings {
+            let mut container_port_elements = container_port.splitn(2, '/');
+            let target_port = container_port_elements
+                .next()
+                .and_then(|port| port.parse::<i32>().ok());
+            let protocol = container_port_elements
+                .next()
+                .unwrap_or("tcp")
+                .to_uppercase();
+
+            if let Some(target_port) = target_port {
+                let host_ports = bindings
+                    .iter()
+                    .map(|binding| {
+                        binding
+                            .host_port()
+                            .and_then(|port| port.parse::<i32>().ok())
+                            .unwrap_or(target_port)
+                    })
+                    .collect::<Vec<_>>();
+                let host_ports = if host_ports.is_empty() {
+                    vec![target_port]
+                } else {
+                    host_ports
+                };
+
+                for port in host_ports {
+                    ports.push(api_core::ServicePort {
+                        name: Some(format!(
+                            "{}-{}-{}",
+                            port,
+                            target_port,
+                            protocol.to_lowercase()
+                        )),
+                        port,
+                        protocol: Some(protocol.clone()),
+                        target_port: Some(IntOrString::Int(target_port)),
+                        ..api_core::ServicePort::default()
+                    });
+                }
+            } else {
+                warn!(
+                    "Port binding {} did not follow format port[/protocol]",
+                    container_port
+                );
+            }
+        }
+    }
+
+    if ports.is_empty() {
+        return Ok((service_name, None));
+    }
+
+    // A Service may not expose the same port twice, and a stable order keeps it from
+    // being replaced on every deployment.
+    ports.sort_by(|a, b| (a.port, &a.protocol).cmp(&(b.port, &b.protocol)));
+    ports.dedup_by(|a, b| a.port == b.port && a.protocol == b.protocol);
+
+    // labels
+    let mut labels = BTreeMap::new();
+    labels.insert(EDGE_MODULE_LABEL.to_string(), module_label_value);
+    labels.insert(EDGE_DEVICE_LABEL.to_string(), device_label_value);
+    labels.insert(EDGE_HUBNAME_LABEL.to_string(), hubname_label);
+
+    // annotations
+    let mut annotations = BTreeMap::new();
+    annotations.insert(EDGE_ORIGINAL_MODULEID.to_string(), spec.name().to_string());
+
+    let service = api_core::Service {
+        metadata: Some(api_meta::ObjectMeta {
+            name: Some(service_name.clone()),
+            namespace: Some(settings.namespace().to_string()),
+            labels: Some(labels.clone()),
+            annotations: Some(annotations),
+            owner_references: Some(owner_references(module_owner)),
+            ..api_meta::ObjectMeta::default()
+        }),
+        spec: Some(api_core::ServiceSpec {
+            type_: Some(settings.port_mapping_service_type().to_string()),
+            selector: Some(labels),
+            ports: Some(ports),
+            ..api_core::ServiceSpec::default()
+        }),
+        ..api_core::Service::default()
+    };
+
+    Ok((service_name, Some(service)))
+}
+
+/// Converts the named volumes of a module into Persistent Volume Claims when a storage class
+/// is configured. Claims are not labeled with the module since modules may share a volume.
+pub fn spec_to_persistent_volume_claims(
+    settings: &Settings,
+    spec: &ModuleSpec<DockerConfig>,
+    module_owner: &KubeModuleOwner,
+) -> Result<Vec<(String, api_core::PersistentVolumeClaim)>> {
+    let storage_class_name = match settings.storage_class_name() {
+        Some(storage_class_name) => storage_class_name,
+        None => return Ok(Vec::new()),
+    };
+    let device_label_value =
+        sanitize_label_value(settings.device_id().ok_or(ErrorKind::MissingDeviceId)?);
+    let hubname_label = sanitize_label_value(
+        settings
+            .iot_hub_hostname()
+            .ok_or(ErrorKind::MissingHubName)?,
+    );
+
+    // labels
+    let mut labels = BTreeMap::new();
+    labels.insert(EDGE_DEVICE_LABEL.to_string(), device_label_value);
+    labels.insert(EDGE_HUBNAME_LABEL.to_string(), hubname_label);
+
+    let mut requests = BTreeMap::new();
+    requests.insert(
+        "storage".to_string(),
+        Quantity(format!(
+            "{}Mi",
+            settings.persistent_volume_claim_default_size_in_mb()
+        )),
+    );
+
+    let mut claim_names = named_volume_sources(spec)
+        .into_iter()
+        .map(sanitize_dns_value)
+        .collect::<Result<Vec<_>>>()?;
+    claim_names.sort();
+    claim_names.dedup();
+
+    Ok(claim_names
+        .into_iter()
+        .map(|claim_name| {
+            let claim = api_core::PersistentVolumeClaim {
+                metadata: Some(api_meta::ObjectMeta {
+                    name: Some(claim_name.clone()),
+                    namespace: Some(settings.namespace().to_string()),
+                    labels: Some(labels.clone()),
+                    owner_references: Some(owner_references(module_owner)),
+                    ..api_meta::ObjectMeta::default()
+                }),
+                spec: Some(api_core::PersistentVolumeClaimSpec {
+                    access_modes: Some(vec!["ReadWriteOnce".to_string()]),
+                    resources: Some(api_core::ResourceRequirements {
+                        requests: Some(requests.clone()),
+                        ..api_core::ResourceRequirements::default()
+                    }),
+                    storage_class_name: Some(storage_class_name.to_string()),
+                    ..api_core::PersistentVolumeClaimSpec::default()
+                }),
+                ..api_core::PersistentVolumeClaim::default()
+            };
+            (claim_name, claim)
+        })
+        .collect())
+}
+
 /// Creates Config Map with Edge Trust Bundle.
 pub fn trust_bundle_to_config_map(
     settings: &Settings,
@@ -535,16 +786,19 @@ mod tests {
     use docker::models::AuthConfig;
     use docker::models::ContainerCreateBody;
     use docker::models::HostConfig;
+    use docker::models::HostConfigPortBindings;
     use docker::models::Mount;
     use edgelet_core::{ImagePullPolicy, ModuleSpec};
     use edgelet_docker::DockerConfig;
     use edgelet_test_utils::cert::TestCert;
+    use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+    use serde_json::json;
 
     use crate::constants::env::*;
     use crate::constants::*;
     use crate::convert::{
-        spec_to_deployment, spec_to_role_binding, spec_to_service_account,
-        trust_bundle_to_config_map,
+        spec_to_deployment, spec_to_persistent_volume_claims, spec_to_role_binding,
+        spec_to_service, spec_to_service_account, trust_bundle_to_config_map,
     };
     use crate::tests::{
         create_module_owner, make_settings, PROXY_CONFIG_MAP_NAME,
@@ -793,6 +1047,170 @@ mod tests {
         }
     }
 
+    fn create_module_spec_with_host_config(host_config: HostConfig) -> ModuleSpec<DockerConfig> {
+        let create_body = ContainerCreateBody::new().with_host_config(host_config);
+        ModuleSpec::new(
+            "$edgeHub".to_string(),
+            "docker".to_string(),
+            DockerConfig::new("my-image:v1.0".to_string(), create_body, None).unwrap(),
+            HashMap::new(),
+            ImagePullPolicy::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn module_to_service() {
+        let module = create_module_spec_with_host_config(HostConfig::new().with_port_bindings({
+            let mut port_bindings = HashMap::new();
+            port_bindings.insert(
+                "443/tcp".to_string(),
+                vec![HostConfigPortBindings::new().with_host_port("8443".to_string())],
+            );
+            port_bindings.insert(
+                "5671/tcp".to_string(),
+                vec![HostConfigPortBindings::new().with_host_port("5671".to_string())],
+            );
+            port_bindings.insert("1883".to_string(), vec![]);
+            port_bindings.insert("not-a-port/udp".to_string(), vec![]);
+            port_bindings
+        }));
+        let module_owner = create_module_owner();
+        let settings = make_settings(Some(json!({"port_mapping_service_type": "LoadBalancer"})));
+
+        let (name, service) = spec_to_service(&settings, &module, &module_owner).unwrap();
+        assert_eq!(name, "edgehub");
+
+        let service = service.unwrap();
+        let metadata = service.metadata.unwrap();
+        assert_eq!(metadata.name, Some("edgehub".to_string()));
+        assert_eq!(metadata.namespace, Some("default".to_string()));
+        assert_eq!(
+            metadata.owner_references.map(|owners| owners.len()),
+            Some(1)
+        );
+
+        let spec = service.spec.unwrap();
+        assert_eq!(spec.type_, Some("LoadBalancer".to_string()));
+        let selector = spec.selector.unwrap();
+        assert_eq!(selector[EDGE_MODULE_LABEL], "edgehub");
+        assert_eq!(selector[EDGE_DEVICE_LABEL], "device1");
+        assert_eq!(selector[EDGE_HUBNAME_LABEL], "iothub");
+
+        let ports = spec.ports.unwrap();
+        let ports: Vec<_> = ports
+            .iter()
+            .map(|port| {
+                (
+                    port.name.as_deref().unwrap(),
+                    port.port,
+                    port.target_port.clone().unwrap(),
+                    port.protocol.as_deref().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            ports,
+            vec![
+                ("1883-1883-tcp", 1883, IntOrString::Int(1883), "TCP"),
+                ("5671-5671-tcp", 5671, IntOrString::Int(5671), "TCP"),
+                ("8443-443-tcp", 8443, IntOrString::Int(443), "TCP"),
+            ]
+        );
+    }
+
+    #[test]
+    fn module_without_port_bindings_has_no_service() {
+        let module = create_module_spec();
+        let module_owner = create_module_owner();
+
+        let (name, service) =
+            spec_to_service(&make_settings(None), &module, &module_owner).unwrap();
+        assert_eq!(name, "edgeagent");
+        assert!(service.is_none());
+    }
+
+    #[test]
+    fn named_volumes_to_persistent_volume_claims() {
+        let module = create_module_spec_with_host_config(
+            HostConfig::new()
+                .with_binds(vec![String::from("/a:/b"), String::from("data:/data:ro")])
+                .with_mounts(vec![
+                    Mount::new()
+                        .with__type(String::from("volume"))
+                        .with_source(String::from("Data"))
+                        .with_target(String::from("/other-data")),
+                    Mount::new()
+                        .with__type(String::from("volume"))
+                        .with_source(String::from("logs"))
+                        .with_target(String::from("/logs")),
+                ]),
+        );
+        let module_owner = create_module_owner();
+        let settings = make_settings(Some(json!({
+            "storage_class_name": "fast",
+            "persistent_volume_claim_default_size_in_mb": 512
+        })));
+
+        let claims = spec_to_persistent_volume_claims(&settings, &module, &module_owner).unwrap();
+        let names: Vec<_> = claims.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["data", "logs"]);
+
+        let (_, claim) = &claims[0];
+        let metadata = claim.metadata.as_ref().unwrap();
+        let labels = metadata.labels.as_ref().unwrap();
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels[EDGE_DEVICE_LABEL], "device1");
+        assert_eq!(labels[EDGE_HUBNAME_LABEL], "iothub");
+        let spec = claim.spec.as_ref().unwrap();
+        assert_eq!(spec.storage_class_name, Some("fast".to_string()));
+        assert_eq!(spec.access_modes, Some(vec!["ReadWriteOnce".to_string()]));
+        let requests = spec.resources.as_ref().unwrap().requests.as_ref().unwrap();
+        assert_eq!(requests["storage"].0, "512Mi");
+
+        let (_, deployment) = spec_to_deployment(&settings, &module, &module_owner).unwrap();
+        let volumes = deployment
+            .spec
+            .unwrap()
+            .template
+            .spec
+            .unwrap()
+            .volumes
+            .unwrap();
+        let data = volumes.iter().find(|volume| volume.name == "data").unwrap();
+        assert_eq!(
+            data.persistent_volume_claim
+                .as_ref()
+                .map(|claim| claim.claim_name.as_str()),
+            Some("data")
+        );
+        assert!(data.host_path.is_none());
+    }
+
+    #[test]
+    fn named_volumes_without_storage_class_are_empty_dirs() {
+        let module = create_module_spec_with_host_config(
+            HostConfig::new().with_binds(vec![String::from("data:/data")]),
+        );
+        let module_owner = create_module_owner();
+        let settings = make_settings(None);
+
+        let claims = spec_to_persistent_volume_claims(&settings, &module, &module_owner).unwrap();
+        assert!(claims.is_empty());
+
+        let (_, deployment) = spec_to_deployment(&settings, &module, &module_owner).unwrap();
+        let volumes = deployment
+            .spec
+            .unwrap()
+            .template
+            .spec
+            .unwrap()
+            .volumes
+            .unwrap();
+        let data = volumes.iter().find(|volume| volume.name == "data").unwrap();
+        assert!(data.empty_dir.is_some());
+    }
+
     #[test]
     fn trust_bundle_to_config_map_fails_when_cert_is_not_available() {
         let config_map = trust_bundle_to_config_map(
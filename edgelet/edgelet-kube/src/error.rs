@@ -66,6 +66,9 @@ pub enum ErrorKind {
 
     #[fail(display = "Kubernetes object metadata is missing")]
     MissingMetadata(MissingMetadataReason),
+
+    #[fail(display = "Watch of Kubernetes objects failed: {}", _0)]
+    Watch(String),
 }
 
 #[derive(Clone, Debug, PartialEq)]
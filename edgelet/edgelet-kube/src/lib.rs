@@ -18,6 +18,8 @@ mod module;
 mod registry;
 mod runtime;
 mod settings;
+mod system_resources;
+mod watch;
 
 use std::convert::TryFrom;
 
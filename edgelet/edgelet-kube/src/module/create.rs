@@ -9,13 +9,17 @@ use futures::prelude::*;
 use futures::{future, Future, Stream};
 use hyper::service::Service;
 use hyper::Body;
+use k8s_openapi::api::core::v1 as api_core;
 
 use edgelet_core::{ModuleSpec, RuntimeOperation};
 use edgelet_docker::DockerConfig;
 use kube_client::TokenSource;
 
 use crate::constants::EDGE_EDGE_AGENT_NAME;
-use crate::convert::{spec_to_deployment, spec_to_role_binding, spec_to_service_account};
+use crate::convert::{
+    spec_to_deployment, spec_to_persistent_volume_claims, spec_to_role_binding, spec_to_service,
+    spec_to_service_account,
+};
 use crate::error::{Error, MissingMetadataReason};
 use crate::{ErrorKind, KubeModuleOwner, KubeModuleRuntime};
 
@@ -48,12 +52,24 @@ where
                         let owner = owner.clone();
                         move |_| create_or_update_role_binding(&runtime, &module, &owner)
                     })
+                    .and_then({
+                        let runtime = runtime.clone();
+                        let module = module.clone();
+                        let owner = owner.clone();
+                        move |_| create_persistent_volume_claims(&runtime, &module, &owner)
+                    })
                     .and_then({
                         let runtime = runtime.clone();
                         let module = module.clone();
                         let owner = owner.clone();
                         move |_| create_or_update_deployment(&runtime, &module, &owner)
                     })
+                    .and_then({
+                        let runtime = runtime.clone();
+                        let module = module.clone();
+                        let owner = owner.clone();
+                        move |_| create_or_update_service(&runtime, &module, &owner)
+                    })
             }
         })
         .map_err(|err| {
@@ -291,6 +307,197 @@ where
         .flatten()
 }
 
+/// Creates the claims of the named volumes of a module that do not exist yet. Existing claims
+/// are left alone since their spec can hardly change and their data outlives the module.
+fn create_persistent_volume_claims<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    module: &ModuleSpec<DockerConfig>,
+    module_owner: &KubeModuleOwner,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Send + Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+{
+    spec_to_persistent_volume_claims(runtime.settings(), module, module_owner)
+        .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+        .map(|new_claims| {
+            if new_claims.is_empty() {
+                return Either::A(future::ok(()));
+            }
+
+            let client_copy = runtime.client();
+            let namespace_copy = runtime.settings().namespace().to_owned();
+
+            let fut = runtime
+                .client()
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .list_persistent_volume_claims(runtime.settings().namespace(), None, None)
+                .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+                .and_then(move |claims| {
+                    let creates = new_claims
+                        .into_iter()
+                        .filter(|(name, _)| {
+                            !claims.items.iter().any(|claim| {
+                                claim.metadata.as_ref().map_or(false, |meta| {
+                                    meta.name.as_ref().map_or(false, |n| n == name)
+                                })
+                            })
+                        })
+                        .map(|(_, new_claim)| {
+                            client_copy
+                                .lock()
+                                .expect("Unexpected lock error")
+                                .borrow_mut()
+                                .create_persistent_volume_claim(&namespace_copy, &new_claim)
+                                .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+                        })
+                        .collect::<Vec<_>>();
+
+                    future::join_all(creates).map(|_| ())
+                });
+
+            Either::B(fut)
+        })
+        .into_future()
+        .flatten()
+}
+
+/// Creates, replaces or deletes the Service of a module so that it exposes the module's port bindings.
+fn create_or_update_service<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    module: &ModuleSpec<DockerConfig>,
+    module_owner: &KubeModuleOwner,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Send + Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+{
+    spec_to_service(runtime.settings(), module, module_owner)
+        .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+        .map(|(name, new_service)| {
+            let client_copy = runtime.client();
+            let namespace_copy = runtime.settings().namespace().to_owned();
+
+            runtime
+                .client()
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .list_services(
+                    runtime.settings().namespace(),
+                    Some(&name),
+                    Some(&runtime.settings().device_hub_selector()),
+                )
+                .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+                .and_then(move |services| {
+                    let current = services.items.into_iter().find(|service| {
+                        service.metadata.as_ref().map_or(false, |meta| {
+                            meta.name.as_ref().map_or(false, |n| *n == name)
+                        })
+                    });
+
+                    match (current, new_service) {
+                        (Some(current), Some(mut new_service)) => {
+                            keep_assigned_fields(&current, &mut new_service);
+                            if is_service_up_to_date(&current, &new_service) {
+                                Either::A(Either::A(future::ok(())))
+                            } else {
+                                let fut = client_copy
+                                    .lock()
+                                    .expect("Unexpected lock error")
+                                    .borrow_mut()
+                                    .replace_service(&namespace_copy, &name, &new_service)
+                                    .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+                                    .map(|_| ());
+
+                                Either::A(Either::B(fut))
+                            }
+                        }
+                        (None, Some(new_service)) => {
+                            let fut = client_copy
+                                .lock()
+                                .expect("Unexpected lock error")
+                                .borrow_mut()
+                                .create_service(&namespace_copy, &new_service)
+                                .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+                                .map(|_| ());
+
+                            Either::B(Either::A(fut))
+                        }
+                        (Some(_), None) => {
+                            let fut = client_copy
+                                .lock()
+                                .expect("Unexpected lock error")
+                                .borrow_mut()
+                                .delete_service(&namespace_copy, &name)
+                                .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)));
+
+                            Either::B(Either::B(Either::A(fut)))
+                        }
+                        (None, None) => Either::B(Either::B(Either::B(future::ok(())))),
+                    }
+                })
+        })
+        .into_future()
+        .flatten()
+}
+
+/// Copies the fields Kubernetes assigns to a Service, which a replacement may not change.
+fn keep_assigned_fields(current: &api_core::Service, new_service: &mut api_core::Service) {
+    if let (Some(current), Some(new_meta)) =
+        (current.metadata.as_ref(), new_service.metadata.as_mut())
+    {
+        new_meta.resource_version = current.resource_version.clone();
+    }
+
+    if let (Some(current), Some(new_spec)) = (current.spec.as_ref(), new_service.spec.as_mut()) {
+        new_spec.cluster_ip = current.cluster_ip.clone();
+
+        if let (Some(current_ports), Some(new_ports)) =
+            (current.ports.as_ref(), new_spec.ports.as_mut())
+        {
+            for port in new_ports {
+                port.node_port = current_ports
+                    .iter()
+                    .find(|current| current.port == port.port && current.protocol == port.protocol)
+                    .and_then(|current| current.node_port);
+            }
+        }
+    }
+}
+
+fn is_service_up_to_date(current: &api_core::Service, new_service: &api_core::Service) -> bool {
+    let metadata = |service: &api_core::Service| {
+        service
+            .metadata
+            .as_ref()
+            .map(|meta| (meta.labels.clone(), meta.annotations.clone()))
+    };
+    let spec = |service: &api_core::Service| {
+        service.spec.as_ref().map(|spec| {
+            (
+                spec.type_.clone(),
+                spec.selector.clone(),
+                spec.ports.clone(),
+            )
+        })
+    };
+
+    metadata(current) == metadata(new_service) && spec(current) == spec(new_service)
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -301,7 +508,9 @@ mod tests {
     use serde_json::json;
     use tokio::runtime::Runtime;
 
-    use docker::models::{AuthConfig, ContainerCreateBody, HostConfig, Mount};
+    use docker::models::{
+        AuthConfig, ContainerCreateBody, HostConfig, HostConfigPortBindings, Mount,
+    };
     use edgelet_core::{ImagePullPolicy, ModuleSpec, RuntimeOperation};
     use edgelet_docker::DockerConfig;
     use edgelet_test_utils::routes;
@@ -311,8 +520,8 @@ mod tests {
 
     use crate::error::ErrorKind::RuntimeOperation as RuntimeOperationErrorKind;
     use crate::module::create::{
-        create_or_update_deployment, create_or_update_role_binding,
-        create_or_update_service_account,
+        create_or_update_deployment, create_or_update_role_binding, create_or_update_service,
+        create_or_update_service_account, create_persistent_volume_claims,
     };
     use crate::module::create_module;
     use crate::tests::{
@@ -441,6 +650,90 @@ mod tests {
         runtime.block_on(task).unwrap();
     }
 
+    #[test]
+    fn it_creates_new_service_if_does_not_exist() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/api/v1/namespaces/{}/services", settings.namespace()) => empty_service_list_handler(),
+            POST format!("/api/v1/namespaces/{}/services", settings.namespace()) => create_service_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+        let module = create_module_spec_with_port_bindings("edgehub");
+        let module_owner = create_module_owner();
+
+        let task = create_or_update_service(&runtime, &module, &module_owner);
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    #[test]
+    fn it_replaces_existing_service_if_ports_changed() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/api/v1/namespaces/{}/services", settings.namespace()) => service_list_handler(),
+            PUT format!("/api/v1/namespaces/{}/services/edgehub", settings.namespace()) => create_service_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+        let module = create_module_spec_with_port_bindings("edgehub");
+        let module_owner = create_module_owner();
+
+        let task = create_or_update_service(&runtime, &module, &module_owner);
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    #[test]
+    fn it_deletes_service_if_module_has_no_port_bindings() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/api/v1/namespaces/{}/services", settings.namespace()) => service_list_handler(),
+            DELETE format!("/api/v1/namespaces/{}/services/edgehub", settings.namespace()) => delete_service_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+        let module = create_module_spec("edgehub");
+        let module_owner = create_module_owner();
+
+        let task = create_or_update_service(&runtime, &module, &module_owner);
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    #[test]
+    fn it_creates_missing_persistent_volume_claims() {
+        let settings = make_settings(Some(json!({ "storage_class_name": "default" })));
+
+        let dispatch_table = routes!(
+            GET format!("/api/v1/namespaces/{}/persistentvolumeclaims", settings.namespace()) => persistent_volume_claim_list_handler(),
+            POST format!("/api/v1/namespaces/{}/persistentvolumeclaims", settings.namespace()) => create_persistent_volume_claim_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+        let module = create_module_spec("edgeagent");
+        let module_owner = create_module_owner();
+
+        let task = create_persistent_volume_claims(&runtime, &module, &module_owner);
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
     #[test]
     fn it_creates_all_required_resources() {
         let settings = make_settings(None);
@@ -451,6 +744,7 @@ mod tests {
             PUT format!("/apis/rbac.authorization.k8s.io/v1/namespaces/{}/rolebindings/edgeagent", settings.namespace()) => replace_role_binding_handler(),
             GET format!("/apis/apps/v1/namespaces/{}/deployments", settings.namespace()) => noagent_deployment_list_handler(),
             POST format!("/apis/apps/v1/namespaces/{}/deployments", settings.namespace()) => create_deployment_handler(),
+            GET format!("/api/v1/namespaces/{}/services", settings.namespace()) => empty_service_list_handler(),
         );
 
         let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
@@ -664,6 +958,139 @@ mod tests {
         }
     }
 
+    fn empty_service_list_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "ServiceList",
+                    "apiVersion": "v1",
+                    "items": []
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn service_list_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "ServiceList",
+                    "apiVersion": "v1",
+                    "items": [
+                        {
+                            "metadata": {
+                                "name": "edgehub",
+                                "namespace": "my-namespace",
+                                "resourceVersion": "42"
+                            },
+                            "spec": {
+                                "type": "ClusterIP",
+                                "clusterIP": "10.0.0.10",
+                                "ports": [
+                                    {
+                                        "name": "8883-8883-tcp",
+                                        "port": 8883,
+                                        "targetPort": 8883,
+                                        "protocol": "TCP"
+                                    }
+                                ]
+                            }
+                        }
+                    ]
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn create_service_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::CREATED, || {
+                json!({
+                    "kind": "Service",
+                    "apiVersion": "v1",
+                    "metadata": {
+                        "name": "edgehub",
+                        "namespace": "my-namespace",
+                    }
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn delete_service_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "Status",
+                    "apiVersion": "v1",
+                    "status": "Success"
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn persistent_volume_claim_list_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "PersistentVolumeClaimList",
+                    "apiVersion": "v1",
+                    "items": [
+                        {
+                            "metadata": {
+                                "name": "i",
+                                "namespace": "my-namespace",
+                            }
+                        }
+                    ]
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn create_persistent_volume_claim_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone
+    {
+        move |req| {
+            assert_eq!(Method::POST, *req.method());
+            response(StatusCode::CREATED, || {
+                json!({
+                    "kind": "PersistentVolumeClaim",
+                    "apiVersion": "v1",
+                    "metadata": {
+                        "name": "k",
+                        "namespace": "my-namespace",
+                    }
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn create_module_spec_with_port_bindings(name: &str) -> ModuleSpec<DockerConfig> {
+        let create_body =
+            ContainerCreateBody::new().with_host_config(HostConfig::new().with_port_bindings({
+                let mut port_bindings = HashMap::new();
+                port_bindings.insert(
+                    "443/tcp".to_string(),
+                    vec![HostConfigPortBindings::new().with_host_port("443".to_string())],
+                );
+                port_bindings
+            }));
+        ModuleSpec::new(
+            name.to_string(),
+            "docker".to_string(),
+            DockerConfig::new("my-image:v1.0".to_string(), create_body, None).unwrap(),
+            HashMap::new(),
+            ImagePullPolicy::default(),
+        )
+        .unwrap()
+    }
+
     fn create_module_spec(name: &str) -> ModuleSpec<DockerConfig> {
         let create_body = ContainerCreateBody::new()
             .with_host_config(
@@ -0,0 +1,804 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use chrono::Utc;
+use failure::Fail;
+use futures::future::Either;
+use futures::prelude::*;
+use futures::{future, Future, Stream};
+use hyper::service::Service;
+use hyper::Body;
+use k8s_openapi::api::core::v1 as api_core;
+
+use edgelet_core::{ModuleRuntimeState, ModuleStatus, RuntimeOperation};
+use kube_client::{ErrorKind as KubeClientErrorKind, TokenSource};
+
+use super::{module_selector, KubeModule};
+use crate::constants::{EDGE_EDGE_AGENT_NAME, EDGE_RESTARTED_AT};
+use crate::convert::{pod_to_module, pod_to_state, sanitize_dns_value};
+use crate::error::{Error, ErrorKind, Result};
+use crate::{Deployment, KubeModuleRuntime};
+
+pub fn get_module<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    id: &str,
+) -> impl Future<Item = (KubeModule, ModuleRuntimeState), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+{
+    let runtime = runtime.clone();
+    let operation = RuntimeOperation::GetModule(id.to_string());
+
+    sanitize_dns_value(id)
+        .map(|name| {
+            get_deployment(&runtime, &name, id).and_then(move |deployment| {
+                runtime
+                    .client()
+                    .lock()
+                    .expect("Unexpected lock error")
+                    .borrow_mut()
+                    .list_pods(
+                        runtime.settings().namespace(),
+                        Some(&module_selector(runtime.settings(), &name)),
+                    )
+                    .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+                    .and_then(|pods| module_with_state(deployment, pods.items))
+            })
+        })
+        .into_future()
+        .flatten()
+        .map_err(|err| Error::from(err.context(ErrorKind::RuntimeOperation(operation))))
+}
+
+pub fn start_module<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    id: &str,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+{
+    let operation = RuntimeOperation::StartModule(id.to_string());
+
+    update_deployment(runtime, id, |deployment| set_replicas(deployment, 1))
+        .map_err(|err| Error::from(err.context(ErrorKind::RuntimeOperation(operation))))
+}
+
+pub fn stop_module<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    id: &str,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+{
+    let operation = RuntimeOperation::StopModule(id.to_string());
+
+    update_deployment(runtime, id, |deployment| set_replicas(deployment, 0))
+        .map_err(|err| Error::from(err.context(ErrorKind::RuntimeOperation(operation))))
+}
+
+/// Replaces the pod of a module by changing an annotation of its pod template,
+/// which makes Kubernetes roll the deployment out again. A stopped module is started.
+pub fn restart_module<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    id: &str,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+{
+    let operation = RuntimeOperation::RestartModule(id.to_string());
+    let restarted_at = Utc::now().to_rfc3339();
+
+    update_deployment(runtime, id, move |deployment| {
+        set_replicas(deployment, 1);
+        if let Some(spec) = deployment.spec.as_mut() {
+            spec.template
+                .metadata
+                .get_or_insert_with(Default::default)
+                .annotations
+                .get_or_insert_with(Default::default)
+                .insert(EDGE_RESTARTED_AT.to_string(), restarted_at);
+        }
+    })
+    .map_err(|err| Error::from(err.context(ErrorKind::RuntimeOperation(operation))))
+}
+
+/// Deletes the deployment, service account and role binding created for a module.
+pub fn remove_module<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    id: &str,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+{
+    let runtime = runtime.clone();
+    let operation = RuntimeOperation::RemoveModule(id.to_string());
+
+    sanitize_dns_value(id)
+        .map(|name| {
+            runtime
+                .client()
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .delete_deployment(runtime.settings().namespace(), &name)
+                .and_then({
+                    let runtime = runtime.clone();
+                    let name = name.clone();
+                    move |_| {
+                        runtime
+                            .client()
+                            .lock()
+                            .expect("Unexpected lock error")
+                            .borrow_mut()
+                            .delete_service_account(runtime.settings().namespace(), &name)
+                            .or_else(|err| match err.kind() {
+                                // the service account of a module may have been deleted already
+                                KubeClientErrorKind::NotFound(_) => Ok(()),
+                                _ => Err(err),
+                            })
+                    }
+                })
+                .and_then({
+                    let runtime = runtime.clone();
+                    let name = name.clone();
+                    move |_| remove_service(&runtime, &name)
+                })
+                .and_then(move |_| {
+                    // role bindings are only created for edge agent
+                    if name == EDGE_EDGE_AGENT_NAME {
+                        Either::A(
+                            runtime
+                                .client()
+                                .lock()
+                                .expect("Unexpected lock error")
+                                .borrow_mut()
+                                .delete_role_binding(runtime.settings().namespace(), &name),
+                        )
+                    } else {
+                        Either::B(future::ok(()))
+                    }
+                })
+                .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+        })
+        .into_future()
+        .flatten()
+        .map_err(|err| Error::from(err.context(ErrorKind::RuntimeOperation(operation))))
+}
+
+/// Deletes the Service exposing the ports of a module, if it has one.
+fn remove_service<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    name: &str,
+) -> impl Future<Item = (), Error = kube_client::Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+{
+    let client_copy = runtime.client();
+    let namespace_copy = runtime.settings().namespace().to_owned();
+    let name_copy = name.to_owned();
+
+    runtime
+        .client()
+        .lock()
+        .expect("Unexpected lock error")
+        .borrow_mut()
+        .list_services(
+            runtime.settings().namespace(),
+            Some(name),
+            Some(&runtime.settings().device_hub_selector()),
+        )
+        .and_then(move |services| {
+            if services.items.is_empty() {
+                Either::A(future::ok(()))
+            } else {
+                Either::B(
+                    client_copy
+                        .lock()
+                        .expect("Unexpected lock error")
+                        .borrow_mut()
+                        .delete_service(&namespace_copy, &name_copy),
+                )
+            }
+        })
+}
+
+fn get_deployment<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    name: &str,
+    id: &str,
+) -> impl Future<Item = Deployment, Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+{
+    let name = name.to_string();
+    let id = id.to_string();
+
+    runtime
+        .client()
+        .lock()
+        .expect("Unexpected lock error")
+        .borrow_mut()
+        .list_deployments(
+            runtime.settings().namespace(),
+            Some(&name),
+            Some(&runtime.settings().device_hub_selector()),
+        )
+        .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+        .and_then(move |deployments| {
+            deployments
+                .items
+                .into_iter()
+                .find(|deployment| {
+                    deployment.metadata.as_ref().map_or(false, |meta| {
+                        meta.name.as_ref().map_or(false, |n| *n == name)
+                    })
+                })
+                .ok_or_else(|| Error::from(ErrorKind::NotFound(format!("Module {} not found", id))))
+        })
+}
+
+fn update_deployment<T, S, F>(
+    runtime: &KubeModuleRuntime<T, S>,
+    id: &str,
+    update: F,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+    F: FnOnce(&mut Deployment),
+{
+    let client = runtime.client();
+    let namespace = runtime.settings().namespace().to_owned();
+
+    sanitize_dns_value(id)
+        .map(|name| {
+            get_deployment(runtime, &name, id).and_then(move |current| {
+                let mut deployment = current.clone();
+                update(&mut deployment);
+
+                if deployment == current {
+                    Either::A(future::ok(()))
+                } else {
+                    let fut = client
+                        .lock()
+                        .expect("Unexpected lock error")
+                        .borrow_mut()
+                        .replace_deployment(&namespace, &name, &deployment)
+                        .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+                        .map(|_| ());
+
+                    Either::B(fut)
+                }
+            })
+        })
+        .into_future()
+        .flatten()
+}
+
+fn set_replicas(deployment: &mut Deployment, replicas: i32) {
+    if let Some(spec) = deployment.spec.as_mut() {
+        spec.replicas = Some(replicas);
+    }
+}
+
+pub fn module_with_state(
+    deployment: Deployment,
+    pods: Vec<api_core::Pod>,
+) -> Result<(KubeModule, ModuleRuntimeState)> {
+    // Pods replaced by a rollout are listed until they have terminated.
+    let pod = pods.into_iter().find(|pod| {
+        pod.metadata
+            .as_ref()
+            .map_or(true, |meta| meta.deletion_timestamp.is_none())
+    });
+
+    let (pod, state) = if let Some(pod) = pod {
+        let state = pod_to_state(&pod);
+        (pod, state)
+    } else {
+        // A stopped module has no pod, so it is read from the pod template of its deployment.
+        let spec = deployment.spec.unwrap_or_default();
+        let status = if spec.replicas == Some(0) {
+            ModuleStatus::Stopped
+        } else {
+            ModuleStatus::Unknown
+        };
+        let pod = api_core::Pod {
+            metadata: spec.template.metadata,
+            spec: spec.template.spec,
+            ..api_core::Pod::default()
+        };
+        (pod, ModuleRuntimeState::default().with_status(status))
+    };
+
+    let module = pod_to_module(&pod).unwrap_or_else(|| Err(ErrorKind::PodToModule.into()))?;
+    Ok((module, state))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{Future, Stream};
+    use hyper::service::service_fn;
+    use hyper::{Body, Method, Request, StatusCode};
+    use maplit::btreemap;
+    use serde_json::{json, Value as JsonValue};
+    use tokio::runtime::Runtime;
+
+    use edgelet_core::{Module, ModuleRuntimeErrorReason, ModuleStatus, RuntimeOperation};
+    use edgelet_test_utils::routes;
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+
+    use crate::error::ErrorKind::RuntimeOperation as RuntimeOperationErrorKind;
+    use crate::module::{get_module, remove_module, restart_module, start_module, stop_module};
+    use crate::tests::{create_runtime, make_settings, not_found_handler, response};
+
+    #[test]
+    fn it_scales_deployment_up_to_start_module() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/apis/apps/v1/namespaces/{}/deployments", settings.namespace()) => deployment_list_handler(0),
+            PUT format!("/apis/apps/v1/namespaces/{}/deployments/edgehub", settings.namespace()) => replace_deployment_handler(|deployment| {
+                assert_eq!(deployment["spec"]["replicas"], 1);
+            }),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = start_module(&runtime, "$edgeHub");
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    #[test]
+    fn it_scales_deployment_down_to_stop_module() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/apis/apps/v1/namespaces/{}/deployments", settings.namespace()) => deployment_list_handler(1),
+            PUT format!("/apis/apps/v1/namespaces/{}/deployments/edgehub", settings.namespace()) => replace_deployment_handler(|deployment| {
+                assert_eq!(deployment["spec"]["replicas"], 0);
+            }),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = stop_module(&runtime, "$edgeHub");
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    #[test]
+    fn it_does_not_replace_deployment_already_scaled() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/apis/apps/v1/namespaces/{}/deployments", settings.namespace()) => deployment_list_handler(1),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = start_module(&runtime, "$edgeHub");
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    #[test]
+    fn it_rolls_out_pod_template_to_restart_module() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/apis/apps/v1/namespaces/{}/deployments", settings.namespace()) => deployment_list_handler(1),
+            PUT format!("/apis/apps/v1/namespaces/{}/deployments/edgehub", settings.namespace()) => replace_deployment_handler(|deployment| {
+                assert_eq!(deployment["spec"]["replicas"], 1);
+                assert!(deployment["spec"]["template"]["metadata"]["annotations"]
+                    ["net.azure-devices.edge.restarted-at"]
+                    .is_string());
+            }),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = restart_module(&runtime, "$edgeHub");
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    #[test]
+    fn it_fails_to_start_missing_module() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/apis/apps/v1/namespaces/{}/deployments", settings.namespace()) => empty_deployment_list_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = start_module(&runtime, "$edgeHub");
+
+        let mut runtime = Runtime::new().unwrap();
+        let err = runtime.block_on(task).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &RuntimeOperationErrorKind(RuntimeOperation::StartModule("$edgeHub".to_string()))
+        );
+        match ModuleRuntimeErrorReason::from(&err) {
+            ModuleRuntimeErrorReason::NotFound => (),
+            reason => panic!("Expected NotFound, got {:?}", reason),
+        }
+    }
+
+    #[test]
+    fn it_removes_module_resources() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            DELETE format!("/apis/apps/v1/namespaces/{}/deployments/edgeagent", settings.namespace()) => delete_handler(),
+            DELETE format!("/api/v1/namespaces/{}/serviceaccounts/edgeagent", settings.namespace()) => delete_handler(),
+            GET format!("/api/v1/namespaces/{}/services", settings.namespace()) => empty_service_list_handler(),
+            DELETE format!("/apis/rbac.authorization.k8s.io/v1/namespaces/{}/rolebindings/edgeagent", settings.namespace()) => delete_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = remove_module(&runtime, "$edgeAgent");
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    #[test]
+    fn it_removes_module_without_role_binding() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            DELETE format!("/apis/apps/v1/namespaces/{}/deployments/edgehub", settings.namespace()) => delete_handler(),
+            DELETE format!("/api/v1/namespaces/{}/serviceaccounts/edgehub", settings.namespace()) => delete_handler(),
+            GET format!("/api/v1/namespaces/{}/services", settings.namespace()) => service_list_handler(),
+            DELETE format!("/api/v1/namespaces/{}/services/edgehub", settings.namespace()) => delete_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = remove_module(&runtime, "$edgeHub");
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    #[test]
+    fn it_removes_module_without_service_account() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            DELETE format!("/apis/apps/v1/namespaces/{}/deployments/edgehub", settings.namespace()) => delete_handler(),
+            GET format!("/api/v1/namespaces/{}/services", settings.namespace()) => empty_service_list_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = remove_module(&runtime, "$edgeHub");
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    #[test]
+    fn it_gets_module_state_from_pod() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/apis/apps/v1/namespaces/{}/deployments", settings.namespace()) => deployment_list_handler(1),
+            GET format!("/api/v1/namespaces/{}/pods", settings.namespace()) => pod_list_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = get_module(&runtime, "$edgeHub");
+
+        let mut runtime = Runtime::new().unwrap();
+        let (module, state) = runtime.block_on(task).unwrap();
+        assert_eq!(module.name(), "$edgeHub");
+        assert_eq!(module.config().image(), "edgehub:1.0");
+        assert_eq!(state.status(), &ModuleStatus::Running);
+    }
+
+    #[test]
+    fn it_gets_stopped_module_from_deployment() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/apis/apps/v1/namespaces/{}/deployments", settings.namespace()) => deployment_list_handler(0),
+            GET format!("/api/v1/namespaces/{}/pods", settings.namespace()) => empty_pod_list_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = get_module(&runtime, "$edgeHub");
+
+        let mut runtime = Runtime::new().unwrap();
+        let (module, state) = runtime.block_on(task).unwrap();
+        assert_eq!(module.name(), "$edgeHub");
+        assert_eq!(state.status(), &ModuleStatus::Stopped);
+    }
+
+    #[test]
+    fn it_fails_to_get_missing_module() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/apis/apps/v1/namespaces/{}/deployments", settings.namespace()) => empty_deployment_list_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = get_module(&runtime, "$edgeHub");
+
+        let mut runtime = Runtime::new().unwrap();
+        let err = runtime.block_on(task).map(|_| ()).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &RuntimeOperationErrorKind(RuntimeOperation::GetModule("$edgeHub".to_string()))
+        );
+    }
+
+    fn deployment(replicas: i32) -> JsonValue {
+        json!({
+            "kind": "Deployment",
+            "apiVersion": "apps/v1",
+            "metadata": {
+                "name": "edgehub",
+                "namespace": "default",
+            },
+            "spec": {
+                "replicas": replicas,
+                "selector": {
+                    "matchLabels": {
+                        "net.azure-devices.edge.module": "edgehub"
+                    }
+                },
+                "template": {
+                    "metadata": {
+                        "labels": {
+                            "net.azure-devices.edge.module": "edgehub"
+                        },
+                        "annotations": {
+                            "net.azure-devices.edge.original-moduleid": "$edgeHub"
+                        }
+                    },
+                    "spec": {
+                        "containers": [
+                            {
+                                "name": "edgehub",
+                                "image": "edgehub:1.0"
+                            }
+                        ]
+                    }
+                }
+            }
+        })
+    }
+
+    fn deployment_list_handler(replicas: i32) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, move || {
+                json!({
+                    "kind": "DeploymentList",
+                    "apiVersion": "apps/v1",
+                    "items": [deployment(replicas)]
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn empty_deployment_list_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "DeploymentList",
+                    "apiVersion": "apps/v1",
+                    "items": []
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn replace_deployment_handler(
+        check: fn(&JsonValue),
+    ) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |req| {
+            Box::new(req.into_body().concat2().and_then(move |body| {
+                let deployment: JsonValue = serde_json::from_slice(&body).unwrap();
+                check(&deployment);
+                response(StatusCode::OK, move || deployment.to_string())
+            })) as ResponseFuture
+        }
+    }
+
+    fn empty_service_list_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "ServiceList",
+                    "apiVersion": "v1",
+                    "items": []
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn service_list_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "ServiceList",
+                    "apiVersion": "v1",
+                    "items": [
+                        {
+                            "metadata": {
+                                "name": "edgehub",
+                                "namespace": "my-namespace",
+                            }
+                        }
+                    ]
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn delete_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "Status",
+                    "apiVersion": "v1",
+                    "metadata": {},
+                    "status": "Success"
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn pod_list_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "PodList",
+                    "apiVersion": "v1",
+                    "items": [
+                        {
+                            "metadata": {
+                                "name": "edgehub-5c9d6fb7c4-wq7rx",
+                                "namespace": "default",
+                                "labels": {
+                                    "net.azure-devices.edge.module": "edgehub"
+                                },
+                                "annotations": {
+                                    "net.azure-devices.edge.original-moduleid": "$edgeHub"
+                                }
+                            },
+                            "spec": {
+                                "containers": [
+                                    {
+                                        "name": "edgehub",
+                                        "image": "edgehub:1.0"
+                                    }
+                                ]
+                            },
+                            "status": {
+                                "phase": "Running",
+                                "containerStatuses": [
+                                    {
+                                        "name": "edgehub",
+                                        "image": "edgehub:1.0",
+                                        "imageID": "docker-pullable://edgehub@sha256:01",
+                                        "ready": true,
+                                        "restartCount": 0,
+                                        "state": {
+                                            "running": {
+                                                "startedAt": "2019-11-20T22:30:37Z"
+                                            }
+                                        }
+                                    }
+                                ]
+                            }
+                        }
+                    ]
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn empty_pod_list_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "PodList",
+                    "apiVersion": "v1",
+                    "items": []
+                })
+                .to_string()
+            })
+        }
+    }
+}
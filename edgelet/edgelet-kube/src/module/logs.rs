@@ -0,0 +1,399 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::cmp;
+use std::convert::TryFrom;
+
+use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, TimeZone, Utc};
+use failure::Fail;
+use futures::future::{self, Either};
+use futures::prelude::*;
+use futures::{try_ready, Future, Stream};
+use hyper::service::Service;
+use hyper::Body;
+use k8s_openapi::api::core::v1 as api_core;
+
+use edgelet_core::{LogChunk, LogOptions, LogTail, RuntimeOperation};
+use kube_client::TokenSource;
+
+use super::module_selector;
+use crate::convert::sanitize_dns_value;
+use crate::error::{Error, ErrorKind};
+use crate::KubeModuleRuntime;
+
+/// Streams the logs of the module container of a module's pod.
+///
+/// Kubernetes serves logs as plain text, so each line is framed the way the container
+/// engine frames logs. Kubernetes does not keep stdout and stderr apart, so every line
+/// is framed as stdout and no line is returned when stdout is not asked for.
+pub fn module_logs<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    id: &str,
+    options: &LogOptions,
+) -> impl Future<Item = Body, Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+{
+    if !options.stdout() {
+        return Either::A(future::ok(Body::empty()));
+    }
+
+    let runtime = runtime.clone();
+    let options = options.clone();
+    let operation = RuntimeOperation::GetModuleLogs(id.to_string());
+    let id = id.to_string();
+
+    let logs = sanitize_dns_value(&id)
+        .map(|name| {
+            runtime
+                .client()
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .list_pods(
+                    runtime.settings().namespace(),
+                    Some(&module_selector(runtime.settings(), &name)),
+                )
+                .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+                .and_then(move |pods| {
+                    pods.items
+                        .into_iter()
+                        .filter_map(|pod| pod.metadata)
+                        .filter(|meta| meta.deletion_timestamp.is_none())
+                        .find_map(|meta| meta.name)
+                        .ok_or_else(|| {
+                            Error::from(ErrorKind::NotFound(format!("Module {} not found", id)))
+                        })
+                })
+                .and_then(move |pod_name| {
+                    let params = api_core::ReadNamespacedPodLogOptional {
+                        container: Some(&name),
+                        follow: Some(options.follow()),
+                        since_seconds: since_seconds(options.since()),
+                        tail_lines: match options.tail() {
+                            LogTail::All => None,
+                            LogTail::Num(num) => {
+                                Some(i64::try_from(*num).unwrap_or_else(|_| i64::max_value()))
+                            }
+                        },
+                        // the timestamps are needed to stop at the until time
+                        timestamps: Some(options.timestamps() || options.until().is_some()),
+                        ..api_core::ReadNamespacedPodLogOptional::default()
+                    };
+
+                    runtime
+                        .client()
+                        .lock()
+                        .expect("Unexpected lock error")
+                        .borrow_mut()
+                        .read_pod_log(runtime.settings().namespace(), &pod_name, params)
+                        .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+                        .map(move |logs| Body::wrap_stream(PodLogFrames::new(logs, &options)))
+                })
+        })
+        .into_future()
+        .flatten()
+        .map_err(|err| Error::from(err.context(ErrorKind::RuntimeOperation(operation))));
+
+    Either::B(logs)
+}
+
+/// Kubernetes takes how far back to read logs rather than the time to read them from.
+fn since_seconds(since: i32) -> Option<i64> {
+    if since > 0 {
+        Some((Utc::now().timestamp() - i64::from(since)).max(1))
+    } else {
+        None
+    }
+}
+
+/// Splits plain text logs into lines and frames each line as stdout.
+///
+/// When the logs are read until some time, the lines start with the timestamp Kubernetes
+/// adds, which is removed again unless the timestamps were asked for.
+struct PodLogFrames<S> {
+    inner: S,
+    buf: BytesMut,
+    until: Option<DateTime<Utc>>,
+    timestamps: bool,
+    done: bool,
+}
+
+impl<S> PodLogFrames<S> {
+    fn new(inner: S, options: &LogOptions) -> Self {
+        PodLogFrames {
+            inner,
+            buf: BytesMut::new(),
+            until: options.until().map(|until| Utc.timestamp(until.into(), 0)),
+            timestamps: options.timestamps(),
+            done: false,
+        }
+    }
+
+    fn next_line(&mut self) -> Option<BytesMut> {
+        match self.buf.iter().position(|b| *b == b'\n') {
+            Some(end) => Some(self.buf.split_to(end + 1)),
+            None if self.done && !self.buf.is_empty() => Some(self.buf.take()),
+            None => None,
+        }
+    }
+
+    /// Frames a line, or returns `None` once the line is past the until time.
+    fn frame(&self, mut line: BytesMut) -> Option<Bytes> {
+        if let Some(until) = self.until {
+            let timestamp_len = line
+                .iter()
+                .position(|b| *b == b' ')
+                .unwrap_or_else(|| line.len());
+            let timestamp = std::str::from_utf8(&line[..timestamp_len])
+                .ok()
+                .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok());
+            if timestamp.map_or(false, |timestamp| timestamp.with_timezone(&Utc) > until) {
+                return None;
+            }
+
+            if !self.timestamps {
+                line.advance(cmp::min(timestamp_len + 1, line.len()));
+            }
+        }
+
+        Some(LogChunk::Stdout(line.freeze()).into_frame())
+    }
+}
+
+impl<S> Stream for PodLogFrames<S>
+where
+    S: Stream,
+    S::Item: AsRef<[u8]>,
+{
+    type Item = Bytes;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if let Some(line) = self.next_line() {
+                return match self.frame(line) {
+                    Some(frame) => Ok(Async::Ready(Some(frame))),
+                    None => {
+                        // Logs are in time order, so no later line is needed.
+                        self.done = true;
+                        self.buf.clear();
+                        Ok(Async::Ready(None))
+                    }
+                };
+            }
+
+            if self.done {
+                return Ok(Async::Ready(None));
+            }
+
+            match try_ready!(self.inner.poll()) {
+                Some(chunk) => self.buf.extend_from_slice(chunk.as_ref()),
+                None => self.done = true,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use futures::stream::iter_ok;
+    use futures::{Future, Stream};
+    use hyper::service::service_fn;
+    use hyper::{Body, Method, Request, StatusCode};
+    use maplit::btreemap;
+    use serde_json::json;
+    use tokio::runtime::Runtime;
+
+    use edgelet_core::{LogChunk, LogOptions, LogTail, RuntimeOperation};
+    use edgelet_test_utils::routes;
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+
+    use super::PodLogFrames;
+    use crate::error::ErrorKind::RuntimeOperation as RuntimeOperationErrorKind;
+    use crate::module::module_logs;
+    use crate::tests::{create_runtime, make_settings, not_found_handler, response};
+
+    #[test]
+    fn it_frames_lines_split_across_chunks() {
+        let chunks = vec![&b"Roses are"[..], &b" red\nviolets"[..], &b" are blue"[..]];
+
+        let frames = PodLogFrames::new(iter_ok::<_, ()>(chunks), &LogOptions::new())
+            .collect()
+            .wait()
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                stdout_frame("Roses are red\n"),
+                stdout_frame("violets are blue"),
+            ],
+            frames
+        );
+    }
+
+    #[test]
+    fn it_streams_logs_of_module_container() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/api/v1/namespaces/{}/pods", settings.namespace()) => pod_list_handler(),
+            GET format!("/api/v1/namespaces/{}/pods/edgehub-5c9d6fb7c4-wq7rx/log", settings.namespace()) => pod_log_handler(
+                "Roses are red\nviolets are blue\n",
+                |query| {
+                    assert!(query.contains("container=edgehub"));
+                    assert!(query.contains("tailLines=10"));
+                    assert!(query.contains("timestamps=false"));
+                },
+            ),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+        let options = LogOptions::new().with_tail(LogTail::Num(10));
+
+        let task = module_logs(&runtime, "$edgeHub", &options)
+            .map_err(|_| ())
+            .and_then(|logs| logs.concat2().map_err(|_| ()));
+
+        let mut runtime = Runtime::new().unwrap();
+        let logs = runtime.block_on(task).unwrap();
+        let expected = [
+            stdout_frame("Roses are red\n"),
+            stdout_frame("violets are blue\n"),
+        ]
+        .concat();
+        assert_eq!(expected, logs.as_ref());
+    }
+
+    #[test]
+    fn it_stops_reading_logs_at_until() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/api/v1/namespaces/{}/pods", settings.namespace()) => pod_list_handler(),
+            GET format!("/api/v1/namespaces/{}/pods/edgehub-5c9d6fb7c4-wq7rx/log", settings.namespace()) => pod_log_handler(
+                "2019-11-20T22:30:37.123456789Z Roses are red\n2019-11-20T22:40:37Z violets are blue\n",
+                |query| assert!(query.contains("timestamps=true")),
+            ),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+        // 2019-11-20T22:35:00Z
+        let options = LogOptions::new().with_until(Some(1_574_289_300));
+
+        let task = module_logs(&runtime, "$edgeHub", &options)
+            .map_err(|_| ())
+            .and_then(|logs| logs.concat2().map_err(|_| ()));
+
+        let mut runtime = Runtime::new().unwrap();
+        let logs = runtime.block_on(task).unwrap();
+        assert_eq!(stdout_frame("Roses are red\n"), logs.as_ref());
+    }
+
+    #[test]
+    fn it_returns_no_logs_without_stdout() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/api/v1/namespaces/{}/pods", settings.namespace()) => pod_list_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+        let options = LogOptions::new().with_stdout(false);
+
+        let task = module_logs(&runtime, "$edgeHub", &options)
+            .map_err(|_| ())
+            .and_then(|logs| logs.concat2().map_err(|_| ()));
+
+        let mut runtime = Runtime::new().unwrap();
+        let logs = runtime.block_on(task).unwrap();
+        assert!(logs.is_empty());
+    }
+
+    #[test]
+    fn it_fails_to_read_logs_without_pod() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/api/v1/namespaces/{}/pods", settings.namespace()) => empty_pod_list_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = module_logs(&runtime, "$edgeHub", &LogOptions::new());
+
+        let mut runtime = Runtime::new().unwrap();
+        let err = runtime.block_on(task).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &RuntimeOperationErrorKind(RuntimeOperation::GetModuleLogs("$edgeHub".to_string()))
+        );
+    }
+
+    fn stdout_frame(line: &'static str) -> Bytes {
+        LogChunk::Stdout(Bytes::from(line)).into_frame()
+    }
+
+    fn pod_list_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "PodList",
+                    "apiVersion": "v1",
+                    "items": [
+                        {
+                            "metadata": {
+                                "name": "edgehub-5c9d6fb7c4-wq7rx",
+                                "namespace": "default",
+                                "labels": {
+                                    "net.azure-devices.edge.module": "edgehub"
+                                }
+                            }
+                        }
+                    ]
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn empty_pod_list_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "PodList",
+                    "apiVersion": "v1",
+                    "items": []
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn pod_log_handler(
+        logs: &'static str,
+        check: fn(&str),
+    ) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |req| {
+            check(req.uri().query().unwrap_or_default());
+            response(StatusCode::OK, move || logs.to_string())
+        }
+    }
+}
@@ -2,10 +2,16 @@
 
 mod authentication;
 mod create;
+mod lifecycle;
+mod logs;
 mod trust_bundle;
 
 pub use authentication::authenticate;
 pub use create::create_module;
+pub use lifecycle::{
+    get_module, module_with_state, remove_module, restart_module, start_module, stop_module,
+};
+pub use logs::module_logs;
 pub use trust_bundle::init_trust_bundle;
 
 use edgelet_core::{Module, ModuleRuntimeState, ModuleStatus};
@@ -13,7 +19,9 @@ use edgelet_docker::DockerConfig;
 use edgelet_utils::ensure_not_empty_with_context;
 use futures::{future, Future};
 
+use crate::constants::EDGE_MODULE_LABEL;
 use crate::error::{Error, ErrorKind, Result};
+use crate::settings::Settings;
 
 const MODULE_TYPE: &str = "docker";
 
@@ -56,3 +64,13 @@ impl Module for KubeModule {
         ))
     }
 }
+
+/// Selects the pods of a module among those of this device.
+fn module_selector(settings: &Settings, module_label_value: &str) -> String {
+    let module = format!("{}={}", EDGE_MODULE_LABEL, module_label_value);
+    if settings.device_hub_selector().is_empty() {
+        module
+    } else {
+        format!("{},{}", settings.device_hub_selector(), module)
+    }
+}
@@ -2,6 +2,7 @@
 
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::io;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -14,23 +15,30 @@ use hyper::{Body, Chunk as HyperChunk, Request};
 use hyper_tls::HttpsConnector;
 
 use edgelet_core::{
-    AuthId, Authenticator, GetTrustBundle, LogOptions, MakeModuleRuntime, ModuleRegistry,
-    ModuleRuntime, ModuleRuntimeState, ModuleSpec, ProvisioningResult as CoreProvisioningResult,
-    RuntimeOperation, SystemInfo, SystemResources,
+    AuthId, Authenticator, FilteredLogs, GetTrustBundle, LogOptions, MakeModuleRuntime,
+    ModuleRegistry, ModuleRuntime, ModuleRuntimeState, ModuleSpec,
+    ProvisioningResult as CoreProvisioningResult, RuntimeOperation, SystemInfo, SystemResources,
 };
 use edgelet_docker::DockerConfig;
 use kube_client::{get_config, Client as KubeClient, HttpClient, TokenSource, ValueToken};
 use provisioning::ProvisioningResult;
 
-use crate::convert::pod_to_module;
+use crate::convert::{pod_to_module, pod_to_state};
 use crate::error::{Error, ErrorKind};
-use crate::module::{authenticate, create_module, init_trust_bundle, KubeModule};
+use crate::module::{
+    authenticate, get_module, init_trust_bundle, module_logs, remove_module, restart_module,
+    start_module, stop_module, KubeModule,
+};
 use crate::registry::create_image_pull_secrets;
 use crate::settings::Settings;
+use crate::system_resources::{system_resources, ResourceHistory};
+use crate::watch::{apply_module, watch_modules, ModuleCache};
 
 pub struct KubeModuleRuntime<T, S> {
     client: Arc<Mutex<RefCell<KubeClient<T, S>>>>,
     settings: Settings,
+    cache: Arc<Mutex<ModuleCache>>,
+    resource_history: Arc<Mutex<ResourceHistory>>,
 }
 
 impl<T, S> KubeModuleRuntime<T, S> {
@@ -38,6 +46,8 @@ impl<T, S> KubeModuleRuntime<T, S> {
         KubeModuleRuntime {
             client: Arc::new(Mutex::new(RefCell::new(client))),
             settings,
+            cache: Arc::new(Mutex::new(ModuleCache::default())),
+            resource_history: Arc::new(Mutex::new(ResourceHistory::default())),
         }
     }
 
@@ -45,6 +55,14 @@ impl<T, S> KubeModuleRuntime<T, S> {
         self.client.clone()
     }
 
+    pub(crate) fn cache(&self) -> &Mutex<ModuleCache> {
+        &self.cache
+    }
+
+    pub(crate) fn resource_history(&self) -> Arc<Mutex<ResourceHistory>> {
+        self.resource_history.clone()
+    }
+
     pub(crate) fn settings(&self) -> &Settings {
         &self.settings
     }
@@ -64,6 +82,8 @@ impl<T, S> Clone for KubeModuleRuntime<T, S> {
         KubeModuleRuntime {
             client: self.client(),
             settings: self.settings().clone(),
+            cache: self.cache.clone(),
+            resource_history: self.resource_history(),
         }
     }
 }
@@ -123,6 +143,10 @@ impl MakeModuleRuntime
                     .map_err(|err| Error::from(err.context(ErrorKind::Initialization)))
                     .map(|settings| KubeModuleRuntime::new(KubeClient::new(config), settings))
                     .and_then(move |runtime| init_trust_bundle(&runtime, crypto).map(|_| runtime))
+                    .map(|runtime| {
+                        tokio::spawn(watch_modules(&runtime));
+                        runtime
+                    })
             })
             .into_future()
             .flatten();
@@ -165,27 +189,46 @@ where
     type RemoveAllFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
 
     fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
-        Box::new(create_module(self, module))
+        Box::new(apply_module(self, module))
     }
 
-    fn get(&self, _id: &str) -> Self::GetFuture {
-        unimplemented!()
+    fn get(&self, id: &str) -> Self::GetFuture {
+        let cached = self.cache.lock().expect("Unexpected lock error").module(id);
+        match cached {
+            Some(module) => {
+                let operation = RuntimeOperation::GetModule(id.to_string());
+                Box::new(
+                    module
+                        .map_err(|err| {
+                            Error::from(err.context(ErrorKind::RuntimeOperation(operation)))
+                        })
+                        .into_future(),
+                )
+            }
+            None => Box::new(get_module(self, id)),
+        }
     }
 
-    fn start(&self, _id: &str) -> Self::StartFuture {
-        Box::new(future::ok(()))
+    fn start(&self, id: &str) -> Self::StartFuture {
+        Box::new(start_module(self, id))
     }
 
-    fn stop(&self, _id: &str, _wait_before_kill: Option<Duration>) -> Self::StopFuture {
-        Box::new(future::ok(()))
+    // The pod is given the termination grace period of its spec to stop.
+    fn stop(&self, id: &str, _wait_before_kill: Option<Duration>) -> Self::StopFuture {
+        Box::new(stop_module(self, id))
     }
 
-    fn restart(&self, _id: &str) -> Self::RestartFuture {
-        Box::new(future::ok(()))
+    fn restart(&self, id: &str) -> Self::RestartFuture {
+        Box::new(restart_module(self, id))
     }
 
-    fn remove(&self, _id: &str) -> Self::RemoveFuture {
-        Box::new(future::ok(()))
+    fn remove(&self, id: &str) -> Self::RemoveFuture {
+        self.cache.lock().expect("Unexpected lock error").forget(id);
+        let cache = self.cache.clone();
+        let id = id.to_string();
+        Box::new(remove_module(self, &id).map(move |()| {
+            cache.lock().expect("Unexpected lock error").removed(&id);
+        }))
     }
 
     fn system_info(&self) -> Self::SystemInfoFuture {
@@ -247,19 +290,24 @@ where
     }
 
     fn system_resources(&self) -> Self::SystemResourcesFuture {
-        // TODO: add support for system resources on k8s
-        Box::new(future::ok(SystemResources::new(
-            0,
-            0,
-            0.0,
-            0,
-            0,
-            vec![],
-            "".to_owned(),
-        )))
+        Box::new(system_resources(self))
     }
 
     fn list(&self) -> Self::ListFuture {
+        let cached = self.cache.lock().expect("Unexpected lock error").modules();
+        if let Some(modules) = cached {
+            return Box::new(
+                modules
+                    .map(|modules| modules.into_iter().map(|(module, _)| module).collect())
+                    .map_err(|err| {
+                        Error::from(
+                            err.context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules)),
+                        )
+                    })
+                    .into_future(),
+            );
+        }
+
         let result = self
             .client
             .lock()
@@ -289,11 +337,55 @@ where
     }
 
     fn list_with_details(&self) -> Self::ListWithDetailsStream {
-        Box::new(stream::empty())
+        let cached = self.cache.lock().expect("Unexpected lock error").modules();
+        let modules = if let Some(modules) = cached {
+            future::Either::A(modules.into_future())
+        } else {
+            let modules = self
+                .client
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .list_pods(
+                    self.settings().namespace(),
+                    Some(&self.settings().device_hub_selector()),
+                )
+                .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+                .and_then(|pods| {
+                    pods.items
+                        .iter()
+                        .filter_map(|pod| {
+                            pod_to_module(pod).map(|module| module.map(|m| (m, pod_to_state(pod))))
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                });
+            future::Either::B(modules)
+        };
+
+        Box::new(
+            modules
+                .map_err(|err| {
+                    Error::from(
+                        err.context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules)),
+                    )
+                })
+                .map(stream::iter_ok)
+                .flatten_stream(),
+        )
     }
 
-    fn logs(&self, _id: &str, _options: &LogOptions) -> Self::LogsFuture {
-        Box::new(future::ok(Logs("".to_string(), Body::empty())))
+    fn logs(&self, id: &str, options: &LogOptions) -> Self::LogsFuture {
+        let id = id.to_string();
+        let options = options.clone();
+
+        Box::new(module_logs(self, &id, &options).map(move |logs| {
+            let logs = if options.is_filtered() {
+                filter_logs(logs, &options)
+            } else {
+                logs
+            };
+            Logs(id, logs)
+        }))
     }
 
     fn registry(&self) -> &Self::ModuleRegistry {
@@ -324,6 +416,13 @@ where
     }
 }
 
+/// Applies the regex and severity of `options` to logs in the framed format
+/// `iotedge logs` reads, since Kubernetes cannot filter log lines.
+fn filter_logs(logs: Body, options: &LogOptions) -> Body {
+    let logs = logs.map_err(|err| io::Error::new(io::ErrorKind::Other, err));
+    Body::wrap_stream(FilteredLogs::new(logs, options))
+}
+
 #[derive(Debug)]
 pub struct Logs(String, Body);
 
@@ -377,13 +476,14 @@ impl AsRef<[u8]> for Chunk {
 
 #[cfg(test)]
 mod tests {
+    use futures::Stream;
     use hyper::service::service_fn;
     use hyper::{Body, Method, Request, StatusCode};
     use maplit::btreemap;
     use serde_json::json;
     use tokio::runtime::Runtime;
 
-    use edgelet_core::ModuleRuntime;
+    use edgelet_core::{Module, ModuleRuntime, ModuleStatus};
     use edgelet_test_utils::routes;
     use edgelet_test_utils::web::{
         make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
@@ -391,6 +491,29 @@ mod tests {
 
     use crate::tests::{create_runtime, make_settings, not_found_handler, response};
 
+    #[test]
+    fn runtime_lists_modules_with_details_from_pods_until_cache_is_synced() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/api/v1/namespaces/{}/pods", settings.namespace()) => list_pod_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = runtime.list_with_details().collect();
+
+        let mut runtime = Runtime::new().unwrap();
+        let modules = runtime.block_on(task).unwrap();
+
+        assert_eq!(1, modules.len());
+        let (module, state) = &modules[0];
+        assert_eq!("$edgeHub", module.name());
+        assert_eq!(&ModuleStatus::Running, state.status());
+    }
+
     #[test]
     fn runtime_get_system_info() {
         let settings = make_settings(None);
@@ -459,6 +582,224 @@ mod tests {
         );
     }
 
+    #[test]
+    fn runtime_get_system_resources() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET "/api/v1/nodes" => node_allocatable_handler(),
+            GET "/apis/metrics.k8s.io/v1beta1/nodes" => node_metrics_handler(),
+            GET format!("/api/v1/namespaces/{}/pods", settings.namespace()) => module_pods_handler(),
+            GET format!("/apis/metrics.k8s.io/v1beta1/namespaces/{}/pods", settings.namespace()) => pod_metrics_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = runtime.system_resources();
+
+        let mut runtime = Runtime::new().unwrap();
+        let resources = runtime.block_on(task).unwrap();
+
+        assert!((resources.used_cpu() - 25.0).abs() < 1e-9);
+        assert_eq!(resources.used_ram(), 1024 * 1024 * 1024);
+        assert_eq!(resources.total_ram(), 4 * 1024 * 1024 * 1024);
+        let stats: serde_json::Value = serde_json::from_str(resources.docker_stats()).unwrap();
+        assert_eq!(
+            stats,
+            json!([{
+                "name": "/$edgeHub",
+                "cpu_stats": { "cpu_usage": { "total_usage": 0 }, "system_cpu_usage": 0 },
+                "memory_stats": { "usage": 64 * 1024 * 1024, "limit": 4 * 1024 * 1024 * 1024_u64 },
+            }])
+        );
+    }
+
+    #[test]
+    fn runtime_get_system_resources_without_metrics_server() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET "/api/v1/nodes" => node_allocatable_handler(),
+            GET format!("/api/v1/namespaces/{}/pods", settings.namespace()) => module_pods_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = runtime.system_resources();
+
+        let mut runtime = Runtime::new().unwrap();
+        let resources = runtime.block_on(task).unwrap();
+
+        assert!(resources.used_cpu().abs() < f64::EPSILON);
+        assert_eq!(resources.used_ram(), 0);
+        assert_eq!(resources.total_ram(), 4 * 1024 * 1024 * 1024);
+        assert_eq!(resources.docker_stats(), "[]");
+    }
+
+    #[test]
+    fn runtime_get_system_resources_no_rbac() {
+        let more_settings = json!({"has_nodes_rbac" : "false"});
+        let settings = make_settings(Option::Some(more_settings));
+
+        let dispatch_table = routes!(
+            GET format!("/api/v1/namespaces/{}/pods", settings.namespace()) => module_pods_handler(),
+            GET format!("/apis/metrics.k8s.io/v1beta1/namespaces/{}/pods", settings.namespace()) => pod_metrics_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = runtime.system_resources();
+
+        let mut runtime = Runtime::new().unwrap();
+        let resources = runtime.block_on(task).unwrap();
+
+        assert!(resources.used_cpu().abs() < f64::EPSILON);
+        assert_eq!(resources.total_ram(), 0);
+        let stats: serde_json::Value = serde_json::from_str(resources.docker_stats()).unwrap();
+        assert_eq!(stats[0]["name"], "/$edgeHub");
+    }
+
+    fn node_allocatable_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind" : "NodeList",
+                    "items" : [
+                        {
+                            "kind" : "Node",
+                            "status" : {
+                                "allocatable": { "cpu": "2", "memory": "2Gi" },
+                                "capacity": { "cpu": "2", "memory": "3Gi" }
+                            }
+                        },
+                        {
+                            "kind" : "Node",
+                            "status" : {
+                                "allocatable": { "cpu": "2", "memory": "2Gi" },
+                                "capacity": { "cpu": "2", "memory": "3Gi" }
+                            }
+                        }
+                    ]
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn node_metrics_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "NodeMetricsList",
+                    "apiVersion": "metrics.k8s.io/v1beta1",
+                    "items": [
+                        { "metadata": { "name": "node1" }, "usage": { "cpu": "750m", "memory": "768Mi" } },
+                        { "metadata": { "name": "node2" }, "usage": { "cpu": "250m", "memory": "256Mi" } }
+                    ]
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn module_pods_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "PodList",
+                    "apiVersion": "v1",
+                    "items": [
+                        {
+                            "metadata": {
+                                "name": "edgehub-5c9d6fb7c4-wq7rx",
+                                "labels": { "net.azure-devices.edge.module": "edgehub" },
+                                "annotations": { "net.azure-devices.edge.original-moduleid": "$edgeHub" }
+                            }
+                        }
+                    ]
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn pod_metrics_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "PodMetricsList",
+                    "apiVersion": "metrics.k8s.io/v1beta1",
+                    "items": [
+                        {
+                            "metadata": { "name": "edgehub-5c9d6fb7c4-wq7rx" },
+                            "containers": [
+                                { "name": "edgehub", "usage": { "cpu": "1500000n", "memory": "60Mi" } },
+                                { "name": "proxy", "usage": { "cpu": "500u", "memory": "4Mi" } }
+                            ]
+                        }
+                    ]
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn list_pod_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "PodList",
+                    "apiVersion": "v1",
+                    "items": [
+                        {
+                            "metadata": {
+                                "name": "edgehub-5c9d6fb7c4-wq7rx",
+                                "labels": {
+                                    "net.azure-devices.edge.module": "edgehub"
+                                },
+                                "annotations": {
+                                    "net.azure-devices.edge.original-moduleid": "$edgeHub"
+                                }
+                            },
+                            "spec": {
+                                "containers": [
+                                    {
+                                        "name": "edgehub",
+                                        "image": "edgehub:1.0"
+                                    }
+                                ]
+                            },
+                            "status": {
+                                "phase": "Running",
+                                "containerStatuses": [
+                                    {
+                                        "name": "edgehub",
+                                        "image": "edgehub:1.0",
+                                        "imageID": "docker-pullable://edgehub@sha256:1234",
+                                        "ready": true,
+                                        "restartCount": 0,
+                                        "state": {
+                                            "running": {
+                                                "startedAt": "2019-11-05T21:41:49Z"
+                                            }
+                                        }
+                                    }
+                                ]
+                            }
+                        }
+                    ]
+                })
+                .to_string()
+            })
+        }
+    }
+
     fn list_node_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
         move |_| {
             response(StatusCode::OK, || {
@@ -26,6 +26,11 @@ pub struct Settings {
     proxy: ProxySettings,
     #[serde(default = "Settings::default_nodes_rbac")]
     has_nodes_rbac: bool,
+    #[serde(default = "Settings::default_port_mapping_service_type")]
+    port_mapping_service_type: String,
+    storage_class_name: Option<String>,
+    #[serde(default = "Settings::default_persistent_volume_claim_size_in_mb")]
+    persistent_volume_claim_default_size_in_mb: u32,
 }
 
 impl Settings {
@@ -87,9 +92,31 @@ impl Settings {
         self.has_nodes_rbac
     }
 
+    /// Type of the Services exposing the port bindings of modules, such as `ClusterIP` or `LoadBalancer`.
+    pub fn port_mapping_service_type(&self) -> &str {
+        &self.port_mapping_service_type
+    }
+
+    /// Storage class of the claims backing named volumes. Named volumes are `emptyDir` volumes when unset.
+    pub fn storage_class_name(&self) -> Option<&str> {
+        self.storage_class_name.as_deref()
+    }
+
+    pub fn persistent_volume_claim_default_size_in_mb(&self) -> u32 {
+        self.persistent_volume_claim_default_size_in_mb
+    }
+
     fn default_nodes_rbac() -> bool {
         true
     }
+
+    fn default_port_mapping_service_type() -> String {
+        "ClusterIP".to_string()
+    }
+
+    fn default_persistent_volume_claim_size_in_mb() -> u32 {
+        100
+    }
 }
 
 impl RuntimeSettings for Settings {
@@ -0,0 +1,596 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use failure::Fail;
+use futures::future::Either;
+use futures::prelude::*;
+use futures::{future, Future, Stream};
+use hyper::service::Service;
+use hyper::Body;
+use k8s_openapi::api::core::v1 as api_core;
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use log::warn;
+use serde_json::json;
+
+use edgelet_core::{RuntimeOperation, SystemResources};
+use kube_client::{Error as KubeClientError, NodeMetrics, PodMetrics, TokenSource};
+
+use crate::constants::EDGE_ORIGINAL_MODULEID;
+use crate::error::{Error, ErrorKind};
+use crate::KubeModuleRuntime;
+
+/// Reports the resources of the cluster in the shape the Docker runtime reports those of its host.
+///
+/// The totals are the allocatable resources of the nodes and the usage is the one measured by
+/// metrics-server. Node figures stay at zero when the runtime is not allowed to list nodes, and
+/// usage figures stay at zero when metrics-server is not deployed. The host uptime is how long
+/// the longest-ready node has been ready, and the process uptime is the age of the runtime.
+/// Disks are not reported, since neither the node status nor metrics-server tells how much
+/// of a node's storage is free.
+///
+/// Each module reports container stats summed over its pods, see `ResourceHistory`.
+pub fn system_resources<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+) -> impl Future<Item = SystemResources, Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+{
+    let history = runtime.resource_history();
+
+    let nodes = if runtime.settings().has_nodes_rbac() {
+        let nodes = runtime
+            .client()
+            .lock()
+            .expect("Unexpected lock error")
+            .borrow_mut()
+            .list_nodes()
+            .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)));
+        let metrics = runtime
+            .client()
+            .lock()
+            .expect("Unexpected lock error")
+            .borrow_mut()
+            .list_node_metrics()
+            .then(|metrics| Ok::<_, Error>(usage_or_warn(metrics.map(|metrics| metrics.items))));
+
+        Either::A(nodes.join(metrics).map(|(nodes, metrics)| {
+            NodeResources::new(&nodes.items, &metrics.unwrap_or_default(), Utc::now())
+        }))
+    } else {
+        Either::B(future::ok(NodeResources::default()))
+    };
+
+    let namespace = runtime.settings().namespace();
+    let selector = runtime.settings().device_hub_selector();
+    let pods = runtime
+        .client()
+        .lock()
+        .expect("Unexpected lock error")
+        .borrow_mut()
+        .list_pods(namespace, Some(selector))
+        .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)));
+    let metrics = runtime
+        .client()
+        .lock()
+        .expect("Unexpected lock error")
+        .borrow_mut()
+        .list_pod_metrics(namespace, Some(selector))
+        .then(|metrics| Ok::<_, Error>(usage_or_warn(metrics.map(|metrics| metrics.items))));
+
+    nodes
+        .join(pods.join(metrics))
+        .map(move |(nodes, (pods, metrics))| {
+            let modules = module_usage(&pods.items, &metrics.unwrap_or_default());
+            let mut history = history.lock().expect("Unexpected lock error");
+            let modules = history.module_stats(Instant::now(), &nodes, modules);
+
+            SystemResources::new(
+                nodes.host_uptime,
+                history.started.elapsed().as_secs(),
+                nodes.used_cpu_percent(),
+                nodes.used_memory,
+                nodes.allocatable_memory,
+                vec![],
+                serde_json::Value::Array(modules).to_string(),
+            )
+        })
+        .map_err(|err| {
+            Error::from(err.context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::SystemResources,
+            )))
+        })
+}
+
+fn usage_or_warn<T>(metrics: Result<T, KubeClientError>) -> Option<T> {
+    metrics
+        .map_err(|err| {
+            warn!(
+                "Could not query metrics-server, resource usage is reported as zero: {}",
+                err
+            );
+        })
+        .ok()
+}
+
+/// CPU in cores and memory in bytes, summed over the nodes of the cluster, and the uptime in
+/// seconds of the longest-ready node.
+#[derive(Debug, Default, PartialEq)]
+struct NodeResources {
+    allocatable_cpu: f64,
+    allocatable_memory: u64,
+    used_cpu: f64,
+    used_memory: u64,
+    host_uptime: u64,
+}
+
+impl NodeResources {
+    fn new(nodes: &[api_core::Node], metrics: &[NodeMetrics], now: DateTime<Utc>) -> Self {
+        let allocatable = || {
+            nodes
+                .iter()
+                .filter_map(|node| node.status.as_ref())
+                .filter_map(|status| status.allocatable.as_ref().or(status.capacity.as_ref()))
+        };
+        let usage = || metrics.iter().map(|node| &node.usage);
+        let host_uptime = nodes
+            .iter()
+            .filter_map(|node| node.status.as_ref()?.conditions.as_ref())
+            .flatten()
+            .filter(|condition| condition.type_ == "Ready" && condition.status == "True")
+            .filter_map(|condition| condition.last_transition_time.as_ref())
+            .map(|since| now.signed_duration_since(since.0).num_seconds())
+            .max()
+            .map_or(0, |seconds| u64::try_from(seconds).unwrap_or_default());
+
+        NodeResources {
+            allocatable_cpu: sum(allocatable(), "cpu"),
+            allocatable_memory: to_integer(sum(allocatable(), "memory")),
+            used_cpu: sum(usage(), "cpu"),
+            used_memory: to_integer(sum(usage(), "memory")),
+            host_uptime,
+        }
+    }
+
+    fn used_cpu_percent(&self) -> f64 {
+        if self.allocatable_cpu > 0.0 {
+            100.0 * self.used_cpu / self.allocatable_cpu
+        } else {
+            0.0
+        }
+    }
+}
+
+/// What the runtime remembers from one report of system resources to the next.
+///
+/// Docker reports the CPU time used by a container and by the whole host since they started,
+/// and consumers of its stats derive a CPU percentage from two reports. metrics-server only
+/// measures the current rate of usage, so the runtime integrates the rates of each module and
+/// the allocatable CPU of the nodes over the time between two reports. The first report of a
+/// module therefore shows no CPU time, and the host CPU time stays at zero when the runtime is
+/// not allowed to list nodes.
+#[derive(Debug)]
+pub(crate) struct ResourceHistory {
+    started: Instant,
+    last_report: Option<Instant>,
+    system_cpu_usage: u64,
+    module_cpu_usage: HashMap<String, u64>,
+}
+
+impl Default for ResourceHistory {
+    fn default() -> Self {
+        ResourceHistory {
+            started: Instant::now(),
+            last_report: None,
+            system_cpu_usage: 0,
+            module_cpu_usage: HashMap::new(),
+        }
+    }
+}
+
+impl ResourceHistory {
+    /// Container stats of each module in the schema of Docker, with CPU times in nanoseconds
+    /// and memory in bytes. A module without a memory limit on all of its containers is
+    /// limited by the allocatable memory of the nodes.
+    fn module_stats(
+        &mut self,
+        now: Instant,
+        nodes: &NodeResources,
+        modules: BTreeMap<&str, ModuleUsage>,
+    ) -> Vec<serde_json::Value> {
+        let elapsed = self.last_report.map_or(0.0, |last_report| {
+            now.duration_since(last_report).as_secs_f64()
+        });
+        self.last_report = Some(now);
+        self.system_cpu_usage += to_integer(nodes.allocatable_cpu * elapsed * 1e9);
+
+        let mut module_cpu_usage = HashMap::with_capacity(modules.len());
+        let mut stats = Vec::with_capacity(modules.len());
+        for (module_id, usage) in modules {
+            let total_usage = self
+                .module_cpu_usage
+                .get(module_id)
+                .copied()
+                .unwrap_or_default()
+                + to_integer(usage.cpu * elapsed * 1e9);
+            module_cpu_usage.insert(module_id.to_string(), total_usage);
+
+            stats.push(json!({
+                "name": format!("/{}", module_id),
+                "cpu_stats": {
+                    "cpu_usage": { "total_usage": total_usage },
+                    "system_cpu_usage": self.system_cpu_usage,
+                },
+                "memory_stats": {
+                    "usage": to_integer(usage.memory),
+                    "limit": usage.memory_limit.map_or(nodes.allocatable_memory, to_integer),
+                },
+            }));
+        }
+        self.module_cpu_usage = module_cpu_usage;
+
+        stats
+    }
+}
+
+/// CPU in cores and memory in bytes used by the pods of a module.
+#[derive(Debug, Default, PartialEq)]
+struct ModuleUsage {
+    cpu: f64,
+    memory: f64,
+    memory_limit: Option<f64>,
+}
+
+/// Sums the usage of the pods of each module, since a module has several pods while it rolls out.
+fn module_usage<'a>(
+    pods: &'a [api_core::Pod],
+    metrics: &[PodMetrics],
+) -> BTreeMap<&'a str, ModuleUsage> {
+    let module_ids: HashMap<&str, (&str, Option<f64>)> = pods
+        .iter()
+        .filter_map(|pod| {
+            let meta = pod.metadata.as_ref()?;
+            let name = meta.name.as_ref()?;
+            let module_id = meta.annotations.as_ref()?.get(EDGE_ORIGINAL_MODULEID)?;
+            Some((name.as_str(), (module_id.as_str(), memory_limit(pod))))
+        })
+        .collect();
+
+    metrics
+        .iter()
+        .filter_map(|pod| {
+            let name = pod.metadata.name.as_ref()?;
+            let module = module_ids.get(name.as_str())?;
+            Some((module, pod))
+        })
+        .fold(
+            BTreeMap::new(),
+            |mut modules, (&(module_id, memory_limit), pod)| {
+                let usage = || pod.containers.iter().map(|container| &container.usage);
+                let module = modules.entry(module_id).or_insert(ModuleUsage {
+                    memory_limit: Some(0.0),
+                    ..ModuleUsage::default()
+                });
+                module.cpu += sum(usage(), "cpu");
+                module.memory += sum(usage(), "memory");
+                module.memory_limit = module
+                    .memory_limit
+                    .and_then(|total| memory_limit.map(|limit| total + limit));
+                modules
+            },
+        )
+}
+
+/// The memory limit of a pod, if all of its containers have one.
+fn memory_limit(pod: &api_core::Pod) -> Option<f64> {
+    pod.spec
+        .as_ref()?
+        .containers
+        .iter()
+        .map(|container| {
+            let limits = container.resources.as_ref()?.limits.as_ref()?;
+            parse_quantity(limits.get("memory")?)
+        })
+        .sum()
+}
+
+fn sum<'a, I>(resources: I, name: &str) -> f64
+where
+    I: Iterator<Item = &'a BTreeMap<String, Quantity>>,
+{
+    resources
+        .filter_map(|resources| resources.get(name))
+        .filter_map(|quantity| {
+            let value = parse_quantity(quantity);
+            if value.is_none() {
+                warn!("Ignoring unrecognized {} quantity {:?}", name, quantity.0);
+            }
+            value
+        })
+        .sum()
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn to_integer(value: f64) -> u64 {
+    value.max(0.0).round() as u64
+}
+
+/// Parses a Kubernetes quantity such as `250m`, `12345n`, `128Mi` or `1e3` into its value.
+fn parse_quantity(quantity: &Quantity) -> Option<f64> {
+    let quantity = quantity.0.trim();
+    let split = quantity
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '+' || c == '-'))
+        .unwrap_or(quantity.len());
+    let (number, suffix) = quantity.split_at(split);
+    let number: f64 = number.parse().ok()?;
+
+    let multiplier = match suffix {
+        "" => 1.0,
+        "n" => 1e-9,
+        "u" => 1e-6,
+        "m" => 1e-3,
+        "k" => 1e3,
+        "M" => 1e6,
+        "G" => 1e9,
+        "T" => 1e12,
+        "P" => 1e15,
+        "E" => 1e18,
+        "Ki" => 1024_f64,
+        "Mi" => 1024_f64.powi(2),
+        "Gi" => 1024_f64.powi(3),
+        "Ti" => 1024_f64.powi(4),
+        "Pi" => 1024_f64.powi(5),
+        "Ei" => 1024_f64.powi(6),
+        exponent if exponent.starts_with('e') || exponent.starts_with('E') => {
+            10_f64.powi(exponent[1..].parse().ok()?)
+        }
+        _ => return None,
+    };
+
+    Some(number * multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::time::{Duration, Instant};
+
+    use chrono::{TimeZone, Utc};
+    use k8s_openapi::api::core::v1 as api_core;
+    use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+    use kube_client::{NodeMetrics, PodMetrics};
+    use serde_json::json;
+
+    use super::{
+        module_usage, parse_quantity, to_integer, ModuleUsage, NodeResources, ResourceHistory,
+    };
+
+    fn quantity(value: &str) -> Quantity {
+        Quantity(value.to_string())
+    }
+
+    #[test]
+    fn parse_quantity_handles_suffixes() {
+        assert_eq!(Some(2.0), parse_quantity(&quantity("2")));
+        assert_eq!(Some(0.25), parse_quantity(&quantity("250m")));
+        assert_eq!(Some(0.5), parse_quantity(&quantity("500000000n")));
+        assert_eq!(
+            Some(128.0 * 1024.0 * 1024.0),
+            parse_quantity(&quantity("128Mi"))
+        );
+        assert_eq!(Some(1500.0), parse_quantity(&quantity("1.5k")));
+        assert_eq!(Some(3e9), parse_quantity(&quantity("3G")));
+        assert_eq!(Some(1000.0), parse_quantity(&quantity("1e3")));
+        assert_eq!(Some(1e18), parse_quantity(&quantity("1E")));
+        assert_eq!(None, parse_quantity(&quantity("12Xi")));
+        assert_eq!(None, parse_quantity(&quantity("")));
+    }
+
+    #[test]
+    fn node_resources_prefer_allocatable_to_capacity() {
+        let nodes: Vec<api_core::Node> = serde_json::from_value(json!([
+            {
+                "status": {
+                    "allocatable": { "cpu": "1800m", "memory": "1Gi" },
+                    "capacity": { "cpu": "2", "memory": "2Gi" },
+                    "conditions": [
+                        {
+                            "type": "MemoryPressure",
+                            "status": "False",
+                            "lastTransitionTime": "2019-06-01T00:00:00Z"
+                        },
+                        {
+                            "type": "Ready",
+                            "status": "True",
+                            "lastTransitionTime": "2020-01-01T00:00:00Z"
+                        }
+                    ]
+                }
+            },
+            {
+                "status": {
+                    "capacity": { "cpu": "2", "memory": "1Gi" },
+                    "conditions": [
+                        {
+                            "type": "Ready",
+                            "status": "True",
+                            "lastTransitionTime": "2020-01-01T12:00:00Z"
+                        }
+                    ]
+                }
+            },
+            {}
+        ]))
+        .unwrap();
+        let metrics: Vec<NodeMetrics> = serde_json::from_value(json!([
+            { "metadata": { "name": "node1" }, "usage": { "cpu": "330m", "memory": "512Mi" } },
+            { "metadata": { "name": "node2" }, "usage": { "cpu": "1", "memory": "1Gi" } }
+        ]))
+        .unwrap();
+
+        let resources = NodeResources::new(&nodes, &metrics, Utc.ymd(2020, 1, 2).and_hms(0, 0, 0));
+
+        assert_eq!(2 * 1024 * 1024 * 1024_u64, resources.allocatable_memory);
+        assert_eq!(24 * 60 * 60, resources.host_uptime);
+        assert_eq!(1536 * 1024 * 1024_u64, resources.used_memory);
+        assert!((resources.used_cpu_percent() - 35.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn node_resources_without_nodes_are_zero() {
+        let resources = NodeResources::new(&[], &[], Utc::now());
+
+        assert_eq!(NodeResources::default(), resources);
+        assert!(resources.used_cpu_percent().abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn module_usage_sums_pods_of_a_module() {
+        let pods: Vec<api_core::Pod> = serde_json::from_value(json!([
+            {
+                "metadata": {
+                    "name": "edgehub-1",
+                    "annotations": { "net.azure-devices.edge.original-moduleid": "$edgeHub" }
+                },
+                "spec": {
+                    "containers": [
+                        { "name": "edgehub", "resources": { "limits": { "memory": "64Mi" } } },
+                        { "name": "proxy", "resources": { "limits": { "memory": "16Mi" } } }
+                    ]
+                }
+            },
+            {
+                "metadata": {
+                    "name": "edgehub-2",
+                    "annotations": { "net.azure-devices.edge.original-moduleid": "$edgeHub" }
+                },
+                "spec": {
+                    "containers": [
+                        { "name": "edgehub", "resources": { "limits": { "memory": "64Mi" } } }
+                    ]
+                }
+            },
+            {
+                "metadata": {
+                    "name": "sensor-1",
+                    "annotations": { "net.azure-devices.edge.original-moduleid": "Sensor" }
+                },
+                "spec": {
+                    "containers": [
+                        { "name": "sensor", "resources": { "requests": { "memory": "8Mi" } } }
+                    ]
+                }
+            },
+            { "metadata": { "name": "unrelated" } }
+        ]))
+        .unwrap();
+        let metrics: Vec<PodMetrics> = serde_json::from_value(json!([
+            {
+                "metadata": { "name": "edgehub-1" },
+                "containers": [
+                    { "name": "edgehub", "usage": { "cpu": "2m", "memory": "40Mi" } },
+                    { "name": "proxy", "usage": { "cpu": "1m", "memory": "2Mi" } }
+                ]
+            },
+            {
+                "metadata": { "name": "edgehub-2" },
+                "containers": [
+                    { "name": "edgehub", "usage": { "cpu": "1500000n", "memory": "8Mi" } }
+                ]
+            },
+            {
+                "metadata": { "name": "sensor-1" },
+                "containers": [
+                    { "name": "sensor", "usage": { "cpu": "0", "memory": "1000Ki" } }
+                ]
+            },
+            {
+                "metadata": { "name": "unrelated" },
+                "containers": [
+                    { "name": "unrelated", "usage": { "cpu": "1", "memory": "1Gi" } }
+                ]
+            }
+        ]))
+        .unwrap();
+
+        let modules = module_usage(&pods, &metrics);
+
+        assert_eq!(
+            vec!["$edgeHub", "Sensor"],
+            modules.keys().copied().collect::<Vec<_>>()
+        );
+        let edge_hub = &modules["$edgeHub"];
+        assert_eq!(4_500_000, to_integer(edge_hub.cpu * 1e9));
+        assert_eq!(50 * 1024 * 1024, to_integer(edge_hub.memory));
+        assert_eq!(Some(144.0 * 1024.0 * 1024.0), edge_hub.memory_limit);
+        let sensor = &modules["Sensor"];
+        assert_eq!(0, to_integer(sensor.cpu * 1e9));
+        assert_eq!(1000 * 1024, to_integer(sensor.memory));
+        assert_eq!(None, sensor.memory_limit);
+    }
+
+    #[test]
+    fn resource_history_integrates_cpu_usage_between_reports() {
+        let nodes = NodeResources {
+            allocatable_cpu: 2.0,
+            allocatable_memory: 1024,
+            ..NodeResources::default()
+        };
+        let modules = |cpu, memory_limit| {
+            let mut modules = BTreeMap::new();
+            modules.insert(
+                "$edgeHub",
+                ModuleUsage {
+                    cpu,
+                    memory: 512.0,
+                    memory_limit,
+                },
+            );
+            modules
+        };
+        let stats = |total_usage: u64, system_cpu_usage: u64, limit: u64| {
+            vec![json!({
+                "name": "/$edgeHub",
+                "cpu_stats": {
+                    "cpu_usage": { "total_usage": total_usage },
+                    "system_cpu_usage": system_cpu_usage,
+                },
+                "memory_stats": { "usage": 512, "limit": limit },
+            })]
+        };
+
+        let mut history = ResourceHistory::default();
+        let start = Instant::now();
+
+        assert_eq!(
+            stats(0, 0, 1024),
+            history.module_stats(start, &nodes, modules(0.5, None))
+        );
+        assert_eq!(
+            stats(5_000_000_000, 20_000_000_000, 1024),
+            history.module_stats(start + Duration::from_secs(10), &nodes, modules(0.5, None))
+        );
+
+        // A module that goes away starts over when it comes back.
+        assert!(history
+            .module_stats(start + Duration::from_secs(20), &nodes, BTreeMap::new())
+            .is_empty());
+        assert_eq!(
+            stats(2_500_000_000, 60_000_000_000, 256),
+            history.module_stats(
+                start + Duration::from_secs(30),
+                &nodes,
+                modules(0.25, Some(256.0))
+            )
+        );
+    }
+}
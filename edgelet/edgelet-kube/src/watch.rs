@@ -0,0 +1,1000 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+use failure::Fail;
+use futures::future::{Either, Loop};
+use futures::prelude::*;
+use futures::{future, stream, Future, Stream};
+use hyper::service::Service;
+use hyper::Body;
+use k8s_openapi::api::core::v1 as api_core;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1 as api_meta;
+use k8s_openapi::{List, ListableResource};
+use log::{debug, info, warn, Level};
+use tokio::timer::{Delay, Timeout};
+
+use edgelet_core::{ModuleRuntimeState, ModuleSpec};
+use edgelet_docker::DockerConfig;
+use edgelet_utils::log_failure;
+use kube_client::{Client as KubeClient, Error as KubeClientError, TokenSource};
+
+use crate::constants::EDGE_MODULE_LABEL;
+use crate::convert::{sanitize_dns_value, spec_to_deployment};
+use crate::error::{Error, ErrorKind, Result};
+use crate::module::{create_module, module_with_state, KubeModule};
+use crate::settings::Settings;
+use crate::{Deployment, KubeModuleOwner, KubeModuleRuntime};
+
+/// Time to wait before listing and watching objects again after their watch failed.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Time without any event after which a watch is given up and objects are listed again.
+/// Kubernetes closes watches after 300 seconds, so this only elapses when the connection
+/// was lost without being closed.
+const WATCH_TIMEOUT: Duration = Duration::from_secs(330);
+
+/// The deployments, pods and service accounts of the modules of a device, as last seen by
+/// `watch_modules`, and the specs the modules were last created with.
+#[derive(Default)]
+pub struct ModuleCache {
+    deployments: Option<BTreeMap<String, Deployment>>,
+    pods: Option<BTreeMap<String, api_core::Pod>>,
+    service_accounts: Option<BTreeMap<String, api_core::ServiceAccount>>,
+    desired: BTreeMap<String, ModuleSpec<DockerConfig>>,
+    applying: BTreeSet<String>,
+}
+
+impl ModuleCache {
+    /// Lists the modules with their state, or `None` until deployments and pods have been listed.
+    pub fn modules(&self) -> Option<Result<Vec<(KubeModule, ModuleRuntimeState)>>> {
+        let deployments = self.deployments.as_ref()?;
+        let pods = self.pods.as_ref()?;
+
+        let modules = deployments
+            .iter()
+            .filter(|(_, deployment)| module_label(deployment.metadata.as_ref()).is_some())
+            .map(|(name, deployment)| {
+                module_with_state(deployment.clone(), module_pods(pods, name))
+            })
+            .collect();
+        Some(modules)
+    }
+
+    /// Gets a module with its state, or `None` if its deployment is not in the cache, which
+    /// happens until deployments have been listed and shortly after the module was created.
+    pub fn module(&self, id: &str) -> Option<Result<(KubeModule, ModuleRuntimeState)>> {
+        let name = sanitize_dns_value(id).ok()?;
+        let deployment = self
+            .deployments
+            .as_ref()?
+            .get(&name)
+            .filter(|deployment| module_label(deployment.metadata.as_ref()).is_some())?;
+        let pods = self.pods.as_ref()?;
+
+        Some(module_with_state(
+            deployment.clone(),
+            module_pods(pods, &name),
+        ))
+    }
+
+    /// Stops correcting the drift of a module while it is being created.
+    pub fn start_applying(&mut self, name: &str) {
+        self.applying.insert(name.to_string());
+    }
+
+    /// Records the spec a module was created with. The previous spec is kept if creation failed.
+    pub fn finish_applying(&mut self, name: &str, spec: Option<ModuleSpec<DockerConfig>>) {
+        self.applying.remove(name);
+        if let Some(spec) = spec {
+            self.desired.insert(name.to_string(), spec);
+        }
+    }
+
+    /// Stops correcting the drift of a module that is being removed.
+    pub fn forget(&mut self, id: &str) {
+        if let Ok(name) = sanitize_dns_value(id) {
+            self.desired.remove(&name);
+        }
+    }
+
+    /// Drops the deployment of a module that was removed, since the watch may only see its
+    /// deletion later.
+    pub fn removed(&mut self, id: &str) {
+        if let (Some(deployments), Ok(name)) = (self.deployments.as_mut(), sanitize_dns_value(id)) {
+            deployments.remove(&name);
+        }
+    }
+
+    /// Forgets the objects of one kind after their watch failed, since changes may have been
+    /// missed. Modules are listed from Kubernetes again until the objects are listed again.
+    fn unsync<R: Cached>(&mut self) {
+        *R::objects(self) = None;
+    }
+
+    fn apply<R: Cached>(&mut self, change: Change<R>) {
+        let objects = R::objects(self);
+        match change {
+            Change::Synced(items) => {
+                *objects = Some(
+                    items
+                        .into_iter()
+                        .filter_map(|object| name_of(&object).map(|name| (name, object)))
+                        .collect(),
+                );
+            }
+            Change::Applied(object) => {
+                if let (Some(objects), Some(name)) = (objects.as_mut(), name_of(&object)) {
+                    objects.insert(name, object);
+                }
+            }
+            Change::Deleted(object) => {
+                if let (Some(objects), Some(name)) = (objects.as_mut(), name_of(&object)) {
+                    objects.remove(&name);
+                }
+            }
+        }
+    }
+
+    /// Finds the modules whose deployment or service account was deleted or edited since they
+    /// were created, and marks them as being created again so that concurrent changes do not
+    /// find them too. Nothing has drifted until both have been listed.
+    fn drifted(&mut self, settings: &Settings) -> Vec<ModuleSpec<DockerConfig>> {
+        let (deployments, service_accounts) =
+            match (self.deployments.as_ref(), self.service_accounts.as_ref()) {
+                (Some(deployments), Some(service_accounts)) => (deployments, service_accounts),
+                _ => return Vec::new(),
+            };
+
+        let applying = &self.applying;
+        let drifted: Vec<_> = self
+            .desired
+            .iter()
+            .filter(|(name, _)| !applying.contains(name.as_str()))
+            .filter(|(name, spec)| {
+                !service_accounts.contains_key(name.as_str())
+                    || deployments.get(name.as_str()).map_or(true, |deployment| {
+                        is_deployment_drifted(settings, spec, deployment)
+                    })
+            })
+            .map(|(name, spec)| (name.clone(), spec.clone()))
+            .collect();
+
+        drifted
+            .into_iter()
+            .map(|(name, spec)| {
+                self.applying.insert(name);
+                spec
+            })
+            .collect()
+    }
+}
+
+/// Creates a module and records the spec it was created with so that its drift is corrected.
+pub fn apply_module<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    module: ModuleSpec<DockerConfig>,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+{
+    let runtime = runtime.clone();
+    let name = sanitize_dns_value(module.name()).ok();
+    if let Some(name) = &name {
+        runtime
+            .cache()
+            .lock()
+            .expect("Unexpected lock error")
+            .start_applying(name);
+    }
+
+    create_module(&runtime, module.clone()).then(move |result| {
+        if let Some(name) = name {
+            runtime
+                .cache()
+                .lock()
+                .expect("Unexpected lock error")
+                .finish_applying(&name, result.as_ref().ok().map(|_| module));
+        }
+        result
+    })
+}
+
+/// Keeps the cache of a runtime up to date with the deployments, pods and service accounts
+/// of its modules, and creates modules again when they drift from the specs they were
+/// created with. Never completes.
+pub fn watch_modules<T, S>(runtime: &KubeModuleRuntime<T, S>) -> impl Future<Item = (), Error = ()>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+{
+    let deployments = reflect(
+        runtime.clone(),
+        "deployments",
+        |client: &mut KubeClient<T, S>, namespace: &str, selector: &str| {
+            client.list_deployments(namespace, None, Some(selector))
+        },
+        |client: &mut KubeClient<T, S>, namespace: &str, selector: &str, version: &str| {
+            client.watch_deployments(namespace, Some(selector), Some(version))
+        },
+    );
+    let pods = reflect(
+        runtime.clone(),
+        "pods",
+        |client: &mut KubeClient<T, S>, namespace: &str, selector: &str| {
+            client.list_pods(namespace, Some(selector))
+        },
+        |client: &mut KubeClient<T, S>, namespace: &str, selector: &str, version: &str| {
+            client.watch_pods(namespace, Some(selector), Some(version))
+        },
+    );
+    let service_accounts = reflect(
+        runtime.clone(),
+        "service accounts",
+        |client: &mut KubeClient<T, S>, namespace: &str, selector: &str| {
+            client.list_service_accounts(namespace, None, Some(selector))
+        },
+        |client: &mut KubeClient<T, S>, namespace: &str, selector: &str, version: &str| {
+            client.watch_service_accounts(namespace, Some(selector), Some(version))
+        },
+    );
+
+    deployments.join3(pods, service_accounts).map(|_| ())
+}
+
+/// Lists and watches objects of one kind again and again, since Kubernetes closes watches
+/// after a few minutes.
+fn reflect<T, S, R, L, LF, W, WS>(
+    runtime: KubeModuleRuntime<T, S>,
+    kind: &'static str,
+    list: L,
+    watch: W,
+) -> impl Future<Item = (), Error = ()>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+    R: Cached,
+    L: Fn(&mut KubeClient<T, S>, &str, &str) -> LF,
+    LF: Future<Item = List<R>, Error = KubeClientError>,
+    W: Fn(&mut KubeClient<T, S>, &str, &str, &str) -> WS + Clone,
+    WS: Stream<Item = api_meta::WatchEvent<R>, Error = KubeClientError>,
+{
+    future::loop_fn((), move |()| {
+        list_and_watch(&runtime, kind, &list, watch.clone()).then(move |result| match result {
+            Ok(()) => {
+                debug!("Watch of {} closed, listing them again", kind);
+                Either::A(future::ok(Loop::Continue(())))
+            }
+            Err(err) => {
+                warn!(
+                    "Watch of {} failed, listing them again in {} seconds",
+                    kind,
+                    RETRY_DELAY.as_secs()
+                );
+                log_failure(Level::Warn, &err);
+                Either::B(Delay::new(Instant::now() + RETRY_DELAY).then(|_| Ok(Loop::Continue(()))))
+            }
+        })
+    })
+}
+
+/// Lists objects of one kind into the cache, then applies their changes until the watch closes.
+/// If listing or watching fails, the objects are dropped from the cache.
+fn list_and_watch<T, S, R, L, LF, W, WS>(
+    runtime: &KubeModuleRuntime<T, S>,
+    kind: &'static str,
+    list: &L,
+    watch: W,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+    R: Cached,
+    L: Fn(&mut KubeClient<T, S>, &str, &str) -> LF,
+    LF: Future<Item = List<R>, Error = KubeClientError>,
+    W: Fn(&mut KubeClient<T, S>, &str, &str, &str) -> WS,
+    WS: Stream<Item = api_meta::WatchEvent<R>, Error = KubeClientError>,
+{
+    let runtime = runtime.clone();
+    let failed_runtime = runtime.clone();
+    let namespace = runtime.settings().namespace().to_owned();
+    let selector = runtime.settings().device_hub_selector().to_owned();
+
+    let client = runtime.client();
+    let objects = list(
+        &mut *client.lock().expect("Unexpected lock error").borrow_mut(),
+        &namespace,
+        &selector,
+    );
+
+    objects
+        .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+        .and_then(move |objects| {
+            let version = objects
+                .metadata
+                .and_then(|meta| meta.resource_version)
+                .unwrap_or_default();
+            debug!(
+                "Listed {} {} at version {}",
+                objects.items.len(),
+                kind,
+                version
+            );
+
+            let events = watch(
+                &mut *client.lock().expect("Unexpected lock error").borrow_mut(),
+                &namespace,
+                &selector,
+                &version,
+            )
+            .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)));
+            let events = Timeout::new(events, WATCH_TIMEOUT).map_err(move |err| {
+                err.into_inner().unwrap_or_else(|| {
+                    Error::from(ErrorKind::Watch(format!(
+                        "no {} event received in {} seconds",
+                        kind,
+                        WATCH_TIMEOUT.as_secs()
+                    )))
+                })
+            });
+
+            apply_change(&runtime, Change::Synced(objects.items)).and_then(move |_| {
+                events.for_each(move |event| match event {
+                    api_meta::WatchEvent::Added(object)
+                    | api_meta::WatchEvent::Modified(object) => {
+                        Either::A(apply_change(&runtime, Change::Applied(object)))
+                    }
+                    api_meta::WatchEvent::Deleted(object) => {
+                        Either::A(apply_change(&runtime, Change::Deleted(object)))
+                    }
+                    api_meta::WatchEvent::ErrorStatus(status) => Either::B(future::err(
+                        Error::from(ErrorKind::Watch(status.message.unwrap_or_default())),
+                    )),
+                    _ => Either::B(future::ok(())),
+                })
+            })
+        })
+        .map_err(move |err| {
+            failed_runtime
+                .cache()
+                .lock()
+                .expect("Unexpected lock error")
+                .unsync::<R>();
+            err
+        })
+}
+
+/// Updates the cache, then creates the modules that drifted again, one at a time.
+fn apply_change<T, S, R>(
+    runtime: &KubeModuleRuntime<T, S>,
+    change: Change<R>,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+    R: Cached,
+{
+    let drifted = {
+        let mut cache = runtime.cache().lock().expect("Unexpected lock error");
+        cache.apply(change);
+        if R::RECONCILED {
+            cache.drifted(runtime.settings())
+        } else {
+            Vec::new()
+        }
+    };
+
+    let runtime = runtime.clone();
+    stream::iter_ok(drifted).for_each(move |module| {
+        info!(
+            "Module {} has drifted from the spec it was created with, creating it again",
+            module.name()
+        );
+        apply_module(&runtime, module)
+    })
+}
+
+enum Change<R> {
+    Synced(Vec<R>),
+    Applied(R),
+    Deleted(R),
+}
+
+/// The kinds of objects kept in the cache.
+trait Cached: ListableResource + Sized {
+    /// Whether changes of these objects can make a module drift from its spec.
+    const RECONCILED: bool;
+
+    fn metadata(&self) -> Option<&api_meta::ObjectMeta>;
+
+    fn objects(cache: &mut ModuleCache) -> &mut Option<BTreeMap<String, Self>>;
+}
+
+impl Cached for Deployment {
+    const RECONCILED: bool = true;
+
+    fn metadata(&self) -> Option<&api_meta::ObjectMeta> {
+        self.metadata.as_ref()
+    }
+
+    fn objects(cache: &mut ModuleCache) -> &mut Option<BTreeMap<String, Self>> {
+        &mut cache.deployments
+    }
+}
+
+impl Cached for api_core::Pod {
+    const RECONCILED: bool = false;
+
+    fn metadata(&self) -> Option<&api_meta::ObjectMeta> {
+        self.metadata.as_ref()
+    }
+
+    fn objects(cache: &mut ModuleCache) -> &mut Option<BTreeMap<String, Self>> {
+        &mut cache.pods
+    }
+}
+
+impl Cached for api_core::ServiceAccount {
+    const RECONCILED: bool = true;
+
+    fn metadata(&self) -> Option<&api_meta::ObjectMeta> {
+        self.metadata.as_ref()
+    }
+
+    fn objects(cache: &mut ModuleCache) -> &mut Option<BTreeMap<String, Self>> {
+        &mut cache.service_accounts
+    }
+}
+
+fn name_of<R: Cached>(object: &R) -> Option<String> {
+    object.metadata().and_then(|meta| meta.name.clone())
+}
+
+fn module_label(metadata: Option<&api_meta::ObjectMeta>) -> Option<&str> {
+    metadata
+        .and_then(|meta| meta.labels.as_ref())
+        .and_then(|labels| labels.get(EDGE_MODULE_LABEL))
+        .map(String::as_str)
+}
+
+fn module_pods(pods: &BTreeMap<String, api_core::Pod>, name: &str) -> Vec<api_core::Pod> {
+    pods.values()
+        .filter(|pod| module_label(pod.metadata.as_ref()) == Some(name))
+        .cloned()
+        .collect()
+}
+
+fn is_deployment_drifted(
+    settings: &Settings,
+    module: &ModuleSpec<DockerConfig>,
+    current: &Deployment,
+) -> bool {
+    // The owner is read back from the deployment, which has drifted if it lost it.
+    let owner = current
+        .metadata
+        .as_ref()
+        .and_then(|meta| meta.owner_references.as_ref())
+        .and_then(|owners| owners.first())
+        .map(|owner| {
+            KubeModuleOwner::new(
+                owner.name.clone(),
+                owner.api_version.clone(),
+                owner.kind.clone(),
+                owner.uid.clone(),
+            )
+        });
+
+    match owner.map(|owner| spec_to_deployment(settings, module, &owner)) {
+        Some(Ok((_, desired))) => {
+            DeploymentFields::from(current) != DeploymentFields::from(&desired)
+        }
+        Some(Err(err)) => {
+            log_failure(Level::Warn, &err);
+            false
+        }
+        None => true,
+    }
+}
+
+/// The fields of a deployment set by `spec_to_deployment` which Kubernetes does not default.
+/// Replicas and pod annotations are left out since starting, stopping and restarting a
+/// module changes them.
+#[derive(Debug, PartialEq)]
+struct DeploymentFields {
+    labels: BTreeMap<String, String>,
+    owner_references: Vec<api_meta::OwnerReference>,
+    selector: BTreeMap<String, String>,
+    pod_labels: BTreeMap<String, String>,
+    service_account_name: Option<String>,
+    containers: Vec<ContainerFields>,
+    volumes: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+struct ContainerFields {
+    name: String,
+    image: Option<String>,
+    command: Vec<String>,
+    args: Vec<String>,
+    env: Vec<api_core::EnvVar>,
+}
+
+impl From<&Deployment> for DeploymentFields {
+    fn from(deployment: &Deployment) -> Self {
+        let metadata = deployment.metadata.clone().unwrap_or_default();
+        let spec = deployment.spec.clone().unwrap_or_default();
+        let pod_metadata = spec.template.metadata.unwrap_or_default();
+        let pod_spec = spec.template.spec.unwrap_or_default();
+
+        DeploymentFields {
+            labels: metadata.labels.unwrap_or_default(),
+            owner_references: metadata.owner_references.unwrap_or_default(),
+            selector: spec.selector.match_labels.unwrap_or_default(),
+            pod_labels: pod_metadata.labels.unwrap_or_default(),
+            service_account_name: pod_spec.service_account_name,
+            containers: pod_spec
+                .containers
+                .into_iter()
+                .map(|container| ContainerFields {
+                    name: container.name,
+                    image: container.image,
+                    command: container.command.unwrap_or_default(),
+                    args: container.args.unwrap_or_default(),
+                    env: container.env.unwrap_or_default(),
+                })
+                .collect(),
+            volumes: pod_spec
+                .volumes
+                .unwrap_or_default()
+                .into_iter()
+                .map(|volume| volume.name)
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use hyper::service::service_fn;
+    use hyper::{Body, Method, Request, StatusCode};
+    use k8s_openapi::api::core::v1 as api_core;
+    use maplit::btreemap;
+    use serde_json::json;
+    use tokio::runtime::Runtime;
+
+    use docker::models::ContainerCreateBody;
+    use edgelet_core::{ImagePullPolicy, Module, ModuleRuntime, ModuleSpec, ModuleStatus};
+    use edgelet_docker::DockerConfig;
+    use edgelet_test_utils::routes;
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+    use kube_client::Client as KubeClient;
+
+    use super::{list_and_watch, Change, ModuleCache};
+    use crate::convert::{spec_to_deployment, spec_to_service_account};
+    use crate::tests::{
+        create_module_owner, create_runtime, make_settings, not_found_handler, response,
+        TestTokenSource,
+    };
+    use crate::Deployment;
+
+    fn create_module_spec(image: &str) -> ModuleSpec<DockerConfig> {
+        ModuleSpec::new(
+            "$edgeHub".to_string(),
+            "docker".to_string(),
+            DockerConfig::new(image.to_string(), ContainerCreateBody::new(), None).unwrap(),
+            HashMap::new(),
+            ImagePullPolicy::default(),
+        )
+        .unwrap()
+    }
+
+    fn deployment(image: &str) -> Deployment {
+        let settings = make_settings(None);
+        let (_, deployment) = spec_to_deployment(
+            &settings,
+            &create_module_spec(image),
+            &create_module_owner(),
+        )
+        .unwrap();
+        deployment
+    }
+
+    fn service_account() -> api_core::ServiceAccount {
+        let settings = make_settings(None);
+        let (_, service_account) = spec_to_service_account(
+            &settings,
+            &create_module_spec("edgehub:1.0"),
+            &create_module_owner(),
+        )
+        .unwrap();
+        service_account
+    }
+
+    fn running_pod() -> api_core::Pod {
+        serde_json::from_value(json!({
+            "metadata": {
+                "name": "edgehub-5c9d6fb7c4-wq7rx",
+                "labels": {
+                    "net.azure-devices.edge.module": "edgehub"
+                },
+                "annotations": {
+                    "net.azure-devices.edge.original-moduleid": "$edgeHub"
+                }
+            },
+            "spec": {
+                "containers": [
+                    {
+                        "name": "edgehub",
+                        "image": "edgehub:1.0"
+                    }
+                ]
+            },
+            "status": {
+                "phase": "Running",
+                "containerStatuses": [
+                    {
+                        "name": "edgehub",
+                        "image": "edgehub:1.0",
+                        "imageID": "docker-pullable://edgehub@sha256:1234",
+                        "ready": true,
+                        "restartCount": 0,
+                        "state": {
+                            "running": {
+                                "startedAt": "2019-11-05T21:41:49Z"
+                            }
+                        }
+                    }
+                ]
+            }
+        }))
+        .unwrap()
+    }
+
+    fn synced_cache() -> ModuleCache {
+        let mut cache = ModuleCache::default();
+        cache.apply(Change::Synced(vec![deployment("edgehub:1.0")]));
+        cache.apply(Change::Synced(vec![running_pod()]));
+        cache.apply(Change::Synced(vec![service_account()]));
+        cache
+    }
+
+    #[test]
+    fn cache_is_empty_until_deployments_and_pods_are_listed() {
+        let mut cache = ModuleCache::default();
+        assert!(cache.modules().is_none());
+
+        cache.apply(Change::Synced(vec![deployment("edgehub:1.0")]));
+        assert!(cache.modules().is_none());
+        assert!(cache.module("$edgeHub").is_none());
+
+        cache.apply(Change::<api_core::Pod>::Synced(vec![]));
+        let modules = cache.modules().unwrap().unwrap();
+        assert_eq!(1, modules.len());
+    }
+
+    #[test]
+    fn cache_serves_modules_with_state_of_their_pods() {
+        let cache = synced_cache();
+
+        let (module, state) = cache.module("$edgeHub").unwrap().unwrap();
+        assert_eq!("$edgeHub", module.name());
+        assert_eq!("edgehub:1.0", module.config().image());
+        assert_eq!(&ModuleStatus::Running, state.status());
+
+        assert!(cache.module("$edgeAgent").is_none());
+    }
+
+    #[test]
+    fn cache_applies_watched_changes() {
+        let mut cache = synced_cache();
+
+        cache.apply(Change::Applied(deployment("edgehub:1.1")));
+        let (module, _) = cache.module("$edgeHub").unwrap().unwrap();
+        assert_eq!("edgehub:1.1", module.config().image());
+
+        cache.apply(Change::Deleted(deployment("edgehub:1.1")));
+        assert!(cache.module("$edgeHub").is_none());
+        assert!(cache.modules().unwrap().unwrap().is_empty());
+    }
+
+    #[test]
+    fn module_as_created_has_not_drifted() {
+        let settings = make_settings(None);
+        let mut cache = synced_cache();
+        cache.finish_applying("edgehub", Some(create_module_spec("edgehub:1.0")));
+
+        // Kubernetes adds status and defaults which are not compared.
+        let mut current = deployment("edgehub:1.0");
+        if let Some(spec) = current.spec.as_mut() {
+            spec.replicas = Some(0);
+            spec.progress_deadline_seconds = Some(600);
+        }
+        cache.apply(Change::Applied(current));
+
+        assert!(cache.drifted(&settings).is_empty());
+    }
+
+    #[test]
+    fn edited_deployment_has_drifted() {
+        let settings = make_settings(None);
+        let mut cache = synced_cache();
+        cache.finish_applying("edgehub", Some(create_module_spec("edgehub:1.0")));
+
+        cache.apply(Change::Applied(deployment("edgehub:1.1")));
+
+        let drifted = cache.drifted(&settings);
+        assert_eq!(1, drifted.len());
+        assert_eq!("edgehub:1.0", drifted[0].config().image());
+    }
+
+    #[test]
+    fn deleted_service_account_has_drifted() {
+        let settings = make_settings(None);
+        let mut cache = synced_cache();
+        cache.finish_applying("edgehub", Some(create_module_spec("edgehub:1.0")));
+
+        cache.apply(Change::Deleted(service_account()));
+
+        assert_eq!(1, cache.drifted(&settings).len());
+    }
+
+    #[test]
+    fn modules_being_created_or_removed_do_not_drift() {
+        let settings = make_settings(None);
+        let mut cache = synced_cache();
+        cache.finish_applying("edgehub", Some(create_module_spec("edgehub:1.0")));
+        cache.apply(Change::Deleted(deployment("edgehub:1.0")));
+
+        cache.start_applying("edgehub");
+        assert!(cache.drifted(&settings).is_empty());
+
+        cache.finish_applying("edgehub", None);
+        assert_eq!(1, cache.drifted(&settings).len());
+
+        // A drifted module is not found again while it is being created.
+        assert!(cache.drifted(&settings).is_empty());
+
+        cache.finish_applying("edgehub", None);
+        cache.forget("$edgeHub");
+        assert!(cache.drifted(&settings).is_empty());
+    }
+
+    #[test]
+    fn it_lists_then_watches_deployments_into_cache() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/apis/apps/v1/namespaces/{}/deployments", settings.namespace()) => deployment_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+        runtime
+            .cache()
+            .lock()
+            .unwrap()
+            .apply(Change::<api_core::Pod>::Synced(vec![]));
+
+        let task = list_and_watch(
+            &runtime,
+            "deployments",
+            &|client: &mut KubeClient<TestTokenSource, _>, namespace: &str, selector: &str| {
+                client.list_deployments(namespace, None, Some(selector))
+            },
+            |client: &mut KubeClient<TestTokenSource, _>,
+             namespace: &str,
+             selector: &str,
+             version: &str| {
+                client.watch_deployments(namespace, Some(selector), Some(version))
+            },
+        );
+
+        Runtime::new().unwrap().block_on(task).unwrap();
+
+        let (module, _) = runtime
+            .cache()
+            .lock()
+            .unwrap()
+            .module("$edgeHub")
+            .unwrap()
+            .unwrap();
+        assert_eq!("edgehub:1.1", module.config().image());
+    }
+
+    #[test]
+    fn failed_watch_lists_modules_from_api_again() {
+        let settings = make_settings(None);
+        let pod_lists = Arc::new(AtomicUsize::new(0));
+
+        let dispatch_table = routes!(
+            GET format!("/api/v1/namespaces/{}/pods", settings.namespace()) => failing_pod_handler(pod_lists.clone()),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+        *runtime.cache().lock().unwrap() = synced_cache();
+
+        let task = list_and_watch(
+            &runtime,
+            "pods",
+            &|client: &mut KubeClient<TestTokenSource, _>, namespace: &str, selector: &str| {
+                client.list_pods(namespace, Some(selector))
+            },
+            |client: &mut KubeClient<TestTokenSource, _>,
+             namespace: &str,
+             selector: &str,
+             version: &str| {
+                client.watch_pods(namespace, Some(selector), Some(version))
+            },
+        );
+
+        let mut tokio_runtime = Runtime::new().unwrap();
+        tokio_runtime.block_on(task).unwrap_err();
+        assert_eq!(1, pod_lists.load(Ordering::SeqCst));
+        assert!(runtime.cache().lock().unwrap().modules().is_none());
+
+        let modules = tokio_runtime.block_on(runtime.list()).unwrap();
+        assert_eq!(1, modules.len());
+        assert_eq!("$edgeHub", modules[0].name());
+        assert_eq!(2, pod_lists.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn removed_module_is_dropped_from_cache() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            DELETE format!("/apis/apps/v1/namespaces/{}/deployments/edgehub", settings.namespace()) => delete_handler(),
+            DELETE format!("/api/v1/namespaces/{}/serviceaccounts/edgehub", settings.namespace()) => delete_handler(),
+            GET format!("/api/v1/namespaces/{}/services", settings.namespace()) => empty_service_list_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+        *runtime.cache().lock().unwrap() = synced_cache();
+
+        Runtime::new()
+            .unwrap()
+            .block_on(runtime.remove("$edgeHub"))
+            .unwrap();
+
+        let cache = runtime.cache().lock().unwrap();
+        assert!(cache.module("$edgeHub").is_none());
+        assert!(cache.modules().unwrap().unwrap().is_empty());
+    }
+
+    fn delete_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "Status",
+                    "apiVersion": "v1",
+                    "metadata": {},
+                    "status": "Success"
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn empty_service_list_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "ServiceList",
+                    "apiVersion": "v1",
+                    "items": []
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn failing_pod_handler(
+        lists: Arc<AtomicUsize>,
+    ) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |req| {
+            let watch = req
+                .uri()
+                .query()
+                .map_or(false, |query| query.contains("watch=true"));
+            if watch {
+                response(StatusCode::OK, || {
+                    json!({
+                        "type": "ERROR",
+                        "object": {
+                            "kind": "Status",
+                            "apiVersion": "v1",
+                            "metadata": {},
+                            "status": "Failure",
+                            "message": "too old resource version: 42",
+                            "reason": "Expired",
+                            "code": 410
+                        }
+                    })
+                    .to_string()
+                })
+            } else {
+                lists.fetch_add(1, Ordering::SeqCst);
+                response(StatusCode::OK, || {
+                    json!({
+                        "kind": "PodList",
+                        "apiVersion": "v1",
+                        "metadata": {
+                            "resourceVersion": "42"
+                        },
+                        "items": [running_pod()]
+                    })
+                    .to_string()
+                })
+            }
+        }
+    }
+
+    fn deployment_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |req| {
+            let watch = req
+                .uri()
+                .query()
+                .map_or(false, |query| query.contains("watch=true"));
+            if watch {
+                assert!(req.uri().query().unwrap().contains("resourceVersion=42"));
+                response(StatusCode::OK, || {
+                    json!({
+                        "type": "MODIFIED",
+                        "object": deployment("edgehub:1.1")
+                    })
+                    .to_string()
+                })
+            } else {
+                response(StatusCode::OK, || {
+                    json!({
+                        "kind": "DeploymentList",
+                        "apiVersion": "apps/v1",
+                        "metadata": {
+                            "resourceVersion": "42"
+                        },
+                        "items": [deployment("edgehub:1.0")]
+                    })
+                    .to_string()
+                })
+            }
+        }
+    }
+}
@@ -7,19 +7,30 @@ use hyper::service::{NewService, Service};
 use hyper::{header, Body, Method, Request, Response, StatusCode};
 use log::{debug, info};
 
+use crate::metrics::Metrics;
 use crate::{logging, Error};
 
 #[derive(Clone)]
-pub struct ApiService;
+pub struct ApiService {
+    metrics: Metrics,
+}
 
 impl ApiService {
-    pub fn new() -> Self {
-        ApiService
+    pub fn new(metrics: Metrics) -> Self {
+        ApiService { metrics }
     }
 
-    fn handle(req: &Request<Body>) -> Result<Response<Body>, Error> {
+    fn handle(&self, req: &Request<Body>) -> Result<Response<Body>, Error> {
         match (req.method(), req.uri().path()) {
             (&Method::GET, "/health") => Ok(Response::new(Body::empty())),
+            (&Method::GET, "/metrics") => {
+                let body = self.metrics.render();
+                Ok(Response::builder()
+                    .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+                    .header(header::CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .expect("response builder failure"))
+            }
             _ => Ok(Response::builder()
                 .status(StatusCode::NOT_FOUND)
                 .body(Body::empty())
@@ -38,7 +49,8 @@ impl Service for ApiService {
         let request = format!("{} {} {:?}", req.method(), req.uri(), req.version());
         debug!("Starting api request {}", request);
 
-        let fut = Self::handle(&req)
+        let fut = self
+            .handle(&req)
             .into_future()
             .map_err(|err: Error| {
                 logging::failure(&err);
@@ -73,3 +85,40 @@ impl NewService for ApiService {
         future::ok(self.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use failure::Compat;
+    use futures::{Future, Stream};
+    use hyper::service::Service;
+    use hyper::{header, Body, Request, StatusCode};
+    use tokio::runtime::current_thread;
+
+    use crate::api::ApiService;
+    use crate::metrics::Metrics;
+    use crate::{Error, ErrorKind};
+
+    #[test]
+    fn it_serves_metrics() {
+        let metrics = Metrics::new();
+        metrics.service("management");
+        let mut api = ApiService::new(metrics);
+        let req = Request::get("/metrics").body(Body::empty()).unwrap();
+
+        let task = api.call(req).map_err(Compat::into_inner).and_then(|res| {
+            let status = res.status();
+            let content_type = res.headers()[header::CONTENT_TYPE].clone();
+            res.into_body()
+                .concat2()
+                .map(move |body| (status, content_type, body.into_bytes()))
+                .map_err(|_| Error::from(ErrorKind::Generic))
+        });
+
+        let (status, content_type, body) = current_thread::block_on_all(task).unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "text/plain; version=0.0.4");
+        assert!(std::str::from_utf8(body.as_ref())
+            .unwrap()
+            .contains("iotedge_proxy_upstream_errors_total{service=\"management\"} 0\n"));
+    }
+}
@@ -29,6 +29,12 @@ pub enum ErrorKind {
     #[fail(display = "Could not make an HTTP request: {:?}", _0)]
     HttpRequest(String),
 
+    #[fail(display = "HTTP request timed out: {:?}", _0)]
+    Timeout(String),
+
+    #[fail(display = "Could not read HTTP request body")]
+    RequestBody,
+
     #[fail(display = "Invalid URI to parse: {:?}", _0)]
     Uri(String),
 
@@ -75,6 +81,14 @@ impl Error {
     pub fn kind(&self) -> &ErrorKind {
         self.inner.get_context()
     }
+
+    /// Whether the error means the backend did not respond, so the request can be sent again.
+    pub fn is_upstream(&self) -> bool {
+        match self.kind() {
+            ErrorKind::HttpRequest(_) | ErrorKind::Timeout(_) => true,
+            _ => false,
+        }
+    }
 }
 
 impl Fail for Error {
@@ -104,6 +118,7 @@ impl IntoResponse for Error {
 
         let status_code = match *self.kind() {
             ErrorKind::HttpRequest(_) => StatusCode::BAD_GATEWAY,
+            ErrorKind::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
@@ -16,6 +16,7 @@ mod api;
 pub mod app;
 mod error;
 pub mod logging;
+mod metrics;
 mod proxy;
 mod routine;
 mod settings;
@@ -23,7 +24,7 @@ pub mod signal;
 
 pub use error::{Error, ErrorKind, InitializeErrorReason};
 pub use routine::Routine;
-pub use settings::{ApiSettings, ServiceSettings, Settings};
+pub use settings::{ApiSettings, IdentitySettings, RouteSettings, ServiceSettings, Settings};
 
 use hyper::{Body, Response};
 
@@ -42,6 +43,7 @@ mod tls {
     use openssl::error::ErrorStack;
     use openssl::hash::MessageDigest;
     use openssl::nid::Nid;
+    use openssl::pkcs12::Pkcs12;
     use openssl::pkey::PKey;
     use openssl::rsa::Rsa;
     use openssl::x509::extension::{
@@ -83,6 +85,7 @@ mod tls {
         public_key: Option<PathBuf>,
         private_key: Option<PathBuf>,
         cert: Option<PathBuf>,
+        identity: Option<(PathBuf, String)>,
         common_name: Option<String>,
     }
 
@@ -102,6 +105,11 @@ mod tls {
             self
         }
 
+        pub fn identity(&mut self, path: &Path, password: &str) -> &Self {
+            self.identity = Some((path.to_path_buf(), password.to_string()));
+            self
+        }
+
         pub fn common_name(&mut self, name: String) -> &Self {
             self.common_name = Some(name);
             self
@@ -160,6 +168,11 @@ mod tls {
                 fs::write(cert_path, x509.to_pem()?)?;
             }
 
+            if let Some((identity_path, password)) = &self.identity {
+                let identity = Pkcs12::builder().build(password, "identity", &pkey, &x509)?;
+                fs::write(identity_path, identity.to_der()?)?;
+            }
+
             Ok(x509)
         }
     }
@@ -0,0 +1,199 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::StatusCode;
+
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Clone, Default)]
+pub struct Metrics {
+    services: Arc<Mutex<BTreeMap<String, ServiceStats>>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    pub fn service(&self, name: &str) -> ServiceMetrics {
+        self.services
+            .lock()
+            .expect("Unexpected lock error")
+            .entry(name.to_string())
+            .or_insert_with(ServiceStats::default);
+
+        ServiceMetrics {
+            name: name.to_string(),
+            metrics: self.clone(),
+        }
+    }
+
+    /// Renders collected metrics in Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let services = self.services.lock().expect("Unexpected lock error");
+        let mut output = String::new();
+
+        output.push_str(
+            "# HELP iotedge_proxy_requests_total Number of requests handled by a proxy service\n",
+        );
+        output.push_str("# TYPE iotedge_proxy_requests_total counter\n");
+        for (name, stats) in services.iter() {
+            for (code, count) in &stats.requests {
+                output.push_str(&format!(
+                    "iotedge_proxy_requests_total{{service=\"{}\",code=\"{}\"}} {}\n",
+                    escape(name),
+                    code,
+                    count
+                ));
+            }
+        }
+
+        output.push_str(
+            "# HELP iotedge_proxy_request_duration_seconds Time spent to handle a request by a proxy service\n",
+        );
+        output.push_str("# TYPE iotedge_proxy_request_duration_seconds histogram\n");
+        for (name, stats) in services.iter() {
+            let name = escape(name);
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(stats.latency_buckets.iter()) {
+                output.push_str(&format!(
+                    "iotedge_proxy_request_duration_seconds_bucket{{service=\"{}\",le=\"{}\"}} {}\n",
+                    name, bound, count
+                ));
+            }
+            output.push_str(&format!(
+                "iotedge_proxy_request_duration_seconds_bucket{{service=\"{}\",le=\"+Inf\"}} {}\n",
+                name, stats.latency_count
+            ));
+            output.push_str(&format!(
+                "iotedge_proxy_request_duration_seconds_sum{{service=\"{}\"}} {}\n",
+                name, stats.latency_sum
+            ));
+            output.push_str(&format!(
+                "iotedge_proxy_request_duration_seconds_count{{service=\"{}\"}} {}\n",
+                name, stats.latency_count
+            ));
+        }
+
+        output.push_str(
+            "# HELP iotedge_proxy_upstream_errors_total Number of requests a proxy service failed to get a response from backend for\n",
+        );
+        output.push_str("# TYPE iotedge_proxy_upstream_errors_total counter\n");
+        for (name, stats) in services.iter() {
+            output.push_str(&format!(
+                "iotedge_proxy_upstream_errors_total{{service=\"{}\"}} {}\n",
+                escape(name),
+                stats.upstream_errors
+            ));
+        }
+
+        output
+    }
+}
+
+#[derive(Clone)]
+pub struct ServiceMetrics {
+    name: String,
+    metrics: Metrics,
+}
+
+impl ServiceMetrics {
+    pub fn observe(&self, status: StatusCode, elapsed: Duration, upstream_error: bool) {
+        let mut services = self.metrics.services.lock().expect("Unexpected lock error");
+        let stats = services
+            .entry(self.name.clone())
+            .or_insert_with(ServiceStats::default);
+
+        *stats.requests.entry(status.as_u16()).or_insert(0) += 1;
+
+        let elapsed = elapsed.as_secs_f64();
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(stats.latency_buckets.iter_mut()) {
+            if elapsed <= *bound {
+                *count += 1;
+            }
+        }
+        stats.latency_sum += elapsed;
+        stats.latency_count += 1;
+
+        if upstream_error {
+            stats.upstream_errors += 1;
+        }
+    }
+}
+
+#[derive(Default)]
+struct ServiceStats {
+    requests: BTreeMap<u16, u64>,
+    latency_buckets: [u64; 11],
+    latency_sum: f64,
+    latency_count: u64,
+    upstream_errors: u64,
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use hyper::StatusCode;
+
+    use crate::metrics::Metrics;
+
+    #[test]
+    fn it_renders_registered_service_without_requests() {
+        let metrics = Metrics::new();
+        metrics.service("management");
+
+        let output = metrics.render();
+
+        assert!(output
+            .contains("iotedge_proxy_request_duration_seconds_count{service=\"management\"} 0\n"));
+        assert!(output.contains("iotedge_proxy_upstream_errors_total{service=\"management\"} 0\n"));
+        assert!(!output.contains("iotedge_proxy_requests_total{"));
+    }
+
+    #[test]
+    fn it_counts_requests_latencies_and_upstream_errors_per_service() {
+        let metrics = Metrics::new();
+        let management = metrics.service("management");
+        let workload = metrics.service("workload");
+
+        management.observe(StatusCode::OK, Duration::from_millis(20), false);
+        management.observe(StatusCode::OK, Duration::from_millis(200), false);
+        management.observe(StatusCode::BAD_GATEWAY, Duration::from_secs(20), true);
+        workload.observe(StatusCode::NOT_FOUND, Duration::from_millis(1), false);
+
+        let output = metrics.render();
+
+        assert!(output
+            .contains("iotedge_proxy_requests_total{service=\"management\",code=\"200\"} 2\n"));
+        assert!(output
+            .contains("iotedge_proxy_requests_total{service=\"management\",code=\"502\"} 1\n"));
+        assert!(
+            output.contains("iotedge_proxy_requests_total{service=\"workload\",code=\"404\"} 1\n")
+        );
+        assert!(output.contains(
+            "iotedge_proxy_request_duration_seconds_bucket{service=\"management\",le=\"0.025\"} 1\n"
+        ));
+        assert!(output.contains(
+            "iotedge_proxy_request_duration_seconds_bucket{service=\"management\",le=\"10\"} 2\n"
+        ));
+        assert!(output.contains(
+            "iotedge_proxy_request_duration_seconds_bucket{service=\"management\",le=\"+Inf\"} 3\n"
+        ));
+        assert!(output
+            .contains("iotedge_proxy_request_duration_seconds_count{service=\"management\"} 3\n"));
+        assert!(output.contains("iotedge_proxy_upstream_errors_total{service=\"management\"} 1\n"));
+        assert!(output.contains("iotedge_proxy_upstream_errors_total{service=\"workload\"} 0\n"));
+    }
+}
@@ -1,15 +1,19 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::convert::AsRef;
+use std::sync::Arc;
+use std::time::Instant;
 
 use failure::{Fail, ResultExt};
-use futures::{Future, IntoFuture};
+use futures::future::{self, Either, Loop};
+use futures::{Future, IntoFuture, Stream};
 use hyper::client::connect::Connect;
 use hyper::client::HttpConnector;
 use hyper::header::HeaderValue;
 use hyper::{header, Body, Client as HyperClient, Request, Response, Uri};
 use hyper_tls::HttpsConnector;
-use log::info;
+use log::{debug, info};
+use tokio::timer::Timeout;
 use url::percent_encoding::percent_decode;
 use url::Url;
 
@@ -22,7 +26,7 @@ where
     T: TokenSource,
 {
     config: Config<T>,
-    client: S,
+    client: Arc<S>,
 }
 
 impl<T> Client<T, HyperHttpClient<HttpsConnector<HttpConnector>>>
@@ -48,45 +52,126 @@ where
     T: TokenSource,
 {
     pub fn with_client(client: S, config: Config<T>) -> Self {
-        Client { config, client }
+        Client {
+            config,
+            client: Arc::new(client),
+        }
     }
 }
 
 impl<T, S> Client<T, S>
 where
     T: TokenSource,
-    S: HttpClient,
+    S: HttpClient + Send + Sync + 'static,
 {
-    pub fn request(
-        &self,
-        mut req: Request<Body>,
-    ) -> impl Future<Item = Response<Body>, Error = Error> {
-        build_uri(self.config.host().clone(), req.uri())
-            .and_then(|url| {
-                // set a full URL to redirect request to
-                *req.uri_mut() = url;
-
-                // set host value in request header
-                if let Ok(host) = req.uri().host().unwrap_or_default().parse() {
-                    req.headers_mut().insert(header::HOST, host);
-                }
+    pub fn request(&self, req: Request<Body>) -> impl Future<Item = Response<Body>, Error = Error> {
+        // only requests that are safe to repeat are sent again when backend did not respond
+        let retries = if req.method().is_idempotent() {
+            self.config.retries()
+        } else {
+            0
+        };
+        // the timeout bounds the whole request rather than every attempt to send it
+        let deadline = self
+            .config
+            .timeout()
+            .map(|timeout| Instant::now() + timeout);
+        let client = self.client.clone();
+
+        self.prepare(req).into_future().and_then(move |req| {
+            if retries == 0 {
+                return Either::A(send(&*client, req, deadline));
+            }
+
+            // request body has to be buffered to be sent more than once
+            let (parts, body) = req.into_parts();
+            let template = Request::from_parts(parts, ());
+            let fut = body
+                .concat2()
+                .map_err(|err| Error::from(err.context(ErrorKind::RequestBody)))
+                .and_then(move |body| {
+                    let body = body.into_bytes();
+                    future::loop_fn(0, move |attempt| {
+                        let req = copy_request(&template, body.clone());
+                        send(&*client, req, deadline).then(move |result| match result {
+                            Err(ref err)
+                                if attempt < retries
+                                    && err.is_upstream()
+                                    && deadline
+                                        .map_or(true, |deadline| Instant::now() < deadline) =>
+                            {
+                                debug!(
+                                    "Retrying request after attempt {} failed: {}",
+                                    attempt + 1,
+                                    err
+                                );
+                                Ok(Loop::Continue(attempt + 1))
+                            }
+                            Err(err) => Err(err),
+                            Ok(res) => Ok(Loop::Break(res)),
+                        })
+                    })
+                });
+
+            Either::B(fut)
+        })
+    }
 
-                // add authorization header with bearer token to authenticate request
-                if let Some(token) = self.config.token().get() {
-                    let token = HeaderValue::from_str(format!("Bearer {}", token).as_str())
-                        .with_context(|_| ErrorKind::HeaderValue("Authorization".to_owned()))?;
+    fn prepare(&self, mut req: Request<Body>) -> Result<Request<Body>, Error> {
+        let is_routed = self.config.route(&req).is_some();
 
-                    req.headers_mut().insert(header::AUTHORIZATION, token);
-                }
+        // set a full URL to redirect request to
+        let url = build_uri(self.config.backend(&req).clone(), req.uri())?;
+        *req.uri_mut() = url;
 
-                Ok(req)
-            })
-            .map(|req| self.client.request(req))
-            .into_future()
-            .flatten()
+        // set host value in request header
+        if let Ok(host) = req.uri().host().unwrap_or_default().parse() {
+            req.headers_mut().insert(header::HOST, host);
+        }
+
+        // add authorization header with bearer token to authenticate request, only to the
+        // default backend since route backends are other services the token is not meant for
+        if let Some(token) = self.config.token().get().filter(|_| !is_routed) {
+            let token = HeaderValue::from_str(format!("Bearer {}", token).as_str())
+                .with_context(|_| ErrorKind::HeaderValue("Authorization".to_owned()))?;
+
+            req.headers_mut().insert(header::AUTHORIZATION, token);
+        }
+
+        Ok(req)
     }
 }
 
+fn send<S>(client: &S, req: Request<Body>, deadline: Option<Instant>) -> ResponseFuture
+where
+    S: HttpClient,
+{
+    match deadline {
+        Some(deadline) => {
+            let request = format!("{} {} {:?}", req.method(), req.uri(), req.version());
+            let fut = Timeout::new_at(client.request(req), deadline).map_err(move |err| {
+                err.into_inner()
+                    .unwrap_or_else(|| Error::from(ErrorKind::Timeout(request)))
+            });
+
+            Box::new(fut)
+        }
+        None => client.request(req),
+    }
+}
+
+fn copy_request<B>(template: &Request<()>, body: B) -> Request<Body>
+where
+    B: Into<Body>,
+{
+    let mut req = Request::new(body.into());
+    *req.method_mut() = template.method().clone();
+    *req.uri_mut() = template.uri().clone();
+    *req.version_mut() = template.version();
+    *req.headers_mut() = template.headers().clone();
+    req
+}
+
 fn build_uri(base_url: Url, requested_uri: &Uri) -> Result<Uri, Error> {
     let path = percent_decode(requested_uri.path().as_bytes())
         .decode_utf8()
@@ -152,14 +237,18 @@ pub trait HttpClient {
 
 #[cfg(test)]
 mod tests {
-    use futures::{Future, Stream};
-    use hyper::{Body, Request, Response, Uri};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use futures::{future, Future, Stream};
+    use hyper::{header, Body, Method, Request, Response, Uri};
     use native_tls::TlsConnector;
     use tokio::runtime::current_thread;
     use url::Url;
 
     use crate::proxy::client::build_uri;
-    use crate::proxy::config::ValueToken;
+    use crate::proxy::config::{Route, ValueToken};
     use crate::proxy::test::config::config;
     use crate::proxy::test::http::client_fn;
     use crate::proxy::{Client, Config};
@@ -256,4 +345,176 @@ mod tests {
             .unwrap();
         assert_eq!(full_url, expected_url);
     }
+
+    #[test]
+    fn it_redirects_req_to_route_backend() {
+        let http = client_fn(|req| {
+            let uri = "https://iotedged:8081/modules/edgeHub?version=v1"
+                .parse::<Uri>()
+                .unwrap();
+            assert_eq!(req.uri(), &uri);
+
+            Ok(Response::new("This Is Fine".into()))
+        });
+        let config = config().with_routes(vec![Route::new(
+            None,
+            Some("/modules".to_string()),
+            Url::parse("https://iotedged:8081").unwrap(),
+        )]);
+        let client = Client::with_client(http, config);
+        let mut req = Request::new(Body::empty());
+        *req.uri_mut() = "http://localhost:3000/modules/edgeHub?version=v1"
+            .parse()
+            .unwrap();
+
+        let task = client.request(req);
+
+        current_thread::block_on_all(task).unwrap();
+    }
+
+    #[test]
+    fn it_adds_token_only_to_req_to_default_backend() {
+        let http = client_fn(|req| {
+            let authorization = req.headers().get(header::AUTHORIZATION);
+            if req.uri().port_u16() == Some(8081) {
+                assert_eq!(authorization, None);
+            } else {
+                assert_eq!(authorization.unwrap(), "Bearer token");
+            }
+
+            Ok(Response::new("This Is Fine".into()))
+        });
+        let config = Config::new(
+            Url::parse("https://iotedged:8080").unwrap(),
+            ValueToken(Some("token".to_string())),
+            TlsConnector::builder().build().unwrap(),
+        )
+        .with_routes(vec![Route::new(
+            None,
+            Some("/modules".to_string()),
+            Url::parse("https://iotedged:8081").unwrap(),
+        )]);
+        let client = Client::with_client(http, config);
+
+        for uri in &["http://localhost:3000/modules", "http://localhost:3000/api"] {
+            let mut req = Request::new(Body::empty());
+            *req.uri_mut() = uri.parse().unwrap();
+
+            current_thread::block_on_all(client.request(req)).unwrap();
+        }
+    }
+
+    #[test]
+    fn it_retries_idempotent_req_when_server_is_unavailable() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let http = client_fn({
+            let attempts = attempts.clone();
+            move |_| {
+                if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(Error::from(ErrorKind::HttpRequest(
+                        "PUT / HTTP 1.1".to_string(),
+                    )))
+                } else {
+                    Ok(Response::new("This Is Fine".into()))
+                }
+            }
+        });
+        let client = Client::with_client(http, config().with_retries(2));
+        let mut req = Request::new(Body::from("payload"));
+        *req.method_mut() = Method::PUT;
+
+        let task = client.request(req);
+
+        current_thread::block_on_all(task).unwrap();
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn it_fails_when_retries_are_exhausted() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let http = client_fn({
+            let attempts = attempts.clone();
+            move |_| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(Error::from(ErrorKind::HttpRequest(
+                    "GET / HTTP 1.1".to_string(),
+                )))
+            }
+        });
+        let client = Client::with_client(http, config().with_retries(2));
+        let req = Request::new(Body::empty());
+
+        let task = client.request(req);
+
+        let err = current_thread::block_on_all(task).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::HttpRequest("GET / HTTP 1.1".to_string())
+        );
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn it_does_not_retry_non_idempotent_req() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let http = client_fn({
+            let attempts = attempts.clone();
+            move |_| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(Error::from(ErrorKind::HttpRequest(
+                    "POST / HTTP 1.1".to_string(),
+                )))
+            }
+        });
+        let client = Client::with_client(http, config().with_retries(2));
+        let mut req = Request::new(Body::from("payload"));
+        *req.method_mut() = Method::POST;
+
+        let task = client.request(req);
+
+        current_thread::block_on_all(task).unwrap_err();
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn it_does_not_retry_req_after_timeout() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let http = client_fn({
+            let attempts = attempts.clone();
+            move |_| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                future::empty::<Response<Body>, Error>()
+            }
+        });
+        let config = config()
+            .with_timeout(Some(Duration::from_millis(50)))
+            .with_retries(2);
+        let client = Client::with_client(http, config);
+        let req = Request::new(Body::empty());
+
+        let task = client.request(req);
+
+        let err = current_thread::block_on_all(task).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::Timeout("GET https://iotedged:8080/ HTTP/1.1".to_string())
+        );
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn it_fails_when_server_does_not_respond_in_time() {
+        let http = client_fn(|_| future::empty::<Response<Body>, Error>());
+        let client =
+            Client::with_client(http, config().with_timeout(Some(Duration::from_millis(50))));
+        let req = Request::new(Body::empty());
+
+        let task = client.request(req);
+
+        let err = current_thread::block_on_all(task).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::Timeout("GET https://iotedged:8080/ HTTP/1.1".to_string())
+        );
+    }
 }
@@ -1,12 +1,14 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::fs;
+use std::time::Duration;
 
 use failure::ResultExt;
-use native_tls::{Certificate, TlsConnector};
+use hyper::{header, Request};
+use native_tls::{Certificate, Identity, TlsConnector};
 use url::Url;
 
-use crate::{Error, ErrorKind, InitializeErrorReason, ServiceSettings};
+use crate::{Error, ErrorKind, InitializeErrorReason, RouteSettings, ServiceSettings};
 
 #[derive(Clone)]
 pub struct Config<T>
@@ -16,6 +18,9 @@ where
     host: Url,
     token: T,
     tls: TlsConnector,
+    routes: Vec<Route>,
+    timeout: Option<Duration>,
+    retries: u32,
 }
 
 impl<T> Config<T>
@@ -23,13 +28,53 @@ where
     T: TokenSource,
 {
     pub fn new(host: Url, token: T, tls: TlsConnector) -> Self {
-        Config { host, token, tls }
+        Config {
+            host,
+            token,
+            tls,
+            routes: Vec::new(),
+            timeout: None,
+            retries: 0,
+        }
+    }
+
+    pub fn with_routes(mut self, routes: Vec<Route>) -> Self {
+        self.routes = routes;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
     }
 
     pub fn host(&self) -> &Url {
         &self.host
     }
 
+    /// Returns the first route matching a request, or `None` if it goes to the default backend.
+    pub fn route<B>(&self, req: &Request<B>) -> Option<&Route> {
+        self.routes.iter().find(|route| route.matches(req))
+    }
+
+    /// Returns a backend of the first route matching a request, or the default one otherwise.
+    pub fn backend<B>(&self, req: &Request<B>) -> &Url {
+        self.route(req).map_or(&self.host, Route::backend)
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
     pub fn tls(&self) -> &TlsConnector {
         &self.tls
     }
@@ -57,12 +102,90 @@ pub fn get_config(settings: &ServiceSettings) -> Result<Config<ValueToken>, Erro
         tls.add_root_certificate(cert);
     }
 
+    if let Some(identity) = settings.identity() {
+        let path = identity.pkcs12();
+        let file = fs::read(path).context(ErrorKind::Initialize(
+            InitializeErrorReason::ClientConfigReadFile(path.display().to_string()),
+        ))?;
+
+        let identity = Identity::from_pkcs12(&file, identity.password())
+            .context(ErrorKind::Initialize(InitializeErrorReason::ClientConfig))?;
+
+        tls.identity(identity);
+    }
+
+    let routes = settings.routes().iter().map(Route::from).collect();
+
     Ok(Config::new(
         settings.backend().clone(),
         ValueToken(Some(token)),
         tls.build()
             .context(ErrorKind::Initialize(InitializeErrorReason::ClientConfig))?,
-    ))
+    )
+    .with_routes(routes)
+    .with_timeout(settings.timeout())
+    .with_retries(settings.retries()))
+}
+
+#[derive(Clone, Debug)]
+pub struct Route {
+    host: Option<String>,
+    path: Option<String>,
+    backend: Url,
+}
+
+impl Route {
+    pub fn new(host: Option<String>, path: Option<String>, backend: Url) -> Self {
+        Route {
+            host,
+            path,
+            backend,
+        }
+    }
+
+    pub fn backend(&self) -> &Url {
+        &self.backend
+    }
+
+    fn matches<B>(&self, req: &Request<B>) -> bool {
+        let host_matches = self.host.as_ref().map_or(true, |expected| {
+            request_host(req).map_or(false, |host| host.eq_ignore_ascii_case(expected))
+        });
+
+        let path_matches = self.path.as_ref().map_or(true, |prefix| {
+            let path = req.uri().path();
+            path.starts_with(prefix.as_str())
+                && (prefix.ends_with('/')
+                    || path.len() == prefix.len()
+                    || path[prefix.len()..].starts_with('/'))
+        });
+
+        host_matches && path_matches
+    }
+}
+
+impl From<&RouteSettings> for Route {
+    fn from(settings: &RouteSettings) -> Self {
+        Route::new(
+            settings.host().map(ToString::to_string),
+            settings.path().map(ToString::to_string),
+            settings.backend().clone(),
+        )
+    }
+}
+
+fn request_host<B>(req: &Request<B>) -> Option<&str> {
+    let host = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .or_else(|| req.uri().host())?;
+
+    // strip port number but keep IPv6 addresses intact
+    match host.rfind(':') {
+        Some(index) if !host[index..].contains(']') => Some(&host[..index]),
+        _ => Some(host),
+    }
 }
 
 pub trait TokenSource {
@@ -82,12 +205,19 @@ impl TokenSource for ValueToken {
 mod tests {
     use std::fs;
 
+    use std::time::Duration;
+
+    use hyper::{header, Request};
     use tempfile::TempDir;
     use url::Url;
 
+    use crate::proxy::config::Route;
+    use crate::proxy::test::config::config;
     use crate::proxy::{get_config, TokenSource};
     use crate::tls::CertGenerator;
-    use crate::{ErrorKind, InitializeErrorReason, ServiceSettings};
+    use crate::{
+        ErrorKind, IdentitySettings, InitializeErrorReason, RouteSettings, ServiceSettings,
+    };
 
     #[test]
     fn it_loads_config_from_filesystem() {
@@ -193,4 +323,144 @@ mod tests {
             &ErrorKind::Initialize(InitializeErrorReason::ClientConfig)
         );
     }
+
+    #[test]
+    fn it_loads_config_with_identity_routes_and_timeouts() {
+        let dir = TempDir::new().unwrap();
+
+        let token = dir.path().join("token");
+        fs::write(&token, "token").unwrap();
+
+        let identity = dir.path().join("identity.pfx");
+        CertGenerator::default()
+            .identity(&identity, "secret")
+            .generate()
+            .unwrap();
+
+        let settings = ServiceSettings::new(
+            "management".to_owned(),
+            Url::parse("http://localhost:3000").unwrap(),
+            Url::parse("https://iotedged:30000").unwrap(),
+            None,
+            &token,
+        )
+        .with_identity(IdentitySettings::new(&identity, "secret".to_string()))
+        .with_routes(vec![RouteSettings::new(
+            None,
+            Some("/modules".to_string()),
+            Url::parse("https://iotedged:30001").unwrap(),
+        )])
+        .with_timeout(Duration::from_secs(10))
+        .with_retries(3);
+
+        let config = get_config(&settings).unwrap();
+
+        let req = Request::get("http://localhost:3000/modules/edgeHub")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            config.backend(&req),
+            &Url::parse("https://iotedged:30001").unwrap()
+        );
+        assert_eq!(config.timeout(), Some(Duration::from_secs(10)));
+        assert_eq!(config.retries(), 3);
+    }
+
+    #[test]
+    fn it_fails_to_load_config_if_identity_not_exist() {
+        let dir = TempDir::new().unwrap();
+
+        let token = dir.path().join("token");
+        fs::write(&token, "token").unwrap();
+
+        let identity = dir.path().join("identity.pfx");
+
+        let settings = ServiceSettings::new(
+            "management".to_owned(),
+            Url::parse("http://localhost:3000").unwrap(),
+            Url::parse("https://iotedged:30000").unwrap(),
+            None,
+            &token,
+        )
+        .with_identity(IdentitySettings::new(&identity, "secret".to_string()));
+
+        let err = get_config(&settings).err().unwrap();
+
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::Initialize(InitializeErrorReason::ClientConfigReadFile(
+                identity.display().to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn it_fails_to_load_config_if_identity_password_is_wrong() {
+        let dir = TempDir::new().unwrap();
+
+        let token = dir.path().join("token");
+        fs::write(&token, "token").unwrap();
+
+        let identity = dir.path().join("identity.pfx");
+        CertGenerator::default()
+            .identity(&identity, "secret")
+            .generate()
+            .unwrap();
+
+        let settings = ServiceSettings::new(
+            "management".to_owned(),
+            Url::parse("http://localhost:3000").unwrap(),
+            Url::parse("https://iotedged:30000").unwrap(),
+            None,
+            &token,
+        )
+        .with_identity(IdentitySettings::new(&identity, "wrong".to_string()));
+
+        let err = get_config(&settings).err().unwrap();
+
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::Initialize(InitializeErrorReason::ClientConfig)
+        );
+    }
+
+    #[test]
+    fn it_selects_backend_by_route_rules() {
+        let config = config().with_routes(vec![
+            Route::new(
+                Some("edgehub".to_string()),
+                Some("/devices".to_string()),
+                Url::parse("https://edgehub:443").unwrap(),
+            ),
+            Route::new(
+                None,
+                Some("/modules".to_string()),
+                Url::parse("https://iotedged:8081").unwrap(),
+            ),
+        ]);
+
+        let backend = |uri: &str, host: Option<&str>| {
+            let mut req = Request::get(uri);
+            if let Some(host) = host {
+                req.header(header::HOST, host);
+            }
+            config.backend(&req.body(()).unwrap()).to_string()
+        };
+
+        assert_eq!(
+            backend("/devices/d1", Some("EdgeHub:8080")),
+            "https://edgehub/"
+        );
+        assert_eq!(backend("http://edgehub/devices", None), "https://edgehub/");
+        assert_eq!(
+            backend("/devices/d1", Some("iotedged")),
+            "https://iotedged:8080/"
+        );
+        assert_eq!(backend("/modules", None), "https://iotedged:8081/");
+        assert_eq!(
+            backend("/modules/edgeHub?api-version=2019-01-30", None),
+            "https://iotedged:8081/"
+        );
+        assert_eq!(backend("/modulesfoo", None), "https://iotedged:8080/");
+    }
 }
@@ -1,6 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::sync::Arc;
+use std::time::Instant;
 
 use failure::Compat;
 use futures::future::FutureResult;
@@ -9,6 +10,7 @@ use hyper::service::{NewService, Service};
 use hyper::{Body, Request, Response};
 use log::debug;
 
+use crate::metrics::ServiceMetrics;
 use crate::proxy::{Client, HttpClient, TokenSource};
 use crate::{logging, Error, IntoResponse};
 
@@ -17,15 +19,17 @@ where
     T: TokenSource,
 {
     client: Arc<Client<T, S>>,
+    metrics: ServiceMetrics,
 }
 
 impl<T, S> ProxyService<T, S>
 where
     T: TokenSource,
 {
-    pub fn new(client: Client<T, S>) -> Self {
+    pub fn new(client: Client<T, S>, metrics: ServiceMetrics) -> Self {
         ProxyService {
             client: Arc::new(client),
+            metrics,
         }
     }
 }
@@ -37,6 +41,7 @@ where
     fn clone(&self) -> Self {
         ProxyService {
             client: self.client.clone(),
+            metrics: self.metrics.clone(),
         }
     }
 }
@@ -44,7 +49,7 @@ where
 impl<T, S> Service for ProxyService<T, S>
 where
     T: TokenSource + 'static,
-    S: HttpClient + 'static,
+    S: HttpClient + Send + Sync + 'static,
 {
     type ReqBody = Body;
     type ResBody = Body;
@@ -55,20 +60,26 @@ where
         let request = format!("{} {} {:?}", req.method(), req.uri(), req.version());
         debug!("Starting request processing {}", request);
 
+        let started = Instant::now();
+        let metrics = self.metrics.clone();
+
         let fut = self.client.request(req).then(move |result| {
-            let response = match result {
+            let (response, upstream_error) = match result {
                 Ok(response) => {
                     debug!("Finished request processing {}", request);
-                    response
+                    (response, false)
                 }
                 Err(err) => {
                     debug!("Finished request processing with error: {}", request);
 
                     logging::failure(&err);
-                    err.into_response()
+                    let upstream_error = err.is_upstream();
+                    (err.into_response(), upstream_error)
                 }
             };
 
+            metrics.observe(response.status(), started.elapsed(), upstream_error);
+
             Ok(response)
         });
 
@@ -79,7 +90,7 @@ where
 impl<T, S> NewService for ProxyService<T, S>
 where
     T: TokenSource + 'static,
-    S: HttpClient + 'static,
+    S: HttpClient + Send + Sync + 'static,
 {
     type ReqBody = Body;
     type ResBody = Body;
@@ -102,6 +113,7 @@ mod tests {
     use serde_json::json;
     use tokio::runtime::Runtime;
 
+    use crate::metrics::Metrics;
     use crate::proxy::test::config::config;
     use crate::proxy::test::http::client_fn;
     use crate::proxy::{Client, ProxyService};
@@ -113,7 +125,7 @@ mod tests {
         let http = client_fn(|_| Ok(Response::new(Body::from("This Is Fine"))));
         let client = Client::with_client(http, config());
         let req = Request::new(Body::empty());
-        let mut proxy = ProxyService::new(client);
+        let mut proxy = ProxyService::new(client, Metrics::new().service("management"));
 
         let task = proxy.call(req).map_err(Compat::into_inner).and_then(|res| {
             res.into_body()
@@ -132,7 +144,7 @@ mod tests {
         let http = client_fn(|_| Err(Error::from(ErrorKind::Generic)));
         let client = Client::with_client(http, config());
         let req = Request::new(Body::empty());
-        let mut proxy = ProxyService::new(client);
+        let mut proxy = ProxyService::new(client, Metrics::new().service("management"));
 
         let task = proxy.call(req).map_err(Compat::into_inner).and_then(|res| {
             let status = res.status();
@@ -165,7 +177,7 @@ mod tests {
         });
         let client = Client::with_client(http, config());
         let req = Request::new(Body::empty());
-        let mut proxy = ProxyService::new(client);
+        let mut proxy = ProxyService::new(client, Metrics::new().service("management"));
 
         let task = proxy.call(req).map_err(Compat::into_inner).and_then(|res| {
             let status = res.status();
@@ -191,4 +203,35 @@ mod tests {
             json!({ "message": "Could not make an HTTP request: \"GET / HTTP/1.1\""}).to_string()
         );
     }
+
+    #[test]
+    fn it_records_metrics_for_each_request() {
+        let http = client_fn(|req: Request<Body>| {
+            if req.uri().path() == "/fail" {
+                Err(Error::from(ErrorKind::HttpRequest(
+                    "GET /fail HTTP/1.1".to_string(),
+                )))
+            } else {
+                Ok(Response::new(Body::empty()))
+            }
+        });
+        let client = Client::with_client(http, config());
+        let metrics = Metrics::new();
+        let mut proxy = ProxyService::new(client, metrics.service("management"));
+
+        let mut runtime = Runtime::new().unwrap();
+        for path in &["/ok", "/ok", "/fail"] {
+            let req = Request::get(*path).body(Body::empty()).unwrap();
+            runtime.block_on(proxy.call(req)).unwrap();
+        }
+
+        let output = metrics.render();
+        assert!(output
+            .contains("iotedge_proxy_requests_total{service=\"management\",code=\"200\"} 2\n"));
+        assert!(output
+            .contains("iotedge_proxy_requests_total{service=\"management\",code=\"502\"} 1\n"));
+        assert!(output
+            .contains("iotedge_proxy_request_duration_seconds_count{service=\"management\"} 3\n"));
+        assert!(output.contains("iotedge_proxy_upstream_errors_total{service=\"management\"} 1\n"));
+    }
 }
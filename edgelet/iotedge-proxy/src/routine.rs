@@ -12,6 +12,7 @@ use log::{debug, info, warn};
 use tokio::runtime::Runtime;
 
 use crate::api::ApiService;
+use crate::metrics::{Metrics, ServiceMetrics};
 use crate::proxy::{get_config, Client, ProxyService};
 use crate::signal::ShutdownSignal;
 use crate::{ApiSettings, Error, ErrorKind, InitializeErrorReason, ServiceSettings, Settings};
@@ -31,12 +32,13 @@ impl Routine {
         } else {
             let mut servers: Vec<Box<dyn Future<Item = (), Error = Error> + Send>> = Vec::new();
             let mut senders = Vec::new();
+            let metrics = Metrics::new();
 
             for settings in self.settings.services().iter() {
                 let (tx, rx) = oneshot::channel();
                 senders.push(tx);
 
-                let proxy = start_proxy(&settings, rx);
+                let proxy = start_proxy(&settings, metrics.service(settings.name()), rx);
                 servers.push(Box::new(proxy));
             }
 
@@ -44,7 +46,7 @@ impl Routine {
             senders.push(tx);
 
             if let Some(settings) = self.settings.api() {
-                let api = start_api(settings, rx);
+                let api = start_api(settings, metrics, rx);
                 servers.push(Box::new(api));
             }
 
@@ -70,6 +72,7 @@ impl Routine {
 
 fn start_api(
     settings: &ApiSettings,
+    metrics: Metrics,
     shutdown: Receiver<()>,
 ) -> impl Future<Item = (), Error = Error> {
     let settings = settings.clone();
@@ -97,7 +100,7 @@ fn start_api(
             })
         })
         .and_then(move |addr| {
-            let new_service = ApiService::new();
+            let new_service = ApiService::new(metrics);
 
             let server = Server::bind(&addr)
                 .serve(new_service)
@@ -117,6 +120,7 @@ fn start_api(
 
 fn start_proxy(
     settings: &ServiceSettings,
+    metrics: ServiceMetrics,
     shutdown: Receiver<()>,
 ) -> impl Future<Item = (), Error = Error> {
     let settings = settings.clone();
@@ -150,7 +154,7 @@ fn start_proxy(
         .and_then(move |addr| {
             let config = get_config(&settings)?;
             let client = Client::new(config);
-            let new_service = ProxyService::new(client);
+            let new_service = ProxyService::new(client, metrics);
 
             let server = Server::bind(&addr)
                 .serve(new_service)
@@ -185,6 +189,7 @@ mod tests {
     use tokio::runtime::current_thread::Runtime;
     use url::Url;
 
+    use crate::metrics::Metrics;
     use crate::proxy::test::http::get_unused_tcp_port;
     use crate::routine::{start_api, start_proxy};
     use crate::{logging, ApiSettings, ErrorKind, InitializeErrorReason, ServiceSettings};
@@ -206,7 +211,7 @@ mod tests {
 
         let (tx, rx) = oneshot::channel();
 
-        let proxy = start_proxy(&settings, rx);
+        let proxy = start_proxy(&settings, Metrics::new().service(settings.name()), rx);
 
         let mut runtime = Runtime::new().unwrap();
         runtime.spawn(proxy.map_err(|err| println!("{:?}", err)));
@@ -232,7 +237,7 @@ mod tests {
 
         let (_, rx) = oneshot::channel();
 
-        let proxy = start_proxy(&settings, rx);
+        let proxy = start_proxy(&settings, Metrics::new().service(settings.name()), rx);
 
         let mut runtime = Runtime::new().unwrap();
         let err = runtime.block_on(proxy).unwrap_err();
@@ -257,7 +262,7 @@ mod tests {
 
         let (_, rx) = oneshot::channel();
 
-        let proxy = start_proxy(&settings, rx);
+        let proxy = start_proxy(&settings, Metrics::new().service(settings.name()), rx);
 
         let mut runtime = Runtime::new().unwrap();
         let err = runtime.block_on(proxy).unwrap_err();
@@ -278,7 +283,7 @@ mod tests {
 
         let (tx, rx) = oneshot::channel();
 
-        let proxy = start_api(&settings, rx);
+        let proxy = start_api(&settings, Metrics::new(), rx);
 
         let mut runtime = Runtime::new().unwrap();
         runtime.spawn(proxy.map_err(|err| println!("{:?}", err)));
@@ -299,7 +304,7 @@ mod tests {
 
         let (_, rx) = oneshot::channel();
 
-        let proxy = start_api(&settings, rx);
+        let proxy = start_api(&settings, Metrics::new(), rx);
 
         let mut runtime = Runtime::new().unwrap();
         let err = runtime.block_on(proxy).unwrap_err();
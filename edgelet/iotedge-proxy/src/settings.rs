@@ -1,6 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use config::{Config, File, FileFormat};
 use failure::ResultExt;
@@ -67,13 +68,17 @@ fn convert(config: Config) -> Result<Settings, Error> {
             .into());
         }
 
-        if settings.backend().scheme() != "https" {
-            return Err(ErrorKind::Initialize(
-                InitializeErrorReason::LoadSettingsUnsupportedSchema(
-                    settings.backend().as_str().to_owned(),
-                ),
-            )
-            .into());
+        let backends = std::iter::once(settings.backend())
+            .chain(settings.routes().iter().map(RouteSettings::backend));
+        for backend in backends {
+            if backend.scheme() != "https" {
+                return Err(ErrorKind::Initialize(
+                    InitializeErrorReason::LoadSettingsUnsupportedSchema(
+                        backend.as_str().to_owned(),
+                    ),
+                )
+                .into());
+            }
         }
     }
 
@@ -94,6 +99,16 @@ pub struct ServiceSettings {
 
     #[serde(default = "default_token")]
     token: PathBuf,
+
+    identity: Option<IdentitySettings>,
+
+    #[serde(default)]
+    routes: Vec<RouteSettings>,
+
+    timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    retries: u32,
 }
 
 fn default_token() -> PathBuf {
@@ -114,9 +129,33 @@ impl ServiceSettings {
             backend,
             certificate: cert.map(Path::to_path_buf),
             token: token.to_path_buf(),
+            identity: None,
+            routes: Vec::new(),
+            timeout_secs: None,
+            retries: 0,
         }
     }
 
+    pub fn with_identity(mut self, identity: IdentitySettings) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    pub fn with_routes(mut self, routes: Vec<RouteSettings>) -> Self {
+        self.routes = routes;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout_secs = Some(timeout.as_secs());
+        self
+    }
+
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -136,6 +175,82 @@ impl ServiceSettings {
     pub fn token(&self) -> &Path {
         &self.token
     }
+
+    pub fn identity(&self) -> Option<&IdentitySettings> {
+        self.identity.as_ref()
+    }
+
+    pub fn routes(&self) -> &[RouteSettings] {
+        &self.routes
+    }
+
+    /// Time to get a response from backend in, including all retries.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_secs.map(Duration::from_secs)
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+}
+
+/// Client certificate presented to a backend in addition to a bearer token.
+#[derive(Clone, Debug, Deserialize)]
+pub struct IdentitySettings {
+    pkcs12: PathBuf,
+
+    #[serde(default)]
+    password: String,
+}
+
+impl IdentitySettings {
+    pub fn new(pkcs12: &Path, password: String) -> Self {
+        IdentitySettings {
+            pkcs12: pkcs12.to_path_buf(),
+            password,
+        }
+    }
+
+    pub fn pkcs12(&self) -> &Path {
+        &self.pkcs12
+    }
+
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+}
+
+/// Sends requests matching a host and/or a path prefix to a separate backend.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RouteSettings {
+    host: Option<String>,
+
+    path: Option<String>,
+
+    #[serde(with = "url_serde")]
+    backend: Url,
+}
+
+impl RouteSettings {
+    pub fn new(host: Option<String>, path: Option<String>, backend: Url) -> Self {
+        RouteSettings {
+            host,
+            path,
+            backend,
+        }
+    }
+
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn backend(&self) -> &Url {
+        &self.backend
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -157,6 +272,7 @@ impl ApiSettings {
 #[cfg(test)]
 mod tests {
     use std::path::Path;
+    use std::time::Duration;
 
     use url::Url;
 
@@ -220,6 +336,35 @@ mod tests {
             &Url::parse("http://example:443").unwrap()
         );
         assert_eq!(settings.services()[1].token(), Path::new("token"));
+
+        assert!(settings.services()[0].routes().is_empty());
+        assert!(settings.services()[0].identity().is_none());
+        assert_eq!(settings.services()[0].timeout(), None);
+        assert_eq!(settings.services()[0].retries(), 0);
+
+        let identity = settings.services()[1].identity().unwrap();
+        assert_eq!(identity.pkcs12(), Path::new("workload.pfx"));
+        assert_eq!(identity.password(), "secret");
+
+        let routes = settings.services()[1].routes();
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[0].host(), None);
+        assert_eq!(routes[0].path(), Some("/modules"));
+        assert_eq!(
+            routes[0].backend(),
+            &Url::parse("https://iotedged:35003").unwrap()
+        );
+        assert_eq!(routes[1].host(), Some("edgehub"));
+        assert_eq!(routes[1].path(), None);
+        assert_eq!(
+            routes[1].backend(),
+            &Url::parse("https://edgehub:443").unwrap()
+        );
+        assert_eq!(
+            settings.services()[1].timeout(),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(settings.services()[1].retries(), 2);
     }
 
     #[test]
@@ -265,4 +410,17 @@ mod tests {
             ))
         );
     }
+
+    #[test]
+    fn it_allows_only_https_for_route_backend() {
+        let err =
+            Settings::new(Some(Path::new("test/unsupported.route.backend.yaml"))).unwrap_err();
+
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::Initialize(InitializeErrorReason::LoadSettingsUnsupportedSchema(
+                "http://edgehub:443/".to_owned()
+            ))
+        );
+    }
 }
@@ -6,7 +6,7 @@ use failure::{self, Context, ResultExt};
 use edgelet_core::RuntimeSettings;
 
 use super::identity_certificate_expiry::CertificateValidity;
-use crate::check::{checker::Checker, Check, CheckResult};
+use crate::check::{checker::Checker, Check, CheckResult, Remediation};
 
 #[derive(Default, serde_derive::Serialize)]
 pub(crate) struct CertificatesQuickstart {
@@ -15,10 +15,10 @@ pub(crate) struct CertificatesQuickstart {
 }
 
 impl Checker for CertificatesQuickstart {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         "certificates-quickstart"
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         "production readiness: certificates"
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
@@ -93,7 +93,8 @@ impl CertificatesQuickstart {
                     not_after,
                 ))
                 .into(),
-            ));
+            )
+            .with_remediation(production_certificates()));
             } else {
                 return Ok(CheckResult::Warning(
                 Context::new(format!(
@@ -104,10 +105,17 @@ impl CertificatesQuickstart {
                     (not_after - now).num_days(), not_after,
                 ))
                 .into(),
-            ));
+            )
+            .with_remediation(production_certificates()));
             }
         }
 
         Ok(CheckResult::Ok)
     }
 }
+
+fn production_certificates() -> Remediation {
+    Remediation::new(
+        "Set the device CA certificate, its private key and the trusted CA certificates in the certificates section of the config file, then restart the IoT Edge daemon.",
+    )
+}
@@ -14,10 +14,10 @@ pub(crate) struct ConnectManagementUri {
 }
 
 impl Checker for ConnectManagementUri {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         "connect-management-uri"
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         "config.yaml has correct URIs for daemon mgmt endpoint"
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
@@ -102,7 +102,7 @@ impl ConnectManagementUri {
             Cow::Owned(OsString::from(connect_management_uri.to_string())),
         ]);
 
-        match super::docker(docker_host_arg, args) {
+        match super::docker(check.check_timeout, docker_host_arg, args) {
             Ok(_) => Ok(CheckResult::Ok),
             Err((Some(stderr), err)) => Err(err.context(stderr).into()),
             Err((None, err)) => Err(err.context("Could not spawn docker process").into()),
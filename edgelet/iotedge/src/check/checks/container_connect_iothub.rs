@@ -61,10 +61,10 @@ pub(crate) struct ContainerConnectIotHub {
 }
 
 impl Checker for ContainerConnectIotHub {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         self.id
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         self.description
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
@@ -118,7 +118,7 @@ impl ContainerConnectIotHub {
         ]);
         self.diagnostics_image_name = Some(check.diagnostics_image_name.clone());
 
-        if let Err((_, err)) = super::docker(docker_host_arg, args) {
+        if let Err((_, err)) = super::docker(check.check_timeout, docker_host_arg, args) {
             return Err(err
                 .context(format!(
                     "Container on the {} network could not connect to {}:{}",
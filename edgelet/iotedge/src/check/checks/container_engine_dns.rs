@@ -2,7 +2,7 @@ use std::fs::File;
 
 use failure::{self, Context, ResultExt};
 
-use crate::check::{checker::Checker, Check, CheckResult};
+use crate::check::{checker::Checker, Check, CheckResult, Remediation};
 
 #[derive(Default, serde_derive::Serialize)]
 pub(crate) struct ContainerEngineDns {
@@ -11,15 +11,18 @@ pub(crate) struct ContainerEngineDns {
 }
 
 impl Checker for ContainerEngineDns {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         "container-engine-dns"
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         "DNS server"
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
         self.inner_execute(check)
             .unwrap_or_else(CheckResult::Failed)
+            .with_remediation(Remediation::new(
+                "Set \"dns\" to DNS servers reachable from the device in the container engine config file, then restart the container engine.",
+            ))
     }
     fn get_json(&self) -> serde_json::Value {
         serde_json::to_value(self).unwrap()
@@ -9,10 +9,10 @@ pub(crate) struct ContainerEngineInstalled {
 }
 
 impl Checker for ContainerEngineInstalled {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         "container-engine-uri"
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         "container engine is installed and functional"
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
@@ -53,6 +53,7 @@ impl ContainerEngineInstalled {
         };
 
         let output = super::docker(
+            check.check_timeout,
             &docker_host_arg,
             &["version", "--format", "{{.Server.Version}}"],
         );
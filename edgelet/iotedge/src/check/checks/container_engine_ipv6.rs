@@ -13,10 +13,10 @@ pub(crate) struct ContainerEngineIPv6 {
 }
 
 impl Checker for ContainerEngineIPv6 {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         "container-engine-ipv6"
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         "IPv6 network configuration"
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
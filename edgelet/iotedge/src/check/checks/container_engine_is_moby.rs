@@ -1,6 +1,6 @@
 use failure::{self, Context, Fail};
 
-use crate::check::{checker::Checker, Check, CheckResult};
+use crate::check::{checker::Checker, Check, CheckResult, Remediation};
 
 #[derive(Default, serde_derive::Serialize)]
 pub(crate) struct ContainerEngineIsMoby {
@@ -9,15 +9,18 @@ pub(crate) struct ContainerEngineIsMoby {
 }
 
 impl Checker for ContainerEngineIsMoby {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         "container-engine-is-moby"
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         "production readiness: container engine"
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
         self.inner_execute(check)
             .unwrap_or_else(CheckResult::Failed)
+            .with_remediation(Remediation::new(
+                "Install the moby-engine package as the container engine.",
+            ))
     }
     fn get_json(&self) -> serde_json::Value {
         serde_json::to_value(self).unwrap()
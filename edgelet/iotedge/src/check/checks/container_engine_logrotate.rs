@@ -2,7 +2,7 @@ use std::fs::File;
 
 use failure::{self, Context, ResultExt};
 
-use crate::check::{checker::Checker, Check, CheckResult};
+use crate::check::{checker::Checker, Check, CheckResult, Remediation};
 
 #[derive(Default, serde_derive::Serialize)]
 pub(crate) struct ContainerEngineLogrotate {
@@ -10,15 +10,18 @@ pub(crate) struct ContainerEngineLogrotate {
 }
 
 impl Checker for ContainerEngineLogrotate {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         "container-engine-logrotate"
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         "production readiness: logs policy"
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
         self.inner_execute(check)
             .unwrap_or_else(CheckResult::Failed)
+            .with_remediation(Remediation::new(
+                "Set \"log-driver\" and \"log-opts\" with \"max-size\" and \"max-file\" in the container engine config file, then restart the container engine.",
+            ))
     }
     fn get_json(&self) -> serde_json::Value {
         serde_json::to_value(self).unwrap()
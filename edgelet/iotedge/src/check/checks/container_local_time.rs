@@ -11,10 +11,10 @@ pub(crate) struct ContainerLocalTime {
 }
 
 impl Checker for ContainerLocalTime {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         "container-local-time"
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         "container time is close to host time"
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
@@ -35,6 +35,7 @@ impl ContainerLocalTime {
         };
 
         let output = super::docker(
+            check.check_timeout,
             docker_host_arg,
             vec![
                 "run",
@@ -1,4 +1,5 @@
 use std::net::TcpStream;
+use std::time::Duration;
 
 use failure::{Context, ResultExt};
 
@@ -13,10 +14,10 @@ pub(crate) struct HostConnectDpsEndpoint {
 }
 
 impl Checker for HostConnectDpsEndpoint {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         "host-connect-dps-endpoint"
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         "host can connect to and perform TLS handshake with DPS endpoint"
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
@@ -49,7 +50,12 @@ impl HostConnectDpsEndpoint {
         })?;
         self.dps_hostname = Some(dps_hostname.to_owned());
 
-        resolve_and_tls_handshake(&dps_endpoint, dps_hostname, dps_hostname)?;
+        resolve_and_tls_handshake(
+            check.check_timeout,
+            &dps_endpoint,
+            dps_hostname,
+            dps_hostname,
+        )?;
 
         Ok(CheckResult::Ok)
     }
@@ -60,7 +66,10 @@ impl HostConnectDpsEndpoint {
 // `tls_hostname` is used for SNI validation and certificate hostname validation.
 //
 // `hostname_display` is used for the error messages.
+//
+// `timeout` bounds each read and write of the TLS handshake.
 pub fn resolve_and_tls_handshake(
+    timeout: Duration,
     to_socket_addrs: &impl std::net::ToSocketAddrs,
     tls_hostname: &str,
     hostname_display: &str,
@@ -81,7 +90,11 @@ pub fn resolve_and_tls_handshake(
             ))
         })?;
 
-    let stream = TcpStream::connect_timeout(&host_addr, std::time::Duration::from_secs(10))
+    let stream = TcpStream::connect_timeout(&host_addr, Duration::from_secs(10))
+        .with_context(|_| format!("Could not connect to {}", hostname_display))?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .and_then(|()| stream.set_write_timeout(Some(timeout)))
         .with_context(|_| format!("Could not connect to {}", hostname_display))?;
 
     let tls_connector = native_tls::TlsConnector::new().with_context(|_| {
@@ -33,10 +33,10 @@ pub(crate) struct HostConnectIotHub {
 }
 
 impl Checker for HostConnectIotHub {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         self.id
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         self.description
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
@@ -58,6 +58,7 @@ impl HostConnectIotHub {
         self.iothub_hostname = Some(iothub_hostname.clone());
 
         super::host_connect_dps_endpoint::resolve_and_tls_handshake(
+            check.check_timeout,
             &(&**iothub_hostname, self.port_number),
             iothub_hostname,
             &format!("{}:{}", iothub_hostname, self.port_number),
@@ -8,10 +8,10 @@ pub(crate) struct HostLocalTime {
 }
 
 impl Checker for HostLocalTime {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         "host-local-time"
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         "host time is close to real time"
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
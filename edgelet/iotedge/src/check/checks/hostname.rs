@@ -15,10 +15,10 @@ pub(crate) struct Hostname {
 }
 
 impl Checker for Hostname {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         "hostname"
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         "config.yaml has correct hostname"
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
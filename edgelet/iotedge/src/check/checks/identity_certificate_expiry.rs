@@ -15,10 +15,10 @@ pub(crate) struct IdentityCertificateExpiry {
 }
 
 impl Checker for IdentityCertificateExpiry {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         "identity-certificate-expiry"
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         "production readiness: identity certificates expiry"
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
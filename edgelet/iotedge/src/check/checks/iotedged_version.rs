@@ -12,10 +12,10 @@ pub(crate) struct IotedgedVersion {
 }
 
 impl Checker for IotedgedVersion {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         "iotedged-version"
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         "latest security daemon"
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
@@ -45,8 +45,7 @@ impl IotedgedVersion {
             process.env("IOTEDGE_RUN_AS_CONSOLE", "true");
         }
 
-        let output = process
-            .output()
+        let output = super::output_with_timeout(&mut process, check.check_timeout)
             .context("Could not spawn iotedged process")?;
         if !output.status.success() {
             return Err(Context::new(format!(
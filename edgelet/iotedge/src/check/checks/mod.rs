@@ -39,11 +39,18 @@ pub(crate) use self::well_formed_connection_string::WellFormedConnectionString;
 pub(crate) use self::windows_host_version::WindowsHostVersion;
 
 use std::ffi::OsStr;
-use std::process::Command;
+use std::io::Read;
+use std::process::{Command, Output, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use failure::{self, Context, Fail};
+use failure::{self, Context, ResultExt};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 pub(crate) fn docker<I>(
+    timeout: Duration,
     docker_host_arg: &str,
     args: I,
 ) -> Result<Vec<u8>, (Option<String>, failure::Error)>
@@ -57,12 +64,7 @@ where
 
     process.args(args);
 
-    let output = process.output().map_err(|err| {
-        (
-            None,
-            err.context(format!("could not run {:?}", process)).into(),
-        )
-    })?;
+    let output = output_with_timeout(&mut process, timeout).map_err(|err| (None, err))?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&*output.stderr).into_owned();
@@ -76,3 +78,75 @@ where
 
     Ok(output.stdout)
 }
+
+/// Runs a process until it exits or times out, and collects what it printed.
+///
+/// Both pipes are drained while waiting, so that a chatty process does not block on a full pipe.
+/// They are given up at the same deadline, since a process may leave children holding them open.
+pub(crate) fn output_with_timeout(
+    process: &mut Command,
+    timeout: Duration,
+) -> Result<Output, failure::Error> {
+    let child = process
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = child.with_context(|_| format!("could not run {:?}", process))?;
+
+    let stdout = child.stdout.take().map(read_to_end);
+    let stderr = child.stderr.take().map(read_to_end);
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child
+            .try_wait()
+            .with_context(|_| format!("could not run {:?}", process))?
+        {
+            break status;
+        }
+
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(Context::new(format!(
+                "{:?} did not finish within {} seconds",
+                process,
+                timeout.as_secs(),
+            ))
+            .into());
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    let collect = |output: Option<Receiver<Vec<u8>>>| {
+        output
+            .and_then(|output| {
+                let left = deadline
+                    .checked_duration_since(Instant::now())
+                    .unwrap_or_default();
+                output.recv_timeout(left).ok()
+            })
+            .unwrap_or_default()
+    };
+
+    Ok(Output {
+        status,
+        stdout: collect(stdout),
+        stderr: collect(stderr),
+    })
+}
+
+fn read_to_end<R>(mut reader: R) -> Receiver<Vec<u8>>
+where
+    R: 'static + Read + Send,
+{
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = reader.read_to_end(&mut buf);
+        let _ = sender.send(buf);
+    });
+    receiver
+}
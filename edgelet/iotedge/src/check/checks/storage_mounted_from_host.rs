@@ -1,11 +1,12 @@
 //! Note: Keep in sync with Microsoft.Azure.Devices.Edge.Agent.Service.Program.GetStoragePath and Microsoft.Azure.Devices.Edge.Hub.Service.DependencyManager.GetStoragePath
 
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use failure::{self, Context, ResultExt};
 use regex::Regex;
 
-use crate::check::{checker::Checker, Check, CheckResult};
+use crate::check::{checker::Checker, Check, CheckResult, Remediation};
 
 #[derive(Default, serde_derive::Serialize)]
 pub(crate) struct EdgeAgentStorageMounted {
@@ -14,10 +15,10 @@ pub(crate) struct EdgeAgentStorageMounted {
 }
 
 impl Checker for EdgeAgentStorageMounted {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         "edge-agent-storage-mounted-from-host"
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         "production readiness: Edge Agent's storage directory is persisted on the host filesystem"
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
@@ -42,10 +43,10 @@ pub struct EdgeHubStorageMounted {
 }
 
 impl Checker for EdgeHubStorageMounted {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         "edge-hub-storage-mounted-from-host"
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         "production readiness: Edge Hub's storage directory is persisted on the host filesystem"
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
@@ -82,7 +83,7 @@ fn storage_mounted_from_host(
         return Ok(CheckResult::Skipped);
     };
 
-    let inspect_result = inspect_container(docker_host_arg, container_name)?;
+    let inspect_result = inspect_container(check.check_timeout, docker_host_arg, container_name)?;
 
     let temp_dir = inspect_result
         .config()
@@ -138,17 +139,23 @@ fn storage_mounted_from_host(
                 container_name,
                 storage_directory.display(),
             )).into(),
-        ));
+        )
+        .with_remediation(Remediation::new(format!(
+            "Bind a directory of the host to the {} directory in the createOptions of the {} module.",
+            storage_directory.display(),
+            container_name,
+        ))));
     }
 
     Ok(CheckResult::Ok)
 }
 
 fn inspect_container(
+    timeout: Duration,
     docker_host_arg: &str,
     name: &str,
 ) -> Result<docker::models::InlineResponse200, failure::Error> {
-    Ok(super::docker(docker_host_arg, &["inspect", name])
+    Ok(super::docker(timeout, docker_host_arg, &["inspect", name])
         .map_err(|(_, err)| err)
         .and_then(|output| {
             let (inspect_result,): (docker::models::InlineResponse200,) =
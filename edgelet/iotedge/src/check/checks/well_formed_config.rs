@@ -11,10 +11,10 @@ use crate::check::{checker::Checker, Check, CheckResult};
 pub(crate) struct WellFormedConfig {}
 
 impl Checker for WellFormedConfig {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         "config-yaml-well-formed"
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         "config.yaml is well-formed"
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
@@ -11,10 +11,10 @@ pub(crate) struct WellFormedConnectionString {
 }
 
 impl Checker for WellFormedConnectionString {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         "connection-string"
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         "config.yaml has well-formed connection string"
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
@@ -16,10 +16,10 @@ pub(crate) struct WindowsHostVersion {
 }
 
 impl Checker for WindowsHostVersion {
-    fn id(&self) -> &'static str {
+    fn id(&self) -> &str {
         "windows-host-version"
     }
-    fn description(&self) -> &'static str {
+    fn description(&self) -> &str {
         "Windows host version is supported"
     }
     fn execute(&mut self, check: &mut Check) -> CheckResult {
@@ -3,7 +3,8 @@
 use std;
 use std::collections::{BTreeMap, BTreeSet};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use failure::Fail;
 use failure::{self, ResultExt};
@@ -32,15 +33,22 @@ use checker::Checker;
 mod checks;
 use checks::*;
 
+mod plugin;
+use plugin::load_plugins;
+
 pub struct Check {
     config_file: PathBuf,
     container_engine_config_path: PathBuf,
     diagnostics_image_name: String,
     dont_run: BTreeSet<String>,
+    exclude: BTreeSet<String>,
+    include: BTreeSet<String>,
     iotedged: PathBuf,
     latest_versions: Result<super::LatestVersions, Option<Error>>,
     ntp_server: String,
     output_format: OutputFormat,
+    plugins_dir: PathBuf,
+    check_timeout: Duration,
     verbose: bool,
     warnings_as_errors: bool,
 
@@ -83,6 +91,59 @@ pub enum CheckResult {
 
     /// Check failed, and further checks should not be performed.
     Fatal(failure::Error),
+
+    /// Check failed with the inner result, and knows how to fix the problem it found.
+    WithRemediation(Box<CheckResult>, Remediation),
+}
+
+impl CheckResult {
+    /// Attaches how to fix the problem to a warning or a failure. Other results are returned as is.
+    pub fn with_remediation(self, remediation: Remediation) -> Self {
+        match self {
+            CheckResult::Warning(_) | CheckResult::Failed(_) | CheckResult::Fatal(_) => {
+                CheckResult::WithRemediation(Box::new(self), remediation)
+            }
+            CheckResult::WithRemediation(check_result, _) => {
+                CheckResult::WithRemediation(check_result, remediation)
+            }
+            check_result => check_result,
+        }
+    }
+
+    /// Splits the remediation off a result.
+    fn into_remediation(self) -> (Self, Option<Remediation>) {
+        match self {
+            CheckResult::WithRemediation(check_result, remediation) => {
+                (*check_result, Some(remediation))
+            }
+            check_result => (check_result, None),
+        }
+    }
+}
+
+/// How to fix the problem reported by a check.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct Remediation {
+    description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+}
+
+impl Remediation {
+    pub fn new(description: impl Into<String>) -> Self {
+        Remediation {
+            description: description.into(),
+            url: None,
+        }
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_ref().map(AsRef::as_ref)
+    }
 }
 
 impl Check {
@@ -91,11 +152,15 @@ impl Check {
         container_engine_config_path: PathBuf,
         diagnostics_image_name: String,
         dont_run: BTreeSet<String>,
+        exclude: BTreeSet<String>,
         expected_iotedged_version: Option<String>,
+        include: BTreeSet<String>,
         iotedged: PathBuf,
         iothub_hostname: Option<String>,
         ntp_server: String,
         output_format: OutputFormat,
+        plugins_dir: PathBuf,
+        check_timeout: Duration,
         verbose: bool,
         warnings_as_errors: bool,
     ) -> impl Future<Item = Self, Error = Error> + Send {
@@ -198,10 +263,14 @@ impl Check {
                 container_engine_config_path,
                 diagnostics_image_name,
                 dont_run,
+                exclude,
+                include,
                 iotedged,
                 latest_versions: latest_versions.map_err(Some),
                 ntp_server,
                 output_format,
+                plugins_dir,
+                check_timeout,
                 verbose,
                 warnings_as_errors,
 
@@ -216,11 +285,12 @@ impl Check {
         }))
     }
 
-    fn checks() -> [(&'static str, Vec<Box<dyn Checker>>); 2] {
+    fn checks() -> Vec<(&'static str, &'static str, Vec<Box<dyn Checker>>)> {
         /* Note: keep ordering consistant. Later tests may depend on earlier tests. */
-        [
+        vec![
             (
                 "Configuration checks",
+                "config",
                 vec![
                     Box::new(WellFormedConfig::default()),
                     Box::new(WellFormedConnectionString::default()),
@@ -241,7 +311,7 @@ impl Check {
                     Box::new(EdgeHubStorageMounted::default()),
                 ],
             ),
-            ("Connectivity checks", {
+            ("Connectivity checks", "connectivity", {
                 let mut tests: Vec<Box<dyn Checker>> = Vec::new();
                 tests.push(Box::new(HostConnectDpsEndpoint::default()));
                 tests.extend(get_host_connect_iothub_tests());
@@ -251,51 +321,95 @@ impl Check {
         ]
     }
 
-    pub fn possible_ids() -> impl Iterator<Item = &'static str> {
-        let result: Vec<&'static str> = Check::checks()
+    /// The built-in checks followed by the ones declared in `plugins_dir`.
+    fn checks_with_plugins(
+        plugins_dir: &Path,
+        check_timeout: Duration,
+    ) -> Result<Vec<(&'static str, &'static str, Vec<Box<dyn Checker>>)>, Error> {
+        let mut checks = Check::checks();
+
+        let plugins = load_plugins(plugins_dir, check_timeout)?;
+        let mut ids: BTreeSet<String> = Check::possible_ids().collect();
+        for plugin in &plugins {
+            if !ids.insert(plugin.id().to_owned()) {
+                return Err(ErrorKind::DuplicateCheckId(plugin.id().to_owned()).into());
+            }
+        }
+
+        if !plugins.is_empty() {
+            checks.push((
+                "Plugin checks",
+                "plugin",
+                plugins
+                    .into_iter()
+                    .map(|plugin| -> Box<dyn Checker> { Box::new(plugin) })
+                    .collect(),
+            ));
+        }
+
+        Ok(checks)
+    }
+
+    /// The IDs of the built-in checks.
+    pub fn possible_ids() -> impl Iterator<Item = String> {
+        let result: Vec<String> = Check::checks()
             .iter()
-            .flat_map(|(_, section_checks)| section_checks)
-            .map(|check| check.id())
+            .flat_map(|(_, _, section_checks)| section_checks)
+            .map(|check| check.id().to_owned())
             .collect();
 
         result.into_iter()
     }
 
-    pub fn print_list() -> Result<(), Error> {
+    pub fn print_list(plugins_dir: &Path) -> Result<(), Error> {
         // All our text is ASCII, so we can measure text width in bytes rather than using unicode-segmentation to count graphemes.
-        let checks = Check::checks();
+        let checks = Check::checks_with_plugins(plugins_dir, Duration::default())?;
         let widest_section_name_len = checks
             .iter()
-            .map(|(section_name, _)| section_name.len())
+            .map(|(section_name, _, _)| section_name.len())
             .max()
             .expect("Have at least one section");
         let section_name_column_width = widest_section_name_len + 1;
         let widest_check_id_len = checks
             .iter()
-            .flat_map(|(_, section_checks)| section_checks)
+            .flat_map(|(_, _, section_checks)| section_checks)
             .map(|check| check.id().len())
             .max()
             .expect("Have at least one check");
         let check_id_column_width = widest_check_id_len + 1;
+        let widest_tags_len = checks
+            .iter()
+            .flat_map(|(_, section_tag, section_checks)| {
+                section_checks
+                    .iter()
+                    .map(move |check| tags(section_tag, &**check).len())
+            })
+            .max()
+            .expect("Have at least one check");
+        let tags_column_width = widest_tags_len + 1;
 
         println!(
-            "{:section_name_column_width$}{:check_id_column_width$}DESCRIPTION",
+            "{:section_name_column_width$}{:check_id_column_width$}{:tags_column_width$}DESCRIPTION",
             "CATEGORY",
             "ID",
+            "TAGS",
             section_name_column_width = section_name_column_width,
             check_id_column_width = check_id_column_width,
+            tags_column_width = tags_column_width,
         );
         println!();
 
-        for (section_name, section_checks) in &checks {
+        for (section_name, section_tag, section_checks) in &checks {
             for check in section_checks {
                 println!(
-                    "{:section_name_column_width$}{:check_id_column_width$}{}",
+                    "{:section_name_column_width$}{:check_id_column_width$}{:tags_column_width$}{}",
                     section_name,
                     check.id(),
+                    tags(section_tag, &**check),
                     check.description(),
                     section_name_column_width = section_name_column_width,
                     check_id_column_width = check_id_column_width,
+                    tags_column_width = tags_column_width,
                 );
             }
 
@@ -305,9 +419,36 @@ impl Check {
         Ok(())
     }
 
+    /// Whether the check is selected by `--include`, `--exclude` and `--dont-run`.
+    ///
+    /// Checks are selected by their ID, their tags or the tag of their section.
+    fn is_selected(&self, section_tag: &str, check: &dyn Checker) -> bool {
+        let matches = |selectors: &BTreeSet<String>| {
+            selectors.contains(check.id())
+                || selectors.contains(section_tag)
+                || check.tags().iter().any(|tag| selectors.contains(tag))
+        };
+
+        !self.dont_run.contains(check.id())
+            && (self.include.is_empty() || matches(&self.include))
+            && !matches(&self.exclude)
+    }
+
     fn execute_inner(&mut self) -> Result<(), Error> {
-        let mut checks: BTreeMap<&str, CheckOutputSerializable> = Default::default();
-        let mut check_data = Check::checks();
+        let mut checks: BTreeMap<String, CheckOutputSerializable> = Default::default();
+        let mut check_data = Check::checks_with_plugins(&self.plugins_dir, self.check_timeout)?;
+
+        for selector in self.include.iter().chain(&self.exclude) {
+            let known = check_data.iter().any(|(_, section_tag, section_checks)| {
+                section_tag == selector
+                    || section_checks.iter().any(|check| {
+                        check.id() == selector || check.tags().iter().any(|tag| tag == selector)
+                    })
+            });
+            if !known {
+                return Err(ErrorKind::UnknownCheck(selector.clone()).into());
+            }
+        }
 
         let mut stdout = Stdout::new(self.output_format);
 
@@ -317,7 +458,7 @@ impl Check {
         let mut num_fatal = 0_usize;
         let mut num_errors = 0_usize;
 
-        for (section_name, section_checks) in &mut check_data {
+        for (section_name, section_tag, section_checks) in &mut check_data {
             if num_fatal > 0 {
                 break;
             }
@@ -328,18 +469,19 @@ impl Check {
             }
 
             for check in section_checks {
-                let check_id = check.id();
-                let check_name = check.description();
+                let check_id = check.id().to_owned();
+                let check_name = check.description().to_owned();
 
                 if num_fatal > 0 {
                     break;
                 }
 
-                let check_result = if self.dont_run.contains(check.id()) {
-                    CheckResult::Ignored
-                } else {
+                let check_result = if self.is_selected(section_tag, &**check) {
                     check.execute(self)
+                } else {
+                    CheckResult::Ignored
                 };
+                let (check_result, remediation) = check_result.into_remediation();
 
                 match check_result {
                     CheckResult::Ok => {
@@ -370,6 +512,7 @@ impl Check {
                                         .iter_chain()
                                         .map(ToString::to_string)
                                         .collect(),
+                                    remediation: remediation.clone(),
                                 },
                                 additional_info: check.get_json(),
                             },
@@ -393,6 +536,10 @@ impl Check {
                                 }
                             }
 
+                            if let Some(remediation) = &remediation {
+                                write_remediation(stdout, remediation)?;
+                            }
+
                             Ok(())
                         });
                     }
@@ -435,6 +582,7 @@ impl Check {
                             CheckOutputSerializable {
                                 result: CheckResultSerializable::Fatal {
                                     details: err.iter_chain().map(ToString::to_string).collect(),
+                                    remediation: remediation.clone(),
                                 },
                                 additional_info: check.get_json(),
                             },
@@ -458,6 +606,10 @@ impl Check {
                                 }
                             }
 
+                            if let Some(remediation) = &remediation {
+                                write_remediation(stdout, remediation)?;
+                            }
+
                             Ok(())
                         });
                     }
@@ -470,6 +622,7 @@ impl Check {
                             CheckOutputSerializable {
                                 result: CheckResultSerializable::Error {
                                     details: err.iter_chain().map(ToString::to_string).collect(),
+                                    remediation: remediation.clone(),
                                 },
                                 additional_info: check.get_json(),
                             },
@@ -493,9 +646,19 @@ impl Check {
                                 }
                             }
 
+                            if let Some(remediation) = &remediation {
+                                write_remediation(stdout, remediation)?;
+                            }
+
                             Ok(())
                         });
                     }
+
+                    CheckResult::WithRemediation(..) => {
+                        unreachable!(
+                            "remediation is split off check results before they are reported"
+                        )
+                    }
                 }
             }
 
@@ -581,6 +744,30 @@ impl crate::Command for Check {
     }
 }
 
+fn write_remediation(
+    writer: &mut (impl Write + ?Sized),
+    remediation: &Remediation,
+) -> std::io::Result<()> {
+    write_lines(
+        writer,
+        "    remediation: ",
+        "                 ",
+        remediation.description().lines(),
+    )?;
+    if let Some(url) = remediation.url() {
+        writeln!(writer, "                 See {} for more details.", url)?;
+    }
+    Ok(())
+}
+
+/// The tags a check is selected by, besides its ID.
+fn tags(section_tag: &str, check: &dyn Checker) -> String {
+    std::iter::once(section_tag)
+        .chain(check.tags().iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
 fn write_lines<'a>(
     writer: &mut (impl Write + ?Sized),
     first_line_indent: &str,
@@ -601,7 +788,7 @@ fn write_lines<'a>(
 #[derive(Debug, serde_derive::Serialize)]
 struct CheckResultsSerializable<'a> {
     additional_info: &'a AdditionalInfo,
-    checks: BTreeMap<&'static str, CheckOutputSerializable>,
+    checks: BTreeMap<String, CheckOutputSerializable>,
 }
 
 #[derive(Debug, serde_derive::Serialize)]
@@ -609,11 +796,23 @@ struct CheckResultsSerializable<'a> {
 #[serde(rename_all = "snake_case")]
 enum CheckResultSerializable {
     Ok,
-    Warning { details: Vec<String> },
+    Warning {
+        details: Vec<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        remediation: Option<Remediation>,
+    },
     Ignored,
     Skipped,
-    Fatal { details: Vec<String> },
-    Error { details: Vec<String> },
+    Fatal {
+        details: Vec<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        remediation: Option<Remediation>,
+    },
+    Error {
+        details: Vec<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        remediation: Option<Remediation>,
+    },
 }
 
 #[derive(Debug, serde_derive::Serialize)]
@@ -626,6 +825,29 @@ struct CheckOutputSerializable {
 mod tests {
     use super::*;
 
+    #[test]
+    fn remediation_is_attached_to_failures_only() {
+        let remediation = Remediation::new("restart the container engine");
+
+        let (check_result, attached) = CheckResult::Warning(failure::err_msg("no DNS server"))
+            .with_remediation(remediation.clone())
+            .into_remediation();
+        match check_result {
+            CheckResult::Warning(_) => (),
+            check_result => panic!("check returned {:?}", check_result),
+        }
+        assert_eq!(Some(remediation.clone()), attached);
+
+        let (check_result, attached) = CheckResult::Ok
+            .with_remediation(remediation)
+            .into_remediation();
+        match check_result {
+            CheckResult::Ok => (),
+            check_result => panic!("check returned {:?}", check_result),
+        }
+        assert_eq!(None, attached);
+    }
+
     #[test]
     fn config_file_checks_ok() {
         let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
@@ -644,11 +866,15 @@ mod tests {
                     "daemon.json".into(), // unused for this test
                     "mcr.microsoft.com/azureiotedge-diagnostics:1.0.0".to_owned(), // unused for this test
                     Default::default(),
-                    Some("1.0.0".to_owned()),      // unused for this test
+                    Default::default(),
+                    Some("1.0.0".to_owned()), // unused for this test
+                    Default::default(),
                     "iotedged".into(),             // unused for this test
                     None,                          // unused for this test
                     "pool.ntp.org:123".to_owned(), // unused for this test
                     super::OutputFormat::Text,     // unused for this test
+                    "check.d".into(),              // unused for this test
+                    Duration::from_secs(30),       // unused for this test
                     false,
                     false,
                 ))
@@ -715,11 +941,15 @@ mod tests {
                 "daemon.json".into(), // unused for this test
                 "mcr.microsoft.com/azureiotedge-diagnostics:1.0.0".to_owned(), // unused for this test
                 Default::default(),
-                Some("1.0.0".to_owned()),      // unused for this test
+                Default::default(),
+                Some("1.0.0".to_owned()), // unused for this test
+                Default::default(),
                 "iotedged".into(),             // unused for this test
                 None,                          // unused for this test
                 "pool.ntp.org:123".to_owned(), // unused for this test
                 super::OutputFormat::Text,     // unused for this test
+                "check.d".into(),              // unused for this test
+                Duration::from_secs(30),       // unused for this test
                 false,
                 false,
             ))
@@ -762,11 +992,15 @@ mod tests {
                 "daemon.json".into(), // unused for this test
                 "mcr.microsoft.com/azureiotedge-diagnostics:1.0.0".to_owned(), // unused for this test
                 Default::default(),
+                Default::default(),
                 Some("1.0.0".to_owned()), // unused for this test
-                "iotedged".into(),        // unused for this test
+                Default::default(),
+                "iotedged".into(),                          // unused for this test
                 Some("something.something.com".to_owned()), // pretend user specified --iothub-hostname
                 "pool.ntp.org:123".to_owned(),              // unused for this test
                 super::OutputFormat::Text,                  // unused for this test
+                "check.d".into(),                           // unused for this test
+                Duration::from_secs(30),                    // unused for this test
                 false,
                 false,
             ))
@@ -801,11 +1035,15 @@ mod tests {
                 "daemon.json".into(), // unused for this test
                 "mcr.microsoft.com/azureiotedge-diagnostics:1.0.0".to_owned(), // unused for this test
                 Default::default(),
-                Some("1.0.0".to_owned()),      // unused for this test
+                Default::default(),
+                Some("1.0.0".to_owned()), // unused for this test
+                Default::default(),
                 "iotedged".into(),             // unused for this test
                 None,                          // pretend user did not specify --iothub-hostname
                 "pool.ntp.org:123".to_owned(), // unused for this test
                 super::OutputFormat::Text,     // unused for this test
+                "check.d".into(),              // unused for this test
+                Duration::from_secs(30),       // unused for this test
                 false,
                 false,
             ))
@@ -825,6 +1063,63 @@ mod tests {
         }
     }
 
+    fn check_with_selectors(include: &[&str], exclude: &[&str]) -> Check {
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+
+        runtime
+            .block_on(Check::new(
+                "config.yaml".into(), // unused for this test
+                "daemon.json".into(), // unused for this test
+                "mcr.microsoft.com/azureiotedge-diagnostics:1.0.0".to_owned(), // unused for this test
+                Default::default(),
+                exclude.iter().map(ToString::to_string).collect(),
+                Some("1.0.0".to_owned()), // unused for this test
+                include.iter().map(ToString::to_string).collect(),
+                "iotedged".into(),             // unused for this test
+                None,                          // unused for this test
+                "pool.ntp.org:123".to_owned(), // unused for this test
+                super::OutputFormat::Json,
+                "check.d".into(),        // unused for this test
+                Duration::from_secs(30), // unused for this test
+                false,
+                false,
+            ))
+            .unwrap()
+    }
+
+    #[test]
+    fn include_and_exclude_select_by_id_and_tag() {
+        let check = check_with_selectors(
+            &["connectivity", "config-yaml-well-formed"],
+            &["host-connect-dps-endpoint"],
+        );
+
+        assert!(check.is_selected("config", &WellFormedConfig::default()));
+        assert!(!check.is_selected("config", &Hostname::default()));
+        assert!(!check.is_selected("connectivity", &HostConnectDpsEndpoint::default()));
+        for iothub_check in get_host_connect_iothub_tests() {
+            assert!(check.is_selected("connectivity", &*iothub_check));
+        }
+
+        let check = check_with_selectors(&[], &["config"]);
+
+        assert!(!check.is_selected("config", &WellFormedConfig::default()));
+        assert!(check.is_selected("connectivity", &HostConnectDpsEndpoint::default()));
+    }
+
+    #[test]
+    fn unknown_selector_fails() {
+        let mut check = check_with_selectors(&["no-such-check"], &[]);
+
+        match check.execute_inner() {
+            Err(err) => assert_eq!(
+                "No check has the ID or tag \"no-such-check\"",
+                err.to_string()
+            ),
+            Ok(()) => panic!("unknown selector was accepted"),
+        }
+    }
+
     #[test]
     #[cfg(windows)]
     fn moby_runtime_uri_windows_wants_moby_based_on_runtime_uri() {
@@ -844,11 +1139,15 @@ mod tests {
                 "daemon.json".into(), // unused for this test
                 "mcr.microsoft.com/azureiotedge-diagnostics:1.0.0".to_owned(), // unused for this test
                 Default::default(),
-                Some("1.0.0".to_owned()),      // unused for this test
+                Default::default(),
+                Some("1.0.0".to_owned()), // unused for this test
+                Default::default(),
                 "iotedged".into(),             // unused for this test
                 None,                          // unused for this test
                 "pool.ntp.org:123".to_owned(), // unused for this test
                 super::OutputFormat::Text,     // unused for this test
+                "check.d".into(),              // unused for this test
+                Duration::from_secs(30),       // unused for this test
                 false,
                 false,
             ))
@@ -897,11 +1196,15 @@ mod tests {
                 "daemon.json".into(), // unused for this test
                 "mcr.microsoft.com/azureiotedge-diagnostics:1.0.0".to_owned(), // unused for this test
                 Default::default(),
-                Some("1.0.0".to_owned()),      // unused for this test
+                Default::default(),
+                Some("1.0.0".to_owned()), // unused for this test
+                Default::default(),
                 "iotedged".into(),             // unused for this test
                 None,                          // unused for this test
                 "pool.ntp.org:123".to_owned(), // unused for this test
                 super::OutputFormat::Text,     // unused for this test
+                "check.d".into(),              // unused for this test
+                Duration::from_secs(30),       // unused for this test
                 false,
                 false,
             ))
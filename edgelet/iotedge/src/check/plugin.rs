@@ -0,0 +1,377 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use failure::{self, Context, Fail, ResultExt};
+
+use crate::check::checks::output_with_timeout;
+use crate::check::{checker::Checker, Check, CheckResult, Remediation};
+use crate::error::{Error, ErrorKind};
+
+/// A check run by an external executable, declared by a manifest in the plugins directory.
+///
+/// The executable reports its result as a JSON object on stdout, for example
+///
+/// ```json
+/// {
+///     "result": "warning",
+///     "message": "Proxy proxy.contoso.com:3128 is not reachable",
+///     "remediation": {
+///         "description": "Allow outbound connections to the site proxy",
+///         "url": "https://wiki.contoso.com/edge/proxy"
+///     },
+///     "additional_info": { "latency_ms": 1200 }
+/// }
+/// ```
+#[derive(serde_derive::Serialize)]
+pub(crate) struct PluginCheck {
+    #[serde(skip)]
+    manifest: Manifest,
+    #[serde(skip)]
+    dir: PathBuf,
+    #[serde(skip)]
+    timeout: Duration,
+
+    command: PathBuf,
+    additional_info: Option<serde_json::Value>,
+}
+
+#[derive(Debug, serde_derive::Deserialize)]
+struct Manifest {
+    id: String,
+    description: String,
+    command: PathBuf,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Timeout in seconds, overriding the default one.
+    timeout: Option<u64>,
+}
+
+#[derive(Debug, serde_derive::Deserialize)]
+struct PluginOutput {
+    result: PluginResult,
+    message: Option<String>,
+    remediation: Option<Remediation>,
+    additional_info: Option<serde_json::Value>,
+}
+
+#[derive(Clone, Copy, Debug, serde_derive::Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum PluginResult {
+    Ok,
+    Warning,
+    Ignored,
+    Skipped,
+    Error,
+    Fatal,
+}
+
+/// Loads the checks declared by the `*.yaml` manifests in `dir`, sorted by file name.
+///
+/// A missing directory declares no checks.
+pub(crate) fn load_plugins(dir: &Path, timeout: Duration) -> Result<Vec<PluginCheck>, Error> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(err
+                .context(ErrorKind::LoadCheckPlugin(dir.display().to_string()))
+                .into())
+        }
+    };
+
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry
+            .context(ErrorKind::LoadCheckPlugin(dir.display().to_string()))?
+            .path();
+        if path
+            .extension()
+            .map_or(false, |extension| extension == "yaml")
+        {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let file = fs::File::open(&path)
+                .context(ErrorKind::LoadCheckPlugin(path.display().to_string()))?;
+            let manifest: Manifest = serde_yaml::from_reader(file)
+                .context(ErrorKind::LoadCheckPlugin(path.display().to_string()))?;
+            Ok(PluginCheck::new(manifest, dir, timeout))
+        })
+        .collect()
+}
+
+impl PluginCheck {
+    fn new(manifest: Manifest, dir: &Path, default_timeout: Duration) -> Self {
+        let timeout = manifest
+            .timeout
+            .map_or(default_timeout, Duration::from_secs);
+        PluginCheck {
+            command: dir.join(&manifest.command),
+            manifest,
+            dir: dir.to_owned(),
+            timeout,
+            additional_info: None,
+        }
+    }
+
+    fn inner_execute(&mut self, check: &Check) -> Result<CheckResult, failure::Error> {
+        let PluginOutput {
+            result,
+            message,
+            remediation,
+            additional_info,
+        } = self.run(check)?;
+
+        self.additional_info = additional_info;
+
+        let command = &self.command;
+        let details = || -> failure::Error {
+            Context::new(
+                message
+                    .clone()
+                    .unwrap_or_else(|| format!("{} did not report any details", command.display())),
+            )
+            .into()
+        };
+        let check_result = match result {
+            PluginResult::Ok => CheckResult::Ok,
+            PluginResult::Warning => CheckResult::Warning(details()),
+            PluginResult::Ignored => CheckResult::Ignored,
+            PluginResult::Skipped => CheckResult::Skipped,
+            PluginResult::Error => CheckResult::Failed(details()),
+            PluginResult::Fatal => CheckResult::Fatal(details()),
+        };
+        Ok(match remediation {
+            Some(remediation) => check_result.with_remediation(remediation),
+            None => check_result,
+        })
+    }
+
+    /// Runs the executable until it exits or times out, and parses what it printed.
+    fn run(&self, check: &Check) -> Result<PluginOutput, failure::Error> {
+        let mut process = Command::new(&self.command);
+        process
+            .args(&self.manifest.args)
+            .current_dir(&self.dir)
+            .env("IOTEDGE_CHECK_CONFIG_FILE", &check.config_file);
+
+        let output = output_with_timeout(&mut process, self.timeout)?;
+
+        serde_json::from_slice(&output.stdout).map_err(|err| {
+            err.context(format!(
+                "{} returned {} without a valid result, stderr = {}",
+                self.command.display(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr),
+            ))
+            .into()
+        })
+    }
+}
+
+impl Checker for PluginCheck {
+    fn id(&self) -> &str {
+        &self.manifest.id
+    }
+    fn description(&self) -> &str {
+        &self.manifest.description
+    }
+    fn tags(&self) -> &[String] {
+        &self.manifest.tags
+    }
+    fn execute(&mut self, check: &mut Check) -> CheckResult {
+        self.inner_execute(check)
+            .unwrap_or_else(CheckResult::Failed)
+    }
+    fn get_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    #[test]
+    fn manifest_timeout_overrides_default() {
+        let manifest: Manifest = serde_yaml::from_str(
+            "id: site-proxy\n\
+             description: site proxy is reachable\n\
+             command: proxy.sh\n\
+             tags: [network, site]\n\
+             timeout: 5\n",
+        )
+        .unwrap();
+        let check = PluginCheck::new(
+            manifest,
+            Path::new("/etc/iotedge/check.d"),
+            Duration::from_secs(30),
+        );
+
+        assert_eq!("site-proxy", check.id());
+        assert_eq!("site proxy is reachable", check.description());
+        assert_eq!(&["network".to_owned(), "site".to_owned()], check.tags());
+        assert_eq!(Duration::from_secs(5), check.timeout);
+        assert_eq!(Path::new("/etc/iotedge/check.d/proxy.sh"), check.command);
+
+        let manifest: Manifest = serde_yaml::from_str(
+            "id: site-proxy\n\
+             description: site proxy is reachable\n\
+             command: /opt/checks/proxy.sh\n",
+        )
+        .unwrap();
+        let check = PluginCheck::new(
+            manifest,
+            Path::new("/etc/iotedge/check.d"),
+            Duration::from_secs(30),
+        );
+
+        assert!(check.tags().is_empty());
+        assert_eq!(Duration::from_secs(30), check.timeout);
+        assert_eq!(Path::new("/opt/checks/proxy.sh"), check.command);
+    }
+
+    #[test]
+    fn load_plugins_from_missing_dir_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let plugins = load_plugins(&dir.path().join("check.d"), Duration::from_secs(30)).unwrap();
+        assert!(plugins.is_empty());
+    }
+
+    #[test]
+    fn load_plugins_reads_yaml_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("b.yaml"),
+            "id: b\ndescription: second\ncommand: b.sh\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("a.yaml"),
+            "id: a\ndescription: first\ncommand: a.sh\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("a.sh"), "#!/bin/sh\n").unwrap();
+
+        let plugins = load_plugins(dir.path(), Duration::from_secs(30)).unwrap();
+        let ids: Vec<_> = plugins.iter().map(Checker::id).collect();
+        assert_eq!(vec!["a", "b"], ids);
+
+        fs::write(dir.path().join("c.yaml"), "id: c\n").unwrap();
+        let err = load_plugins(dir.path(), Duration::from_secs(30))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("c.yaml"), "{}", err);
+    }
+
+    #[cfg(unix)]
+    fn shell_check(script: &str, timeout: u64) -> PluginCheck {
+        let manifest = Manifest {
+            id: "shell".to_owned(),
+            description: "shell".to_owned(),
+            command: "/bin/sh".into(),
+            args: vec!["-c".to_owned(), script.to_owned()],
+            tags: Vec::new(),
+            timeout: Some(timeout),
+        };
+        PluginCheck::new(manifest, Path::new("/"), Duration::from_secs(30))
+    }
+
+    #[cfg(unix)]
+    fn check() -> Check {
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(Check::new(
+                "config.yaml".into(),
+                "daemon.json".into(),
+                "mcr.microsoft.com/azureiotedge-diagnostics:1.0.0".to_owned(),
+                Default::default(),
+                Default::default(),
+                Some("1.0.0".to_owned()),
+                Default::default(),
+                "iotedged".into(),
+                None,
+                "pool.ntp.org:123".to_owned(),
+                crate::check::OutputFormat::Json,
+                "check.d".into(),
+                Duration::from_secs(30),
+                false,
+                false,
+            ))
+            .unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn plugin_reports_result_and_remediation() {
+        let mut check = check();
+        let mut plugin = shell_check(
+            r#"echo '{"result": "warning", "message": "proxy unreachable", "remediation": {"description": "open port 3128"}, "additional_info": {"port": 3128}}'"#,
+            10,
+        );
+
+        let remediation = match plugin.execute(&mut check) {
+            CheckResult::WithRemediation(check_result, remediation) => match *check_result {
+                CheckResult::Warning(warning) => {
+                    assert_eq!("proxy unreachable", warning.to_string());
+                    remediation
+                }
+                check_result => panic!("plugin returned {:?}", check_result),
+            },
+            check_result => panic!("plugin returned {:?}", check_result),
+        };
+        assert_eq!("open port 3128", remediation.description());
+        assert_eq!(None, remediation.url());
+        assert_eq!(
+            serde_json::json!({ "command": "/bin/sh", "additional_info": { "port": 3128 } }),
+            plugin.get_json()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn plugin_without_result_fails() {
+        let mut check = check();
+        let mut plugin = shell_check("echo oops >&2; exit 3", 10);
+
+        match plugin.execute(&mut check) {
+            CheckResult::Failed(err) => {
+                let message = err.to_string();
+                assert!(message.contains("without a valid result"), "{}", message);
+                assert!(message.contains("oops"), "{}", message);
+            }
+            check_result => panic!("plugin returned {:?}", check_result),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn plugin_times_out() {
+        let mut check = check();
+        let mut plugin = shell_check("exec sleep 10", 1);
+
+        let start = Instant::now();
+        match plugin.execute(&mut check) {
+            CheckResult::Failed(err) => assert!(
+                err.to_string().contains("did not finish within 1 seconds"),
+                "{}",
+                err
+            ),
+            check_result => panic!("plugin returned {:?}", check_result),
+        }
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+}
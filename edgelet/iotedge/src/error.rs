@@ -12,18 +12,45 @@ pub struct Error {
 
 #[derive(Clone, Debug, Fail)]
 pub enum ErrorKind {
+    #[fail(display = "Invalid value for --check-timeout parameter")]
+    BadCheckTimeoutParameter,
+
     #[fail(display = "Invalid value for --host parameter")]
     BadHostParameter,
 
+    #[fail(display = "Invalid value for --max-file-size parameter")]
+    BadMaxFileSizeParameter,
+
+    #[fail(display = "Invalid value for --max-total-size parameter")]
+    BadMaxTotalSizeParameter,
+
+    #[fail(display = "Invalid value for --redact parameter")]
+    BadRedactParameter,
+
+    #[fail(display = "Invalid value for --regex parameter")]
+    BadRegexParameter,
+
+    #[fail(display = "Invalid value for --severity parameter")]
+    BadSeverityParameter,
+
     #[fail(display = "Invalid value for --since parameter")]
     BadSinceParameter,
 
     #[fail(display = "Invalid value for --tail parameter")]
     BadTailParameter,
 
+    #[fail(display = "Invalid value for --until parameter")]
+    BadUntilParameter,
+
+    #[fail(display = "Invalid value for --upload-url parameter")]
+    BadUploadUrlParameter,
+
     #[fail(display = "")]
     Diagnostics,
 
+    #[fail(display = "More than one check has the ID {:?}", _0)]
+    DuplicateCheckId(String),
+
     #[fail(
         display = "Error while fetching latest versions of edge components: {}",
         _0
@@ -33,15 +60,33 @@ pub enum ErrorKind {
     #[fail(display = "Could not initialize tokio runtime")]
     InitializeTokio,
 
+    #[fail(display = "Could not load check plugin {}", _0)]
+    LoadCheckPlugin(String),
+
     #[fail(display = "Missing --host parameter")]
     MissingHostParameter,
 
     #[fail(display = "A module runtime error occurred")]
     ModuleRuntime,
 
+    #[fail(display = "Could not serialize output")]
+    SerializeOutput,
+
     #[fail(display = "Could not generate support bundle")]
     SupportBundle,
 
+    #[fail(display = "No check has the ID or tag {:?}", _0)]
+    UnknownCheck(String),
+
+    #[fail(display = "Could not upload support bundle")]
+    UploadSupportBundle,
+
+    #[fail(
+        display = "Could not upload support bundle: server responded with {}",
+        _0
+    )]
+    UploadSupportBundleStatus(hyper::StatusCode),
+
     #[fail(display = "Could not write to stdout")]
     WriteToStdout,
 
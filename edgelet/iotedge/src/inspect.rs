@@ -0,0 +1,107 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fmt::Display;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use failure::{Fail, ResultExt};
+use futures::Future;
+use serde::Serialize;
+use serde_derive::Serialize;
+use tabwriter::TabWriter;
+
+use edgelet_core::{Module, ModuleRuntime, ModuleRuntimeState};
+
+use crate::error::{Error, ErrorKind};
+use crate::list::humanize_state;
+use crate::output::{write_output, DisplayFormat};
+use crate::Command;
+
+pub struct Inspect<M, W> {
+    id: String,
+    runtime: M,
+    format: DisplayFormat,
+    output: Arc<Mutex<TabWriter<W>>>,
+}
+
+#[derive(Serialize)]
+struct ModuleDetails<'a, C> {
+    name: &'a str,
+    #[serde(rename = "type")]
+    type_: &'a str,
+    config: &'a C,
+    #[serde(flatten)]
+    state: &'a ModuleRuntimeState,
+}
+
+impl<M, W> Inspect<M, W>
+where
+    W: Write,
+{
+    pub fn new(id: String, runtime: M, format: DisplayFormat, output: W) -> Self {
+        let tab = TabWriter::new(output).minwidth(15);
+        Inspect {
+            id,
+            runtime,
+            format,
+            output: Arc::new(Mutex::new(tab)),
+        }
+    }
+}
+
+impl<M, W> Command for Inspect<M, W>
+where
+    M: 'static + ModuleRuntime + Clone,
+    M::Config: Display + Serialize,
+    W: 'static + Write + Send,
+{
+    type Future = Box<dyn Future<Item = (), Error = Error> + Send>;
+
+    fn execute(self) -> Self::Future {
+        let format = self.format;
+        let write = self.output.clone();
+        let result = self
+            .runtime
+            .get(&self.id)
+            .map_err(|err| Error::from(err.context(ErrorKind::ModuleRuntime)))
+            .and_then(move |(module, state)| {
+                let details = ModuleDetails {
+                    name: module.name(),
+                    type_: module.type_(),
+                    config: module.config(),
+                    state: &state,
+                };
+
+                let mut w = write.lock().unwrap();
+                write_output(&mut *w, format, &details, |w, details| {
+                    writeln!(w, "NAME\t{}", details.name).context(ErrorKind::WriteToStdout)?;
+                    writeln!(w, "TYPE\t{}", details.type_).context(ErrorKind::WriteToStdout)?;
+                    writeln!(w, "STATUS\t{}", details.state.status())
+                        .context(ErrorKind::WriteToStdout)?;
+                    writeln!(w, "DESCRIPTION\t{}", humanize_state(details.state))
+                        .context(ErrorKind::WriteToStdout)?;
+                    writeln!(w, "CONFIG\t{}", details.config).context(ErrorKind::WriteToStdout)?;
+                    if let Some(image_id) = details.state.image_id() {
+                        writeln!(w, "IMAGE ID\t{}", image_id).context(ErrorKind::WriteToStdout)?;
+                    }
+                    if let Some(pid) = details.state.pid() {
+                        writeln!(w, "PID\t{}", pid).context(ErrorKind::WriteToStdout)?;
+                    }
+                    if let Some(exit_code) = details.state.exit_code() {
+                        writeln!(w, "EXIT CODE\t{}", exit_code)
+                            .context(ErrorKind::WriteToStdout)?;
+                    }
+                    if let Some(started_at) = details.state.started_at() {
+                        writeln!(w, "STARTED AT\t{}", started_at.to_rfc3339())
+                            .context(ErrorKind::WriteToStdout)?;
+                    }
+                    if let Some(finished_at) = details.state.finished_at() {
+                        writeln!(w, "FINISHED AT\t{}", finished_at.to_rfc3339())
+                            .context(ErrorKind::WriteToStdout)?;
+                    }
+                    Ok(())
+                })
+            });
+        Box::new(result)
+    }
+}
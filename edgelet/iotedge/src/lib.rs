@@ -18,19 +18,31 @@ use serde_derive::Deserialize;
 
 mod check;
 mod error;
+mod inspect;
 mod list;
 mod logs;
+mod output;
+mod redact;
 mod restart;
+mod start;
+mod stop;
 mod support_bundle;
+mod system_info;
 mod unknown;
 mod version;
 
 pub use crate::check::{Check, OutputFormat};
 pub use crate::error::{Error, ErrorKind, FetchLatestVersionsReason};
+pub use crate::inspect::Inspect;
 pub use crate::list::List;
 pub use crate::logs::Logs;
+pub use crate::output::DisplayFormat;
+pub use crate::redact::Redaction;
 pub use crate::restart::Restart;
+pub use crate::start::Start;
+pub use crate::stop::Stop;
 pub use crate::support_bundle::{OutputLocation, SupportBundle};
+pub use crate::system_info::SystemInfo;
 pub use crate::unknown::Unknown;
 pub use crate::version::Version;
 
@@ -8,26 +8,38 @@ use chrono::{Duration, Utc};
 use chrono_humanize::{Accuracy, HumanTime, Tense};
 use failure::{Fail, ResultExt};
 use futures::{Future, Stream};
+use serde_derive::Serialize;
 use tabwriter::TabWriter;
 
 use edgelet_core::{Module, ModuleRuntime, ModuleRuntimeState, ModuleStatus};
 
 use crate::error::{Error, ErrorKind};
+use crate::output::{write_output, DisplayFormat};
 use crate::Command;
 
 pub struct List<M, W> {
     runtime: M,
+    format: DisplayFormat,
     output: Arc<Mutex<TabWriter<W>>>,
 }
 
+#[derive(Serialize)]
+struct ModuleSummary<'a> {
+    name: &'a str,
+    config: String,
+    #[serde(flatten)]
+    state: &'a ModuleRuntimeState,
+}
+
 impl<M, W> List<M, W>
 where
     W: Write,
 {
-    pub fn new(runtime: M, output: W) -> Self {
+    pub fn new(runtime: M, format: DisplayFormat, output: W) -> Self {
         let tab = TabWriter::new(output).minwidth(15);
         List {
             runtime,
+            format,
             output: Arc::new(Mutex::new(tab)),
         }
     }
@@ -43,6 +55,7 @@ where
     type Future = Box<dyn Future<Item = (), Error = Error> + Send>;
 
     fn execute(self) -> Self::Future {
+        let format = self.format;
         let write = self.output.clone();
         let result = self
             .runtime
@@ -52,28 +65,38 @@ where
             .and_then(move |mut result| {
                 result.sort_by(|(mod1, _), (mod2, _)| mod1.name().cmp(mod2.name()));
 
+                let modules: Vec<_> = result
+                    .iter()
+                    .map(|(module, state)| ModuleSummary {
+                        name: module.name(),
+                        config: module.config().to_string(),
+                        state,
+                    })
+                    .collect();
+
                 let mut w = write.lock().unwrap();
-                writeln!(w, "NAME\tSTATUS\tDESCRIPTION\tCONFIG")
-                    .context(ErrorKind::WriteToStdout)?;
-                for (module, state) in result {
-                    writeln!(
-                        w,
-                        "{}\t{}\t{}\t{}",
-                        module.name(),
-                        state.status(),
-                        humanize_state(&state),
-                        module.config(),
-                    )
-                    .context(ErrorKind::WriteToStdout)?;
-                }
-                w.flush().context(ErrorKind::WriteToStdout)?;
-                Ok(())
+                write_output(&mut *w, format, &modules, |w, modules| {
+                    writeln!(w, "NAME\tSTATUS\tDESCRIPTION\tCONFIG")
+                        .context(ErrorKind::WriteToStdout)?;
+                    for module in modules {
+                        writeln!(
+                            w,
+                            "{}\t{}\t{}\t{}",
+                            module.name,
+                            module.state.status(),
+                            humanize_state(module.state),
+                            module.config,
+                        )
+                        .context(ErrorKind::WriteToStdout)?;
+                    }
+                    Ok(())
+                })
             });
         Box::new(result)
     }
 }
 
-fn humanize_state(state: &ModuleRuntimeState) -> String {
+pub(crate) fn humanize_state(state: &ModuleRuntimeState) -> String {
     match *state.status() {
         ModuleStatus::Unknown => "Unknown".to_string(),
         ModuleStatus::Stopped => state.finished_at().map_or_else(
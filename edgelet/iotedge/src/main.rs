@@ -8,10 +8,12 @@ use std::borrow::Cow;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::process;
+use std::time::Duration;
 
-use clap::{crate_description, crate_name, App, AppSettings, Arg, SubCommand};
+use clap::{crate_description, crate_name, App, AppSettings, Arg, ArgMatches, SubCommand};
 use failure::{Fail, ResultExt};
 use futures::Future;
+use regex::Regex;
 use url::Url;
 
 use edgelet_core::{parse_since, LogOptions, LogTail};
@@ -37,42 +39,52 @@ fn main() {
 
 #[allow(clippy::too_many_lines)]
 fn run() -> Result<(), Error> {
-    let (default_mgmt_uri, default_config_path, default_container_engine_config_path) =
-        if cfg!(windows) {
-            let program_data: PathBuf = std::env::var_os("PROGRAMDATA")
-                .map_or_else(|| r"C:\ProgramData".into(), Into::into);
+    let (
+        default_mgmt_uri,
+        default_config_path,
+        default_container_engine_config_path,
+        default_plugins_dir,
+    ) = if cfg!(windows) {
+        let program_data: PathBuf =
+            std::env::var_os("PROGRAMDATA").map_or_else(|| r"C:\ProgramData".into(), Into::into);
 
-            let default_mgmt_uri = program_data
-                .to_str()
-                .expect("PROGRAMDATA is not a utf-8 path")
-                .replace('\\', "/");
-            let default_mgmt_uri = format!("unix:///{}/iotedge/mgmt/sock", default_mgmt_uri);
-            let default_mgmt_uri = Cow::Owned(default_mgmt_uri);
+        let default_mgmt_uri = program_data
+            .to_str()
+            .expect("PROGRAMDATA is not a utf-8 path")
+            .replace('\\', "/");
+        let default_mgmt_uri = format!("unix:///{}/iotedge/mgmt/sock", default_mgmt_uri);
+        let default_mgmt_uri = Cow::Owned(default_mgmt_uri);
 
-            let mut default_config_path = program_data.clone();
-            default_config_path.push("iotedge");
-            default_config_path.push("config.yaml");
-            let default_config_path = Cow::Owned(default_config_path);
+        let mut default_config_path = program_data.clone();
+        default_config_path.push("iotedge");
+        default_config_path.push("config.yaml");
+        let default_config_path = Cow::Owned(default_config_path);
 
-            let mut default_container_engine_config_path = program_data;
-            default_container_engine_config_path.push("iotedge-moby");
-            default_container_engine_config_path.push("config");
-            default_container_engine_config_path.push("daemon.json");
-            let default_container_engine_config_path =
-                Cow::Owned(default_container_engine_config_path);
+        let mut default_plugins_dir = program_data.clone();
+        default_plugins_dir.push("iotedge");
+        default_plugins_dir.push("check.d");
+        let default_plugins_dir = Cow::Owned(default_plugins_dir);
 
-            (
-                default_mgmt_uri,
-                default_config_path,
-                default_container_engine_config_path,
-            )
-        } else {
-            (
-                Cow::Borrowed("unix:///var/run/iotedge/mgmt.sock"),
-                Cow::Borrowed(Path::new("/etc/iotedge/config.yaml")),
-                Cow::Borrowed(Path::new("/etc/docker/daemon.json")),
-            )
-        };
+        let mut default_container_engine_config_path = program_data;
+        default_container_engine_config_path.push("iotedge-moby");
+        default_container_engine_config_path.push("config");
+        default_container_engine_config_path.push("daemon.json");
+        let default_container_engine_config_path = Cow::Owned(default_container_engine_config_path);
+
+        (
+            default_mgmt_uri,
+            default_config_path,
+            default_container_engine_config_path,
+            default_plugins_dir,
+        )
+    } else {
+        (
+            Cow::Borrowed("unix:///var/run/iotedge/mgmt.sock"),
+            Cow::Borrowed(Path::new("/etc/iotedge/config.yaml")),
+            Cow::Borrowed(Path::new("/etc/docker/daemon.json")),
+            Cow::Borrowed(Path::new("/etc/iotedge/check.d")),
+        )
+    };
 
     let default_mgmt_uri = option_env!("IOTEDGE_HOST").unwrap_or(&*default_mgmt_uri);
 
@@ -81,8 +93,9 @@ fn run() -> Result<(), Error> {
         edgelet_core::version().replace("~", "-")
     );
 
-    let mut possible_check_id_values: Vec<_> = Check::possible_ids().collect();
-    possible_check_id_values.sort();
+    let mut possible_check_ids: Vec<_> = Check::possible_ids().collect();
+    possible_check_ids.sort();
+    let possible_check_id_values: Vec<_> = possible_check_ids.iter().map(String::as_str).collect();
 
     let matches = App::new(crate_name!())
         .version(edgelet_core::version_with_source_version())
@@ -102,6 +115,14 @@ fn run() -> Result<(), Error> {
         .subcommand(
             SubCommand::with_name("check")
                 .about("Check for common config and deployment issues")
+                .arg(
+                    Arg::with_name("check-timeout")
+                        .long("check-timeout")
+                        .value_name("SECONDS")
+                        .help("Sets how long a check may wait on a process or a connection before it fails. A check from --plugins-dir can override it with the timeout field of its manifest.")
+                        .takes_value(true)
+                        .default_value("60"),
+                )
                 .arg(
                     Arg::with_name("config-file")
                         .short("c")
@@ -136,6 +157,14 @@ fn run() -> Result<(), Error> {
                         .takes_value(true)
                         .possible_values(&possible_check_id_values),
                 )
+                .arg(
+                    Arg::with_name("exclude")
+                        .long("exclude")
+                        .value_name("ID_OR_TAG")
+                        .help("Space-separated list of check IDs or tags. The checks matching any of them will not be run. See 'iotedge check-list' for details of all checks.")
+                        .multiple(true)
+                        .takes_value(true),
+                )
                 .arg(
                     Arg::with_name("expected-iotedged-version")
                         .long("expected-iotedged-version")
@@ -143,6 +172,14 @@ fn run() -> Result<(), Error> {
                         .help("Sets the expected version of the iotedged binary. Defaults to the value contained in <http://aka.ms/latest-iotedge-stable>")
                         .takes_value(true),
                 )
+                .arg(
+                    Arg::with_name("include")
+                        .long("include")
+                        .value_name("ID_OR_TAG")
+                        .help("Space-separated list of check IDs or tags. Only the checks matching any of them will be run. See 'iotedge check-list' for details of all checks.")
+                        .multiple(true)
+                        .takes_value(true),
+                )
                 .arg(
                     Arg::with_name("iotedged")
                         .long("iotedged")
@@ -178,6 +215,7 @@ fn run() -> Result<(), Error> {
                         .possible_values(&["json", "text"])
                         .default_value("text"),
                 )
+                .arg(plugins_dir_arg(&default_plugins_dir))
                 .arg(
                     Arg::with_name("verbose")
                         .long("verbose")
@@ -193,8 +231,27 @@ fn run() -> Result<(), Error> {
                         .takes_value(false),
                 ),
         )
-        .subcommand(SubCommand::with_name("check-list").about("List the checks that are run for 'iotedge check'"))
-        .subcommand(SubCommand::with_name("list").about("List modules"))
+        .subcommand(
+            SubCommand::with_name("check-list")
+                .about("List the checks that are run for 'iotedge check'")
+                .arg(plugins_dir_arg(&default_plugins_dir)),
+        )
+        .subcommand(
+            SubCommand::with_name("inspect")
+                .about("Show the details of a module")
+                .arg(
+                    Arg::with_name("MODULE")
+                        .help("Sets the module identity to inspect")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(output_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("List modules")
+                .arg(output_arg()),
+        )
         .subcommand(
             SubCommand::with_name("restart")
                 .about("Restart a module")
@@ -230,6 +287,41 @@ fn run() -> Result<(), Error> {
                         .value_name("DURATION or TIMESTAMP")
                         .default_value("1 day"),
                 )
+                .arg(
+                    Arg::with_name("until")
+                        .help("Only return logs before this time, as a duration (1 day, 90 minutes, 2 days 3 hours 2 minutes), rfc3339 timestamp, or UNIX timestamp")
+                        .long("until")
+                        .takes_value(true)
+                        .value_name("DURATION or TIMESTAMP"),
+                )
+                .arg(
+                    Arg::with_name("timestamps")
+                        .help("Show timestamps")
+                        .short("t")
+                        .long("timestamps"),
+                )
+                .arg(
+                    Arg::with_name("stream")
+                        .help("Only return logs written to this stream")
+                        .long("stream")
+                        .takes_value(true)
+                        .possible_values(&["all", "stdout", "stderr"])
+                        .default_value("all"),
+                )
+                .arg(
+                    Arg::with_name("regex")
+                        .help("Only return log lines matching this regular expression")
+                        .long("regex")
+                        .takes_value(true)
+                        .value_name("REGEX"),
+                )
+                .arg(
+                    Arg::with_name("severity")
+                        .help("Only return log lines at least as severe as this syslog severity, as a level (0-7) or name (emerg, alert, crit, err, warning, notice, info, debug)")
+                        .long("severity")
+                        .takes_value(true)
+                        .value_name("SEVERITY"),
+                )
                 .arg(
                     Arg::with_name("follow")
                         .help("Follow output log")
@@ -275,9 +367,69 @@ fn run() -> Result<(), Error> {
                         .long("quiet")
                         .short("q")
                         .takes_value(false),
+                ).arg(
+                    Arg::with_name("redact")
+                        .help("Replaces the matches of this regular expression with <REDACTED> in every file of the bundle. Can be specified multiple times.")
+                        .long("redact")
+                        .takes_value(true)
+                        .value_name("REGEX")
+                        .multiple(true)
+                        .number_of_values(1),
+                ).arg(
+                    Arg::with_name("no-builtin-redaction")
+                        .help("Do not redact SAS tokens, keys, connection string secrets and IP addresses from the bundle")
+                        .long("no-builtin-redaction")
+                        .takes_value(false),
+                ).arg(
+                    Arg::with_name("max-file-size")
+                        .help("Truncates each file in the bundle to this many bytes")
+                        .long("max-file-size")
+                        .takes_value(true)
+                        .value_name("BYTES"),
+                ).arg(
+                    Arg::with_name("max-total-size")
+                        .help("Truncates the files in the bundle once they add up to this many bytes")
+                        .long("max-total-size")
+                        .takes_value(true)
+                        .value_name("BYTES"),
+                ).arg(
+                    Arg::with_name("upload-url")
+                        .help("Uploads the bundle with an HTTP PUT to this URL, which can be an HTTP(S) endpoint or an Azure blob SAS URL")
+                        .long("upload-url")
+                        .takes_value(true)
+                        .value_name("URL"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("start")
+                .about("Start a module")
+                .arg(
+                    Arg::with_name("MODULE")
+                        .help("Sets the module identity to start")
+                        .required(true)
+                        .index(1),
                 ),
         )
-        .subcommand(SubCommand::with_name("version").about("Show the version information"))
+        .subcommand(
+            SubCommand::with_name("stop")
+                .about("Stop a module")
+                .arg(
+                    Arg::with_name("MODULE")
+                        .help("Sets the module identity to stop")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("system-info")
+                .about("Show information about the host and the resources it uses")
+                .arg(output_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("version")
+                .about("Show the version information")
+                .arg(output_arg()),
+        )
         .get_matches();
 
     let runtime = || -> Result<_, Error> {
@@ -314,8 +466,18 @@ fn run() -> Result<(), Error> {
                     .flatten()
                     .map(ToOwned::to_owned)
                     .collect(),
+                args.values_of("exclude")
+                    .into_iter()
+                    .flatten()
+                    .map(ToOwned::to_owned)
+                    .collect(),
                 args.value_of("expected-iotedged-version")
                     .map(ToOwned::to_owned),
+                args.values_of("include")
+                    .into_iter()
+                    .flatten()
+                    .map(ToOwned::to_owned)
+                    .collect(),
                 args.value_of_os("iotedged")
                     .expect("arg has a default value")
                     .to_os_string()
@@ -331,13 +493,36 @@ fn run() -> Result<(), Error> {
                         _ => unreachable!(),
                     })
                     .expect("arg has a default value"),
+                args.value_of_os("plugins-dir")
+                    .expect("arg has a default value")
+                    .to_os_string()
+                    .into(),
+                args.value_of("check-timeout")
+                    .map(str::parse)
+                    .transpose()
+                    .context(ErrorKind::BadCheckTimeoutParameter)?
+                    .map(Duration::from_secs)
+                    .expect("arg has a default value"),
                 args.is_present("verbose"),
                 args.is_present("warnings-as-errors"),
             )
             .and_then(Command::execute),
         ),
-        ("check-list", _) => Check::print_list(),
-        ("list", _) => tokio_runtime.block_on(List::new(runtime()?, io::stdout()).execute()),
+        ("check-list", Some(args)) => Check::print_list(Path::new(
+            args.value_of_os("plugins-dir")
+                .expect("arg has a default value"),
+        )),
+        ("inspect", Some(args)) => tokio_runtime.block_on(
+            Inspect::new(
+                args.value_of("MODULE").unwrap().to_string(),
+                runtime()?,
+                display_format(args),
+                io::stdout(),
+            )
+            .execute(),
+        ),
+        ("list", Some(args)) => tokio_runtime
+            .block_on(List::new(runtime()?, display_format(args), io::stdout()).execute()),
         ("restart", Some(args)) => tokio_runtime.block_on(
             Restart::new(
                 args.value_of("MODULE").unwrap().to_string(),
@@ -363,10 +548,35 @@ fn run() -> Result<(), Error> {
                 .transpose()
                 .context(ErrorKind::BadSinceParameter)?
                 .expect("arg has a default value");
+            let until = args
+                .value_of("until")
+                .map(|s| parse_since(s))
+                .transpose()
+                .context(ErrorKind::BadUntilParameter)?;
+            let timestamps = args.is_present("timestamps");
+            let stream = args.value_of("stream").expect("arg has a default value");
+            let regex = args
+                .value_of("regex")
+                .map(Regex::new)
+                .transpose()
+                .context(ErrorKind::BadRegexParameter)?;
+            let severity = args
+                .value_of("severity")
+                .map(str::parse)
+                .transpose()
+                .map_err(|err: edgelet_core::Error| {
+                    Error::from(err.context(ErrorKind::BadSeverityParameter))
+                })?;
             let options = LogOptions::new()
                 .with_follow(follow)
                 .with_tail(tail)
-                .with_since(since);
+                .with_since(since)
+                .with_until(until)
+                .with_timestamps(timestamps)
+                .with_stdout(stream != "stderr")
+                .with_stderr(stream != "stdout")
+                .with_regex(regex)
+                .with_severity(severity);
             tokio_runtime.block_on(Logs::new(id, options, runtime()?).execute())
         }
         ("support-bundle", Some(args)) => {
@@ -389,6 +599,32 @@ fn run() -> Result<(), Error> {
             } else {
                 OutputLocation::File(location.to_owned())
             };
+            let redaction = args
+                .values_of("redact")
+                .into_iter()
+                .flatten()
+                .try_fold(
+                    Redaction::new().with_builtin(!args.is_present("no-builtin-redaction")),
+                    |redaction, pattern| {
+                        Regex::new(pattern).map(|pattern| redaction.with_pattern(pattern))
+                    },
+                )
+                .context(ErrorKind::BadRedactParameter)?;
+            let max_file_size = args
+                .value_of("max-file-size")
+                .map(str::parse)
+                .transpose()
+                .context(ErrorKind::BadMaxFileSizeParameter)?;
+            let max_total_size = args
+                .value_of("max-total-size")
+                .map(str::parse)
+                .transpose()
+                .context(ErrorKind::BadMaxTotalSizeParameter)?;
+            let upload_url = args
+                .value_of("upload-url")
+                .map(Url::parse)
+                .transpose()
+                .context(ErrorKind::BadUploadUrlParameter)?;
 
             tokio_runtime.block_on(
                 SupportBundle::new(
@@ -399,10 +635,65 @@ fn run() -> Result<(), Error> {
                     output_location,
                     runtime()?,
                 )
+                .with_redaction(redaction)
+                .with_max_file_size(max_file_size)
+                .with_max_total_size(max_total_size)
+                .with_upload_url(upload_url)
                 .execute(),
             )
         }
-        ("version", _) => tokio_runtime.block_on(Version::new().execute()),
+        ("start", Some(args)) => tokio_runtime.block_on(
+            Start::new(
+                args.value_of("MODULE").unwrap().to_string(),
+                runtime()?,
+                io::stdout(),
+            )
+            .execute(),
+        ),
+        ("stop", Some(args)) => tokio_runtime.block_on(
+            Stop::new(
+                args.value_of("MODULE").unwrap().to_string(),
+                runtime()?,
+                io::stdout(),
+            )
+            .execute(),
+        ),
+        ("system-info", Some(args)) => tokio_runtime
+            .block_on(SystemInfo::new(runtime()?, display_format(args), io::stdout()).execute()),
+        ("version", Some(args)) => {
+            tokio_runtime.block_on(Version::new(display_format(args)).execute())
+        }
         (command, _) => tokio_runtime.block_on(Unknown::new(command.to_string()).execute()),
     }
 }
+
+fn plugins_dir_arg<'a, 'b>(default_plugins_dir: &'a Path) -> Arg<'a, 'b> {
+    Arg::with_name("plugins-dir")
+        .long("plugins-dir")
+        .value_name("DIR")
+        .help("Sets the directory of the manifests declaring additional checks")
+        .takes_value(true)
+        .default_value_os(default_plugins_dir.as_os_str())
+}
+
+fn output_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("output")
+        .long("output")
+        .short("o")
+        .value_name("FORMAT")
+        .help("Output format")
+        .takes_value(true)
+        .possible_values(&["json", "yaml", "table"])
+        .default_value("table")
+}
+
+fn display_format(args: &ArgMatches<'_>) -> DisplayFormat {
+    args.value_of("output")
+        .map(|arg| match arg {
+            "json" => DisplayFormat::Json,
+            "yaml" => DisplayFormat::Yaml,
+            "table" => DisplayFormat::Table,
+            _ => unreachable!(),
+        })
+        .expect("arg has a default value")
+}
@@ -0,0 +1,91 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::io::Write;
+
+use failure::ResultExt;
+use serde::Serialize;
+
+use crate::error::{Error, ErrorKind};
+
+/// Output format of the commands listing modules or system information.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DisplayFormat {
+    Json,
+    Yaml,
+    Table,
+}
+
+/// Writes `value` as JSON or YAML, or as a table written by `table`.
+pub fn write_output<W, T, F>(
+    w: &mut W,
+    format: DisplayFormat,
+    value: &T,
+    table: F,
+) -> Result<(), Error>
+where
+    W: Write,
+    T: Serialize,
+    F: FnOnce(&mut W, &T) -> Result<(), Error>,
+{
+    match format {
+        DisplayFormat::Json => {
+            serde_json::to_writer_pretty(&mut *w, value).context(ErrorKind::SerializeOutput)?;
+            writeln!(w).context(ErrorKind::WriteToStdout)?;
+        }
+        DisplayFormat::Yaml => {
+            serde_yaml::to_writer(&mut *w, value).context(ErrorKind::SerializeOutput)?;
+            writeln!(w).context(ErrorKind::WriteToStdout)?;
+        }
+        DisplayFormat::Table => table(w, value)?,
+    }
+    w.flush().context(ErrorKind::WriteToStdout)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_derive::Serialize;
+
+    #[derive(Serialize)]
+    struct Module {
+        name: &'static str,
+        status: &'static str,
+    }
+
+    fn write(format: DisplayFormat) -> String {
+        let module = Module {
+            name: "edgeAgent",
+            status: "running",
+        };
+        let mut output = Vec::new();
+        write_output(&mut output, format, &module, |w, module| {
+            writeln!(w, "{}\t{}", module.name, module.status).context(ErrorKind::WriteToStdout)?;
+            Ok(())
+        })
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn writes_json() {
+        assert_eq!(
+            "{\n  \"name\": \"edgeAgent\",\n  \"status\": \"running\"\n}\n",
+            write(DisplayFormat::Json)
+        );
+    }
+
+    #[test]
+    fn writes_yaml() {
+        assert_eq!(
+            "---\nname: edgeAgent\nstatus: running\n",
+            write(DisplayFormat::Yaml)
+        );
+    }
+
+    #[test]
+    fn writes_table() {
+        assert_eq!("edgeAgent\trunning\n", write(DisplayFormat::Table));
+    }
+}
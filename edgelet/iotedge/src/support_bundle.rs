@@ -1,22 +1,30 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::cmp;
+use std::convert::TryFrom;
 use std::env;
 use std::ffi::OsString;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{stdout, Cursor, Seek};
 use std::path::{Path, PathBuf};
 use std::process::Command as ShellCommand;
 
 use chrono::{DateTime, Local, NaiveDateTime, Utc};
-use failure::Fail;
+use failure::{Fail, ResultExt};
 use futures::{Future, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Method, Request};
 use tokio::prelude::*;
+use url::Url;
 use zip::{write::FileOptions, CompressionMethod, ZipWriter};
 
 use edgelet_core::{LogOptions, LogTail, Module, ModuleRuntime};
+use edgelet_http::client::ClientImpl;
+use edgelet_http::MaybeProxyClient;
 
 use crate::error::{Error, ErrorKind};
 use crate::logs::pull_logs;
+use crate::redact::Redaction;
 use crate::Command;
 
 pub struct SupportBundle<M> {
@@ -26,6 +34,10 @@ pub struct SupportBundle<M> {
     verbose: bool,
     iothub_hostname: Option<String>,
     output_location: OutputLocation,
+    redaction: Redaction,
+    max_file_size: Option<u64>,
+    max_total_size: Option<u64>,
+    upload_url: Option<Url>,
 }
 
 struct BundleState<M, W>
@@ -37,6 +49,10 @@ where
     include_ms_only: bool,
     verbose: bool,
     iothub_hostname: Option<String>,
+    redaction: Redaction,
+    max_file_size: Option<u64>,
+    max_total_size: Option<u64>,
+    written: u64,
     file_options: FileOptions,
     zip_writer: ZipWriter<W>,
 }
@@ -50,28 +66,51 @@ where
     fn execute(self) -> Self::Future {
         println!("Making support bundle");
 
+        let upload_url = self.upload_url.clone();
         match self.output_location.clone() {
             OutputLocation::File(location) => Box::new(
-                Self::bundle_all(future::result(self.make_file_state())).map(|_state| {
-                    let path = PathBuf::from(location);
-                    println!(
-                        "Created support bundle at {}",
-                        path.canonicalize().unwrap_or_else(|_| path).display()
-                    );
-                }),
+                Self::bundle_all(future::result(self.make_file_state()))
+                    .and_then(|mut state| {
+                        state
+                            .zip_writer
+                            .finish()
+                            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
+                        let path = PathBuf::from(location);
+                        let path = path.canonicalize().unwrap_or_else(|_| path);
+                        println!("Created support bundle at {}", path.display());
+                        Ok(path)
+                    })
+                    .and_then(move |path| match upload_url {
+                        Some(url) => future::Either::A(
+                            fs::read(path)
+                                .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))
+                                .into_future()
+                                .and_then(move |bundle| upload(&url, bundle).map(move |()| url))
+                                .map(|url| {
+                                    println!("Uploaded support bundle to {}", display_url(&url));
+                                }),
+                        ),
+                        None => future::Either::B(future::ok(())),
+                    }),
             ),
             OutputLocation::Console => Box::new(
-                Self::bundle_all(future::result(self.make_vector_state())).and_then(|mut state| {
-                    stdout()
-                        .write_all(
-                            state
-                                .zip_writer
-                                .finish()
-                                .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?
-                                .get_ref(),
-                        )
-                        .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))
-                }),
+                Self::bundle_all(future::result(self.make_vector_state()))
+                    .and_then(|mut state| {
+                        let bundle = state
+                            .zip_writer
+                            .finish()
+                            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?
+                            .into_inner();
+                        stdout()
+                            .write_all(&bundle)
+                            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
+                        Ok(bundle)
+                    })
+                    .and_then(move |bundle| match upload_url {
+                        // Stdout holds the bundle itself, so the upload is not reported.
+                        Some(url) => future::Either::A(upload(&url, bundle)),
+                        None => future::Either::B(future::ok(())),
+                    }),
             ),
         }
     }
@@ -96,9 +135,37 @@ where
             verbose,
             iothub_hostname,
             output_location,
+            redaction: Redaction::new(),
+            max_file_size: None,
+            max_total_size: None,
+            upload_url: None,
         }
     }
 
+    /// Sets the rules redacting the contents of every file in the bundle.
+    pub fn with_redaction(mut self, redaction: Redaction) -> Self {
+        self.redaction = redaction;
+        self
+    }
+
+    /// Caps the size of each file in the bundle, before compression.
+    pub fn with_max_file_size(mut self, max_file_size: Option<u64>) -> Self {
+        self.max_file_size = max_file_size;
+        self
+    }
+
+    /// Caps the total size of the files in the bundle, before compression.
+    pub fn with_max_total_size(mut self, max_total_size: Option<u64>) -> Self {
+        self.max_total_size = max_total_size;
+        self
+    }
+
+    /// Uploads the bundle to an HTTP(S) endpoint or an Azure blob SAS URL once it is created.
+    pub fn with_upload_url(mut self, upload_url: Option<Url>) -> Self {
+        self.upload_url = upload_url;
+        self
+    }
+
     fn make_file_state(self) -> Result<BundleState<M, File>, Error> {
         let writer = File::create(Path::new(self.output_location.get_file_location()))
             .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
@@ -123,6 +190,10 @@ where
             include_ms_only: self.include_ms_only,
             verbose: self.verbose,
             iothub_hostname: self.iothub_hostname,
+            redaction: self.redaction,
+            max_file_size: self.max_file_size,
+            max_total_size: self.max_total_size,
+            written: 0,
             file_options,
             zip_writer,
         })
@@ -227,36 +298,16 @@ where
         W: Write + Seek + Send,
     {
         state.print_verbose(&format!("Writing {} logs to file", module_name));
-        let BundleState {
-            runtime,
-            log_options,
-            include_ms_only,
-            verbose,
-            iothub_hostname,
-            file_options,
-            mut zip_writer,
-        } = state;
-
-        let file_name = format!("{}_log.txt", module_name);
-        zip_writer
-            .start_file_from_path(&Path::new("logs").join(file_name), file_options)
-            .into_future()
-            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))
-            .and_then(move |_| {
-                pull_logs(&runtime, &module_name, &log_options, zip_writer).map(move |zw| {
-                    let state = BundleState {
-                        runtime,
-                        log_options,
-                        include_ms_only,
-                        verbose,
-                        iothub_hostname,
-                        file_options,
-                        zip_writer: zw,
-                    };
-                    state.print_verbose(&format!("Wrote {} logs to file", module_name));
-                    state
-                })
-            })
+
+        let file_name = Path::new("logs").join(format!("{}_log.txt", module_name));
+        pull_logs(&state.runtime, &module_name, &state.log_options, Vec::new()).and_then(
+            move |logs| {
+                let mut state = state;
+                state.write_file(&file_name, &logs)?;
+                state.print_verbose(&format!("Wrote {} logs to file", module_name));
+                Ok(state)
+            },
+        )
     }
 
     fn write_edgelet_log<W>(mut state: BundleState<M, W>) -> Result<BundleState<M, W>, Error>
@@ -299,15 +350,7 @@ where
             ("iotedged_err.txt", err_message.as_bytes().to_vec())
         };
 
-        state
-            .zip_writer
-            .start_file_from_path(&Path::new("logs").join(file_name), state.file_options)
-            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
-
-        state
-            .zip_writer
-            .write_all(&output)
-            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
+        state.write_file(&Path::new("logs").join(file_name), &output)?;
 
         state.print_verbose("Got logs for iotedged");
         Ok(state)
@@ -356,15 +399,7 @@ where
             ("docker_err.txt", err_message.as_bytes().to_vec())
         };
 
-        state
-            .zip_writer
-            .start_file_from_path(&Path::new("logs").join(file_name), state.file_options)
-            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
-
-        state
-            .zip_writer
-            .write_all(&output)
-            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
+        state.write_file(&Path::new("logs").join(file_name), &output)?;
 
         state.print_verbose("Got logs for docker");
         Ok(state)
@@ -387,15 +422,7 @@ where
             .output()
             .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
 
-        state
-            .zip_writer
-            .start_file_from_path(&Path::new("check.json"), state.file_options)
-            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
-
-        state
-            .zip_writer
-            .write_all(&check.stdout)
-            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
+        state.write_file(&Path::new("check.json"), &check.stdout)?;
 
         state.print_verbose("Wrote check output to file");
         Ok(state)
@@ -441,15 +468,7 @@ where
             )
         };
 
-        state
-            .zip_writer
-            .start_file_from_path(&Path::new(&file_name), state.file_options)
-            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
-
-        state
-            .zip_writer
-            .write_all(&output)
-            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
+        state.write_file(&Path::new(&file_name), &output)?;
 
         state.print_verbose(&format!("Got docker inspect for {}", module_name));
         Ok(state)
@@ -531,15 +550,7 @@ where
             )
         };
 
-        state
-            .zip_writer
-            .start_file_from_path(&Path::new(&file_name), state.file_options)
-            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
-
-        state
-            .zip_writer
-            .write_all(&output)
-            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
+        state.write_file(&Path::new(&file_name), &output)?;
 
         state.print_verbose(&format!("Got docker network inspect for {}", network_name));
         Ok(state)
@@ -555,6 +566,106 @@ where
             println!("{}", message);
         }
     }
+
+    /// How many bytes the next file may hold, or `None` if it is not limited.
+    fn file_limit(&self) -> Option<u64> {
+        let remaining = self
+            .max_total_size
+            .map(|max_total_size| max_total_size.saturating_sub(self.written));
+        match (self.max_file_size, remaining) {
+            (Some(max_file_size), Some(remaining)) => Some(cmp::min(max_file_size, remaining)),
+            (max_file_size, remaining) => max_file_size.or(remaining),
+        }
+    }
+
+    /// Adds a file to the bundle after redacting and truncating its contents.
+    ///
+    /// Truncation applies to the redacted contents, so a secret cut in half is still redacted.
+    /// A truncated file ends with a marker saying how much was left out.
+    fn write_file(&mut self, file_name: &Path, contents: &[u8]) -> Result<(), Error> {
+        let contents = self.redaction.redact_bytes(contents);
+
+        let mut kept = contents.len();
+        if let Some(limit) = self.file_limit() {
+            kept = cmp::min(kept, usize::try_from(limit).unwrap_or(usize::max_value()));
+        }
+        let dropped = contents.len() - kept;
+
+        self.zip_writer
+            .start_file_from_path(file_name, self.file_options)
+            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
+        self.zip_writer
+            .write_all(&contents[..kept])
+            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
+        if dropped > 0 {
+            write!(
+                self.zip_writer,
+                "\n[support bundle: truncated {} bytes]\n",
+                dropped
+            )
+            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
+        }
+
+        self.written += kept as u64;
+        Ok(())
+    }
+}
+
+fn upload(url: &Url, bundle: Vec<u8>) -> impl Future<Item = (), Error = Error> + Send {
+    let proxy = env::var("HTTPS_PROXY")
+        .ok()
+        .or_else(|| env::var("https_proxy").ok())
+        .map(|proxy| proxy.parse::<hyper::Uri>())
+        .transpose()
+        .context(ErrorKind::UploadSupportBundle);
+    let client = proxy.and_then(|proxy| {
+        MaybeProxyClient::new(proxy, None, None).context(ErrorKind::UploadSupportBundle)
+    });
+    let client = match client {
+        Ok(client) => client,
+        Err(err) => return future::Either::A(future::err(err.into())),
+    };
+
+    // Azure Storage needs to be told which kind of blob a SAS URL creates.
+    let is_blob_sas = url.query_pairs().any(|(key, _)| key == "sig");
+    let mut request = Request::builder();
+    request
+        .method(Method::PUT)
+        .uri(url.as_str())
+        .header(CONTENT_TYPE, "application/zip")
+        .header(CONTENT_LENGTH, bundle.len().to_string().as_str());
+    if is_blob_sas {
+        request.header("x-ms-blob-type", "BlockBlob");
+    }
+    let request = match request.body(Body::from(bundle)) {
+        Ok(request) => request,
+        Err(err) => {
+            return future::Either::A(future::err(
+                err.context(ErrorKind::UploadSupportBundle).into(),
+            ))
+        }
+    };
+
+    future::Either::B(
+        client
+            .call(request)
+            .map_err(|err| Error::from(err.context(ErrorKind::UploadSupportBundle)))
+            .and_then(|response| {
+                let status = response.status();
+                if status.is_success() {
+                    Ok(())
+                } else {
+                    Err(Error::from(ErrorKind::UploadSupportBundleStatus(status)))
+                }
+            }),
+    )
+}
+
+/// The upload URL without its query, which may hold a SAS token.
+fn display_url(url: &Url) -> Url {
+    let mut url = url.clone();
+    url.set_query(None);
+    url
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -783,6 +894,138 @@ mod tests {
         File::open(file_path).unwrap();
     }
 
+    #[test]
+    fn module_logs_are_truncated() {
+        let bundle = SupportBundle::new(
+            LogOptions::default(),
+            false,
+            false,
+            None,
+            OutputLocation::Console,
+            make_runtime("test-module"),
+        )
+        .with_max_file_size(Some(13));
+
+        let state = bundle.make_vector_state().unwrap();
+        let state = SupportBundle::write_log_to_file(state, "test-module".to_owned())
+            .wait()
+            .unwrap();
+
+        assert_eq!(
+            "Roses are red\n[support bundle: truncated 16 bytes]\n",
+            read_bundle_file(state, "logs/test-module_log.txt")
+        );
+    }
+
+    #[test]
+    fn module_logs_are_redacted_before_they_are_truncated() {
+        let bundle = SupportBundle::new(
+            LogOptions::default(),
+            false,
+            false,
+            None,
+            OutputLocation::Console,
+            make_runtime("test-module"),
+        )
+        .with_max_file_size(Some(15))
+        .with_redaction(Redaction::new().with_pattern(Regex::new("redviolets").unwrap()));
+
+        let state = bundle.make_vector_state().unwrap();
+        let state = SupportBundle::write_log_to_file(state, "test-module".to_owned())
+            .wait()
+            .unwrap();
+
+        assert_eq!(
+            "Roses are <REDA\n[support bundle: truncated 14 bytes]\n",
+            read_bundle_file(state, "logs/test-module_log.txt")
+        );
+    }
+
+    #[test]
+    fn total_size_is_capped() {
+        let bundle = SupportBundle::new(
+            LogOptions::default(),
+            false,
+            false,
+            None,
+            OutputLocation::Console,
+            make_runtime("test-module"),
+        )
+        .with_max_file_size(Some(8))
+        .with_max_total_size(Some(10));
+
+        let mut state = bundle.make_vector_state().unwrap();
+        state
+            .write_file(Path::new("first.txt"), b"Roses are red")
+            .unwrap();
+        state
+            .write_file(Path::new("second.txt"), b"violets are blue")
+            .unwrap();
+        state.write_file(Path::new("third.txt"), b"").unwrap();
+        assert_eq!(10, state.written);
+
+        let mut archive = zip::ZipArchive::new(state.zip_writer.finish().unwrap()).unwrap();
+        let mut read = |name| {
+            let mut contents = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+            contents
+        };
+        assert_eq!(
+            "Roses ar\n[support bundle: truncated 5 bytes]\n",
+            read("first.txt")
+        );
+        assert_eq!(
+            "vi\n[support bundle: truncated 14 bytes]\n",
+            read("second.txt")
+        );
+        assert_eq!("", read("third.txt"));
+    }
+
+    #[test]
+    fn files_are_redacted() {
+        let bundle = SupportBundle::new(
+            LogOptions::default(),
+            false,
+            false,
+            None,
+            OutputLocation::Console,
+            make_runtime("test-module"),
+        )
+        .with_redaction(
+            Redaction::new()
+                .with_builtin(true)
+                .with_pattern(Regex::new("violets").unwrap()),
+        );
+
+        let mut state = bundle.make_vector_state().unwrap();
+        state
+            .write_file(Path::new("check.json"), b"SharedAccessKey=bXlrZXk= violets")
+            .unwrap();
+
+        assert_eq!(
+            "SharedAccessKey=<REDACTED> <REDACTED>",
+            read_bundle_file(state, "check.json")
+        );
+    }
+
+    fn read_bundle_file(
+        mut state: BundleState<TestRuntime<Error, TestSettings>, Cursor<Vec<u8>>>,
+        name: &str,
+    ) -> String {
+        let mut archive = zip::ZipArchive::new(state.zip_writer.finish().unwrap()).unwrap();
+        let mut contents = String::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        contents
+    }
+
     fn make_runtime(module_name: &str) -> TestRuntime<Error, TestSettings> {
         let logs = vec![
             &[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, b'R', b'o'][..],
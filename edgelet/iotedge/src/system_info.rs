@@ -0,0 +1,114 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use byte_unit::Byte;
+use failure::{Fail, ResultExt};
+use futures::Future;
+use serde_derive::Serialize;
+use tabwriter::TabWriter;
+
+use edgelet_core::{ModuleRuntime, SystemResources};
+
+use crate::error::{Error, ErrorKind};
+use crate::output::{write_output, DisplayFormat};
+use crate::Command;
+
+pub struct SystemInfo<M, W> {
+    runtime: M,
+    format: DisplayFormat,
+    output: Arc<Mutex<TabWriter<W>>>,
+}
+
+#[derive(Serialize)]
+struct SystemDetails<'a> {
+    os_type: &'a str,
+    architecture: &'a str,
+    version: &'a str,
+    resources: &'a SystemResources,
+}
+
+impl<M, W> SystemInfo<M, W>
+where
+    W: Write,
+{
+    pub fn new(runtime: M, format: DisplayFormat, output: W) -> Self {
+        let tab = TabWriter::new(output).minwidth(15);
+        SystemInfo {
+            runtime,
+            format,
+            output: Arc::new(Mutex::new(tab)),
+        }
+    }
+}
+
+impl<M, W> Command for SystemInfo<M, W>
+where
+    M: 'static + ModuleRuntime + Clone,
+    W: 'static + Write + Send,
+{
+    type Future = Box<dyn Future<Item = (), Error = Error> + Send>;
+
+    fn execute(self) -> Self::Future {
+        let format = self.format;
+        let write = self.output.clone();
+        let result = self
+            .runtime
+            .system_info()
+            .join(self.runtime.system_resources())
+            .map_err(|err| Error::from(err.context(ErrorKind::ModuleRuntime)))
+            .and_then(move |(info, resources)| {
+                let details = SystemDetails {
+                    os_type: info.os_type(),
+                    architecture: info.architecture(),
+                    version: info.version(),
+                    resources: &resources,
+                };
+
+                let mut w = write.lock().unwrap();
+                write_output(&mut *w, format, &details, |w, details| {
+                    let resources = details.resources;
+                    writeln!(w, "OS TYPE\t{}", details.os_type)
+                        .context(ErrorKind::WriteToStdout)?;
+                    writeln!(w, "ARCHITECTURE\t{}", details.architecture)
+                        .context(ErrorKind::WriteToStdout)?;
+                    writeln!(w, "VERSION\t{}", details.version)
+                        .context(ErrorKind::WriteToStdout)?;
+                    writeln!(w, "HOST UPTIME\t{}s", resources.host_uptime())
+                        .context(ErrorKind::WriteToStdout)?;
+                    writeln!(w, "PROCESS UPTIME\t{}s", resources.process_uptime())
+                        .context(ErrorKind::WriteToStdout)?;
+                    writeln!(w, "USED CPU\t{:.2}", resources.used_cpu())
+                        .context(ErrorKind::WriteToStdout)?;
+                    writeln!(
+                        w,
+                        "USED RAM\t{} / {}",
+                        pretty_bytes(resources.used_ram()),
+                        pretty_bytes(resources.total_ram()),
+                    )
+                    .context(ErrorKind::WriteToStdout)?;
+                    for disk in resources.disks() {
+                        writeln!(
+                            w,
+                            "DISK {}\t{} free of {} ({}, {})",
+                            disk.name(),
+                            pretty_bytes(disk.available_space()),
+                            pretty_bytes(disk.total_space()),
+                            disk.file_system(),
+                            disk.file_type(),
+                        )
+                        .context(ErrorKind::WriteToStdout)?;
+                    }
+                    Ok(())
+                })
+            });
+        Box::new(result)
+    }
+}
+
+fn pretty_bytes(bytes: u64) -> String {
+    Byte::from_bytes(u128::from(bytes))
+        .get_appropriate_unit(true)
+        .format(2)
+}
@@ -1,32 +1,48 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::io;
+use std::io::Write;
+
 use clap::crate_name;
+use failure::ResultExt;
+use serde_derive::Serialize;
 
 use edgelet_core;
 use futures::future::{self, FutureResult};
 
-use crate::error::Error;
+use crate::error::{Error, ErrorKind};
+use crate::output::{write_output, DisplayFormat};
 use crate::Command;
 
-#[derive(Default)]
-pub struct Version;
+pub struct Version {
+    format: DisplayFormat,
+}
+
+#[derive(Serialize)]
+struct VersionInfo {
+    name: &'static str,
+    version: &'static str,
+}
 
 impl Version {
-    pub fn new() -> Self {
-        Version
+    pub fn new(format: DisplayFormat) -> Self {
+        Version { format }
     }
 }
 
 impl Command for Version {
     type Future = FutureResult<(), Error>;
 
-    #[allow(clippy::print_literal)]
     fn execute(self) -> Self::Future {
-        println!(
-            "{} {}",
-            crate_name!(),
-            edgelet_core::version_with_source_version(),
-        );
-        future::ok(())
+        let info = VersionInfo {
+            name: crate_name!(),
+            version: edgelet_core::version_with_source_version(),
+        };
+        let stdout = io::stdout();
+        let result = write_output(&mut stdout.lock(), self.format, &info, |w, info| {
+            writeln!(w, "{} {}", info.name, info.version).context(ErrorKind::WriteToStdout)?;
+            Ok(())
+        });
+        future::result(result)
     }
 }
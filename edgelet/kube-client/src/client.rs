@@ -2,8 +2,8 @@
 
 use bytes::BytesMut;
 use failure::{Fail, ResultExt};
-use futures::future;
 use futures::prelude::*;
+use futures::{future, stream};
 use hyper::body::Payload;
 use hyper::client::connect::Connect;
 use hyper::client::{Client as HyperClient, HttpConnector, ResponseFuture};
@@ -21,11 +21,20 @@ use k8s_openapi::apimachinery::pkg::apis::meta::v1 as api_meta;
 use k8s_openapi::{
     http, CreateOptional, CreateResponse, DeleteOptional, DeleteResponse, List, ListOptional,
     ListResponse, ReplaceOptional, ReplaceResponse, Response as K8sResponse, ResponseBody,
+    ResponseError, WatchOptional, WatchResponse,
 };
 use log::{debug, trace};
+use serde::de::DeserializeOwned;
+use url::form_urlencoded;
+use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
 
 use crate::config::{Config, TokenSource};
 use crate::error::{Error, ErrorKind, RequestType};
+use crate::metrics::{MetricsList, NodeMetrics, PodMetrics};
+
+/// Number of seconds after which Kubernetes is asked to close a watch, so that watchers list
+/// objects again instead of waiting on a connection that may have been lost.
+const WATCH_TIMEOUT_SECONDS: i64 = 300;
 
 pub struct HttpClient<C, B>(pub HyperClient<C, B>);
 
@@ -277,6 +286,29 @@ where
             .flatten()
     }
 
+    /// Streams the changes of the deployments of a namespace after `resource_version`.
+    /// The stream ends when Kubernetes closes the watch, after a few minutes.
+    pub fn watch_deployments(
+        &mut self,
+        namespace: &str,
+        label_selector: Option<&str>,
+        resource_version: Option<&str>,
+    ) -> impl Stream<Item = api_meta::WatchEvent<api_apps::Deployment>, Error = Error> {
+        let params = WatchOptional {
+            label_selector,
+            resource_version,
+            timeout_seconds: Some(WATCH_TIMEOUT_SECONDS),
+            ..WatchOptional::default()
+        };
+        api_apps::Deployment::watch_namespaced_deployment(namespace, params)
+            .map_err(|err| {
+                Error::from(err.context(ErrorKind::Request(RequestType::DeploymentWatch)))
+            })
+            .map(|req| self.watch(req, RequestType::DeploymentWatch))
+            .into_future()
+            .flatten_stream()
+    }
+
     pub fn create_deployment(
         &mut self,
         namespace: &str,
@@ -364,6 +396,176 @@ where
         .flatten()
     }
 
+    pub fn list_services(
+        &mut self,
+        namespace: &str,
+        name: Option<&str>,
+        label_selector: Option<&str>,
+    ) -> impl Future<Item = List<api_core::Service>, Error = Error> {
+        let field_selector = name.map(|name| format!("metadata.name={}", name));
+        let params = ListOptional {
+            field_selector: field_selector.as_ref().map(String::as_ref),
+            label_selector,
+            ..ListOptional::default()
+        };
+        api_core::Service::list_namespaced_service(namespace, params)
+            .map_err(|err| Error::from(err.context(ErrorKind::Request(RequestType::ServiceList))))
+            .map(|req| {
+                self.request(req, true)
+                    .and_then(|response| match response {
+                        ListResponse::Ok(list) => Ok(list),
+                        _ => Err(Error::from(ErrorKind::Response(RequestType::ServiceList))),
+                    })
+                    .map_err(|err| {
+                        Error::from(err.context(ErrorKind::Response(RequestType::ServiceList)))
+                    })
+            })
+            .into_future()
+            .flatten()
+    }
+
+    pub fn create_service(
+        &mut self,
+        namespace: &str,
+        service: &api_core::Service,
+    ) -> impl Future<Item = api_core::Service, Error = Error> {
+        api_core::Service::create_namespaced_service(namespace, service, CreateOptional::default())
+            .map_err(|err| Error::from(err.context(ErrorKind::Request(RequestType::ServiceCreate))))
+            .map(|req| {
+                self.request(req, true)
+                    .and_then(|response| match response {
+                        CreateResponse::Accepted(s)
+                        | CreateResponse::Created(s)
+                        | CreateResponse::Ok(s) => Ok(s),
+                        _ => Err(Error::from(ErrorKind::Response(RequestType::ServiceCreate))),
+                    })
+                    .map_err(|err| {
+                        Error::from(err.context(ErrorKind::Response(RequestType::ServiceCreate)))
+                    })
+            })
+            .into_future()
+            .flatten()
+    }
+
+    pub fn replace_service(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        service: &api_core::Service,
+    ) -> impl Future<Item = api_core::Service, Error = Error> {
+        api_core::Service::replace_namespaced_service(
+            name,
+            namespace,
+            service,
+            ReplaceOptional::default(),
+        )
+        .map_err(|err| Error::from(err.context(ErrorKind::Request(RequestType::ServiceReplace))))
+        .map(|req| {
+            self.request(req, true)
+                .and_then(|response| match response {
+                    ReplaceResponse::Created(s) | ReplaceResponse::Ok(s) => Ok(s),
+                    _ => Err(Error::from(ErrorKind::Response(
+                        RequestType::ServiceReplace,
+                    ))),
+                })
+                .map_err(|err| {
+                    Error::from(err.context(ErrorKind::Response(RequestType::ServiceReplace)))
+                })
+        })
+        .into_future()
+        .flatten()
+    }
+
+    pub fn delete_service(
+        &mut self,
+        namespace: &str,
+        name: &str,
+    ) -> impl Future<Item = (), Error = Error> {
+        api_core::Service::delete_namespaced_service(name, namespace, DeleteOptional::default())
+            .map_err(|err| Error::from(err.context(ErrorKind::Request(RequestType::ServiceDelete))))
+            .map(|req| {
+                self.request(req, true)
+                    .and_then(|response| match response {
+                        DeleteResponse::OkStatus(_) | DeleteResponse::OkValue(_) => Ok(()),
+                        _ => Err(Error::from(ErrorKind::Response(RequestType::ServiceDelete))),
+                    })
+                    .map_err(|err| {
+                        Error::from(err.context(ErrorKind::Response(RequestType::ServiceDelete)))
+                    })
+            })
+            .into_future()
+            .flatten()
+    }
+
+    pub fn list_persistent_volume_claims(
+        &mut self,
+        namespace: &str,
+        name: Option<&str>,
+        label_selector: Option<&str>,
+    ) -> impl Future<Item = List<api_core::PersistentVolumeClaim>, Error = Error> {
+        let field_selector = name.map(|name| format!("metadata.name={}", name));
+        let params = ListOptional {
+            field_selector: field_selector.as_ref().map(String::as_ref),
+            label_selector,
+            ..ListOptional::default()
+        };
+        api_core::PersistentVolumeClaim::list_namespaced_persistent_volume_claim(namespace, params)
+            .map_err(|err| {
+                Error::from(err.context(ErrorKind::Request(RequestType::PersistentVolumeClaimList)))
+            })
+            .map(|req| {
+                self.request(req, true)
+                    .and_then(|response| match response {
+                        ListResponse::Ok(list) => Ok(list),
+                        _ => Err(Error::from(ErrorKind::Response(
+                            RequestType::PersistentVolumeClaimList,
+                        ))),
+                    })
+                    .map_err(|err| {
+                        Error::from(
+                            err.context(ErrorKind::Response(
+                                RequestType::PersistentVolumeClaimList,
+                            )),
+                        )
+                    })
+            })
+            .into_future()
+            .flatten()
+    }
+
+    pub fn create_persistent_volume_claim(
+        &mut self,
+        namespace: &str,
+        claim: &api_core::PersistentVolumeClaim,
+    ) -> impl Future<Item = api_core::PersistentVolumeClaim, Error = Error> {
+        api_core::PersistentVolumeClaim::create_namespaced_persistent_volume_claim(
+            namespace,
+            claim,
+            CreateOptional::default(),
+        )
+        .map_err(|err| {
+            Error::from(err.context(ErrorKind::Request(RequestType::PersistentVolumeClaimCreate)))
+        })
+        .map(|req| {
+            self.request(req, true)
+                .and_then(|response| match response {
+                    CreateResponse::Accepted(s)
+                    | CreateResponse::Created(s)
+                    | CreateResponse::Ok(s) => Ok(s),
+                    _ => Err(Error::from(ErrorKind::Response(
+                        RequestType::PersistentVolumeClaimCreate,
+                    ))),
+                })
+                .map_err(|err| {
+                    Error::from(err.context(ErrorKind::Response(
+                        RequestType::PersistentVolumeClaimCreate,
+                    )))
+                })
+        })
+        .into_future()
+        .flatten()
+    }
+
     pub fn list_pods(
         &mut self,
         namespace: &str,
@@ -389,6 +591,49 @@ where
             .flatten()
     }
 
+    /// Streams the changes of the pods of a namespace after `resource_version`.
+    pub fn watch_pods(
+        &mut self,
+        namespace: &str,
+        label_selector: Option<&str>,
+        resource_version: Option<&str>,
+    ) -> impl Stream<Item = api_meta::WatchEvent<api_core::Pod>, Error = Error> {
+        let params = WatchOptional {
+            label_selector,
+            resource_version,
+            timeout_seconds: Some(WATCH_TIMEOUT_SECONDS),
+            ..WatchOptional::default()
+        };
+        api_core::Pod::watch_namespaced_pod(namespace, params)
+            .map_err(|err| Error::from(err.context(ErrorKind::Request(RequestType::PodWatch))))
+            .map(|req| self.watch(req, RequestType::PodWatch))
+            .into_future()
+            .flatten_stream()
+    }
+
+    /// Streams the log of a container of a pod, as the plain text Kubernetes serves it.
+    pub fn read_pod_log(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        params: api_core::ReadNamespacedPodLogOptional<'_>,
+    ) -> impl Future<Item = Body, Error = Error> {
+        api_core::Pod::read_namespaced_pod_log(name, namespace, params)
+            .map_err(|err| Error::from(err.context(ErrorKind::Request(RequestType::PodLogRead))))
+            .map(|(req, _)| {
+                self.execute(req).and_then(|response| {
+                    if response.status().is_success() {
+                        Ok(response.into_body())
+                    } else {
+                        debug!("HTTP Status: {}", response.status());
+                        Err(Error::from(ErrorKind::Response(RequestType::PodLogRead)))
+                    }
+                })
+            })
+            .into_future()
+            .flatten()
+    }
+
     pub fn list_nodes(&mut self) -> impl Future<Item = List<api_core::Node>, Error = Error> {
         api_core::Node::list_node(ListOptional::default())
             .map_err(|err| Error::from(err.context(ErrorKind::Request(RequestType::NodeList))))
@@ -406,6 +651,36 @@ where
             .flatten()
     }
 
+    /// Lists the usage of the nodes of the cluster. Fails if metrics-server is not deployed.
+    pub fn list_node_metrics(
+        &mut self,
+    ) -> impl Future<Item = MetricsList<NodeMetrics>, Error = Error> {
+        self.get_metrics(
+            "/apis/metrics.k8s.io/v1beta1/nodes".to_string(),
+            RequestType::NodeMetricsList,
+        )
+    }
+
+    /// Lists the usage of the pods of a namespace. Fails if metrics-server is not deployed.
+    pub fn list_pod_metrics(
+        &mut self,
+        namespace: &str,
+        label_selector: Option<&str>,
+    ) -> impl Future<Item = MetricsList<PodMetrics>, Error = Error> {
+        let mut path = format!(
+            "/apis/metrics.k8s.io/v1beta1/namespaces/{}/pods",
+            utf8_percent_encode(namespace, PATH_SEGMENT_ENCODE_SET)
+        );
+        if let Some(label_selector) = label_selector {
+            let query = form_urlencoded::Serializer::new(String::new())
+                .append_pair("labelSelector", label_selector)
+                .finish();
+            path.push('?');
+            path.push_str(&query);
+        }
+        self.get_metrics(path, RequestType::PodMetricsList)
+    }
+
     pub fn list_secrets(
         &mut self,
         namespace: &str,
@@ -550,6 +825,28 @@ where
             .flatten()
     }
 
+    /// Streams the changes of the service accounts of a namespace after `resource_version`.
+    pub fn watch_service_accounts(
+        &mut self,
+        namespace: &str,
+        label_selector: Option<&str>,
+        resource_version: Option<&str>,
+    ) -> impl Stream<Item = api_meta::WatchEvent<api_core::ServiceAccount>, Error = Error> {
+        let params = WatchOptional {
+            label_selector,
+            resource_version,
+            timeout_seconds: Some(WATCH_TIMEOUT_SECONDS),
+            ..WatchOptional::default()
+        };
+        api_core::ServiceAccount::watch_namespaced_service_account(namespace, params)
+            .map_err(|err| {
+                Error::from(err.context(ErrorKind::Request(RequestType::ServiceAccountWatch)))
+            })
+            .map(|req| self.watch(req, RequestType::ServiceAccountWatch))
+            .into_future()
+            .flatten_stream()
+    }
+
     pub fn create_service_account(
         &mut self,
         namespace: &str,
@@ -657,17 +954,20 @@ where
         .map_err(|err| {
             Error::from(err.context(ErrorKind::Request(RequestType::ServiceAccountDelete)))
         })
-        .map(|req| {
-            self.request(req, true)
-                .and_then(|response| match response {
-                    DeleteResponse::OkStatus(_) | DeleteResponse::OkValue(_) => Ok(()),
+        .map(|(req, _)| {
+            // a missing service account is told apart from other errors, for removal to go on
+            self.execute(req).and_then(|response| {
+                debug!("HTTP Status: {}", response.status());
+                match response.status() {
+                    status if status.is_success() => Ok(()),
+                    hyper::StatusCode::NOT_FOUND => Err(Error::from(ErrorKind::NotFound(
+                        RequestType::ServiceAccountDelete,
+                    ))),
                     _ => Err(Error::from(ErrorKind::Response(
                         RequestType::ServiceAccountDelete,
                     ))),
-                })
-                .map_err(|err| {
-                    Error::from(err.context(ErrorKind::Response(RequestType::ServiceAccountDelete)))
-                })
+                }
+            })
         })
         .into_future()
         .flatten()
@@ -771,6 +1071,97 @@ where
             .and_then(move |response| next(response, should_log_trace))
     }
 
+    fn get_metrics<R>(
+        &mut self,
+        path: String,
+        request_type: RequestType,
+    ) -> impl Future<Item = R, Error = Error>
+    where
+        R: DeserializeOwned,
+    {
+        let response_type = request_type.clone();
+        http::Request::get(path)
+            .body(Vec::new())
+            .map_err(|err| Error::from(err.context(ErrorKind::Request(request_type))))
+            .map(|req| {
+                self.execute(req).and_then(|response| {
+                    let status_code = response.status();
+                    response
+                        .into_body()
+                        .concat2()
+                        .map_err(|err| Error::from(err.context(ErrorKind::Hyper)))
+                        .and_then(move |body| {
+                            debug!("HTTP Status: {}", status_code);
+                            if status_code.is_success() {
+                                serde_json::from_slice(&body).map_err(|err| {
+                                    Error::from(err.context(ErrorKind::Response(response_type)))
+                                })
+                            } else {
+                                Err(Error::from(ErrorKind::Response(response_type)))
+                            }
+                        })
+                })
+            })
+            .into_future()
+            .flatten()
+    }
+
+    /// Executes a watch request and parses the events of the response body as they arrive.
+    fn watch<R>(
+        &mut self,
+        (req, response_body): (
+            http::Request<Vec<u8>>,
+            fn(http::StatusCode) -> ResponseBody<WatchResponse<R>>,
+        ),
+        request_type: RequestType,
+    ) -> impl Stream<Item = api_meta::WatchEvent<R>, Error = Error>
+    where
+        R: DeserializeOwned,
+    {
+        self.execute(req)
+            .and_then(move |response| {
+                debug!("HTTP Status: {}", response.status());
+                let status_code = http::StatusCode::from_u16(response.status().as_u16())
+                    .map_err(|err| Error::from(err.context(ErrorKind::KubeOpenApi)))?;
+                if !status_code.is_success() {
+                    return Err(Error::from(ErrorKind::Response(request_type)));
+                }
+
+                // Events are concatenated JSON objects which may be split across chunks.
+                let mut body = response_body(status_code);
+                let events = response
+                    .into_body()
+                    .map_err(|err| Error::from(err.context(ErrorKind::Hyper)))
+                    .map(move |chunk| {
+                        body.append_slice(chunk.as_ref());
+
+                        let mut events = Vec::new();
+                        loop {
+                            match body.parse() {
+                                Ok(WatchResponse::Ok(event)) => events.push(Ok(event)),
+                                Ok(WatchResponse::Other(_)) => {
+                                    events.push(Err(Error::from(ErrorKind::Response(
+                                        request_type.clone(),
+                                    ))));
+                                    break;
+                                }
+                                Err(ResponseError::NeedMoreData) => break,
+                                Err(err) => {
+                                    events.push(Err(Error::from(
+                                        err.context(ErrorKind::KubeOpenApi),
+                                    )));
+                                    break;
+                                }
+                            }
+                        }
+                        stream::iter_result(events)
+                    })
+                    .flatten();
+                Ok(events)
+            })
+            .flatten_stream()
+    }
+
     fn execute(
         &mut self,
         req: http::Request<Vec<u8>>,
@@ -849,11 +1240,12 @@ mod tests {
 
     use bytes::BytesMut;
     use failure::Fail;
-    use futures::{future, Future, Stream};
+    use futures::{future, stream, Future, Stream};
     use hyper::service::{service_fn, Service};
     use hyper::{Body, Error as HyperError, Request, Response, StatusCode};
     use k8s_openapi::api::apps::v1 as api_apps;
     use k8s_openapi::api::core::v1 as api_core;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::WatchEvent;
     use native_tls::TlsConnector;
     use serde_json;
     use tokio::runtime::Runtime;
@@ -1094,6 +1486,74 @@ mod tests {
         }
     }
 
+    #[test]
+    fn read_pod_log_success() {
+        const NAMESPACE: &str = "custom-namespace";
+        const NAME: &str = "edgehub-5c9d6fb7c4-wq7rx";
+        let service = service_fn(|req: Request<Body>| -> Result<Response<Body>, HyperError> {
+            let p = req.uri().path();
+            let q = req.uri().query().unwrap();
+            assert!(p.contains(NAMESPACE));
+            assert!(p.ends_with(&format!("/pods/{}/log", NAME)));
+            assert!(q.contains("container=edgehub"));
+            assert!(q.contains("follow=true"));
+            assert!(q.contains("tailLines=10"));
+            Ok(Response::new(Body::from(
+                "Roses are red\nviolets are blue\n",
+            )))
+        });
+
+        let mut client = make_test_client(service);
+
+        let params = api_core::ReadNamespacedPodLogOptional {
+            container: Some("edgehub"),
+            follow: Some(true),
+            tail_lines: Some(10),
+            ..api_core::ReadNamespacedPodLogOptional::default()
+        };
+        let fut = client
+            .read_pod_log(NAMESPACE, NAME, params)
+            .and_then(|logs| {
+                logs.concat2()
+                    .map_err(|err| super::Error::from(err.context(ErrorKind::Hyper)))
+            })
+            .map(|logs| {
+                assert_eq!(&b"Roses are red\nviolets are blue\n"[..], logs.as_ref());
+            });
+
+        Runtime::new()
+            .unwrap()
+            .block_on(fut)
+            .expect("Expected future to be OK");
+    }
+
+    #[test]
+    fn read_pod_log_error_response() {
+        let service = service_fn(
+            |_req: Request<Body>| -> Result<Response<Body>, HyperError> {
+                let res = Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::empty())
+                    .unwrap();
+                Ok(res)
+            },
+        );
+
+        let mut client = make_test_client(service);
+
+        let fut = client.read_pod_log(
+            "custom-namespace",
+            "edgehub-5c9d6fb7c4-wq7rx",
+            api_core::ReadNamespacedPodLogOptional::default(),
+        );
+
+        if let Err(err) = Runtime::new().unwrap().block_on(fut) {
+            assert_eq!(err.kind(), &ErrorKind::Response(RequestType::PodLogRead))
+        } else {
+            panic!("Expected and error result")
+        }
+    }
+
     const LIST_NODE_RESPONSE: &str = r###"{
             "kind" : "NodeList",
             "items" : [
@@ -1148,6 +1608,81 @@ mod tests {
         }
     }
 
+    const LIST_POD_METRICS_RESPONSE: &str = r###"{
+            "kind": "PodMetricsList",
+            "apiVersion": "metrics.k8s.io/v1beta1",
+            "items": [
+                {
+                    "metadata": { "name": "edgehub-5c9d6fb7c4-wq7rx", "namespace": "custom-namespace" },
+                    "timestamp": "2019-10-16T19:40:12Z",
+                    "window": "30s",
+                    "containers": [
+                        { "name": "edgehub", "usage": { "cpu": "2412035n", "memory": "48232Ki" } },
+                        { "name": "proxy", "usage": { "cpu": "0", "memory": "3564Ki" } }
+                    ]
+                }
+            ]
+        }"###;
+
+    #[test]
+    fn list_pod_metrics_success() {
+        let service = service_fn(|req: Request<Body>| -> Result<Response<Body>, HyperError> {
+            assert_eq!(
+                "/apis/metrics.k8s.io/v1beta1/namespaces/custom-namespace/pods",
+                req.uri().path()
+            );
+            assert_eq!(
+                Some("labelSelector=net.azure-devices.edge.deviceid%3Ddevice1"),
+                req.uri().query()
+            );
+            Ok(Response::new(Body::from(LIST_POD_METRICS_RESPONSE)))
+        });
+
+        let mut client = make_test_client(service);
+
+        let fut = client
+            .list_pod_metrics(
+                "custom-namespace",
+                Some("net.azure-devices.edge.deviceid=device1"),
+            )
+            .map(|metrics| {
+                assert_eq!(1, metrics.items.len());
+                let containers = &metrics.items[0].containers;
+                assert_eq!(2, containers.len());
+                assert_eq!("edgehub", containers[0].name);
+                assert_eq!("48232Ki", containers[0].usage["memory"].0);
+            });
+
+        Runtime::new()
+            .unwrap()
+            .block_on(fut)
+            .expect("Expected future to be OK");
+    }
+
+    #[test]
+    fn list_node_metrics_without_metrics_server() {
+        let service = service_fn(|req: Request<Body>| -> Result<Response<Body>, HyperError> {
+            assert_eq!("/apis/metrics.k8s.io/v1beta1/nodes", req.uri().path());
+            let res = Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from("404 page not found"))
+                .unwrap();
+            Ok(res)
+        });
+
+        let mut client = make_test_client(service);
+        let fut = client.list_node_metrics();
+
+        if let Err(err) = Runtime::new().unwrap().block_on(fut) {
+            assert_eq!(
+                err.kind(),
+                &ErrorKind::Response(RequestType::NodeMetricsList)
+            )
+        } else {
+            panic!("Expected and error result")
+        }
+    }
+
     #[test]
     fn replace_deployment_error_response() {
         const NAMESPACE: &str = "custom-namespace";
@@ -1324,6 +1859,169 @@ mod tests {
         }
     }
 
+    const LIST_SERVICE_RESPONSE: &str = r###"{
+            "kind": "ServiceList",
+            "apiVersion": "v1",
+            "items": [
+                {
+                    "metadata": { "name": "edgehub", "namespace": "custom-namespace" },
+                    "spec": {
+                        "type": "ClusterIP",
+                        "clusterIP": "10.0.12.34",
+                        "ports": [ { "name": "443-443-tcp", "port": 443, "protocol": "TCP", "targetPort": 443 } ]
+                    }
+                }
+            ]
+        }"###;
+
+    #[test]
+    fn list_services_with_name_success() {
+        const NAMESPACE: &str = "custom-namespace";
+        const FIELD_SELECTOR: &str = "metadata.name=edgehub";
+        let service = service_fn(|req: Request<Body>| -> Result<Response<Body>, HyperError> {
+            let p = req.uri().path();
+            let q = req.uri().query().unwrap();
+            assert_eq!(p, format!("/api/v1/namespaces/{}/services", NAMESPACE));
+            assert!(
+                q.contains(&utf8_percent_encode(FIELD_SELECTOR, USERINFO_ENCODE_SET).to_string())
+            );
+            Ok(Response::new(Body::from(LIST_SERVICE_RESPONSE)))
+        });
+        let mut client = make_test_client(service);
+
+        let fut = client
+            .list_services(NAMESPACE, Some("edgehub"), None)
+            .map(|services| {
+                assert_eq!(1, services.items.len());
+                let spec = services.items[0].spec.as_ref().unwrap();
+                assert_eq!(Some("10.0.12.34"), spec.cluster_ip.as_deref());
+            });
+
+        Runtime::new()
+            .unwrap()
+            .block_on(fut)
+            .expect("Expected future to be OK");
+    }
+
+    #[test]
+    fn delete_service_success() {
+        let service = service_fn(|req: Request<Body>| -> Result<Response<Body>, HyperError> {
+            assert_eq!(req.method(), &hyper::Method::DELETE);
+            assert_eq!(
+                req.uri().path(),
+                "/api/v1/namespaces/custom-namespace/services/edgehub"
+            );
+            Ok(Response::new(Body::from(
+                r#"{"kind": "Status", "apiVersion": "v1", "status": "Success"}"#,
+            )))
+        });
+        let mut client = make_test_client(service);
+
+        let fut = client.delete_service("custom-namespace", "edgehub");
+
+        Runtime::new()
+            .unwrap()
+            .block_on(fut)
+            .expect("Expected future to be OK");
+    }
+
+    #[test]
+    fn create_persistent_volume_claim_error_response() {
+        let service = service_fn(
+            move |_req: Request<Body>| -> Result<Response<Body>, HyperError> {
+                let mut res = Response::new(Body::empty());
+                *res.status_mut() = StatusCode::FORBIDDEN;
+                Ok(res)
+            },
+        );
+
+        let mut client = make_test_client(service);
+        let claim = api_core::PersistentVolumeClaim::default();
+        let fut = client.create_persistent_volume_claim("custom-namespace", &claim);
+
+        if let Err(err) = Runtime::new().unwrap().block_on(fut) {
+            assert_eq!(
+                err.kind(),
+                &ErrorKind::Response(RequestType::PersistentVolumeClaimCreate)
+            )
+        } else {
+            panic!("Expected and error result")
+        }
+    }
+
+    #[test]
+    fn watch_deployments_parses_events_split_across_chunks() {
+        let service = service_fn(|req: Request<Body>| -> Result<Response<Body>, HyperError> {
+            let q = req.uri().query().unwrap();
+            assert_eq!(
+                req.uri().path(),
+                "/apis/apps/v1/namespaces/custom-namespace/deployments"
+            );
+            assert!(q.contains("watch=true"));
+            assert!(q.contains("resourceVersion=42"));
+            assert!(q.contains("timeoutSeconds=300"));
+            // The second event is split across two chunks.
+            let chunks = vec![
+                r#"{"type":"ADDED","object":{"kind":"Deployment","apiVersion":"apps/v1","metadata":{"name":"edgehub"}}}"#,
+                "\n{\"type\":\"DELETED\",\"object\":{\"kind\":\"Deployment\",",
+                r#""apiVersion":"apps/v1","metadata":{"name":"edgeagent"}}}"#,
+            ];
+            Ok(Response::new(Body::wrap_stream(stream::iter_ok::<
+                _,
+                HyperError,
+            >(chunks))))
+        });
+        let mut client = make_test_client(service);
+
+        let fut = client
+            .watch_deployments("custom-namespace", None, Some("42"))
+            .collect()
+            .map(|events| {
+                assert_eq!(2, events.len());
+                match &events[0] {
+                    WatchEvent::Added(deployment) => assert_eq!(
+                        Some("edgehub"),
+                        deployment.metadata.as_ref().unwrap().name.as_deref()
+                    ),
+                    _ => panic!("Expected an added deployment"),
+                }
+                match &events[1] {
+                    WatchEvent::Deleted(deployment) => assert_eq!(
+                        Some("edgeagent"),
+                        deployment.metadata.as_ref().unwrap().name.as_deref()
+                    ),
+                    _ => panic!("Expected a deleted deployment"),
+                }
+            });
+
+        Runtime::new()
+            .unwrap()
+            .block_on(fut)
+            .expect("Expected future to be OK");
+    }
+
+    #[test]
+    fn watch_pods_error_response() {
+        let service = service_fn(
+            move |_req: Request<Body>| -> Result<Response<Body>, HyperError> {
+                let mut res = Response::new(Body::empty());
+                *res.status_mut() = StatusCode::GONE;
+                Ok(res)
+            },
+        );
+
+        let mut client = make_test_client(service);
+        let fut = client
+            .watch_pods("custom-namespace", None, Some("1"))
+            .collect();
+
+        if let Err(err) = Runtime::new().unwrap().block_on(fut) {
+            assert_eq!(err.kind(), &ErrorKind::Response(RequestType::PodWatch))
+        } else {
+            panic!("Expected and error result")
+        }
+    }
+
     const TOKEN_REVIEW_JSON: &str = r###"{"apiVersion":"authentication.k8s.io/v1","kind":"TokenReview","metadata":{"namespace":"NAMESPACE"},"spec":{"token":"BEARERTOKEN"}}"###;
 
     const TOKEN_REVIEW_AUTHENTICATED_RESPONSE_JSON: &str = r###"{
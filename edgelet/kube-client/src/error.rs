@@ -42,6 +42,9 @@ pub enum ErrorKind {
     #[fail(display = "HTTP response error: {}", _0)]
     Response(RequestType),
 
+    #[fail(display = "Kubernetes object not found: {}", _0)]
+    NotFound(RequestType),
+
     #[cfg(test)]
     #[fail(display = "HTTP test error")]
     HttpTest,
@@ -137,8 +140,19 @@ pub enum RequestType {
     DeploymentCreate,
     DeploymentReplace,
     DeploymentDelete,
+    DeploymentWatch,
     PodList,
+    PodLogRead,
+    PodWatch,
     NodeList,
+    NodeMetricsList,
+    PodMetricsList,
+    PersistentVolumeClaimList,
+    PersistentVolumeClaimCreate,
+    ServiceList,
+    ServiceCreate,
+    ServiceReplace,
+    ServiceDelete,
     SecretList,
     SecretCreate,
     SecretReplace,
@@ -149,6 +163,7 @@ pub enum RequestType {
     ServiceAccountReplace,
     ServiceAccountGet,
     ServiceAccountDelete,
+    ServiceAccountWatch,
     RoleBindingReplace,
     RoleBindingDelete,
 }
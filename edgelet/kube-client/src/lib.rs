@@ -17,7 +17,9 @@ pub mod client;
 pub mod config;
 pub mod error;
 pub mod kube;
+pub mod metrics;
 
 pub use self::client::{Client, HttpClient};
 pub use self::config::{get_config, Config, TokenSource, ValueToken};
 pub use self::error::{Error, ErrorKind, RequestType};
+pub use self::metrics::{ContainerMetrics, MetricsList, NodeMetrics, PodMetrics};
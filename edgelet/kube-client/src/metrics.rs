@@ -0,0 +1,40 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Resources of the `metrics.k8s.io` API served by metrics-server, which `k8s-openapi` does not model.
+
+use std::collections::BTreeMap;
+
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use serde_derive::Deserialize;
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct MetricsList<T> {
+    #[serde(default)]
+    pub items: Vec<T>,
+}
+
+/// Resource usage of a node, keyed by resource name such as `cpu` or `memory`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct NodeMetrics {
+    #[serde(default)]
+    pub metadata: ObjectMeta,
+    #[serde(default)]
+    pub usage: BTreeMap<String, Quantity>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct PodMetrics {
+    #[serde(default)]
+    pub metadata: ObjectMeta,
+    #[serde(default)]
+    pub containers: Vec<ContainerMetrics>,
+}
+
+/// Resource usage of a container of a pod, keyed by resource name such as `cpu` or `memory`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct ContainerMetrics {
+    pub name: String,
+    #[serde(default)]
+    pub usage: BTreeMap<String, Quantity>,
+}
@@ -45,11 +45,12 @@ pub trait ModuleApi: Send + Sync {
         &self,
         api_version: &str,
         name: &str,
-    ) -> Box<dyn Future<Item = crate::models::ModuleDetails, Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = crate::models::ModuleDetails, Error = Error<serde_json::Value>> + Send>;
     fn list_modules(
         &self,
         api_version: &str,
     ) -> Box<dyn Future<Item = crate::models::ModuleList, Error = Error<serde_json::Value>> + Send>;
+    #[allow(clippy::too_many_arguments)]
     fn module_logs(
         &self,
         api_version: &str,
@@ -57,6 +58,12 @@ pub trait ModuleApi: Send + Sync {
         follow: bool,
         tail: &str,
         since: i32,
+        until: Option<i32>,
+        timestamps: bool,
+        stdout: bool,
+        stderr: bool,
+        regex: Option<&str>,
+        severity: Option<&str>,
     ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send>;
     fn restart_module(
         &self,
@@ -209,7 +216,7 @@ where
         &self,
         api_version: &str,
         name: &str,
-    ) -> Box<dyn Future<Item = crate::models::ModuleDetails, Error = Error<serde_json::Value>>>
+    ) -> Box<dyn Future<Item = crate::models::ModuleDetails, Error = Error<serde_json::Value>> + Send>
     {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
@@ -320,6 +327,7 @@ where
         )
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn module_logs(
         &self,
         api_version: &str,
@@ -327,17 +335,36 @@ where
         follow: bool,
         tail: &str,
         since: i32,
+        until: Option<i32>,
+        timestamps: bool,
+        stdout: bool,
+        stderr: bool,
+        regex: Option<&str>,
+        severity: Option<&str>,
     ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::GET;
 
-        let query = ::url::form_urlencoded::Serializer::new(String::new())
+        let mut query = ::url::form_urlencoded::Serializer::new(String::new());
+        query
             .append_pair("api-version", &api_version.to_string())
             .append_pair("follow", &follow.to_string())
             .append_pair("tail", &tail.to_string())
             .append_pair("since", &since.to_string())
-            .finish();
+            .append_pair("timestamps", &timestamps.to_string())
+            .append_pair("stdout", &stdout.to_string())
+            .append_pair("stderr", &stderr.to_string());
+        if let Some(until) = until {
+            query.append_pair("until", &until.to_string());
+        }
+        if let Some(regex) = regex {
+            query.append_pair("regex", regex);
+        }
+        if let Some(severity) = severity {
+            query.append_pair("severity", severity);
+        }
+        let query = query.finish();
         let uri_str = format!(
             "/modules/{name}/logs?{}",
             query,
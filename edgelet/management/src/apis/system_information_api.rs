@@ -32,7 +32,13 @@ pub trait SystemInformationApi: Send + Sync {
     fn get_system_info(
         &self,
         api_version: &str,
-    ) -> Box<dyn Future<Item = crate::models::SystemInfo, Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = crate::models::SystemInfo, Error = Error<serde_json::Value>> + Send>;
+    fn get_system_resources(
+        &self,
+        api_version: &str,
+    ) -> Box<
+        dyn Future<Item = crate::models::SystemResources, Error = Error<serde_json::Value>> + Send,
+    >;
 }
 
 impl<C> SystemInformationApi for SystemInformationApiClient<C>
@@ -44,7 +50,8 @@ where
     fn get_system_info(
         &self,
         api_version: &str,
-    ) -> Box<dyn Future<Item = crate::models::SystemInfo, Error = Error<serde_json::Value>>> {
+    ) -> Box<dyn Future<Item = crate::models::SystemInfo, Error = Error<serde_json::Value>> + Send>
+    {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::GET;
@@ -94,4 +101,60 @@ where
                 }),
         )
     }
+
+    fn get_system_resources(
+        &self,
+        api_version: &str,
+    ) -> Box<
+        dyn Future<Item = crate::models::SystemResources, Error = Error<serde_json::Value>> + Send,
+    > {
+        let configuration: &configuration::Configuration<C> = self.configuration.borrow();
+
+        let method = hyper::Method::GET;
+
+        let query = ::url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("api-version", &api_version.to_string())
+            .finish();
+        let uri_str = format!("/systeminfo/resources?{}", query);
+
+        let uri = (configuration.uri_composer)(&configuration.base_path, &uri_str);
+        // TODO(farcaller): handle error
+        // if let Err(e) = uri {
+        //     return Box::new(futures::future::err(e));
+        // }
+        let mut req = hyper::Request::builder();
+        req.method(method).uri(uri.unwrap());
+        if let Some(ref user_agent) = configuration.user_agent {
+            req.header(http::header::USER_AGENT, &**user_agent);
+        }
+        let req = req
+            .body(hyper::Body::empty())
+            .expect("could not build hyper::Request");
+
+        // send request
+        Box::new(
+            configuration
+                .client
+                .request(req)
+                .map_err(Error::from)
+                .and_then(|resp| {
+                    let (http::response::Parts { status, .. }, body) = resp.into_parts();
+                    body.concat2()
+                        .and_then(move |body| Ok((status, body)))
+                        .map_err(Error::from)
+                })
+                .and_then(|(status, body)| {
+                    if status.is_success() {
+                        Ok(body)
+                    } else {
+                        Err(Error::from((status, &*body)))
+                    }
+                })
+                .and_then(|body| {
+                    let parsed: Result<crate::models::SystemResources, _> =
+                        serde_json::from_slice(&body);
+                    parsed.map_err(Error::from)
+                }),
+        )
+    }
 }
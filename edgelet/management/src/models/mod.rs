@@ -1,5 +1,7 @@
 mod config;
 pub use self::config::Config;
+mod disk;
+pub use self::disk::Disk;
 mod env_var;
 pub use self::env_var::EnvVar;
 mod error_response;
@@ -26,6 +28,8 @@ mod status;
 pub use self::status::Status;
 mod system_info;
 pub use self::system_info::SystemInfo;
+mod system_resources;
+pub use self::system_resources::SystemResources;
 
 // TODO(farcaller): sort out files
 pub struct File;
@@ -123,6 +123,19 @@ listen:
 homedir: {{ .Values.iotedged.data.targetPath | quote }}
 namespace: {{ .Release.Namespace | quote }}
 device_hub_selector: ""
+{{- if .Values.edgeAgent.env.portMappingServiceType }}
+port_mapping_service_type: {{ .Values.edgeAgent.env.portMappingServiceType | quote }}
+{{- end }}
+{{- if .Values.edgeAgent.env.storageClassName }}
+{{- if eq "-" .Values.edgeAgent.env.storageClassName }}
+storage_class_name: ""
+{{- else }}
+storage_class_name: {{ .Values.edgeAgent.env.storageClassName | quote }}
+{{- end }}
+{{- end }}
+{{- if .Values.edgeAgent.env.persistentVolumeClaimDefaultSizeInMb }}
+persistent_volume_claim_default_size_in_mb: {{ .Values.edgeAgent.env.persistentVolumeClaimDefaultSizeInMb }}
+{{- end }}
 proxy:
   image: "{{.Values.iotedgedProxy.image.repository}}:{{.Values.iotedgedProxy.image.tag}}"
   image_pull_policy: {{ .Values.iotedgedProxy.image.pullPolicy | quote }}
@@ -7,7 +7,7 @@ use criterion::*;
 use mqtt3::proto::{Publication, QoS};
 use mqtt_broker::{
     BrokerState, ClientId, ConsolidatedStateFormat, FileFormat, FilePersistor, Persist,
-    SessionState,
+    PersistError, ReceivedPublication, SessionState,
 };
 use tempfile::TempDir;
 use tokio::runtime::Runtime;
@@ -20,7 +20,7 @@ fn test_write<F>(
     num_retained: u32,
     format: F,
 ) where
-    F: FileFormat + Clone + Send + 'static,
+    F: FileFormat<Error = PersistError> + Clone + Send + 'static,
 {
     let name = format!(
         "{}: Write {} unique and {} shared messages for {} sessions with {} retained messages",
@@ -64,7 +64,7 @@ fn test_read<F>(
     num_retained: u32,
     format: F,
 ) where
-    F: FileFormat + Clone + Send + 'static,
+    F: FileFormat<Error = PersistError> + Clone + Send + 'static,
 {
     let name = format!(
         "{}: Read {} unique and {} shared messages for {} sessions with {} retained messages",
@@ -115,7 +115,7 @@ fn make_fake_state(
         )
     }));
 
-    let shared_messages: Vec<Publication> = (0..num_shared_messages)
+    let shared_messages: Vec<ReceivedPublication> = (0..num_shared_messages)
         .map(|_| make_fake_publish("Shared Topic".to_owned()))
         .collect();
 
@@ -137,13 +137,14 @@ fn make_fake_state(
     BrokerState::new(retained, sessions)
 }
 
-fn make_fake_publish(topic_name: String) -> Publication {
-    Publication {
+fn make_fake_publish(topic_name: String) -> ReceivedPublication {
+    ReceivedPublication::new(Publication {
         topic_name,
         retain: false,
         qos: QoS::AtLeastOnce,
         payload: make_random_payload(10),
-    }
+        properties: Default::default(),
+    })
 }
 
 fn make_random_payload(size: u32) -> Bytes {
@@ -0,0 +1,67 @@
+use criterion::*;
+use mqtt_broker::{ClientId, TopicFilter, TopicTrie};
+
+fn make_filters(num_clients: u32, num_subscriptions: u32) -> Vec<(ClientId, Vec<TopicFilter>)> {
+    (0..num_clients)
+        .map(|i| {
+            let filters = (0..num_subscriptions)
+                .map(|j| match j % 3 {
+                    0 => format!("devices/{}/messages/{}", i, j),
+                    1 => format!("devices/{}/+/{}", i, j),
+                    _ => format!("devices/{}/modules/{}/#", i, j),
+                })
+                .map(|filter| filter.parse().unwrap())
+                .collect();
+            (ClientId::from(format!("Session {}", i)), filters)
+        })
+        .collect()
+}
+
+fn test_linear(c: &mut Criterion, num_clients: u32, num_subscriptions: u32) {
+    let name = format!(
+        "Linear: Match topic against {} sessions with {} subscriptions",
+        num_clients, num_subscriptions
+    );
+
+    let sessions = make_filters(num_clients, num_subscriptions);
+    let topic_name = format!("devices/{}/messages/0", num_clients / 2);
+
+    c.bench_function(&name, |b| {
+        b.iter(|| {
+            sessions
+                .iter()
+                .filter(|(_, filters)| filters.iter().any(|f| f.matches(&topic_name)))
+                .map(|(client_id, _)| client_id.clone())
+                .collect::<Vec<_>>()
+        })
+    });
+}
+
+fn test_trie(c: &mut Criterion, num_clients: u32, num_subscriptions: u32) {
+    let name = format!(
+        "Trie: Match topic against {} sessions with {} subscriptions",
+        num_clients, num_subscriptions
+    );
+
+    let mut trie = TopicTrie::new();
+    for (client_id, filters) in make_filters(num_clients, num_subscriptions) {
+        for filter in filters {
+            trie.insert(&filter, client_id.clone());
+        }
+    }
+    let topic_name = format!("devices/{}/messages/0", num_clients / 2);
+
+    c.bench_function(&name, |b| b.iter(|| trie.matches(&topic_name)));
+}
+
+fn bench(c: &mut Criterion) {
+    let tests = vec![(1, 1), (10, 10), (100, 10), (500, 20)];
+
+    for (clients, subscriptions) in tests {
+        test_linear(c, clients, subscriptions);
+        test_trie(c, clients, subscriptions);
+    }
+}
+
+criterion_group!(basic, bench);
+criterion_main!(basic);
@@ -0,0 +1,374 @@
+use serde::{Deserialize, Serialize};
+
+#[cfg(unix)]
+pub use self::imp::{admin_request, serve_admin};
+
+/// A request to the admin endpoint.
+///
+/// Requests and responses are exchanged as a single line of JSON each.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum AdminRequest {
+    ListSessions,
+    ListRetained,
+    DeleteRetained { topic_name: String },
+    DisconnectSession { client_id: String },
+    PurgeSession { client_id: String },
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum AdminResponse {
+    Sessions { sessions: Vec<SessionInfo> },
+    Retained { retained: Vec<RetainedInfo> },
+    Done,
+    NotFound,
+    Error { message: String },
+}
+
+/// Describes a session held by the broker.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct SessionInfo {
+    client_id: String,
+    auth_id: Option<String>,
+    connected: bool,
+    persistent: bool,
+    subscriptions: Vec<String>,
+    queued_messages: usize,
+    inflight_messages: usize,
+}
+
+impl SessionInfo {
+    pub fn new(
+        client_id: String,
+        auth_id: Option<String>,
+        connected: bool,
+        persistent: bool,
+        subscriptions: Vec<String>,
+        queued_messages: usize,
+        inflight_messages: usize,
+    ) -> Self {
+        Self {
+            client_id,
+            auth_id,
+            connected,
+            persistent,
+            subscriptions,
+            queued_messages,
+            inflight_messages,
+        }
+    }
+
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    /// The identity the client authenticated with. Offline sessions have none.
+    pub fn auth_id(&self) -> Option<&str> {
+        self.auth_id.as_ref().map(String::as_str)
+    }
+
+    pub fn connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Returns true if the session outlives its connection.
+    pub fn persistent(&self) -> bool {
+        self.persistent
+    }
+
+    pub fn subscriptions(&self) -> &[String] {
+        &self.subscriptions
+    }
+
+    pub fn queued_messages(&self) -> usize {
+        self.queued_messages
+    }
+
+    pub fn inflight_messages(&self) -> usize {
+        self.inflight_messages
+    }
+}
+
+/// Describes a retained message.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RetainedInfo {
+    topic_name: String,
+    qos: u8,
+    payload_size: usize,
+}
+
+impl RetainedInfo {
+    pub fn new(topic_name: String, qos: u8, payload_size: usize) -> Self {
+        Self {
+            topic_name,
+            qos,
+            payload_size,
+        }
+    }
+
+    pub fn topic_name(&self) -> &str {
+        &self.topic_name
+    }
+
+    pub fn qos(&self) -> u8 {
+        self.qos
+    }
+
+    pub fn payload_size(&self) -> usize {
+        self.payload_size
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::fs::{self, DirBuilder};
+    use std::io;
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use std::path::Path;
+    use std::process;
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{UnixListener, UnixStream};
+    use tokio::sync::oneshot;
+    use tracing::{debug, info, warn};
+
+    use super::{AdminRequest, AdminResponse};
+    use crate::{BrokerHandle, ClientId, Error, InitializeBrokerError, Message, SystemEvent};
+
+    /// Largest request the admin endpoint reads before answering.
+    const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+    /// How long a client has to send its request and read the response.
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Serves admin requests on a unix socket.
+    ///
+    /// A stale socket file left behind by a previous run is removed. The socket
+    /// is only accessible to the user running the broker.
+    pub async fn serve_admin<P>(path: P, broker_handle: BrokerHandle) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if path.exists() {
+            fs::remove_file(path).map_err(InitializeBrokerError::BindServer)?;
+        }
+
+        let mut listener = bind_private(path).map_err(InitializeBrokerError::BindServer)?;
+        info!("Serving admin requests on {}", path.display());
+
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let broker_handle = broker_handle.clone();
+                    tokio::spawn(async move {
+                        match tokio::time::timeout(REQUEST_TIMEOUT, respond(stream, broker_handle))
+                            .await
+                        {
+                            Ok(Ok(())) => debug!("served admin request"),
+                            Ok(Err(e)) => {
+                                warn!(message = "failed to serve admin request", error=%e)
+                            }
+                            Err(_) => debug!("admin request timed out"),
+                        }
+                    });
+                }
+                Err(e) => warn!(message = "failed to accept admin connection", error=%e),
+            }
+        }
+    }
+
+    /// Binds a unix socket that only the user running the broker can connect to.
+    ///
+    /// The socket is bound in a directory only that user can enter, and is moved
+    /// to `path` once restricted, so that it is never reachable with the looser
+    /// permissions the umask gives it on bind.
+    pub(super) fn bind_private(path: &Path) -> io::Result<UnixListener> {
+        let name = path
+            .file_name()
+            .map_or_else(|| "admin".into(), |name| name.to_string_lossy());
+        let dir = path.with_file_name(format!(".{}.{}", name, process::id()));
+        DirBuilder::new().mode(0o700).create(&dir)?;
+
+        let bind = || {
+            let socket = dir.join("socket");
+            let listener = UnixListener::bind(&socket)?;
+            fs::set_permissions(&socket, fs::Permissions::from_mode(0o600))?;
+            fs::rename(&socket, path)?;
+            Ok(listener)
+        };
+        let listener = bind();
+
+        if let Err(e) = fs::remove_dir(&dir) {
+            warn!(message = "failed to remove admin socket directory", error=%e);
+        }
+        listener
+    }
+
+    /// Sends a request to the admin endpoint of a running broker.
+    pub async fn admin_request<P>(path: P, request: &AdminRequest) -> Result<AdminResponse, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut stream = UnixStream::connect(path).await.map_err(Error::Admin)?;
+
+        let mut line = serde_json::to_vec(request).map_err(|e| Error::Admin(e.into()))?;
+        line.push(b'\n');
+        stream.write_all(&line).await.map_err(Error::Admin)?;
+
+        let mut response = Vec::new();
+        stream
+            .read_to_end(&mut response)
+            .await
+            .map_err(Error::Admin)?;
+        serde_json::from_slice(&response).map_err(|e| Error::Admin(e.into()))
+    }
+
+    async fn respond(mut stream: UnixStream, mut broker_handle: BrokerHandle) -> io::Result<()> {
+        let mut request = Vec::new();
+        let mut buf = [0_u8; 1024];
+        while !request.contains(&b'\n') && request.len() < MAX_REQUEST_SIZE {
+            let read = stream.read(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+            request.extend_from_slice(&buf[..read]);
+        }
+
+        let response = match serde_json::from_slice(&request) {
+            Ok(request) => handle(request, &mut broker_handle)
+                .await
+                .unwrap_or_else(|e| AdminResponse::Error {
+                    message: e.to_string(),
+                }),
+            Err(e) => AdminResponse::Error {
+                message: format!("malformed request: {}", e),
+            },
+        };
+
+        let mut line = serde_json::to_vec(&response)?;
+        line.push(b'\n');
+        stream.write_all(&line).await?;
+        stream.shutdown(std::net::Shutdown::Write)
+    }
+
+    /// Asks the broker to carry out a request.
+    async fn handle(
+        request: AdminRequest,
+        broker_handle: &mut BrokerHandle,
+    ) -> Result<AdminResponse, Error> {
+        let done = |found| {
+            if found {
+                AdminResponse::Done
+            } else {
+                AdminResponse::NotFound
+            }
+        };
+
+        let response = match request {
+            AdminRequest::ListSessions => {
+                let mut sessions = ask(broker_handle, SystemEvent::ListSessions).await?;
+                sessions.sort_by(|a, b| a.client_id.cmp(&b.client_id));
+                AdminResponse::Sessions { sessions }
+            }
+            AdminRequest::ListRetained => {
+                let mut retained = ask(broker_handle, SystemEvent::ListRetained).await?;
+                retained.sort_by(|a, b| a.topic_name.cmp(&b.topic_name));
+                AdminResponse::Retained { retained }
+            }
+            AdminRequest::DeleteRetained { topic_name } => done(
+                ask(broker_handle, |reply| {
+                    SystemEvent::DeleteRetained(topic_name, reply)
+                })
+                .await?,
+            ),
+            AdminRequest::DisconnectSession { client_id } => {
+                let client_id = ClientId::from(client_id);
+                done(
+                    ask(broker_handle, |reply| {
+                        SystemEvent::DisconnectSession(client_id, reply)
+                    })
+                    .await?,
+                )
+            }
+            AdminRequest::PurgeSession { client_id } => {
+                let client_id = ClientId::from(client_id);
+                done(
+                    ask(broker_handle, |reply| {
+                        SystemEvent::PurgeSession(client_id, reply)
+                    })
+                    .await?,
+                )
+            }
+        };
+        Ok(response)
+    }
+
+    async fn ask<T, F>(broker_handle: &mut BrokerHandle, event: F) -> Result<T, Error>
+    where
+        F: FnOnce(oneshot::Sender<T>) -> SystemEvent,
+    {
+        let (reply, response) = oneshot::channel();
+        broker_handle.send(Message::System(event(reply))).await?;
+        response.await.map_err(|_| Error::AdminReply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn it_encodes_requests() {
+        let request = AdminRequest::PurgeSession {
+            client_id: "client".to_string(),
+        };
+
+        assert_eq!(
+            json!({ "command": "purge_session", "client_id": "client" }),
+            serde_json::to_value(&request).unwrap()
+        );
+        assert_eq!(
+            AdminRequest::ListSessions,
+            serde_json::from_value(json!({ "command": "list_sessions" })).unwrap()
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn it_binds_socket_only_owner_can_access() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("admin.sock");
+
+        let _listener = imp::bind_private(&path).unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(0o600, mode & 0o777);
+        assert_eq!(1, std::fs::read_dir(dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn it_encodes_responses() {
+        let response = AdminResponse::Retained {
+            retained: vec![RetainedInfo::new("topic".to_string(), 1, 7)],
+        };
+
+        assert_eq!(
+            json!({
+                "result": "retained",
+                "retained": [{ "topic_name": "topic", "qos": 1, "payload_size": 7 }]
+            }),
+            serde_json::to_value(&response).unwrap()
+        );
+        assert_eq!(
+            AdminResponse::NotFound,
+            serde_json::from_value(json!({ "result": "not_found" })).unwrap()
+        );
+    }
+}
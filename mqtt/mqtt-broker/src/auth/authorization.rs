@@ -64,6 +64,14 @@ impl Activity {
         }
     }
 
+    pub fn auth_id(&self) -> &AuthId {
+        &self.auth_id
+    }
+
+    pub fn client_id(&self) -> &ClientId {
+        &self.client_id
+    }
+
     pub fn operation(&self) -> &Operation {
         &self.operation
     }
@@ -122,6 +130,12 @@ pub struct Publication {
     retain: bool,
 }
 
+impl Publication {
+    pub fn topic_name(&self) -> &str {
+        &self.topic_name
+    }
+}
+
 impl From<proto::Publication> for Publication {
     fn from(publication: proto::Publication) -> Self {
         Self {
@@ -137,6 +151,12 @@ pub struct Publish {
     publication: Publication,
 }
 
+impl Publish {
+    pub fn publication(&self) -> &Publication {
+        &self.publication
+    }
+}
+
 impl From<proto::Publish> for Publish {
     fn from(publish: proto::Publish) -> Self {
         Self {
@@ -179,6 +199,12 @@ pub struct Receive {
     publication: Publication,
 }
 
+impl Receive {
+    pub fn publication(&self) -> &Publication {
+        &self.publication
+    }
+}
+
 impl From<proto::Publication> for Receive {
     fn from(publication: proto::Publication) -> Self {
         Self {
@@ -204,6 +230,7 @@ mod tests {
             keep_alive: Duration::from_secs(1),
             protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
             protocol_level: mqtt3::PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         }
     }
 
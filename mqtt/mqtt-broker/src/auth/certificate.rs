@@ -0,0 +1,189 @@
+use async_trait::async_trait;
+use openssl::{error::ErrorStack, nid::Nid, x509::X509};
+
+use crate::auth::{AuthId, Authenticator, Certificate, Credentials};
+use crate::configuration::CertificateIdentity;
+
+/// Authenticates MQTT clients by the X.509 certificate they presented on a
+/// TLS transport.
+///
+/// The certificate chain has already been validated against the CA bundle of
+/// the transport during the TLS handshake, so all that is left is to map the
+/// certificate to an identity: either its subject common name or its first
+/// DNS, email or URI subject alternative name.
+pub struct CertificateAuthenticator {
+    identity: CertificateIdentity,
+    allow_anonymous: bool,
+}
+
+impl CertificateAuthenticator {
+    /// Creates a new authenticator.
+    ///
+    /// If `allow_anonymous` is set, clients connecting without a certificate
+    /// are authenticated as anonymous instead of being refused.
+    pub fn new(identity: CertificateIdentity, allow_anonymous: bool) -> Self {
+        Self {
+            identity,
+            allow_anonymous,
+        }
+    }
+
+    fn identify(
+        &self,
+        certificate: &Certificate,
+    ) -> Result<Option<AuthId>, CertificateAuthenticateError> {
+        let certificate =
+            X509::from_der(certificate.as_ref()).map_err(CertificateAuthenticateError)?;
+        let identity = match self.identity {
+            CertificateIdentity::CommonName => {
+                common_name(&certificate).map_err(CertificateAuthenticateError)?
+            }
+            CertificateIdentity::SubjectAltName => subject_alt_name(&certificate),
+        };
+        Ok(identity.map(AuthId::from))
+    }
+}
+
+#[async_trait]
+impl Authenticator for CertificateAuthenticator {
+    type Error = CertificateAuthenticateError;
+
+    async fn authenticate(&self, credentials: Credentials) -> Result<Option<AuthId>, Self::Error> {
+        match credentials {
+            Credentials::ClientCertificate(certificate) => self.identify(&certificate),
+            Credentials::Basic(_, _) if self.allow_anonymous => Ok(Some(AuthId::Anonymous)),
+            Credentials::Basic(_, _) => Ok(None),
+        }
+    }
+}
+
+fn common_name(certificate: &X509) -> Result<Option<String>, ErrorStack> {
+    certificate
+        .subject_name()
+        .entries_by_nid(Nid::COMMONNAME)
+        .next()
+        .map(|entry| entry.data().as_utf8().map(|name| name.to_string()))
+        .transpose()
+}
+
+fn subject_alt_name(certificate: &X509) -> Option<String> {
+    certificate
+        .subject_alt_names()?
+        .iter()
+        .find_map(|name| {
+            name.dnsname()
+                .or_else(|| name.email())
+                .or_else(|| name.uri())
+        })
+        .map(str::to_string)
+}
+
+/// Client certificate authentication error.
+#[derive(Debug, thiserror::Error)]
+#[error("An error occurred reading client certificate.")]
+pub struct CertificateAuthenticateError(#[source] ErrorStack);
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+    use openssl::{
+        asn1::Asn1Time,
+        ec::{EcGroup, EcKey},
+        hash::MessageDigest,
+        pkey::PKey,
+        x509::{extension::SubjectAlternativeName, X509NameBuilder},
+    };
+
+    use super::*;
+
+    fn certificate(common_name: Option<&str>, dns_names: &[&str]) -> Certificate {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+
+        let mut name = X509NameBuilder::new().unwrap();
+        if let Some(common_name) = common_name {
+            name.append_entry_by_nid(Nid::COMMONNAME, common_name)
+                .unwrap();
+        }
+        let name = name.build();
+
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        if !dns_names.is_empty() {
+            let mut san = SubjectAlternativeName::new();
+            for dns_name in dns_names {
+                san.dns(dns_name);
+            }
+            let san = san.build(&builder.x509v3_context(None, None)).unwrap();
+            builder.append_extension(san).unwrap();
+        }
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+
+        builder.build().to_der().unwrap().into()
+    }
+
+    #[tokio::test]
+    async fn it_maps_common_name() {
+        let authenticator = CertificateAuthenticator::new(CertificateIdentity::CommonName, false);
+        let credentials =
+            Credentials::ClientCertificate(certificate(Some("device-1"), &["device-1.local"]));
+
+        let auth_id = authenticator.authenticate(credentials).await;
+
+        assert_matches!(auth_id, Ok(Some(AuthId::Identity(id))) if id == "device-1");
+    }
+
+    #[tokio::test]
+    async fn it_maps_subject_alt_name() {
+        let authenticator =
+            CertificateAuthenticator::new(CertificateIdentity::SubjectAltName, false);
+        let credentials =
+            Credentials::ClientCertificate(certificate(Some("device-1"), &["device-1.local"]));
+
+        let auth_id = authenticator.authenticate(credentials).await;
+
+        assert_matches!(auth_id, Ok(Some(AuthId::Identity(id))) if id == "device-1.local");
+    }
+
+    #[tokio::test]
+    async fn it_does_not_identify_certificate_without_name() {
+        let authenticator = CertificateAuthenticator::new(CertificateIdentity::CommonName, true);
+        let credentials = Credentials::ClientCertificate(certificate(None, &["device-1.local"]));
+
+        let auth_id = authenticator.authenticate(credentials).await;
+
+        assert_matches!(auth_id, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn it_fails_on_malformed_certificate() {
+        let authenticator = CertificateAuthenticator::new(CertificateIdentity::CommonName, true);
+        let credentials = Credentials::ClientCertificate(vec![1, 2, 3].into());
+
+        let auth_id = authenticator.authenticate(credentials).await;
+
+        assert_matches!(auth_id, Err(_));
+    }
+
+    #[tokio::test]
+    async fn it_authenticates_clients_without_certificate_as_anonymous() {
+        let credentials = || Credentials::Basic(Some("username".into()), None);
+
+        let authenticator = CertificateAuthenticator::new(CertificateIdentity::CommonName, true);
+        let auth_id = authenticator.authenticate(credentials()).await;
+        assert_matches!(auth_id, Ok(Some(AuthId::Anonymous)));
+
+        let authenticator = CertificateAuthenticator::new(CertificateIdentity::CommonName, false);
+        let auth_id = authenticator.authenticate(credentials()).await;
+        assert_matches!(auth_id, Ok(None));
+    }
+}
@@ -1,10 +1,14 @@
 mod authentication;
 mod authorization;
+mod certificate;
+mod policy;
 
 pub use authentication::{
     AuthenticateError, Authenticator, Certificate, Credentials, DefaultAuthenticator,
 };
 pub use authorization::{Activity, AuthorizeError, Authorizer, DefaultAuthorizer, Operation};
+pub use certificate::{CertificateAuthenticateError, CertificateAuthenticator};
+pub use policy::{Policy, PolicyAuthorizer, PolicyError};
 
 /// Authenticated MQTT client identity.
 #[derive(Clone, Debug, PartialEq)]
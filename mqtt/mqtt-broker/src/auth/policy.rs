@@ -0,0 +1,614 @@
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use config::{Config, ConfigError, File};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::auth::{Activity, AuthId, AuthorizeError, Authorizer, Operation};
+use crate::subscription::{is_topic_level, TopicFilter};
+
+const CLIENT_ID_VARIABLE: &str = "{{clientid}}";
+const IDENTITY_VARIABLE: &str = "{{identity}}";
+const WILDCARD: char = '*';
+const ANY_IDENTITY: &str = "*";
+
+/// A declarative access control list.
+///
+/// A policy is a list of statements. An activity is allowed if at least one
+/// statement allowing it matches and no statement denying it matches.
+/// Anything not explicitly allowed is denied.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Policy {
+    statements: Vec<Statement>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Effect {
+    Allow,
+    Deny,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum OperationKind {
+    Connect,
+    Publish,
+    Subscribe,
+    Receive,
+}
+
+/// A single rule of a policy.
+///
+/// Every non-empty list must have an entry matching the activity for the
+/// statement to apply. Identity and client id patterns may contain `*`
+/// wildcards, anonymous clients are only matched by `*`. Topic patterns are
+/// topic filters and may refer to the client id and identity of the client
+/// with `{{clientid}}` and `{{identity}}`. A topic pattern which cannot be
+/// checked for a client, like one referring to a client id containing `/`,
+/// matches in deny statements and does not match in allow statements.
+#[derive(Clone, Debug, Deserialize)]
+struct Statement {
+    effect: Effect,
+    #[serde(default)]
+    identities: Vec<String>,
+    #[serde(default)]
+    clients: Vec<String>,
+    operations: Vec<OperationKind>,
+    #[serde(default)]
+    topics: Vec<String>,
+}
+
+impl Policy {
+    /// Loads a policy from a JSON or YAML file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, PolicyError> {
+        let path = path.as_ref();
+        let mut s = Config::new();
+        s.merge(File::from(path))
+            .map_err(|e| PolicyError::Load(path.to_path_buf(), e))?;
+        let policy: Self = s
+            .try_into()
+            .map_err(|e| PolicyError::Load(path.to_path_buf(), e))?;
+
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    /// A policy which allows every activity.
+    pub fn allow_all() -> Self {
+        Self {
+            statements: vec![Statement {
+                effect: Effect::Allow,
+                identities: vec![],
+                clients: vec![],
+                operations: vec![
+                    OperationKind::Connect,
+                    OperationKind::Publish,
+                    OperationKind::Subscribe,
+                    OperationKind::Receive,
+                ],
+                topics: vec![],
+            }],
+        }
+    }
+
+    /// Returns whether the policy allows an activity.
+    pub fn evaluate(&self, activity: &Activity) -> bool {
+        let mut allowed = false;
+        for statement in self.statements.iter().filter(|s| s.matches(activity)) {
+            match statement.effect {
+                Effect::Deny => return false,
+                Effect::Allow => allowed = true,
+            }
+        }
+        allowed
+    }
+
+    fn validate(&self) -> Result<(), PolicyError> {
+        for topic in self.statements.iter().flat_map(|s| &s.topics) {
+            topic
+                .replace(CLIENT_ID_VARIABLE, "x")
+                .replace(IDENTITY_VARIABLE, "x")
+                .parse::<TopicFilter>()
+                .map_err(|_| PolicyError::InvalidTopicFilter(topic.clone()))?;
+        }
+        Ok(())
+    }
+}
+
+impl Statement {
+    fn matches(&self, activity: &Activity) -> bool {
+        let (kind, topic) = match activity.operation() {
+            Operation::Connect(_) => (OperationKind::Connect, None),
+            Operation::Publish(publish) => (
+                OperationKind::Publish,
+                Some(publish.publication().topic_name()),
+            ),
+            Operation::Subscribe(subscribe) => {
+                (OperationKind::Subscribe, Some(subscribe.topic_filter()))
+            }
+            Operation::Receive(receive) => (
+                OperationKind::Receive,
+                Some(receive.publication().topic_name()),
+            ),
+        };
+
+        self.operations.contains(&kind)
+            && any_or_empty(&self.identities, |pattern| {
+                identity_matches(pattern, activity.auth_id())
+            })
+            && any_or_empty(&self.clients, |pattern| {
+                wildcard_matches(pattern, activity.client_id().as_str())
+            })
+            && topic.map_or(true, |topic| self.topic_matches(topic, activity))
+    }
+
+    fn topic_matches(&self, topic: &str, activity: &Activity) -> bool {
+        // Fail closed, so that an unusual client id cannot get around a deny statement.
+        let unchecked = self.effect == Effect::Deny;
+
+        // Topic names are valid topic filters, so both can be checked the same way.
+        let topic = match topic.parse::<TopicFilter>() {
+            Ok(topic) => topic,
+            Err(_) => return unchecked,
+        };
+
+        any_or_empty(&self.topics, |pattern| {
+            substitute(pattern, activity)
+                .and_then(|filter| filter.parse::<TopicFilter>().ok())
+                .map_or(unchecked, |filter| filter.covers(&topic))
+        })
+    }
+}
+
+fn any_or_empty<F>(patterns: &[String], mut f: F) -> bool
+where
+    F: FnMut(&str) -> bool,
+{
+    patterns.is_empty() || patterns.iter().any(|pattern| f(pattern))
+}
+
+fn identity_matches(pattern: &str, auth_id: &AuthId) -> bool {
+    match auth_id {
+        AuthId::Anonymous => pattern == ANY_IDENTITY,
+        AuthId::Identity(identity) => wildcard_matches(pattern, identity),
+    }
+}
+
+/// Matches `value` against a pattern where `*` stands for any sequence of
+/// characters.
+fn wildcard_matches(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split(WILDCARD);
+    let first = parts.next().unwrap_or_default();
+    if !value.starts_with(first) {
+        return false;
+    }
+
+    let mut rest = &value[first.len()..];
+    let parts: Vec<_> = parts.collect();
+    match parts.split_last() {
+        None => rest.is_empty(),
+        Some((last, middle)) => {
+            for part in middle {
+                match rest.find(part) {
+                    Some(index) => rest = &rest[index + part.len()..],
+                    None => return false,
+                }
+            }
+            rest.ends_with(last)
+        }
+    }
+}
+
+/// Replaces the variables in a topic pattern with the values of the client
+/// performing an activity.
+///
+/// Returns `None` if a variable cannot be replaced by a single topic level,
+/// so that a client id like `#` does not widen the pattern.
+fn substitute(pattern: &str, activity: &Activity) -> Option<String> {
+    let mut filter = pattern.to_string();
+    if filter.contains(CLIENT_ID_VARIABLE) {
+        let client_id = activity.client_id().as_str();
+        if !is_topic_level(client_id) {
+            return None;
+        }
+        filter = filter.replace(CLIENT_ID_VARIABLE, client_id);
+    }
+
+    if filter.contains(IDENTITY_VARIABLE) {
+        match activity.auth_id() {
+            AuthId::Identity(identity) if is_topic_level(identity) => {
+                filter = filter.replace(IDENTITY_VARIABLE, identity);
+            }
+            _ => return None,
+        }
+    }
+
+    Some(filter)
+}
+
+/// Authorizes client activities with a `Policy`.
+///
+/// The policy can be replaced while the broker is running, either directly
+/// with `update` or by watching the policy file with `reload_on_change`.
+#[derive(Clone)]
+pub struct PolicyAuthorizer {
+    policy: Arc<RwLock<Policy>>,
+}
+
+impl PolicyAuthorizer {
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy: Arc::new(RwLock::new(policy)),
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, PolicyError> {
+        Ok(Self::new(Policy::from_file(path)?))
+    }
+
+    /// Replaces the current policy.
+    pub fn update(&self, policy: Policy) {
+        let mut current = self.policy.write().unwrap_or_else(PoisonError::into_inner);
+        *current = policy;
+    }
+
+    /// Loads the policy from a file and replaces the current one.
+    ///
+    /// The current policy is kept if the file cannot be loaded.
+    pub fn reload<P: AsRef<Path>>(&self, path: P) -> Result<(), PolicyError> {
+        let policy = Policy::from_file(path)?;
+        self.update(policy);
+        Ok(())
+    }
+
+    /// Checks the policy file every `period` and reloads it when it has been
+    /// modified.
+    pub fn reload_on_change<P>(&self, path: P, period: Duration) -> impl Future<Output = ()>
+    where
+        P: Into<PathBuf>,
+    {
+        let authorizer = self.clone();
+        let path = path.into();
+        async move {
+            info!(
+                "Watching authorization policy {} every {:?}",
+                path.display(),
+                period
+            );
+            let mut last_modified = modified(&path);
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                let current = modified(&path);
+                if current == last_modified {
+                    continue;
+                }
+                last_modified = current;
+
+                info!("reloading authorization policy from {}", path.display());
+                if let Err(e) = authorizer.reload(&path) {
+                    warn!(message = "failed to reload authorization policy", error=%e);
+                }
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    path.metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[async_trait]
+impl Authorizer for PolicyAuthorizer {
+    type Error = AuthorizeError;
+
+    async fn authorize(&self, activity: Activity) -> Result<bool, Self::Error> {
+        let policy = self.policy.read().unwrap_or_else(PoisonError::into_inner);
+        Ok(policy.evaluate(&activity))
+    }
+}
+
+/// Represents errors occurred while loading a policy.
+#[derive(Debug, thiserror::Error)]
+pub enum PolicyError {
+    #[error("An error occurred loading policy from file {0}.")]
+    Load(PathBuf, #[source] ConfigError),
+
+    #[error("Policy topic is not a valid topic filter: {0}")]
+    InvalidTopicFilter(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use bytes::Bytes;
+    use matches::assert_matches;
+    use mqtt3::proto;
+    use serde_json::json;
+
+    use super::*;
+
+    fn policy(statements: serde_json::Value) -> Policy {
+        serde_json::from_value(json!({ "statements": statements })).unwrap()
+    }
+
+    fn connect() -> Operation {
+        Operation::new_connect(proto::Connect {
+            username: None,
+            password: None,
+            will: None,
+            client_id: proto::ClientId::ServerGenerated,
+            keep_alive: Duration::from_secs(1),
+            protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
+            protocol_level: mqtt3::PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
+        })
+    }
+
+    fn publish(topic_name: &str) -> Operation {
+        Operation::new_publish(proto::Publish {
+            packet_identifier_dup_qos: proto::PacketIdentifierDupQoS::AtMostOnce,
+            retain: false,
+            topic_name: topic_name.to_string(),
+            payload: Bytes::new(),
+            properties: proto::Properties::default(),
+        })
+    }
+
+    fn subscribe(topic_filter: &str) -> Operation {
+        Operation::new_subscribe(proto::SubscribeTo {
+            topic_filter: topic_filter.to_string(),
+            qos: proto::QoS::AtMostOnce,
+            options: proto::SubscriptionOptions::default(),
+        })
+    }
+
+    fn receive(topic_name: &str) -> Operation {
+        Operation::new_receive(proto::Publication {
+            topic_name: topic_name.to_string(),
+            qos: proto::QoS::AtMostOnce,
+            retain: false,
+            payload: Bytes::new(),
+            properties: proto::Properties::default(),
+        })
+    }
+
+    #[test]
+    fn it_denies_by_default() {
+        let policy = policy(json!([]));
+
+        assert!(!policy.evaluate(&Activity::new("device-1", "client-1", connect())));
+    }
+
+    #[test]
+    fn it_allows_matching_statements() {
+        let policy = policy(json!([
+            { "effect": "allow", "identities": ["device-*"], "operations": ["connect", "publish"], "topics": ["telemetry/#"] }
+        ]));
+
+        assert!(policy.evaluate(&Activity::new("device-1", "client-1", connect())));
+        assert!(policy.evaluate(&Activity::new(
+            "device-1",
+            "client-1",
+            publish("telemetry/temp")
+        )));
+        assert!(!policy.evaluate(&Activity::new(
+            "device-1",
+            "client-1",
+            publish("commands/reboot")
+        )));
+        assert!(!policy.evaluate(&Activity::new(
+            "device-1",
+            "client-1",
+            subscribe("telemetry/#")
+        )));
+        assert!(!policy.evaluate(&Activity::new("module-1", "client-1", connect())));
+    }
+
+    #[test]
+    fn it_lets_deny_override_allow() {
+        let policy = policy(json!([
+            { "effect": "allow", "operations": ["publish"], "topics": ["#"] },
+            { "effect": "deny", "clients": ["untrusted-*"], "operations": ["publish"], "topics": ["commands/#"] }
+        ]));
+
+        assert!(policy.evaluate(&Activity::new(
+            "device-1",
+            "trusted-1",
+            publish("commands/reboot")
+        )));
+        assert!(!policy.evaluate(&Activity::new(
+            "device-1",
+            "untrusted-1",
+            publish("commands/reboot")
+        )));
+        assert!(policy.evaluate(&Activity::new(
+            "device-1",
+            "untrusted-1",
+            publish("telemetry/temp")
+        )));
+    }
+
+    #[test]
+    fn it_substitutes_variables() {
+        let policy = policy(json!([
+            { "effect": "allow", "operations": ["publish"], "topics": ["devices/{{clientid}}/#"] },
+            { "effect": "allow", "operations": ["receive"], "topics": ["identities/{{identity}}/+"] }
+        ]));
+
+        assert!(policy.evaluate(&Activity::new(
+            "device-1",
+            "client-1",
+            publish("devices/client-1/messages")
+        )));
+        assert!(!policy.evaluate(&Activity::new(
+            "device-1",
+            "client-1",
+            publish("devices/client-2/messages")
+        )));
+        assert!(policy.evaluate(&Activity::new(
+            "device-1",
+            "client-1",
+            receive("identities/device-1/twin")
+        )));
+        assert!(!policy.evaluate(&Activity::new(
+            AuthId::Anonymous,
+            "client-1",
+            receive("identities/device-1/twin")
+        )));
+    }
+
+    #[test]
+    fn it_does_not_widen_topics_by_variables() {
+        let policy = policy(json!([
+            { "effect": "allow", "operations": ["subscribe"], "topics": ["devices/{{clientid}}"] }
+        ]));
+
+        assert!(!policy.evaluate(&Activity::new("device-1", "#", subscribe("devices/#"))));
+        assert!(!policy.evaluate(&Activity::new("device-1", "a/b", subscribe("devices/a/b"))));
+    }
+
+    #[test]
+    fn it_denies_when_variables_cannot_be_substituted() {
+        let policy = policy(json!([
+            { "effect": "allow", "operations": ["publish"], "topics": ["#"] },
+            { "effect": "deny", "operations": ["publish"], "topics": ["devices/{{clientid}}/commands"] }
+        ]));
+
+        assert!(policy.evaluate(&Activity::new(
+            "device-1",
+            "client-1",
+            publish("devices/client-2/commands")
+        )));
+        assert!(!policy.evaluate(&Activity::new(
+            "device-1",
+            "client-1",
+            publish("devices/client-1/commands")
+        )));
+        assert!(!policy.evaluate(&Activity::new(
+            "device-1",
+            "a/b",
+            publish("devices/a/b/commands")
+        )));
+        assert!(!policy.evaluate(&Activity::new("device-1", "a/b", publish("telemetry/temp"))));
+    }
+
+    #[test]
+    fn it_checks_subscriptions_are_covered() {
+        let policy = policy(json!([
+            { "effect": "allow", "operations": ["subscribe"], "topics": ["telemetry/+/temp"] }
+        ]));
+
+        assert!(policy.evaluate(&Activity::new(
+            "device-1",
+            "client-1",
+            subscribe("telemetry/+/temp")
+        )));
+        assert!(policy.evaluate(&Activity::new(
+            "device-1",
+            "client-1",
+            subscribe("$share/group/telemetry/device-1/temp")
+        )));
+        assert!(!policy.evaluate(&Activity::new(
+            "device-1",
+            "client-1",
+            subscribe("telemetry/#")
+        )));
+    }
+
+    #[test]
+    fn it_matches_anonymous_clients_by_wildcard_only() {
+        let policy = policy(json!([
+            { "effect": "allow", "identities": ["*"], "operations": ["connect"] },
+            { "effect": "deny", "identities": ["anon*"], "operations": ["connect"] }
+        ]));
+
+        assert!(policy.evaluate(&Activity::new(AuthId::Anonymous, "client-1", connect())));
+        assert!(!policy.evaluate(&Activity::new("anonymous", "client-1", connect())));
+    }
+
+    #[test]
+    fn it_matches_wildcards() {
+        assert!(wildcard_matches("device-1", "device-1"));
+        assert!(!wildcard_matches("device-1", "device-10"));
+        assert!(wildcard_matches("device-*", "device-10"));
+        assert!(wildcard_matches("*-1", "device-1"));
+        assert!(wildcard_matches("d*v*-1", "device-1"));
+        assert!(!wildcard_matches("d*x*-1", "device-1"));
+        assert!(wildcard_matches("*", ""));
+    }
+
+    #[tokio::test]
+    async fn it_authorizes_activities() {
+        let authorizer = PolicyAuthorizer::new(policy(json!([
+            { "effect": "allow", "operations": ["connect"] }
+        ])));
+
+        let res = authorizer
+            .authorize(Activity::new("device-1", "client-1", connect()))
+            .await;
+        assert_matches!(res, Ok(true));
+
+        authorizer.update(policy(json!([])));
+        let res = authorizer
+            .authorize(Activity::new("device-1", "client-1", connect()))
+            .await;
+        assert_matches!(res, Ok(false));
+    }
+
+    #[test]
+    fn it_loads_policy_files() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let json_path = dir.path().join("policy.json");
+        let mut file = std::fs::File::create(&json_path).unwrap();
+        write!(
+            file,
+            r#"{{ "statements": [{{ "effect": "allow", "operations": ["connect"] }}] }}"#
+        )
+        .unwrap();
+
+        let yaml_path = dir.path().join("policy.yaml");
+        let mut file = std::fs::File::create(&yaml_path).unwrap();
+        write!(
+            file,
+            "statements:\n  - effect: deny\n    operations: [connect]\n"
+        )
+        .unwrap();
+
+        let authorizer = PolicyAuthorizer::from_file(&json_path).unwrap();
+        let activity = || Activity::new("device-1", "client-1", connect());
+        assert!(authorizer.policy.read().unwrap().evaluate(&activity()));
+
+        authorizer.reload(&yaml_path).unwrap();
+        assert!(!authorizer.policy.read().unwrap().evaluate(&activity()));
+
+        let result = authorizer.reload(dir.path().join("missing.json"));
+        assert_matches!(result, Err(PolicyError::Load(_, _)));
+        assert!(!authorizer.policy.read().unwrap().evaluate(&activity()));
+    }
+
+    #[test]
+    fn it_refuses_invalid_topics() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("policy.json");
+        let mut file = std::fs::File::create(&path).unwrap();
+        write!(
+            file,
+            r#"{{ "statements": [{{ "effect": "allow", "operations": ["publish"], "topics": ["a/#/b"] }}] }}"#
+        )
+        .unwrap();
+
+        let result = Policy::from_file(&path);
+
+        assert_matches!(result, Err(PolicyError::InvalidTopicFilter(topic)) if topic == "a/#/b");
+    }
+}
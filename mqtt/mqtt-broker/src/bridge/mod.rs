@@ -0,0 +1,608 @@
+mod queue;
+mod rules;
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::future::{self, Either};
+use futures_util::stream::{self, StreamExt};
+use mqtt3::{proto, Client, Event as ClientEvent, PublishError, ReceivedPublication};
+use openssl::ssl::{SslConnector, SslMethod};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::configuration::{Bridge as BridgeConfig, BridgeTls};
+use crate::persist::{Persist, PersistError};
+use crate::transport::{self, StreamSelector};
+use crate::{ClientId, Error, InitializeBrokerError};
+
+use self::queue::OutboundQueue;
+use self::rules::TopicMapper;
+
+/// How often the outbound queue is persisted if it has changed.
+const PERSIST_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How many forwarded messages are remembered to recognize them when the
+/// other side delivers them back to the bridge.
+const MAX_ECHOES: usize = 1000;
+
+type PublishFuture = Pin<Box<dyn Future<Output = Result<(), PublishError>> + Send>>;
+
+enum Event {
+    Upstream(ClientEvent),
+    Local(ClientEvent),
+    Tick,
+    Shutdown,
+}
+
+#[derive(Debug)]
+pub struct ShutdownHandle(Sender<Event>);
+
+impl ShutdownHandle {
+    pub async fn shutdown(&mut self) -> Result<(), Error> {
+        self.0
+            .send(Event::Shutdown)
+            .await
+            .map_err(|_| Error::SendBridgeMessage)?;
+        Ok(())
+    }
+}
+
+/// Forwards messages between the local broker and an upstream broker.
+///
+/// The bridge holds two client connections: one to the local broker and one
+/// to the upstream broker. Both reconnect with back-off when the connection
+/// is lost. Messages going upstream are kept in a queue until the upstream
+/// broker acknowledges them, and the queue is persisted with `P` so that it
+/// survives a restart. The connection to the upstream broker uses TLS if the
+/// bridge is configured with TLS settings.
+pub struct Bridge<P> {
+    config: BridgeConfig,
+    mapper: TopicMapper,
+    connector: Option<SslConnector>,
+    persistor: P,
+    sender: Sender<Event>,
+    events: Receiver<Event>,
+}
+
+impl<P> Bridge<P>
+where
+    P: Persist<Error = PersistError>,
+{
+    pub fn new(config: BridgeConfig, persistor: P) -> Result<Self, Error> {
+        let mapper = TopicMapper::new(config.topics())?;
+        let connector = config.tls().map(tls_connector).transpose()?;
+        let (sender, events) = mpsc::channel(1024);
+        Ok(Self {
+            config,
+            mapper,
+            connector,
+            persistor,
+            sender,
+            events,
+        })
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle(self.sender.clone())
+    }
+
+    pub async fn run(self) -> Result<P, Error> {
+        let Bridge {
+            config,
+            mapper,
+            connector,
+            mut persistor,
+            sender,
+            events,
+        } = self;
+
+        info!("starting bridge {} to {}", config.name(), config.address());
+
+        let client_id = ClientId::from(config.name());
+        let mut queue = match persistor.load().await? {
+            Some(state) => {
+                OutboundQueue::from_state(client_id, config.max_queued_messages(), state)
+            }
+            None => OutboundQueue::new(client_id, config.max_queued_messages()),
+        };
+        info!("loaded {} messages waiting to be forwarded", queue.len());
+
+        let mut upstream = client(
+            config.client_id(),
+            config.address(),
+            config.username(),
+            config.password(),
+            connector,
+            &config,
+        );
+        let mut local = client(
+            &format!("{}-bridge", config.name()),
+            config.local_address(),
+            None,
+            None,
+            None,
+            &config,
+        );
+
+        for subscribe_to in mapper.remote_subscriptions() {
+            upstream
+                .subscribe(subscribe_to)
+                .map_err(Error::BridgeSubscribe)?;
+        }
+        for subscribe_to in mapper.local_subscriptions() {
+            local
+                .subscribe(subscribe_to)
+                .map_err(Error::BridgeSubscribe)?;
+        }
+
+        let upstream_publish = upstream.publish_handle().map_err(Error::BridgePublish)?;
+        let local_publish = local.publish_handle().map_err(Error::BridgePublish)?;
+        let mut shutdown_handles = Vec::new();
+        shutdown_handles.extend(upstream.shutdown_handle().ok());
+        shutdown_handles.extend(local.shutdown_handle().ok());
+
+        let pumps = vec![
+            pump("upstream", upstream, sender.clone(), Event::Upstream),
+            pump("local", local, sender, Event::Local),
+        ];
+
+        let ticks = tokio::time::interval(PERSIST_INTERVAL).map(|_| Event::Tick);
+        let mut events = stream::select(events, ticks);
+
+        let mut remote_echoes = Echoes::default();
+        let mut local_echoes = Echoes::default();
+        let mut inflight: Option<PublishFuture> = None;
+        let mut dirty = false;
+
+        loop {
+            if inflight.is_none() {
+                if let Some(publication) = queue.front() {
+                    if mapper.echoes_remote(&publication.topic_name) {
+                        remote_echoes.insert(&publication.topic_name, &publication.payload);
+                    }
+
+                    let mut handle = upstream_publish.clone();
+                    let publication = publication.clone();
+                    inflight = Some(Box::pin(async move { handle.publish(publication).await }));
+                }
+            }
+
+            let next = match inflight.as_mut() {
+                Some(publish) => match future::select(publish, events.next()).await {
+                    Either::Left((result, _)) => Either::Left(result),
+                    Either::Right((event, _)) => Either::Right(event),
+                },
+                None => Either::Right(events.next().await),
+            };
+
+            let event = match next {
+                Either::Left(result) => {
+                    inflight = None;
+                    match result {
+                        Ok(()) => (),
+                        Err(PublishError::EncodePacket(publication, e)) => {
+                            warn!(
+                                message = "dropping message that cannot be forwarded",
+                                topic = %publication.topic_name,
+                                error=%e
+                            );
+                        }
+                        Err(e) => return Err(Error::BridgePublish(e)),
+                    }
+                    queue.pop_front();
+                    dirty = true;
+                    continue;
+                }
+                Either::Right(event) => event,
+            };
+
+            match event {
+                Some(Event::Upstream(ClientEvent::Publication(publication))) => {
+                    if remote_echoes.remove(&publication.topic_name, &publication.payload) {
+                        continue;
+                    }
+
+                    if let Some((topic_name, qos)) = mapper.to_local(&publication.topic_name) {
+                        debug!(
+                            "forwarding message from {} to {}",
+                            publication.topic_name, topic_name
+                        );
+                        if mapper.echoes_local(&topic_name) {
+                            local_echoes.insert(&topic_name, &publication.payload);
+                        }
+
+                        let mut handle = local_publish.clone();
+                        let publication = remap(publication, topic_name, qos);
+                        tokio::spawn(async move {
+                            if let Err(e) = handle.publish(publication).await {
+                                warn!(
+                                    message = "failed to forward message to local broker",
+                                    error=%e
+                                );
+                            }
+                        });
+                    }
+                }
+                Some(Event::Local(ClientEvent::Publication(publication))) => {
+                    if local_echoes.remove(&publication.topic_name, &publication.payload) {
+                        continue;
+                    }
+
+                    if let Some((topic_name, qos)) = mapper.to_remote(&publication.topic_name) {
+                        debug!(
+                            "queueing message from {} to {}",
+                            publication.topic_name, topic_name
+                        );
+                        if queue.push(remap(publication, topic_name, qos)) {
+                            dirty = true;
+                        } else {
+                            warn!("bridge queue is full, dropping message");
+                        }
+                    }
+                }
+                Some(Event::Upstream(ClientEvent::NewConnection { reset_session })) => {
+                    info!(
+                        "connected to upstream broker (session reset: {})",
+                        reset_session
+                    );
+                }
+                Some(Event::Local(ClientEvent::NewConnection { .. })) => {
+                    info!("connected to local broker");
+                }
+                Some(Event::Upstream(ClientEvent::SubscriptionUpdates(_)))
+                | Some(Event::Local(ClientEvent::SubscriptionUpdates(_))) => (),
+                Some(Event::Tick) => {
+                    if dirty {
+                        if let Err(e) = persistor.store(queue.to_state()).await {
+                            warn!(message = "an error occurred persisting bridge queue.", error=%e);
+                        }
+                        dirty = false;
+                    }
+                }
+                Some(Event::Shutdown) | None => {
+                    info!("bridge {} shutting down...", config.name());
+                    break;
+                }
+            }
+        }
+
+        for mut handle in shutdown_handles {
+            if let Err(e) = handle.shutdown().await {
+                warn!(message = "failed to shut down bridge client", error=%e);
+            }
+        }
+        for result in future::join_all(pumps).await {
+            if let Err(e) = result {
+                warn!(message = "failed to join bridge client", error=%e);
+            }
+        }
+
+        // The message in flight stays at the front of the queue and is sent
+        // again once the bridge restarts.
+        persistor.store(queue.to_state()).await?;
+        info!(
+            "bridge {} stopped with {} messages queued",
+            config.name(),
+            queue.len()
+        );
+
+        Ok(persistor)
+    }
+}
+
+/// Creates a TLS connector which verifies the upstream broker's certificate.
+fn tls_connector(tls: &BridgeTls) -> Result<SslConnector, InitializeBrokerError> {
+    let mut builder =
+        SslConnector::builder(SslMethod::tls()).map_err(InitializeBrokerError::Tls)?;
+
+    if let Some(ca) = tls.ca() {
+        info!("Loading upstream CA certificates from {}", ca.display());
+        builder
+            .set_ca_file(ca)
+            .map_err(|e| InitializeBrokerError::LoadCaCertificates(ca.to_path_buf(), e))?;
+    }
+
+    if let Some(certificate) = tls.certificate() {
+        let identity = transport::load_identity(certificate)?;
+        builder
+            .set_private_key(&identity.pkey)
+            .map_err(InitializeBrokerError::Tls)?;
+        builder
+            .set_certificate(&identity.cert)
+            .map_err(InitializeBrokerError::Tls)?;
+        for cert in identity.chain.into_iter().flatten() {
+            builder
+                .add_extra_chain_cert(cert)
+                .map_err(InitializeBrokerError::Tls)?;
+        }
+    }
+
+    Ok(builder.build())
+}
+
+type ConnectFuture =
+    Pin<Box<dyn Future<Output = io::Result<(StreamSelector, Option<String>)>> + Send>>;
+
+type StreamSource = Box<dyn FnMut() -> ConnectFuture + Send>;
+
+fn client(
+    client_id: &str,
+    address: &str,
+    username: Option<&str>,
+    password: Option<&str>,
+    connector: Option<SslConnector>,
+    config: &BridgeConfig,
+) -> Client<StreamSource> {
+    let address = address.to_string();
+    let password = password.map(str::to_string);
+    let io_source: StreamSource = Box::new(move || -> ConnectFuture {
+        let address = address.clone();
+        let password = password.clone();
+        let connector = connector.clone();
+        Box::pin(async move {
+            let io = TcpStream::connect(&address).await?;
+            let io = match connector {
+                Some(connector) => {
+                    let config = connector
+                        .configure()
+                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                    let io = tokio_openssl::connect(config, host(&address), io)
+                        .await
+                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                    StreamSelector::Tls(io)
+                }
+                None => StreamSelector::Tcp(io),
+            };
+            Ok((io, password))
+        })
+    });
+
+    Client::new(
+        Some(client_id.to_string()),
+        username.map(str::to_string),
+        None,
+        io_source,
+        config.max_reconnect_back_off(),
+        config.keep_alive(),
+    )
+}
+
+/// Returns the host name of `address`, which the upstream broker's
+/// certificate is verified against.
+fn host(address: &str) -> &str {
+    let host = match address.rfind(':') {
+        Some(index) if !address.ends_with(']') => &address[..index],
+        _ => address,
+    };
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+/// Drives a client connection and passes its events on to the bridge.
+fn pump<F>(
+    name: &'static str,
+    mut client: Client<StreamSource>,
+    mut sender: Sender<Event>,
+    wrap: F,
+) -> JoinHandle<()>
+where
+    F: Fn(ClientEvent) -> Event + Send + 'static,
+{
+    tokio::spawn(async move {
+        while let Some(event) = client.next().await {
+            match event {
+                Ok(event) => {
+                    if sender.send(wrap(event)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(e) => warn!(message = "bridge client error", client = name, error=%e),
+            }
+        }
+        debug!("bridge client {} stopped", name);
+    })
+}
+
+fn remap(
+    publication: ReceivedPublication,
+    topic_name: String,
+    qos: proto::QoS,
+) -> proto::Publication {
+    proto::Publication {
+        topic_name,
+        qos,
+        retain: publication.retain,
+        payload: publication.payload,
+        properties: proto::Properties::default(),
+    }
+}
+
+/// Messages the bridge expects to receive back after forwarding them.
+///
+/// When a rule forwards in both directions, a message the bridge publishes on
+/// one side matches its own subscription on that side and would otherwise be
+/// forwarded back, over and over.
+#[derive(Debug, Default)]
+struct Echoes(VecDeque<(String, Bytes)>);
+
+impl Echoes {
+    fn insert(&mut self, topic_name: &str, payload: &Bytes) {
+        if self.0.len() >= MAX_ECHOES {
+            self.0.pop_front();
+        }
+        self.0.push_back((topic_name.to_string(), payload.clone()));
+    }
+
+    fn remove(&mut self, topic_name: &str, payload: &Bytes) -> bool {
+        let position = self
+            .0
+            .iter()
+            .position(|(topic, bytes)| topic == topic_name && bytes == payload);
+        match position {
+            Some(index) => {
+                self.0.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::future::FutureExt;
+    use serde_json::json;
+    use tokio::sync::oneshot;
+
+    use super::*;
+    use crate::persist::NullPersistor;
+    use crate::{AuthId, BrokerBuilder, Server, TransportBuilder};
+
+    fn free_address() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    fn start_broker(address: &str) -> oneshot::Sender<()> {
+        let broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+        let transports = vec![TransportBuilder::Tcp(address.to_string())];
+
+        let (shutdown, signal) = oneshot::channel::<()>();
+        tokio::spawn(Server::from_broker(broker).serve(transports, signal.map(drop)));
+        shutdown
+    }
+
+    /// Connects a client which subscribes to `topic_filter`, and waits until
+    /// the subscription is acknowledged.
+    async fn subscriber(
+        client_id: &str,
+        address: &str,
+        topic_filter: &str,
+        config: &BridgeConfig,
+    ) -> (mqtt3::PublishHandle, Receiver<Event>) {
+        let mut client = client(client_id, address, None, None, None, config);
+        client
+            .subscribe(proto::SubscribeTo {
+                topic_filter: topic_filter.to_string(),
+                qos: proto::QoS::AtLeastOnce,
+                options: proto::SubscriptionOptions::default(),
+            })
+            .unwrap();
+        let publish_handle = client.publish_handle().unwrap();
+
+        let (sender, mut events) = mpsc::channel(16);
+        pump("test", client, sender, Event::Local);
+        loop {
+            if let Some(Event::Local(ClientEvent::SubscriptionUpdates(_))) = events.recv().await {
+                return (publish_handle, events);
+            }
+        }
+    }
+
+    fn received(events: &mut Receiver<Event>) -> Vec<(String, Bytes)> {
+        let mut received = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let Event::Local(ClientEvent::Publication(publication)) = event {
+                received.push((publication.topic_name, publication.payload));
+            }
+        }
+        received.sort();
+        received
+    }
+
+    #[tokio::test]
+    async fn it_forwards_both_ways_without_looping() {
+        let local_address = free_address();
+        let upstream_address = free_address();
+        let local_shutdown = start_broker(&local_address);
+        let upstream_shutdown = start_broker(&upstream_address);
+
+        let config: BridgeConfig = serde_json::from_value(json!({
+            "name": "upstream",
+            "address": upstream_address,
+            "local_address": local_address,
+            "client_id": "edge1",
+            "max_reconnect_back_off": "1s",
+            "topics": [{ "pattern": "telemetry/#", "direction": "both", "qos": 1 }]
+        }))
+        .unwrap();
+
+        let (mut local, mut local_events) =
+            subscriber("local", &local_address, "telemetry/#", &config).await;
+        let (mut upstream, mut upstream_events) =
+            subscriber("upstream", &upstream_address, "telemetry/#", &config).await;
+
+        let bridge = Bridge::new(config, NullPersistor).unwrap();
+        let mut bridge_shutdown = bridge.shutdown_handle();
+        let bridge = tokio::spawn(bridge.run());
+        tokio::time::delay_for(Duration::from_secs(2)).await;
+
+        let publication = |topic_name: &str, payload: &'static str| proto::Publication {
+            topic_name: topic_name.to_string(),
+            qos: proto::QoS::AtLeastOnce,
+            retain: false,
+            payload: Bytes::from(payload),
+            properties: proto::Properties::default(),
+        };
+        local
+            .publish(publication("telemetry/a", "1"))
+            .await
+            .unwrap();
+        upstream
+            .publish(publication("telemetry/b", "2"))
+            .await
+            .unwrap();
+        tokio::time::delay_for(Duration::from_secs(2)).await;
+
+        // Each message arrives once on each side, and is not sent back.
+        let expected = vec![
+            ("telemetry/a".to_string(), Bytes::from("1")),
+            ("telemetry/b".to_string(), Bytes::from("2")),
+        ];
+        assert_eq!(expected, received(&mut local_events));
+        assert_eq!(expected, received(&mut upstream_events));
+
+        bridge_shutdown.shutdown().await.unwrap();
+        bridge.await.unwrap().unwrap();
+        local_shutdown.send(()).unwrap();
+        upstream_shutdown.send(()).unwrap();
+    }
+
+    #[test]
+    fn host_is_taken_from_address() {
+        assert_eq!("parent", host("parent:8883"));
+        assert_eq!("parent", host("parent"));
+        assert_eq!("::1", host("[::1]:8883"));
+        assert_eq!("::1", host("[::1]"));
+    }
+
+    #[test]
+    fn echoes_are_removed_once() {
+        let mut echoes = Echoes::default();
+        echoes.insert("a/b", &Bytes::from("1"));
+
+        assert!(!echoes.remove("a/b", &Bytes::from("2")));
+        assert!(echoes.remove("a/b", &Bytes::from("1")));
+        assert!(!echoes.remove("a/b", &Bytes::from("1")));
+    }
+
+    #[test]
+    fn echoes_are_bounded() {
+        let mut echoes = Echoes::default();
+        for i in 0..=MAX_ECHOES {
+            echoes.insert(&i.to_string(), &Bytes::new());
+        }
+
+        assert!(!echoes.remove("0", &Bytes::new()));
+        assert!(echoes.remove(&MAX_ECHOES.to_string(), &Bytes::new()));
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::{HashMap, VecDeque};
+
+use mqtt3::proto;
+
+use crate::session::SessionState;
+use crate::{BrokerState, ClientId, ReceivedPublication};
+
+/// Messages waiting to be forwarded to the upstream broker.
+///
+/// The queue is stored through the regular `Persist` machinery as a
+/// `BrokerState` holding a single session named after the bridge, so that
+/// messages survive a restart while the upstream broker is unreachable.
+#[derive(Debug)]
+pub(crate) struct OutboundQueue {
+    client_id: ClientId,
+    messages: VecDeque<proto::Publication>,
+    max_count: usize,
+}
+
+impl OutboundQueue {
+    pub fn new(client_id: ClientId, max_count: usize) -> Self {
+        Self {
+            client_id,
+            messages: VecDeque::new(),
+            max_count,
+        }
+    }
+
+    pub fn from_state(client_id: ClientId, max_count: usize, state: BrokerState) -> Self {
+        let (_retained, sessions) = state.into_parts();
+        let messages = sessions
+            .into_iter()
+            .map(SessionState::into_parts)
+            .find(|(id, _, _)| *id == client_id)
+            .map(|(_, _, messages)| {
+                messages
+                    .into_iter()
+                    .map(ReceivedPublication::into_publication)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            client_id,
+            messages,
+            max_count,
+        }
+    }
+
+    /// Adds a message to the back of the queue.
+    ///
+    /// Returns `false` and drops the message if the queue is full.
+    pub fn push(&mut self, publication: proto::Publication) -> bool {
+        if self.messages.len() >= self.max_count {
+            return false;
+        }
+
+        self.messages.push_back(publication);
+        true
+    }
+
+    pub fn front(&self) -> Option<&proto::Publication> {
+        self.messages.front()
+    }
+
+    pub fn pop_front(&mut self) -> Option<proto::Publication> {
+        self.messages.pop_front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn to_state(&self) -> BrokerState {
+        let messages = self
+            .messages
+            .iter()
+            .cloned()
+            .map(ReceivedPublication::new)
+            .collect();
+        let session = SessionState::from_parts(self.client_id.clone(), HashMap::new(), messages);
+        BrokerState::new(HashMap::new(), vec![session])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    fn publication(topic: &str) -> proto::Publication {
+        proto::Publication {
+            topic_name: topic.to_string(),
+            qos: proto::QoS::AtLeastOnce,
+            retain: false,
+            payload: Bytes::from("payload"),
+            properties: proto::Properties::default(),
+        }
+    }
+
+    #[test]
+    fn it_keeps_messages_in_order() {
+        let mut queue = OutboundQueue::new("bridge".into(), 10);
+        assert!(queue.push(publication("a")));
+        assert!(queue.push(publication("b")));
+
+        assert_eq!(Some(&publication("a")), queue.front());
+        assert_eq!(Some(publication("a")), queue.pop_front());
+        assert_eq!(Some(publication("b")), queue.pop_front());
+        assert_eq!(None, queue.pop_front());
+    }
+
+    #[test]
+    fn it_drops_new_messages_when_full() {
+        let mut queue = OutboundQueue::new("bridge".into(), 2);
+        assert!(queue.push(publication("a")));
+        assert!(queue.push(publication("b")));
+        assert!(!queue.push(publication("c")));
+
+        assert_eq!(2, queue.len());
+        assert_eq!(Some(publication("a")), queue.pop_front());
+        assert_eq!(Some(publication("b")), queue.pop_front());
+    }
+
+    #[test]
+    fn it_restores_from_state() {
+        let mut queue = OutboundQueue::new("bridge".into(), 10);
+        queue.push(publication("a"));
+        queue.push(publication("b"));
+
+        let mut restored = OutboundQueue::from_state("bridge".into(), 10, queue.to_state());
+        assert_eq!(2, restored.len());
+        assert_eq!(Some(publication("a")), restored.pop_front());
+        assert_eq!(Some(publication("b")), restored.pop_front());
+    }
+
+    #[test]
+    fn it_ignores_state_of_other_bridges() {
+        let mut queue = OutboundQueue::new("other".into(), 10);
+        queue.push(publication("a"));
+
+        let restored = OutboundQueue::from_state("bridge".into(), 10, queue.to_state());
+        assert_eq!(0, restored.len());
+    }
+}
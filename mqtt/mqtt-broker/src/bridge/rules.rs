@@ -0,0 +1,252 @@
+use mqtt3::proto;
+
+use crate::configuration::{Direction, TopicRule as TopicRuleConfig};
+use crate::subscription::TopicFilter;
+use crate::Error;
+
+/// Maps topic names between the local broker and the upstream broker.
+///
+/// Every rule strips its prefix from one side and replaces it with the prefix
+/// of the other side. The remainder must match the rule's pattern.
+#[derive(Debug)]
+pub(crate) struct TopicMapper {
+    rules: Vec<TopicRule>,
+}
+
+#[derive(Debug)]
+struct TopicRule {
+    pattern: String,
+    filter: TopicFilter,
+    direction: Direction,
+    local_prefix: String,
+    remote_prefix: String,
+    qos: proto::QoS,
+}
+
+impl TopicRule {
+    fn new(rule: &TopicRuleConfig) -> Result<Self, Error> {
+        // Both sides must form a valid filter once the prefix is added.
+        for prefix in &[rule.local_prefix(), rule.remote_prefix()] {
+            format!("{}{}", prefix, rule.pattern()).parse::<TopicFilter>()?;
+        }
+
+        Ok(Self {
+            pattern: rule.pattern().to_string(),
+            filter: rule.pattern().parse()?,
+            direction: rule.direction(),
+            local_prefix: rule.local_prefix().to_string(),
+            remote_prefix: rule.remote_prefix().to_string(),
+            qos: rule.qos(),
+        })
+    }
+
+    fn forwards_out(&self) -> bool {
+        self.direction != Direction::In
+    }
+
+    fn forwards_in(&self) -> bool {
+        self.direction != Direction::Out
+    }
+
+    fn subscribe_to(&self, prefix: &str) -> proto::SubscribeTo {
+        proto::SubscribeTo {
+            topic_filter: format!("{}{}", prefix, self.pattern),
+            qos: self.qos,
+            options: proto::SubscriptionOptions::default(),
+        }
+    }
+
+    fn map(&self, topic_name: &str, from: &str, to: &str) -> Option<(String, proto::QoS)> {
+        if !topic_name.starts_with(from) {
+            return None;
+        }
+
+        let rest = &topic_name[from.len()..];
+        if self.filter.matches(rest) {
+            Some((format!("{}{}", to, rest), self.qos))
+        } else {
+            None
+        }
+    }
+}
+
+impl TopicMapper {
+    pub fn new<'a, I>(rules: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = &'a TopicRuleConfig>,
+    {
+        let rules = rules
+            .into_iter()
+            .map(TopicRule::new)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { rules })
+    }
+
+    /// Subscriptions the bridge needs on the local broker.
+    pub fn local_subscriptions(&self) -> Vec<proto::SubscribeTo> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.forwards_out())
+            .map(|rule| rule.subscribe_to(&rule.local_prefix))
+            .collect()
+    }
+
+    /// Subscriptions the bridge needs on the upstream broker.
+    pub fn remote_subscriptions(&self) -> Vec<proto::SubscribeTo> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.forwards_in())
+            .map(|rule| rule.subscribe_to(&rule.remote_prefix))
+            .collect()
+    }
+
+    /// Maps a local topic name to the topic and QoS it is published with upstream.
+    pub fn to_remote(&self, topic_name: &str) -> Option<(String, proto::QoS)> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.forwards_out())
+            .find_map(|rule| rule.map(topic_name, &rule.local_prefix, &rule.remote_prefix))
+    }
+
+    /// Maps an upstream topic name to the topic and QoS it is published with locally.
+    pub fn to_local(&self, topic_name: &str) -> Option<(String, proto::QoS)> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.forwards_in())
+            .find_map(|rule| rule.map(topic_name, &rule.remote_prefix, &rule.local_prefix))
+    }
+
+    /// Whether a message published upstream to `topic_name` is delivered
+    /// back to the bridge by its own upstream subscriptions.
+    pub fn echoes_remote(&self, topic_name: &str) -> bool {
+        self.to_local(topic_name).is_some()
+    }
+
+    /// Whether a message published locally to `topic_name` is delivered
+    /// back to the bridge by its own local subscriptions.
+    pub fn echoes_local(&self, topic_name: &str) -> bool {
+        self.to_remote(topic_name).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+    use serde_json::json;
+
+    use super::*;
+
+    fn mapper(rules: serde_json::Value) -> Result<TopicMapper, Error> {
+        let rules: Vec<TopicRuleConfig> = serde_json::from_value(rules).unwrap();
+        TopicMapper::new(&rules)
+    }
+
+    #[test]
+    fn it_maps_outgoing_topics() {
+        let mapper = mapper(json!([
+            { "pattern": "telemetry/#", "direction": "out", "local_prefix": "local/", "remote_prefix": "edge1/", "qos": 1 }
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            Some(("edge1/telemetry/temp".to_string(), proto::QoS::AtLeastOnce)),
+            mapper.to_remote("local/telemetry/temp")
+        );
+        assert_eq!(None, mapper.to_remote("telemetry/temp"));
+        assert_eq!(None, mapper.to_remote("local/other/temp"));
+        assert_eq!(None, mapper.to_local("edge1/telemetry/temp"));
+    }
+
+    #[test]
+    fn it_maps_incoming_topics() {
+        let mapper = mapper(json!([
+            { "pattern": "commands/+", "direction": "in", "remote_prefix": "edge1/", "qos": 0 }
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            Some(("commands/reboot".to_string(), proto::QoS::AtMostOnce)),
+            mapper.to_local("edge1/commands/reboot")
+        );
+        assert_eq!(None, mapper.to_local("edge1/commands/reboot/now"));
+        assert_eq!(None, mapper.to_remote("commands/reboot"));
+    }
+
+    #[test]
+    fn it_maps_both_directions() {
+        let mapper = mapper(json!([
+            { "pattern": "twin/#", "direction": "both", "remote_prefix": "edge1/", "qos": 2 }
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            Some(("edge1/twin/desired".to_string(), proto::QoS::ExactlyOnce)),
+            mapper.to_remote("twin/desired")
+        );
+        assert_eq!(
+            Some(("twin/desired".to_string(), proto::QoS::ExactlyOnce)),
+            mapper.to_local("edge1/twin/desired")
+        );
+        assert!(mapper.echoes_remote("edge1/twin/desired"));
+        assert!(mapper.echoes_local("twin/desired"));
+    }
+
+    #[test]
+    fn it_uses_first_matching_rule() {
+        let mapper = mapper(json!([
+            { "pattern": "a/#", "direction": "out", "remote_prefix": "first/", "qos": 0 },
+            { "pattern": "#", "direction": "out", "remote_prefix": "second/", "qos": 1 }
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            Some(("first/a/b".to_string(), proto::QoS::AtMostOnce)),
+            mapper.to_remote("a/b")
+        );
+        assert_eq!(
+            Some(("second/c/d".to_string(), proto::QoS::AtLeastOnce)),
+            mapper.to_remote("c/d")
+        );
+    }
+
+    #[test]
+    fn it_builds_subscriptions() {
+        let mapper = mapper(json!([
+            { "pattern": "telemetry/#", "direction": "out", "local_prefix": "local/", "qos": 1 },
+            { "pattern": "commands/#", "direction": "in", "remote_prefix": "edge1/", "qos": 0 },
+            { "pattern": "twin/#", "direction": "both", "qos": 2 }
+        ]))
+        .unwrap();
+
+        let local = mapper.local_subscriptions();
+        assert_eq!(2, local.len());
+        assert_eq!("local/telemetry/#", local[0].topic_filter);
+        assert_eq!(proto::QoS::AtLeastOnce, local[0].qos);
+        assert_eq!("twin/#", local[1].topic_filter);
+
+        let remote = mapper.remote_subscriptions();
+        assert_eq!(2, remote.len());
+        assert_eq!("edge1/commands/#", remote[0].topic_filter);
+        assert_eq!(proto::QoS::AtMostOnce, remote[0].qos);
+        assert_eq!("twin/#", remote[1].topic_filter);
+    }
+
+    #[test]
+    fn it_refuses_invalid_pattern() {
+        let result = mapper(json!([
+            { "pattern": "a/#/b", "direction": "out", "qos": 0 }
+        ]));
+
+        assert_matches!(result, Err(Error::InvalidTopicFilter(_)));
+    }
+
+    #[test]
+    fn it_refuses_invalid_prefix() {
+        let result = mapper(json!([
+            { "pattern": "a", "direction": "in", "remote_prefix": "#/", "qos": 0 }
+        ]));
+
+        assert_matches!(result, Err(Error::InvalidTopicFilter(_)));
+    }
+}
@@ -1,9 +1,13 @@
-use std::collections::HashMap;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::time::{Duration, Instant, SystemTime};
 
 use futures_util::future;
 use mqtt3::proto;
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::sync::watch;
 use tracing::{debug, info, span, warn, Level};
 use tracing_futures::Instrument;
 
@@ -11,13 +15,24 @@ use crate::auth::{
     Activity, Authenticator, Authorizer, Credentials, DefaultAuthenticator, DefaultAuthorizer,
     Operation,
 };
-use crate::session::{ConnectedSession, Session, SessionState};
+use crate::configuration::{BrokerConfig, SessionPersistence};
+use crate::connection::TOPIC_ALIAS_MAXIMUM;
+use crate::limits::PublishLimits;
+use crate::retention::{ReceivedPublication, RetentionPolicy};
+use crate::session::{ConnectedSession, Session, SessionConfig, SessionState};
+use crate::snapshot::StateSnapshotHandle;
+use crate::stats::{BrokerStats, SessionStats, StatsTracker};
+use crate::subscription::{Subscription, TopicFilter};
+use crate::trie::TopicTrie;
+use crate::wal::Journal;
 use crate::{
-    subscription::Subscription, AuthId, ClientEvent, ClientId, ConnReq, Error, Message, SystemEvent,
+    AuthId, ClientEvent, ClientId, ConnReq, Error, Message, RetainedInfo, SessionInfo, SystemEvent,
 };
 
 static EXPECTED_PROTOCOL_NAME: &str = mqtt3::PROTOCOL_NAME;
-const EXPECTED_PROTOCOL_LEVEL: u8 = mqtt3::PROTOCOL_LEVEL;
+
+/// How often the broker looks for expired offline sessions and retained messages.
+const EXPIRATION_CHECK_INTERVAL: Duration = Duration::from_secs(60);
 
 macro_rules! try_send {
     ($session:expr, $msg:expr) => {{
@@ -35,9 +50,19 @@ where
     sender: Sender<Message>,
     messages: Receiver<Message>,
     sessions: HashMap<ClientId, Session>,
-    retained: HashMap<String, proto::Publication>,
+    subscriptions: TopicTrie,
+    retained: HashMap<String, RetainedPublication>,
+    sys_retained: HashMap<String, proto::Publication>,
     authenticator: N,
     authorizer: Z,
+    config: BrokerConfig,
+    retention: RetentionPolicy,
+    last_expiration_check: Instant,
+    stats: StatsTracker,
+    snapshot_handle: Option<StateSnapshotHandle>,
+    unsaved_messages: u32,
+    journal: Option<Journal>,
+    publish_limits: PublishLimits,
 }
 
 impl<N, Z> Broker<N, Z>
@@ -49,8 +74,26 @@ where
         BrokerHandle(self.sender.clone())
     }
 
+    /// Returns a receiver for the statistics the broker shares every time
+    /// it is asked to publish them.
+    pub fn stats(&self) -> watch::Receiver<BrokerStats> {
+        self.stats.subscribe()
+    }
+
+    /// Returns the publish rate clients of the broker are held to, shared by
+    /// all of their connections.
+    pub(crate) fn publish_limits(&self) -> PublishLimits {
+        self.publish_limits.clone()
+    }
+
     pub async fn run(mut self) -> BrokerState {
         while let Some(message) = self.messages.recv().await {
+            if self.last_expiration_check.elapsed() >= EXPIRATION_CHECK_INTERVAL {
+                self.expire_sessions();
+                self.expire_retained();
+                self.last_expiration_check = Instant::now();
+            }
+
             match message {
                 Message::Client(client_id, event) => {
                     let span = span!(Level::INFO, "broker", client_id = %client_id, event="client");
@@ -73,33 +116,137 @@ where
                             }
                             break;
                         }
-                        SystemEvent::StateSnapshot(mut handle) => {
-                            let state = self.snapshot();
-                            let _guard = span.enter();
-                            info!("asking snapshotter to persist state...");
-                            if let Err(e) = handle.send(state).await {
-                                warn!(message = "an error occurred communicating with the snapshotter", error = %e);
-                            } else {
-                                info!("sent state to snapshotter.");
+                        SystemEvent::StateSnapshot(handle) => {
+                            self.send_snapshot(handle).instrument(span).await;
+                        }
+                        SystemEvent::PublishStats => {
+                            if let Err(e) = self.publish_stats().instrument(span).await {
+                                warn!(message = "an error occurred publishing broker statistics", error = %e);
+                            }
+                        }
+                        SystemEvent::ListSessions(reply) => {
+                            let _ = reply.send(self.session_infos());
+                        }
+                        SystemEvent::ListRetained(reply) => {
+                            let _ = reply.send(self.retained_infos());
+                        }
+                        SystemEvent::DeleteRetained(topic_name, reply) => {
+                            let _ = reply.send(self.delete_retained(topic_name));
+                        }
+                        SystemEvent::DisconnectSession(client_id, reply) => {
+                            match self.disconnect_session(client_id).instrument(span).await {
+                                Ok(connected) => {
+                                    let _ = reply.send(connected);
+                                }
+                                Err(e) => {
+                                    warn!(message = "an error occurred disconnecting a session", error = %e);
+                                }
+                            }
+                        }
+                        SystemEvent::PurgeSession(client_id, reply) => {
+                            match self.purge_session(client_id).instrument(span).await {
+                                Ok(purged) => {
+                                    let _ = reply.send(purged);
+                                }
+                                Err(e) => {
+                                    warn!(message = "an error occurred purging a session", error = %e);
+                                }
                             }
                         }
                     }
                 }
             }
+
+            self.flush_changes().await;
         }
 
         info!("broker is shutdown.");
+        self.expire_queued();
         self.snapshot()
     }
 
+    async fn send_snapshot(&mut self, mut handle: StateSnapshotHandle) {
+        self.expire_queued();
+
+        // The snapshot contains all changes made so far.
+        if let Some(journal) = self.journal.as_mut() {
+            journal.collect(&mut self.sessions);
+        }
+
+        let state = self.snapshot();
+        self.unsaved_messages = 0;
+        info!("asking snapshotter to persist state...");
+        if let Err(e) = handle.send(state).await {
+            warn!(message = "an error occurred communicating with the snapshotter", error = %e);
+        } else {
+            info!("sent state to snapshotter.");
+        }
+    }
+
+    /// Sends the state changes made while processing the last message to the
+    /// snapshotter, which appends them to the write-ahead log of the last snapshot.
+    async fn flush_changes(&mut self) {
+        let changes = match self.journal.as_mut() {
+            Some(journal) => journal.collect(&mut self.sessions),
+            None => return,
+        };
+        if changes.is_empty() {
+            return;
+        }
+
+        if let Some(handle) = self.snapshot_handle.as_mut() {
+            if let Err(e) = handle.send_changes(changes).await {
+                warn!(message = "an error occurred sending state changes to the snapshotter", error = %e);
+            }
+        }
+    }
+
+    /// Lets the journal know that the session of `client_id` may have changed
+    /// since the last flush.
+    fn session_changed(&mut self, client_id: &ClientId) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.session_changed(client_id);
+        }
+    }
+
+    /// Counts a message published since the last snapshot and snapshots the
+    /// state once `persistence.unsaved_message_count` messages have
+    /// accumulated. A count of `0` disables these early snapshots.
+    async fn count_unsaved_message(&mut self) {
+        self.unsaved_messages = self.unsaved_messages.saturating_add(1);
+
+        let threshold = self
+            .config
+            .persistence()
+            .map_or(0, SessionPersistence::unsaved_message_count);
+        if threshold == 0 || self.unsaved_messages < threshold {
+            return;
+        }
+
+        if let Some(handle) = self.snapshot_handle.clone() {
+            debug!("{} messages not persisted yet", self.unsaved_messages);
+            self.send_snapshot(handle).await;
+        }
+    }
+
     fn snapshot(&self) -> BrokerState {
-        let retained = self.retained.clone();
+        let retained = self
+            .retained
+            .iter()
+            .filter_map(|(topic, retained)| {
+                if retained.is_expired() {
+                    None
+                } else {
+                    Some((topic.clone(), retained.to_received()))
+                }
+            })
+            .collect();
         let sessions = self
             .sessions
             .values()
             .filter_map(|session| match session {
                 Session::Persistent(ref c) => Some(c.state().clone()),
-                Session::Offline(ref o) => Some(o.state().clone()),
+                Session::Offline(ref o) if !o.is_expired() => Some(o.state().clone()),
                 _ => None,
             })
             .collect::<Vec<SessionState>>();
@@ -107,19 +254,250 @@ where
         BrokerState { retained, sessions }
     }
 
+    fn session_config(&self) -> SessionConfig {
+        SessionConfig::from(&self.config)
+    }
+
+    /// Shares current statistics and publishes them as retained messages
+    /// on the `$SYS/broker` topics.
+    ///
+    /// These are kept apart from the retained messages of clients, so they
+    /// neither count against the retained message limit nor get persisted.
+    async fn publish_stats(&mut self) -> Result<(), Error> {
+        self.expire_retained();
+        let sessions = self
+            .sessions
+            .values()
+            .filter_map(|session| match session {
+                Session::Transient(c) | Session::Persistent(c) => Some((true, c.state())),
+                Session::Offline(o) => Some((false, o.state())),
+                Session::Disconnecting(_) => None,
+            })
+            .map(|(connected, state)| {
+                SessionStats::new(
+                    state.client_id().clone(),
+                    connected,
+                    state.queued_messages(),
+                    state.inflight_messages(),
+                )
+            })
+            .collect();
+
+        let publications = self.stats.update(self.retained.len(), sessions);
+        for publication in publications {
+            if publication.payload.is_empty() {
+                self.sys_retained.remove(&publication.topic_name);
+            } else {
+                self.sys_retained
+                    .insert(publication.topic_name.clone(), publication.clone());
+            }
+            self.publish_to_subscribers(publication).await?;
+        }
+        Ok(())
+    }
+
+    /// Describes the sessions for the admin endpoint.
+    fn session_infos(&self) -> Vec<SessionInfo> {
+        self.sessions
+            .values()
+            .filter_map(|session| {
+                let (connected, persistent, state) = match session {
+                    Session::Transient(c) => (true, false, c.state()),
+                    Session::Persistent(c) => (true, true, c.state()),
+                    Session::Offline(o) => (false, true, o.state()),
+                    Session::Disconnecting(_) => return None,
+                };
+                let auth_id = session.auth_id().ok().map(ToString::to_string);
+
+                Some(SessionInfo::new(
+                    state.client_id().to_string(),
+                    auth_id,
+                    connected,
+                    persistent,
+                    state.subscriptions().keys().cloned().collect(),
+                    state.queued_messages(),
+                    state.inflight_messages(),
+                ))
+            })
+            .collect()
+    }
+
+    /// Describes the retained messages for the admin endpoint.
+    fn retained_infos(&self) -> Vec<RetainedInfo> {
+        self.retained
+            .iter()
+            .filter(|(_, retained)| !retained.is_expired())
+            .map(|(topic, retained)| {
+                RetainedInfo::new(
+                    topic.clone(),
+                    retained.publication.qos.into(),
+                    retained.publication.payload.len(),
+                )
+            })
+            .collect()
+    }
+
+    fn delete_retained(&mut self, topic_name: String) -> bool {
+        if self.retained.remove(&topic_name).is_none() {
+            return false;
+        }
+
+        info!("deleted retained message for topic \"{}\"", topic_name);
+        if let Some(journal) = self.journal.as_mut() {
+            journal.retained_removed(topic_name);
+        }
+        true
+    }
+
+    /// Drops the connection of a client, the same way as if the connection
+    /// was lost. Returns false if the client is not connected.
+    async fn disconnect_session(&mut self, client_id: ClientId) -> Result<bool, Error> {
+        match self.sessions.get(&client_id) {
+            Some(Session::Transient(_)) | Some(Session::Persistent(_)) => {
+                info!("disconnecting {}", client_id);
+                self.drop_connection(client_id).await?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Drops the connection of a client and removes its session, including
+    /// subscriptions and queued messages. Returns false if there is no session.
+    async fn purge_session(&mut self, client_id: ClientId) -> Result<bool, Error> {
+        let connected = self.disconnect_session(client_id.clone()).await?;
+
+        self.session_changed(&client_id);
+        let purged = match self.sessions.remove(&client_id) {
+            Some(Session::Offline(offline)) => {
+                info!("purging offline session for {}", client_id);
+                unindex_subscriptions(&mut self.subscriptions, offline.state());
+                true
+            }
+            Some(session) => {
+                self.sessions.insert(client_id, session);
+                false
+            }
+            None => false,
+        };
+        Ok(connected || purged)
+    }
+
+    /// Returns the session config for a client, narrowed down by the
+    /// session expiry interval and receive maximum it asked for.
+    fn session_config_for(&self, connect: &proto::Connect) -> SessionConfig {
+        let mut config = self.session_config();
+        if connect.protocol_level == mqtt3::PROTOCOL_LEVEL_V5 {
+            // If the Session Expiry Interval is absent the value 0 is used.
+            let interval = connect.properties.session_expiry_interval().unwrap_or(0);
+            config.limit_expiration(Duration::from_secs(interval.into()));
+            config.set_ends_with_connection(interval == 0);
+
+            if let Some(receive_maximum) = connect.properties.receive_maximum() {
+                config.limit_max_inflight_messages(receive_maximum.into());
+            }
+        }
+        config
+    }
+
+    /// Removes offline sessions which have not been reconnected within the
+    /// configured session expiration.
+    fn expire_sessions(&mut self) {
+        let subscriptions = &mut self.subscriptions;
+        let journal = &mut self.journal;
+        self.sessions.retain(|client_id, session| match session {
+            Session::Offline(offline) if offline.is_expired() => {
+                info!("offline session for {} expired", client_id);
+                unindex_subscriptions(subscriptions, offline.state());
+                if let Some(journal) = journal.as_mut() {
+                    journal.session_changed(client_id);
+                }
+                false
+            }
+            _ => true,
+        });
+    }
+
+    /// Drops expired messages from the queues of sessions, so they are not
+    /// part of the next snapshot.
+    fn expire_queued(&mut self) {
+        let now = SystemTime::now();
+        for (client_id, session) in &mut self.sessions {
+            if let Some(state) = session.state_mut() {
+                state.expire_queued(&self.retention, now);
+                if let Some(journal) = self.journal.as_mut() {
+                    journal.session_changed(client_id);
+                }
+            }
+        }
+    }
+
+    fn expire_retained(&mut self) {
+        let journal = &mut self.journal;
+        self.retained.retain(|topic, retained| {
+            if retained.is_expired() {
+                info!("retained message for topic \"{}\" expired", topic);
+                if let Some(journal) = journal.as_mut() {
+                    journal.retained_removed(topic.clone());
+                }
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    fn store_retained(&mut self, publication: proto::Publication) {
+        let max_count = self.config.retained_messages().max_count();
+        if !self.retained.contains_key(&publication.topic_name) && self.retained.len() >= max_count
+        {
+            self.expire_retained();
+            if self.retained.len() >= max_count {
+                warn!(
+                    "retained message limit of {} reached. dropping retained message for topic \"{}\"",
+                    max_count, publication.topic_name
+                );
+                return;
+            }
+        }
+
+        let topic_name = publication.topic_name.clone();
+        let publication = ReceivedPublication::new(publication);
+        if let Some(journal) = self.journal.as_mut() {
+            journal.retained_stored(publication.clone());
+        }
+        let expiration = self.config.retained_messages().expiration();
+        let retained = RetainedPublication::new(publication, expiration, &self.retention);
+        if self.retained.insert(topic_name.clone(), retained).is_none() {
+            info!("new retained message for topic \"{}\"", topic_name);
+        }
+    }
+
+    /// Asks the broker to drop the connection for a client once the
+    /// current message has been processed.
+    fn schedule_drop_connection(&mut self, client_id: ClientId) {
+        let message = Message::Client(client_id, ClientEvent::DropConnection);
+        if let Err(e) = self.sender.try_send(message) {
+            warn!(message = "failed to schedule drop connection", error = %e);
+        }
+    }
+
     async fn process_message(
         &mut self,
         client_id: ClientId,
         event: ClientEvent,
     ) -> Result<(), Error> {
         debug!("incoming: {:?}", event);
+        self.session_changed(&client_id);
         let result = match event {
             ClientEvent::ConnReq(connreq) => self.process_connect(client_id, connreq).await,
             ClientEvent::ConnAck(_) => {
                 info!("broker received CONNACK, ignoring");
                 Ok(())
             }
-            ClientEvent::Disconnect(_) => self.process_disconnect(client_id).await,
+            ClientEvent::Disconnect(disconnect) => {
+                self.process_disconnect(client_id, disconnect).await
+            }
             ClientEvent::DropConnection => self.process_drop_connection(client_id).await,
             ClientEvent::CloseSession => self.process_close_session(client_id).await,
             ClientEvent::PingReq(ping) => self.process_ping_req(client_id, ping).await,
@@ -189,6 +567,7 @@ where
                 let ack = proto::ConnAck {
                     session_present: false,
                     return_code: proto::ConnectReturnCode::Refused($reason),
+                    properties: proto::Properties::default(),
                 };
 
                 debug!("sending connack with: {:?}", ack.return_code);
@@ -224,7 +603,9 @@ where
         // with a CONNACK return code 0x01 (unacceptable protocol level)
         // and then disconnect the Client if the Protocol Level is not supported
         // by the Server.
-        if connreq.connect().protocol_level != EXPECTED_PROTOCOL_LEVEL {
+        //
+        // Both MQTT 3.1.1 and MQTT 5.0 are supported.
+        if proto::ProtocolVersion::from_level(connreq.connect().protocol_level).is_none() {
             warn!(
                 "invalid protocol level received from client: {}",
                 connreq.connect().protocol_level
@@ -233,6 +614,20 @@ where
             return Ok(());
         }
 
+        // Enhanced authentication (AUTH exchanges) is not supported.
+        // A client asking for it gets a CONNACK with reason code 0x8C
+        // (Bad authentication method) and is disconnected.
+        if let Some(method) = connreq.connect().properties.authentication_method() {
+            warn!(
+                "unsupported authentication method received from client: {}",
+                method
+            );
+            refuse_connection!(proto::ConnectionRefusedReason::Other(
+                proto::ReasonCode::BAD_AUTHENTICATION_METHOD.0
+            ));
+            return Ok(());
+        }
+
         // [MQTT-3.1.4-3] - The Server MAY check that the contents of the CONNECT
         // Packet meet any further restrictions and MAY perform authentication
         // and authorization checks. If any of these checks fail, it SHOULD send an
@@ -284,6 +679,17 @@ where
             }
         }
 
+        if self.connection_quota_exceeded(&client_id, &auth_id) {
+            self.stats.connection_refused();
+            let reason = if connreq.connect().protocol_level == mqtt3::PROTOCOL_LEVEL_V5 {
+                proto::ConnectionRefusedReason::Other(proto::ReasonCode::QUOTA_EXCEEDED.0)
+            } else {
+                proto::ConnectionRefusedReason::ServerUnavailable
+            };
+            refuse_connection!(reason);
+            return Ok(());
+        }
+
         // Process the CONNECT packet after it has been validated
         // TODO - fix ConnAck return_code != accepted to not add session to sessions map
         match self.open_session(auth_id, connreq) {
@@ -294,9 +700,11 @@ where
                     .expect("session must exist");
                 session.send(ClientEvent::ConnAck(ack)).await?;
 
+                let sent = session.take_sent_messages();
                 for event in events {
                     session.send(event).await?;
                 }
+                self.stats.messages_sent(sent);
             }
             Err(SessionError::DuplicateSession(mut old_session, ack)) => {
                 // Drop the old connection
@@ -325,12 +733,66 @@ where
         Ok(())
     }
 
-    async fn process_disconnect(&mut self, client_id: ClientId) -> Result<(), Error> {
+    /// Checks whether another connection would exceed the connection quotas.
+    ///
+    /// A client reconnecting with the client id of a connected session takes
+    /// its connection over, so that connection is not counted.
+    fn connection_quota_exceeded(&self, client_id: &ClientId, auth_id: &AuthId) -> bool {
+        let limits = self.config.limits();
+        let connected = self
+            .sessions
+            .iter()
+            .filter(|(id, _)| *id != client_id)
+            .filter_map(|(_, session)| match session {
+                Session::Transient(connected) | Session::Persistent(connected) => {
+                    Some(connected.auth_id())
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(max_connections) = limits.max_connections() {
+            if connected.len() >= max_connections {
+                warn!(
+                    "maximum of {} connections reached. refusing connection of {}",
+                    max_connections, client_id
+                );
+                return true;
+            }
+        }
+
+        if let Some(max_connections) = limits.max_connections_per_identity() {
+            let count = connected.iter().filter(|id| **id == auth_id).count();
+            if count >= max_connections {
+                warn!(
+                    "maximum of {} connections for {} reached. refusing connection of {}",
+                    max_connections, auth_id, client_id
+                );
+                return true;
+            }
+        }
+
+        false
+    }
+
+    async fn process_disconnect(
+        &mut self,
+        client_id: ClientId,
+        disconnect: proto::Disconnect,
+    ) -> Result<(), Error> {
         debug!("handling disconnect...");
         if let Some(mut session) = self.close_session(&client_id) {
             session
-                .send(ClientEvent::Disconnect(proto::Disconnect))
+                .send(ClientEvent::Disconnect(proto::Disconnect::default()))
                 .await?;
+
+            // A MQTT 5.0 client can ask for its will to be published
+            // even though it disconnects gracefully.
+            if disconnect.reason_code == proto::ReasonCode::DISCONNECT_WITH_WILL_MESSAGE {
+                if let Some(will) = session.into_will() {
+                    self.publish_all(will).await?;
+                }
+            }
         } else {
             debug!("no session for {}", client_id);
         }
@@ -394,36 +856,63 @@ where
         client_id: ClientId,
         sub: proto::Subscribe,
     ) -> Result<(), Error> {
-        let subscriptions = if let Some(session) = self.sessions.get_mut(&client_id) {
-            let (suback, subscriptions) = subscribe(&self.authorizer, session, sub.clone()).await?;
+        let retained_subscriptions = if let Some(session) = self.sessions.get_mut(&client_id) {
+            let (suback, subscriptions, retained_subscriptions) =
+                subscribe(&self.authorizer, &mut self.stats, session, sub.clone()).await?;
+            for subscription in &subscriptions {
+                self.subscriptions
+                    .insert(subscription.filter(), client_id.clone());
+            }
             session.send(ClientEvent::SubAck(suback)).await?;
-            subscriptions
+            retained_subscriptions
         } else {
             debug!("no session for {}", client_id);
             return Ok(());
         };
 
         // Handle retained messages
+        self.expire_retained();
+        let now = SystemTime::now();
+        let matches = |topic_name: &str| {
+            // Retained messages are not sent to shared subscriptions.
+            retained_subscriptions
+                .iter()
+                .filter(|sub| sub.filter().share().is_none())
+                .any(|sub| sub.filter().matches(topic_name))
+        };
         let publications = self
             .retained
             .values()
-            .filter(|p| {
-                subscriptions
-                    .iter()
-                    .any(|sub| sub.filter().matches(&p.topic_name))
-            })
-            .cloned()
+            .filter(|retained| matches(&retained.publication.topic_name))
+            .map(|retained| retained.to_received().into_forwarded(now))
+            .chain(
+                self.sys_retained
+                    .values()
+                    .filter(|publication| matches(&publication.topic_name))
+                    .cloned(),
+            )
             .collect::<Vec<proto::Publication>>();
 
+        let mut queue_full = false;
         if let Some(session) = self.sessions.get_mut(&client_id) {
             for mut publication in publications {
                 publication.retain = true;
-                publish_to(&self.authorizer, session, &publication).await?;
+                match publish_to(&self.authorizer, &mut self.stats, session, &publication).await {
+                    Err(Error::SessionQueueFull) => {
+                        queue_full = true;
+                        break;
+                    }
+                    result => result?,
+                }
             }
         } else {
             debug!("no session for {}", client_id);
         }
 
+        if queue_full {
+            self.schedule_drop_connection(client_id);
+        }
+
         Ok(())
     }
 
@@ -432,15 +921,17 @@ where
         client_id: ClientId,
         unsubscribe: proto::Unsubscribe,
     ) -> Result<(), Error> {
-        match self.get_session_mut(&client_id) {
-            Ok(session) => {
-                let unsuback = session.unsubscribe(&unsubscribe)?;
-                session.send(ClientEvent::UnsubAck(unsuback)).await
-            }
-            Err(NoSessionError) => {
-                debug!("no session for {}", client_id);
-                Ok(())
+        if let Some(session) = self.sessions.get_mut(&client_id) {
+            let unsuback = session.unsubscribe(&unsubscribe)?;
+            for filter in &unsubscribe.unsubscribe_from {
+                if let Ok(filter) = filter.parse::<TopicFilter>() {
+                    self.subscriptions.remove(&filter, &client_id);
+                }
             }
+            session.send(ClientEvent::UnsubAck(unsuback)).await
+        } else {
+            debug!("no session for {}", client_id);
+            Ok(())
         }
     }
 
@@ -449,6 +940,7 @@ where
         client_id: ClientId,
         publish: proto::Publish,
     ) -> Result<(), Error> {
+        self.stats.message_received();
         let operation = Operation::new_publish(publish.clone());
         if let Some(session) = self.sessions.get_mut(&client_id) {
             let activity = Activity::new(session.auth_id()?.clone(), client_id.clone(), operation);
@@ -462,7 +954,8 @@ where
                     }
 
                     if let Some(publication) = maybe_publication {
-                        self.publish_all(publication).await?
+                        self.publish_all(publication).await?;
+                        self.count_unsaved_message().await;
                     }
                 }
                 Ok(false) => {
@@ -489,18 +982,23 @@ where
         client_id: ClientId,
         puback: proto::PubAck,
     ) -> Result<(), Error> {
-        match self.get_session_mut(&client_id) {
-            Ok(session) => {
-                if let Some(event) = session.handle_puback(&puback)? {
-                    session.send(event).await?
+        let sent = match self.get_session_mut(&client_id) {
+            Ok(session) => match session.handle_puback(&puback)? {
+                Some(event) => {
+                    let sent = session.take_sent_messages();
+                    session.send(event).await?;
+                    sent
                 }
-                Ok(())
-            }
+                None => 0,
+            },
             Err(NoSessionError) => {
                 debug!("no session for {}", client_id);
-                Ok(())
+                return Ok(());
             }
-        }
+        };
+
+        self.stats.messages_sent(sent);
+        Ok(())
     }
 
     async fn process_puback0(
@@ -508,18 +1006,23 @@ where
         client_id: ClientId,
         id: proto::PacketIdentifier,
     ) -> Result<(), Error> {
-        match self.get_session_mut(&client_id) {
-            Ok(session) => {
-                if let Some(event) = session.handle_puback0(id)? {
-                    session.send(event).await?
+        let sent = match self.get_session_mut(&client_id) {
+            Ok(session) => match session.handle_puback0(id)? {
+                Some(event) => {
+                    let sent = session.take_sent_messages();
+                    session.send(event).await?;
+                    sent
                 }
-                Ok(())
-            }
+                None => 0,
+            },
             Err(NoSessionError) => {
                 debug!("no session for {}", client_id);
-                Ok(())
+                return Ok(());
             }
-        }
+        };
+
+        self.stats.messages_sent(sent);
+        Ok(())
     }
 
     async fn process_pubrec(
@@ -551,7 +1054,17 @@ where
                 let packet_identifier = pubrel.packet_identifier;
                 let maybe_publication = session.handle_pubrel(&pubrel)?;
 
-                let pubcomp = proto::PubComp { packet_identifier };
+                let reason_code = if maybe_publication.is_some() {
+                    proto::ReasonCode::SUCCESS
+                } else {
+                    proto::ReasonCode::PACKET_IDENTIFIER_NOT_FOUND
+                };
+
+                let pubcomp = proto::PubComp {
+                    packet_identifier,
+                    reason_code,
+                    properties: proto::Properties::default(),
+                };
                 session.send(ClientEvent::PubComp(pubcomp)).await?;
                 maybe_publication
             }
@@ -572,18 +1085,23 @@ where
         client_id: ClientId,
         pubcomp: proto::PubComp,
     ) -> Result<(), Error> {
-        match self.get_session_mut(&client_id) {
-            Ok(session) => {
-                if let Some(event) = session.handle_pubcomp(&pubcomp)? {
-                    session.send(event).await?
+        let sent = match self.get_session_mut(&client_id) {
+            Ok(session) => match session.handle_pubcomp(&pubcomp)? {
+                Some(event) => {
+                    let sent = session.take_sent_messages();
+                    session.send(event).await?;
+                    sent
                 }
-                Ok(())
-            }
+                None => 0,
+            },
             Err(NoSessionError) => {
                 debug!("no session for {}", client_id);
-                Ok(())
+                return Ok(());
             }
-        }
+        };
+
+        self.stats.messages_sent(sent);
+        Ok(())
     }
 
     fn get_session_mut(&mut self, client_id: &ClientId) -> Result<&mut Session, NoSessionError> {
@@ -598,13 +1116,24 @@ where
         connreq: ConnReq,
     ) -> Result<(proto::ConnAck, Vec<ClientEvent>), SessionError> {
         let client_id = connreq.client_id().clone();
+        let config = self.session_config_for(connreq.connect());
+        let properties = connack_properties(&connreq, &config);
+        self.session_changed(&client_id);
+
+        if let Some(Session::Offline(offline)) = self.sessions.get(&client_id) {
+            if offline.is_expired() {
+                info!("offline session for {} expired", client_id);
+                unindex_subscriptions(&mut self.subscriptions, offline.state());
+                self.sessions.remove(&client_id);
+            }
+        }
 
         match self.sessions.remove(&client_id) {
             Some(Session::Transient(current_connected)) => {
-                self.open_session_connected(auth_id, connreq, current_connected)
+                self.open_session_connected(auth_id, connreq, current_connected, config, properties)
             }
             Some(Session::Persistent(current_connected)) => {
-                self.open_session_connected(auth_id, connreq, current_connected)
+                self.open_session_connected(auth_id, connreq, current_connected, config, properties)
             }
             Some(Session::Offline(offline)) => {
                 debug!("found an offline session for {}", client_id);
@@ -615,11 +1144,12 @@ where
                         let (state, events) = offline
                             .into_online()
                             .map_err(|_| SessionError::PacketIdentifiersExhausted)?;
-                        let new_session = Session::new_persistent(auth_id, connreq, state);
+                        let new_session = Session::new_persistent(auth_id, connreq, state, config);
                         (new_session, events, true)
                     } else {
                         info!("cleaning offline session for {}", client_id);
-                        let new_session = Session::new_transient(auth_id, connreq);
+                        unindex_subscriptions(&mut self.subscriptions, offline.state());
+                        let new_session = new_clean_session(auth_id, connreq, config);
                         (new_session, vec![], false)
                     };
 
@@ -628,6 +1158,7 @@ where
                 let ack = proto::ConnAck {
                     session_present,
                     return_code: proto::ConnectReturnCode::Accepted,
+                    properties,
                 };
 
                 Ok((ack, events))
@@ -637,22 +1168,15 @@ where
             )),
             None => {
                 // No session present - create a new one.
-                let new_session = if let proto::ClientId::IdWithExistingSession(_) =
-                    connreq.connect().client_id
-                {
-                    info!("creating new persistent session for {}", client_id);
-                    let state = SessionState::new(client_id.clone());
-                    Session::new_persistent(auth_id, connreq, state)
-                } else {
-                    info!("creating new transient session for {}", client_id);
-                    Session::new_transient(auth_id, connreq)
-                };
+                info!("creating new session for {}", client_id);
+                let new_session = new_clean_session(auth_id, connreq, config);
 
                 self.sessions.insert(client_id.clone(), new_session);
 
                 let ack = proto::ConnAck {
                     session_present: false,
                     return_code: proto::ConnectReturnCode::Accepted,
+                    properties,
                 };
                 let events = vec![];
 
@@ -666,6 +1190,8 @@ where
         auth_id: AuthId,
         connreq: ConnReq,
         current_connected: ConnectedSession,
+        config: SessionConfig,
+        properties: proto::Properties,
     ) -> Result<(proto::ConnAck, Vec<ClientEvent>), SessionError> {
         if current_connected.handle() == connreq.handle() {
             // [MQTT-3.1.0-2] - The Server MUST process a second CONNECT Packet
@@ -698,11 +1224,12 @@ where
                         "moving persistent session to this connection for {}",
                         client_id
                     );
-                    let new_session = Session::new_persistent(auth_id, connreq, state);
+                    let new_session = Session::new_persistent(auth_id, connreq, state, config);
                     (new_session, true)
                 } else {
                     info!("cleaning session for {}", client_id);
-                    let new_session = Session::new_transient(auth_id, connreq);
+                    unindex_subscriptions(&mut self.subscriptions, &state);
+                    let new_session = new_clean_session(auth_id, connreq, config);
                     (new_session, false)
                 };
 
@@ -710,6 +1237,7 @@ where
             let ack = proto::ConnAck {
                 session_present,
                 return_code: proto::ConnectReturnCode::Accepted,
+                properties,
             };
 
             Err(SessionError::DuplicateSession(old_session, ack))
@@ -717,10 +1245,12 @@ where
     }
 
     fn close_session(&mut self, client_id: &ClientId) -> Option<Session> {
+        self.session_changed(client_id);
         match self.sessions.remove(client_id) {
             Some(Session::Transient(connected)) => {
                 info!("closing transient session for {}", client_id);
-                let (auth_id, _state, will, handle) = connected.into_parts();
+                let (auth_id, state, will, handle) = connected.into_parts();
+                unindex_subscriptions(&mut self.subscriptions, &state);
                 Some(Session::new_disconnecting(
                     auth_id,
                     client_id.clone(),
@@ -733,10 +1263,18 @@ where
                 // Return a disconnecting session to allow a disconnect
                 // to be sent on the connection
 
-                info!("moving persistent session to offline for {}", client_id);
+                let config = connected.config().clone();
                 let (auth_id, state, will, handle) = connected.into_parts();
-                let new_session = Session::new_offline(state);
-                self.sessions.insert(client_id.clone(), new_session);
+                if config.ends_with_connection() {
+                    // A MQTT 5.0 session with a zero session expiry interval
+                    // ends as soon as the network connection is closed.
+                    info!("closing expired persistent session for {}", client_id);
+                    unindex_subscriptions(&mut self.subscriptions, &state);
+                } else {
+                    info!("moving persistent session to offline for {}", client_id);
+                    let new_session = Session::new_offline(state, config);
+                    self.sessions.insert(client_id.clone(), new_session);
+                }
                 Some(Session::new_disconnecting(
                     auth_id,
                     client_id.clone(),
@@ -754,7 +1292,7 @@ where
         }
     }
 
-    async fn publish_all(&mut self, mut publication: proto::Publication) -> Result<(), Error> {
+    async fn publish_all(&mut self, publication: proto::Publication) -> Result<(), Error> {
         if publication.retain {
             // [MQTT-3.3.1-6]. If the Server receives a QoS 0 message with the
             // RETAIN flag set to 1 it MUST discard any message previously
@@ -769,55 +1307,199 @@ where
                     "removing retained message for topic \"{}\"",
                     publication.topic_name
                 );
-                self.retained.remove(&publication.topic_name);
-            } else {
-                let maybe_retained = self
-                    .retained
-                    .insert(publication.topic_name.to_owned(), publication.clone());
-                if maybe_retained.is_none() {
-                    info!(
-                        "new retained message for topic \"{}\"",
-                        publication.topic_name
-                    );
+                let removed = self.retained.remove(&publication.topic_name);
+                if let (Some(journal), Some(_)) = (self.journal.as_mut(), removed) {
+                    journal.retained_removed(publication.topic_name.clone());
                 }
+            } else {
+                self.store_retained(publication.clone());
             }
         }
 
+        self.publish_to_subscribers(publication).await
+    }
+
+    async fn publish_to_subscribers(
+        &mut self,
+        mut publication: proto::Publication,
+    ) -> Result<(), Error> {
         // Set the retain to false. This should only be set true
         // when sending due to a new subscription.
         //
         // This will not happen here.
         publication.retain = false;
 
-        for session in self.sessions.values_mut() {
-            if let Err(e) = publish_to(&self.authorizer, session, &publication).await {
-                warn!(message = "error processing message", error = %e);
+        let client_ids = self.subscriptions.matches(&publication.topic_name);
+
+        // Only one member of each matching shared subscription group
+        // gets the publication. A client picked for a group gets it on top
+        // of any copy due to its other subscriptions. Groups without a
+        // connected member drop the publication.
+        let sessions = &self.sessions;
+        let mut unpicked = 0;
+        let shared_client_ids: HashSet<_> = self
+            .subscriptions
+            .matching_groups(&publication.topic_name)
+            .into_iter()
+            .filter_map(|group| {
+                let picked = group
+                    .pick(|client_id| shared_delivery_rank(sessions.get(client_id), &publication));
+                if picked.is_none() {
+                    unpicked += 1;
+                }
+                picked
+            })
+            .collect();
+
+        if unpicked > 0 {
+            debug!(
+                "no connected member of {} shared subscription groups for topic \"{}\". dropping message",
+                unpicked, publication.topic_name
+            );
+            self.stats.messages_dropped(unpicked);
+        }
+
+        let mut queue_full = vec![];
+        for client_id in client_ids.into_iter().chain(shared_client_ids) {
+            self.session_changed(&client_id);
+            if let Some(session) = self.sessions.get_mut(&client_id) {
+                match publish_to(&self.authorizer, &mut self.stats, session, &publication).await {
+                    Ok(()) => (),
+                    Err(Error::SessionQueueFull) => queue_full.push(client_id),
+                    Err(e) => warn!(message = "error processing message", error = %e),
+                }
             }
         }
 
+        // Sessions configured to disconnect when their queue is full
+        for client_id in queue_full {
+            self.schedule_drop_connection(client_id);
+        }
+
         Ok(())
     }
 }
 
-async fn subscribe<Z>(
-    authorizer: &Z,
-    session: &mut Session,
-    subscribe: proto::Subscribe,
-) -> Result<(proto::SubAck, Vec<Subscription>), Error>
-where
-    Z: Authorizer,
-{
-    let auth_id = session.auth_id()?.clone();
-    let client_id = session.client_id().clone();
+/// Creates a session with an empty state.
+///
+/// MQTT 5.0 clients asking for a non-zero session expiry interval get a
+/// persistent session even when starting clean.
+fn new_clean_session(auth_id: AuthId, connreq: ConnReq, config: SessionConfig) -> Session {
+    let connect = connreq.connect();
+    let persistent = match connect.client_id {
+        proto::ClientId::IdWithExistingSession(_) => true,
+        _ => {
+            connect.protocol_level == mqtt3::PROTOCOL_LEVEL_V5
+                && config.expiration() > Duration::default()
+        }
+    };
 
-    let mut subscriptions = Vec::with_capacity(subscribe.subscribe_to.len());
-    let mut acks = Vec::with_capacity(subscribe.subscribe_to.len());
+    if persistent {
+        let state = SessionState::new(connreq.client_id().clone());
+        Session::new_persistent(auth_id, connreq, state, config)
+    } else {
+        Session::new_transient(auth_id, connreq, config)
+    }
+}
 
-    let auth_results = subscribe
-        .subscribe_to
-        .into_iter()
-        .map(|subscribe_to| async {
-            let operation = Operation::new_subscribe(subscribe_to.clone());
+/// Builds the CONNACK properties reported back to a MQTT 5.0 client.
+fn connack_properties(connreq: &ConnReq, config: &SessionConfig) -> proto::Properties {
+    let connect = connreq.connect();
+    let mut properties = proto::Properties::new();
+    if connect.protocol_level != mqtt3::PROTOCOL_LEVEL_V5 {
+        return properties;
+    }
+
+    if let proto::ClientId::ServerGenerated = connect.client_id {
+        properties.push(proto::Property::AssignedClientIdentifier(
+            connreq.client_id().as_str().to_string(),
+        ));
+    }
+
+    properties.push(proto::Property::TopicAliasMaximum(TOPIC_ALIAS_MAXIMUM));
+    properties.push(proto::Property::SubscriptionIdentifierAvailable(0));
+
+    // Let the client know when the session expiry interval it asked for
+    // was lowered to the one configured on the broker.
+    if let Some(interval) = connect.properties.session_expiry_interval() {
+        if let Ok(expiration) = u32::try_from(config.expiration().as_secs()) {
+            if expiration < interval {
+                properties.push(proto::Property::SessionExpiryInterval(expiration));
+            }
+        }
+    }
+
+    properties
+}
+
+/// Ranks how well a session can take a publication delivered to its shared
+/// subscription group.
+///
+/// Only connected sessions are eligible. Those with room in their inflight
+/// window come first, then those with room in their queue, then those whose
+/// queue is full and which apply their `when_full` action to the publication.
+/// Offline sessions are never picked, so that publications do not pile up in
+/// the queue of a member which may not come back while others are online.
+fn shared_delivery_rank(session: Option<&Session>, publication: &proto::Publication) -> Option<u8> {
+    match session {
+        Some(Session::Transient(connected)) | Some(Session::Persistent(connected)) => {
+            if connected.can_send() {
+                Some(2)
+            } else if connected.can_queue(publication.payload.len() as u64) {
+                Some(1)
+            } else {
+                Some(0)
+            }
+        }
+        Some(Session::Offline(_)) | Some(Session::Disconnecting(_)) | None => None,
+    }
+}
+
+fn index_subscriptions(index: &mut TopicTrie, state: &SessionState) {
+    for subscription in state.subscriptions().values() {
+        index.insert(subscription.filter(), state.client_id().clone());
+    }
+}
+
+fn unindex_subscriptions(index: &mut TopicTrie, state: &SessionState) {
+    for subscription in state.subscriptions().values() {
+        index.remove(subscription.filter(), state.client_id());
+    }
+}
+
+async fn subscribe<Z>(
+    authorizer: &Z,
+    stats: &mut StatsTracker,
+    session: &mut Session,
+    subscribe: proto::Subscribe,
+) -> Result<(proto::SubAck, Vec<Subscription>, Vec<Subscription>), Error>
+where
+    Z: Authorizer,
+{
+    let auth_id = session.auth_id()?.clone();
+    let client_id = session.client_id().clone();
+
+    // Subscription identifiers are not supported, which the broker tells
+    // MQTT 5.0 clients when they connect.
+    if subscribe.properties.subscription_identifier().is_some() {
+        warn!("client {} asked for a subscription identifier", client_id);
+        let reason_code = proto::ReasonCode::SUBSCRIPTION_IDENTIFIERS_NOT_SUPPORTED;
+        let suback = proto::SubAck {
+            packet_identifier: subscribe.packet_identifier,
+            qos: vec![proto::SubAckQos::Failure(reason_code); subscribe.subscribe_to.len()],
+        };
+        return Ok((suback, vec![], vec![]));
+    }
+
+    let mut subscriptions = Vec::with_capacity(subscribe.subscribe_to.len());
+    let mut retained_subscriptions = Vec::with_capacity(subscribe.subscribe_to.len());
+    let mut acks = Vec::with_capacity(subscribe.subscribe_to.len());
+
+    let auth_results = subscribe
+        .subscribe_to
+        .into_iter()
+        .map(|subscribe_to| async {
+            let operation = Operation::new_subscribe(subscribe_to.clone());
             let activity = Activity::new(auth_id.clone(), client_id.clone(), operation);
             let auth = authorizer.authorize(activity).await;
             auth.map(|auth| (auth, subscribe_to))
@@ -825,18 +1507,43 @@ where
 
     for auth in future::join_all(auth_results).await {
         let ack_qos = match auth {
-            Ok((true, subscribe_to)) => match session.subscribe_to(subscribe_to) {
-                Ok((qos, subscription)) => {
-                    if let Some(subscription) = subscription {
-                        subscriptions.push(subscription);
+            Ok((true, subscribe_to)) => {
+                let send_retained = match subscribe_to.options.retain_handling {
+                    proto::RetainHandling::SendAtSubscribe => true,
+                    proto::RetainHandling::SendAtNewSubscribe => {
+                        session.state().map_or(true, |state| {
+                            !state
+                                .subscriptions()
+                                .contains_key(&subscribe_to.topic_filter)
+                        })
+                    }
+                    proto::RetainHandling::DoNotSend => false,
+                };
+
+                match session.subscribe_to(subscribe_to) {
+                    Ok((qos, subscription)) => {
+                        if let Some(subscription) = subscription {
+                            if send_retained {
+                                retained_subscriptions.push(subscription.clone());
+                            }
+                            subscriptions.push(subscription);
+                        }
+                        qos
+                    }
+                    Err(Error::SubscriptionQuotaExceeded) => {
+                        warn!(
+                            "client {} reached its maximum number of subscriptions",
+                            client_id
+                        );
+                        stats.subscription_refused();
+                        proto::SubAckQos::Failure(proto::ReasonCode::QUOTA_EXCEEDED)
+                    }
+                    Err(e) => {
+                        warn!(message="error subscribing to a topic: {}", error = %e);
+                        proto::SubAckQos::Failure(proto::ReasonCode::UNSPECIFIED_ERROR)
                     }
-                    qos
-                }
-                Err(e) => {
-                    warn!(message="error subscribing to a topic: {}", error = %e);
-                    proto::SubAckQos::Failure
                 }
-            },
+            }
             Ok((false, subscribe_to)) => {
                 debug!(
                     "client {} not allowed to subscribe to topic {} qos {}",
@@ -844,11 +1551,11 @@ where
                     subscribe_to.topic_filter,
                     u8::from(subscribe_to.qos)
                 );
-                proto::SubAckQos::Failure
+                proto::SubAckQos::Failure(proto::ReasonCode::NOT_AUTHORIZED)
             }
             Err(e) => {
                 warn!(message="error authorizing client subscription: {}", error = %e);
-                proto::SubAckQos::Failure
+                proto::SubAckQos::Failure(proto::ReasonCode::UNSPECIFIED_ERROR)
             }
         };
         acks.push(ack_qos);
@@ -859,11 +1566,12 @@ where
         qos: acks,
     };
 
-    Ok((suback, subscriptions))
+    Ok((suback, subscriptions, retained_subscriptions))
 }
 
 async fn publish_to<Z>(
     authorizer: &Z,
+    stats: &mut StatsTracker,
     session: &mut Session,
     publication: &proto::Publication,
 ) -> Result<(), Error>
@@ -878,8 +1586,12 @@ where
 
     match authorizer.authorize(activity).await {
         Ok(true) => {
-            if let Some(event) = session.publish_to(&publication)? {
-                session.send(event).await?
+            let result = session.publish_to(&publication);
+            stats.messages_dropped(session.take_dropped_messages());
+            if let Some(event) = result? {
+                let sent = session.take_sent_messages();
+                session.send(event).await?;
+                stats.messages_sent(sent);
             }
         }
         Ok(false) => {
@@ -895,18 +1607,62 @@ where
     Ok(())
 }
 
+#[derive(Debug)]
+struct RetainedPublication {
+    publication: proto::Publication,
+    received_at: SystemTime,
+    expires_at: Option<SystemTime>,
+}
+
+impl RetainedPublication {
+    /// Keeps a retained message until either `expiration` or the time to live
+    /// given by the retention policy runs out.
+    fn new(
+        publication: ReceivedPublication,
+        expiration: Duration,
+        retention: &RetentionPolicy,
+    ) -> Self {
+        let expires_at = match (
+            publication.received_at().checked_add(expiration),
+            retention.expires_at(&publication),
+        ) {
+            (Some(expires_at), Some(ttl_expires_at)) => Some(cmp::min(expires_at, ttl_expires_at)),
+            (expires_at, ttl_expires_at) => expires_at.or(ttl_expires_at),
+        };
+
+        let (publication, received_at) = publication.into_parts();
+        Self {
+            publication,
+            received_at,
+            expires_at,
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        self.expires_at
+            .map_or(false, |expires_at| expires_at <= SystemTime::now())
+    }
+
+    fn to_received(&self) -> ReceivedPublication {
+        ReceivedPublication::from_parts(self.publication.clone(), self.received_at)
+    }
+}
+
 #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct BrokerState {
-    retained: HashMap<String, proto::Publication>,
+    retained: HashMap<String, ReceivedPublication>,
     sessions: Vec<SessionState>,
 }
 
 impl BrokerState {
-    pub fn new(retained: HashMap<String, proto::Publication>, sessions: Vec<SessionState>) -> Self {
+    pub fn new(
+        retained: HashMap<String, ReceivedPublication>,
+        sessions: Vec<SessionState>,
+    ) -> Self {
         Self { retained, sessions }
     }
 
-    pub fn into_parts(self) -> (HashMap<String, proto::Publication>, Vec<SessionState>) {
+    pub fn into_parts(self) -> (HashMap<String, ReceivedPublication>, Vec<SessionState>) {
         (self.retained, self.sessions)
     }
 }
@@ -915,6 +1671,8 @@ pub struct BrokerBuilder<N, Z> {
     state: Option<BrokerState>,
     authenticator: N,
     authorizer: Z,
+    config: BrokerConfig,
+    snapshot_handle: Option<StateSnapshotHandle>,
 }
 
 impl Default for BrokerBuilder<DefaultAuthenticator, DefaultAuthorizer> {
@@ -923,6 +1681,8 @@ impl Default for BrokerBuilder<DefaultAuthenticator, DefaultAuthorizer> {
             state: None,
             authenticator: DefaultAuthenticator,
             authorizer: DefaultAuthorizer,
+            config: BrokerConfig::default(),
+            snapshot_handle: None,
         }
     }
 }
@@ -940,6 +1700,8 @@ where
             state: self.state,
             authenticator,
             authorizer: self.authorizer,
+            config: self.config,
+            snapshot_handle: self.snapshot_handle,
         }
     }
 
@@ -951,6 +1713,8 @@ where
             state: self.state,
             authenticator: self.authenticator,
             authorizer,
+            config: self.config,
+            snapshot_handle: self.snapshot_handle,
         }
     }
 
@@ -959,28 +1723,78 @@ where
         self
     }
 
+    pub fn config(mut self, config: BrokerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sets the snapshotter the broker sends its state to when too many
+    /// published messages have not been persisted yet.
+    pub fn snapshot_handle(mut self, snapshot_handle: StateSnapshotHandle) -> Self {
+        self.snapshot_handle = Some(snapshot_handle);
+        self
+    }
+
     pub fn build(self) -> Broker<N, Z> {
-        let (retained, sessions) = match self.state {
+        let session_config = SessionConfig::from(&self.config);
+        let expiration = self.config.retained_messages().expiration();
+        let retention = RetentionPolicy::from(self.config.message_expiration());
+
+        let mut subscriptions = TopicTrie::new();
+        let (retained, mut sessions) = match self.state {
             Some(state) => {
+                let retained = state
+                    .retained
+                    .into_iter()
+                    .map(|(topic, p)| (topic, RetainedPublication::new(p, expiration, &retention)))
+                    .collect::<HashMap<String, RetainedPublication>>();
                 let sessions = state
                     .sessions
                     .into_iter()
-                    .map(|s| (s.client_id().clone(), Session::new_offline(s)))
+                    .map(|s| {
+                        index_subscriptions(&mut subscriptions, &s);
+                        (
+                            s.client_id().clone(),
+                            Session::new_offline(s, session_config.clone()),
+                        )
+                    })
                     .collect::<HashMap<ClientId, Session>>();
-                (state.retained, sessions)
+                (retained, sessions)
             }
             None => (HashMap::default(), HashMap::default()),
         };
 
+        // State changes are only journaled when the state is persisted.
+        let journal = match (&self.snapshot_handle, self.config.persistence()) {
+            (Some(_), Some(_)) => Some(Journal::new(&mut sessions)),
+            _ => None,
+        };
+
+        let stats = StatsTracker::new();
+        let publish_limits = PublishLimits::new(
+            self.config.limits().publish_rate().cloned(),
+            stats.rate_limits(),
+        );
+
         let (sender, messages) = mpsc::channel(1024);
 
         Broker {
             sender,
             messages,
             sessions,
+            subscriptions,
             retained,
+            sys_retained: HashMap::new(),
             authenticator: self.authenticator,
             authorizer: self.authorizer,
+            config: self.config,
+            retention,
+            last_expiration_check: Instant::now(),
+            stats,
+            snapshot_handle: self.snapshot_handle,
+            unsaved_messages: 0,
+            journal,
+            publish_limits,
         }
     }
 }
@@ -1019,16 +1833,21 @@ pub(crate) mod tests {
     use tokio::sync::mpsc::error::TryRecvError;
     use uuid::Uuid;
 
+    use serde_json::json;
+
+    use crate::configuration::tests::config_with;
     use crate::session::tests::*;
     use crate::tests::*;
     use crate::{
         auth::{AuthenticateError, AuthorizeError},
-        AuthId, ConnectionHandle,
+        persist::{Persist, PersistError},
+        wal::{replay, SessionChange, StateChange},
+        AuthId, ConnectionHandle, Publish, Snapshotter,
     };
 
     prop_compose! {
         pub fn arb_broker_state()(
-            retained in hash_map(arb_topic(), arb_publication(), 0..20),
+            retained in hash_map(arb_topic(), arb_received_publication(), 0..20),
             sessions in vec(arb_session_state(), 0..10),
         ) -> BrokerState {
             BrokerState {
@@ -1053,6 +1872,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: crate::PROTOCOL_NAME.to_string(),
             protocol_level: crate::PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         }
     }
 
@@ -1065,6 +1885,20 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: crate::PROTOCOL_NAME.to_string(),
             protocol_level: crate::PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
+        }
+    }
+
+    fn v5_connect(client_id: proto::ClientId, properties: Vec<proto::Property>) -> proto::Connect {
+        proto::Connect {
+            username: None,
+            password: None,
+            will: None,
+            client_id,
+            keep_alive: Duration::default(),
+            protocol_name: crate::PROTOCOL_NAME.to_string(),
+            protocol_level: mqtt3::PROTOCOL_LEVEL_V5,
+            properties: properties.into_iter().collect(),
         }
     }
 
@@ -1087,6 +1921,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: crate::PROTOCOL_NAME.to_string(),
             protocol_level: crate::PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
         let connect2 = proto::Connect {
             username: None,
@@ -1096,6 +1931,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: crate::PROTOCOL_NAME.to_string(),
             protocol_level: crate::PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
         let id = Uuid::new_v4();
         let (tx1, mut rx1) = mpsc::channel(128);
@@ -1150,6 +1986,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: crate::PROTOCOL_NAME.to_string(),
             protocol_level: crate::PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
         let connect2 = proto::Connect {
             username: None,
@@ -1159,6 +1996,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: crate::PROTOCOL_NAME.to_string(),
             protocol_level: crate::PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
         let (tx1, mut rx1) = mpsc::channel(128);
         let (tx2, mut rx2) = mpsc::channel(128);
@@ -1218,6 +2056,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: "AMQP".to_string(),
             protocol_level: crate::PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
         let (tx1, mut rx1) = mpsc::channel(128);
         let conn1 = ConnectionHandle::from_sender(tx1);
@@ -1257,6 +2096,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: crate::PROTOCOL_NAME.to_string(),
             protocol_level: 0x3,
+            properties: proto::Properties::default(),
         };
         let (tx1, mut rx1) = mpsc::channel(128);
         let conn1 = ConnectionHandle::from_sender(tx1);
@@ -1306,6 +2146,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: crate::PROTOCOL_NAME.to_string(),
             protocol_level: crate::PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
 
         let (tx1, mut rx1) = mpsc::channel(128);
@@ -1349,6 +2190,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: crate::PROTOCOL_NAME.to_string(),
             protocol_level: crate::PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
 
         let (tx1, mut rx1) = mpsc::channel(128);
@@ -1399,6 +2241,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: crate::PROTOCOL_NAME.to_string(),
             protocol_level: crate::PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
 
         let (tx1, mut rx1) = mpsc::channel(128);
@@ -1449,6 +2292,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: crate::PROTOCOL_NAME.to_string(),
             protocol_level: crate::PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
 
         let (tx1, mut rx1) = mpsc::channel(128);
@@ -1499,6 +2343,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: crate::PROTOCOL_NAME.to_string(),
             protocol_level: crate::PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         };
 
         let (tx1, mut rx1) = mpsc::channel(128);
@@ -1815,6 +2660,175 @@ pub(crate) mod tests {
         assert_matches!(broker.sessions[&client_id], Session::Transient(_));
     }
 
+    #[test]
+    fn test_add_session_v5_clean_start_with_expiry_persistent() {
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        let id = "id1".to_string();
+        let client_id = ClientId::from(id.clone());
+        let connect1 = v5_connect(
+            proto::ClientId::IdWithCleanSession(id),
+            vec![proto::Property::SessionExpiryInterval(60)],
+        );
+        let req1 = ConnReq::new(client_id.clone(), connect1, None, connection_handle());
+
+        let (ack, _) = broker.open_session(AuthId::Anonymous, req1).unwrap();
+        assert!(!ack.session_present);
+        assert_matches!(broker.sessions[&client_id], Session::Persistent(_));
+
+        broker.close_session(&client_id);
+        assert_matches!(broker.sessions[&client_id], Session::Offline(_));
+    }
+
+    #[test]
+    fn test_add_session_v5_zero_expiry_not_kept_offline() {
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        let id = "id1".to_string();
+        let client_id = ClientId::from(id.clone());
+        let connect1 = v5_connect(proto::ClientId::IdWithExistingSession(id), vec![]);
+        let req1 = ConnReq::new(client_id.clone(), connect1, None, connection_handle());
+
+        broker.open_session(AuthId::Anonymous, req1).unwrap();
+        assert_matches!(broker.sessions[&client_id], Session::Persistent(_));
+
+        let old_session = broker.close_session(&client_id);
+        assert_matches!(old_session, Some(Session::Disconnecting(_)));
+        assert_eq!(0, broker.sessions.len());
+    }
+
+    #[test]
+    fn test_add_session_v5_connack_properties() {
+        let config = config_with(&json!({ "session": { "expiration": "1m" } }));
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .config(config)
+            .build();
+
+        let client_id = ClientId::from("generated".to_string());
+        let connect1 = v5_connect(
+            proto::ClientId::ServerGenerated,
+            vec![proto::Property::SessionExpiryInterval(3600)],
+        );
+        let req1 = ConnReq::new(client_id, connect1, None, connection_handle());
+
+        let (ack, _) = broker.open_session(AuthId::Anonymous, req1).unwrap();
+        let properties = ack.properties.iter().cloned().collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                proto::Property::AssignedClientIdentifier("generated".to_string()),
+                proto::Property::TopicAliasMaximum(TOPIC_ALIAS_MAXIMUM),
+                proto::Property::SubscriptionIdentifierAvailable(0),
+                proto::Property::SessionExpiryInterval(60),
+            ],
+            properties
+        );
+    }
+
+    #[test]
+    fn test_add_session_v311_no_connack_properties() {
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        let id = "id1".to_string();
+        let client_id = ClientId::from(id.clone());
+        let req1 = ConnReq::new(client_id, transient_connect(id), None, connection_handle());
+
+        let (ack, _) = broker.open_session(AuthId::Anonymous, req1).unwrap();
+        assert!(ack.properties.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_connect_bad_authentication_method() {
+        let broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        let mut broker_handle = broker.handle();
+        tokio::spawn(broker.run().map(drop));
+
+        let connect1 = v5_connect(
+            proto::ClientId::IdWithCleanSession("blah".to_string()),
+            vec![proto::Property::AuthenticationMethod("SCRAM".to_string())],
+        );
+        let (tx1, mut rx1) = mpsc::channel(128);
+        let conn1 = ConnectionHandle::from_sender(tx1);
+        let client_id = ClientId::from("blah".to_string());
+        let req1 = ConnReq::new(client_id.clone(), connect1, None, conn1);
+
+        broker_handle
+            .send(Message::Client(client_id, ClientEvent::ConnReq(req1)))
+            .await
+            .unwrap();
+
+        assert_matches!(
+            rx1.recv().await,
+            Some(Message::Client(_, ClientEvent::ConnAck(proto::ConnAck {
+                return_code:
+                    proto::ConnectReturnCode::Refused(
+                        proto::ConnectionRefusedReason::Other(0x8C),
+                    ),
+                ..
+            })))
+        );
+        assert_matches!(
+            rx1.recv().await,
+            Some(Message::Client(_, ClientEvent::DropConnection))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connection_quota_per_identity() {
+        let config = config_with(&json!({ "limits": { "max_connections_per_identity": 1 } }));
+        let broker = BrokerBuilder::default()
+            .config(config)
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        let mut broker_handle = broker.handle();
+        tokio::spawn(broker.run().map(drop));
+
+        let (_, _rx1) = connect_client("a", &mut broker_handle).await.unwrap();
+
+        // taking over the connection of the same client is allowed
+        let (_, _rx2) = connect_client("a", &mut broker_handle).await.unwrap();
+
+        let (tx, mut rx) = mpsc::channel(128);
+        let client_id = ClientId::from("b");
+        let req = ConnReq::new(
+            client_id.clone(),
+            persistent_connect("b".to_string()),
+            None,
+            ConnectionHandle::from_sender(tx),
+        );
+        broker_handle
+            .send(Message::Client(client_id, ClientEvent::ConnReq(req)))
+            .await
+            .unwrap();
+
+        let refused =
+            proto::ConnectReturnCode::Refused(proto::ConnectionRefusedReason::ServerUnavailable);
+        assert_matches!(
+            rx.recv().await,
+            Some(Message::Client(_, ClientEvent::ConnAck(ack))) if ack.return_code == refused
+        );
+        assert_matches!(
+            rx.recv().await,
+            Some(Message::Client(_, ClientEvent::DropConnection))
+        );
+    }
+
     #[tokio::test]
     async fn test_publish_client_has_no_permissions() {
         let broker = BrokerBuilder::default()
@@ -1838,6 +2852,7 @@ pub(crate) mod tests {
             retain: true,
             topic_name: "/foo/bar".to_string(),
             payload: Bytes::new(),
+            properties: proto::Properties::default(),
         };
 
         let message = Message::Client(client_id.clone(), ClientEvent::PublishFrom(publish));
@@ -1875,16 +2890,20 @@ pub(crate) mod tests {
                 proto::SubscribeTo {
                     topic_filter: "/topic/allowed".to_string(),
                     qos: proto::QoS::AtLeastOnce,
+                    options: proto::SubscriptionOptions::default(),
                 },
                 proto::SubscribeTo {
                     topic_filter: "/topic/denied".to_string(),
                     qos: proto::QoS::AtMostOnce,
+                    options: proto::SubscriptionOptions::default(),
                 },
                 proto::SubscribeTo {
                     topic_filter: "/topic/in#va/#lid".to_string(),
                     qos: proto::QoS::ExactlyOnce,
+                    options: proto::SubscriptionOptions::default(),
                 },
             ],
+            properties: proto::Properties::default(),
         };
 
         let message = Message::Client(client_id.clone(), ClientEvent::Subscribe(subscribe));
@@ -1892,8 +2911,8 @@ pub(crate) mod tests {
 
         let expected_qos = vec![
             proto::SubAckQos::Success(proto::QoS::AtLeastOnce),
-            proto::SubAckQos::Failure,
-            proto::SubAckQos::Failure,
+            proto::SubAckQos::Failure(proto::ReasonCode::NOT_AUTHORIZED),
+            proto::SubAckQos::Failure(proto::ReasonCode::TOPIC_FILTER_INVALID),
         ];
         assert_matches!(
             rx1.recv().await,
@@ -1901,6 +2920,78 @@ pub(crate) mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_subscribe_with_subscription_identifier() {
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        let mut receivers = shared_subscribers(&mut broker, &["sub"], "foo").await;
+
+        let mut subscribe = subscribe_to("bar");
+        subscribe
+            .properties
+            .push(proto::Property::SubscriptionIdentifier(1));
+        broker
+            .process_subscribe(ClientId::from("sub"), subscribe)
+            .await
+            .unwrap();
+
+        let reason_code = proto::ReasonCode::SUBSCRIPTION_IDENTIFIERS_NOT_SUPPORTED;
+        let expected_qos = vec![proto::SubAckQos::Failure(reason_code)];
+        assert_matches!(
+            receivers[0].try_recv(),
+            Ok(Message::Client(_, ClientEvent::SubAck(suback))) if suback.qos == expected_qos
+        );
+        assert!(broker.subscriptions.matches("bar").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_retain_handling() {
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        broker.store_retained(retained_publication("foo"));
+
+        let mut receivers = shared_subscribers(&mut broker, &["sub"], "bar").await;
+        let client_id = ClientId::from("sub");
+        let subscribe = |topic_filter, retain_handling| {
+            let mut subscribe = subscribe_to(topic_filter);
+            subscribe.subscribe_to[0].options.retain_handling = retain_handling;
+            subscribe
+        };
+
+        let cases = vec![
+            // a new subscription gets the retained message
+            ("foo", proto::RetainHandling::SendAtNewSubscribe, true),
+            // renewing it does not
+            ("foo", proto::RetainHandling::SendAtNewSubscribe, false),
+            ("foo", proto::RetainHandling::SendAtSubscribe, true),
+            ("#", proto::RetainHandling::DoNotSend, false),
+        ];
+        for (topic_filter, retain_handling, retained) in cases {
+            broker
+                .process_subscribe(client_id.clone(), subscribe(topic_filter, retain_handling))
+                .await
+                .unwrap();
+
+            assert_matches!(
+                receivers[0].try_recv(),
+                Ok(Message::Client(_, ClientEvent::SubAck(_)))
+            );
+            if retained {
+                assert_matches!(
+                    receivers[0].try_recv(),
+                    Ok(Message::Client(_, ClientEvent::PublishTo(_)))
+                );
+            }
+            assert_matches!(receivers[0].try_recv(), Err(TryRecvError::Empty));
+        }
+    }
+
     #[tokio::test]
     async fn test_receive_client_has_no_permissions() {
         let broker = BrokerBuilder::default()
@@ -1923,7 +3014,9 @@ pub(crate) mod tests {
             subscribe_to: vec![proto::SubscribeTo {
                 topic_filter: "/foo/bar".to_string(),
                 qos: proto::QoS::AtLeastOnce,
+                options: proto::SubscriptionOptions::default(),
             }],
+            properties: proto::Properties::default(),
         };
 
         let message = Message::Client(sub_id.clone(), ClientEvent::Subscribe(subscribe));
@@ -1939,6 +3032,7 @@ pub(crate) mod tests {
             retain: true,
             topic_name: "/foo/bar".to_string(),
             payload: Bytes::new(),
+            properties: proto::Properties::default(),
         };
 
         let message = Message::Client(pub_id.clone(), ClientEvent::PublishFrom(publish));
@@ -1956,22 +3050,293 @@ pub(crate) mod tests {
         assert_matches!(sub_rx.try_recv(), Err(TryRecvError::Empty))
     }
 
-    async fn connect_client(
-        client_id: &str,
-        broker_handle: &mut BrokerHandle,
-    ) -> Result<(ClientId, Receiver<Message>), Error> {
-        let connect = persistent_connect(client_id.into());
+    fn retained_publication(topic_name: &str) -> proto::Publication {
+        proto::Publication {
+            topic_name: topic_name.to_string(),
+            qos: proto::QoS::AtMostOnce,
+            retain: true,
+            payload: Bytes::from("payload"),
+            properties: proto::Properties::default(),
+        }
+    }
 
-        let (tx, mut rx) = mpsc::channel(128);
-        let conn = ConnectionHandle::from_sender(tx);
-        let client_id = ClientId::from(client_id);
-        let req = ConnReq::new(client_id.clone(), connect, None, conn);
-        broker_handle
-            .send(Message::Client(
-                client_id.clone(),
-                ClientEvent::ConnReq(req),
-            ))
-            .await?;
+    #[test]
+    fn test_retained_max_count() {
+        let config = config_with(&json!({ "retained_messages": { "max_count": 1 } }));
+        let mut broker = BrokerBuilder::default().config(config).build();
+
+        broker.store_retained(retained_publication("topic/a"));
+        broker.store_retained(retained_publication("topic/b"));
+        assert_eq!(1, broker.retained.len());
+        assert!(broker.retained.contains_key("topic/a"));
+
+        // updating an existing topic is always allowed
+        broker.store_retained(retained_publication("topic/a"));
+        assert_eq!(1, broker.retained.len());
+    }
+
+    #[test]
+    fn test_retained_expiration() {
+        let config = config_with(&json!({ "retained_messages": { "expiration": "0s" } }));
+        let mut broker = BrokerBuilder::default().config(config).build();
+
+        broker.store_retained(retained_publication("topic/a"));
+        let (retained, _) = broker.snapshot().into_parts();
+        assert!(retained.is_empty());
+
+        broker.expire_retained();
+        assert!(broker.retained.is_empty());
+    }
+
+    #[test]
+    fn test_retained_message_expiry_interval() {
+        let mut broker = BrokerBuilder::default().build();
+
+        let mut publication = retained_publication("topic/a");
+        publication
+            .properties
+            .push(proto::Property::MessageExpiryInterval(0));
+        broker.store_retained(publication);
+
+        broker.expire_retained();
+        assert!(broker.retained.is_empty());
+    }
+
+    #[test]
+    fn test_offline_session_expiration() {
+        let config = config_with(&json!({ "session": { "expiration": "0s" } }));
+        let mut broker = BrokerBuilder::default()
+            .config(config)
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        let id = "id1".to_string();
+        let client_id = ClientId::from(id.clone());
+        let req = ConnReq::new(
+            client_id.clone(),
+            persistent_connect(id),
+            None,
+            connection_handle(),
+        );
+        broker.open_session(AuthId::Anonymous, req).unwrap();
+        broker.close_session(&client_id);
+        assert_matches!(broker.sessions[&client_id], Session::Offline(_));
+
+        let (_, sessions) = broker.snapshot().into_parts();
+        assert!(sessions.is_empty());
+
+        broker.expire_sessions();
+        assert!(broker.sessions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_queue_full_disconnect() {
+        let config = config_with(&json!({
+            "inflight_messages": { "max_count": 1 },
+            "session": { "messages": { "max_count": 1, "when_full": "disconnect" } }
+        }));
+        let broker = BrokerBuilder::default()
+            .config(config)
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        let mut broker_handle = broker.handle();
+        tokio::spawn(broker.run().map(drop));
+
+        let (sub_id, mut sub_rx) = connect_client("sub", &mut broker_handle).await.unwrap();
+        let subscribe = proto::Subscribe {
+            packet_identifier: proto::PacketIdentifier::new(1).unwrap(),
+            subscribe_to: vec![proto::SubscribeTo {
+                topic_filter: "/foo/bar".to_string(),
+                qos: proto::QoS::AtLeastOnce,
+                options: proto::SubscriptionOptions::default(),
+            }],
+            properties: proto::Properties::default(),
+        };
+        let message = Message::Client(sub_id, ClientEvent::Subscribe(subscribe));
+        broker_handle.send(message).await.unwrap();
+        assert_matches!(
+            sub_rx.recv().await,
+            Some(Message::Client(_, ClientEvent::SubAck(_)))
+        );
+
+        let (pub_id, _pub_rx) = connect_client("pub", &mut broker_handle).await.unwrap();
+        for _ in 0..3 {
+            let publish = proto::Publish {
+                packet_identifier_dup_qos: proto::PacketIdentifierDupQoS::AtMostOnce,
+                retain: false,
+                topic_name: "/foo/bar".to_string(),
+                payload: Bytes::from("payload"),
+                properties: proto::Properties::default(),
+            };
+            let message = Message::Client(pub_id.clone(), ClientEvent::PublishFrom(publish));
+            broker_handle.send(message).await.unwrap();
+        }
+
+        // first message is inflight, second is queued, third overflows the queue
+        assert_matches!(
+            sub_rx.recv().await,
+            Some(Message::Client(_, ClientEvent::PublishTo(_)))
+        );
+        assert_matches!(
+            sub_rx.recv().await,
+            Some(Message::Client(_, ClientEvent::DropConnection))
+        );
+    }
+
+    fn subscribe_to(topic_filter: &str) -> proto::Subscribe {
+        proto::Subscribe {
+            packet_identifier: proto::PacketIdentifier::new(1).unwrap(),
+            subscribe_to: vec![proto::SubscribeTo {
+                topic_filter: topic_filter.to_string(),
+                qos: proto::QoS::AtLeastOnce,
+                options: proto::SubscriptionOptions::default(),
+            }],
+            properties: proto::Properties::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_admin_purge_session() {
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        let (tx, mut rx) = mpsc::channel(128);
+        let client_id = ClientId::from("sub");
+        let req = ConnReq::new(
+            client_id.clone(),
+            persistent_connect("sub".to_string()),
+            None,
+            ConnectionHandle::from_sender(tx),
+        );
+        broker.open_session(AuthId::Anonymous, req).unwrap();
+        broker
+            .process_subscribe(client_id.clone(), subscribe_to("/foo/+"))
+            .await
+            .unwrap();
+
+        let infos = broker.session_infos();
+        assert_eq!(1, infos.len());
+        assert_eq!("sub", infos[0].client_id());
+        assert_eq!(Some("*"), infos[0].auth_id());
+        assert!(infos[0].connected());
+        assert!(infos[0].persistent());
+        assert_eq!(&["/foo/+".to_string()], infos[0].subscriptions());
+
+        assert!(broker.purge_session(client_id.clone()).await.unwrap());
+        assert_matches!(
+            rx.recv().await,
+            Some(Message::Client(_, ClientEvent::SubAck(_)))
+        );
+        assert_matches!(
+            rx.recv().await,
+            Some(Message::Client(_, ClientEvent::DropConnection))
+        );
+        assert_eq!(0, broker.sessions.len());
+        assert!(broker.subscriptions.is_empty());
+
+        assert!(!broker.purge_session(client_id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_subscription_index_unsubscribe() {
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        let (tx, _rx) = mpsc::channel(128);
+        let client_id = ClientId::from("sub");
+        let req = ConnReq::new(
+            client_id.clone(),
+            transient_connect("sub".to_string()),
+            None,
+            ConnectionHandle::from_sender(tx),
+        );
+        broker.open_session(AuthId::Anonymous, req).unwrap();
+
+        broker
+            .process_subscribe(client_id.clone(), subscribe_to("/foo/+"))
+            .await
+            .unwrap();
+        assert!(broker
+            .subscriptions
+            .matches("/foo/bar")
+            .contains(&client_id));
+
+        let unsubscribe = proto::Unsubscribe {
+            packet_identifier: proto::PacketIdentifier::new(2).unwrap(),
+            unsubscribe_from: vec!["/foo/+".to_string()],
+        };
+        broker
+            .process_unsubscribe(client_id.clone(), unsubscribe)
+            .await
+            .unwrap();
+        assert!(broker.subscriptions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_subscription_index_session_close() {
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        let (tx, _rx) = mpsc::channel(128);
+        let transient_id = ClientId::from("transient");
+        let req = ConnReq::new(
+            transient_id.clone(),
+            transient_connect("transient".to_string()),
+            None,
+            ConnectionHandle::from_sender(tx.clone()),
+        );
+        broker.open_session(AuthId::Anonymous, req).unwrap();
+
+        let persistent_id = ClientId::from("persistent");
+        let req = ConnReq::new(
+            persistent_id.clone(),
+            persistent_connect("persistent".to_string()),
+            None,
+            ConnectionHandle::from_sender(tx),
+        );
+        broker.open_session(AuthId::Anonymous, req).unwrap();
+
+        for client_id in &[transient_id.clone(), persistent_id.clone()] {
+            broker
+                .process_subscribe(client_id.clone(), subscribe_to("/foo/#"))
+                .await
+                .unwrap();
+        }
+
+        // transient subscriptions go away with the session,
+        // offline sessions keep receiving messages
+        broker.close_session(&transient_id);
+        broker.close_session(&persistent_id);
+        let matched = broker.subscriptions.matches("/foo/bar");
+        assert!(!matched.contains(&transient_id));
+        assert!(matched.contains(&persistent_id));
+    }
+
+    async fn connect_client(
+        client_id: &str,
+        broker_handle: &mut BrokerHandle,
+    ) -> Result<(ClientId, Receiver<Message>), Error> {
+        let connect = persistent_connect(client_id.into());
+
+        let (tx, mut rx) = mpsc::channel(128);
+        let conn = ConnectionHandle::from_sender(tx);
+        let client_id = ClientId::from(client_id);
+        let req = ConnReq::new(client_id.clone(), connect, None, conn);
+        broker_handle
+            .send(Message::Client(
+                client_id.clone(),
+                ClientEvent::ConnReq(req),
+            ))
+            .await?;
 
         assert_matches!(
             rx.recv().await,
@@ -1984,4 +3349,539 @@ pub(crate) mod tests {
 
         Ok((client_id, rx))
     }
+
+    async fn shared_subscribers<N, Z>(
+        broker: &mut Broker<N, Z>,
+        ids: &[&str],
+        topic_filter: &str,
+    ) -> Vec<Receiver<Message>>
+    where
+        N: Authenticator,
+        Z: Authorizer,
+    {
+        let mut receivers = vec![];
+        for id in ids {
+            let (tx, mut rx) = mpsc::channel(128);
+            let client_id = ClientId::from(*id);
+            let req = ConnReq::new(
+                client_id.clone(),
+                transient_connect(id.to_string()),
+                None,
+                ConnectionHandle::from_sender(tx),
+            );
+            broker.open_session(AuthId::Anonymous, req).unwrap();
+            broker
+                .process_subscribe(client_id, subscribe_to(topic_filter))
+                .await
+                .unwrap();
+
+            assert_matches!(
+                rx.try_recv(),
+                Ok(Message::Client(_, ClientEvent::SubAck(_)))
+            );
+            receivers.push(rx);
+        }
+        receivers
+    }
+
+    fn publication(topic_name: &str, qos: proto::QoS) -> proto::Publication {
+        proto::Publication {
+            topic_name: topic_name.to_string(),
+            qos,
+            retain: false,
+            payload: Bytes::from("payload"),
+            properties: proto::Properties::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shared_subscription_round_robin() {
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        let mut receivers =
+            shared_subscribers(&mut broker, &["sub1", "sub2"], "$share/group/foo/+").await;
+
+        for _ in 0..4 {
+            broker
+                .publish_all(publication("foo/bar", proto::QoS::AtMostOnce))
+                .await
+                .unwrap();
+        }
+
+        for rx in &mut receivers {
+            for _ in 0..2 {
+                assert_matches!(
+                    rx.try_recv(),
+                    Ok(Message::Client(_, ClientEvent::PublishTo(_)))
+                );
+            }
+            assert_matches!(rx.try_recv(), Err(TryRecvError::Empty));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shared_subscription_skips_full_inflight() {
+        let config = config_with(&json!({ "inflight_messages": { "max_count": 1 } }));
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .config(config)
+            .build();
+
+        let mut receivers =
+            shared_subscribers(&mut broker, &["sub1", "sub2"], "$share/group/foo/+").await;
+
+        // fill the inflight window of sub1
+        broker
+            .process_subscribe(ClientId::from("sub1"), subscribe_to("bar"))
+            .await
+            .unwrap();
+        broker
+            .publish_all(publication("bar", proto::QoS::AtLeastOnce))
+            .await
+            .unwrap();
+        assert_matches!(
+            receivers[0].try_recv(),
+            Ok(Message::Client(_, ClientEvent::SubAck(_)))
+        );
+        assert_matches!(
+            receivers[0].try_recv(),
+            Ok(Message::Client(_, ClientEvent::PublishTo(_)))
+        );
+
+        broker
+            .publish_all(publication("foo/bar", proto::QoS::AtLeastOnce))
+            .await
+            .unwrap();
+
+        assert_matches!(receivers[0].try_recv(), Err(TryRecvError::Empty));
+        assert_matches!(
+            receivers[1].try_recv(),
+            Ok(Message::Client(_, ClientEvent::PublishTo(_)))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shared_subscription_skips_full_queue() {
+        let config = config_with(&json!({
+            "inflight_messages": { "max_count": 1 },
+            "session": { "messages": { "max_count": 1, "when_full": "drop_new" } }
+        }));
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .config(config)
+            .build();
+
+        let mut receivers =
+            shared_subscribers(&mut broker, &["sub1", "sub2"], "$share/group/foo/+").await;
+
+        // fill the inflight window and the queue of sub1, and the inflight window of sub2
+        for (id, topic, count) in &[("sub1", "bar", 2), ("sub2", "baz", 1)] {
+            broker
+                .process_subscribe(ClientId::from(*id), subscribe_to(topic))
+                .await
+                .unwrap();
+            for _ in 0..*count {
+                broker
+                    .publish_all(publication(topic, proto::QoS::AtLeastOnce))
+                    .await
+                    .unwrap();
+            }
+        }
+        for rx in &mut receivers {
+            assert_matches!(
+                rx.try_recv(),
+                Ok(Message::Client(_, ClientEvent::SubAck(_)))
+            );
+            assert_matches!(
+                rx.try_recv(),
+                Ok(Message::Client(_, ClientEvent::PublishTo(_)))
+            );
+        }
+
+        broker
+            .publish_all(publication("foo/bar", proto::QoS::AtLeastOnce))
+            .await
+            .unwrap();
+
+        let queued = |broker: &Broker<_, _>, id: &str| {
+            broker.sessions[&ClientId::from(id)]
+                .state()
+                .unwrap()
+                .queued_messages()
+        };
+        assert_eq!(1, queued(&broker, "sub1"));
+        assert_eq!(1, queued(&broker, "sub2"));
+        assert_eq!(
+            0,
+            broker
+                .sessions
+                .get_mut(&ClientId::from("sub1"))
+                .unwrap()
+                .take_dropped_messages()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shared_subscription_drops_without_connected_member() {
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+        let stats = broker.stats();
+
+        let client_id = ClientId::from("sub1");
+        let (tx, _rx) = mpsc::channel(128);
+        let req = ConnReq::new(
+            client_id.clone(),
+            persistent_connect("sub1".to_string()),
+            None,
+            ConnectionHandle::from_sender(tx),
+        );
+        broker.open_session(AuthId::Anonymous, req).unwrap();
+        broker
+            .process_subscribe(client_id.clone(), subscribe_to("$share/group/foo"))
+            .await
+            .unwrap();
+        broker.close_session(&client_id);
+        assert_matches!(broker.sessions[&client_id], Session::Offline(_));
+
+        broker
+            .publish_all(publication("foo", proto::QoS::AtLeastOnce))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            0,
+            broker.sessions[&client_id]
+                .state()
+                .unwrap()
+                .queued_messages()
+        );
+        broker.publish_stats().await.unwrap();
+        assert_eq!(1, stats.borrow().messages_dropped());
+    }
+
+    #[tokio::test]
+    async fn test_shared_subscription_with_own_subscription() {
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        let mut receivers = shared_subscribers(&mut broker, &["sub1"], "$share/group/foo/+").await;
+        broker
+            .process_subscribe(ClientId::from("sub1"), subscribe_to("foo/bar"))
+            .await
+            .unwrap();
+        assert_matches!(
+            receivers[0].try_recv(),
+            Ok(Message::Client(_, ClientEvent::SubAck(_)))
+        );
+
+        broker
+            .publish_all(publication("foo/bar", proto::QoS::AtMostOnce))
+            .await
+            .unwrap();
+
+        for _ in 0..2 {
+            assert_matches!(
+                receivers[0].try_recv(),
+                Ok(Message::Client(_, ClientEvent::PublishTo(_)))
+            );
+        }
+        assert_matches!(receivers[0].try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[tokio::test]
+    async fn test_shared_subscription_no_retained() {
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        broker.store_retained(retained_publication("foo/bar"));
+
+        let mut receivers = shared_subscribers(&mut broker, &["sub1"], "$share/group/foo/+").await;
+        assert_matches!(receivers[0].try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[tokio::test]
+    async fn test_publish_stats() {
+        let mut broker = BrokerBuilder::default()
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+        let stats = broker.stats();
+
+        let mut receivers =
+            shared_subscribers(&mut broker, &["sub1"], "$SYS/broker/clients/connected").await;
+
+        broker.publish_stats().await.unwrap();
+
+        assert_matches!(
+            receivers[0].try_recv(),
+            Ok(Message::Client(_, ClientEvent::PublishTo(_)))
+        );
+        assert!(broker
+            .sys_retained
+            .contains_key("$SYS/broker/messages/received"));
+        assert_eq!(1, stats.borrow().connected_sessions());
+    }
+
+    #[tokio::test]
+    async fn test_publish_stats_keeps_sys_topics_apart() {
+        let config = config_with(&json!({ "retained_messages": { "max_count": 1 } }));
+        let mut broker = BrokerBuilder::default()
+            .config(config)
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+
+        broker.publish_stats().await.unwrap();
+        assert!(broker.retained.is_empty());
+        assert!(broker.snapshot().into_parts().0.is_empty());
+
+        // $SYS topics do not count against the retained message limit
+        let mut retained = publication("topic/a", proto::QoS::AtMostOnce);
+        retained.retain = true;
+        broker.publish_all(retained).await.unwrap();
+        assert!(broker.retained.contains_key("topic/a"));
+
+        // a new subscriber still gets the latest statistics
+        let mut receivers =
+            shared_subscribers(&mut broker, &["sub1"], "$SYS/broker/messages/retained").await;
+        match receivers[0].try_recv() {
+            Ok(Message::Client(_, ClientEvent::PublishTo(Publish::QoS0(_, publish)))) => {
+                assert!(publish.retain)
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_stats_counts_sent_messages() {
+        let config = config_with(&json!({
+            "inflight_messages": { "max_count": 1 },
+            "session": { "messages": { "max_count": 1 } }
+        }));
+        let mut broker = BrokerBuilder::default()
+            .config(config)
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .build();
+        let stats = broker.stats();
+
+        let mut receivers = shared_subscribers(&mut broker, &["sub1"], "foo").await;
+
+        // first message is inflight, second is queued, third overflows the queue
+        for _ in 0..3 {
+            broker
+                .publish_all(publication("foo", proto::QoS::AtLeastOnce))
+                .await
+                .unwrap();
+        }
+        broker.publish_stats().await.unwrap();
+        assert_eq!(1, stats.borrow().messages_sent());
+
+        let packet_identifier = match receivers[0].try_recv() {
+            Ok(Message::Client(_, ClientEvent::PublishTo(Publish::QoS12(id, _)))) => id,
+            other => panic!("unexpected message {:?}", other),
+        };
+        let puback = proto::PubAck {
+            packet_identifier,
+            reason_code: proto::ReasonCode::SUCCESS,
+            properties: proto::Properties::default(),
+        };
+        broker
+            .process_puback(ClientId::from("sub1"), puback)
+            .await
+            .unwrap();
+        assert_matches!(
+            receivers[0].try_recv(),
+            Ok(Message::Client(_, ClientEvent::PublishTo(_)))
+        );
+
+        broker.publish_stats().await.unwrap();
+        assert_eq!(2, stats.borrow().messages_sent());
+    }
+
+    struct ChannelPersistor(mpsc::UnboundedSender<BrokerState>);
+
+    #[async_trait::async_trait]
+    impl Persist for ChannelPersistor {
+        type Error = PersistError;
+
+        async fn load(&mut self) -> Result<Option<BrokerState>, Self::Error> {
+            Ok(None)
+        }
+
+        async fn store(&mut self, state: BrokerState) -> Result<(), Self::Error> {
+            let _ = self.0.send(state);
+            Ok(())
+        }
+
+        async fn append(&mut self, _: Vec<StateChange>) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_unsaved_messages() {
+        let config = config_with(&json!({
+            "persistence": { "file_path": "state", "time_interval": "5m", "unsaved_message_count": 2 }
+        }));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let snapshotter = Snapshotter::new(ChannelPersistor(tx));
+        let snapshot_handle = snapshotter.snapshot_handle();
+        tokio::spawn(snapshotter.run());
+
+        let mut broker = BrokerBuilder::default()
+            .config(config)
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .snapshot_handle(snapshot_handle)
+            .build();
+
+        let client_id = ClientId::from("pub");
+        let req = ConnReq::new(
+            client_id.clone(),
+            transient_connect("pub".to_string()),
+            None,
+            connection_handle(),
+        );
+        broker.open_session(AuthId::Anonymous, req).unwrap();
+
+        for i in 0..2 {
+            assert_matches!(rx.try_recv(), Err(TryRecvError::Empty));
+
+            let publish = proto::Publish {
+                packet_identifier_dup_qos: proto::PacketIdentifierDupQoS::AtMostOnce,
+                retain: true,
+                topic_name: format!("topic/{}", i),
+                payload: Bytes::from("payload"),
+                properties: proto::Properties::default(),
+            };
+            broker
+                .process_publish(client_id.clone(), publish)
+                .await
+                .unwrap();
+        }
+
+        let (retained, _) = rx.recv().await.unwrap().into_parts();
+        assert_eq!(2, retained.len());
+        assert_eq!(0, broker.unsaved_messages);
+    }
+
+    #[tokio::test]
+    async fn test_journal_replays_to_snapshot() {
+        let config = config_with(&json!({
+            "persistence": { "file_path": "state", "time_interval": "5m", "unsaved_message_count": 0 }
+        }));
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let snapshotter = Snapshotter::new(ChannelPersistor(tx));
+
+        let mut broker = BrokerBuilder::default()
+            .config(config)
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .snapshot_handle(snapshotter.snapshot_handle())
+            .build();
+
+        let (tx, _rx) = mpsc::channel(128);
+        let client_id = ClientId::from("sub");
+        let req = ConnReq::new(
+            client_id.clone(),
+            persistent_connect("sub".to_string()),
+            None,
+            ConnectionHandle::from_sender(tx),
+        );
+        broker.open_session(AuthId::Anonymous, req).unwrap();
+        broker
+            .process_subscribe(client_id.clone(), subscribe_to("foo/+"))
+            .await
+            .unwrap();
+
+        let journal = broker.journal.as_mut().unwrap();
+        let mut changes = journal.collect(&mut broker.sessions);
+
+        broker.close_session(&client_id);
+        for topic in &["foo/a", "foo/b", "bar/c"] {
+            broker
+                .publish_all(publication(topic, proto::QoS::AtLeastOnce))
+                .await
+                .unwrap();
+        }
+        let mut retained = publication("foo/r", proto::QoS::AtLeastOnce);
+        retained.retain = true;
+        broker.publish_all(retained).await.unwrap();
+
+        let journal = broker.journal.as_mut().unwrap();
+        changes.extend(journal.collect(&mut broker.sessions));
+
+        let (expected_retained, expected_sessions) = broker.snapshot().into_parts();
+        let (retained, sessions) = replay(BrokerState::default(), changes).into_parts();
+
+        assert_eq!(expected_retained, retained);
+        assert_eq!(1, sessions.len());
+        assert_eq!(
+            expected_sessions[0].clone().into_parts(),
+            sessions[0].clone().into_parts()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_journal_collects_changed_sessions_only() {
+        let config = config_with(&json!({
+            "persistence": { "file_path": "state", "time_interval": "5m", "unsaved_message_count": 0 }
+        }));
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let snapshotter = Snapshotter::new(ChannelPersistor(tx));
+
+        let mut broker = BrokerBuilder::default()
+            .config(config)
+            .authenticator(|_| Ok(Some(AuthId::Anonymous)))
+            .authorizer(|_| Ok(true))
+            .snapshot_handle(snapshotter.snapshot_handle())
+            .build();
+
+        for (id, topic_filter) in &[("sub1", "foo/+"), ("sub2", "bar/+")] {
+            let (tx, _rx) = mpsc::channel(128);
+            let client_id = ClientId::from(*id);
+            let req = ConnReq::new(
+                client_id.clone(),
+                persistent_connect(id.to_string()),
+                None,
+                ConnectionHandle::from_sender(tx),
+            );
+            broker.open_session(AuthId::Anonymous, req).unwrap();
+            broker
+                .process_subscribe(client_id.clone(), subscribe_to(topic_filter))
+                .await
+                .unwrap();
+            broker.close_session(&client_id);
+        }
+
+        let journal = broker.journal.as_mut().unwrap();
+        let changes = journal.collect(&mut broker.sessions);
+        assert!(!changes.is_empty());
+        assert!(journal.collect(&mut broker.sessions).is_empty());
+
+        broker
+            .publish_all(publication("foo/a", proto::QoS::AtLeastOnce))
+            .await
+            .unwrap();
+
+        let journal = broker.journal.as_mut().unwrap();
+        let changes = journal.collect(&mut broker.sessions);
+        assert_eq!(1, changes.len());
+        assert_matches!(
+            &changes[0],
+            StateChange::Session(client_id, SessionChange::Queued(_)) if client_id.as_str() == "sub1"
+        );
+    }
 }
@@ -6,9 +6,12 @@ use std::time::Duration;
 
 use config::{Config, ConfigError, File, FileFormat};
 use lazy_static::lazy_static;
+use mqtt3::proto;
 use regex::Regex;
 use serde::{Deserialize, Deserializer};
 
+use crate::subscription::TopicFilter;
+
 pub const DEFAULTS: &str = include_str!("../config/default.json");
 
 #[derive(Debug, Clone, Deserialize)]
@@ -20,10 +23,48 @@ pub enum Transport {
     Tls {
         address: String,
         certificate: PathBuf,
+        client_certificates: Option<ClientCertificates>,
+    },
+    Ws {
+        address: String,
+        path: String,
+    },
+    Wss {
+        address: String,
+        path: String,
+        certificate: PathBuf,
+        client_certificates: Option<ClientCertificates>,
     },
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClientCertificates {
+    ca: PathBuf,
+    #[serde(default = "default_client_certificates_required")]
+    required: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CertificateIdentity {
+    CommonName,
+    SubjectAltName,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Authentication {
+    certificate_identity: CertificateIdentity,
+    allow_anonymous: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Authorization {
+    policy: PathBuf,
+    #[serde(default = "default_reload_interval", with = "humantime_serde")]
+    reload_interval: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum QueueFullAction {
     DropNew,
@@ -31,19 +72,35 @@ pub enum QueueFullAction {
     Disconnect,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 pub struct InflightMessages {
     max_count: u32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 pub struct RetainedMessages {
     max_count: u32,
     #[serde(with = "humantime_serde")]
     expiration: Duration,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
+pub struct TopicExpiration {
+    #[serde(deserialize_with = "topic_filter")]
+    topic_filter: TopicFilter,
+    #[serde(deserialize_with = "ttl")]
+    ttl: Option<Duration>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MessageExpiration {
+    #[serde(deserialize_with = "ttl")]
+    ttl: Option<Duration>,
+    #[serde(default)]
+    topics: Vec<TopicExpiration>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
 pub struct SessionMessages {
     #[serde(deserialize_with = "humansize")]
     max_message_size: u64,
@@ -53,7 +110,7 @@ pub struct SessionMessages {
     when_full: QueueFullAction,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 pub struct SessionPersistence {
     file_path: String,
     #[serde(with = "humantime_serde")]
@@ -61,26 +118,435 @@ pub struct SessionPersistence {
     unsaved_message_count: u32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 pub struct Session {
     #[serde(with = "humantime_serde")]
     expiration: Duration,
     messages: SessionMessages,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitAction {
+    Throttle,
+    Disconnect,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PublishRate {
+    messages_per_second: Option<u32>,
+    #[serde(default, deserialize_with = "optional_humansize")]
+    bytes_per_second: Option<u64>,
+    #[serde(default = "default_when_exceeded")]
+    when_exceeded: RateLimitAction,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Limits {
+    max_connections: Option<u32>,
+    max_connections_per_identity: Option<u32>,
+    max_subscriptions: Option<u32>,
+    publish_rate: Option<PublishRate>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Admin {
+    socket: PathBuf,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Prometheus {
+    address: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Statistics {
+    #[serde(with = "humantime_serde")]
+    interval: Duration,
+    prometheus: Option<Prometheus>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    In,
+    Out,
+    Both,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TopicRule {
+    pattern: String,
+    direction: Direction,
+    #[serde(default)]
+    local_prefix: String,
+    #[serde(default)]
+    remote_prefix: String,
+    #[serde(deserialize_with = "qos")]
+    qos: proto::QoS,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Bridge {
+    name: String,
+    address: String,
+    local_address: String,
+    client_id: String,
+    username: Option<String>,
+    password: Option<String>,
+    #[serde(default = "default_keep_alive", with = "humantime_serde")]
+    keep_alive: Duration,
+    #[serde(default = "default_max_reconnect_back_off", with = "humantime_serde")]
+    max_reconnect_back_off: Duration,
+    #[serde(default = "default_max_queued_messages")]
+    max_queued_messages: u32,
+    tls: Option<BridgeTls>,
+    topics: Vec<TopicRule>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BridgeTls {
+    ca: Option<PathBuf>,
+    certificate: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
 pub struct BrokerConfig {
     transports: Vec<Transport>,
     inflight_messages: InflightMessages,
     retained_messages: RetainedMessages,
+    message_expiration: MessageExpiration,
     session: Session,
     persistence: Option<SessionPersistence>,
+    statistics: Statistics,
+    authentication: Authentication,
+    authorization: Option<Authorization>,
+    admin: Option<Admin>,
+    #[serde(default)]
+    limits: Limits,
+    #[serde(default)]
+    bridges: Vec<Bridge>,
+}
+
+impl ClientCertificates {
+    pub fn ca(&self) -> &Path {
+        &self.ca
+    }
+
+    pub fn required(&self) -> bool {
+        self.required
+    }
+}
+
+fn default_client_certificates_required() -> bool {
+    true
+}
+
+impl Authentication {
+    pub fn certificate_identity(&self) -> CertificateIdentity {
+        self.certificate_identity
+    }
+
+    pub fn allow_anonymous(&self) -> bool {
+        self.allow_anonymous
+    }
+}
+
+impl SessionPersistence {
+    pub fn file_path(&self) -> &str {
+        &self.file_path
+    }
+
+    pub fn time_interval(&self) -> Duration {
+        self.time_interval
+    }
+
+    pub fn unsaved_message_count(&self) -> u32 {
+        self.unsaved_message_count
+    }
+}
+
+impl Authorization {
+    pub fn policy(&self) -> &Path {
+        &self.policy
+    }
+
+    pub fn reload_interval(&self) -> Duration {
+        self.reload_interval
+    }
+}
+
+fn default_reload_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+impl InflightMessages {
+    pub fn max_count(&self) -> usize {
+        self.max_count as usize
+    }
+}
+
+impl RetainedMessages {
+    pub fn max_count(&self) -> usize {
+        self.max_count as usize
+    }
+
+    pub fn expiration(&self) -> Duration {
+        self.expiration
+    }
+}
+
+impl TopicExpiration {
+    pub fn topic_filter(&self) -> &TopicFilter {
+        &self.topic_filter
+    }
+
+    /// Time to live of messages on matching topics, `None` if they never expire.
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+}
+
+impl MessageExpiration {
+    /// Time to live of messages on topics no topic filter matches, `None` if
+    /// they never expire.
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// Overrides of the time to live, the first matching topic filter wins.
+    pub fn topics(&self) -> &[TopicExpiration] {
+        &self.topics
+    }
+}
+
+impl SessionMessages {
+    pub fn max_message_size(&self) -> u64 {
+        self.max_message_size
+    }
+
+    pub fn max_count(&self) -> usize {
+        self.max_count as usize
+    }
+
+    pub fn max_total_space(&self) -> u64 {
+        self.max_total_space
+    }
+
+    pub fn when_full(&self) -> QueueFullAction {
+        self.when_full
+    }
+}
+
+impl Session {
+    pub fn expiration(&self) -> Duration {
+        self.expiration
+    }
+
+    pub fn messages(&self) -> &SessionMessages {
+        &self.messages
+    }
+}
+
+impl PublishRate {
+    /// Publications a client may send per second, `None` if unlimited.
+    pub fn messages_per_second(&self) -> Option<u32> {
+        self.messages_per_second
+    }
+
+    /// Payload bytes a client may publish per second, `None` if unlimited.
+    pub fn bytes_per_second(&self) -> Option<u64> {
+        self.bytes_per_second
+    }
+
+    pub fn when_exceeded(&self) -> RateLimitAction {
+        self.when_exceeded
+    }
+}
+
+fn default_when_exceeded() -> RateLimitAction {
+    RateLimitAction::Throttle
+}
+
+impl Limits {
+    /// Maximum number of connected clients, `None` if unlimited.
+    pub fn max_connections(&self) -> Option<usize> {
+        self.max_connections.map(|max| max as usize)
+    }
+
+    /// Maximum number of clients connected with the same identity, `None` if
+    /// unlimited.
+    pub fn max_connections_per_identity(&self) -> Option<usize> {
+        self.max_connections_per_identity.map(|max| max as usize)
+    }
+
+    /// Maximum number of subscriptions of a session, `None` if unlimited.
+    pub fn max_subscriptions(&self) -> Option<usize> {
+        self.max_subscriptions.map(|max| max as usize)
+    }
+
+    pub fn publish_rate(&self) -> Option<&PublishRate> {
+        self.publish_rate.as_ref()
+    }
+}
+
+impl Admin {
+    /// Path of the unix socket the admin endpoint listens on.
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+}
+
+impl Prometheus {
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+}
+
+impl Statistics {
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn prometheus(&self) -> Option<&Prometheus> {
+        self.prometheus.as_ref()
+    }
+}
+
+impl TopicRule {
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn local_prefix(&self) -> &str {
+        &self.local_prefix
+    }
+
+    pub fn remote_prefix(&self) -> &str {
+        &self.remote_prefix
+    }
+
+    pub fn qos(&self) -> proto::QoS {
+        self.qos
+    }
+}
+
+impl Bridge {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn local_address(&self) -> &str {
+        &self.local_address
+    }
+
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_ref().map(String::as_str)
+    }
+
+    pub fn password(&self) -> Option<&str> {
+        self.password.as_ref().map(String::as_str)
+    }
+
+    pub fn keep_alive(&self) -> Duration {
+        self.keep_alive
+    }
+
+    pub fn max_reconnect_back_off(&self) -> Duration {
+        self.max_reconnect_back_off
+    }
+
+    pub fn max_queued_messages(&self) -> usize {
+        self.max_queued_messages as usize
+    }
+
+    pub fn tls(&self) -> Option<&BridgeTls> {
+        self.tls.as_ref()
+    }
+
+    pub fn topics(&self) -> &Vec<TopicRule> {
+        &self.topics
+    }
+}
+
+impl BridgeTls {
+    /// CA certificates the upstream broker's certificate must chain to.
+    ///
+    /// The system trust store is used if not set.
+    pub fn ca(&self) -> Option<&Path> {
+        self.ca.as_ref().map(PathBuf::as_path)
+    }
+
+    /// PKCS#12 identity the bridge presents to the upstream broker.
+    pub fn certificate(&self) -> Option<&Path> {
+        self.certificate.as_ref().map(PathBuf::as_path)
+    }
+}
+
+fn default_keep_alive() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_max_reconnect_back_off() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_max_queued_messages() -> u32 {
+    1000
 }
 
 impl BrokerConfig {
     pub fn transports(&self) -> &Vec<Transport> {
         &self.transports
     }
+
+    pub fn inflight_messages(&self) -> &InflightMessages {
+        &self.inflight_messages
+    }
+
+    pub fn retained_messages(&self) -> &RetainedMessages {
+        &self.retained_messages
+    }
+
+    pub fn message_expiration(&self) -> &MessageExpiration {
+        &self.message_expiration
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    pub fn statistics(&self) -> &Statistics {
+        &self.statistics
+    }
+
+    pub fn authentication(&self) -> &Authentication {
+        &self.authentication
+    }
+
+    pub fn authorization(&self) -> Option<&Authorization> {
+        self.authorization.as_ref()
+    }
+
+    pub fn admin(&self) -> Option<&Admin> {
+        self.admin.as_ref()
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
 }
 
 pub fn humansize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
@@ -108,6 +574,51 @@ where
     Ok(base * multiplier)
 }
 
+pub fn optional_humansize<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    humansize(deserializer).map(Some)
+}
+
+pub fn qos<'de, D>(deserializer: D) -> Result<proto::QoS, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match u8::deserialize(deserializer)? {
+        0 => Ok(proto::QoS::AtMostOnce),
+        1 => Ok(proto::QoS::AtLeastOnce),
+        2 => Ok(proto::QoS::ExactlyOnce),
+        other => Err(serde::de::Error::invalid_value(
+            serde::de::Unexpected::Unsigned(other.into()),
+            &"0, 1 or 2",
+        )),
+    }
+}
+
+/// Deserializes a duration like `1h`, or `never` for no duration at all.
+pub fn ttl<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    if s == "never" {
+        return Ok(None);
+    }
+
+    humantime::parse_duration(&s)
+        .map(Some)
+        .map_err(|_| error::<D>(&s, &"a duration like \"1h\" or \"never\""))
+}
+
+pub fn topic_filter<'de, D>(deserializer: D) -> Result<TopicFilter, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(|_| error::<D>(&s, &"a topic filter"))
+}
+
 fn get_multiplier<'de, T, D>(str: &str) -> Result<T, D::Error>
 where
     T: From<u32>,
@@ -150,10 +661,20 @@ impl BrokerConfig {
     pub fn persistence(&self) -> Option<&SessionPersistence> {
         self.persistence.as_ref()
     }
+
+    pub fn bridges(&self) -> &Vec<Bridge> {
+        &self.bridges
+    }
+}
+
+impl Default for BrokerConfig {
+    fn default() -> Self {
+        Self::new().expect("default configuration must be valid")
+    }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use std::path::Path;
     use std::time::Duration;
 
@@ -165,6 +686,17 @@ mod tests {
 
     use super::*;
 
+    /// Creates a configuration from the defaults with `overrides` merged on top.
+    pub fn config_with(overrides: &serde_json::Value) -> BrokerConfig {
+        let mut s = Config::new();
+        s.merge(File::from_str(DEFAULTS, FileFormat::Json))
+            .expect("defaults must be valid");
+        s.merge(File::from_str(&overrides.to_string(), FileFormat::Json))
+            .expect("overrides must be valid");
+
+        s.try_into().expect("configuration must be valid")
+    }
+
     #[test]
     fn it_loads_defaults() {
         let settings = BrokerConfig::new().expect("should be able to create default instance");
@@ -193,6 +725,253 @@ mod tests {
         assert_matches!(settings, Err(_err));
     }
 
+    #[test]
+    fn it_loads_websocket_transports() {
+        let settings = config_with(&json!({
+            "transports": [
+                { "ws": { "address": "0.0.0.0:8080", "path": "/mqtt" } },
+                { "wss": { "address": "0.0.0.0:8443", "path": "/mqtt", "certificate": "cert.pfx" } },
+            ]
+        }));
+
+        let transports = settings.transports();
+        assert_eq!(2, transports.len());
+        assert_matches!(&transports[0], Transport::Ws { path, .. } if path == "/mqtt");
+        assert_matches!(&transports[1], Transport::Wss { .. });
+    }
+
+    #[test]
+    fn it_loads_client_certificates() {
+        let settings = config_with(&json!({
+            "transports": [
+                { "tls": { "address": "0.0.0.0:8883", "certificate": "cert.pfx" } },
+                {
+                    "tls": {
+                        "address": "0.0.0.0:8884",
+                        "certificate": "cert.pfx",
+                        "client_certificates": { "ca": "ca.pem" }
+                    }
+                },
+                {
+                    "wss": {
+                        "address": "0.0.0.0:8443",
+                        "path": "/mqtt",
+                        "certificate": "cert.pfx",
+                        "client_certificates": { "ca": "ca.pem", "required": false }
+                    }
+                },
+            ]
+        }));
+
+        let transports = settings.transports();
+        assert_matches!(
+            &transports[0],
+            Transport::Tls {
+                client_certificates: None,
+                ..
+            }
+        );
+        assert_matches!(
+            &transports[1],
+            Transport::Tls { client_certificates: Some(client), .. }
+                if client.ca() == Path::new("ca.pem") && client.required()
+        );
+        assert_matches!(
+            &transports[2],
+            Transport::Wss { client_certificates: Some(client), .. } if !client.required()
+        );
+    }
+
+    #[test]
+    fn it_loads_authentication() {
+        let settings = BrokerConfig::new().expect("should be able to create default instance");
+        assert_eq!(
+            settings.authentication().certificate_identity(),
+            CertificateIdentity::CommonName
+        );
+        assert!(settings.authentication().allow_anonymous());
+
+        let settings = config_with(&json!({
+            "authentication": { "certificate_identity": "subject_alt_name", "allow_anonymous": false }
+        }));
+        assert_eq!(
+            settings.authentication().certificate_identity(),
+            CertificateIdentity::SubjectAltName
+        );
+        assert!(!settings.authentication().allow_anonymous());
+    }
+
+    #[test]
+    fn it_loads_authorization() {
+        let settings = BrokerConfig::new().expect("should be able to create default instance");
+        assert!(settings.authorization().is_none());
+
+        let settings = config_with(&json!({
+            "authorization": { "policy": "policy.json" }
+        }));
+        let authorization = settings.authorization().expect("authorization");
+        assert_eq!(authorization.policy(), Path::new("policy.json"));
+        assert_eq!(authorization.reload_interval(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn it_loads_statistics() {
+        let settings = BrokerConfig::new().expect("should be able to create default instance");
+        assert_eq!(settings.statistics().interval(), Duration::from_secs(10));
+        assert!(settings.statistics().prometheus().is_none());
+
+        let settings = config_with(&json!({
+            "statistics": { "interval": "1m", "prometheus": { "address": "0.0.0.0:9090" } }
+        }));
+        assert_eq!(settings.statistics().interval(), Duration::from_secs(60));
+        assert_matches!(
+            settings.statistics().prometheus(),
+            Some(prometheus) if prometheus.address() == "0.0.0.0:9090"
+        );
+    }
+
+    #[test]
+    fn it_loads_message_expiration() {
+        let settings = BrokerConfig::new().expect("should be able to create default instance");
+        assert_eq!(None, settings.message_expiration().ttl());
+        assert!(settings.message_expiration().topics().is_empty());
+
+        let settings = config_with(&json!({
+            "message_expiration": {
+                "ttl": "1d",
+                "topics": [
+                    { "topic_filter": "telemetry/#", "ttl": "1h" },
+                    { "topic_filter": "config/#", "ttl": "never" },
+                ]
+            }
+        }));
+        let expiration = settings.message_expiration();
+        assert_eq!(Some(Duration::from_secs(24 * 60 * 60)), expiration.ttl());
+
+        let topics = expiration.topics();
+        assert_eq!(2, topics.len());
+        assert!(topics[0].topic_filter().matches("telemetry/temperature"));
+        assert_eq!(Some(Duration::from_secs(60 * 60)), topics[0].ttl());
+        assert!(topics[1].topic_filter().matches("config/desired"));
+        assert_eq!(None, topics[1].ttl());
+    }
+
+    #[test]
+    fn it_rejects_invalid_message_expiration() {
+        let mut s = Config::new();
+        s.merge(File::from_str(DEFAULTS, FileFormat::Json)).unwrap();
+        s.merge(File::from_str(
+            &json!({ "message_expiration": { "ttl": "sometimes" } }).to_string(),
+            FileFormat::Json,
+        ))
+        .unwrap();
+
+        assert!(s.try_into::<BrokerConfig>().is_err());
+    }
+
+    #[test]
+    fn it_loads_limits() {
+        let settings = BrokerConfig::new().expect("should be able to create default instance");
+        assert_eq!(None, settings.limits().max_connections());
+        assert!(settings.limits().publish_rate().is_none());
+
+        let settings = config_with(&json!({
+            "limits": {
+                "max_connections": 100,
+                "max_connections_per_identity": 2,
+                "max_subscriptions": 50,
+                "publish_rate": { "messages_per_second": 10, "bytes_per_second": "64kb" }
+            }
+        }));
+        let limits = settings.limits();
+        assert_eq!(Some(100), limits.max_connections());
+        assert_eq!(Some(2), limits.max_connections_per_identity());
+        assert_eq!(Some(50), limits.max_subscriptions());
+
+        let rate = limits.publish_rate().expect("publish_rate");
+        assert_eq!(Some(10), rate.messages_per_second());
+        assert_eq!(Some(64 * 1024), rate.bytes_per_second());
+        assert_eq!(RateLimitAction::Throttle, rate.when_exceeded());
+    }
+
+    #[test]
+    fn it_loads_admin() {
+        let settings = BrokerConfig::new().expect("should be able to create default instance");
+        assert!(settings.admin().is_none());
+
+        let settings = config_with(&json!({
+            "admin": { "socket": "/var/run/mqttd/admin.sock" }
+        }));
+        let admin = settings.admin().expect("admin");
+        assert_eq!(admin.socket(), Path::new("/var/run/mqttd/admin.sock"));
+    }
+
+    #[test]
+    fn it_loads_bridges() {
+        let settings = config_with(&json!({
+            "bridges": [{
+                "name": "upstream",
+                "address": "parent:1883",
+                "local_address": "localhost:1883",
+                "client_id": "edge1",
+                "topics": [
+                    { "pattern": "telemetry/#", "direction": "out", "remote_prefix": "edge1/", "qos": 1 },
+                    { "pattern": "commands/#", "direction": "in", "qos": 0 },
+                ]
+            }]
+        }));
+
+        let bridges = settings.bridges();
+        assert_eq!(1, bridges.len());
+        assert_eq!(Duration::from_secs(60), bridges[0].keep_alive());
+        assert_eq!(1000, bridges[0].max_queued_messages());
+        assert!(bridges[0].tls().is_none());
+
+        let topics = bridges[0].topics();
+        assert_eq!(Direction::Out, topics[0].direction());
+        assert_eq!("edge1/", topics[0].remote_prefix());
+        assert_eq!(proto::QoS::AtLeastOnce, topics[0].qos());
+        assert_eq!("", topics[1].local_prefix());
+    }
+
+    #[test]
+    fn it_loads_bridge_tls() {
+        let bridge = json!({
+            "name": "upstream",
+            "address": "parent:8883",
+            "local_address": "localhost:1883",
+            "client_id": "edge1",
+            "tls": { "ca": "ca.pem" },
+            "topics": []
+        });
+        let bridge = serde_json::from_value::<Bridge>(bridge).unwrap();
+
+        let tls = bridge.tls().unwrap();
+        assert_eq!(Some(Path::new("ca.pem")), tls.ca());
+        assert_eq!(None, tls.certificate());
+    }
+
+    #[test]
+    fn it_defaults_to_no_bridges() {
+        let settings = BrokerConfig::new().expect("should be able to create default instance");
+
+        assert!(settings.bridges().is_empty());
+    }
+
+    #[test]
+    fn it_refuses_bridge_with_invalid_qos() {
+        let bridge = json!({
+            "name": "upstream",
+            "address": "parent:1883",
+            "local_address": "localhost:1883",
+            "client_id": "edge1",
+            "topics": [{ "pattern": "#", "direction": "both", "qos": 3 }]
+        });
+        let result = serde_json::from_value::<Bridge>(bridge);
+
+        assert_matches!(result, Err(_err));
+    }
+
     #[test]
     fn it_type_mismatch_fails() {
         let settings = BrokerConfig::from_file(Path::new("test/config_bad_value_type.json"));
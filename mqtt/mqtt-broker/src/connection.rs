@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
@@ -17,6 +18,7 @@ use tracing_futures::Instrument;
 use uuid::Uuid;
 
 use crate::broker::BrokerHandle;
+use crate::limits::{PublishLimiter, PublishLimits};
 use crate::transport::GetPeerCertificate;
 use crate::{Certificate, ClientEvent, ClientId, ConnReq, Error, Message, Publish};
 
@@ -26,6 +28,9 @@ lazy_static! {
 
 const KEEPALIVE_MULT: f32 = 1.5;
 
+/// Highest topic alias a MQTT 5.0 client may use on a connection.
+pub(crate) const TOPIC_ALIAS_MAXIMUM: u16 = 16;
+
 /// Allows sending events to a connection.
 ///
 /// It is important that this struct doesn't implement Clone,
@@ -70,10 +75,13 @@ impl PartialEq for ConnectionHandle {
 ///
 /// Receives a source of packets and a handle to the Broker.
 /// Starts two tasks (sending and receiving)
+///
+/// Publications are read no faster than the client is allowed by `publish_limits`.
 pub async fn process<I>(
     io: I,
     remote_addr: SocketAddr,
     mut broker_handle: BrokerHandle,
+    publish_limits: PublishLimits,
 ) -> Result<(), Error>
 where
     I: AsyncRead + AsyncWrite + GetPeerCertificate<Certificate = Certificate> + Unpin,
@@ -126,9 +134,10 @@ where
                 broker_handle.send(message).await?;
 
                 // Start up the processing tasks
+                let limiter = publish_limits.limiter(&client_id);
                 let (outgoing, incoming) = codec.split();
                 let incoming_task =
-                    incoming_task(client_id.clone(), incoming, broker_handle.clone());
+                    incoming_task(client_id.clone(), incoming, broker_handle.clone(), limiter);
                 let outgoing_task = outgoing_task(client_id.clone(), events, outgoing, broker_handle.clone());
                 pin_mut!(incoming_task);
                 pin_mut!(outgoing_task);
@@ -204,11 +213,13 @@ async fn incoming_task<S>(
     client_id: ClientId,
     mut incoming: S,
     mut broker: BrokerHandle,
+    mut limiter: Option<PublishLimiter>,
 ) -> Result<(), Error>
 where
     S: Stream<Item = Result<Packet, DecodeError>> + Unpin,
 {
     debug!("incoming_task start");
+    let mut topic_aliases = HashMap::new();
     while let Some(maybe_packet) = incoming.next().await {
         match maybe_packet {
             Ok(packet) => {
@@ -232,13 +243,26 @@ where
                     Packet::PingResp(pingresp) => ClientEvent::PingResp(pingresp),
                     Packet::PubAck(puback) => ClientEvent::PubAck(puback),
                     Packet::PubComp(pubcomp) => ClientEvent::PubComp(pubcomp),
-                    Packet::Publish(publish) => ClientEvent::PublishFrom(publish),
+                    Packet::Publish(publish) => {
+                        if let Some(limiter) = limiter.as_mut() {
+                            limiter.acquire(publish.payload.len()).await?;
+                        }
+                        let publish = resolve_topic_alias(&mut topic_aliases, publish)?;
+                        ClientEvent::PublishFrom(publish)
+                    }
                     Packet::PubRec(pubrec) => ClientEvent::PubRec(pubrec),
                     Packet::PubRel(pubrel) => ClientEvent::PubRel(pubrel),
                     Packet::Subscribe(subscribe) => ClientEvent::Subscribe(subscribe),
                     Packet::SubAck(suback) => ClientEvent::SubAck(suback),
                     Packet::Unsubscribe(unsubscribe) => ClientEvent::Unsubscribe(unsubscribe),
                     Packet::UnsubAck(unsuback) => ClientEvent::UnsubAck(unsuback),
+                    Packet::Auth(_) => {
+                        // Enhanced authentication is never negotiated in the CONNACK,
+                        // so the client is not allowed to send an AUTH packet.
+
+                        warn!("AUTH packet received without enhanced authentication, dropping connection due to protocol violation");
+                        return Err(Error::ProtocolViolation);
+                    }
                 };
 
                 let message = Message::Client(client_id.clone(), event);
@@ -339,3 +363,100 @@ fn client_id(client_id: &proto::ClientId) -> ClientId {
     };
     ClientId(Arc::new(id))
 }
+
+/// Replaces the topic alias of an incoming MQTT 5.0 PUBLISH with the topic
+/// name it stands for.
+///
+/// A PUBLISH carrying both a topic name and a topic alias sets the alias
+/// for the rest of the connection.
+fn resolve_topic_alias(
+    aliases: &mut HashMap<u16, String>,
+    mut publish: proto::Publish,
+) -> Result<proto::Publish, Error> {
+    let alias = match publish.properties.topic_alias() {
+        Some(alias) => alias,
+        None => return Ok(publish),
+    };
+
+    if alias == 0 || alias > TOPIC_ALIAS_MAXIMUM {
+        warn!(
+            "invalid topic alias {} received, dropping connection due to protocol violation",
+            alias
+        );
+        return Err(Error::ProtocolViolation);
+    }
+
+    if publish.topic_name.is_empty() {
+        match aliases.get(&alias) {
+            Some(topic_name) => publish.topic_name = topic_name.clone(),
+            None => {
+                warn!(
+                    "unknown topic alias {} received, dropping connection due to protocol violation",
+                    alias
+                );
+                return Err(Error::ProtocolViolation);
+            }
+        }
+    } else {
+        aliases.insert(alias, publish.topic_name.clone());
+    }
+
+    publish.properties.retain(|property| match property {
+        proto::Property::TopicAlias(_) => false,
+        _ => true,
+    });
+    Ok(publish)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bytes::Bytes;
+    use matches::assert_matches;
+
+    fn publish(topic_name: &str, alias: u16) -> proto::Publish {
+        proto::Publish {
+            packet_identifier_dup_qos: proto::PacketIdentifierDupQoS::AtMostOnce,
+            retain: false,
+            topic_name: topic_name.to_string(),
+            payload: Bytes::from("payload"),
+            properties: vec![proto::Property::TopicAlias(alias)]
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_topic_alias_set_and_resolved() {
+        let mut aliases = HashMap::new();
+
+        let resolved = resolve_topic_alias(&mut aliases, publish("/foo/bar", 1)).unwrap();
+        assert_eq!("/foo/bar", resolved.topic_name);
+        assert_eq!(None, resolved.properties.topic_alias());
+
+        let resolved = resolve_topic_alias(&mut aliases, publish("", 1)).unwrap();
+        assert_eq!("/foo/bar", resolved.topic_name);
+        assert_eq!(None, resolved.properties.topic_alias());
+    }
+
+    #[test]
+    fn test_topic_alias_unknown() {
+        let mut aliases = HashMap::new();
+
+        let result = resolve_topic_alias(&mut aliases, publish("", 1));
+        assert_matches!(result, Err(Error::ProtocolViolation));
+    }
+
+    #[test]
+    fn test_topic_alias_out_of_range() {
+        let mut aliases = HashMap::new();
+
+        let result = resolve_topic_alias(&mut aliases, publish("/foo/bar", 0));
+        assert_matches!(result, Err(Error::ProtocolViolation));
+
+        let result =
+            resolve_topic_alias(&mut aliases, publish("/foo/bar", TOPIC_ALIAS_MAXIMUM + 1));
+        assert_matches!(result, Err(Error::ProtocolViolation));
+    }
+}
@@ -31,6 +31,15 @@ pub enum Error {
     #[error("Session is offline.")]
     SessionOffline,
 
+    #[error("Session queue is full.")]
+    SessionQueueFull,
+
+    #[error("Session has reached its maximum number of subscriptions.")]
+    SubscriptionQuotaExceeded,
+
+    #[error("Client exceeded its publish rate.")]
+    PublishRateExceeded,
+
     #[error("MQTT protocol violation occurred.")]
     ProtocolViolation,
 
@@ -47,10 +56,25 @@ pub enum Error {
     Persist(#[from] crate::persist::PersistError),
 
     #[error("Unable to obtain peer certificate.")]
-    PeerCertificate(#[source] native_tls::Error),
+    PeerCertificate(#[source] openssl::error::ErrorStack),
+
+    #[error("An error occurred sending a message to a bridge.")]
+    SendBridgeMessage,
+
+    #[error("An error occurred subscribing to topics of a bridged broker.")]
+    BridgeSubscribe(#[source] mqtt3::UpdateSubscriptionError),
+
+    #[error("An error occurred forwarding a message to a bridged broker.")]
+    BridgePublish(#[source] mqtt3::PublishError),
 
     #[error("Unable to start broker.")]
     InitializeBroker(#[from] InitializeBrokerError),
+
+    #[error("An error occurred talking to the admin endpoint.")]
+    Admin(#[source] std::io::Error),
+
+    #[error("The broker did not reply to an admin request.")]
+    AdminReply,
 }
 
 /// Represents errors occurred while bootstrapping broker.
@@ -72,8 +96,20 @@ pub enum InitializeBrokerError {
     LoadIdentity(PathBuf, #[source] std::io::Error),
 
     #[error("An error occurred  decoding identity content.")]
-    DecodeIdentity(#[source] native_tls::Error),
+    DecodeIdentity(#[source] openssl::error::ErrorStack),
+
+    #[error("An error occurred loading client CA certificates from file {0}.")]
+    LoadClientCertificates(PathBuf, #[source] openssl::error::ErrorStack),
+
+    #[error("An error occurred loading CA certificates from file {0}.")]
+    LoadCaCertificates(PathBuf, #[source] openssl::error::ErrorStack),
+
+    #[error("An error occurred loading authorization policy.")]
+    LoadPolicy(#[source] crate::auth::PolicyError),
+
+    #[error("Bridge name {0} cannot be used as a directory name.")]
+    BridgeName(String),
 
     #[error("An error occurred  bootstrapping TLS")]
-    Tls(#[source] native_tls::Error),
+    Tls(#[source] openssl::error::ErrorStack),
 }
@@ -15,31 +15,53 @@ use std::sync::Arc;
 
 use mqtt3::*;
 use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
 
+mod admin;
 mod auth;
+mod bridge;
 mod broker;
 mod configuration;
 mod connection;
 mod error;
+mod limits;
 mod persist;
+mod prometheus;
+mod retention;
 mod server;
 mod session;
 mod snapshot;
+mod stats;
 mod subscription;
 mod transport;
-
-pub use crate::auth::{AuthId, Certificate};
+mod trie;
+mod wal;
+
+#[cfg(unix)]
+pub use crate::admin::{admin_request, serve_admin};
+pub use crate::admin::{AdminRequest, AdminResponse, RetainedInfo, SessionInfo};
+pub use crate::auth::{
+    AuthId, Certificate, CertificateAuthenticator, Policy, PolicyAuthorizer, PolicyError,
+};
+pub use crate::bridge::Bridge;
 pub use crate::broker::{Broker, BrokerBuilder, BrokerHandle, BrokerState};
-pub use crate::configuration::BrokerConfig;
+pub use crate::configuration::{BrokerConfig, CertificateIdentity};
 pub use crate::connection::ConnectionHandle;
 pub use crate::error::{Error, InitializeBrokerError};
 pub use crate::persist::{
     ConsolidatedStateFormat, FileFormat, FilePersistor, NullPersistor, Persist, PersistError,
+    STATE_VERSION,
 };
+pub use crate::prometheus::serve_metrics;
+pub use crate::retention::{ReceivedPublication, RetentionPolicy};
 pub use crate::server::Server;
 pub use crate::session::SessionState;
 pub use crate::snapshot::{Snapshotter, StateSnapshotHandle};
+pub use crate::stats::{BrokerStats, SessionStats};
+pub use crate::subscription::TopicFilter;
 pub use crate::transport::TransportBuilder;
+pub use crate::trie::TopicTrie;
+pub use crate::wal::{replay, SessionChange, StateChange};
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub struct ClientId(Arc<String>);
@@ -180,7 +202,20 @@ pub enum ClientEvent {
 pub enum SystemEvent {
     Shutdown,
     StateSnapshot(StateSnapshotHandle),
+    PublishStats,
     // ConfigUpdate,
+    ListSessions(oneshot::Sender<Vec<SessionInfo>>),
+    ListRetained(oneshot::Sender<Vec<RetainedInfo>>),
+
+    /// Deletes the retained message of a topic. Replies whether there was one.
+    DeleteRetained(String, oneshot::Sender<bool>),
+
+    /// Drops the connection of a client. Replies whether it was connected.
+    DisconnectSession(ClientId, oneshot::Sender<bool>),
+
+    /// Drops the connection of a client and removes its session. Replies
+    /// whether there was a session.
+    PurgeSession(ClientId, oneshot::Sender<bool>),
 }
 
 #[derive(Debug)]
@@ -193,6 +228,8 @@ pub enum Message {
 pub(crate) mod tests {
     use super::*;
 
+    use std::time::{Duration, SystemTime};
+
     use bytes::Bytes;
     use proptest::collection::vec;
     use proptest::num;
@@ -229,10 +266,18 @@ pub(crate) mod tests {
                 qos,
                 retain,
                 payload,
+                properties: proto::Properties::default(),
             }
         }
     }
 
+    pub fn arb_received_publication() -> impl Strategy<Value = ReceivedPublication> {
+        (arb_publication(), 0..u64::from(u32::max_value())).prop_map(|(publication, secs)| {
+            let received_at = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+            ReceivedPublication::from_parts(publication, received_at)
+        })
+    }
+
     pub fn arb_pidq() -> impl Strategy<Value = proto::PacketIdentifierDupQoS> {
         prop_oneof![
             Just(proto::PacketIdentifierDupQoS::AtMostOnce),
@@ -255,6 +300,7 @@ pub(crate) mod tests {
                 retain,
                 topic_name,
                 payload,
+                properties: proto::Properties::default(),
             }
         }
     }
@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use tracing::{debug, warn};
+
+use crate::configuration::{PublishRate, RateLimitAction};
+use crate::{ClientId, Error};
+
+/// Counts publications of clients which exceeded their publish rate.
+///
+/// The counters are shared by all connections and read by the broker when it
+/// updates its statistics.
+#[derive(Debug, Default)]
+pub struct RateLimitCounters {
+    throttled: AtomicU64,
+    disconnected: AtomicU64,
+}
+
+impl RateLimitCounters {
+    /// Publications which were delayed to keep their client within its rate.
+    pub fn throttled(&self) -> u64 {
+        self.throttled.load(Ordering::Relaxed)
+    }
+
+    /// Connections which were dropped because their client exceeded its rate.
+    pub fn disconnected(&self) -> u64 {
+        self.disconnected.load(Ordering::Relaxed)
+    }
+}
+
+/// The publish rate every client is held to.
+///
+/// Clones share the buckets of each client, so a client that reconnects, or
+/// that has several connections, is still held to a single rate.
+#[derive(Clone, Debug, Default)]
+pub struct PublishLimits {
+    rate: Option<PublishRate>,
+    counters: Arc<RateLimitCounters>,
+    clients: Arc<Mutex<HashMap<ClientId, Arc<Mutex<Buckets>>>>>,
+}
+
+impl PublishLimits {
+    pub fn new(rate: Option<PublishRate>, counters: Arc<RateLimitCounters>) -> Self {
+        Self {
+            rate,
+            counters,
+            clients: Arc::default(),
+        }
+    }
+
+    /// Creates the limiter of a new connection of `client_id`, or `None` if
+    /// publishing is not limited.
+    pub fn limiter(&self, client_id: &ClientId) -> Option<PublishLimiter> {
+        let rate = self.rate.as_ref()?;
+        if rate.messages_per_second().is_none() && rate.bytes_per_second().is_none() {
+            return None;
+        }
+
+        let now = Instant::now();
+        let mut clients = self.clients.lock().expect("rate limit lock poisoned");
+
+        // Buckets which are full and not used by any connection are the same
+        // as new ones, so there is no need to remember them.
+        clients.retain(|_, buckets| {
+            Arc::strong_count(buckets) > 1
+                || !buckets
+                    .lock()
+                    .expect("rate limit lock poisoned")
+                    .is_full(now)
+        });
+
+        let buckets = clients
+            .entry(client_id.clone())
+            .or_insert_with(|| Arc::new(Mutex::new(Buckets::new(rate))))
+            .clone();
+
+        Some(PublishLimiter {
+            buckets,
+            when_exceeded: rate.when_exceeded(),
+            counters: self.counters.clone(),
+        })
+    }
+}
+
+/// Holds the publications read from the connections of a client to a message
+/// rate and a byte rate.
+#[derive(Debug)]
+pub struct PublishLimiter {
+    buckets: Arc<Mutex<Buckets>>,
+    when_exceeded: RateLimitAction,
+    counters: Arc<RateLimitCounters>,
+}
+
+impl PublishLimiter {
+    /// Admits a publication with a payload of `size` bytes.
+    ///
+    /// A client exceeding its rate is either throttled, by not reading from
+    /// its connection until the publication fits into the rate, or refused
+    /// with `Error::PublishRateExceeded`.
+    pub async fn acquire(&mut self, size: usize) -> Result<(), Error> {
+        let wait = self.buckets().wait_time(size as u64, Instant::now());
+        if wait > Duration::default() {
+            match self.when_exceeded {
+                RateLimitAction::Throttle => {
+                    debug!("publish rate exceeded. throttling for {:?}", wait);
+                    self.counters.throttled.fetch_add(1, Ordering::Relaxed);
+                    tokio::time::delay_for(wait).await;
+                }
+                RateLimitAction::Disconnect => {
+                    warn!("publish rate exceeded. dropping connection");
+                    self.counters.disconnected.fetch_add(1, Ordering::Relaxed);
+                    return Err(Error::PublishRateExceeded);
+                }
+            }
+        }
+
+        self.buckets().take(size as u64, Instant::now());
+        Ok(())
+    }
+
+    fn buckets(&self) -> MutexGuard<'_, Buckets> {
+        self.buckets.lock().expect("rate limit lock poisoned")
+    }
+}
+
+/// The message and byte buckets of a client.
+#[derive(Debug)]
+struct Buckets {
+    messages: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+impl Buckets {
+    fn new(rate: &PublishRate) -> Self {
+        Self {
+            messages: rate
+                .messages_per_second()
+                .map(|rate| TokenBucket::new(rate.into())),
+            bytes: rate.bytes_per_second().map(TokenBucket::new),
+        }
+    }
+
+    /// Returns how long to wait until a publication fits into the rate.
+    fn wait_time(&mut self, size: u64, now: Instant) -> Duration {
+        let messages = self
+            .messages
+            .as_mut()
+            .map_or_else(Duration::default, |bucket| bucket.wait_time(1, now));
+        let bytes = self
+            .bytes
+            .as_mut()
+            .map_or_else(Duration::default, |bucket| bucket.wait_time(size, now));
+        messages.max(bytes)
+    }
+
+    fn take(&mut self, size: u64, now: Instant) {
+        if let Some(bucket) = self.messages.as_mut() {
+            bucket.take(1, now);
+        }
+        if let Some(bucket) = self.bytes.as_mut() {
+            bucket.take(size, now);
+        }
+    }
+
+    fn is_full(&mut self, now: Instant) -> bool {
+        let messages = self
+            .messages
+            .as_mut()
+            .map_or(true, |bucket| bucket.is_full(now));
+        let bytes = self
+            .bytes
+            .as_mut()
+            .map_or(true, |bucket| bucket.is_full(now));
+        messages && bytes
+    }
+}
+
+/// A token bucket refilled at a constant rate, holding at most one second
+/// worth of tokens.
+///
+/// Taking more tokens than the bucket holds leaves it in debt, so a single
+/// publication larger than the byte rate is admitted once the bucket is full.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    #[allow(clippy::cast_precision_loss)]
+    fn new(rate: u64) -> Self {
+        let rate = rate as f64;
+        Self {
+            rate,
+            tokens: rate,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.rate);
+        self.refilled_at = now;
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn wait_time(&mut self, amount: u64, now: Instant) -> Duration {
+        self.refill(now);
+        let missing = (amount as f64).min(self.rate) - self.tokens;
+        if missing > 0.0 && self.rate > 0.0 {
+            Duration::from_secs_f64(missing / self.rate)
+        } else {
+            Duration::default()
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn take(&mut self, amount: u64, now: Instant) {
+        self.refill(now);
+        self.tokens -= amount as f64;
+    }
+
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use matches::assert_matches;
+
+    #[test]
+    fn token_bucket_admits_one_second_burst() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(2);
+        bucket.refilled_at = now;
+
+        assert_eq!(Duration::default(), bucket.wait_time(1, now));
+        bucket.take(1, now);
+        assert_eq!(Duration::default(), bucket.wait_time(1, now));
+        bucket.take(1, now);
+        assert_eq!(Duration::from_millis(500), bucket.wait_time(1, now));
+
+        let later = now + Duration::from_millis(500);
+        assert_eq!(Duration::default(), bucket.wait_time(1, later));
+    }
+
+    #[test]
+    fn token_bucket_admits_oversized_amount_when_full() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(10);
+        bucket.refilled_at = now;
+
+        assert_eq!(Duration::default(), bucket.wait_time(25, now));
+        bucket.take(25, now);
+        assert_eq!(Duration::from_millis(2500), bucket.wait_time(10, now));
+    }
+
+    #[test]
+    fn limiter_waits_for_slowest_bucket() {
+        let now = Instant::now();
+        let mut messages = TokenBucket::new(100);
+        messages.refilled_at = now;
+        let mut bytes = TokenBucket::new(10);
+        bytes.refilled_at = now;
+        let mut buckets = Buckets {
+            messages: Some(messages),
+            bytes: Some(bytes),
+        };
+
+        assert_eq!(Duration::default(), buckets.wait_time(10, now));
+        buckets.take(10, now);
+        assert_eq!(Duration::from_millis(100), buckets.wait_time(1, now));
+    }
+
+    #[tokio::test]
+    async fn limiter_disconnects_when_exceeded() {
+        let counters = Arc::new(RateLimitCounters::default());
+        let mut limiter = PublishLimiter {
+            buckets: Arc::new(Mutex::new(Buckets {
+                messages: Some(TokenBucket::new(1)),
+                bytes: None,
+            })),
+            when_exceeded: RateLimitAction::Disconnect,
+            counters: counters.clone(),
+        };
+
+        limiter.acquire(10).await.unwrap();
+        let result = limiter.acquire(10).await;
+
+        assert_matches!(result, Err(Error::PublishRateExceeded));
+        assert_eq!(1, counters.disconnected());
+    }
+
+    #[tokio::test]
+    async fn limiter_survives_reconnect() {
+        let rate = serde_json::from_value(serde_json::json!({
+            "messages_per_second": 1,
+            "when_exceeded": "disconnect"
+        }))
+        .unwrap();
+        let limits = PublishLimits::new(Some(rate), Arc::default());
+
+        let mut limiter = limits.limiter(&"client1".into()).unwrap();
+        limiter.acquire(10).await.unwrap();
+        drop(limiter);
+
+        let mut limiter = limits.limiter(&"client1".into()).unwrap();
+        let result = limiter.acquire(10).await;
+        assert_matches!(result, Err(Error::PublishRateExceeded));
+
+        let mut limiter = limits.limiter(&"client2".into()).unwrap();
+        limiter.acquire(10).await.unwrap();
+    }
+}
@@ -1,13 +1,15 @@
 use std::cmp;
 use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
 use std::fs::{self, OpenOptions};
-use std::io::{Read, Write};
+use std::io::{Cursor, Read, Write};
 use std::iter::FromIterator;
 #[cfg(unix)]
 use std::os::unix::fs::symlink;
 #[cfg(windows)]
 use std::os::windows::fs::symlink_file;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use async_trait::async_trait;
 use bytes::Bytes;
@@ -16,12 +18,15 @@ use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
 use flate2::Compression;
 use mqtt3::proto::Publication;
+use serde::de::DeserializeOwned;
 use serde::ser::SerializeMap;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use tracing::{debug, info, span, Level};
+use tracing::{debug, info, span, warn, Level};
 
+use crate::retention::ReceivedPublication;
 use crate::session::SessionState;
 use crate::subscription::Subscription;
+use crate::wal::{self, StateChange};
 use crate::BrokerState;
 use crate::ClientId;
 
@@ -29,6 +34,18 @@ use crate::ClientId;
 const STATE_DEFAULT_PREVIOUS_COUNT: usize = 2;
 static STATE_DEFAULT_STEM: &str = "state";
 static STATE_EXTENSION: &str = "dat";
+static WAL_DEFAULT_STEM: &str = "wal";
+static WAL_EXTENSION: &str = "log";
+
+/// The schema version of state files written by `ConsolidatedStateFormat`.
+///
+/// Bump it whenever the layout of `ConsolidatedState` changes and teach
+/// `migrate` to read the previous version.
+pub const STATE_VERSION: u16 = 2;
+
+/// Marks the start of a versioned state file. It is followed by the schema
+/// version as a little endian `u16`.
+const STATE_MAGIC: [u8; 4] = *b"MQST";
 
 #[async_trait]
 pub trait Persist {
@@ -37,6 +54,11 @@ pub trait Persist {
     async fn load(&mut self) -> Result<Option<BrokerState>, Self::Error>;
 
     async fn store(&mut self, state: BrokerState) -> Result<(), Self::Error>;
+
+    /// Saves changes made to the state since the last call to `store` or `append`.
+    ///
+    /// Persistors which only save snapshots can ignore the changes.
+    async fn append(&mut self, changes: Vec<StateChange>) -> Result<(), Self::Error>;
 }
 
 /// A persistor that does nothing.
@@ -54,6 +76,10 @@ impl Persist for NullPersistor {
     async fn store(&mut self, _: BrokerState) -> Result<(), Self::Error> {
         Ok(())
     }
+
+    async fn append(&mut self, _: Vec<StateChange>) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
 
 /// An abstraction over the broker state's file format.
@@ -77,6 +103,12 @@ pub trait FileFormat {
 /// It also aids in "transactionally" saving the state. Updating the symlink
 /// "commits" the changes. This is an attempt to prevent corrupting the state
 /// file in case the process crashes in the middle of writing.
+///
+/// Changes made after a snapshot are appended to a write-ahead log which
+/// belongs to that snapshot, e.g. `wal.<timestamp>.log` for `state.<timestamp>.dat`,
+/// and are replayed on top of it on load. Storing a new snapshot starts a new,
+/// empty log, so a crash before the new snapshot is committed replays the old
+/// log on the old snapshot. Logs are pruned along with their snapshots.
 #[derive(Debug)]
 pub struct FilePersistor<F> {
     dir: PathBuf,
@@ -85,6 +117,9 @@ pub struct FilePersistor<F> {
 
     /// seq is bumped on every store to disambiguate stores in the same timestamp
     seq: u16,
+
+    /// the write-ahead log of the current snapshot, if known
+    wal: Option<PathBuf>,
 }
 
 impl<F> FilePersistor<F> {
@@ -94,6 +129,7 @@ impl<F> FilePersistor<F> {
             format,
             previous_count: STATE_DEFAULT_PREVIOUS_COUNT,
             seq: 0,
+            wal: None,
         }
     }
 
@@ -111,24 +147,89 @@ impl<F> FilePersistor<F> {
 #[derive(Clone, Debug, Default)]
 pub struct ConsolidatedStateFormat;
 
+impl ConsolidatedStateFormat {
+    /// Loads `BrokerState` from a reader along with the schema version it was
+    /// stored in.
+    ///
+    /// State files written before the format was versioned have no header and
+    /// are read as version `0`.
+    pub fn load_versioned<R: Read>(
+        &self,
+        mut reader: R,
+    ) -> Result<(u16, BrokerState), PersistError> {
+        let mut header = Vec::with_capacity(STATE_MAGIC.len());
+        reader
+            .by_ref()
+            .take(STATE_MAGIC.len() as u64)
+            .read_to_end(&mut header)
+            .map_err(|e| PersistError::ReadHeader(Some(e)))?;
+
+        let (version, header) = if header[..] == STATE_MAGIC[..] {
+            let mut version = [0; 2];
+            reader
+                .read_exact(&mut version)
+                .map_err(|e| PersistError::ReadHeader(Some(e)))?;
+            (u16::from_le_bytes(version), Vec::new())
+        } else {
+            (0, header)
+        };
+
+        let state = migrate(version, Cursor::new(header).chain(reader))?;
+        Ok((version, state.try_into()?))
+    }
+}
+
+/// Reads the consolidated state stored in a schema version into the current
+/// schema.
+fn migrate<R: Read>(version: u16, reader: R) -> Result<ConsolidatedState, PersistError> {
+    match version {
+        // Messages stored before arrival times were recorded are treated as
+        // arriving now.
+        0 => {
+            let state: v0::ConsolidatedState = deserialize(reader)?;
+            Ok(state.migrate().migrate(SystemTime::now()))
+        }
+        1 => {
+            let state: v1::ConsolidatedState = deserialize(reader)?;
+            Ok(state.migrate(SystemTime::now()))
+        }
+        2 => deserialize(reader),
+        version => Err(PersistError::UnsupportedVersion(version)),
+    }
+}
+
+fn deserialize<T: DeserializeOwned, R: Read>(reader: R) -> Result<T, PersistError> {
+    let decoder = GzDecoder::new(reader);
+    fail_point!("bincodeformat.load.deserialize_from", |_| {
+        Err(PersistError::Deserialize(None))
+    });
+
+    bincode::deserialize_from(decoder).map_err(|e| PersistError::Deserialize(Some(e)))
+}
+
 impl FileFormat for ConsolidatedStateFormat {
     type Error = PersistError;
 
     fn load<R: Read>(&self, reader: R) -> Result<BrokerState, Self::Error> {
-        let decoder = GzDecoder::new(reader);
-        fail_point!("bincodeformat.load.deserialize_from", |_| {
-            Err(PersistError::Deserialize(None))
-        });
-
-        let state: ConsolidatedState =
-            bincode::deserialize_from(decoder).map_err(|e| PersistError::Deserialize(Some(e)))?;
+        let (version, state) = self.load_versioned(reader)?;
+        if version < STATE_VERSION {
+            info!(
+                "migrated state from version {} to version {}.",
+                version, STATE_VERSION
+            );
+        }
 
-        Ok(state.into())
+        Ok(state)
     }
 
-    fn store<W: Write>(&self, writer: W, state: BrokerState) -> Result<(), Self::Error> {
+    fn store<W: Write>(&self, mut writer: W, state: BrokerState) -> Result<(), Self::Error> {
         let state: ConsolidatedState = state.into();
 
+        writer
+            .write_all(&STATE_MAGIC)
+            .and_then(|_| writer.write_all(&STATE_VERSION.to_le_bytes()))
+            .map_err(|e| PersistError::WriteHeader(Some(e)))?;
+
         let encoder = GzEncoder::new(writer, Compression::default());
         fail_point!("bincodeformat.store.serialize_into", |_| {
             Err(PersistError::Serialize(None))
@@ -144,7 +245,8 @@ impl From<BrokerState> for ConsolidatedState {
         #[allow(clippy::mutable_key_type)]
         let mut payloads = HashMap::new();
 
-        let mut shrink_payload = |publication: Publication| {
+        let mut shrink_payload = |publication: ReceivedPublication| {
+            let (publication, received_at) = publication.into_parts();
             let next_id = payloads.len() as u64;
 
             let id = *payloads.entry(publication.payload).or_insert(next_id);
@@ -154,6 +256,8 @@ impl From<BrokerState> for ConsolidatedState {
                 qos: publication.qos,
                 retain: publication.retain,
                 payload: id,
+                properties: publication.properties,
+                received_at,
             }
         };
 
@@ -196,28 +300,37 @@ impl From<BrokerState> for ConsolidatedState {
     }
 }
 
-impl From<ConsolidatedState> for BrokerState {
-    fn from(state: ConsolidatedState) -> Self {
+impl TryFrom<ConsolidatedState> for BrokerState {
+    type Error = PersistError;
+
+    fn try_from(state: ConsolidatedState) -> Result<Self, Self::Error> {
         let ConsolidatedState {
             payloads,
             retained,
             sessions,
         } = state;
 
-        let expand_payload = |publication: SimplifiedPublication| Publication {
-            topic_name: publication.topic_name,
-            qos: publication.qos,
-            retain: publication.retain,
-            payload: payloads
-                .get(&publication.payload)
-                .expect("corrupted data")
-                .clone(),
-        };
+        let expand_payload =
+            |publication: SimplifiedPublication| -> Result<ReceivedPublication, PersistError> {
+                let payload = payloads
+                    .get(&publication.payload)
+                    .ok_or(PersistError::MissingPayload(publication.payload))?;
+
+                let received_at = publication.received_at;
+                let publication = Publication {
+                    topic_name: publication.topic_name,
+                    qos: publication.qos,
+                    retain: publication.retain,
+                    payload: payload.clone(),
+                    properties: publication.properties,
+                };
+                Ok(ReceivedPublication::from_parts(publication, received_at))
+            };
 
         let retained = retained
             .into_iter()
-            .map(|(topic, publication)| (topic, expand_payload(publication)))
-            .collect();
+            .map(|(topic, publication)| Ok((topic, expand_payload(publication)?)))
+            .collect::<Result<_, PersistError>>()?;
 
         #[allow(clippy::redundant_closure)] // removing closure leads to borrow error
         let sessions = sessions
@@ -227,16 +340,16 @@ impl From<ConsolidatedState> for BrokerState {
                     .waiting_to_be_sent
                     .into_iter()
                     .map(|publication| expand_payload(publication))
-                    .collect();
-                SessionState::from_parts(
+                    .collect::<Result<_, _>>()?;
+                Ok(SessionState::from_parts(
                     session.client_id,
                     session.subscriptions,
                     waiting_to_be_sent,
-                )
+                ))
             })
-            .collect();
+            .collect::<Result<_, PersistError>>()?;
 
-        BrokerState::new(retained, sessions)
+        Ok(BrokerState::new(retained, sessions))
     }
 }
 
@@ -262,6 +375,202 @@ struct SimplifiedPublication {
     qos: crate::proto::QoS,
     retain: bool,
     payload: u64,
+    properties: crate::proto::Properties,
+    received_at: SystemTime,
+}
+
+/// The layout of unversioned state files, written before messages had
+/// properties and topic filters could name a shared subscription group.
+mod v0 {
+    use std::collections::HashMap;
+
+    use bytes::Bytes;
+    use serde::{Deserialize, Serialize};
+
+    use crate::ClientId;
+
+    #[derive(Deserialize, Serialize)]
+    pub(super) struct ConsolidatedState {
+        #[serde(serialize_with = "super::serialize_payloads")]
+        #[serde(deserialize_with = "super::deserialize_payloads")]
+        pub(super) payloads: HashMap<u64, Bytes>,
+        pub(super) retained: HashMap<String, SimplifiedPublication>,
+        pub(super) sessions: Vec<ConsolidatedSession>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    pub(super) struct ConsolidatedSession {
+        pub(super) client_id: ClientId,
+        pub(super) subscriptions: HashMap<String, Subscription>,
+        pub(super) waiting_to_be_sent: Vec<SimplifiedPublication>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    pub(super) struct SimplifiedPublication {
+        pub(super) topic_name: String,
+        pub(super) qos: crate::proto::QoS,
+        pub(super) retain: bool,
+        pub(super) payload: u64,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    pub(super) struct Subscription {
+        pub(super) filter: TopicFilter,
+        pub(super) max_qos: crate::proto::QoS,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    pub(super) struct TopicFilter {
+        pub(super) segments: Vec<Segment>,
+        #[allow(dead_code)] // recomputed from the segments on migration
+        pub(super) multilevel: bool,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    pub(super) enum Segment {
+        Level(String),
+        SingleLevelWildcard,
+        MultiLevelWildcard,
+    }
+
+    impl ConsolidatedState {
+        pub(super) fn migrate(self) -> super::v1::ConsolidatedState {
+            let retained = self
+                .retained
+                .into_iter()
+                .map(|(topic, publication)| (topic, publication.migrate()))
+                .collect();
+            let sessions = self
+                .sessions
+                .into_iter()
+                .map(|session| super::v1::ConsolidatedSession {
+                    client_id: session.client_id,
+                    subscriptions: session
+                        .subscriptions
+                        .into_iter()
+                        .map(|(filter, subscription)| (filter, subscription.migrate()))
+                        .collect(),
+                    waiting_to_be_sent: session
+                        .waiting_to_be_sent
+                        .into_iter()
+                        .map(SimplifiedPublication::migrate)
+                        .collect(),
+                })
+                .collect();
+
+            super::v1::ConsolidatedState {
+                payloads: self.payloads,
+                retained,
+                sessions,
+            }
+        }
+    }
+
+    impl SimplifiedPublication {
+        fn migrate(self) -> super::v1::SimplifiedPublication {
+            super::v1::SimplifiedPublication {
+                topic_name: self.topic_name,
+                qos: self.qos,
+                retain: self.retain,
+                payload: self.payload,
+                properties: crate::proto::Properties::default(),
+            }
+        }
+    }
+
+    impl Subscription {
+        fn migrate(self) -> crate::subscription::Subscription {
+            let segments = self
+                .filter
+                .segments
+                .into_iter()
+                .map(|segment| match segment {
+                    Segment::Level(level) => crate::subscription::Segment::Level(level),
+                    Segment::SingleLevelWildcard => {
+                        crate::subscription::Segment::SingleLevelWildcard
+                    }
+                    Segment::MultiLevelWildcard => crate::subscription::Segment::MultiLevelWildcard,
+                })
+                .collect();
+            let filter = crate::subscription::TopicFilter::new(segments);
+            crate::subscription::Subscription::new(filter, self.max_qos)
+        }
+    }
+}
+
+/// The layout of state files before arrival times of messages were stored.
+mod v1 {
+    use std::collections::HashMap;
+    use std::time::SystemTime;
+
+    use bytes::Bytes;
+    use serde::{Deserialize, Serialize};
+
+    use crate::subscription::Subscription;
+    use crate::ClientId;
+
+    #[derive(Deserialize, Serialize)]
+    pub(super) struct ConsolidatedState {
+        #[serde(serialize_with = "super::serialize_payloads")]
+        #[serde(deserialize_with = "super::deserialize_payloads")]
+        pub(super) payloads: HashMap<u64, Bytes>,
+        pub(super) retained: HashMap<String, SimplifiedPublication>,
+        pub(super) sessions: Vec<ConsolidatedSession>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    pub(super) struct ConsolidatedSession {
+        pub(super) client_id: ClientId,
+        pub(super) subscriptions: HashMap<String, Subscription>,
+        pub(super) waiting_to_be_sent: Vec<SimplifiedPublication>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    pub(super) struct SimplifiedPublication {
+        pub(super) topic_name: String,
+        pub(super) qos: crate::proto::QoS,
+        pub(super) retain: bool,
+        pub(super) payload: u64,
+        pub(super) properties: crate::proto::Properties,
+    }
+
+    impl ConsolidatedState {
+        pub(super) fn migrate(self, received_at: SystemTime) -> super::ConsolidatedState {
+            let stamp = |publication: SimplifiedPublication| super::SimplifiedPublication {
+                topic_name: publication.topic_name,
+                qos: publication.qos,
+                retain: publication.retain,
+                payload: publication.payload,
+                properties: publication.properties,
+                received_at,
+            };
+
+            let retained = self
+                .retained
+                .into_iter()
+                .map(|(topic, publication)| (topic, stamp(publication)))
+                .collect();
+            let sessions = self
+                .sessions
+                .into_iter()
+                .map(|session| super::ConsolidatedSession {
+                    client_id: session.client_id,
+                    subscriptions: session.subscriptions,
+                    waiting_to_be_sent: session
+                        .waiting_to_be_sent
+                        .into_iter()
+                        .map(&stamp)
+                        .collect(),
+                })
+                .collect();
+
+            super::ConsolidatedState {
+                payloads: self.payloads,
+                retained,
+                sessions,
+            }
+        }
+    }
 }
 
 fn serialize_payloads<S>(payloads: &HashMap<u64, Bytes>, serializer: S) -> Result<S::Ok, S::Error>
@@ -300,7 +609,7 @@ where
 
         let res = tokio::task::spawn_blocking(move || {
             let path = dir.join(format!("{}.{}", STATE_DEFAULT_STEM, STATE_EXTENSION));
-            if path.exists() {
+            let state = if path.exists() {
                 info!("loading state from file {}.", path.display());
 
                 fail_point!("filepersistor.load.fileopen", |_| {
@@ -314,19 +623,47 @@ where
                 fail_point!("filepersistor.load.format", |_| {
                     Err(PersistError::Deserialize(None))
                 });
-                let state = format.load(file)?;
-                Ok(Some(state))
+                Some(format.load(file)?)
             } else {
                 info!("no state file found at {}.", path.display());
-                Ok(None)
+                None
+            };
+
+            let wal_path = current_wal_path(&dir);
+            if !wal_path.exists() {
+                return Ok((state, wal_path));
             }
+
+            info!("replaying state changes from file {}.", wal_path.display());
+            fail_point!("filepersistor.load.wal_read", |_| {
+                Err(PersistError::FileRead(wal_path.clone(), None))
+            });
+            let log = fs::read(&wal_path)
+                .map_err(|e| PersistError::FileRead(wal_path.clone(), Some(e)))?;
+            let (changes, len) = wal::read_changes(&log);
+            if len < log.len() {
+                // Drop the torn record so that new changes are appended after the last complete one.
+                fail_point!("filepersistor.load.wal_truncate", |_| {
+                    Err(PersistError::FileWrite(wal_path.clone(), None))
+                });
+                OpenOptions::new()
+                    .write(true)
+                    .open(&wal_path)
+                    .and_then(|file| file.set_len(len as u64))
+                    .map_err(|e| PersistError::FileWrite(wal_path.clone(), Some(e)))?;
+            }
+
+            let state = wal::replay(state.unwrap_or_default(), changes);
+            Ok((Some(state), wal_path))
         })
         .await;
 
         fail_point!("filepersistor.load.spawn_blocking", |_| {
             Err(PersistError::TaskJoin(None))
         });
-        res.map_err(|e| PersistError::TaskJoin(Some(e)))?
+        let (state, wal_path) = res.map_err(|e| PersistError::TaskJoin(Some(e)))??;
+        self.wal = Some(wal_path);
+        Ok(state)
     }
 
     #[allow(clippy::too_many_lines)]
@@ -338,6 +675,9 @@ where
         self.seq = self.seq.wrapping_add(1);
         let seq = self.seq;
 
+        // The new snapshot may get committed even if storing it fails later on.
+        self.wal = None;
+
         let res = tokio::task::spawn_blocking(move || {
             let span = span!(Level::INFO, "persistor", dir = %dir.display());
             let _guard = span.enter();
@@ -461,6 +801,39 @@ where
                             .map_err(|e| PersistError::FileUnlink(entry.path(), Some(e)))?;
                         debug!("{} pruned.", entry.file_name().to_string_lossy());
                     }
+
+                    // Prune the logs of pruned states
+                    let kept = entries
+                        .iter()
+                        .take(previous_count)
+                        .map(|entry| wal_path(&dir, Some(&entry.path())))
+                        .collect::<Vec<PathBuf>>();
+
+                    let logs = fs::read_dir(&dir)
+                        .map_err(|e| (PersistError::ReadDir(dir.clone(), Some(e))))?
+                        .filter_map(Result::ok)
+                        .filter(|entry| entry.file_type().ok().map_or(false, |f| f.is_file()))
+                        .filter(|entry| {
+                            entry
+                                .file_name()
+                                .to_string_lossy()
+                                .starts_with(WAL_DEFAULT_STEM)
+                        })
+                        .filter(|entry| !kept.contains(&entry.path()));
+
+                    for entry in logs {
+                        debug!(
+                            "pruning old state log {}...",
+                            entry.file_name().to_string_lossy()
+                        );
+
+                        fail_point!("filepersistor.store.wal_unlink", |_| {
+                            Err(PersistError::FileUnlink(entry.path(), None))
+                        });
+                        fs::remove_file(&entry.path())
+                            .map_err(|e| PersistError::FileUnlink(entry.path(), Some(e)))?;
+                        debug!("{} pruned.", entry.file_name().to_string_lossy());
+                    }
                 }
                 Err(e) => {
                     fail_point!("filepersistor.store.new_file_unlink", |_| {
@@ -472,15 +845,95 @@ where
                 }
             }
             info!(message="persisted state.", file=%path.display());
-            Ok(())
+            Ok(path)
         })
         .await;
 
         fail_point!("filepersistor.load.spawn_blocking", |_| {
             Err(PersistError::TaskJoin(None))
         });
-        res.map_err(|e| PersistError::TaskJoin(Some(e)))?
+        let path = res.map_err(|e| PersistError::TaskJoin(Some(e)))??;
+        self.wal = Some(wal_path(&self.dir, Some(&path)));
+        Ok(())
     }
+
+    async fn append(&mut self, changes: Vec<StateChange>) -> Result<(), Self::Error> {
+        let dir = self.dir.clone();
+        let wal = self.wal.clone();
+
+        let res = tokio::task::spawn_blocking(move || {
+            let path = wal.unwrap_or_else(|| current_wal_path(&dir));
+            let records = wal::encode_changes(&changes)?;
+
+            if !dir.exists() {
+                fail_point!("filepersistor.append.createdir", |_| {
+                    Err(PersistError::CreateDir(dir.clone(), None))
+                });
+                fs::create_dir_all(&dir)
+                    .map_err(|e| PersistError::CreateDir(dir.clone(), Some(e)))?;
+            }
+
+            debug!(
+                "appending {} state changes to {}...",
+                changes.len(),
+                path.display()
+            );
+            fail_point!("filepersistor.append.fileopen", |_| {
+                Err(PersistError::FileOpen(path.clone(), None))
+            });
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| PersistError::FileOpen(path.clone(), Some(e)))?;
+            let len = file
+                .metadata()
+                .map_err(|e| PersistError::FileOpen(path.clone(), Some(e)))?
+                .len();
+
+            fail_point!("filepersistor.append.write", |_| {
+                Err(PersistError::FileWrite(path.clone(), None))
+            });
+            if let Err(e) = file.write_all(&records).and_then(|_| file.sync_data()) {
+                // Don't leave a torn record in the middle of the log
+                if let Err(e) = file.set_len(len) {
+                    warn!(message = "failed to truncate state log", error = %e);
+                }
+                return Err(PersistError::FileWrite(path, Some(e)));
+            }
+
+            Ok(path)
+        })
+        .await;
+
+        fail_point!("filepersistor.append.spawn_blocking", |_| {
+            Err(PersistError::TaskJoin(None))
+        });
+        let path = res.map_err(|e| PersistError::TaskJoin(Some(e)))??;
+        self.wal = Some(path);
+        Ok(())
+    }
+}
+
+/// Returns the write-ahead log of a state file. Changes made before the
+/// first state is stored go to `wal.log`.
+fn wal_path(dir: &Path, state: Option<&Path>) -> PathBuf {
+    let suffix = state
+        .and_then(Path::file_stem)
+        .map(|stem| {
+            stem.to_string_lossy()
+                .trim_start_matches(STATE_DEFAULT_STEM)
+                .to_string()
+        })
+        .unwrap_or_default();
+    dir.join(format!("{}{}.{}", WAL_DEFAULT_STEM, suffix, WAL_EXTENSION))
+}
+
+/// Returns the write-ahead log of the state file `state.dat` links to.
+fn current_wal_path(dir: &Path) -> PathBuf {
+    let link_path = dir.join(format!("{}.{}", STATE_DEFAULT_STEM, STATE_EXTENSION));
+    let state = fs::read_link(&link_path).ok();
+    wal_path(dir, state.as_ref().map(PathBuf::as_path))
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -488,6 +941,12 @@ pub enum PersistError {
     #[error("failed to open file {0}")]
     FileOpen(PathBuf, #[source] Option<std::io::Error>),
 
+    #[error("failed to read file {0}")]
+    FileRead(PathBuf, #[source] Option<std::io::Error>),
+
+    #[error("failed to write file {0}")]
+    FileWrite(PathBuf, #[source] Option<std::io::Error>),
+
     #[error("failed to rename file {0} to {}")]
     FileRename(PathBuf, PathBuf, #[source] Option<std::io::Error>),
 
@@ -512,6 +971,21 @@ pub enum PersistError {
     #[error("failed to deserialize state")]
     Deserialize(#[source] Option<bincode::Error>),
 
+    #[error("failed to read state header")]
+    ReadHeader(#[source] Option<std::io::Error>),
+
+    #[error("failed to write state header")]
+    WriteHeader(#[source] Option<std::io::Error>),
+
+    #[error(
+        "unsupported state version {0}, the newest supported version is {}",
+        STATE_VERSION
+    )]
+    UnsupportedVersion(u16),
+
+    #[error("state refers to missing payload {0}")]
+    MissingPayload(u64),
+
     #[error("An error occurred joining a task.")]
     TaskJoin(#[source] Option<tokio::task::JoinError>),
 }
@@ -520,8 +994,7 @@ pub enum PersistError {
 pub(crate) mod tests {
     use super::*;
 
-    use std::io::Cursor;
-
+    use matches::assert_matches;
     use proptest::prelude::*;
     use tempfile::TempDir;
 
@@ -536,7 +1009,7 @@ pub(crate) mod tests {
             prop_assert_eq!(expected_retained.len(), consolidated.retained.len());
             prop_assert_eq!(expected_sessions.len(), consolidated.sessions.len());
 
-            let state: BrokerState = consolidated.into();
+            let state: BrokerState = consolidated.try_into().unwrap();
             let (result_retained, result_sessions) = state.into_parts();
 
             prop_assert_eq!(expected_retained, result_retained);
@@ -566,6 +1039,127 @@ pub(crate) mod tests {
         }
     }
 
+    /// Writes a state file in the layout of version 1 with a single retained
+    /// message, without a header if `version` is `None`.
+    fn legacy_state_file(version: Option<u16>) -> Vec<u8> {
+        let mut payloads = HashMap::new();
+        payloads.insert(0, Bytes::from("payload"));
+        let mut retained = HashMap::new();
+        retained.insert(
+            "topic".to_string(),
+            v1::SimplifiedPublication {
+                topic_name: "topic".to_string(),
+                qos: crate::proto::QoS::AtMostOnce,
+                retain: true,
+                payload: 0,
+                properties: crate::proto::Properties::default(),
+            },
+        );
+        let state = v1::ConsolidatedState {
+            payloads,
+            retained,
+            sessions: Vec::new(),
+        };
+
+        let mut buffer = Vec::new();
+        if let Some(version) = version {
+            buffer.extend_from_slice(&STATE_MAGIC);
+            buffer.extend_from_slice(&version.to_le_bytes());
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        bincode::serialize_into(&mut encoder, &state).unwrap();
+        buffer.extend(encoder.finish().unwrap());
+        buffer
+    }
+
+    #[test]
+    fn consolidated_format_stores_current_version() {
+        let format = ConsolidatedStateFormat;
+        let mut buffer = Vec::new();
+        format.store(&mut buffer, BrokerState::default()).unwrap();
+
+        assert_eq!(STATE_MAGIC[..], buffer[..STATE_MAGIC.len()]);
+        let (version, state) = format.load_versioned(Cursor::new(buffer)).unwrap();
+        assert_eq!(STATE_VERSION, version);
+        assert_eq!(BrokerState::default(), state);
+    }
+
+    #[test]
+    fn consolidated_format_migrates_unversioned_state() {
+        // written by the broker before state files were versioned
+        let buffer = fs::read("test/unversioned_state.dat").unwrap();
+        let before = SystemTime::now();
+
+        let format = ConsolidatedStateFormat;
+        let (version, loaded) = format.load_versioned(Cursor::new(&buffer)).unwrap();
+        assert_eq!(0, version);
+
+        let (retained, sessions) = loaded.into_parts();
+        let retained = &retained["retained/topic"];
+        assert_eq!(
+            Bytes::from("retained payload"),
+            retained.publication().payload
+        );
+        assert!(retained.publication().retain);
+        assert_eq!(
+            crate::proto::Properties::default(),
+            retained.publication().properties
+        );
+        assert!(retained.received_at() >= before);
+
+        assert_eq!(1, sessions.len());
+        let (client_id, subscriptions, waiting_to_be_sent) = sessions[0].clone().into_parts();
+        assert_eq!(ClientId::from("client1"), client_id);
+
+        let mut filters = subscriptions.keys().cloned().collect::<Vec<_>>();
+        filters.sort();
+        assert_eq!(vec!["a/#", "a/+/c"], filters);
+        let subscription = &subscriptions["a/+/c"];
+        assert_eq!(crate::proto::QoS::AtLeastOnce, *subscription.max_qos());
+        assert_eq!(None, subscription.filter().share());
+        assert!(subscription.filter().matches("a/b/c"));
+        assert!(subscriptions["a/#"].filter().matches("a/b/c/d"));
+
+        assert_eq!(1, waiting_to_be_sent.len());
+        let queued = waiting_to_be_sent[0].publication();
+        assert_eq!("a/b/c", queued.topic_name);
+        assert_eq!(Bytes::from("queued payload"), queued.payload);
+
+        assert_eq!(
+            vec!["retained/topic"],
+            retained_topics(format.load(Cursor::new(&buffer)).unwrap())
+        );
+    }
+
+    #[test]
+    fn consolidated_format_stamps_messages_of_version_1() {
+        let before = SystemTime::now();
+        let buffer = legacy_state_file(Some(1));
+
+        let (version, loaded) = ConsolidatedStateFormat
+            .load_versioned(Cursor::new(buffer))
+            .unwrap();
+        assert_eq!(1, version);
+
+        let (retained, _) = loaded.into_parts();
+        let publication = &retained["topic"];
+        assert_eq!(Bytes::from("payload"), publication.publication().payload);
+        assert!(publication.received_at() >= before);
+    }
+
+    #[test]
+    fn consolidated_format_rejects_newer_version() {
+        let mut buffer = STATE_MAGIC.to_vec();
+        buffer.extend_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+        buffer.extend(legacy_state_file(None));
+
+        let result = ConsolidatedStateFormat.load(Cursor::new(buffer));
+        assert_matches!(
+            result,
+            Err(PersistError::UnsupportedVersion(version)) if version == STATE_VERSION + 1
+        );
+    }
+
     #[tokio::test]
     async fn filepersistor_smoketest() {
         let tmp_dir = TempDir::new().unwrap();
@@ -576,4 +1170,69 @@ pub(crate) mod tests {
         let state = persistor.load().await.unwrap().unwrap();
         assert_eq!(BrokerState::default(), state);
     }
+
+    fn retained_change(topic_name: &str) -> StateChange {
+        StateChange::RetainedStored(ReceivedPublication::new(Publication {
+            topic_name: topic_name.to_string(),
+            qos: crate::proto::QoS::AtMostOnce,
+            retain: true,
+            payload: Bytes::from("payload"),
+            properties: crate::proto::Properties::default(),
+        }))
+    }
+
+    fn retained_topics(state: BrokerState) -> Vec<String> {
+        let (retained, _) = state.into_parts();
+        let mut topics = retained.into_iter().map(|(t, _)| t).collect::<Vec<_>>();
+        topics.sort();
+        topics
+    }
+
+    #[tokio::test]
+    async fn filepersistor_replays_changes() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().to_owned();
+        let mut persistor = FilePersistor::new(&path, ConsolidatedStateFormat::default());
+
+        // changes made before the first snapshot
+        persistor.append(vec![retained_change("a")]).await.unwrap();
+        let state = persistor.load().await.unwrap().unwrap();
+        assert_eq!(vec!["a"], retained_topics(state.clone()));
+
+        persistor.store(state).await.unwrap();
+        persistor.append(vec![retained_change("b")]).await.unwrap();
+        persistor.append(vec![retained_change("c")]).await.unwrap();
+
+        let mut persistor = FilePersistor::new(&path, ConsolidatedStateFormat::default());
+        let state = persistor.load().await.unwrap().unwrap();
+        assert_eq!(vec!["a", "b", "c"], retained_topics(state.clone()));
+
+        // a new snapshot starts with an empty log
+        persistor.store(BrokerState::default()).await.unwrap();
+        let state = persistor.load().await.unwrap().unwrap();
+        assert_eq!(Vec::<String>::new(), retained_topics(state));
+        assert!(!path.join("wal.log").exists());
+    }
+
+    #[tokio::test]
+    async fn filepersistor_drops_torn_change() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().to_owned();
+        let mut persistor = FilePersistor::new(&path, ConsolidatedStateFormat::default());
+
+        persistor.append(vec![retained_change("a")]).await.unwrap();
+        let mut log = OpenOptions::new()
+            .append(true)
+            .open(path.join("wal.log"))
+            .unwrap();
+        log.write_all(&[0xff, 0x00]).unwrap();
+
+        let mut persistor = FilePersistor::new(&path, ConsolidatedStateFormat::default());
+        let state = persistor.load().await.unwrap().unwrap();
+        assert_eq!(vec!["a"], retained_topics(state));
+
+        persistor.append(vec![retained_change("b")]).await.unwrap();
+        let state = persistor.load().await.unwrap().unwrap();
+        assert_eq!(vec!["a", "b"], retained_topics(state));
+    }
 }
@@ -0,0 +1,121 @@
+use std::io;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+use crate::stats::BrokerStats;
+use crate::{Error, InitializeBrokerError};
+
+static METRICS_PATH: &str = "/metrics";
+static CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Largest request head the endpoint reads before answering.
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// How long a scraper has to send its request and read the response.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serves the latest broker statistics in the Prometheus text format on
+/// `GET /metrics`.
+pub async fn serve_metrics<A>(address: A, stats: watch::Receiver<BrokerStats>) -> Result<(), Error>
+where
+    A: ToSocketAddrs,
+{
+    let mut listener = TcpListener::bind(address)
+        .await
+        .map_err(InitializeBrokerError::BindServer)?;
+    let addr = listener
+        .local_addr()
+        .map_err(InitializeBrokerError::ConnectionLocalAddress)?;
+    info!("Serving metrics on address {}", addr);
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let stats = stats.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(REQUEST_TIMEOUT, respond(stream, &stats)).await {
+                        Ok(Ok(())) => debug!("served metrics to {}", peer),
+                        Ok(Err(e)) => warn!(message = "failed to serve metrics", error=%e),
+                        Err(_) => debug!("metrics request from {} timed out", peer),
+                    }
+                });
+            }
+            Err(e) => warn!(message = "failed to accept metrics connection", error=%e),
+        }
+    }
+}
+
+async fn respond<S>(mut stream: S, stats: &watch::Receiver<BrokerStats>) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut request = Vec::new();
+    let mut buf = [0_u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_SIZE {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+
+    let response = response(&request, &stats.borrow());
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+fn response(request: &[u8], stats: &BrokerStats) -> String {
+    let request_line = request
+        .split(|b| *b == b'\n')
+        .next()
+        .map(String::from_utf8_lossy)
+        .unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) if path == METRICS_PATH => ("200 OK", stats.to_prometheus()),
+        (Some("GET"), Some(_)) => ("404 Not Found", String::new()),
+        _ => ("400 Bad Request", String::new()),
+    };
+
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        CONTENT_TYPE,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_serves_metrics() {
+        let stats = BrokerStats::default();
+        let response = response(b"GET /metrics HTTP/1.1\r\nHost: edge\r\n\r\n", &stats);
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: text/plain; version=0.0.4\r\n"));
+        assert!(response.ends_with(&stats.to_prometheus()));
+    }
+
+    #[test]
+    fn it_refuses_unknown_path() {
+        let response = response(b"GET /other HTTP/1.1\r\n\r\n", &BrokerStats::default());
+
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn it_refuses_malformed_request() {
+        let response = response(b"\r\n\r\n", &BrokerStats::default());
+
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+}
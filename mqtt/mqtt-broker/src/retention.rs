@@ -0,0 +1,225 @@
+use std::cmp;
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime};
+
+use mqtt3::proto;
+use serde::{Deserialize, Serialize};
+
+use crate::configuration::MessageExpiration;
+use crate::subscription::TopicFilter;
+
+/// A publication held by the broker along with the time it arrived.
+///
+/// The arrival time is persisted with the publication, so a message keeps
+/// aging while the broker is down.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReceivedPublication {
+    publication: proto::Publication,
+    received_at: SystemTime,
+}
+
+impl ReceivedPublication {
+    /// Stamps a publication which has just arrived.
+    pub fn new(publication: proto::Publication) -> Self {
+        Self::from_parts(publication, SystemTime::now())
+    }
+
+    pub fn from_parts(publication: proto::Publication, received_at: SystemTime) -> Self {
+        Self {
+            publication,
+            received_at,
+        }
+    }
+
+    pub fn publication(&self) -> &proto::Publication {
+        &self.publication
+    }
+
+    pub fn received_at(&self) -> SystemTime {
+        self.received_at
+    }
+
+    pub fn into_parts(self) -> (proto::Publication, SystemTime) {
+        (self.publication, self.received_at)
+    }
+
+    pub fn into_publication(self) -> proto::Publication {
+        self.publication
+    }
+
+    /// Returns the publication to send on at `now`.
+    ///
+    /// An MQTT 5.0 message expiry interval is lowered by the time the message
+    /// spent waiting in the broker (MQTT 5.0 3.3.2.3.3).
+    pub fn into_forwarded(self, now: SystemTime) -> proto::Publication {
+        let mut publication = self.publication;
+        if let Some(interval) = publication.properties.message_expiry_interval() {
+            let waited = now
+                .duration_since(self.received_at)
+                .unwrap_or_default()
+                .as_secs();
+            let remaining = interval.saturating_sub(u32::try_from(waited).unwrap_or(u32::MAX));
+
+            publication.properties.retain(|property| match property {
+                proto::Property::MessageExpiryInterval(_) => false,
+                _ => true,
+            });
+            publication
+                .properties
+                .push(proto::Property::MessageExpiryInterval(remaining));
+        }
+        publication
+    }
+}
+
+/// Decides how long the broker keeps a message before dropping it.
+///
+/// A message lives for the time to live of the first configured topic filter
+/// matching its topic, or the default one if none does. An MQTT 5.0 message
+/// expiry interval shortens it further.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RetentionPolicy {
+    ttl: Option<Duration>,
+    topics: Vec<(TopicFilter, Option<Duration>)>,
+}
+
+impl RetentionPolicy {
+    pub fn new(ttl: Option<Duration>, topics: Vec<(TopicFilter, Option<Duration>)>) -> Self {
+        Self { ttl, topics }
+    }
+
+    /// Returns how long a message is kept, or `None` if it never expires.
+    pub fn ttl(&self, publication: &proto::Publication) -> Option<Duration> {
+        let ttl = self
+            .topics
+            .iter()
+            .find(|(filter, _)| filter.matches(&publication.topic_name))
+            .map_or(self.ttl, |(_, ttl)| *ttl);
+        let interval = publication
+            .properties
+            .message_expiry_interval()
+            .map(|interval| Duration::from_secs(interval.into()));
+
+        match (ttl, interval) {
+            (Some(ttl), Some(interval)) => Some(cmp::min(ttl, interval)),
+            (ttl, interval) => ttl.or(interval),
+        }
+    }
+
+    /// Returns when a message expires, or `None` if it never does.
+    pub fn expires_at(&self, publication: &ReceivedPublication) -> Option<SystemTime> {
+        self.ttl(&publication.publication)
+            .and_then(|ttl| publication.received_at.checked_add(ttl))
+    }
+
+    pub fn is_expired(&self, publication: &ReceivedPublication, now: SystemTime) -> bool {
+        self.expires_at(publication)
+            .map_or(false, |expires_at| expires_at <= now)
+    }
+}
+
+impl From<&MessageExpiration> for RetentionPolicy {
+    fn from(config: &MessageExpiration) -> Self {
+        let topics = config
+            .topics()
+            .iter()
+            .map(|topic| (topic.topic_filter().clone(), topic.ttl()))
+            .collect();
+        Self::new(config.ttl(), topics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bytes::Bytes;
+
+    fn publication(topic_name: &str) -> proto::Publication {
+        proto::Publication {
+            topic_name: topic_name.to_string(),
+            qos: proto::QoS::AtLeastOnce,
+            retain: false,
+            payload: Bytes::from("payload"),
+            properties: proto::Properties::default(),
+        }
+    }
+
+    fn policy() -> RetentionPolicy {
+        RetentionPolicy::new(
+            Some(Duration::from_secs(60)),
+            vec![
+                (
+                    "telemetry/#".parse().unwrap(),
+                    Some(Duration::from_secs(10)),
+                ),
+                ("config/#".parse().unwrap(), None),
+                ("#".parse().unwrap(), Some(Duration::from_secs(1))),
+            ],
+        )
+    }
+
+    #[test]
+    fn first_matching_topic_filter_wins() {
+        let policy = policy();
+
+        assert_eq!(
+            Some(Duration::from_secs(10)),
+            policy.ttl(&publication("telemetry/temperature"))
+        );
+        assert_eq!(None, policy.ttl(&publication("config/desired")));
+        assert_eq!(
+            Some(Duration::from_secs(1)),
+            policy.ttl(&publication("other"))
+        );
+    }
+
+    #[test]
+    fn default_ttl_applies_without_matching_topic_filter() {
+        let policy = RetentionPolicy::new(Some(Duration::from_secs(60)), Vec::new());
+        assert_eq!(
+            Some(Duration::from_secs(60)),
+            policy.ttl(&publication("other"))
+        );
+
+        assert_eq!(None, RetentionPolicy::default().ttl(&publication("other")));
+    }
+
+    #[test]
+    fn message_expiry_interval_shortens_ttl() {
+        let mut short = publication("config/desired");
+        short
+            .properties
+            .push(proto::Property::MessageExpiryInterval(5));
+
+        assert_eq!(Some(Duration::from_secs(5)), policy().ttl(&short));
+    }
+
+    #[test]
+    fn received_publication_expires_after_ttl() {
+        let policy = policy();
+        let received_at = SystemTime::now();
+        let publication = ReceivedPublication::from_parts(publication("telemetry/a"), received_at);
+
+        assert!(!policy.is_expired(&publication, received_at + Duration::from_secs(9)));
+        assert!(policy.is_expired(&publication, received_at + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn forwarded_publication_expiry_interval_counts_waiting_time() {
+        let received_at = SystemTime::now();
+        let mut expiring = publication("telemetry/a");
+        expiring
+            .properties
+            .push(proto::Property::MessageExpiryInterval(30));
+        let expiring = ReceivedPublication::from_parts(expiring, received_at);
+
+        let forwarded = expiring.into_forwarded(received_at + Duration::from_secs(12));
+        assert_eq!(Some(18), forwarded.properties.message_expiry_interval());
+        assert_eq!(1, forwarded.properties.iter().count());
+
+        let forwarded = ReceivedPublication::from_parts(publication("telemetry/a"), received_at)
+            .into_forwarded(received_at + Duration::from_secs(12));
+        assert!(forwarded.properties.is_empty());
+    }
+}
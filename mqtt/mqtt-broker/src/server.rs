@@ -9,6 +9,7 @@ use tracing_futures::Instrument;
 
 use crate::auth::{Authenticator, Authorizer};
 use crate::broker::{Broker, BrokerHandle, BrokerState};
+use crate::limits::PublishLimits;
 use crate::transport::TransportBuilder;
 use crate::{connection, Error, InitializeBrokerError, Message, SystemEvent};
 
@@ -18,6 +19,7 @@ where
     Z: Authorizer,
 {
     broker: Broker<N, Z>,
+    publish_limits: PublishLimits,
 }
 
 impl<N, Z> Server<N, Z>
@@ -26,7 +28,11 @@ where
     Z: Authorizer + Send + Sync + 'static,
 {
     pub fn from_broker(broker: Broker<N, Z>) -> Self {
-        Self { broker }
+        let publish_limits = broker.publish_limits();
+        Self {
+            broker,
+            publish_limits,
+        }
     }
 
     pub async fn serve<A, F, I>(
@@ -39,7 +45,10 @@ where
         F: Future<Output = ()> + Unpin,
         I: IntoIterator<Item = TransportBuilder<A>>,
     {
-        let Server { broker } = self;
+        let Server {
+            broker,
+            publish_limits,
+        } = self;
         let mut handle = broker.handle();
         let broker_task = tokio::spawn(broker.run());
 
@@ -49,7 +58,12 @@ where
             let (itx, irx) = oneshot::channel::<()>();
             shutdown_handles.push(itx);
 
-            let incoming_task = incoming_task(transport, handle.clone(), irx.map(drop));
+            let incoming_task = incoming_task(
+                transport,
+                handle.clone(),
+                publish_limits.clone(),
+                irx.map(drop),
+            );
 
             let incoming_task = Box::pin(incoming_task);
             incoming_tasks.push(incoming_task);
@@ -167,6 +181,7 @@ where
 async fn incoming_task<A, F>(
     transport: TransportBuilder<A>,
     handle: BrokerHandle,
+    publish_limits: PublishLimits,
     mut shutdown_signal: F,
 ) -> Result<(), Error>
 where
@@ -190,9 +205,10 @@ where
                     .map_err(InitializeBrokerError::ConnectionPeerAddress)?;
 
                 let broker_handle = handle.clone();
+                let publish_limits = publish_limits.clone();
                 let span = span.clone();
                 tokio::spawn(async move {
-                    if let Err(e) = connection::process(stream, peer, broker_handle)
+                    if let Err(e) = connection::process(stream, peer, broker_handle, publish_limits)
                         .instrument(span)
                         .await
                     {
@@ -1,4 +1,6 @@
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 use std::{cmp, fmt, mem};
 
 use mqtt3::proto;
@@ -7,10 +9,115 @@ use serde::ser::SerializeTuple;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use tracing::{debug, warn};
 
+use crate::configuration::{BrokerConfig, QueueFullAction};
+use crate::retention::{ReceivedPublication, RetentionPolicy};
 use crate::subscription::Subscription;
+use crate::wal::SessionChange;
 use crate::{AuthId, ClientEvent, ClientId, ConnReq, ConnectionHandle, Error, Message, Publish};
 
-const MAX_INFLIGHT_MESSAGES: usize = 16;
+/// Limits a session applies to its inflight and queued messages.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionConfig {
+    max_inflight_messages: usize,
+    max_message_size: u64,
+    max_queued_messages: usize,
+    max_queued_size: u64,
+    when_full: QueueFullAction,
+    expiration: Duration,
+    ends_with_connection: bool,
+    retention: Arc<RetentionPolicy>,
+    max_subscriptions: Option<usize>,
+}
+
+impl SessionConfig {
+    pub fn new(
+        max_inflight_messages: usize,
+        max_message_size: u64,
+        max_queued_messages: usize,
+        max_queued_size: u64,
+        when_full: QueueFullAction,
+        expiration: Duration,
+    ) -> Self {
+        Self {
+            max_inflight_messages,
+            max_message_size,
+            max_queued_messages,
+            max_queued_size,
+            when_full,
+            expiration,
+            ends_with_connection: false,
+            retention: Arc::new(RetentionPolicy::default()),
+            max_subscriptions: None,
+        }
+    }
+
+    /// Sets the policy deciding when queued messages expire. By default
+    /// they never do.
+    pub fn with_retention(mut self, retention: Arc<RetentionPolicy>) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Sets the maximum number of subscriptions of a session. By default it
+    /// is unlimited.
+    pub fn with_max_subscriptions(mut self, max_subscriptions: Option<usize>) -> Self {
+        self.max_subscriptions = max_subscriptions;
+        self
+    }
+
+    pub fn expiration(&self) -> Duration {
+        self.expiration
+    }
+
+    pub fn retention(&self) -> &RetentionPolicy {
+        &self.retention
+    }
+
+    /// Lowers the session expiration to `expiration` if it is shorter
+    /// than the configured one.
+    pub fn limit_expiration(&mut self, expiration: Duration) {
+        self.expiration = cmp::min(self.expiration, expiration);
+    }
+
+    /// Whether the session ends as soon as its network connection is closed,
+    /// which is the case for MQTT 5.0 clients asking for a zero session
+    /// expiry interval.
+    pub fn ends_with_connection(&self) -> bool {
+        self.ends_with_connection
+    }
+
+    pub fn set_ends_with_connection(&mut self, ends_with_connection: bool) {
+        self.ends_with_connection = ends_with_connection;
+    }
+
+    /// Lowers the maximum number of inflight messages to `max_inflight_messages`
+    /// if it is lower than the configured one.
+    pub fn limit_max_inflight_messages(&mut self, max_inflight_messages: usize) {
+        self.max_inflight_messages = cmp::min(self.max_inflight_messages, max_inflight_messages);
+    }
+}
+
+impl From<&BrokerConfig> for SessionConfig {
+    fn from(config: &BrokerConfig) -> Self {
+        let messages = config.session().messages();
+        Self::new(
+            config.inflight_messages().max_count(),
+            messages.max_message_size(),
+            messages.max_count(),
+            messages.max_total_space(),
+            messages.when_full(),
+            config.session().expiration(),
+        )
+        .with_retention(Arc::new(RetentionPolicy::from(config.message_expiration())))
+        .with_max_subscriptions(config.limits().max_subscriptions())
+    }
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self::from(&BrokerConfig::default())
+    }
+}
 
 #[derive(Debug)]
 pub struct ConnectedSession {
@@ -18,6 +125,7 @@ pub struct ConnectedSession {
     auth_id: AuthId,
     will: Option<proto::Publication>,
     handle: ConnectionHandle,
+    config: SessionConfig,
 }
 
 impl ConnectedSession {
@@ -26,12 +134,14 @@ impl ConnectedSession {
         state: SessionState,
         will: Option<proto::Publication>,
         handle: ConnectionHandle,
+        config: SessionConfig,
     ) -> Self {
         Self {
             auth_id,
             state,
             will,
             handle,
+            config,
         }
     }
 
@@ -51,6 +161,22 @@ impl ConnectedSession {
         &self.state
     }
 
+    pub fn config(&self) -> &SessionConfig {
+        &self.config
+    }
+
+    /// Returns true if a publication can be sent right away rather than
+    /// queued until inflight messages are acknowledged.
+    pub fn can_send(&self) -> bool {
+        self.state.allowed_to_send(&self.config)
+    }
+
+    /// Returns true if a publication with a payload of `size` bytes fits
+    /// into the queue without applying the action for a full queue.
+    pub fn can_queue(&self, size: u64) -> bool {
+        !queue_is_full(&self.state.waiting_to_be_sent, size, &self.config)
+    }
+
     pub fn into_will(self) -> Option<proto::Publication> {
         self.will
     }
@@ -74,14 +200,14 @@ impl ConnectedSession {
     }
 
     pub fn handle_puback(&mut self, puback: &proto::PubAck) -> Result<Option<ClientEvent>, Error> {
-        self.state.handle_puback(puback)
+        self.state.handle_puback(puback, &self.config)
     }
 
     pub fn handle_puback0(
         &mut self,
         id: proto::PacketIdentifier,
     ) -> Result<Option<ClientEvent>, Error> {
-        self.state.handle_puback0(id)
+        self.state.handle_puback0(id, &self.config)
     }
 
     pub fn handle_pubrec(&mut self, pubrec: &proto::PubRec) -> Result<Option<ClientEvent>, Error> {
@@ -99,14 +225,14 @@ impl ConnectedSession {
         &mut self,
         pubcomp: &proto::PubComp,
     ) -> Result<Option<ClientEvent>, Error> {
-        self.state.handle_pubcomp(pubcomp)
+        self.state.handle_pubcomp(pubcomp, &self.config)
     }
 
     pub fn publish_to(
         &mut self,
         publication: proto::Publication,
     ) -> Result<Option<ClientEvent>, Error> {
-        self.state.publish_to(publication)
+        self.state.publish_to(publication, &self.config)
     }
 
     pub fn subscribe_to(
@@ -115,7 +241,31 @@ impl ConnectedSession {
     ) -> Result<(proto::SubAckQos, Option<Subscription>), Error> {
         match subscribe_to.topic_filter.parse() {
             Ok(filter) => {
-                let proto::SubscribeTo { topic_filter, qos } = subscribe_to;
+                let proto::SubscribeTo {
+                    topic_filter,
+                    qos,
+                    options,
+                } = subscribe_to;
+
+                // The No Local and Retain As Published options are not
+                // supported, so subscriptions asking for them are refused.
+                if options.no_local || options.retain_as_published {
+                    warn!(
+                        "unsupported options for topic filter {}: {:?}",
+                        topic_filter, options
+                    );
+                    let reason_code = proto::ReasonCode::IMPLEMENTATION_SPECIFIC_ERROR;
+                    return Ok((proto::SubAckQos::Failure(reason_code), None));
+                }
+
+                if let Some(max_subscriptions) = self.config.max_subscriptions {
+                    let subscriptions = &self.state.subscriptions;
+                    if subscriptions.len() >= max_subscriptions
+                        && !subscriptions.contains_key(&topic_filter)
+                    {
+                        return Err(Error::SubscriptionQuotaExceeded);
+                    }
+                }
 
                 let subscription = Subscription::new(filter, qos);
                 self.state
@@ -124,7 +274,8 @@ impl ConnectedSession {
             }
             Err(e) => {
                 warn!("invalid topic filter {}: {}", subscribe_to.topic_filter, e);
-                Ok((proto::SubAckQos::Failure, None))
+                let reason_code = proto::ReasonCode::TOPIC_FILTER_INVALID;
+                Ok((proto::SubAckQos::Failure(reason_code), None))
             }
         }
     }
@@ -133,12 +284,18 @@ impl ConnectedSession {
         &mut self,
         unsubscribe: &proto::Unsubscribe,
     ) -> Result<proto::UnsubAck, Error> {
-        for filter in &unsubscribe.unsubscribe_from {
-            self.state.remove_subscription(&filter);
-        }
+        let reason_codes = unsubscribe
+            .unsubscribe_from
+            .iter()
+            .map(|filter| match self.state.remove_subscription(&filter) {
+                Some(_) => proto::ReasonCode::SUCCESS,
+                None => proto::ReasonCode::NO_SUBSCRIPTION_EXISTED,
+            })
+            .collect();
 
         let unsuback = proto::UnsubAck {
             packet_identifier: unsubscribe.packet_identifier,
+            reason_codes,
         };
         Ok(unsuback)
     }
@@ -152,11 +309,17 @@ impl ConnectedSession {
 #[derive(Debug)]
 pub struct OfflineSession {
     state: SessionState,
+    config: SessionConfig,
+    offline_since: Instant,
 }
 
 impl OfflineSession {
-    fn new(state: SessionState) -> Self {
-        Self { state }
+    fn new(state: SessionState, config: SessionConfig) -> Self {
+        Self {
+            state,
+            config,
+            offline_since: Instant::now(),
+        }
     }
 
     pub fn client_id(&self) -> &ClientId {
@@ -167,17 +330,29 @@ impl OfflineSession {
         &self.state
     }
 
+    /// Returns true once the session has been offline for longer than
+    /// the configured session expiration.
+    pub fn is_expired(&self) -> bool {
+        self.offline_since.elapsed() >= self.config.expiration
+    }
+
     pub fn publish_to(
         &mut self,
         publication: proto::Publication,
     ) -> Result<Option<ClientEvent>, Error> {
-        self.state.queue_publish(publication)?;
-        Ok(None)
+        match self.state.queue_publish(publication, &self.config) {
+            // There is no connection to drop for an offline session,
+            // so the new message is dropped instead.
+            Ok(()) | Err(Error::SessionQueueFull) => Ok(None),
+            Err(e) => Err(e),
+        }
     }
 
     pub fn into_online(self) -> Result<(SessionState, Vec<ClientEvent>), Error> {
-        let mut events = Vec::with_capacity(MAX_INFLIGHT_MESSAGES);
-        let OfflineSession { mut state } = self;
+        let OfflineSession {
+            mut state, config, ..
+        } = self;
+        let mut events = Vec::with_capacity(config.max_inflight_messages);
 
         // Handle the outstanding QoS 1 and QoS 2 packets
         for (id, publish) in &state.waiting_to_be_acked {
@@ -216,12 +391,14 @@ impl OfflineSession {
         for completed in &state.waiting_to_be_completed {
             events.push(ClientEvent::PubRel(proto::PubRel {
                 packet_identifier: *completed,
+                reason_code: proto::ReasonCode::SUCCESS,
+                properties: proto::Properties::default(),
             }));
         }
 
         // Dequeue any queued messages - up to the max inflight count
-        while state.allowed_to_send() {
-            match state.waiting_to_be_sent.pop_front() {
+        while state.allowed_to_send(&config) {
+            match state.dequeue_unexpired(config.retention()) {
                 Some(publication) => {
                     debug!("dequeueing a message for {}", state.client_id);
                     let event = state.prepare_to_send(&publication)?;
@@ -283,7 +460,7 @@ pub struct SessionState {
     packet_identifiers: PacketIdentifiers,
     packet_identifiers_qos0: PacketIdentifiers,
 
-    waiting_to_be_sent: VecDeque<proto::Publication>,
+    waiting_to_be_sent: PublicationQueue,
 
     // for incoming messages - QoS2
     waiting_to_be_released: HashMap<proto::PacketIdentifier, proto::Publish>,
@@ -292,6 +469,18 @@ pub struct SessionState {
     waiting_to_be_acked: HashMap<proto::PacketIdentifier, Publish>,
     waiting_to_be_acked_qos0: HashMap<proto::PacketIdentifier, Publish>,
     waiting_to_be_completed: HashSet<proto::PacketIdentifier>,
+
+    // messages dropped from the queue which the broker has not counted yet
+    #[serde(skip)]
+    dropped_messages: u64,
+
+    // messages handed out to be sent which the broker has not counted yet
+    #[serde(skip)]
+    sent_messages: u64,
+
+    // changes to subscriptions and queued messages not collected yet, if journaled
+    #[serde(skip)]
+    changes: Option<Vec<SessionChange>>,
 }
 
 impl SessionState {
@@ -302,11 +491,14 @@ impl SessionState {
             packet_identifiers: PacketIdentifiers::default(),
             packet_identifiers_qos0: PacketIdentifiers::default(),
 
-            waiting_to_be_sent: VecDeque::new(),
+            waiting_to_be_sent: PublicationQueue::default(),
             waiting_to_be_acked: HashMap::new(),
             waiting_to_be_acked_qos0: HashMap::new(),
             waiting_to_be_released: HashMap::new(),
             waiting_to_be_completed: HashSet::new(),
+            dropped_messages: 0,
+            sent_messages: 0,
+            changes: None,
         }
     }
 
@@ -314,21 +506,134 @@ impl SessionState {
         &self.client_id
     }
 
+    pub fn subscriptions(&self) -> &HashMap<String, Subscription> {
+        &self.subscriptions
+    }
+
     pub fn update_subscription(
         &mut self,
         topic_filter: String,
         subscription: Subscription,
     ) -> Option<Subscription> {
+        if let Some(changes) = self.changes.as_mut() {
+            changes.push(SessionChange::Subscribed(
+                topic_filter.clone(),
+                subscription.clone(),
+            ));
+        }
         self.subscriptions.insert(topic_filter, subscription)
     }
 
     pub fn remove_subscription(&mut self, topic_filter: &str) -> Option<Subscription> {
-        self.subscriptions.remove(topic_filter)
+        let removed = self.subscriptions.remove(topic_filter);
+        if let (Some(changes), Some(_)) = (self.changes.as_mut(), &removed) {
+            changes.push(SessionChange::Unsubscribed(topic_filter.to_string()));
+        }
+        removed
+    }
+
+    /// Starts journaling changes to subscriptions and queued messages.
+    pub fn record_changes(&mut self) {
+        self.changes = Some(Vec::new());
+    }
+
+    /// Returns the changes made since the last call, or `None` if changes
+    /// are not journaled.
+    pub fn take_changes(&mut self) -> Option<Vec<SessionChange>> {
+        self.changes
+            .as_mut()
+            .map(|changes| mem::replace(changes, Vec::new()))
+    }
+
+    /// Returns the changes which create this session from scratch.
+    pub fn to_changes(&self) -> Vec<SessionChange> {
+        let subscriptions = self
+            .subscriptions
+            .iter()
+            .map(|(topic_filter, subscription)| {
+                SessionChange::Subscribed(topic_filter.clone(), subscription.clone())
+            });
+        let queued = self
+            .waiting_to_be_sent
+            .iter()
+            .map(|publication| SessionChange::Queued(publication.clone()));
+
+        std::iter::once(SessionChange::Created)
+            .chain(subscriptions)
+            .chain(queued)
+            .collect()
+    }
+
+    /// Applies a change replayed from the write-ahead log.
+    pub fn apply(&mut self, change: SessionChange) {
+        match change {
+            SessionChange::Created => {
+                self.subscriptions.clear();
+                self.waiting_to_be_sent.clear();
+            }
+            SessionChange::Subscribed(topic_filter, subscription) => {
+                self.subscriptions.insert(topic_filter, subscription);
+            }
+            SessionChange::Unsubscribed(topic_filter) => {
+                self.subscriptions.remove(&topic_filter);
+            }
+            SessionChange::Queued(publication) => self.waiting_to_be_sent.push_back(publication),
+            SessionChange::LegacyQueued(publication) => self
+                .waiting_to_be_sent
+                .push_back(ReceivedPublication::new(publication)),
+            SessionChange::Dequeued => {
+                self.waiting_to_be_sent.pop_front();
+            }
+        }
+    }
+
+    /// Number of messages waiting to be sent.
+    pub fn queued_messages(&self) -> usize {
+        self.waiting_to_be_sent.len()
     }
 
-    pub fn queue_publish(&mut self, publication: proto::Publication) -> Result<(), Error> {
+    /// Number of messages sent and not yet acknowledged.
+    pub fn inflight_messages(&self) -> usize {
+        self.waiting_to_be_acked.len()
+            + self.waiting_to_be_acked_qos0.len()
+            + self.waiting_to_be_completed.len()
+    }
+
+    /// Drops expired messages from the queue.
+    ///
+    /// Unlike messages leaving the front of the queue, these removals are not
+    /// journaled, so they must be followed by a snapshot.
+    pub fn expire_queued(&mut self, retention: &RetentionPolicy, now: SystemTime) {
+        let queued = self.waiting_to_be_sent.len();
+        self.waiting_to_be_sent
+            .retain(|publication| !retention.is_expired(publication, now));
+
+        let expired = queued - self.waiting_to_be_sent.len();
+        if expired > 0 {
+            debug!("{} queued messages for {} expired", expired, self.client_id);
+            self.dropped_messages += expired as u64;
+        }
+    }
+
+    /// Returns the number of messages dropped since the last call.
+    pub fn take_dropped_messages(&mut self) -> u64 {
+        std::mem::replace(&mut self.dropped_messages, 0)
+    }
+
+    /// Returns the number of messages handed out to be sent since the last call.
+    ///
+    /// Messages sent again after a reconnect are not counted twice.
+    pub fn take_sent_messages(&mut self) -> u64 {
+        std::mem::replace(&mut self.sent_messages, 0)
+    }
+
+    pub fn queue_publish(
+        &mut self,
+        publication: proto::Publication,
+        config: &SessionConfig,
+    ) -> Result<(), Error> {
         if let Some(publication) = self.filter(publication) {
-            self.waiting_to_be_sent.push_back(publication);
+            self.enqueue(publication, config)?;
         }
         Ok(())
     }
@@ -338,13 +643,14 @@ impl SessionState {
     pub fn publish_to(
         &mut self,
         publication: proto::Publication,
+        config: &SessionConfig,
     ) -> Result<Option<ClientEvent>, Error> {
         if let Some(publication) = self.filter(publication) {
-            if self.allowed_to_send() {
+            if self.allowed_to_send(config) {
                 let event = self.prepare_to_send(&publication)?;
                 Ok(Some(event))
             } else {
-                self.waiting_to_be_sent.push_back(publication);
+                self.enqueue(publication, config)?;
                 Ok(None)
             }
         } else {
@@ -352,6 +658,102 @@ impl SessionState {
         }
     }
 
+    /// Adds a publication to the queue of messages waiting to be sent,
+    /// applying the configured queue limits.
+    ///
+    /// Returns `Error::SessionQueueFull` if the queue is full and the
+    /// session should be disconnected.
+    fn enqueue(
+        &mut self,
+        publication: proto::Publication,
+        config: &SessionConfig,
+    ) -> Result<(), Error> {
+        let size = publication.payload.len() as u64;
+        if size > config.max_message_size {
+            warn!(
+                "dropping message of {} bytes on topic \"{}\" for {}. message exceeds max queued message size",
+                size, publication.topic_name, self.client_id
+            );
+            self.dropped_messages += 1;
+            return Ok(());
+        }
+
+        let is_full = |queue: &PublicationQueue| queue_is_full(queue, size, config);
+
+        if is_full(&self.waiting_to_be_sent) {
+            match config.when_full {
+                QueueFullAction::DropNew => {
+                    debug!(
+                        "queue is full for {}. dropping new message on topic \"{}\"",
+                        self.client_id, publication.topic_name
+                    );
+                    self.dropped_messages += 1;
+                    return Ok(());
+                }
+                QueueFullAction::DropOld => {
+                    while is_full(&self.waiting_to_be_sent) {
+                        if let Some(dropped) = self.dequeue() {
+                            let dropped = dropped.publication();
+                            debug!(
+                                "queue is full for {}. dropping old message on topic \"{}\"",
+                                self.client_id, dropped.topic_name
+                            );
+                            self.dropped_messages += 1;
+                        } else {
+                            // the message does not fit even into an empty queue
+                            debug!(
+                                "queue is too small for {}. dropping new message on topic \"{}\"",
+                                self.client_id, publication.topic_name
+                            );
+                            self.dropped_messages += 1;
+                            return Ok(());
+                        }
+                    }
+                }
+                QueueFullAction::Disconnect => {
+                    warn!("queue is full for {}", self.client_id);
+                    self.dropped_messages += 1;
+                    return Err(Error::SessionQueueFull);
+                }
+            }
+        }
+
+        let publication = ReceivedPublication::new(publication);
+        if let Some(changes) = self.changes.as_mut() {
+            changes.push(SessionChange::Queued(publication.clone()));
+        }
+        self.waiting_to_be_sent.push_back(publication);
+        Ok(())
+    }
+
+    /// Removes the oldest message from the queue of messages waiting to be sent.
+    fn dequeue(&mut self) -> Option<ReceivedPublication> {
+        let publication = self.waiting_to_be_sent.pop_front();
+        if let (Some(changes), Some(_)) = (self.changes.as_mut(), &publication) {
+            changes.push(SessionChange::Dequeued);
+        }
+        publication
+    }
+
+    /// Removes the oldest message which has not expired from the queue,
+    /// dropping the expired messages in front of it.
+    fn dequeue_unexpired(&mut self, retention: &RetentionPolicy) -> Option<proto::Publication> {
+        let now = SystemTime::now();
+        while let Some(publication) = self.dequeue() {
+            if !retention.is_expired(&publication, now) {
+                return Some(publication.into_forwarded(now));
+            }
+
+            debug!(
+                "dropping expired message on topic \"{}\" for {}",
+                publication.publication().topic_name,
+                self.client_id
+            );
+            self.dropped_messages += 1;
+        }
+        None
+    }
+
     pub fn handle_publish(
         &mut self,
         publish: proto::Publish,
@@ -363,6 +765,7 @@ impl SessionState {
                     qos: proto::QoS::AtMostOnce,
                     retain: publish.retain,
                     payload: publish.payload,
+                    properties: publish.properties,
                 };
                 (Some(publication), None)
             }
@@ -372,15 +775,24 @@ impl SessionState {
                     qos: proto::QoS::AtLeastOnce,
                     retain: publish.retain,
                     payload: publish.payload,
+                    properties: publish.properties,
+                };
+                let puback = proto::PubAck {
+                    packet_identifier,
+                    reason_code: proto::ReasonCode::SUCCESS,
+                    properties: proto::Properties::default(),
                 };
-                let puback = proto::PubAck { packet_identifier };
                 let event = ClientEvent::PubAck(puback);
                 (Some(publication), Some(event))
             }
             proto::PacketIdentifierDupQoS::ExactlyOnce(packet_identifier, _dup) => {
                 self.waiting_to_be_released
                     .insert(packet_identifier, publish);
-                let pubrec = proto::PubRec { packet_identifier };
+                let pubrec = proto::PubRec {
+                    packet_identifier,
+                    reason_code: proto::ReasonCode::SUCCESS,
+                    properties: proto::Properties::default(),
+                };
                 let event = ClientEvent::PubRec(pubrec);
                 (None, Some(event))
             }
@@ -394,6 +806,8 @@ impl SessionState {
             .insert(pubrec.packet_identifier);
         let pubrel = proto::PubRel {
             packet_identifier: pubrec.packet_identifier,
+            reason_code: proto::ReasonCode::SUCCESS,
+            properties: proto::Properties::default(),
         };
         Ok(Some(ClientEvent::PubRel(pubrel)))
     }
@@ -410,6 +824,7 @@ impl SessionState {
                 qos: proto::QoS::ExactlyOnce,
                 retain: publish.retain,
                 payload: publish.payload,
+                properties: publish.properties,
             });
         Ok(publication)
     }
@@ -417,33 +832,39 @@ impl SessionState {
     pub fn handle_pubcomp(
         &mut self,
         pubcomp: &proto::PubComp,
+        config: &SessionConfig,
     ) -> Result<Option<ClientEvent>, Error> {
         self.waiting_to_be_completed
             .remove(&pubcomp.packet_identifier);
         self.packet_identifiers.discard(pubcomp.packet_identifier);
-        self.try_publish()
+        self.try_publish(config)
     }
 
-    pub fn handle_puback(&mut self, puback: &proto::PubAck) -> Result<Option<ClientEvent>, Error> {
+    pub fn handle_puback(
+        &mut self,
+        puback: &proto::PubAck,
+        config: &SessionConfig,
+    ) -> Result<Option<ClientEvent>, Error> {
         debug!("discarding packet identifier {}", puback.packet_identifier);
         self.waiting_to_be_acked.remove(&puback.packet_identifier);
         self.packet_identifiers.discard(puback.packet_identifier);
-        self.try_publish()
+        self.try_publish(config)
     }
 
     pub fn handle_puback0(
         &mut self,
         id: proto::PacketIdentifier,
+        config: &SessionConfig,
     ) -> Result<Option<ClientEvent>, Error> {
         debug!("discarding QoS 0 packet identifier {}", id);
         self.waiting_to_be_acked_qos0.remove(&id);
         self.packet_identifiers_qos0.discard(id);
-        self.try_publish()
+        self.try_publish(config)
     }
 
-    fn try_publish(&mut self) -> Result<Option<ClientEvent>, Error> {
-        if self.allowed_to_send() {
-            if let Some(publication) = self.waiting_to_be_sent.pop_front() {
+    fn try_publish(&mut self, config: &SessionConfig) -> Result<Option<ClientEvent>, Error> {
+        if self.allowed_to_send(config) {
+            if let Some(publication) = self.dequeue_unexpired(config.retention()) {
                 let event = self.prepare_to_send(&publication)?;
                 return Ok(Some(event));
             }
@@ -451,11 +872,8 @@ impl SessionState {
         Ok(None)
     }
 
-    fn allowed_to_send(&self) -> bool {
-        let num_inflight = self.waiting_to_be_acked.len()
-            + self.waiting_to_be_acked_qos0.len()
-            + self.waiting_to_be_completed.len();
-        num_inflight < MAX_INFLIGHT_MESSAGES
+    fn allowed_to_send(&self, config: &SessionConfig) -> bool {
+        self.inflight_messages() < config.max_inflight_messages
     }
 
     fn filter(&self, mut publication: proto::Publication) -> Option<proto::Publication> {
@@ -473,6 +891,7 @@ impl SessionState {
     }
 
     fn prepare_to_send(&mut self, publication: &proto::Publication) -> Result<ClientEvent, Error> {
+        self.sent_messages += 1;
         let publish = match publication.qos {
             proto::QoS::AtMostOnce => {
                 let id = self.packet_identifiers_qos0.reserve()?;
@@ -481,6 +900,7 @@ impl SessionState {
                     retain: publication.retain,
                     topic_name: publication.topic_name.to_owned(),
                     payload: publication.payload.to_owned(),
+                    properties: publication.properties.to_owned(),
                 };
                 Publish::QoS0(id, packet)
             }
@@ -493,6 +913,7 @@ impl SessionState {
                     retain: publication.retain,
                     topic_name: publication.topic_name.to_owned(),
                     payload: publication.payload.to_owned(),
+                    properties: publication.properties.to_owned(),
                 };
                 Publish::QoS12(id, packet)
             }
@@ -505,6 +926,7 @@ impl SessionState {
                     retain: publication.retain,
                     topic_name: publication.topic_name.to_owned(),
                     payload: publication.payload.to_owned(),
+                    properties: publication.properties.to_owned(),
                 };
                 Publish::QoS12(id, packet)
             }
@@ -530,15 +952,19 @@ impl SessionState {
     ) -> (
         ClientId,
         HashMap<String, Subscription>,
-        VecDeque<proto::Publication>,
+        VecDeque<ReceivedPublication>,
     ) {
-        (self.client_id, self.subscriptions, self.waiting_to_be_sent)
+        (
+            self.client_id,
+            self.subscriptions,
+            self.waiting_to_be_sent.publications,
+        )
     }
 
     pub fn from_parts(
         client_id: ClientId,
         subscriptions: HashMap<String, Subscription>,
-        waiting_to_be_sent: VecDeque<proto::Publication>,
+        waiting_to_be_sent: VecDeque<ReceivedPublication>,
     ) -> Self {
         Self {
             client_id,
@@ -546,11 +972,14 @@ impl SessionState {
             packet_identifiers: PacketIdentifiers::default(),
             packet_identifiers_qos0: PacketIdentifiers::default(),
 
-            waiting_to_be_sent,
+            waiting_to_be_sent: waiting_to_be_sent.into(),
             waiting_to_be_acked: HashMap::new(),
             waiting_to_be_acked_qos0: HashMap::new(),
             waiting_to_be_released: HashMap::new(),
             waiting_to_be_completed: HashSet::new(),
+            dropped_messages: 0,
+            sent_messages: 0,
+            changes: None,
         }
     }
 }
@@ -564,21 +993,28 @@ pub enum Session {
 }
 
 impl Session {
-    pub fn new_transient(auth_id: AuthId, connreq: ConnReq) -> Self {
+    pub fn new_transient(auth_id: AuthId, connreq: ConnReq, config: SessionConfig) -> Self {
         let state = SessionState::new(connreq.client_id().clone());
         let (connect, handle) = connreq.into_parts();
-        let connected = ConnectedSession::new(auth_id, state, connect.will, handle);
+        let will = connect.will.map(into_will_publication);
+        let connected = ConnectedSession::new(auth_id, state, will, handle, config);
         Self::Transient(connected)
     }
 
-    pub fn new_persistent(auth_id: AuthId, connreq: ConnReq, state: SessionState) -> Self {
+    pub fn new_persistent(
+        auth_id: AuthId,
+        connreq: ConnReq,
+        state: SessionState,
+        config: SessionConfig,
+    ) -> Self {
         let (connect, handle) = connreq.into_parts();
-        let connected = ConnectedSession::new(auth_id, state, connect.will, handle);
+        let will = connect.will.map(into_will_publication);
+        let connected = ConnectedSession::new(auth_id, state, will, handle, config);
         Self::Persistent(connected)
     }
 
-    pub fn new_offline(state: SessionState) -> Self {
-        let offline = OfflineSession::new(state);
+    pub fn new_offline(state: SessionState, config: SessionConfig) -> Self {
+        let offline = OfflineSession::new(state, config);
         Self::Offline(offline)
     }
 
@@ -601,6 +1037,35 @@ impl Session {
         }
     }
 
+    /// Returns the state of the session, unless it is disconnecting.
+    pub fn state(&self) -> Option<&SessionState> {
+        match self {
+            Self::Transient(connected) => Some(&connected.state),
+            Self::Persistent(connected) => Some(&connected.state),
+            Self::Offline(offline) => Some(&offline.state),
+            Self::Disconnecting(_) => None,
+        }
+    }
+
+    /// Returns the state of the session, unless it is disconnecting.
+    pub fn state_mut(&mut self) -> Option<&mut SessionState> {
+        match self {
+            Self::Transient(connected) => Some(&mut connected.state),
+            Self::Persistent(connected) => Some(&mut connected.state),
+            Self::Offline(offline) => Some(&mut offline.state),
+            Self::Disconnecting(_) => None,
+        }
+    }
+
+    /// Returns true if the session is part of a broker state snapshot.
+    pub fn is_persisted(&self) -> bool {
+        match self {
+            Self::Persistent(_) => true,
+            Self::Offline(offline) => !offline.is_expired(),
+            Self::Transient(_) | Self::Disconnecting(_) => false,
+        }
+    }
+
     pub fn auth_id(&self) -> Result<&AuthId, Error> {
         match self {
             Self::Transient(connected) => Ok(connected.auth_id()),
@@ -685,6 +1150,24 @@ impl Session {
         }
     }
 
+    pub fn take_dropped_messages(&mut self) -> u64 {
+        match self {
+            Self::Transient(connected) => connected.state.take_dropped_messages(),
+            Self::Persistent(connected) => connected.state.take_dropped_messages(),
+            Self::Offline(offline) => offline.state.take_dropped_messages(),
+            Self::Disconnecting(_) => 0,
+        }
+    }
+
+    pub fn take_sent_messages(&mut self) -> u64 {
+        match self {
+            Self::Transient(connected) => connected.state.take_sent_messages(),
+            Self::Persistent(connected) => connected.state.take_sent_messages(),
+            Self::Offline(offline) => offline.state.take_sent_messages(),
+            Self::Disconnecting(_) => 0,
+        }
+    }
+
     pub fn publish_to(
         &mut self,
         publication: &proto::Publication,
@@ -731,6 +1214,107 @@ impl Session {
     }
 }
 
+/// Drops the will properties which only apply to the will itself.
+///
+/// Wills are published as soon as the connection closes, so the will
+/// delay interval is not honored.
+fn into_will_publication(mut will: proto::Publication) -> proto::Publication {
+    will.properties.retain(|property| match property {
+        proto::Property::WillDelayInterval(_) => false,
+        _ => true,
+    });
+    will
+}
+
+/// Messages waiting to be sent, along with the total size of their payloads.
+///
+/// Serialized as the sequence of messages alone.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct PublicationQueue {
+    publications: VecDeque<ReceivedPublication>,
+    size: u64,
+}
+
+impl PublicationQueue {
+    fn len(&self) -> usize {
+        self.publications.len()
+    }
+
+    /// Total size of the queued payloads in bytes.
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &ReceivedPublication> {
+        self.publications.iter()
+    }
+
+    fn push_back(&mut self, publication: ReceivedPublication) {
+        self.size += payload_size(&publication);
+        self.publications.push_back(publication);
+    }
+
+    fn pop_front(&mut self) -> Option<ReceivedPublication> {
+        let publication = self.publications.pop_front();
+        if let Some(publication) = &publication {
+            self.size -= payload_size(publication);
+        }
+        publication
+    }
+
+    fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&ReceivedPublication) -> bool,
+    {
+        let size = &mut self.size;
+        self.publications.retain(|publication| {
+            let keep = f(publication);
+            if !keep {
+                *size -= payload_size(publication);
+            }
+            keep
+        });
+    }
+
+    fn clear(&mut self) {
+        self.publications.clear();
+        self.size = 0;
+    }
+}
+
+fn queue_is_full(queue: &PublicationQueue, size: u64, config: &SessionConfig) -> bool {
+    queue.len() >= config.max_queued_messages || queue.size() + size > config.max_queued_size
+}
+
+fn payload_size(publication: &ReceivedPublication) -> u64 {
+    publication.publication().payload.len() as u64
+}
+
+impl From<VecDeque<ReceivedPublication>> for PublicationQueue {
+    fn from(publications: VecDeque<ReceivedPublication>) -> Self {
+        let size = publications.iter().map(payload_size).sum();
+        Self { publications, size }
+    }
+}
+
+impl Serialize for PublicationQueue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.publications.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PublicationQueue {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        VecDeque::deserialize(deserializer).map(Self::from)
+    }
+}
+
 #[derive(Clone)]
 struct IdentifiersInUse(Box<[usize; PacketIdentifiers::SIZE]>);
 
@@ -903,7 +1487,7 @@ pub(crate) mod tests {
             subscriptions in hash_map(arb_topic(), arb_subscription(), 0..10),
             packet_identifiers in arb_packet_identifiers(),
             packet_identifiers_qos0 in arb_packet_identifiers(),
-            waiting_to_be_sent in vec_deque(arb_publication(), 0..10),
+            waiting_to_be_sent in vec_deque(arb_received_publication(), 0..10),
             waiting_to_be_released in hash_map(arb_packet_identifier(), arb_proto_publish(), 0..10),
             waiting_to_be_acked in hash_map(arb_packet_identifier(), arb_publish(), 0..10),
             waiting_to_be_acked_qos0 in hash_map(arb_packet_identifier(), arb_publish(), 0..10),
@@ -914,11 +1498,14 @@ pub(crate) mod tests {
                 subscriptions,
                 packet_identifiers,
                 packet_identifiers_qos0,
-                waiting_to_be_sent,
+                waiting_to_be_sent: waiting_to_be_sent.into(),
                 waiting_to_be_released,
                 waiting_to_be_acked,
                 waiting_to_be_acked_qos0,
                 waiting_to_be_completed,
+                dropped_messages: 0,
+                sent_messages: 0,
+                changes: None,
             }
         }
     }
@@ -938,6 +1525,7 @@ pub(crate) mod tests {
             keep_alive: Duration::default(),
             protocol_name: crate::PROTOCOL_NAME.to_string(),
             protocol_level: crate::PROTOCOL_LEVEL,
+            properties: proto::Properties::default(),
         }
     }
 
@@ -949,10 +1537,11 @@ pub(crate) mod tests {
         let handle1 = connection_handle();
         let req1 = ConnReq::new(client_id, connect1, None, handle1);
         let auth_id = "auth-id1".into();
-        let mut session = Session::new_transient(auth_id, req1);
+        let mut session = Session::new_transient(auth_id, req1, SessionConfig::default());
         let subscribe_to = proto::SubscribeTo {
             topic_filter: "topic/new".to_string(),
             qos: proto::QoS::AtMostOnce,
+            options: proto::SubscriptionOptions::default(),
         };
 
         let (ack, subscription) = session.subscribe_to(subscribe_to).unwrap();
@@ -973,6 +1562,7 @@ pub(crate) mod tests {
         let subscribe_to = proto::SubscribeTo {
             topic_filter: "topic/new".to_string(),
             qos: proto::QoS::AtLeastOnce,
+            options: proto::SubscriptionOptions::default(),
         };
 
         let (ack, subscription) = session.subscribe_to(subscribe_to).unwrap();
@@ -999,18 +1589,76 @@ pub(crate) mod tests {
         let handle1 = connection_handle();
         let req1 = ConnReq::new(client_id, connect1, None, handle1);
         let auth_id = "auth-id1".into();
-        let mut session = Session::new_transient(auth_id, req1);
+        let mut session = Session::new_transient(auth_id, req1, SessionConfig::default());
         let subscribe_to = proto::SubscribeTo {
             topic_filter: "topic/#/#".to_string(),
             qos: proto::QoS::AtMostOnce,
+            options: proto::SubscriptionOptions::default(),
         };
 
         let (ack, subscription) = session.subscribe_to(subscribe_to).unwrap();
 
-        assert_eq!(ack, proto::SubAckQos::Failure);
+        assert_eq!(
+            ack,
+            proto::SubAckQos::Failure(proto::ReasonCode::TOPIC_FILTER_INVALID)
+        );
         assert_eq!(subscription, None);
     }
 
+    #[test]
+    fn test_subscribe_to_with_unsupported_options() {
+        let id = "id1".to_string();
+        let client_id = ClientId::from(id.clone());
+        let req1 = ConnReq::new(client_id, transient_connect(id), None, connection_handle());
+        let mut session = Session::new_transient(AuthId::Anonymous, req1, SessionConfig::default());
+        let subscribe_to = |options| proto::SubscribeTo {
+            topic_filter: "topic/new".to_string(),
+            qos: proto::QoS::AtMostOnce,
+            options,
+        };
+
+        let no_local = proto::SubscriptionOptions {
+            no_local: true,
+            ..proto::SubscriptionOptions::default()
+        };
+        let retain_as_published = proto::SubscriptionOptions {
+            retain_as_published: true,
+            ..proto::SubscriptionOptions::default()
+        };
+        for options in &[no_local, retain_as_published] {
+            let (ack, subscription) = session.subscribe_to(subscribe_to(*options)).unwrap();
+
+            assert_eq!(
+                ack,
+                proto::SubAckQos::Failure(proto::ReasonCode::IMPLEMENTATION_SPECIFIC_ERROR)
+            );
+            assert_eq!(subscription, None);
+        }
+    }
+
+    #[test]
+    fn test_subscribe_to_respects_max_subscriptions() {
+        let id = "id1".to_string();
+        let client_id = ClientId::from(id.clone());
+        let req1 = ConnReq::new(client_id, transient_connect(id), None, connection_handle());
+        let config = SessionConfig::default().with_max_subscriptions(Some(1));
+        let mut session = Session::new_transient(AuthId::Anonymous, req1, config);
+        let subscribe_to = |topic_filter: &str| proto::SubscribeTo {
+            topic_filter: topic_filter.to_string(),
+            qos: proto::QoS::AtMostOnce,
+            options: proto::SubscriptionOptions::default(),
+        };
+
+        assert_matches!(session.subscribe_to(subscribe_to("topic/a")), Ok(_));
+        assert_matches!(
+            session.subscribe_to(subscribe_to("topic/b")),
+            Err(Error::SubscriptionQuotaExceeded)
+        );
+
+        // updating an existing subscription is always allowed
+        assert_matches!(session.subscribe_to(subscribe_to("topic/a")), Ok(_));
+    }
+
     #[test]
     fn test_unsubscribe() {
         let id = "id1".to_string();
@@ -1019,11 +1667,12 @@ pub(crate) mod tests {
         let handle1 = connection_handle();
         let req1 = ConnReq::new(client_id, connect1, None, handle1);
         let auth_id = AuthId::Anonymous;
-        let mut session = Session::new_transient(auth_id, req1);
+        let mut session = Session::new_transient(auth_id, req1, SessionConfig::default());
 
         let subscribe_to = proto::SubscribeTo {
             topic_filter: "topic/new".to_string(),
             qos: proto::QoS::AtMostOnce,
+            options: proto::SubscriptionOptions::default(),
         };
 
         let (ack, subscription) = session.subscribe_to(subscribe_to).unwrap();
@@ -1034,7 +1683,11 @@ pub(crate) mod tests {
             packet_identifier: proto::PacketIdentifier::new(1).unwrap(),
             unsubscribe_from: vec!["topic/different".to_string()],
         };
-        session.unsubscribe(&unsubscribe).unwrap();
+        let unsuback = session.unsubscribe(&unsubscribe).unwrap();
+        assert_eq!(
+            vec![proto::ReasonCode::NO_SUBSCRIPTION_EXISTED],
+            unsuback.reason_codes
+        );
 
         match session {
             Session::Transient(ref connected) => {
@@ -1056,6 +1709,7 @@ pub(crate) mod tests {
             proto::PacketIdentifier::new(24).unwrap(),
             unsuback.packet_identifier
         );
+        assert_eq!(vec![proto::ReasonCode::SUCCESS], unsuback.reason_codes);
 
         match session {
             Session::Transient(ref connected) => {
@@ -1069,11 +1723,13 @@ pub(crate) mod tests {
     fn test_offline_subscribe_to() {
         let id = "id1".to_string();
         let client_id = ClientId::from(id);
-        let mut session = Session::new_offline(SessionState::new(client_id));
+        let mut session =
+            Session::new_offline(SessionState::new(client_id), SessionConfig::default());
 
         let subscribe_to = proto::SubscribeTo {
             topic_filter: "topic/new".to_string(),
             qos: proto::QoS::AtMostOnce,
+            options: proto::SubscriptionOptions::default(),
         };
         let result = session.subscribe_to(subscribe_to);
         assert_matches!(result, Err(Error::SessionOffline));
@@ -1083,7 +1739,8 @@ pub(crate) mod tests {
     fn test_offline_unsubscribe() {
         let id = "id1".to_string();
         let client_id = ClientId::from(id);
-        let mut session = Session::new_offline(SessionState::new(client_id));
+        let mut session =
+            Session::new_offline(SessionState::new(client_id), SessionConfig::default());
 
         let unsubscribe = proto::Unsubscribe {
             packet_identifier: proto::PacketIdentifier::new(24).unwrap(),
@@ -1093,6 +1750,199 @@ pub(crate) mod tests {
         assert_matches!(result, Err(Error::SessionOffline));
     }
 
+    fn subscribed_state(topic_filter: &str) -> SessionState {
+        let mut state = SessionState::new(ClientId::from("id1"));
+        let subscription =
+            Subscription::new(topic_filter.parse().unwrap(), proto::QoS::AtLeastOnce);
+        state.update_subscription(topic_filter.to_string(), subscription);
+        state
+    }
+
+    fn publication(topic_name: &str, payload: &'static [u8]) -> proto::Publication {
+        proto::Publication {
+            topic_name: topic_name.to_string(),
+            qos: proto::QoS::AtLeastOnce,
+            retain: false,
+            payload: payload.into(),
+            properties: proto::Properties::default(),
+        }
+    }
+
+    fn queue_config(
+        max_queued_messages: usize,
+        max_queued_size: u64,
+        when_full: QueueFullAction,
+    ) -> SessionConfig {
+        SessionConfig::new(
+            1,
+            8,
+            max_queued_messages,
+            max_queued_size,
+            when_full,
+            Duration::from_secs(60),
+        )
+    }
+
+    fn queued_payloads(state: &SessionState) -> Vec<&[u8]> {
+        state
+            .waiting_to_be_sent
+            .iter()
+            .map(|p| p.publication().payload.as_ref())
+            .collect()
+    }
+
+    #[test]
+    fn test_publish_to_respects_max_inflight() {
+        let mut state = subscribed_state("topic/#");
+        let config = queue_config(10, 1024, QueueFullAction::DropNew);
+
+        let event = state
+            .publish_to(publication("topic/a", b"1"), &config)
+            .unwrap();
+        assert_matches!(event, Some(ClientEvent::PublishTo(_)));
+
+        let event = state
+            .publish_to(publication("topic/a", b"2"), &config)
+            .unwrap();
+        assert_matches!(event, None);
+        assert_eq!(queued_payloads(&state), vec![b"2"]);
+    }
+
+    #[test]
+    fn test_queue_publish_drop_new() {
+        let mut state = subscribed_state("topic/#");
+        let config = queue_config(2, 1024, QueueFullAction::DropNew);
+
+        for payload in &[b"1", b"2", b"3"] {
+            state
+                .queue_publish(publication("topic/a", *payload), &config)
+                .unwrap();
+        }
+
+        assert_eq!(queued_payloads(&state), vec![b"1", b"2"]);
+        assert_eq!(state.take_dropped_messages(), 1);
+    }
+
+    #[test]
+    fn test_queue_publish_drop_old() {
+        let mut state = subscribed_state("topic/#");
+        let config = queue_config(2, 1024, QueueFullAction::DropOld);
+
+        for payload in &[b"1", b"2", b"3"] {
+            state
+                .queue_publish(publication("topic/a", *payload), &config)
+                .unwrap();
+        }
+
+        assert_eq!(queued_payloads(&state), vec![b"2", b"3"]);
+        assert_eq!(state.take_dropped_messages(), 1);
+        assert_eq!(state.take_dropped_messages(), 0);
+    }
+
+    #[test]
+    fn test_queue_publish_disconnect() {
+        let mut state = subscribed_state("topic/#");
+        let config = queue_config(2, 1024, QueueFullAction::Disconnect);
+
+        state
+            .queue_publish(publication("topic/a", b"1"), &config)
+            .unwrap();
+        state
+            .queue_publish(publication("topic/a", b"2"), &config)
+            .unwrap();
+        let result = state.queue_publish(publication("topic/a", b"3"), &config);
+
+        assert_matches!(result, Err(Error::SessionQueueFull));
+        assert_eq!(queued_payloads(&state), vec![b"1", b"2"]);
+    }
+
+    #[test]
+    fn test_expire_queued() {
+        let mut state = subscribed_state("#");
+        let config = queue_config(10, 1024, QueueFullAction::DropNew);
+        let retention = RetentionPolicy::new(
+            None,
+            vec![("telemetry/#".parse().unwrap(), Some(Duration::from_secs(0)))],
+        );
+
+        state
+            .queue_publish(publication("telemetry/a", b"1"), &config)
+            .unwrap();
+        state
+            .queue_publish(publication("config/a", b"2"), &config)
+            .unwrap();
+        state.expire_queued(&retention, SystemTime::now());
+
+        assert_eq!(queued_payloads(&state), vec![b"2"]);
+        assert_eq!(1, state.waiting_to_be_sent.size());
+        assert_eq!(state.take_dropped_messages(), 1);
+    }
+
+    #[test]
+    fn test_queue_publish_max_total_space() {
+        let mut state = subscribed_state("topic/#");
+        let config = queue_config(10, 6, QueueFullAction::DropOld);
+
+        for payload in &[b"111", b"222", b"333"] {
+            state
+                .queue_publish(publication("topic/a", *payload), &config)
+                .unwrap();
+        }
+
+        assert_eq!(queued_payloads(&state), vec![b"222", b"333"]);
+        assert_eq!(6, state.waiting_to_be_sent.size());
+
+        state.dequeue();
+        assert_eq!(3, state.waiting_to_be_sent.size());
+    }
+
+    #[test]
+    fn test_queue_publish_max_message_size() {
+        let mut state = subscribed_state("topic/#");
+        let config = queue_config(10, 1024, QueueFullAction::DropNew);
+
+        state
+            .queue_publish(publication("topic/a", b"too large message"), &config)
+            .unwrap();
+        state
+            .queue_publish(publication("topic/a", b"small"), &config)
+            .unwrap();
+
+        assert_eq!(queued_payloads(&state), vec![b"small"]);
+        assert_eq!(state.take_dropped_messages(), 1);
+    }
+
+    #[test]
+    fn test_offline_drops_new_message_when_configured_to_disconnect() {
+        let mut state = subscribed_state("topic/#");
+        let config = queue_config(1, 1024, QueueFullAction::Disconnect);
+        state
+            .queue_publish(publication("topic/a", b"1"), &config)
+            .unwrap();
+        let mut session = Session::new_offline(state, config);
+
+        let result = session.publish_to(&publication("topic/a", b"2"));
+        assert_matches!(result, Ok(None));
+        match session {
+            Session::Offline(ref offline) => {
+                assert_eq!(queued_payloads(&offline.state), vec![b"1"]);
+            }
+            _ => panic!("not offline"),
+        }
+    }
+
+    #[test]
+    fn test_offline_session_expiration() {
+        let state = SessionState::new(ClientId::from("id1"));
+        let config =
+            SessionConfig::new(1, 8, 1, 8, QueueFullAction::DropNew, Duration::from_secs(0));
+        let session = OfflineSession::new(state.clone(), config);
+        assert!(session.is_expired());
+
+        let session = OfflineSession::new(state, SessionConfig::default());
+        assert!(!session.is_expired());
+    }
+
     #[test]
     fn packet_identifiers() {
         #[cfg(target_pointer_width = "32")]
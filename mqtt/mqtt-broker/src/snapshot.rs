@@ -2,10 +2,12 @@ use tokio::sync::mpsc::{self, Receiver, Sender};
 use tracing::{info, warn};
 
 use crate::persist::Persist;
+use crate::wal::StateChange;
 use crate::{BrokerState, Error};
 
 enum Event {
     State(BrokerState),
+    Changes(Vec<StateChange>),
     Shutdown,
 }
 
@@ -20,6 +22,14 @@ impl StateSnapshotHandle {
             .map_err(|_| Error::SendSnapshotMessage)?;
         Ok(())
     }
+
+    pub async fn send_changes(&mut self, changes: Vec<StateChange>) -> Result<(), Error> {
+        self.0
+            .send(Event::Changes(changes))
+            .await
+            .map_err(|_| Error::SendSnapshotMessage)?;
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -72,6 +82,11 @@ where
                         warn!(message = "an error occurred persisting state snapshot.", error=%e);
                     }
                 }
+                Event::Changes(changes) => {
+                    if let Err(e) = self.persistor.append(changes).await {
+                        warn!(message = "an error occurred persisting state changes.", error=%e);
+                    }
+                }
                 Event::Shutdown => {
                     info!("state snapshotter shutting down...");
                     break;
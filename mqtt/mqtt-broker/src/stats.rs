@@ -0,0 +1,551 @@
+use std::collections::HashSet;
+use std::fmt::{self, Write};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use mqtt3::proto;
+use tokio::sync::watch;
+
+use crate::limits::RateLimitCounters;
+use crate::subscription::{is_topic_level, TOPIC_SEPARATOR};
+use crate::ClientId;
+
+static SYS_PREFIX: &str = "$SYS/broker";
+
+/// A point-in-time view of the broker's sessions and message counters.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BrokerStats {
+    retained_messages: usize,
+    messages_received: u64,
+    messages_sent: u64,
+    messages_dropped: u64,
+    connections_refused: u64,
+    subscriptions_refused: u64,
+    messages_throttled: u64,
+    rate_limit_disconnects: u64,
+    sessions: Vec<SessionStats>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionStats {
+    client_id: ClientId,
+    connected: bool,
+    queued_messages: usize,
+    inflight_messages: usize,
+}
+
+impl SessionStats {
+    pub fn new(
+        client_id: ClientId,
+        connected: bool,
+        queued_messages: usize,
+        inflight_messages: usize,
+    ) -> Self {
+        Self {
+            client_id,
+            connected,
+            queued_messages,
+            inflight_messages,
+        }
+    }
+
+    pub fn client_id(&self) -> &ClientId {
+        &self.client_id
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    pub fn queued_messages(&self) -> usize {
+        self.queued_messages
+    }
+
+    pub fn inflight_messages(&self) -> usize {
+        self.inflight_messages
+    }
+}
+
+impl BrokerStats {
+    pub fn retained_messages(&self) -> usize {
+        self.retained_messages
+    }
+
+    pub fn messages_received(&self) -> u64 {
+        self.messages_received
+    }
+
+    pub fn messages_sent(&self) -> u64 {
+        self.messages_sent
+    }
+
+    pub fn messages_dropped(&self) -> u64 {
+        self.messages_dropped
+    }
+
+    /// Connections refused because a connection quota was reached.
+    pub fn connections_refused(&self) -> u64 {
+        self.connections_refused
+    }
+
+    /// Subscriptions refused because a session reached its maximum number of
+    /// subscriptions.
+    pub fn subscriptions_refused(&self) -> u64 {
+        self.subscriptions_refused
+    }
+
+    /// Publications delayed because their client exceeded its publish rate.
+    pub fn messages_throttled(&self) -> u64 {
+        self.messages_throttled
+    }
+
+    /// Connections dropped because their client exceeded its publish rate.
+    pub fn rate_limit_disconnects(&self) -> u64 {
+        self.rate_limit_disconnects
+    }
+
+    pub fn sessions(&self) -> &[SessionStats] {
+        &self.sessions
+    }
+
+    pub fn connected_sessions(&self) -> usize {
+        self.sessions.iter().filter(|s| s.connected).count()
+    }
+
+    pub fn offline_sessions(&self) -> usize {
+        self.sessions.iter().filter(|s| !s.connected).count()
+    }
+
+    pub fn queued_messages(&self) -> usize {
+        self.sessions.iter().map(|s| s.queued_messages).sum()
+    }
+
+    pub fn inflight_messages(&self) -> usize {
+        self.sessions.iter().map(|s| s.inflight_messages).sum()
+    }
+
+    /// Retained `$SYS/broker/...` publications describing these statistics.
+    ///
+    /// Topics of sessions that were reported in `previous` but are gone now
+    /// are cleared with an empty retained message.
+    pub fn publications(&self, previous: &BrokerStats) -> Vec<proto::Publication> {
+        let mut publications = vec![
+            sys("clients/connected", self.connected_sessions()),
+            sys("clients/disconnected", self.offline_sessions()),
+            sys("messages/retained", self.retained_messages),
+            sys("messages/received", self.messages_received),
+            sys("messages/sent", self.messages_sent),
+            sys("messages/dropped", self.messages_dropped),
+            sys("messages/queued", self.queued_messages()),
+            sys("messages/inflight", self.inflight_messages()),
+            sys("messages/throttled", self.messages_throttled),
+            sys("clients/refused", self.connections_refused),
+            sys("clients/rate_limited", self.rate_limit_disconnects),
+            sys("subscriptions/refused", self.subscriptions_refused),
+        ];
+
+        // Client ids which are not a valid topic level get no topics of their own.
+        let sessions = self
+            .sessions
+            .iter()
+            .filter(|s| is_topic_level(s.client_id.as_str()))
+            .collect::<Vec<_>>();
+        for session in &sessions {
+            let topic = format!("clients/{}", session.client_id);
+            publications.push(sys(&format!("{}/queued", topic), session.queued_messages));
+            publications.push(sys(
+                &format!("{}/inflight", topic),
+                session.inflight_messages,
+            ));
+        }
+
+        let current = sessions
+            .iter()
+            .map(|s| &s.client_id)
+            .collect::<HashSet<_>>();
+        for session in &previous.sessions {
+            if is_topic_level(session.client_id.as_str()) && !current.contains(&session.client_id) {
+                let topic = format!("clients/{}", session.client_id);
+                publications.push(clear(&format!("{}/queued", topic)));
+                publications.push(clear(&format!("{}/inflight", topic)));
+            }
+        }
+
+        publications
+    }
+
+    /// Renders these statistics in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        self.write_prometheus(&mut out)
+            .expect("writing to a string never fails");
+        out
+    }
+
+    fn write_prometheus(&self, out: &mut String) -> fmt::Result {
+        metric_header(out, "mqtt_sessions", "Number of sessions.", "gauge")?;
+        writeln!(
+            out,
+            "mqtt_sessions{{state=\"connected\"}} {}",
+            self.connected_sessions()
+        )?;
+        writeln!(
+            out,
+            "mqtt_sessions{{state=\"offline\"}} {}",
+            self.offline_sessions()
+        )?;
+
+        metric_header(
+            out,
+            "mqtt_retained_messages",
+            "Number of retained messages.",
+            "gauge",
+        )?;
+        writeln!(out, "mqtt_retained_messages {}", self.retained_messages)?;
+
+        metric_header(
+            out,
+            "mqtt_messages_received_total",
+            "Publications received from clients.",
+            "counter",
+        )?;
+        writeln!(
+            out,
+            "mqtt_messages_received_total {}",
+            self.messages_received
+        )?;
+
+        metric_header(
+            out,
+            "mqtt_messages_sent_total",
+            "Publications routed to subscribed sessions.",
+            "counter",
+        )?;
+        writeln!(out, "mqtt_messages_sent_total {}", self.messages_sent)?;
+
+        metric_header(
+            out,
+            "mqtt_messages_dropped_total",
+            "Publications dropped because a session queue was full.",
+            "counter",
+        )?;
+        writeln!(out, "mqtt_messages_dropped_total {}", self.messages_dropped)?;
+
+        metric_header(
+            out,
+            "mqtt_messages_throttled_total",
+            "Publications delayed because their client exceeded its publish rate.",
+            "counter",
+        )?;
+        writeln!(
+            out,
+            "mqtt_messages_throttled_total {}",
+            self.messages_throttled
+        )?;
+
+        metric_header(
+            out,
+            "mqtt_connections_refused_total",
+            "Connections refused because a connection quota was reached.",
+            "counter",
+        )?;
+        writeln!(
+            out,
+            "mqtt_connections_refused_total {}",
+            self.connections_refused
+        )?;
+
+        metric_header(
+            out,
+            "mqtt_rate_limit_disconnects_total",
+            "Connections dropped because their client exceeded its publish rate.",
+            "counter",
+        )?;
+        writeln!(
+            out,
+            "mqtt_rate_limit_disconnects_total {}",
+            self.rate_limit_disconnects
+        )?;
+
+        metric_header(
+            out,
+            "mqtt_subscriptions_refused_total",
+            "Subscriptions refused because a session reached its maximum number of subscriptions.",
+            "counter",
+        )?;
+        writeln!(
+            out,
+            "mqtt_subscriptions_refused_total {}",
+            self.subscriptions_refused
+        )?;
+
+        metric_header(
+            out,
+            "mqtt_session_queued_messages",
+            "Messages waiting to be sent to a session.",
+            "gauge",
+        )?;
+        for session in &self.sessions {
+            writeln!(
+                out,
+                "mqtt_session_queued_messages{{client_id=\"{}\"}} {}",
+                escape_label(session.client_id.as_str()),
+                session.queued_messages
+            )?;
+        }
+
+        metric_header(
+            out,
+            "mqtt_session_inflight_messages",
+            "Messages sent to a session and not yet acknowledged.",
+            "gauge",
+        )?;
+        for session in &self.sessions {
+            writeln!(
+                out,
+                "mqtt_session_inflight_messages{{client_id=\"{}\"}} {}",
+                escape_label(session.client_id.as_str()),
+                session.inflight_messages
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps the broker's message counters and shares the latest statistics.
+#[derive(Debug)]
+pub(crate) struct StatsTracker {
+    messages_received: u64,
+    messages_sent: u64,
+    messages_dropped: u64,
+    connections_refused: u64,
+    subscriptions_refused: u64,
+    rate_limits: Arc<RateLimitCounters>,
+    sender: watch::Sender<BrokerStats>,
+    receiver: watch::Receiver<BrokerStats>,
+}
+
+impl StatsTracker {
+    pub fn new() -> Self {
+        let (sender, receiver) = watch::channel(BrokerStats::default());
+        Self {
+            messages_received: 0,
+            messages_sent: 0,
+            messages_dropped: 0,
+            connections_refused: 0,
+            subscriptions_refused: 0,
+            rate_limits: Arc::default(),
+            sender,
+            receiver,
+        }
+    }
+
+    pub fn message_received(&mut self) {
+        self.messages_received += 1;
+    }
+
+    pub fn messages_sent(&mut self, count: u64) {
+        self.messages_sent += count;
+    }
+
+    pub fn messages_dropped(&mut self, count: u64) {
+        self.messages_dropped += count;
+    }
+
+    pub fn connection_refused(&mut self) {
+        self.connections_refused += 1;
+    }
+
+    pub fn subscription_refused(&mut self) {
+        self.subscriptions_refused += 1;
+    }
+
+    /// Counters connections update when their client exceeds its publish rate.
+    pub fn rate_limits(&self) -> Arc<RateLimitCounters> {
+        self.rate_limits.clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<BrokerStats> {
+        self.receiver.clone()
+    }
+
+    /// Shares new statistics and returns the `$SYS` publications for them.
+    pub fn update(
+        &mut self,
+        retained_messages: usize,
+        sessions: Vec<SessionStats>,
+    ) -> Vec<proto::Publication> {
+        let stats = BrokerStats {
+            retained_messages,
+            messages_received: self.messages_received,
+            messages_sent: self.messages_sent,
+            messages_dropped: self.messages_dropped,
+            connections_refused: self.connections_refused,
+            subscriptions_refused: self.subscriptions_refused,
+            messages_throttled: self.rate_limits.throttled(),
+            rate_limit_disconnects: self.rate_limits.disconnected(),
+            sessions,
+        };
+
+        let publications = stats.publications(&self.receiver.borrow());
+        // The tracker holds a receiver itself, so broadcasting cannot fail.
+        let _ = self.sender.broadcast(stats);
+        publications
+    }
+}
+
+fn sys<T: ToString>(topic: &str, value: T) -> proto::Publication {
+    proto::Publication {
+        topic_name: format!("{}{}{}", SYS_PREFIX, TOPIC_SEPARATOR, topic),
+        qos: proto::QoS::AtMostOnce,
+        retain: true,
+        payload: Bytes::from(value.to_string()),
+        properties: proto::Properties::default(),
+    }
+}
+
+fn clear(topic: &str) -> proto::Publication {
+    sys(topic, "")
+}
+
+fn metric_header(out: &mut String, name: &str, help: &str, kind: &str) -> fmt::Result {
+    writeln!(out, "# HELP {} {}", name, help)?;
+    writeln!(out, "# TYPE {} {}", name, kind)
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(client_id: &str, connected: bool, queued: usize) -> SessionStats {
+        SessionStats::new(client_id.into(), connected, queued, 1)
+    }
+
+    fn payload(publications: &[proto::Publication], topic: &str) -> Option<Bytes> {
+        publications
+            .iter()
+            .find(|p| p.topic_name == topic)
+            .map(|p| p.payload.clone())
+    }
+
+    #[test]
+    fn it_publishes_broker_topics() {
+        let mut tracker = StatsTracker::new();
+        tracker.message_received();
+        tracker.messages_sent(2);
+        tracker.messages_dropped(3);
+        tracker.connection_refused();
+        tracker.subscription_refused();
+        tracker.subscription_refused();
+
+        let publications = tracker.update(4, vec![session("a", true, 2), session("b", false, 5)]);
+
+        assert!(publications.iter().all(|p| p.retain));
+        assert_eq!(
+            Some(Bytes::from("1")),
+            payload(&publications, "$SYS/broker/clients/connected")
+        );
+        assert_eq!(
+            Some(Bytes::from("1")),
+            payload(&publications, "$SYS/broker/clients/disconnected")
+        );
+        assert_eq!(
+            Some(Bytes::from("4")),
+            payload(&publications, "$SYS/broker/messages/retained")
+        );
+        assert_eq!(
+            Some(Bytes::from("2")),
+            payload(&publications, "$SYS/broker/messages/sent")
+        );
+        assert_eq!(
+            Some(Bytes::from("3")),
+            payload(&publications, "$SYS/broker/messages/dropped")
+        );
+        assert_eq!(
+            Some(Bytes::from("7")),
+            payload(&publications, "$SYS/broker/messages/queued")
+        );
+        assert_eq!(
+            Some(Bytes::from("1")),
+            payload(&publications, "$SYS/broker/clients/refused")
+        );
+        assert_eq!(
+            Some(Bytes::from("2")),
+            payload(&publications, "$SYS/broker/subscriptions/refused")
+        );
+        assert_eq!(
+            Some(Bytes::from("5")),
+            payload(&publications, "$SYS/broker/clients/b/queued")
+        );
+    }
+
+    #[test]
+    fn it_clears_topics_of_removed_sessions() {
+        let mut tracker = StatsTracker::new();
+        tracker.update(0, vec![session("a", true, 2), session("b", true, 0)]);
+
+        let publications = tracker.update(0, vec![session("a", true, 2)]);
+
+        assert_eq!(
+            Some(Bytes::from("2")),
+            payload(&publications, "$SYS/broker/clients/a/queued")
+        );
+        assert_eq!(
+            Some(Bytes::new()),
+            payload(&publications, "$SYS/broker/clients/b/queued")
+        );
+        assert_eq!(
+            Some(Bytes::new()),
+            payload(&publications, "$SYS/broker/clients/b/inflight")
+        );
+    }
+
+    #[test]
+    fn it_skips_client_ids_which_are_not_topic_levels() {
+        let mut tracker = StatsTracker::new();
+        let publications = tracker.update(0, vec![session("a/b", true, 2)]);
+
+        assert!(publications
+            .iter()
+            .all(|p| !p.topic_name.starts_with("$SYS/broker/clients/a")));
+        assert_eq!(
+            Some(Bytes::from("2")),
+            payload(&publications, "$SYS/broker/messages/queued")
+        );
+    }
+
+    #[test]
+    fn it_shares_latest_stats() {
+        let mut tracker = StatsTracker::new();
+        let receiver = tracker.subscribe();
+        tracker.message_received();
+        tracker.update(1, vec![session("a", true, 0)]);
+
+        let stats = receiver.borrow();
+        assert_eq!(1, stats.messages_received());
+        assert_eq!(1, stats.retained_messages());
+        assert_eq!(1, stats.connected_sessions());
+    }
+
+    #[test]
+    fn it_renders_prometheus_text() {
+        let mut tracker = StatsTracker::new();
+        tracker.message_received();
+        tracker.update(0, vec![session("a\"b", true, 3)]);
+
+        let text = tracker.subscribe().borrow().to_prometheus();
+
+        assert!(text.contains("# TYPE mqtt_messages_received_total counter\n"));
+        assert!(text.contains("mqtt_messages_received_total 1\n"));
+        assert!(text.contains("mqtt_sessions{state=\"connected\"} 1\n"));
+        assert!(text.contains("mqtt_session_queued_messages{client_id=\"a\\\"b\"} 3\n"));
+    }
+}
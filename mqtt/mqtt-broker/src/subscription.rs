@@ -7,9 +7,10 @@ use serde::{Deserialize, Serialize};
 use crate::Error;
 
 const NUL_CHAR: char = '\0';
-const TOPIC_SEPARATOR: char = '/';
+pub(crate) const TOPIC_SEPARATOR: char = '/';
 static MULTILEVEL_WILDCARD: &str = "#";
 static SINGLELEVEL_WILDCARD: &str = "+";
+static SHARED_SUBSCRIPTION_PREFIX: &str = "$share/";
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Subscription {
@@ -35,18 +36,30 @@ impl Subscription {
 pub struct TopicFilter {
     segments: Vec<Segment>,
     multilevel: bool,
+    share: Option<String>,
 }
 
 impl TopicFilter {
-    fn new(segments: Vec<Segment>) -> Self {
+    pub(crate) fn new(segments: Vec<Segment>) -> Self {
         let len = segments.len();
         let multilevel = len > 0 && segments[len - 1] == Segment::MultiLevelWildcard;
         Self {
             segments,
             multilevel,
+            share: None,
         }
     }
 
+    pub(crate) fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Returns the name of the shared subscription group for filters
+    /// of the form `$share/{ShareName}/{filter}`.
+    pub fn share(&self) -> Option<&str> {
+        self.share.as_ref().map(String::as_str)
+    }
+
     pub fn matches(&self, topic_name: &str) -> bool {
         let mut segments = self.segments.iter();
         let mut levels = topic_name.split(TOPIC_SEPARATOR);
@@ -81,10 +94,47 @@ impl TopicFilter {
         }
         true
     }
+
+    /// Returns whether every topic matched by `other` is also matched by
+    /// this filter. Shared subscription groups are ignored.
+    pub fn covers(&self, other: &TopicFilter) -> bool {
+        match (self.segments.first(), other.segments.first()) {
+            (Some(Segment::MultiLevelWildcard), Some(Segment::Level(l)))
+            | (Some(Segment::SingleLevelWildcard), Some(Segment::Level(l)))
+                if l.starts_with('$') =>
+            {
+                return false
+            }
+            (_, _) => (),
+        }
+
+        let mut segments = self.segments.iter();
+        let mut others = other.segments.iter();
+        loop {
+            match (segments.next(), others.next()) {
+                (Some(Segment::MultiLevelWildcard), _) => return true,
+                (Some(Segment::SingleLevelWildcard), Some(Segment::MultiLevelWildcard)) => {
+                    return false
+                }
+                (Some(Segment::SingleLevelWildcard), Some(_)) => (),
+                (Some(Segment::Level(s)), Some(Segment::Level(o))) if s == o => (),
+                (None, None) => return true,
+                (_, _) => return false,
+            }
+        }
+    }
+}
+
+/// Returns whether `level` can be used as a single level of a topic name.
+pub(crate) fn is_topic_level(level: &str) -> bool {
+    !level.is_empty()
+        && !level.contains(TOPIC_SEPARATOR)
+        && !level.contains(SINGLELEVEL_WILDCARD)
+        && !level.contains(MULTILEVEL_WILDCARD)
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-enum Segment {
+pub(crate) enum Segment {
     Level(String),
     SingleLevelWildcard,
     MultiLevelWildcard,
@@ -102,6 +152,14 @@ impl Segment {
 
 impl fmt::Display for TopicFilter {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(share) = &self.share {
+            write!(
+                f,
+                "{}{}{}",
+                SHARED_SUBSCRIPTION_PREFIX, share, TOPIC_SEPARATOR
+            )?;
+        }
+
         let len = self.segments.len();
         for (i, segment) in self.segments.iter().enumerate() {
             match segment {
@@ -129,8 +187,30 @@ impl FromStr for TopicFilter {
             return Err(Error::InvalidTopicFilter(string.to_owned()));
         }
 
+        // [MQTT-4.8.2-1] - A Shared Subscription's Topic Filter MUST start
+        // with $share/ and MUST contain a ShareName that is at least one
+        // character long.
+        // [MQTT-4.8.2-2] - The ShareName MUST NOT contain the characters "/",
+        // "+" or "#", but MUST be followed by a "/" character. This "/"
+        // character MUST be followed by a Topic Filter.
+        let (share, filter) = if string.starts_with(SHARED_SUBSCRIPTION_PREFIX) {
+            let rest = &string[SHARED_SUBSCRIPTION_PREFIX.len()..];
+            match rest.find(TOPIC_SEPARATOR) {
+                Some(index) if index > 0 && index < rest.len() - 1 => {
+                    let share = &rest[..index];
+                    if share.contains(MULTILEVEL_WILDCARD) || share.contains(SINGLELEVEL_WILDCARD) {
+                        return Err(Error::InvalidTopicFilter(string.to_owned()));
+                    }
+                    (Some(share.to_owned()), &rest[index + 1..])
+                }
+                _ => return Err(Error::InvalidTopicFilter(string.to_owned())),
+            }
+        } else {
+            (None, string)
+        };
+
         let mut segments = Vec::new();
-        for s in string.split(TOPIC_SEPARATOR) {
+        for s in filter.split(TOPIC_SEPARATOR) {
             let segment = if s == MULTILEVEL_WILDCARD {
                 Segment::MultiLevelWildcard
             } else if s == SINGLELEVEL_WILDCARD {
@@ -154,7 +234,10 @@ impl FromStr for TopicFilter {
                 return Err(Error::InvalidTopicFilter(string.to_owned()));
             }
         }
-        let filter = TopicFilter::new(segments);
+        let filter = TopicFilter {
+            share,
+            ..TopicFilter::new(segments)
+        };
         Ok(filter)
     }
 }
@@ -166,6 +249,7 @@ pub(crate) mod tests {
 
     use proptest::bool;
     use proptest::collection::vec;
+    use proptest::option;
     use proptest::prelude::*;
 
     fn arb_segment() -> impl Strategy<Value = Segment> {
@@ -180,6 +264,7 @@ pub(crate) mod tests {
         pub fn arb_topic_filter()(
             segments in vec(arb_segment(), 1..20),
             multi in bool::ANY,
+            share in option::of("[^+#\0/]+"),
         ) -> TopicFilter {
             let mut filtered = vec![];
             for segment in segments {
@@ -192,7 +277,10 @@ pub(crate) mod tests {
                 filtered.push(Segment::MultiLevelWildcard);
             }
 
-            TopicFilter::new(filtered)
+            TopicFilter {
+                share,
+                ..TopicFilter::new(filtered)
+            }
         }
     }
 
@@ -271,6 +359,36 @@ pub(crate) mod tests {
         }
     }
 
+    #[test]
+    fn topic_filter_shared_valid() {
+        let cases = vec![
+            ("$share/group/#", "group", vec![Segment::MultiLevelWildcard]),
+            (
+                "$share/group/sport/+",
+                "group",
+                vec![
+                    Segment::Level("sport".to_string()),
+                    Segment::SingleLevelWildcard,
+                ],
+            ),
+            (
+                "$share/group//finance",
+                "group",
+                vec![
+                    Segment::Level("".to_string()),
+                    Segment::Level("finance".to_string()),
+                ],
+            ),
+        ];
+
+        for (case, share, segments) in cases {
+            let result = TopicFilter::from_str(case).unwrap();
+            assert_eq!(Some(share), result.share());
+            assert_eq!(&segments[..], result.segments());
+            assert_eq!(case, result.to_string());
+        }
+    }
+
     #[test]
     fn topic_filter_invalid() {
         let cases = vec![
@@ -280,6 +398,12 @@ pub(crate) mod tests {
             "sport/tennis/#/ranking",
             "sport+",
             "bla\0h+",
+            "$share/group",
+            "$share/group/",
+            "$share//sport",
+            "$share/gro+up/sport",
+            "$share/gro#up/sport",
+            "$share/group/sport#",
         ];
 
         for case in &cases {
@@ -309,6 +433,8 @@ pub(crate) mod tests {
             ("blah/blah1/blah2", "blah/blah", false),
             ("#", "$SYS/blah", false),
             ("+", "$SYS", false),
+            ("$share/group/blah/+", "blah/blah1", true),
+            ("$share/group/#", "$SYS/blah", false),
         ];
 
         for (filter, topic, expected) in &cases {
@@ -322,4 +448,36 @@ pub(crate) mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_covers() {
+        let cases = vec![
+            ("#", "blah/+", true),
+            ("#", "#", true),
+            ("blah/#", "blah", true),
+            ("blah/#", "blah/+/blah2", true),
+            ("blah/+", "blah/blah1", true),
+            ("blah/+", "blah/+", true),
+            ("blah/+", "blah/#", false),
+            ("blah/blah1", "blah/+", false),
+            ("blah", "blah/#", false),
+            ("blah/+", "blah/blah1/blah2", false),
+            ("#", "$SYS/blah", false),
+            ("+/blah", "$SYS/blah", false),
+            ("$SYS/#", "$SYS/blah", true),
+            ("blah/#", "$share/group/blah/+", true),
+        ];
+
+        for (filter, other, expected) in &cases {
+            let parsed = TopicFilter::from_str(filter).unwrap();
+            let other_parsed = TopicFilter::from_str(other).unwrap();
+            assert_eq!(
+                *expected,
+                parsed.covers(&other_parsed),
+                "filter \"{}\" covers \"{}\"",
+                filter,
+                other
+            );
+        }
+    }
 }
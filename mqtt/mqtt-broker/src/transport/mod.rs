@@ -0,0 +1,574 @@
+mod websocket;
+
+use std::{
+    convert::TryFrom,
+    future::Future,
+    net::SocketAddr,
+    path::Path,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, BufMut};
+use core::mem::MaybeUninit;
+use futures::stream::FuturesUnordered;
+use openssl::{
+    pkcs12::{ParsedPkcs12, Pkcs12},
+    ssl::{SslAcceptor, SslMethod, SslVerifyMode},
+    x509::X509Name,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    stream::Stream,
+};
+use tokio_openssl::SslStream;
+use tracing::{debug, error, info, warn};
+
+use crate::configuration::{ClientCertificates, Transport as TransportConfig};
+use crate::{Certificate, Error, InitializeBrokerError};
+
+use self::websocket::WsStream;
+
+pub enum TransportBuilder<A> {
+    Tcp(A),
+    Tls(A, SslAcceptor),
+    Ws(A, String),
+    Wss(A, SslAcceptor, String),
+}
+
+impl<A> TransportBuilder<A>
+where
+    A: ToSocketAddrs,
+{
+    pub async fn build(self) -> Result<Transport, InitializeBrokerError> {
+        match self {
+            TransportBuilder::Tcp(addr) => Transport::new_tcp(addr).await,
+            TransportBuilder::Tls(addr, acceptor) => Transport::new_tls(addr, acceptor).await,
+            TransportBuilder::Ws(addr, path) => Transport::new_ws(addr, path).await,
+            TransportBuilder::Wss(addr, acceptor, path) => {
+                Transport::new_wss(addr, acceptor, path).await
+            }
+        }
+    }
+}
+
+impl TryFrom<TransportConfig> for TransportBuilder<String> {
+    type Error = InitializeBrokerError;
+
+    fn try_from(transport: TransportConfig) -> Result<Self, Self::Error> {
+        match transport {
+            TransportConfig::Tcp { address } => Ok(Self::Tcp(address)),
+            TransportConfig::Tls {
+                address,
+                certificate,
+                client_certificates,
+            } => {
+                let acceptor = tls_acceptor(&certificate, client_certificates.as_ref())?;
+                Ok(Self::Tls(address, acceptor))
+            }
+            TransportConfig::Ws { address, path } => Ok(Self::Ws(address, path)),
+            TransportConfig::Wss {
+                address,
+                path,
+                certificate,
+                client_certificates,
+            } => {
+                let acceptor = tls_acceptor(&certificate, client_certificates.as_ref())?;
+                Ok(Self::Wss(address, acceptor, path))
+            }
+        }
+    }
+}
+
+/// Loads the PKCS#12 identity in `certificate`.
+pub(crate) fn load_identity(certificate: &Path) -> Result<ParsedPkcs12, InitializeBrokerError> {
+    info!("Loading identity from {}", certificate.display());
+    let cert_buffer = std::fs::read(certificate)
+        .map_err(|e| InitializeBrokerError::LoadIdentity(certificate.to_path_buf(), e))?;
+    Pkcs12::from_der(&cert_buffer)
+        .and_then(|pkcs12| pkcs12.parse(""))
+        .map_err(InitializeBrokerError::DecodeIdentity)
+}
+
+/// Creates a TLS acceptor for the PKCS#12 identity in `certificate`.
+///
+/// If `client_certificates` is set, clients are asked for a certificate which
+/// must chain to one of the CA certificates in the configured bundle. Clients
+/// without a certificate are refused only if certificates are required.
+fn tls_acceptor(
+    certificate: &Path,
+    client_certificates: Option<&ClientCertificates>,
+) -> Result<SslAcceptor, InitializeBrokerError> {
+    let identity = load_identity(certificate)?;
+
+    let mut builder =
+        SslAcceptor::mozilla_intermediate(SslMethod::tls()).map_err(InitializeBrokerError::Tls)?;
+    builder
+        .set_private_key(&identity.pkey)
+        .map_err(InitializeBrokerError::Tls)?;
+    builder
+        .set_certificate(&identity.cert)
+        .map_err(InitializeBrokerError::Tls)?;
+    for cert in identity.chain.into_iter().flatten() {
+        builder
+            .add_extra_chain_cert(cert)
+            .map_err(InitializeBrokerError::Tls)?;
+    }
+
+    if let Some(client_certificates) = client_certificates {
+        let ca = client_certificates.ca();
+        info!("Loading client CA certificates from {}", ca.display());
+        builder
+            .set_ca_file(ca)
+            .map_err(|e| InitializeBrokerError::LoadClientCertificates(ca.to_path_buf(), e))?;
+        let names = X509Name::load_client_ca_file(ca)
+            .map_err(|e| InitializeBrokerError::LoadClientCertificates(ca.to_path_buf(), e))?;
+        builder.set_client_ca_list(names);
+
+        let mut mode = SslVerifyMode::PEER;
+        if client_certificates.required() {
+            mode |= SslVerifyMode::FAIL_IF_NO_PEER_CERT;
+        }
+        builder.set_verify(mode);
+    }
+
+    Ok(builder.build())
+}
+
+pub enum Transport {
+    Tcp(TcpListener),
+    Tls(TcpListener, SslAcceptor),
+    Ws(TcpListener, String),
+    Wss(TcpListener, SslAcceptor, String),
+}
+
+impl Transport {
+    async fn new_tcp<A>(addr: A) -> Result<Self, InitializeBrokerError>
+    where
+        A: ToSocketAddrs,
+    {
+        let tcp = TcpListener::bind(addr)
+            .await
+            .map_err(InitializeBrokerError::BindServer)?;
+
+        Ok(Transport::Tcp(tcp))
+    }
+
+    async fn new_tls<A>(addr: A, acceptor: SslAcceptor) -> Result<Self, InitializeBrokerError>
+    where
+        A: ToSocketAddrs,
+    {
+        let tcp = TcpListener::bind(addr)
+            .await
+            .map_err(InitializeBrokerError::BindServer)?;
+
+        Ok(Transport::Tls(tcp, acceptor))
+    }
+
+    async fn new_ws<A>(addr: A, path: String) -> Result<Self, InitializeBrokerError>
+    where
+        A: ToSocketAddrs,
+    {
+        let tcp = TcpListener::bind(addr)
+            .await
+            .map_err(InitializeBrokerError::BindServer)?;
+
+        Ok(Transport::Ws(tcp, path))
+    }
+
+    async fn new_wss<A>(
+        addr: A,
+        acceptor: SslAcceptor,
+        path: String,
+    ) -> Result<Self, InitializeBrokerError>
+    where
+        A: ToSocketAddrs,
+    {
+        let tcp = TcpListener::bind(addr)
+            .await
+            .map_err(InitializeBrokerError::BindServer)?;
+
+        Ok(Transport::Wss(tcp, acceptor, path))
+    }
+
+    pub fn incoming(self) -> Incoming {
+        match self {
+            Self::Tcp(listener) => Incoming::Tcp(IncomingTcp::new(listener)),
+            Self::Tls(listener, acceptor) => Incoming::Tls(IncomingTls::new(listener, acceptor)),
+            Self::Ws(listener, path) => Incoming::Ws(IncomingWs::new(listener, None, path)),
+            Self::Wss(listener, acceptor, path) => {
+                Incoming::Ws(IncomingWs::new(listener, Some(acceptor), path))
+            }
+        }
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, InitializeBrokerError> {
+        let addr = match self {
+            Self::Tcp(listener) => listener.local_addr(),
+            Self::Tls(listener, _) => listener.local_addr(),
+            Self::Ws(listener, _) => listener.local_addr(),
+            Self::Wss(listener, _, _) => listener.local_addr(),
+        };
+        addr.map_err(InitializeBrokerError::ConnectionLocalAddress)
+    }
+}
+
+type HandshakeFuture = Pin<Box<dyn Future<Output = std::io::Result<SslStream<TcpStream>>> + Send>>;
+
+type WsHandshakeFuture = Pin<Box<dyn Future<Output = std::io::Result<StreamSelector>> + Send>>;
+
+pub enum Incoming {
+    Tcp(IncomingTcp),
+    Tls(IncomingTls),
+    Ws(IncomingWs),
+}
+
+impl Stream for Incoming {
+    type Item = std::io::Result<StreamSelector>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.get_mut() {
+            Self::Tcp(incoming) => Pin::new(incoming).poll_next(cx),
+            Self::Tls(incoming) => Pin::new(incoming).poll_next(cx),
+            Self::Ws(incoming) => Pin::new(incoming).poll_next(cx),
+        }
+    }
+}
+
+pub struct IncomingTcp {
+    listener: TcpListener,
+}
+
+impl IncomingTcp {
+    fn new(listener: TcpListener) -> Self {
+        Self { listener }
+    }
+}
+
+impl Stream for IncomingTcp {
+    type Item = std::io::Result<StreamSelector>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.listener.poll_accept(cx) {
+            Poll::Ready(Ok((tcp, _))) => match tcp.set_nodelay(true) {
+                Ok(()) => {
+                    debug!("TCP: Accepted connection from client");
+                    Poll::Ready(Some(Ok(StreamSelector::Tcp(tcp))))
+                }
+                Err(err) => {
+                    warn!(
+                        "TCP: Dropping client because failed to setup TCP properties: {}",
+                        err
+                    );
+                    Poll::Ready(Some(Err(err)))
+                }
+            },
+            Poll::Ready(Err(err)) => {
+                error!(
+                    "TCP: Dropping client that failed to completely establish a TCP connection: {}",
+                    err
+                );
+                Poll::Ready(Some(Err(err)))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+pub struct IncomingTls {
+    listener: TcpListener,
+    acceptor: SslAcceptor,
+    connections: FuturesUnordered<HandshakeFuture>,
+}
+
+impl IncomingTls {
+    fn new(listener: TcpListener, acceptor: SslAcceptor) -> Self {
+        Self {
+            listener,
+            acceptor,
+            connections: FuturesUnordered::default(),
+        }
+    }
+}
+
+impl Stream for IncomingTls {
+    type Item = std::io::Result<StreamSelector>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.listener.poll_accept(cx) {
+                Poll::Ready(Ok((stream, _))) => match stream.set_nodelay(true) {
+                    Ok(()) => {
+                        let acceptor = self.acceptor.clone();
+                        self.connections
+                            .push(Box::pin(tls_handshake(stream, acceptor)));
+                    }
+                    Err(err) => warn!(
+                        "TCP: Dropping client because failed to setup TCP properties: {}",
+                        err
+                    ),
+                },
+                Poll::Ready(Err(err)) => warn!(
+                    "TCP: Dropping client that failed to completely establish a TCP connection: {}",
+                    err
+                ),
+                Poll::Pending => break,
+            }
+        }
+
+        loop {
+            if self.connections.is_empty() {
+                return Poll::Pending;
+            }
+
+            match Pin::new(&mut self.connections).poll_next(cx) {
+                Poll::Ready(Some(Ok(stream))) => {
+                    debug!("TLS: Accepted connection from client");
+                    return Poll::Ready(Some(Ok(StreamSelector::Tls(stream))));
+                }
+
+                Poll::Ready(Some(Err(err))) => warn!(
+                    "TLS: Dropping client that failed to complete a TLS handshake: {}",
+                    err
+                ),
+
+                Poll::Ready(None) => {
+                    debug!("TLS: Shutting down web server");
+                    return Poll::Ready(None);
+                }
+
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+pub struct IncomingWs {
+    listener: TcpListener,
+    acceptor: Option<SslAcceptor>,
+    path: String,
+    connections: FuturesUnordered<WsHandshakeFuture>,
+}
+
+impl IncomingWs {
+    fn new(listener: TcpListener, acceptor: Option<SslAcceptor>, path: String) -> Self {
+        Self {
+            listener,
+            acceptor,
+            path,
+            connections: FuturesUnordered::default(),
+        }
+    }
+}
+
+impl Stream for IncomingWs {
+    type Item = std::io::Result<StreamSelector>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.listener.poll_accept(cx) {
+                Poll::Ready(Ok((stream, _))) => match stream.set_nodelay(true) {
+                    Ok(()) => {
+                        let acceptor = self.acceptor.clone();
+                        let path = self.path.clone();
+                        self.connections
+                            .push(Box::pin(ws_handshake(stream, acceptor, path)));
+                    }
+                    Err(err) => warn!(
+                        "TCP: Dropping client because failed to setup TCP properties: {}",
+                        err
+                    ),
+                },
+                Poll::Ready(Err(err)) => warn!(
+                    "TCP: Dropping client that failed to completely establish a TCP connection: {}",
+                    err
+                ),
+                Poll::Pending => break,
+            }
+        }
+
+        loop {
+            if self.connections.is_empty() {
+                return Poll::Pending;
+            }
+
+            match Pin::new(&mut self.connections).poll_next(cx) {
+                Poll::Ready(Some(Ok(stream))) => {
+                    debug!("WS: Accepted connection from client");
+                    return Poll::Ready(Some(Ok(stream)));
+                }
+
+                Poll::Ready(Some(Err(err))) => warn!(
+                    "WS: Dropping client that failed to complete a WebSocket handshake: {}",
+                    err
+                ),
+
+                Poll::Ready(None) => {
+                    debug!("WS: Shutting down web server");
+                    return Poll::Ready(None);
+                }
+
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+async fn tls_handshake(
+    stream: TcpStream,
+    acceptor: SslAcceptor,
+) -> std::io::Result<SslStream<TcpStream>> {
+    tokio_openssl::accept(&acceptor, stream)
+        .await
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
+}
+
+async fn ws_handshake(
+    stream: TcpStream,
+    acceptor: Option<SslAcceptor>,
+    path: String,
+) -> std::io::Result<StreamSelector> {
+    match acceptor {
+        Some(acceptor) => {
+            let stream = tls_handshake(stream, acceptor).await?;
+            let stream = websocket::accept(stream, path).await?;
+            Ok(StreamSelector::Wss(stream))
+        }
+        None => {
+            let stream = websocket::accept(stream, path).await?;
+            Ok(StreamSelector::Ws(stream))
+        }
+    }
+}
+
+pub enum StreamSelector {
+    Tcp(TcpStream),
+    Tls(SslStream<TcpStream>),
+    Ws(WsStream<TcpStream>),
+    Wss(WsStream<SslStream<TcpStream>>),
+}
+
+impl StreamSelector {
+    pub fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        match self {
+            StreamSelector::Tcp(stream) => stream.peer_addr(),
+            StreamSelector::Tls(stream) => stream.get_ref().peer_addr(),
+            StreamSelector::Ws(stream) => stream.get_ref().peer_addr(),
+            StreamSelector::Wss(stream) => stream.get_ref().get_ref().peer_addr(),
+        }
+    }
+}
+
+pub trait GetPeerCertificate {
+    type Certificate;
+
+    fn peer_certificate(&self) -> Result<Option<Self::Certificate>, Error>;
+}
+
+impl GetPeerCertificate for StreamSelector {
+    type Certificate = Certificate;
+
+    fn peer_certificate(&self) -> Result<Option<Self::Certificate>, Error> {
+        match self {
+            StreamSelector::Tcp(_) | StreamSelector::Ws(_) => Ok(None),
+            StreamSelector::Tls(stream) => tls_peer_certificate(stream),
+            StreamSelector::Wss(stream) => tls_peer_certificate(stream.get_ref()),
+        }
+    }
+}
+
+fn tls_peer_certificate(stream: &SslStream<TcpStream>) -> Result<Option<Certificate>, Error> {
+    stream
+        .ssl()
+        .peer_certificate()
+        .map(|cert| cert.to_der().map(Certificate::from))
+        .transpose()
+        .map_err(Error::PeerCertificate)
+}
+
+impl AsyncRead for StreamSelector {
+    #[inline]
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [MaybeUninit<u8>]) -> bool {
+        match self {
+            StreamSelector::Tcp(stream) => stream.prepare_uninitialized_buffer(buf),
+            StreamSelector::Tls(stream) => stream.prepare_uninitialized_buffer(buf),
+            StreamSelector::Ws(stream) => stream.prepare_uninitialized_buffer(buf),
+            StreamSelector::Wss(stream) => stream.prepare_uninitialized_buffer(buf),
+        }
+    }
+
+    #[inline]
+    fn poll_read_buf<B: BufMut>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut B,
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            StreamSelector::Tcp(stream) => Pin::new(stream).poll_read_buf(cx, buf),
+            StreamSelector::Tls(stream) => Pin::new(stream).poll_read_buf(cx, buf),
+            StreamSelector::Ws(stream) => Pin::new(stream).poll_read_buf(cx, buf),
+            StreamSelector::Wss(stream) => Pin::new(stream).poll_read_buf(cx, buf),
+        }
+    }
+
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            StreamSelector::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            StreamSelector::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+            StreamSelector::Ws(stream) => Pin::new(stream).poll_read(cx, buf),
+            StreamSelector::Wss(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for StreamSelector {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            StreamSelector::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            StreamSelector::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+            StreamSelector::Ws(stream) => Pin::new(stream).poll_write(cx, buf),
+            StreamSelector::Wss(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_buf<B: Buf>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut B,
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            StreamSelector::Tcp(stream) => Pin::new(stream).poll_write_buf(cx, buf),
+            StreamSelector::Tls(stream) => Pin::new(stream).poll_write_buf(cx, buf),
+            StreamSelector::Ws(stream) => Pin::new(stream).poll_write_buf(cx, buf),
+            StreamSelector::Wss(stream) => Pin::new(stream).poll_write_buf(cx, buf),
+        }
+    }
+
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            StreamSelector::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            StreamSelector::Tls(stream) => Pin::new(stream).poll_flush(cx),
+            StreamSelector::Ws(stream) => Pin::new(stream).poll_flush(cx),
+            StreamSelector::Wss(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            StreamSelector::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            StreamSelector::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+            StreamSelector::Ws(stream) => Pin::new(stream).poll_shutdown(cx),
+            StreamSelector::Wss(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::{
+    cmp, io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, Bytes};
+use futures::{ready, sink::Sink};
+use http::{
+    header::{HeaderValue, SEC_WEBSOCKET_PROTOCOL},
+    StatusCode,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    stream::Stream,
+};
+use tokio_tungstenite::{
+    tungstenite::{
+        handshake::server::{Callback, ErrorResponse, Request, Response},
+        Error as WsError, Message,
+    },
+    WebSocketStream,
+};
+use tracing::debug;
+
+/// WebSocket subprotocol negotiated for MQTT connections.
+pub const MQTT_SUBPROTOCOL: &str = "mqtt";
+
+/// Performs the server side of a WebSocket handshake on `stream`.
+///
+/// The handshake is refused unless the client asks for `path` and offers
+/// the `mqtt` subprotocol.
+pub async fn accept<S>(stream: S, path: String) -> io::Result<WsStream<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let inner = tokio_tungstenite::accept_hdr_async(stream, handshake(path))
+        .await
+        .map_err(into_io_error)?;
+    Ok(WsStream::new(inner))
+}
+
+fn handshake(path: String) -> impl Callback + Unpin {
+    move |request: &Request, mut response: Response| -> Result<Response, ErrorResponse> {
+        if request.uri().path() != path {
+            debug!("WS: refusing handshake for path {}", request.uri().path());
+            return Err(error_response(StatusCode::NOT_FOUND, "unknown path"));
+        }
+
+        // [MQTT-6.0.0-3] - The Client MUST include "mqtt" in the list of
+        // WebSocket Sub Protocols it offers.
+        // [MQTT-6.0.0-4] - The WebSocket Subprotocol name selected and
+        // returned by the Server MUST be "mqtt".
+        let offers_mqtt = request
+            .headers()
+            .get_all(SEC_WEBSOCKET_PROTOCOL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|protocol| protocol.trim() == MQTT_SUBPROTOCOL);
+        if !offers_mqtt {
+            debug!("WS: refusing handshake without mqtt subprotocol");
+            return Err(error_response(
+                StatusCode::BAD_REQUEST,
+                "mqtt subprotocol required",
+            ));
+        }
+
+        response.headers_mut().insert(
+            SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static(MQTT_SUBPROTOCOL),
+        );
+        Ok(response)
+    }
+}
+
+fn error_response(status: StatusCode, reason: &str) -> ErrorResponse {
+    let mut response = ErrorResponse::new(Some(reason.to_string()));
+    *response.status_mut() = status;
+    response
+}
+
+/// Exposes a WebSocket connection as a byte stream.
+///
+/// MQTT packets are carried in binary WebSocket messages. A packet may span
+/// several messages and a message may hold several packets, so messages are
+/// simply concatenated on read, and every write is sent as its own message.
+pub struct WsStream<S> {
+    inner: WebSocketStream<S>,
+    read_buf: Bytes,
+}
+
+impl<S> WsStream<S> {
+    fn new(inner: WebSocketStream<S>) -> Self {
+        Self {
+            inner,
+            read_buf: Bytes::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.inner.get_ref()
+    }
+}
+
+impl<S> AsyncRead for WsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            if !self.read_buf.is_empty() {
+                let len = cmp::min(buf.len(), self.read_buf.len());
+                buf[..len].copy_from_slice(&self.read_buf[..len]);
+                self.read_buf.advance(len);
+                return Poll::Ready(Ok(len));
+            }
+
+            match ready!(Pin::new(&mut self.inner).poll_next(cx)) {
+                Some(Ok(Message::Binary(data))) => self.read_buf = Bytes::from(data),
+                Some(Ok(Message::Text(_))) => {
+                    // [MQTT-6.0.0-1] - MQTT Control Packets MUST be sent in
+                    // WebSocket binary data frames. If any other type of data
+                    // frame is received the recipient MUST close the Network
+                    // Connection.
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "received a text WebSocket message",
+                    )));
+                }
+                Some(Ok(Message::Close(_))) | None => return Poll::Ready(Ok(0)),
+                // Pings are answered by the WebSocket stream itself.
+                Some(Ok(Message::Ping(_))) | Some(Ok(Message::Pong(_))) => (),
+                Some(Err(e)) => return Poll::Ready(Err(into_io_error(e))),
+            }
+        }
+    }
+}
+
+impl<S> AsyncWrite for WsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        ready!(Pin::new(&mut self.inner).poll_ready(cx)).map_err(into_io_error)?;
+        Pin::new(&mut self.inner)
+            .start_send(Message::Binary(buf.to_vec()))
+            .map_err(into_io_error)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner)
+            .poll_flush(cx)
+            .map_err(into_io_error)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner)
+            .poll_close(cx)
+            .map_err(into_io_error)
+    }
+}
+
+fn into_io_error(e: WsError) -> io::Error {
+    match e {
+        WsError::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::Other, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use matches::assert_matches;
+
+    fn request(path: &str, protocols: Option<&str>) -> Request {
+        let mut builder = Request::builder().uri(path);
+        if let Some(protocols) = protocols {
+            builder = builder.header(SEC_WEBSOCKET_PROTOCOL, protocols);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn handshake_selects_mqtt_subprotocol() {
+        let callback = handshake("/mqtt".to_string());
+        let response = callback
+            .on_request(&request("/mqtt", Some("mqttv3.1, mqtt")), Response::new(()))
+            .unwrap();
+
+        assert_eq!(
+            Some(&HeaderValue::from_static(MQTT_SUBPROTOCOL)),
+            response.headers().get(SEC_WEBSOCKET_PROTOCOL)
+        );
+    }
+
+    #[test]
+    fn handshake_refuses_unknown_path() {
+        let callback = handshake("/mqtt".to_string());
+        let result = callback.on_request(&request("/other", Some("mqtt")), Response::new(()));
+
+        assert_matches!(result, Err(response) if response.status() == StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn handshake_refuses_missing_subprotocol() {
+        let callback = handshake("/mqtt".to_string());
+        let result = callback.on_request(&request("/mqtt", None), Response::new(()));
+
+        assert_matches!(result, Err(response) if response.status() == StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,455 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use crate::subscription::{Segment, TopicFilter, TOPIC_SEPARATOR};
+use crate::ClientId;
+
+/// Index of subscribed topic filters, keyed by filter segment.
+///
+/// Each node corresponds to one level of a topic filter. Looking up a topic
+/// name only walks the branches that can match it, so the cost of finding
+/// the subscribers of a publication depends on the depth of the topic rather
+/// than on the total number of subscriptions.
+///
+/// Shared subscriptions are indexed separately, by group, since only one
+/// member of a group receives a given publication.
+#[derive(Debug, Default)]
+pub struct TopicTrie {
+    root: Node<ClientId>,
+    shared: Node<String>,
+    groups: HashMap<String, SharedGroup>,
+}
+
+impl TopicTrie {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `client_id` as a subscriber of `filter`.
+    pub fn insert(&mut self, filter: &TopicFilter, client_id: ClientId) {
+        if filter.share().is_none() {
+            self.root.insert(filter.segments(), client_id);
+            return;
+        }
+
+        let key = filter.to_string();
+        match self.groups.get_mut(&key) {
+            Some(group) => group.insert(client_id),
+            None => {
+                self.shared.insert(filter.segments(), key.clone());
+                let mut group = SharedGroup::default();
+                group.insert(client_id);
+                self.groups.insert(key, group);
+            }
+        }
+    }
+
+    /// Removes `client_id` as a subscriber of `filter`.
+    ///
+    /// Branches left without any subscriber are pruned.
+    pub fn remove(&mut self, filter: &TopicFilter, client_id: &ClientId) {
+        if filter.share().is_none() {
+            self.root.remove(filter.segments(), client_id);
+            return;
+        }
+
+        let key = filter.to_string();
+        if let Some(group) = self.groups.get_mut(&key) {
+            group.remove(client_id);
+            if group.is_empty() {
+                self.groups.remove(&key);
+                self.shared.remove(filter.segments(), &key);
+            }
+        }
+    }
+
+    /// Returns the ids of all clients subscribed to a filter matching `topic_name`.
+    ///
+    /// Members of shared subscription groups are not included.
+    pub fn matches(&self, topic_name: &str) -> HashSet<ClientId> {
+        self.root.matches(topic_name)
+    }
+
+    /// Returns the shared subscription groups subscribed to a filter matching `topic_name`.
+    pub fn matching_groups(&mut self, topic_name: &str) -> Vec<&mut SharedGroup> {
+        let keys = self.shared.matches(topic_name);
+        self.groups
+            .iter_mut()
+            .filter(|(key, _)| keys.contains(*key))
+            .map(|(_, group)| group)
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_empty() && self.shared.is_empty()
+    }
+}
+
+/// Members of a shared subscription group.
+///
+/// Each publication matching the group is delivered to a single member,
+/// members being picked in turn.
+#[derive(Debug, Default)]
+pub struct SharedGroup {
+    members: Vec<ClientId>,
+    next: usize,
+}
+
+impl SharedGroup {
+    fn insert(&mut self, client_id: ClientId) {
+        if !self.members.contains(&client_id) {
+            self.members.push(client_id);
+        }
+    }
+
+    fn remove(&mut self, client_id: &ClientId) {
+        self.members.retain(|member| member != client_id);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Picks the member a publication should be delivered to.
+    ///
+    /// `rank` tells how well a member can take the publication right now,
+    /// `None` meaning it cannot take it at all. The best ranked member is
+    /// picked, members with the same rank taking turns. Returns `None` when
+    /// no member can take the publication.
+    pub fn pick<F>(&mut self, mut rank: F) -> Option<ClientId>
+    where
+        F: FnMut(&ClientId) -> Option<u8>,
+    {
+        let len = self.members.len();
+        let mut picked: Option<(usize, u8)> = None;
+        for i in 0..len {
+            let index = (self.next + i) % len;
+            if let Some(member_rank) = rank(&self.members[index]) {
+                if picked.map_or(true, |(_, best)| member_rank > best) {
+                    picked = Some((index, member_rank));
+                }
+            }
+        }
+
+        picked.map(|(index, _)| {
+            self.next = index + 1;
+            self.members[index].clone()
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Node<T> {
+    /// Subscribers whose filter ends at this level.
+    exact: HashSet<T>,
+
+    /// Subscribers whose filter ends with a multi-level wildcard following this level.
+    multi: HashSet<T>,
+
+    levels: HashMap<String, Node<T>>,
+    single: Option<Box<Node<T>>>,
+}
+
+impl<T> Default for Node<T> {
+    fn default() -> Self {
+        Self {
+            exact: HashSet::new(),
+            multi: HashSet::new(),
+            levels: HashMap::new(),
+            single: None,
+        }
+    }
+}
+
+impl<T> Node<T>
+where
+    T: Clone + Eq + Hash,
+{
+    fn is_empty(&self) -> bool {
+        self.exact.is_empty()
+            && self.multi.is_empty()
+            && self.levels.is_empty()
+            && self.single.is_none()
+    }
+
+    fn insert(&mut self, segments: &[Segment], subscriber: T) {
+        match segments.split_first() {
+            None => {
+                self.exact.insert(subscriber);
+            }
+            Some((Segment::MultiLevelWildcard, _)) => {
+                self.multi.insert(subscriber);
+            }
+            Some((Segment::SingleLevelWildcard, rest)) => self
+                .single
+                .get_or_insert_with(Box::default)
+                .insert(rest, subscriber),
+            Some((Segment::Level(level), rest)) => self
+                .levels
+                .entry(level.clone())
+                .or_default()
+                .insert(rest, subscriber),
+        }
+    }
+
+    fn remove(&mut self, segments: &[Segment], subscriber: &T) {
+        match segments.split_first() {
+            None => {
+                self.exact.remove(subscriber);
+            }
+            Some((Segment::MultiLevelWildcard, _)) => {
+                self.multi.remove(subscriber);
+            }
+            Some((Segment::SingleLevelWildcard, rest)) => {
+                if let Some(child) = self.single.as_mut() {
+                    child.remove(rest, subscriber);
+                    if child.is_empty() {
+                        self.single = None;
+                    }
+                }
+            }
+            Some((Segment::Level(level), rest)) => {
+                if let Some(child) = self.levels.get_mut(level) {
+                    child.remove(rest, subscriber);
+                    if child.is_empty() {
+                        self.levels.remove(level);
+                    }
+                }
+            }
+        }
+    }
+
+    fn matches(&self, topic_name: &str) -> HashSet<T> {
+        let levels = topic_name.split(TOPIC_SEPARATOR).collect::<Vec<_>>();
+
+        // [MQTT-4.7.2-1] - The Server MUST NOT match Topic Filters starting
+        // with a wildcard character (# or +) with Topic Names beginning with
+        // a $ character.
+        let skip_wildcards = levels.first().map_or(false, |l| l.starts_with('$'));
+
+        let mut matched = HashSet::new();
+        self.collect(&levels, skip_wildcards, &mut matched);
+        matched
+    }
+
+    fn collect(&self, levels: &[&str], skip_wildcards: bool, matched: &mut HashSet<T>) {
+        // A multi-level wildcard also matches its parent level,
+        // e.g. "sport/#" matches "sport".
+        if !skip_wildcards {
+            matched.extend(self.multi.iter().cloned());
+        }
+
+        match levels.split_first() {
+            None => matched.extend(self.exact.iter().cloned()),
+            Some((level, rest)) => {
+                if let Some(child) = self.levels.get(*level) {
+                    child.collect(rest, false, matched);
+                }
+                if !skip_wildcards {
+                    if let Some(child) = self.single.as_ref() {
+                        child.collect(rest, false, matched);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    use crate::subscription::tests::arb_topic_filter;
+    use crate::tests::arb_topic;
+
+    fn trie_with(filters: &[(&str, &str)]) -> TopicTrie {
+        let mut trie = TopicTrie::new();
+        for (filter, client_id) in filters {
+            trie.insert(&filter.parse().unwrap(), ClientId::from(*client_id));
+        }
+        trie
+    }
+
+    fn matched(trie: &TopicTrie, topic_name: &str) -> Vec<String> {
+        let mut matched = trie
+            .matches(topic_name)
+            .into_iter()
+            .map(|c| c.as_str().to_string())
+            .collect::<Vec<_>>();
+        matched.sort();
+        matched
+    }
+
+    #[test]
+    fn test_matches() {
+        let trie = trie_with(&[
+            ("#", "all"),
+            ("blah/#", "blah-multi"),
+            ("blah/+/blah2", "blah-single"),
+            ("blah/blah1", "blah1"),
+            ("$SYS/#", "sys"),
+        ]);
+
+        assert_eq!(vec!["all", "blah-multi"], matched(&trie, "blah"));
+        assert_eq!(
+            vec!["all", "blah-multi", "blah1"],
+            matched(&trie, "blah/blah1")
+        );
+        assert_eq!(
+            vec!["all", "blah-multi", "blah-single"],
+            matched(&trie, "blah/blah1/blah2")
+        );
+        assert_eq!(vec!["all"], matched(&trie, "other"));
+        assert_eq!(vec!["sys"], matched(&trie, "$SYS/blah"));
+    }
+
+    #[test]
+    fn test_wildcards_do_not_match_dollar_topics() {
+        let trie = trie_with(&[("#", "multi"), ("+", "single"), ("+/blah", "single2")]);
+
+        assert!(trie.matches("$SYS").is_empty());
+        assert!(trie.matches("$SYS/blah").is_empty());
+    }
+
+    #[test]
+    fn test_remove_prunes_empty_branches() {
+        let mut trie = trie_with(&[
+            ("a/+/c", "client1"),
+            ("a/+/c", "client2"),
+            ("a/#", "client1"),
+        ]);
+
+        trie.remove(&"a/+/c".parse().unwrap(), &ClientId::from("client1"));
+        assert_eq!(vec!["client1", "client2"], matched(&trie, "a/b/c"));
+
+        trie.remove(&"a/#".parse().unwrap(), &ClientId::from("client1"));
+        assert_eq!(vec!["client2"], matched(&trie, "a/b/c"));
+
+        trie.remove(&"a/+/c".parse().unwrap(), &ClientId::from("client2"));
+        assert!(trie.matches("a/b/c").is_empty());
+        assert!(trie.is_empty());
+    }
+
+    #[test]
+    fn test_remove_unknown_is_noop() {
+        let mut trie = trie_with(&[("a/b", "client1")]);
+
+        trie.remove(&"a/c".parse().unwrap(), &ClientId::from("client1"));
+        trie.remove(&"a/b".parse().unwrap(), &ClientId::from("client2"));
+        assert_eq!(vec!["client1"], matched(&trie, "a/b"));
+    }
+
+    fn matched_groups(trie: &mut TopicTrie, topic_name: &str) -> Vec<Vec<String>> {
+        let mut matched = trie
+            .matching_groups(topic_name)
+            .into_iter()
+            .map(|group| {
+                group
+                    .members
+                    .iter()
+                    .map(|c| c.as_str().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        matched.sort();
+        matched
+    }
+
+    #[test]
+    fn test_shared_subscriptions_grouped() {
+        let mut trie = trie_with(&[
+            ("$share/group1/blah/#", "client1"),
+            ("$share/group1/blah/#", "client2"),
+            ("$share/group2/blah/#", "client1"),
+            ("$share/group1/blah/+", "client3"),
+            ("blah/#", "client4"),
+        ]);
+
+        assert_eq!(vec!["client4"], matched(&trie, "blah/blah1"));
+        assert_eq!(
+            vec![
+                vec!["client1".to_string()],
+                vec!["client1".to_string(), "client2".to_string()],
+                vec!["client3".to_string()],
+            ],
+            matched_groups(&mut trie, "blah/blah1")
+        );
+        assert!(matched_groups(&mut trie, "other").is_empty());
+    }
+
+    #[test]
+    fn test_shared_subscriptions_remove() {
+        let mut trie = trie_with(&[
+            ("$share/group1/blah/#", "client1"),
+            ("$share/group1/blah/#", "client2"),
+        ]);
+        let filter = "$share/group1/blah/#".parse().unwrap();
+
+        trie.remove(&filter, &ClientId::from("client1"));
+        assert_eq!(
+            vec![vec!["client2".to_string()]],
+            matched_groups(&mut trie, "blah")
+        );
+
+        trie.remove(&filter, &ClientId::from("client2"));
+        assert!(matched_groups(&mut trie, "blah").is_empty());
+        assert!(trie.is_empty());
+    }
+
+    #[test]
+    fn test_shared_group_pick_round_robin() {
+        let mut group = SharedGroup::default();
+        group.insert(ClientId::from("client1"));
+        group.insert(ClientId::from("client2"));
+        group.insert(ClientId::from("client3"));
+
+        let picked = (0..4)
+            .filter_map(|_| group.pick(|_| Some(0)))
+            .map(|c| c.as_str().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["client1", "client2", "client3", "client1"], picked);
+    }
+
+    #[test]
+    fn test_shared_group_pick_best_rank() {
+        let mut group = SharedGroup::default();
+        group.insert(ClientId::from("client1"));
+        group.insert(ClientId::from("client2"));
+        group.insert(ClientId::from("client3"));
+
+        // client2 is unavailable and client3 ranks better than client1
+        let rank = |c: &ClientId| match c.as_str() {
+            "client1" => Some(0),
+            "client3" => Some(1),
+            _ => None,
+        };
+        assert_eq!(Some(ClientId::from("client3")), group.pick(rank));
+        assert_eq!(Some(ClientId::from("client3")), group.pick(rank));
+        assert_eq!(None, group.pick(|_| None));
+    }
+
+    proptest! {
+        #[test]
+        fn trie_matches_like_topic_filter(
+            filters in vec(arb_topic_filter(), 1..20),
+            topic in arb_topic(),
+        ) {
+            let mut trie = TopicTrie::new();
+            for (i, filter) in filters.iter().enumerate() {
+                trie.insert(filter, ClientId::from(i.to_string()));
+            }
+
+            let expected = filters
+                .iter()
+                .enumerate()
+                .filter(|(_, filter)| filter.share().is_none() && filter.matches(&topic))
+                .map(|(i, _)| ClientId::from(i.to_string()))
+                .collect::<HashSet<_>>();
+
+            prop_assert_eq!(expected, trie.matches(&topic));
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::mem;
+
+use mqtt3::proto::Publication;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::persist::PersistError;
+use crate::retention::ReceivedPublication;
+use crate::session::{Session, SessionState};
+use crate::subscription::Subscription;
+use crate::{BrokerState, ClientId};
+
+/// Size of the length prefix written before every record of the log.
+const RECORD_HEADER_SIZE: usize = 4;
+
+/// A change to the broker state made since the last snapshot.
+///
+/// Changes are encoded by the position of their variant, so new variants go
+/// at the end and the meaning of existing ones never changes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StateChange {
+    Session(ClientId, SessionChange),
+    SessionRemoved(ClientId),
+    /// A retained message logged before arrival times were recorded. It is
+    /// replayed as if it had just arrived.
+    LegacyRetainedStored(Publication),
+    RetainedRemoved(String),
+    RetainedStored(ReceivedPublication),
+}
+
+/// A change to the persisted part of a session.
+///
+/// Only subscriptions and queued messages are persisted, so a message
+/// leaving the queue to be sent is logged as `Dequeued`, the same way a
+/// snapshot taken at that moment would not contain it.
+///
+/// Like `StateChange`, variants are encoded by position.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SessionChange {
+    /// The session was created with no subscriptions and no queued messages,
+    /// replacing any previous session with the same client id.
+    Created,
+    Subscribed(String, Subscription),
+    Unsubscribed(String),
+    /// A queued message logged before arrival times were recorded. It is
+    /// replayed as if it had just arrived.
+    LegacyQueued(Publication),
+    Dequeued,
+    Queued(ReceivedPublication),
+}
+
+/// Collects the changes made to the broker state between two flushes.
+pub(crate) struct Journal {
+    changes: Vec<StateChange>,
+
+    /// sessions whose state is known to the log
+    sessions: HashSet<ClientId>,
+
+    /// sessions which may have changed since the last flush
+    changed: HashSet<ClientId>,
+}
+
+impl Journal {
+    /// Starts journaling the changes of sessions loaded from a snapshot.
+    pub fn new(sessions: &mut HashMap<ClientId, Session>) -> Self {
+        let mut known = HashSet::new();
+        for (client_id, session) in sessions {
+            if let Some(state) = session.state_mut() {
+                state.record_changes();
+                known.insert(client_id.clone());
+            }
+        }
+
+        Self {
+            changes: Vec::new(),
+            sessions: known,
+            changed: HashSet::new(),
+        }
+    }
+
+    pub fn retained_stored(&mut self, publication: ReceivedPublication) {
+        self.changes.push(StateChange::RetainedStored(publication));
+    }
+
+    pub fn retained_removed(&mut self, topic_name: String) {
+        self.changes.push(StateChange::RetainedRemoved(topic_name));
+    }
+
+    /// Marks the session of `client_id` to be looked at by the next call to
+    /// `collect`. Sessions which are not marked are assumed unchanged.
+    pub fn session_changed(&mut self, client_id: &ClientId) {
+        if !self.changed.contains(client_id) {
+            self.changed.insert(client_id.clone());
+        }
+    }
+
+    /// Returns all changes made since the last call.
+    ///
+    /// Sessions that became persisted since then, including sessions that
+    /// replaced a previous one with the same client id, are logged with
+    /// their full state. Changes of sessions that are not persisted are
+    /// discarded.
+    pub fn collect(&mut self, sessions: &mut HashMap<ClientId, Session>) -> Vec<StateChange> {
+        for client_id in mem::replace(&mut self.changed, HashSet::new()) {
+            let is_logged = self.sessions.contains(&client_id);
+            let session = sessions.get_mut(&client_id);
+            let is_persisted = session.as_ref().map_or(false, |s| s.is_persisted());
+            let changes = match session.and_then(Session::state_mut) {
+                Some(state) if is_persisted => match state.take_changes() {
+                    Some(changes) if is_logged => Some(changes),
+                    _ => {
+                        state.record_changes();
+                        Some(state.to_changes())
+                    }
+                },
+                Some(state) => {
+                    state.take_changes();
+                    None
+                }
+                None => None,
+            };
+
+            if let Some(changes) = changes {
+                self.changes.extend(
+                    changes
+                        .into_iter()
+                        .map(|change| StateChange::Session(client_id.clone(), change)),
+                );
+                self.sessions.insert(client_id);
+            } else if self.sessions.remove(&client_id) {
+                self.changes.push(StateChange::SessionRemoved(client_id));
+            }
+        }
+
+        mem::replace(&mut self.changes, Vec::new())
+    }
+}
+
+/// Applies logged changes on top of a snapshot.
+pub fn replay<I>(state: BrokerState, changes: I) -> BrokerState
+where
+    I: IntoIterator<Item = StateChange>,
+{
+    let (mut retained, sessions) = state.into_parts();
+    let mut sessions = sessions
+        .into_iter()
+        .map(|session| (session.client_id().clone(), session))
+        .collect::<HashMap<ClientId, SessionState>>();
+
+    for change in changes {
+        match change {
+            StateChange::Session(client_id, SessionChange::Created) => {
+                let session =
+                    SessionState::from_parts(client_id.clone(), HashMap::new(), Default::default());
+                sessions.insert(client_id, session);
+            }
+            StateChange::Session(client_id, change) => match sessions.get_mut(&client_id) {
+                Some(session) => session.apply(change),
+                None => warn!("ignoring logged change for unknown session {}", client_id),
+            },
+            StateChange::SessionRemoved(client_id) => {
+                sessions.remove(&client_id);
+            }
+            StateChange::RetainedStored(publication) => {
+                let topic_name = publication.publication().topic_name.clone();
+                retained.insert(topic_name, publication);
+            }
+            StateChange::LegacyRetainedStored(publication) => {
+                let topic_name = publication.topic_name.clone();
+                retained.insert(topic_name, ReceivedPublication::new(publication));
+            }
+            StateChange::RetainedRemoved(topic_name) => {
+                retained.remove(&topic_name);
+            }
+        }
+    }
+
+    BrokerState::new(retained, sessions.into_iter().map(|(_, s)| s).collect())
+}
+
+/// Encodes changes as records of the log.
+///
+/// Every record is a little endian `u32` length followed by the bincode
+/// encoded change.
+pub(crate) fn encode_changes(changes: &[StateChange]) -> Result<Vec<u8>, PersistError> {
+    let mut buf = Vec::new();
+    for change in changes {
+        let record = bincode::serialize(change).map_err(|e| PersistError::Serialize(Some(e)))?;
+        let len = u32::try_from(record.len())
+            .map_err(|_| PersistError::Serialize(Some(Box::new(bincode::ErrorKind::SizeLimit))))?;
+
+        buf.extend_from_slice(&len.to_le_bytes());
+        buf.extend_from_slice(&record);
+    }
+    Ok(buf)
+}
+
+/// Reads the records of a log.
+///
+/// Returns the changes of all complete records along with the length of the
+/// log they take. A record torn by a crash in the middle of an append ends
+/// the log.
+pub(crate) fn read_changes(log: &[u8]) -> (Vec<StateChange>, usize) {
+    let mut changes = Vec::new();
+    let mut offset = 0;
+
+    while log.len() - offset >= RECORD_HEADER_SIZE {
+        let mut header = [0; RECORD_HEADER_SIZE];
+        header.copy_from_slice(&log[offset..offset + RECORD_HEADER_SIZE]);
+        let start = offset + RECORD_HEADER_SIZE;
+        let end = start + u32::from_le_bytes(header) as usize;
+        if end > log.len() {
+            break;
+        }
+
+        match bincode::deserialize(&log[start..end]) {
+            Ok(change) => changes.push(change),
+            Err(e) => {
+                warn!(message = "failed to read state change from log", error = %e);
+                break;
+            }
+        }
+        offset = end;
+    }
+
+    if offset < log.len() {
+        warn!(
+            "ignoring {} bytes of incomplete records at the end of the log",
+            log.len() - offset
+        );
+    }
+
+    (changes, offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::VecDeque;
+    use std::time::{Duration, SystemTime};
+
+    use bytes::Bytes;
+    use mqtt3::proto;
+
+    fn publication(topic_name: &str) -> Publication {
+        Publication {
+            topic_name: topic_name.to_string(),
+            qos: proto::QoS::AtLeastOnce,
+            retain: false,
+            payload: Bytes::from("payload"),
+            properties: proto::Properties::default(),
+        }
+    }
+
+    fn received(topic_name: &str) -> ReceivedPublication {
+        let received_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        ReceivedPublication::from_parts(publication(topic_name), received_at)
+    }
+
+    fn changes() -> Vec<StateChange> {
+        let client_id = ClientId::from("client");
+        vec![
+            StateChange::Session(client_id.clone(), SessionChange::Created),
+            StateChange::Session(client_id.clone(), SessionChange::Queued(received("a"))),
+            StateChange::Session(client_id.clone(), SessionChange::Queued(received("b"))),
+            StateChange::Session(client_id, SessionChange::Dequeued),
+            StateChange::RetainedStored(received("c")),
+            StateChange::RetainedStored(received("d")),
+            StateChange::RetainedRemoved("c".to_string()),
+            StateChange::LegacyRetainedStored(publication("e")),
+            StateChange::SessionRemoved(ClientId::from("old")),
+        ]
+    }
+
+    #[test]
+    fn replay_applies_changes() {
+        let old = SessionState::new(ClientId::from("old"));
+        let state = BrokerState::new(HashMap::new(), vec![old]);
+
+        let (retained, sessions) = replay(state, changes()).into_parts();
+
+        assert_eq!(2, retained.len());
+        assert_eq!(Some(&received("d")), retained.get("d"));
+        assert!(retained.contains_key("e"));
+        assert_eq!(1, sessions.len());
+        let (client_id, subscriptions, queued) = sessions[0].clone().into_parts();
+        assert_eq!(ClientId::from("client"), client_id);
+        assert!(subscriptions.is_empty());
+        assert_eq!(VecDeque::from(vec![received("b")]), queued);
+    }
+
+    #[test]
+    fn read_changes_roundtrip() {
+        let log = encode_changes(&changes()).unwrap();
+
+        let (read, len) = read_changes(&log);
+        assert_eq!(changes(), read);
+        assert_eq!(log.len(), len);
+    }
+
+    #[test]
+    fn read_changes_ignores_torn_record() {
+        let mut log = encode_changes(&changes()).unwrap();
+        let complete = log.len();
+        log.extend(encode_changes(&changes()[..1]).unwrap());
+        log.truncate(log.len() - 1);
+
+        let (read, len) = read_changes(&log);
+        assert_eq!(changes(), read);
+        assert_eq!(complete, len);
+    }
+}
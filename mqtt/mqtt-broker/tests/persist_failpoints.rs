@@ -1,6 +1,11 @@
 use fail::FailScenario;
 
-use mqtt_broker::{BrokerState, ConsolidatedStateFormat, FilePersistor, Persist, PersistError};
+use bytes::Bytes;
+use mqtt3::proto::{Properties, Publication, QoS};
+use mqtt_broker::{
+    BrokerState, ClientId, ConsolidatedStateFormat, FilePersistor, Persist, PersistError,
+    ReceivedPublication, SessionChange, StateChange,
+};
 use proptest::collection::vec;
 use proptest::prelude::*;
 use tempfile::TempDir;
@@ -11,6 +16,8 @@ const FAILPOINTS: &[&str] = &[
     "filepersistor.load.fileopen",
     "filepersistor.load.format",
     "filepersistor.load.spawn_blocking",
+    "filepersistor.load.wal_read",
+    "filepersistor.load.wal_truncate",
     "filepersistor.store.fileopen",
     "filepersistor.store.filerename",
     "filepersistor.store.symlink_unlink",
@@ -19,13 +26,19 @@ const FAILPOINTS: &[&str] = &[
     "filepersistor.store.readdir",
     "filepersistor.store.entry_unlink",
     "filepersistor.store.new_file_unlink",
+    "filepersistor.store.wal_unlink",
     "filepersistor.store.spawn_blocking",
+    "filepersistor.append.createdir",
+    "filepersistor.append.fileopen",
+    "filepersistor.append.write",
+    "filepersistor.append.spawn_blocking",
 ];
 
 #[derive(Clone, Debug)]
 enum Op {
     Load,
     Store(BrokerState),
+    Append(Vec<StateChange>),
     AddFailpoint(&'static str),
     RemoveFailpoint(&'static str),
 }
@@ -34,11 +47,29 @@ fn arb_op() -> impl Strategy<Value = Op> {
     prop_oneof![
         Just(Op::Load),
         Just(Op::Store(BrokerState::default())),
+        Just(Op::Append(changes())),
         proptest::sample::select(FAILPOINTS).prop_map(|f| Op::AddFailpoint(f)),
         proptest::sample::select(FAILPOINTS).prop_map(|f| Op::RemoveFailpoint(f)),
     ]
 }
 
+fn changes() -> Vec<StateChange> {
+    let client_id = ClientId::from("client");
+    let publication = ReceivedPublication::new(Publication {
+        topic_name: "topic".to_string(),
+        qos: QoS::AtLeastOnce,
+        retain: true,
+        payload: Bytes::from("payload"),
+        properties: Properties::default(),
+    });
+
+    vec![
+        StateChange::Session(client_id.clone(), SessionChange::Created),
+        StateChange::Session(client_id, SessionChange::Queued(publication.clone())),
+        StateChange::RetainedStored(publication),
+    ]
+}
+
 fn tear_down_failpoints() {
     for (name, _) in fail::list() {
         fail::remove(name);
@@ -64,6 +95,9 @@ async fn test_persistor(count: usize, ops: Vec<Op>) {
             Op::Store(state) => {
                 let _ = persistor.store(state).await;
             }
+            Op::Append(changes) => {
+                let _ = persistor.append(changes).await;
+            }
             Op::AddFailpoint(f) => fail::cfg(f, "return").unwrap(),
             Op::RemoveFailpoint(f) => fail::remove(f),
         }
@@ -16,6 +16,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             mqtt3::proto::Packet::ConnAck(mqtt3::proto::ConnAck {
                 session_present: true,
                 return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                properties: Default::default(),
             }),
         ),
         (
@@ -28,16 +29,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     qos: mqtt3::proto::QoS::ExactlyOnce,
                     retain: true,
                     payload: b"\x00\x01\x02\xFF\xFE\xFD"[..].into(),
+                    properties: Default::default(),
                 }),
                 client_id: mqtt3::proto::ClientId::IdWithExistingSession("id".to_string()),
                 keep_alive: std::time::Duration::from_secs(5),
                 protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                 protocol_level: mqtt3::PROTOCOL_LEVEL,
+                properties: Default::default(),
             }),
         ),
         (
             "disconnect",
-            mqtt3::proto::Packet::Disconnect(mqtt3::proto::Disconnect),
+            mqtt3::proto::Packet::Disconnect(mqtt3::proto::Disconnect::default()),
         ),
         (
             "pingreq",
@@ -51,12 +54,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             "puback",
             mqtt3::proto::Packet::PubAck(mqtt3::proto::PubAck {
                 packet_identifier: mqtt3::proto::PacketIdentifier::new(5).unwrap(),
+                reason_code: mqtt3::proto::ReasonCode::SUCCESS,
+                properties: mqtt3::proto::Properties::default(),
             }),
         ),
         (
             "pubcomp",
             mqtt3::proto::Packet::PubComp(mqtt3::proto::PubComp {
                 packet_identifier: mqtt3::proto::PacketIdentifier::new(5).unwrap(),
+                reason_code: mqtt3::proto::ReasonCode::SUCCESS,
+                properties: mqtt3::proto::Properties::default(),
             }),
         ),
         (
@@ -69,18 +76,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 retain: true,
                 topic_name: "publish-topic".to_string(),
                 payload: b"\x00\x01\x02\xFF\xFE\xFD"[..].into(),
+                properties: Default::default(),
             }),
         ),
         (
             "pubrec",
             mqtt3::proto::Packet::PubRec(mqtt3::proto::PubRec {
                 packet_identifier: mqtt3::proto::PacketIdentifier::new(5).unwrap(),
+                reason_code: mqtt3::proto::ReasonCode::SUCCESS,
+                properties: mqtt3::proto::Properties::default(),
             }),
         ),
         (
             "pubrel",
             mqtt3::proto::Packet::PubRel(mqtt3::proto::PubRel {
                 packet_identifier: mqtt3::proto::PacketIdentifier::new(5).unwrap(),
+                reason_code: mqtt3::proto::ReasonCode::SUCCESS,
+                properties: mqtt3::proto::Properties::default(),
             }),
         ),
         (
@@ -89,7 +101,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 packet_identifier: mqtt3::proto::PacketIdentifier::new(5).unwrap(),
                 qos: vec![
                     mqtt3::proto::SubAckQos::Success(mqtt3::proto::QoS::ExactlyOnce),
-                    mqtt3::proto::SubAckQos::Failure,
+                    mqtt3::proto::SubAckQos::Failure(mqtt3::proto::ReasonCode::UNSPECIFIED_ERROR),
                 ],
             }),
         ),
@@ -100,13 +112,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 subscribe_to: vec![mqtt3::proto::SubscribeTo {
                     topic_filter: "subscribe-topic".to_string(),
                     qos: mqtt3::proto::QoS::ExactlyOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }],
+                properties: mqtt3::proto::Properties::default(),
             }),
         ),
         (
             "unsuback",
             mqtt3::proto::Packet::UnsubAck(mqtt3::proto::UnsubAck {
                 packet_identifier: mqtt3::proto::PacketIdentifier::new(5).unwrap(),
+                reason_codes: vec![],
             }),
         ),
         (
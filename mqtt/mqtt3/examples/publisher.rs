@@ -134,6 +134,7 @@ fn main() {
                         qos,
                         retain: false,
                         payload,
+                        properties: Default::default(),
                     })
                     .await;
                 let () = result.expect("couldn't publish");
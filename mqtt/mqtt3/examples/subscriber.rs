@@ -103,7 +103,11 @@ fn main() {
         .expect("couldn't get subscription update handle");
     runtime.spawn(async move {
         let result = update_subscription_handle
-            .subscribe(mqtt3::proto::SubscribeTo { topic_filter, qos })
+            .subscribe(mqtt3::proto::SubscribeTo {
+                topic_filter,
+                qos,
+                options: mqtt3::proto::SubscriptionOptions::default(),
+            })
             .await;
         if let Err(err) = result {
             panic!("couldn't update subscription: {}", err);
@@ -90,6 +90,7 @@ fn main() {
         qos,
         retain: false,
         payload: payload.into(),
+        properties: Default::default(),
     };
 
     let mut client = mqtt3::Client::new(
@@ -115,6 +116,7 @@ fn main() {
             .subscribe(mqtt3::proto::SubscribeTo {
                 topic_filter: topic,
                 qos,
+                options: mqtt3::proto::SubscriptionOptions::default(),
             })
             .await;
         if let Err(err) = result {
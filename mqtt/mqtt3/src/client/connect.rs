@@ -156,6 +156,7 @@ where
                             keep_alive,
                             protocol_name: crate::PROTOCOL_NAME.to_string(),
                             protocol_level: crate::PROTOCOL_LEVEL,
+                            properties: Default::default(),
                         });
 
                         match std::pin::Pin::new(&mut *framed).start_send(packet) {
@@ -199,6 +200,7 @@ where
                         crate::proto::Packet::ConnAck(crate::proto::ConnAck {
                             session_present,
                             return_code: crate::proto::ConnectReturnCode::Accepted,
+                            ..
                         }) => {
                             self.current_back_off = std::time::Duration::from_secs(0);
 
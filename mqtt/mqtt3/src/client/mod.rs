@@ -7,6 +7,12 @@ mod ping;
 mod publish;
 pub use publish::{PublishError, PublishHandle};
 
+mod store;
+#[cfg(feature = "serde1")]
+pub use store::FileStore;
+use store::SessionSaver;
+pub use store::{MemoryStore, Session, SessionStore};
+
 mod subscriptions;
 pub use subscriptions::{UpdateSubscriptionError, UpdateSubscriptionHandle};
 
@@ -89,9 +95,75 @@ where
             subscriptions: Default::default(),
 
             packets_waiting_to_be_sent: Default::default(),
+
+            session_store: None,
         })
     }
 
+    /// Create a new client that resumes the session saved in the given [`SessionStore`], if any
+    ///
+    /// The client connects with the given `client_id` and without a clean session, so the server keeps its session state
+    /// across connections. Publications that have not been acked by the server, the packet identifiers they use,
+    /// and subscriptions are saved to `session_store` as they change, so a new client created with the same store
+    /// after a restart of the process resends them exactly where the previous client left off.
+    ///
+    /// The other parameters are the same as for [`Client::new`].
+    pub fn with_session_store<S>(
+        client_id: String,
+        username: Option<String>,
+        will: Option<crate::proto::Publication>,
+        io_source: IoS,
+        max_reconnect_back_off: std::time::Duration,
+        keep_alive: std::time::Duration,
+        mut session_store: S,
+    ) -> Result<Self, Error>
+    where
+        S: SessionStore + 'static,
+    {
+        let session = session_store.load().map_err(Error::LoadSession)?;
+
+        let mut client = Client::new(
+            None,
+            username,
+            will,
+            io_source,
+            max_reconnect_back_off,
+            keep_alive,
+        );
+
+        match &mut client.0 {
+            ClientState::Up {
+                client_id: id,
+                packet_identifiers,
+                publish,
+                subscriptions,
+                session_store: store,
+                ..
+            } => {
+                *id = crate::proto::ClientId::IdWithExistingSession(client_id);
+
+                if let Some(session) = &session {
+                    log::debug!("Resuming saved session");
+
+                    if let Some(last_packet_identifier) = session.last_packet_identifier {
+                        packet_identifiers.previous = last_packet_identifier;
+                    }
+                    publish.restore(session, packet_identifiers);
+                    subscriptions.restore(session);
+                }
+
+                *store = Some(
+                    SessionSaver::new(Box::new(session_store), session)
+                        .map_err(Error::SaveSession)?,
+                );
+            }
+
+            _ => unreachable!(),
+        }
+
+        Ok(client)
+    }
+
     /// Queues a message to be published to the server
     pub fn publish(
         &mut self,
@@ -199,6 +271,8 @@ where
                     subscriptions,
 
                     packets_waiting_to_be_sent,
+
+                    session_store,
                     ..
                 } => {
                     match std::pin::Pin::new(shutdown_recv).poll_next(cx) {
@@ -236,6 +310,16 @@ where
                             subscriptions.new_connection(reset_session, packet_identifiers),
                         );
 
+                        if let Err(err) = save_session(
+                            session_store,
+                            packet_identifiers,
+                            publish,
+                            subscriptions,
+                            packets_waiting_to_be_sent.iter().any(needs_sync),
+                        ) {
+                            break Some(err);
+                        }
+
                         return std::task::Poll::Ready(Some(Ok(Event::NewConnection {
                             reset_session,
                         })));
@@ -250,6 +334,7 @@ where
                         ping,
                         publish,
                         subscriptions,
+                        session_store,
                     ) {
                         std::task::Poll::Ready(Ok(event)) => {
                             return std::task::Poll::Ready(Some(Ok(event)))
@@ -339,8 +424,9 @@ where
                         } else {
                             match std::pin::Pin::new(&mut framed).poll_ready(cx) {
                                 std::task::Poll::Ready(Ok(())) => {
-                                    let packet =
-                                        crate::proto::Packet::Disconnect(crate::proto::Disconnect);
+                                    let packet = crate::proto::Packet::Disconnect(
+                                        crate::proto::Disconnect::default(),
+                                    );
                                     match std::pin::Pin::new(&mut framed).start_send(packet) {
                                         Ok(()) => *sent_disconnect = true,
 
@@ -459,6 +545,7 @@ pub enum Event {
 
 /// A subscription update event
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(serde::Deserialize, serde::Serialize))]
 pub enum SubscriptionUpdateEvent {
     Subscribe(crate::proto::SubscribeTo),
     Unsubscribe(String),
@@ -514,6 +601,9 @@ where
 
         /// Packets waiting to be written to the underlying `Framed`
         packets_waiting_to_be_sent: std::collections::VecDeque<crate::proto::Packet>,
+
+        /// Where the session is saved, if the client was created with [`Client::with_session_store`]
+        session_store: Option<SessionSaver>,
     },
 
     ShuttingDown {
@@ -547,6 +637,7 @@ fn client_poll<S>(
     ping: &mut ping::State,
     publish: &mut publish::State,
     subscriptions: &mut subscriptions::State,
+    session_store: &mut Option<SessionSaver>,
 ) -> std::task::Poll<Result<Event, Error>>
 where
     S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
@@ -555,19 +646,34 @@ where
     use futures_sink::Sink;
 
     loop {
-        // Begin sending any packets waiting to be sent
-        while let Some(packet) = packets_waiting_to_be_sent.pop_front() {
-            match std::pin::Pin::new(&mut *framed).poll_ready(cx) {
+        // Packets waiting to be sent may depend on a session that is still being synced,
+        // so hold them back until it is on stable storage.
+        let session_synced = match session_store {
+            Some(session_store) => match session_store.poll_synced(cx) {
                 std::task::Poll::Ready(result) => {
-                    let () = result.map_err(Error::EncodePacket)?;
-                    let () = std::pin::Pin::new(&mut *framed)
-                        .start_send(packet)
-                        .map_err(Error::EncodePacket)?;
+                    let () = result.map_err(Error::SaveSession)?;
+                    true
                 }
+                std::task::Poll::Pending => false,
+            },
+            None => true,
+        };
+
+        // Begin sending any packets waiting to be sent
+        if session_synced {
+            while let Some(packet) = packets_waiting_to_be_sent.pop_front() {
+                match std::pin::Pin::new(&mut *framed).poll_ready(cx) {
+                    std::task::Poll::Ready(result) => {
+                        let () = result.map_err(Error::EncodePacket)?;
+                        let () = std::pin::Pin::new(&mut *framed)
+                            .start_send(packet)
+                            .map_err(Error::EncodePacket)?;
+                    }
 
-                std::task::Poll::Pending => {
-                    packets_waiting_to_be_sent.push_front(packet);
-                    break;
+                    std::task::Poll::Pending => {
+                        packets_waiting_to_be_sent.push_front(packet);
+                        break;
+                    }
                 }
             }
         }
@@ -594,6 +700,7 @@ where
             }
             std::task::Poll::Pending => None,
         };
+        let received_packet = packet.is_some();
 
         let mut new_packets_to_be_sent = vec![];
 
@@ -620,6 +727,18 @@ where
 
         assert!(packet.is_none(), "unconsumed packet");
 
+        // A received packet or a packet about to be sent may have changed the session,
+        // so save it before anything that depends on the change reaches the server.
+        if received_packet || !new_packets_to_be_sent.is_empty() {
+            save_session(
+                session_store,
+                packet_identifiers,
+                publish,
+                subscriptions,
+                new_packets_to_be_sent.iter().any(needs_sync),
+            )?;
+        }
+
         if !new_packets_to_be_sent.is_empty() {
             // Have new packets to send, so keep looping
             continue_loop = true;
@@ -640,6 +759,45 @@ where
     }
 }
 
+fn save_session(
+    session_store: &mut Option<SessionSaver>,
+    packet_identifiers: &PacketIdentifiers,
+    publish: &publish::State,
+    subscriptions: &subscriptions::State,
+    sync: bool,
+) -> Result<(), Error> {
+    if let Some(session_store) = session_store {
+        let mut session = Session {
+            last_packet_identifier: Some(packet_identifiers.previous),
+            ..Default::default()
+        };
+        publish.save(&mut session);
+        subscriptions.save(&mut session);
+        session_store
+            .save(session, sync)
+            .map_err(Error::SaveSession)?;
+    }
+
+    Ok(())
+}
+
+/// Whether sending the packet relies on the saved session being on stable storage,
+/// because it is a QoS 1 or 2 PUBLISH or acknowledges one.
+fn needs_sync(packet: &crate::proto::Packet) -> bool {
+    match packet {
+        crate::proto::Packet::Publish(crate::proto::Publish {
+            packet_identifier_dup_qos: crate::proto::PacketIdentifierDupQoS::AtMostOnce,
+            ..
+        }) => false,
+        crate::proto::Packet::Publish(_)
+        | crate::proto::Packet::PubAck(_)
+        | crate::proto::Packet::PubRec(_)
+        | crate::proto::Packet::PubRel(_)
+        | crate::proto::Packet::PubComp(_) => true,
+        _ => false,
+    }
+}
+
 struct PacketIdentifiers {
     in_use: Box<[usize; PacketIdentifiers::SIZE]>,
     previous: crate::proto::PacketIdentifier,
@@ -678,6 +836,12 @@ impl PacketIdentifiers {
         *block &= !mask;
     }
 
+    /// Marks a packet identifier of a restored session as in use
+    fn restore(&mut self, packet_identifier: crate::proto::PacketIdentifier) {
+        let (block, mask) = self.entry(packet_identifier);
+        *block |= mask;
+    }
+
     fn entry(&mut self, packet_identifier: crate::proto::PacketIdentifier) -> (&mut usize, usize) {
         let packet_identifier = usize::from(packet_identifier.get());
         let (block, offset) = (
@@ -710,8 +874,10 @@ pub enum Error {
     DecodePacket(crate::proto::DecodeError),
     DuplicateExactlyOncePublishPacketNotMarkedDuplicate(crate::proto::PacketIdentifier),
     EncodePacket(crate::proto::EncodeError),
+    LoadSession(std::io::Error),
     PacketIdentifiersExhausted,
     PingTimer(tokio::time::Error),
+    SaveSession(std::io::Error),
     ServerClosedConnection,
     SubAckDoesNotContainEnoughQoS(crate::proto::PacketIdentifier, usize, usize),
     SubscriptionDowngraded(String, crate::proto::QoS, crate::proto::QoS),
@@ -732,6 +898,7 @@ impl Error {
     fn is_user_error(&self) -> bool {
         match self {
             Error::EncodePacket(err) => err.is_user_error(),
+            Error::LoadSession(_) | Error::SaveSession(_) => true,
             _ => false,
         }
     }
@@ -768,12 +935,18 @@ impl std::fmt::Display for Error {
 			Error::EncodePacket(err) =>
 				write!(f, "could not encode packet: {}", err),
 
+			Error::LoadSession(err) =>
+				write!(f, "could not load session: {}", err),
+
 			Error::PacketIdentifiersExhausted =>
 				write!(f, "all packet identifiers exhausted"),
 
 			Error::PingTimer(err) =>
 				write!(f, "ping timer failed: {}", err),
 
+			Error::SaveSession(err) =>
+				write!(f, "could not save session: {}", err),
+
 			Error::ServerClosedConnection =>
 				write!(f, "connection closed by server"),
 
@@ -802,8 +975,10 @@ impl std::error::Error for Error {
             Error::DecodePacket(err) => Some(err),
             Error::DuplicateExactlyOncePublishPacketNotMarkedDuplicate(_) => None,
             Error::EncodePacket(err) => Some(err),
+            Error::LoadSession(err) => Some(err),
             Error::PacketIdentifiersExhausted => None,
             Error::PingTimer(err) => Some(err),
+            Error::SaveSession(err) => Some(err),
             Error::ServerClosedConnection => None,
             Error::SubAckDoesNotContainEnoughQoS(_, _, _) => None,
             Error::SubscriptionDowngraded(_, _, _) => None,
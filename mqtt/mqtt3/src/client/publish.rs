@@ -45,39 +45,41 @@ impl State {
         let mut publication_received = None;
 
         match packet.take() {
-            Some(crate::proto::Packet::PubAck(crate::proto::PubAck { packet_identifier })) => {
-                match self.waiting_to_be_acked.remove(&packet_identifier) {
-                    Some((ack_sender, _)) => {
-                        packet_identifiers.discard(packet_identifier);
+            Some(crate::proto::Packet::PubAck(crate::proto::PubAck {
+                packet_identifier, ..
+            })) => match self.waiting_to_be_acked.remove(&packet_identifier) {
+                Some((ack_sender, _)) => {
+                    packet_identifiers.discard(packet_identifier);
 
-                        match ack_sender.send(()) {
+                    match ack_sender.send(()) {
 						Ok(()) => (),
 						Err(()) => log::debug!("could not send ack for publish request because ack receiver has been dropped"),
 					}
-                    }
-                    None => log::warn!("ignoring PUBACK for a PUBLISH we never sent"),
                 }
-            }
+                None => log::warn!("ignoring PUBACK for a PUBLISH we never sent"),
+            },
 
-            Some(crate::proto::Packet::PubComp(crate::proto::PubComp { packet_identifier })) => {
-                match self.waiting_to_be_completed.remove(&packet_identifier) {
-                    Some((ack_sender, _)) => {
-                        packet_identifiers.discard(packet_identifier);
+            Some(crate::proto::Packet::PubComp(crate::proto::PubComp {
+                packet_identifier,
+                ..
+            })) => match self.waiting_to_be_completed.remove(&packet_identifier) {
+                Some((ack_sender, _)) => {
+                    packet_identifiers.discard(packet_identifier);
 
-                        match ack_sender.send(()) {
+                    match ack_sender.send(()) {
 						Ok(()) => (),
 						Err(()) => log::debug!("could not send ack for publish request because ack receiver has been dropped"),
 					}
-                    }
-                    None => log::warn!("ignoring PUBCOMP for a PUBREL we never sent"),
                 }
-            }
+                None => log::warn!("ignoring PUBCOMP for a PUBREL we never sent"),
+            },
 
             Some(crate::proto::Packet::Publish(crate::proto::Publish {
                 packet_identifier_dup_qos,
                 retain,
                 topic_name,
                 payload,
+                ..
             })) => match packet_identifier_dup_qos {
                 crate::proto::PacketIdentifierDupQoS::AtMostOnce => {
                     publication_received = Some(crate::ReceivedPublication {
@@ -99,7 +101,11 @@ impl State {
                     });
 
                     packets_waiting_to_be_sent.push(crate::proto::Packet::PubAck(
-                        crate::proto::PubAck { packet_identifier },
+                        crate::proto::PubAck {
+                            packet_identifier,
+                            reason_code: crate::proto::ReasonCode::SUCCESS,
+                            properties: crate::proto::Properties::default(),
+                        },
                     ));
                 }
 
@@ -128,12 +134,18 @@ impl State {
                     }
 
                     packets_waiting_to_be_sent.push(crate::proto::Packet::PubRec(
-                        crate::proto::PubRec { packet_identifier },
+                        crate::proto::PubRec {
+                            packet_identifier,
+                            reason_code: crate::proto::ReasonCode::SUCCESS,
+                            properties: crate::proto::Properties::default(),
+                        },
                     ));
                 }
             },
 
-            Some(crate::proto::Packet::PubRec(crate::proto::PubRec { packet_identifier })) => {
+            Some(crate::proto::Packet::PubRec(crate::proto::PubRec {
+                packet_identifier, ..
+            })) => {
                 match self.waiting_to_be_acked.remove(&packet_identifier) {
                     Some((ack_sender, packet)) => {
                         self.waiting_to_be_completed
@@ -143,11 +155,17 @@ impl State {
                 }
 
                 packets_waiting_to_be_sent.push(crate::proto::Packet::PubRel(
-                    crate::proto::PubRel { packet_identifier },
+                    crate::proto::PubRel {
+                        packet_identifier,
+                        reason_code: crate::proto::ReasonCode::SUCCESS,
+                        properties: crate::proto::Properties::default(),
+                    },
                 ));
             }
 
-            Some(crate::proto::Packet::PubRel(crate::proto::PubRel { packet_identifier })) => {
+            Some(crate::proto::Packet::PubRel(crate::proto::PubRel {
+                packet_identifier, ..
+            })) => {
                 if let Some(publication) = self.waiting_to_be_released.remove(&packet_identifier) {
                     packet_identifiers.discard(packet_identifier);
                     publication_received = Some(publication);
@@ -156,7 +174,11 @@ impl State {
                 }
 
                 packets_waiting_to_be_sent.push(crate::proto::Packet::PubComp(
-                    crate::proto::PubComp { packet_identifier },
+                    crate::proto::PubComp {
+                        packet_identifier,
+                        reason_code: crate::proto::ReasonCode::SUCCESS,
+                        properties: crate::proto::Properties::default(),
+                    },
                 ));
             }
 
@@ -184,6 +206,7 @@ impl State {
                             retain: publication.retain,
                             topic_name: publication.topic_name,
                             payload: publication.payload,
+                            properties: publication.properties,
                         },
                     ));
 
@@ -215,6 +238,7 @@ impl State {
                         retain: publication.retain,
                         topic_name: publication.topic_name.clone(),
                         payload: publication.payload.clone(),
+                        properties: publication.properties.clone(),
                     });
 
                     self.waiting_to_be_acked.insert(
@@ -230,6 +254,7 @@ impl State {
                                 retain: publication.retain,
                                 topic_name: publication.topic_name,
                                 payload: publication.payload,
+                                properties: publication.properties,
                             },
                         ),
                     );
@@ -259,6 +284,7 @@ impl State {
                         retain: publication.retain,
                         topic_name: publication.topic_name.clone(),
                         payload: publication.payload.clone(),
+                        properties: publication.properties.clone(),
                     });
 
                     self.waiting_to_be_acked.insert(
@@ -274,6 +300,7 @@ impl State {
                                 retain: publication.retain,
                                 topic_name: publication.topic_name,
                                 payload: publication.payload,
+                                properties: publication.properties,
                             },
                         ),
                     );
@@ -309,7 +336,11 @@ impl State {
                 self.waiting_to_be_released
                     .keys()
                     .map(|&packet_identifier| {
-                        crate::proto::Packet::PubRec(crate::proto::PubRec { packet_identifier })
+                        crate::proto::Packet::PubRec(crate::proto::PubRec {
+                            packet_identifier,
+                            reason_code: crate::proto::ReasonCode::SUCCESS,
+                            properties: crate::proto::Properties::default(),
+                        })
                     }),
             )
             .chain(
@@ -319,6 +350,91 @@ impl State {
             )
     }
 
+    pub(super) fn save(&self, session: &mut super::Session) {
+        session.publications = self
+            .publish_requests_waiting_to_be_sent
+            .iter()
+            .map(|publish_request| publish_request.publication.clone())
+            .collect();
+
+        session.waiting_to_be_acked = self
+            .waiting_to_be_acked
+            .values()
+            .map(|(_, packet)| packet.clone())
+            .collect();
+
+        session.waiting_to_be_completed = self
+            .waiting_to_be_completed
+            .values()
+            .map(|(_, packet)| packet.clone())
+            .collect();
+
+        session.waiting_to_be_released = self
+            .waiting_to_be_released
+            .iter()
+            .map(|(&packet_identifier, publication)| crate::proto::Publish {
+                packet_identifier_dup_qos: crate::proto::PacketIdentifierDupQoS::ExactlyOnce(
+                    packet_identifier,
+                    publication.dup,
+                ),
+                retain: publication.retain,
+                topic_name: publication.topic_name.clone(),
+                payload: publication.payload.clone(),
+                properties: Default::default(),
+            })
+            .collect();
+    }
+
+    pub(super) fn restore(
+        &mut self,
+        session: &super::Session,
+        packet_identifiers: &mut super::PacketIdentifiers,
+    ) {
+        // Whoever queued these publications went away with the previous client, so nobody is waiting for their acks.
+
+        for publication in &session.publications {
+            let (ack_sender, _) = futures_channel::oneshot::channel();
+            self.publish_requests_waiting_to_be_sent
+                .push_back(PublishRequest {
+                    publication: publication.clone(),
+                    ack_sender,
+                });
+        }
+
+        restore_sent_packets(
+            &session.waiting_to_be_acked,
+            &mut self.waiting_to_be_acked,
+            packet_identifiers,
+        );
+
+        restore_sent_packets(
+            &session.waiting_to_be_completed,
+            &mut self.waiting_to_be_completed,
+            packet_identifiers,
+        );
+
+        for packet in &session.waiting_to_be_released {
+            match packet.packet_identifier_dup_qos {
+                crate::proto::PacketIdentifierDupQoS::ExactlyOnce(packet_identifier, dup) => {
+                    self.waiting_to_be_released.insert(
+                        packet_identifier,
+                        crate::ReceivedPublication {
+                            topic_name: packet.topic_name.clone(),
+                            dup,
+                            qos: crate::proto::QoS::ExactlyOnce,
+                            retain: packet.retain,
+                            payload: packet.payload.clone(),
+                        },
+                    );
+                }
+
+                _ => log::warn!(
+                    "ignoring saved PUBLISH waiting for a PUBREL that is not ExactlyOnce"
+                ),
+            }
+        }
+    }
+
     pub(super) fn publish(
         &mut self,
         publication: crate::proto::Publication,
@@ -360,6 +476,31 @@ impl Default for State {
     }
 }
 
+fn restore_sent_packets(
+    packets: &[crate::proto::Publish],
+    waiting: &mut std::collections::BTreeMap<
+        crate::proto::PacketIdentifier,
+        (futures_channel::oneshot::Sender<()>, crate::proto::Publish),
+    >,
+    packet_identifiers: &mut super::PacketIdentifiers,
+) {
+    for packet in packets {
+        match packet.packet_identifier_dup_qos {
+            crate::proto::PacketIdentifierDupQoS::AtLeastOnce(packet_identifier, _)
+            | crate::proto::PacketIdentifierDupQoS::ExactlyOnce(packet_identifier, _) => {
+                packet_identifiers.restore(packet_identifier);
+
+                let (ack_sender, _) = futures_channel::oneshot::channel();
+                waiting.insert(packet_identifier, (ack_sender, packet.clone()));
+            }
+
+            crate::proto::PacketIdentifierDupQoS::AtMostOnce => {
+                log::warn!("ignoring saved AtMostOnce PUBLISH that cannot be waiting for an ack")
+            }
+        }
+    }
+}
+
 /// Used to publish messages to the server
 #[derive(Clone)]
 pub struct PublishHandle(futures_channel::mpsc::Sender<PublishRequest>);
@@ -432,11 +573,12 @@ impl PublishRequest {
             retain: publication.retain,
             topic_name: publication.topic_name,
             payload: publication.payload,
+            properties: publication.properties,
         };
 
         let mut counter = crate::proto::ByteCounter::new();
         let encode_result = packet
-            .encode(&mut counter)
+            .encode(&mut counter, crate::proto::ProtocolVersion::V311)
             .and_then(|()| crate::proto::encode_remaining_length(counter.0, &mut counter));
 
         let publication = crate::proto::Publication {
@@ -444,6 +586,7 @@ impl PublishRequest {
             qos: publication.qos,
             retain: publication.retain,
             payload: packet.payload,
+            properties: packet.properties,
         };
 
         match encode_result {
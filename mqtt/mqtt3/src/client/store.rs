@@ -0,0 +1,490 @@
+/// The state of a client session that must survive a restart of the client in order to resume it.
+///
+/// Packet identifiers in use are recovered from the PUBLISH packets in the session.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(serde::Deserialize, serde::Serialize))]
+pub struct Session {
+    /// The last packet identifier reserved by the client. New packet identifiers continue from this one.
+    pub last_packet_identifier: Option<crate::proto::PacketIdentifier>,
+
+    /// Publications queued by the client that have not been sent to the server yet
+    pub publications: Vec<crate::proto::Publication>,
+
+    /// PUBLISH packets sent by the client, waiting for a corresponding PUBACK or PUBREC
+    pub waiting_to_be_acked: Vec<crate::proto::Publish>,
+
+    /// PUBLISH packets sent by the client, waiting for a corresponding PUBCOMP
+    pub waiting_to_be_completed: Vec<crate::proto::Publish>,
+
+    /// ExactlyOnce PUBLISH packets received from the server that were acked with a PUBREC, waiting for a corresponding PUBREL
+    pub waiting_to_be_released: Vec<crate::proto::Publish>,
+
+    /// Subscriptions acked by the server
+    pub subscriptions: Vec<crate::proto::SubscribeTo>,
+
+    /// Subscription updates that have not been acked by the server yet, in the order they were requested
+    pub subscription_updates: Vec<super::SubscriptionUpdateEvent>,
+}
+
+/// Persists the [`Session`] of a [`Client`](super::Client) created with [`Client::with_session_store`](super::Client::with_session_store).
+///
+/// The client hands every change of the session to a dedicated thread, which calls [`SessionStore::save`] in order,
+/// so a slow store does not block the client's `poll_next`. Sessions that are superseded before the thread gets to them are skipped.
+pub trait SessionStore: std::fmt::Debug + Send {
+    /// Loads the last saved session, if any
+    fn load(&mut self) -> std::io::Result<Option<Session>>;
+
+    /// Saves the given session, replacing the last saved one
+    ///
+    /// `sync` is set when the client is about to send a QoS 1 or 2 PUBLISH, or an acknowledgement of one, that depends on the session.
+    /// The session must then be on stable storage when this returns, and the client does not send anything until it has returned.
+    /// Otherwise the store may leave the session to be written back later.
+    fn save(&mut self, session: &Session, sync: bool) -> std::io::Result<()>;
+}
+
+/// Saves the sessions of a client to its [`SessionStore`] on a dedicated thread.
+///
+/// A session that has not changed since it was last saved is not saved again.
+#[derive(Debug)]
+pub(super) struct SessionSaver {
+    saved: Option<Session>,
+    synced: bool,
+
+    /// Sequence number of the last session handed to the thread
+    queued: u64,
+
+    /// Sequence number of the last session that must be synced before the client sends anything else
+    sync_required: u64,
+
+    shared: std::sync::Arc<SaverShared>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+#[derive(Debug, Default)]
+struct SaverShared {
+    state: std::sync::Mutex<SaverState>,
+    changed: std::sync::Condvar,
+}
+
+#[derive(Debug, Default)]
+struct SaverState {
+    /// The next session to save, whether it must be synced, and its sequence number
+    next: Option<(Session, bool, u64)>,
+
+    /// Sequence number of the last session saved by the thread
+    saved: u64,
+
+    error: Option<std::io::Error>,
+    waker: Option<std::task::Waker>,
+    closed: bool,
+}
+
+impl SessionSaver {
+    pub(super) fn new(
+        store: Box<dyn SessionStore>,
+        saved: Option<Session>,
+    ) -> std::io::Result<Self> {
+        let shared: std::sync::Arc<SaverShared> = Default::default();
+
+        let thread = std::thread::Builder::new()
+            .name("mqtt3-session-store".to_owned())
+            .spawn({
+                let shared = shared.clone();
+                move || run_saver(store, &shared)
+            })?;
+
+        Ok(SessionSaver {
+            saved,
+            synced: true,
+            queued: 0,
+            sync_required: 0,
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// Hands the session to the thread to be saved
+    ///
+    /// Returns the error of a previous save that failed, if any.
+    pub(super) fn save(&mut self, session: Session, sync: bool) -> std::io::Result<()> {
+        if self.saved.as_ref() == Some(&session) && (self.synced || !sync) {
+            return Ok(());
+        }
+
+        let mut state = self.shared.state.lock().expect("session lock poisoned");
+        if let Some(err) = state.error.take() {
+            return Err(err);
+        }
+
+        // A session that replaces one waiting to be synced must be synced in its place.
+        let sync = sync || state.next.as_ref().map_or(false, |(_, sync, _)| *sync);
+
+        self.queued += 1;
+        if sync {
+            self.sync_required = self.queued;
+        }
+        state.next = Some((session.clone(), sync, self.queued));
+        drop(state);
+        self.shared.changed.notify_all();
+
+        self.saved = Some(session);
+        self.synced = sync;
+        Ok(())
+    }
+
+    /// Resolves once every session that was saved with `sync` set is on stable storage
+    pub(super) fn poll_synced(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        let mut state = self.shared.state.lock().expect("session lock poisoned");
+        if let Some(err) = state.error.take() {
+            return std::task::Poll::Ready(Err(err));
+        }
+
+        if state.saved >= self.sync_required {
+            std::task::Poll::Ready(Ok(()))
+        } else {
+            state.waker = Some(cx.waker().clone());
+            std::task::Poll::Pending
+        }
+    }
+
+    /// Blocks until the thread has saved every session handed to it
+    #[cfg(test)]
+    fn flush(&self) {
+        let mut state = self.shared.state.lock().expect("session lock poisoned");
+        while state.saved < self.queued && state.error.is_none() {
+            state = self
+                .shared
+                .changed
+                .wait(state)
+                .expect("session lock poisoned");
+        }
+    }
+}
+
+impl Drop for SessionSaver {
+    /// Waits for the thread to save the last session, so that a new client created with the same store resumes from it.
+    fn drop(&mut self) {
+        self.shared
+            .state
+            .lock()
+            .expect("session lock poisoned")
+            .closed = true;
+        self.shared.changed.notify_all();
+
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::warn!("session store thread panicked");
+            }
+        }
+
+        if let Some(err) = self
+            .shared
+            .state
+            .lock()
+            .expect("session lock poisoned")
+            .error
+            .take()
+        {
+            log::warn!("could not save session: {}", err);
+        }
+    }
+}
+
+fn run_saver(mut store: Box<dyn SessionStore>, shared: &SaverShared) {
+    let mut state = shared.state.lock().expect("session lock poisoned");
+
+    loop {
+        if let Some((session, sync, sequence)) = state.next.take() {
+            drop(state);
+            let result = store.save(&session, sync);
+            state = shared.state.lock().expect("session lock poisoned");
+
+            match result {
+                Ok(()) => state.saved = sequence,
+                Err(err) => state.error = Some(err),
+            }
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+            shared.changed.notify_all();
+        } else if state.closed {
+            break;
+        } else {
+            state = shared.changed.wait(state).expect("session lock poisoned");
+        }
+    }
+}
+
+/// A [`SessionStore`] that keeps the session in memory.
+///
+/// Clones of a `MemoryStore` share the same session, so a client created with a clone resumes the session of a previous client
+/// within the same process.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore(std::sync::Arc<std::sync::Mutex<Option<Session>>>);
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn load(&mut self) -> std::io::Result<Option<Session>> {
+        let session = self.0.lock().expect("session lock poisoned");
+        Ok(session.clone())
+    }
+
+    fn save(&mut self, session: &Session, _sync: bool) -> std::io::Result<()> {
+        let mut saved = self.0.lock().expect("session lock poisoned");
+        *saved = Some(session.clone());
+        Ok(())
+    }
+}
+
+/// A [`SessionStore`] that keeps the session in a file.
+///
+/// The session is first written to a temporary file next to the target file, flushed to disk, and then renamed over it,
+/// so a crash while saving leaves either the previously saved session or the new one intact. The rename itself is only
+/// flushed to disk when the client asks for the session to be synced.
+///
+/// A file that cannot be decoded, for example one written by an incompatible version, is discarded with a warning
+/// and the client starts a new session.
+#[cfg(feature = "serde1")]
+#[derive(Clone, Debug)]
+pub struct FileStore {
+    path: std::path::PathBuf,
+}
+
+#[cfg(feature = "serde1")]
+impl FileStore {
+    pub fn new(path: impl Into<std::path::PathBuf>) -> Self {
+        FileStore { path: path.into() }
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
+#[cfg(feature = "serde1")]
+impl SessionStore for FileStore {
+    fn load(&mut self) -> std::io::Result<Option<Session>> {
+        let file = match std::fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        match bincode::deserialize_from(std::io::BufReader::new(file)) {
+            Ok(session) => Ok(Some(session)),
+            Err(err) => {
+                log::warn!(
+                    "discarding session saved in {} that could not be decoded: {}",
+                    self.path.display(),
+                    err
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    fn save(&mut self, session: &Session, sync: bool) -> std::io::Result<()> {
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".tmp");
+        let temp_path = std::path::PathBuf::from(temp_path);
+
+        let file = std::fs::File::create(&temp_path)?;
+        let mut writer = std::io::BufWriter::new(file);
+        bincode::serialize_into(&mut writer, session)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        let file = writer.into_inner().map_err(std::io::Error::from)?;
+
+        // Always flush the new session before it replaces the old one, which may have been synced.
+        file.sync_all()?;
+
+        std::fs::rename(&temp_path, &self.path)?;
+
+        if sync {
+            let parent = match self.path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => std::path::Path::new("."),
+            };
+            std::fs::File::open(parent)?.sync_all()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        Session {
+            last_packet_identifier: crate::proto::PacketIdentifier::new(1),
+            publications: vec![crate::proto::Publication {
+                topic_name: "topic1".to_owned(),
+                qos: crate::proto::QoS::AtLeastOnce,
+                retain: false,
+                payload: [0x01, 0x02][..].into(),
+                properties: Default::default(),
+            }],
+            waiting_to_be_acked: vec![crate::proto::Publish {
+                packet_identifier_dup_qos: crate::proto::PacketIdentifierDupQoS::AtLeastOnce(
+                    crate::proto::PacketIdentifier::new(1).unwrap(),
+                    true,
+                ),
+                retain: true,
+                topic_name: "topic2".to_owned(),
+                payload: [0x03][..].into(),
+                properties: Default::default(),
+            }],
+            waiting_to_be_completed: vec![],
+            waiting_to_be_released: vec![],
+            subscriptions: vec![crate::proto::SubscribeTo {
+                topic_filter: "topic3/#".to_owned(),
+                qos: crate::proto::QoS::ExactlyOnce,
+                options: crate::proto::SubscriptionOptions::default(),
+            }],
+            subscription_updates: vec![crate::SubscriptionUpdateEvent::Unsubscribe(
+                "topic4".to_owned(),
+            )],
+        }
+    }
+
+    #[test]
+    fn memory_store_shares_session_between_clones() {
+        let mut store = MemoryStore::new();
+        assert_eq!(store.load().unwrap(), None);
+
+        store.save(&session(), false).unwrap();
+
+        let mut clone = store.clone();
+        assert_eq!(clone.load().unwrap(), Some(session()));
+    }
+
+    #[test]
+    fn session_saver_skips_unchanged_sessions() {
+        #[derive(Debug)]
+        struct RecordingStore(std::sync::Arc<std::sync::Mutex<Vec<bool>>>);
+
+        impl SessionStore for RecordingStore {
+            fn load(&mut self) -> std::io::Result<Option<Session>> {
+                Ok(None)
+            }
+
+            fn save(&mut self, _session: &Session, sync: bool) -> std::io::Result<()> {
+                self.0.lock().unwrap().push(sync);
+                Ok(())
+            }
+        }
+
+        let saves: std::sync::Arc<std::sync::Mutex<Vec<bool>>> = Default::default();
+        let mut saver = SessionSaver::new(Box::new(RecordingStore(saves.clone())), None).unwrap();
+
+        for (session, sync) in &[
+            (session(), false),
+            (session(), false),
+            (session(), true),
+            (session(), true),
+            (session(), false),
+            (Session::default(), false),
+        ] {
+            saver.save(session.clone(), *sync).unwrap();
+            saver.flush();
+        }
+
+        assert_eq!(*saves.lock().unwrap(), vec![false, true, false]);
+    }
+
+    #[test]
+    fn session_saver_resolves_once_synced() {
+        #[derive(Debug)]
+        struct BlockingStore(std::sync::mpsc::Receiver<()>);
+
+        impl SessionStore for BlockingStore {
+            fn load(&mut self) -> std::io::Result<Option<Session>> {
+                Ok(None)
+            }
+
+            fn save(&mut self, _session: &Session, _sync: bool) -> std::io::Result<()> {
+                self.0.recv().unwrap();
+                Ok(())
+            }
+        }
+
+        let (release, blocked) = std::sync::mpsc::channel();
+        let mut saver = SessionSaver::new(Box::new(BlockingStore(blocked)), None).unwrap();
+        let mut cx = std::task::Context::from_waker(futures_util::task::noop_waker_ref());
+
+        saver.save(session(), false).unwrap();
+        assert!(saver.poll_synced(&mut cx).is_ready());
+
+        saver.save(Session::default(), true).unwrap();
+        assert!(saver.poll_synced(&mut cx).is_pending());
+
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+        saver.flush();
+        match saver.poll_synced(&mut cx) {
+            std::task::Poll::Ready(result) => result.unwrap(),
+            std::task::Poll::Pending => panic!("session was not synced"),
+        }
+    }
+
+    #[test]
+    fn session_saver_reports_failed_saves() {
+        #[derive(Debug)]
+        struct FailingStore;
+
+        impl SessionStore for FailingStore {
+            fn load(&mut self) -> std::io::Result<Option<Session>> {
+                Ok(None)
+            }
+
+            fn save(&mut self, _session: &Session, _sync: bool) -> std::io::Result<()> {
+                Err(std::io::Error::new(std::io::ErrorKind::Other, "disk full"))
+            }
+        }
+
+        let mut saver = SessionSaver::new(Box::new(FailingStore), None).unwrap();
+
+        saver.save(session(), true).unwrap();
+        saver.flush();
+
+        let err = saver.save(Session::default(), false).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Other);
+    }
+
+    #[cfg(feature = "serde1")]
+    #[test]
+    fn file_store_saves_and_loads_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = FileStore::new(dir.path().join("session.dat"));
+        assert_eq!(store.load().unwrap(), None);
+
+        store.save(&session(), false).unwrap();
+        store.save(&session(), true).unwrap();
+
+        let mut store = FileStore::new(dir.path().join("session.dat"));
+        assert_eq!(store.load().unwrap(), Some(session()));
+    }
+
+    #[cfg(feature = "serde1")]
+    #[test]
+    fn file_store_discards_corrupted_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.dat");
+        std::fs::write(&path, b"\xff\xff\xff\xff\xff\xff\xff\xff").unwrap();
+
+        let mut store = FileStore::new(&path);
+        assert_eq!(store.load().unwrap(), None);
+
+        store.save(&session(), true).unwrap();
+        assert_eq!(store.load().unwrap(), Some(session()));
+    }
+}
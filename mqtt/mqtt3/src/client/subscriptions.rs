@@ -75,6 +75,7 @@ impl State {
                         crate::proto::SubscribeTo {
                             topic_filter,
                             qos: expected_qos,
+                            options,
                         },
                         qos,
                     ) in subscribe_to.into_iter().zip(qos)
@@ -93,6 +94,7 @@ impl State {
                                             crate::proto::SubscribeTo {
                                                 topic_filter,
                                                 qos: actual_qos,
+                                                options,
                                             },
                                         ),
                                     );
@@ -109,7 +111,7 @@ impl State {
                                 }
                             }
 
-                            crate::proto::SubAckQos::Failure => {
+                            crate::proto::SubAckQos::Failure(_) => {
                                 if err.is_none() {
                                     err = Some(super::Error::SubscriptionRejectedByServer);
                                 }
@@ -146,57 +148,58 @@ impl State {
                 }
             },
 
-            Some(crate::proto::Packet::UnsubAck(crate::proto::UnsubAck { packet_identifier })) => {
-                match self.subscription_updates_waiting_to_be_acked.pop_front() {
-                    Some((
-                        packet_identifier_waiting_to_be_acked,
-                        BatchedSubscriptionUpdate::Unsubscribe(unsubscribe_from),
-                    )) => {
-                        if packet_identifier != packet_identifier_waiting_to_be_acked {
-                            self.subscription_updates_waiting_to_be_acked.push_front((
-                                packet_identifier_waiting_to_be_acked,
-                                BatchedSubscriptionUpdate::Unsubscribe(unsubscribe_from),
-                            ));
-                            return Err(super::Error::UnexpectedUnsubAck(
-                                packet_identifier,
-                                super::UnexpectedSubUnsubAckReason::Expected(
-                                    packet_identifier_waiting_to_be_acked,
-                                ),
-                            ));
-                        }
-
-                        packet_identifiers.discard(packet_identifier);
-
-                        for topic_filter in unsubscribe_from {
-                            log::debug!("Unsubscribed from {}", topic_filter);
-                            self.subscriptions.remove(&topic_filter);
-                            subscription_updates
-                                .push(super::SubscriptionUpdateEvent::Unsubscribe(topic_filter));
-                        }
-                    }
-
-                    Some((
-                        packet_identifier_waiting_to_be_acked,
-                        subscribe @ BatchedSubscriptionUpdate::Subscribe(_),
-                    )) => {
-                        self.subscription_updates_waiting_to_be_acked
-                            .push_front((packet_identifier_waiting_to_be_acked, subscribe));
+            Some(crate::proto::Packet::UnsubAck(crate::proto::UnsubAck {
+                packet_identifier,
+                ..
+            })) => match self.subscription_updates_waiting_to_be_acked.pop_front() {
+                Some((
+                    packet_identifier_waiting_to_be_acked,
+                    BatchedSubscriptionUpdate::Unsubscribe(unsubscribe_from),
+                )) => {
+                    if packet_identifier != packet_identifier_waiting_to_be_acked {
+                        self.subscription_updates_waiting_to_be_acked.push_front((
+                            packet_identifier_waiting_to_be_acked,
+                            BatchedSubscriptionUpdate::Unsubscribe(unsubscribe_from),
+                        ));
                         return Err(super::Error::UnexpectedUnsubAck(
                             packet_identifier,
-                            super::UnexpectedSubUnsubAckReason::ExpectedSubAck(
+                            super::UnexpectedSubUnsubAckReason::Expected(
                                 packet_identifier_waiting_to_be_acked,
                             ),
                         ));
                     }
 
-                    None => {
-                        return Err(super::Error::UnexpectedUnsubAck(
-                            packet_identifier,
-                            super::UnexpectedSubUnsubAckReason::DidNotExpect,
-                        ))
+                    packet_identifiers.discard(packet_identifier);
+
+                    for topic_filter in unsubscribe_from {
+                        log::debug!("Unsubscribed from {}", topic_filter);
+                        self.subscriptions.remove(&topic_filter);
+                        subscription_updates
+                            .push(super::SubscriptionUpdateEvent::Unsubscribe(topic_filter));
                     }
                 }
-            }
+
+                Some((
+                    packet_identifier_waiting_to_be_acked,
+                    subscribe @ BatchedSubscriptionUpdate::Subscribe(_),
+                )) => {
+                    self.subscription_updates_waiting_to_be_acked
+                        .push_front((packet_identifier_waiting_to_be_acked, subscribe));
+                    return Err(super::Error::UnexpectedUnsubAck(
+                        packet_identifier,
+                        super::UnexpectedSubUnsubAckReason::ExpectedSubAck(
+                            packet_identifier_waiting_to_be_acked,
+                        ),
+                    ));
+                }
+
+                None => {
+                    return Err(super::Error::UnexpectedUnsubAck(
+                        packet_identifier,
+                        super::UnexpectedSubUnsubAckReason::DidNotExpect,
+                    ))
+                }
+            },
 
             other => *packet = other,
         }
@@ -271,6 +274,7 @@ impl State {
                     pending_subscriptions.push_back(crate::proto::SubscribeTo {
                         topic_filter: topic_filter.clone().into_owned(),
                         qos,
+                        options: crate::proto::SubscriptionOptions::default(),
                     });
                 }
             }
@@ -294,6 +298,7 @@ impl State {
                         let mut packet = crate::proto::Subscribe {
                             packet_identifier,
                             subscribe_to: vec![],
+                            properties: crate::proto::Properties::default(),
                         };
 
                         while let Some(subscribe_to) = pending_subscriptions.pop_front() {
@@ -411,7 +416,10 @@ impl State {
 
                 match subscription_update_waiting_to_be_acked {
                     BatchedSubscriptionUpdate::Subscribe(subscribe_to) => {
-                        for crate::proto::SubscribeTo { topic_filter, qos } in subscribe_to {
+                        for crate::proto::SubscribeTo {
+                            topic_filter, qos, ..
+                        } in subscribe_to
+                        {
                             subscriptions.insert(topic_filter, qos);
                         }
                     }
@@ -427,7 +435,11 @@ impl State {
             // Generate a SUBSCRIBE packet for the final set of subscriptions
             let mut subscriptions_waiting_to_be_acked: Vec<_> = subscriptions
                 .into_iter()
-                .map(|(topic_filter, qos)| crate::proto::SubscribeTo { topic_filter, qos })
+                .map(|(topic_filter, qos)| crate::proto::SubscribeTo {
+                    topic_filter,
+                    qos,
+                    options: crate::proto::SubscriptionOptions::default(),
+                })
                 .collect();
             subscriptions_waiting_to_be_acked.sort_by(|subscribe_to1, subscribe_to2| {
                 subscribe_to1.topic_filter.cmp(&subscribe_to2.topic_filter)
@@ -448,6 +460,7 @@ impl State {
                     crate::proto::Subscribe {
                         packet_identifier,
                         subscribe_to: subscriptions_waiting_to_be_acked,
+                        properties: crate::proto::Properties::default(),
                     },
                 )))
             }
@@ -462,6 +475,7 @@ impl State {
                             crate::proto::Packet::Subscribe(crate::proto::Subscribe {
                                 packet_identifier: *packet_identifier,
                                 subscribe_to: subscribe_to.clone(),
+                                properties: crate::proto::Properties::default(),
                             })
                         }
 
@@ -479,6 +493,75 @@ impl State {
         }
     }
 
+    pub(super) fn save(&self, session: &mut super::Session) {
+        session.subscriptions = self
+            .subscriptions
+            .iter()
+            .map(|(topic_filter, &qos)| crate::proto::SubscribeTo {
+                topic_filter: topic_filter.clone(),
+                qos,
+                options: crate::proto::SubscriptionOptions::default(),
+            })
+            .collect();
+
+        // Unacked updates are saved in the order they were requested. Their packet identifiers are not saved,
+        // since they are sent again in new packets when the session is restored.
+        let mut subscription_updates = vec![];
+
+        for (_, subscription_update) in &self.subscription_updates_waiting_to_be_acked {
+            match subscription_update {
+                BatchedSubscriptionUpdate::Subscribe(subscribe_to) => subscription_updates.extend(
+                    subscribe_to
+                        .iter()
+                        .cloned()
+                        .map(super::SubscriptionUpdateEvent::Subscribe),
+                ),
+
+                BatchedSubscriptionUpdate::Unsubscribe(unsubscribe_from) => subscription_updates
+                    .extend(
+                        unsubscribe_from
+                            .iter()
+                            .cloned()
+                            .map(super::SubscriptionUpdateEvent::Unsubscribe),
+                    ),
+            }
+        }
+
+        for subscription_update in &self.subscription_updates_waiting_to_be_sent {
+            subscription_updates.push(match subscription_update {
+                SubscriptionUpdate::Subscribe(subscribe_to) => {
+                    super::SubscriptionUpdateEvent::Subscribe(subscribe_to.clone())
+                }
+                SubscriptionUpdate::Unsubscribe(unsubscribe_from) => {
+                    super::SubscriptionUpdateEvent::Unsubscribe(unsubscribe_from.clone())
+                }
+            });
+        }
+
+        session.subscription_updates = subscription_updates;
+    }
+
+    pub(super) fn restore(&mut self, session: &super::Session) {
+        self.subscriptions = session
+            .subscriptions
+            .iter()
+            .map(|subscribe_to| (subscribe_to.topic_filter.clone(), subscribe_to.qos))
+            .collect();
+
+        self.subscription_updates_waiting_to_be_sent = session
+            .subscription_updates
+            .iter()
+            .map(|subscription_update| match subscription_update {
+                super::SubscriptionUpdateEvent::Subscribe(subscribe_to) => {
+                    SubscriptionUpdate::Subscribe(subscribe_to.clone())
+                }
+                super::SubscriptionUpdateEvent::Unsubscribe(unsubscribe_from) => {
+                    SubscriptionUpdate::Unsubscribe(unsubscribe_from.clone())
+                }
+            })
+            .collect();
+    }
+
     pub(super) fn subscribe(
         &mut self,
         subscribe_to: crate::proto::SubscribeTo,
@@ -534,6 +617,7 @@ impl SubscriptionUpdate {
         let mut packet = crate::proto::Subscribe {
             packet_identifier: crate::proto::PacketIdentifier::max_value(),
             subscribe_to: vec![],
+            properties: crate::proto::Properties::default(),
         };
 
         let subscribe_to = match try_append_subscription(&mut packet, subscribe_to) {
@@ -677,7 +761,7 @@ fn try_append_subscription(
     packet.subscribe_to.push(subscribe_to);
     let mut counter = crate::proto::ByteCounter::new();
     match packet
-        .encode(&mut counter)
+        .encode(&mut counter, crate::proto::ProtocolVersion::V311)
         .and_then(|()| crate::proto::encode_remaining_length(counter.0, &mut counter))
     {
         Ok(_) => Ok(()),
@@ -703,7 +787,7 @@ fn try_append_unsubscription(
     packet.unsubscribe_from.push(unsubscribe_from);
     let mut counter = crate::proto::ByteCounter::new();
     match packet
-        .encode(&mut counter)
+        .encode(&mut counter, crate::proto::ProtocolVersion::V311)
         .and_then(|()| crate::proto::encode_remaining_length(counter.0, &mut counter))
     {
         Ok(_) => Ok(()),
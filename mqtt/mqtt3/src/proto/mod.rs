@@ -10,11 +10,14 @@ use serde::{Deserialize, Serialize};
 
 mod packet;
 pub use packet::{
-    ConnAck, Connect, Disconnect, Packet, PacketCodec, PacketIdentifierDupQoS, PingReq, PingResp,
-    PubAck, PubComp, PubRec, PubRel, Publication, Publish, QoS, SubAck, SubAckQos, Subscribe,
-    SubscribeTo, UnsubAck, Unsubscribe,
+    Auth, ConnAck, Connect, Disconnect, Packet, PacketCodec, PacketIdentifierDupQoS, PingReq,
+    PingResp, PubAck, PubComp, PubRec, PubRel, Publication, Publish, QoS, RetainHandling, SubAck,
+    SubAckQos, Subscribe, SubscribeTo, SubscriptionOptions, UnsubAck, Unsubscribe,
 };
 
+mod properties;
+pub use properties::{Properties, Property, ProtocolVersion, ReasonCode};
+
 pub(crate) use packet::PacketMeta;
 
 /// The client ID
@@ -67,6 +70,42 @@ impl From<u8> for ConnectReturnCode {
     }
 }
 
+impl ConnectReturnCode {
+    /// Converts an MQTT 5.0 CONNACK reason code into a return code.
+    ///
+    /// Ref: 3.2.2.2 Connect Reason Code (MQTT 5.0)
+    pub(crate) fn from_v5(code: u8) -> Self {
+        match code {
+            0x00 => ConnectReturnCode::Accepted,
+            0x84 => {
+                ConnectReturnCode::Refused(ConnectionRefusedReason::UnacceptableProtocolVersion)
+            }
+            0x85 => ConnectReturnCode::Refused(ConnectionRefusedReason::IdentifierRejected),
+            0x86 => ConnectReturnCode::Refused(ConnectionRefusedReason::BadUserNameOrPassword),
+            0x87 => ConnectReturnCode::Refused(ConnectionRefusedReason::NotAuthorized),
+            0x88 => ConnectReturnCode::Refused(ConnectionRefusedReason::ServerUnavailable),
+            code => ConnectReturnCode::Refused(ConnectionRefusedReason::Other(code)),
+        }
+    }
+
+    /// Converts this return code into an MQTT 5.0 CONNACK reason code.
+    ///
+    /// Ref: 3.2.2.2 Connect Reason Code (MQTT 5.0)
+    pub(crate) fn into_v5(self) -> u8 {
+        match self {
+            ConnectReturnCode::Accepted => 0x00,
+            ConnectReturnCode::Refused(ConnectionRefusedReason::UnacceptableProtocolVersion) => {
+                0x84
+            }
+            ConnectReturnCode::Refused(ConnectionRefusedReason::IdentifierRejected) => 0x85,
+            ConnectReturnCode::Refused(ConnectionRefusedReason::BadUserNameOrPassword) => 0x86,
+            ConnectReturnCode::Refused(ConnectionRefusedReason::NotAuthorized) => 0x87,
+            ConnectReturnCode::Refused(ConnectionRefusedReason::ServerUnavailable) => 0x88,
+            ConnectReturnCode::Refused(ConnectionRefusedReason::Other(code)) => code,
+        }
+    }
+}
+
 impl From<ConnectReturnCode> for u8 {
     fn from(code: ConnectReturnCode) -> Self {
         match code {
@@ -286,6 +325,7 @@ pub enum DecodeError {
     NoTopics,
     RemainingLengthTooHigh,
     StringNotUtf8(std::str::Utf8Error),
+    SubscriptionOptionsReservedSet(u8),
     UnrecognizedConnAckFlags(u8),
     UnrecognizedPacket {
         packet_type: u8,
@@ -294,7 +334,9 @@ pub enum DecodeError {
     },
     UnrecognizedProtocolLevel(u8),
     UnrecognizedProtocolName(String),
+    UnrecognizedProperty(u8),
     UnrecognizedQoS(u8),
+    UnrecognizedRetainHandling(u8),
     ZeroPacketIdentifier,
 }
 
@@ -318,6 +360,11 @@ impl std::fmt::Display for DecodeError {
                 write!(f, "remaining length is too high to be decoded")
             }
             DecodeError::StringNotUtf8(err) => err.fmt(f),
+            DecodeError::SubscriptionOptionsReservedSet(options) => write!(
+                f,
+                "the reserved bits of the subscription options 0x{:02X} are set",
+                options
+            ),
             DecodeError::UnrecognizedConnAckFlags(flags) => {
                 write!(f, "could not parse CONNACK flags 0x{:02X}", flags)
             }
@@ -338,7 +385,15 @@ impl std::fmt::Display for DecodeError {
             DecodeError::UnrecognizedProtocolName(name) => {
                 write!(f, "unexpected protocol name {:?}", name)
             }
+            DecodeError::UnrecognizedProperty(identifier) => {
+                write!(f, "could not identify property 0x{:02X}", identifier)
+            }
             DecodeError::UnrecognizedQoS(qos) => write!(f, "could not parse QoS 0x{:02X}", qos),
+            DecodeError::UnrecognizedRetainHandling(retain_handling) => write!(
+                f,
+                "could not parse retain handling 0x{:02X}",
+                retain_handling
+            ),
             DecodeError::ZeroPacketIdentifier => write!(f, "packet identifier is 0"),
         }
     }
@@ -356,11 +411,14 @@ impl std::error::Error for DecodeError {
             DecodeError::PublishDupAtMostOnce => None,
             DecodeError::RemainingLengthTooHigh => None,
             DecodeError::StringNotUtf8(err) => Some(err),
+            DecodeError::SubscriptionOptionsReservedSet(_) => None,
             DecodeError::UnrecognizedConnAckFlags(_) => None,
             DecodeError::UnrecognizedPacket { .. } => None,
             DecodeError::UnrecognizedProtocolLevel(_) => None,
             DecodeError::UnrecognizedProtocolName(_) => None,
+            DecodeError::UnrecognizedProperty(_) => None,
             DecodeError::UnrecognizedQoS(_) => None,
+            DecodeError::UnrecognizedRetainHandling(_) => None,
             DecodeError::ZeroPacketIdentifier => None,
         }
     }
@@ -374,6 +432,7 @@ impl From<std::io::Error> for DecodeError {
 
 #[derive(Debug)]
 pub enum EncodeError {
+    BinaryTooLarge(usize),
     Io(std::io::Error),
     KeepAliveTooHigh(std::time::Duration),
     RemainingLengthTooHigh(usize),
@@ -385,6 +444,7 @@ impl EncodeError {
     pub fn is_user_error(&self) -> bool {
         #[allow(clippy::match_same_arms)]
         match self {
+            EncodeError::BinaryTooLarge(_) => true,
             EncodeError::Io(_) => false,
             EncodeError::KeepAliveTooHigh(_) => true,
             EncodeError::RemainingLengthTooHigh(_) => true,
@@ -397,6 +457,11 @@ impl EncodeError {
 impl std::fmt::Display for EncodeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            EncodeError::BinaryTooLarge(len) => write!(
+                f,
+                "binary data of length {} is too large to be encoded",
+                len
+            ),
             EncodeError::Io(err) => write!(f, "I/O error: {}", err),
             EncodeError::KeepAliveTooHigh(keep_alive) => {
                 write!(f, "keep-alive {:?} is too high", keep_alive)
@@ -420,6 +485,7 @@ impl std::error::Error for EncodeError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         #[allow(clippy::match_same_arms)]
         match self {
+            EncodeError::BinaryTooLarge(_) => None,
             EncodeError::Io(err) => Some(err),
             EncodeError::KeepAliveTooHigh(_) => None,
             EncodeError::RemainingLengthTooHigh(_) => None,
@@ -442,6 +508,8 @@ pub(crate) trait ByteBuf {
 
     fn put_u16_bytes(&mut self, n: u16);
 
+    fn put_u32_bytes(&mut self, n: u32);
+
     fn put_packet_identifier_bytes(&mut self, packet_identifier: PacketIdentifier) {
         self.put_u16_bytes(packet_identifier.0);
     }
@@ -462,6 +530,10 @@ impl ByteBuf for bytes::BytesMut {
         self.put_u16(n);
     }
 
+    fn put_u32_bytes(&mut self, n: u32) {
+        self.put_u32(n);
+    }
+
     fn put_slice_bytes(&mut self, src: &[u8]) {
         self.put_slice(src);
     }
@@ -486,6 +558,10 @@ impl ByteBuf for ByteCounter {
         self.0 += std::mem::size_of::<u16>();
     }
 
+    fn put_u32_bytes(&mut self, _: u32) {
+        self.0 += std::mem::size_of::<u32>();
+    }
+
     fn put_slice_bytes(&mut self, src: &[u8]) {
         self.0 += src.len();
     }
@@ -496,6 +572,7 @@ trait BufMutExt {
 
     fn try_get_u8(&mut self) -> Result<u8, DecodeError>;
     fn try_get_u16_be(&mut self) -> Result<u16, DecodeError>;
+    fn try_get_u32_be(&mut self) -> Result<u32, DecodeError>;
     fn try_get_packet_identifier(&mut self) -> Result<PacketIdentifier, DecodeError>;
 }
 
@@ -521,6 +598,14 @@ impl BufMutExt for bytes::BytesMut {
         Ok(self.get_u16())
     }
 
+    fn try_get_u32_be(&mut self) -> Result<u32, DecodeError> {
+        if self.len() < std::mem::size_of::<u32>() {
+            return Err(DecodeError::IncompletePacket);
+        }
+
+        Ok(self.get_u32())
+    }
+
     fn try_get_packet_identifier(&mut self) -> Result<PacketIdentifier, DecodeError> {
         if self.len() < std::mem::size_of::<u16>() {
             return Err(DecodeError::IncompletePacket);
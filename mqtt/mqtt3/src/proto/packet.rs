@@ -5,11 +5,14 @@ use bytes::{Buf, BufMut};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use tokio_util::codec::Decoder;
 
-use super::{BufMutExt, ByteBuf};
+use super::{BufMutExt, ByteBuf, Properties, ProtocolVersion, ReasonCode};
 
 /// An MQTT packet
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Packet {
+    /// Ref: 3.15 AUTH – Authentication exchange (MQTT 5.0)
+    Auth(Auth),
+
     /// Ref: 3.2 CONNACK – Acknowledge connection request
     ConnAck(ConnAck),
 
@@ -58,29 +61,86 @@ pub(crate) trait PacketMeta: Sized {
     /// The packet type for this kind of packet
     const PACKET_TYPE: u8;
 
-    /// Decodes this packet from the given buffer
-    fn decode(flags: u8, src: bytes::BytesMut) -> Result<Self, super::DecodeError>;
+    /// Decodes this packet from the given buffer, according to the protocol version of the connection
+    fn decode(
+        flags: u8,
+        src: bytes::BytesMut,
+        version: ProtocolVersion,
+    ) -> Result<Self, super::DecodeError>;
 
     /// Encodes the variable header and payload corresponding to this packet into the given buffer.
     /// The buffer is expected to already have the packet type and body length encoded into it,
     /// and to have reserved enough space to put the bytes of this packet directly into the buffer.
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, version: ProtocolVersion) -> Result<(), super::EncodeError>
     where
         B: ByteBuf;
 }
 
+/// Ref: 3.15 AUTH – Authentication exchange (MQTT 5.0)
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Auth {
+    pub reason_code: ReasonCode,
+    pub properties: Properties,
+}
+
+impl PacketMeta for Auth {
+    const PACKET_TYPE: u8 = 0xF0;
+
+    fn decode(
+        flags: u8,
+        mut src: bytes::BytesMut,
+        version: ProtocolVersion,
+    ) -> Result<Self, super::DecodeError> {
+        if flags != 0 || version == ProtocolVersion::V311 {
+            return Err(super::DecodeError::UnrecognizedPacket {
+                packet_type: Self::PACKET_TYPE,
+                flags,
+                remaining_length: src.len(),
+            });
+        }
+
+        let (reason_code, properties) = decode_reason_code_and_properties(&mut src)?;
+
+        Ok(Auth {
+            reason_code,
+            properties,
+        })
+    }
+
+    fn encode<B>(&self, dst: &mut B, _: ProtocolVersion) -> Result<(), super::EncodeError>
+    where
+        B: ByteBuf,
+    {
+        let Auth {
+            reason_code,
+            properties,
+        } = self;
+        encode_reason_code_and_properties(*reason_code, properties, dst)
+    }
+}
+
 /// Ref: 3.2 CONNACK – Acknowledge connection request
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ConnAck {
     pub session_present: bool,
     pub return_code: super::ConnectReturnCode,
+    pub properties: Properties,
 }
 
 impl PacketMeta for ConnAck {
     const PACKET_TYPE: u8 = 0x20;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
-        if flags != 0 || src.len() != (std::mem::size_of::<u8>() + std::mem::size_of::<u8>()) {
+    fn decode(
+        flags: u8,
+        mut src: bytes::BytesMut,
+        version: ProtocolVersion,
+    ) -> Result<Self, super::DecodeError> {
+        let header_len = std::mem::size_of::<u8>() + std::mem::size_of::<u8>();
+        let has_valid_len = match version {
+            ProtocolVersion::V311 => src.len() == header_len,
+            ProtocolVersion::V5 => src.len() >= header_len,
+        };
+        if flags != 0 || !has_valid_len {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
                 flags,
@@ -97,21 +157,34 @@ impl PacketMeta for ConnAck {
             }
         };
 
-        let return_code: super::ConnectReturnCode = src.get_u8().into();
+        let (return_code, properties) = match version {
+            ProtocolVersion::V311 => (src.get_u8().into(), Properties::default()),
+            ProtocolVersion::V5 => {
+                let return_code = super::ConnectReturnCode::from_v5(src.get_u8());
+                let properties = if src.is_empty() {
+                    Properties::default()
+                } else {
+                    Properties::decode(&mut src)?
+                };
+                (return_code, properties)
+            }
+        };
 
         Ok(ConnAck {
             session_present,
             return_code,
+            properties,
         })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, version: ProtocolVersion) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
         let ConnAck {
             session_present,
             return_code,
+            properties,
         } = self;
         if *session_present {
             dst.put_u8_bytes(0x01);
@@ -119,7 +192,13 @@ impl PacketMeta for ConnAck {
             dst.put_u8_bytes(0x00);
         }
 
-        dst.put_u8_bytes((*return_code).into());
+        match version {
+            ProtocolVersion::V311 => dst.put_u8_bytes((*return_code).into()),
+            ProtocolVersion::V5 => {
+                dst.put_u8_bytes(return_code.into_v5());
+                properties.encode(dst)?;
+            }
+        }
 
         Ok(())
     }
@@ -135,6 +214,7 @@ pub struct Connect {
     pub keep_alive: Duration,
     pub protocol_name: String,
     pub protocol_level: u8,
+    pub properties: Properties,
 }
 
 impl std::fmt::Debug for Connect {
@@ -146,6 +226,7 @@ impl std::fmt::Debug for Connect {
             .field("keep_alive", &self.keep_alive)
             .field("protocol_name", &self.protocol_name)
             .field("protocol_level", &self.protocol_level)
+            .field("properties", &self.properties)
             .finish()
     }
 }
@@ -153,7 +234,11 @@ impl std::fmt::Debug for Connect {
 impl PacketMeta for Connect {
     const PACKET_TYPE: u8 = 0x10;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
+    fn decode(
+        flags: u8,
+        mut src: bytes::BytesMut,
+        _: ProtocolVersion,
+    ) -> Result<Self, super::DecodeError> {
         if flags != 0 {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
@@ -169,12 +254,12 @@ impl PacketMeta for Connect {
             return Err(super::DecodeError::UnrecognizedProtocolName(protocol_name));
         }
 
+        // The protocol level of the CONNECT packet determines the version of the connection,
+        // so it is used instead of the version the codec was created with.
         let protocol_level = src.try_get_u8()?;
-        if protocol_level != crate::PROTOCOL_LEVEL {
-            return Err(super::DecodeError::UnrecognizedProtocolLevel(
-                protocol_level,
-            ));
-        }
+        let version = ProtocolVersion::from_level(protocol_level).ok_or(
+            super::DecodeError::UnrecognizedProtocolLevel(protocol_level),
+        )?;
 
         let connect_flags = src.try_get_u8()?;
         if connect_flags & 0x01 != 0 {
@@ -183,6 +268,11 @@ impl PacketMeta for Connect {
 
         let keep_alive = Duration::from_secs(u64::from(src.try_get_u16_be()?));
 
+        let properties = match version {
+            ProtocolVersion::V311 => Properties::default(),
+            ProtocolVersion::V5 => Properties::decode(&mut src)?,
+        };
+
         let client_id = super::Utf8StringDecoder::default()
             .decode(&mut src)?
             .ok_or(super::DecodeError::IncompletePacket)?;
@@ -200,6 +290,11 @@ impl PacketMeta for Connect {
         let will = if connect_flags & 0x04 == 0 {
             None
         } else {
+            let properties = match version {
+                ProtocolVersion::V311 => Properties::default(),
+                ProtocolVersion::V5 => Properties::decode(&mut src)?,
+            };
+
             let topic_name = super::Utf8StringDecoder::default()
                 .decode(&mut src)?
                 .ok_or(super::DecodeError::IncompletePacket)?;
@@ -224,6 +319,7 @@ impl PacketMeta for Connect {
                 qos,
                 retain,
                 payload,
+                properties,
             })
         };
 
@@ -255,10 +351,11 @@ impl PacketMeta for Connect {
             keep_alive,
             protocol_name,
             protocol_level,
+            properties,
         })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, _: ProtocolVersion) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
@@ -270,8 +367,11 @@ impl PacketMeta for Connect {
             keep_alive,
             protocol_name,
             protocol_level,
+            properties,
         } = self;
 
+        let version = ProtocolVersion::from_level(*protocol_level).unwrap_or_default();
+
         super::encode_utf8_str(protocol_name, dst)?;
 
         dst.put_u8_bytes(*protocol_level);
@@ -311,6 +411,10 @@ impl PacketMeta for Connect {
                 .map_err(|_| super::EncodeError::KeepAliveTooHigh(*keep_alive))?,
         );
 
+        if version == ProtocolVersion::V5 {
+            properties.encode(dst)?;
+        }
+
         match client_id {
             super::ClientId::ServerGenerated => super::encode_utf8_str("", dst)?,
             super::ClientId::IdWithCleanSession(id)
@@ -318,6 +422,10 @@ impl PacketMeta for Connect {
         }
 
         if let Some(will) = will {
+            if version == ProtocolVersion::V5 {
+                will.properties.encode(dst)?;
+            }
+
             super::encode_utf8_str(&will.topic_name, dst)?;
 
             let will_len = will.payload.len();
@@ -343,14 +451,23 @@ impl PacketMeta for Connect {
 }
 
 /// Ref: 3.14 DISCONNECT - Disconnect notification
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Disconnect;
+///
+/// The reason code and properties are only sent on MQTT 5.0 connections.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Disconnect {
+    pub reason_code: ReasonCode,
+    pub properties: Properties,
+}
 
 impl PacketMeta for Disconnect {
     const PACKET_TYPE: u8 = 0xE0;
 
-    fn decode(flags: u8, src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
-        if flags != 0 || !src.is_empty() {
+    fn decode(
+        flags: u8,
+        mut src: bytes::BytesMut,
+        version: ProtocolVersion,
+    ) -> Result<Self, super::DecodeError> {
+        if flags != 0 || (version == ProtocolVersion::V311 && !src.is_empty()) {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
                 flags,
@@ -358,14 +475,26 @@ impl PacketMeta for Disconnect {
             });
         }
 
-        Ok(Disconnect)
+        let (reason_code, properties) = decode_reason_code_and_properties(&mut src)?;
+
+        Ok(Disconnect {
+            reason_code,
+            properties,
+        })
     }
 
-    fn encode<B>(&self, _: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, version: ProtocolVersion) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
-        Ok(())
+        let Disconnect {
+            reason_code,
+            properties,
+        } = self;
+        match version {
+            ProtocolVersion::V311 => Ok(()),
+            ProtocolVersion::V5 => encode_reason_code_and_properties(*reason_code, properties, dst),
+        }
     }
 }
 
@@ -376,7 +505,11 @@ pub struct PingReq;
 impl PacketMeta for PingReq {
     const PACKET_TYPE: u8 = 0xC0;
 
-    fn decode(flags: u8, src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
+    fn decode(
+        flags: u8,
+        src: bytes::BytesMut,
+        _: ProtocolVersion,
+    ) -> Result<Self, super::DecodeError> {
         if flags != 0 || !src.is_empty() {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
@@ -388,7 +521,7 @@ impl PacketMeta for PingReq {
         Ok(PingReq)
     }
 
-    fn encode<B>(&self, _: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, _: &mut B, _: ProtocolVersion) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
@@ -403,7 +536,11 @@ pub struct PingResp;
 impl PacketMeta for PingResp {
     const PACKET_TYPE: u8 = 0xD0;
 
-    fn decode(flags: u8, src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
+    fn decode(
+        flags: u8,
+        src: bytes::BytesMut,
+        _: ProtocolVersion,
+    ) -> Result<Self, super::DecodeError> {
         if flags != 0 || !src.is_empty() {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
@@ -415,7 +552,7 @@ impl PacketMeta for PingResp {
         Ok(PingResp)
     }
 
-    fn encode<B>(&self, _: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, _: &mut B, _: ProtocolVersion) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
@@ -427,32 +564,38 @@ impl PacketMeta for PingResp {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct PubAck {
     pub packet_identifier: super::PacketIdentifier,
+    pub reason_code: ReasonCode,
+    pub properties: Properties,
 }
 
 impl PacketMeta for PubAck {
     const PACKET_TYPE: u8 = 0x40;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
-        if flags != 0 || src.len() != std::mem::size_of::<u16>() {
-            return Err(super::DecodeError::UnrecognizedPacket {
-                packet_type: Self::PACKET_TYPE,
-                flags,
-                remaining_length: src.len(),
-            });
-        }
-
-        let packet_identifier = src.get_packet_identifier()?;
+    fn decode(
+        flags: u8,
+        src: bytes::BytesMut,
+        version: ProtocolVersion,
+    ) -> Result<Self, super::DecodeError> {
+        let (packet_identifier, reason_code, properties) =
+            decode_ack(Self::PACKET_TYPE, 0, flags, src, version)?;
 
-        Ok(PubAck { packet_identifier })
+        Ok(PubAck {
+            packet_identifier,
+            reason_code,
+            properties,
+        })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, version: ProtocolVersion) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
-        let PubAck { packet_identifier } = self;
-        dst.put_packet_identifier_bytes(*packet_identifier);
-        Ok(())
+        let PubAck {
+            packet_identifier,
+            reason_code,
+            properties,
+        } = self;
+        encode_ack(*packet_identifier, *reason_code, properties, dst, version)
     }
 }
 
@@ -461,32 +604,38 @@ impl PacketMeta for PubAck {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct PubComp {
     pub packet_identifier: super::PacketIdentifier,
+    pub reason_code: ReasonCode,
+    pub properties: Properties,
 }
 
 impl PacketMeta for PubComp {
     const PACKET_TYPE: u8 = 0x70;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
-        if flags != 0 || src.len() != std::mem::size_of::<u16>() {
-            return Err(super::DecodeError::UnrecognizedPacket {
-                packet_type: Self::PACKET_TYPE,
-                flags,
-                remaining_length: src.len(),
-            });
-        }
-
-        let packet_identifier = src.get_packet_identifier()?;
+    fn decode(
+        flags: u8,
+        src: bytes::BytesMut,
+        version: ProtocolVersion,
+    ) -> Result<Self, super::DecodeError> {
+        let (packet_identifier, reason_code, properties) =
+            decode_ack(Self::PACKET_TYPE, 0, flags, src, version)?;
 
-        Ok(PubComp { packet_identifier })
+        Ok(PubComp {
+            packet_identifier,
+            reason_code,
+            properties,
+        })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, version: ProtocolVersion) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
-        let PubComp { packet_identifier } = self;
-        dst.put_packet_identifier_bytes(*packet_identifier);
-        Ok(())
+        let PubComp {
+            packet_identifier,
+            reason_code,
+            properties,
+        } = self;
+        encode_ack(*packet_identifier, *reason_code, properties, dst, version)
     }
 }
 
@@ -500,12 +649,17 @@ pub struct Publish {
     #[cfg_attr(feature = "serde1", serde(serialize_with = "serialize_bytes"))]
     #[cfg_attr(feature = "serde1", serde(deserialize_with = "deserialize_bytes"))]
     pub payload: bytes::Bytes,
+    pub properties: Properties,
 }
 
 impl PacketMeta for Publish {
     const PACKET_TYPE: u8 = 0x30;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
+    fn decode(
+        flags: u8,
+        mut src: bytes::BytesMut,
+        version: ProtocolVersion,
+    ) -> Result<Self, super::DecodeError> {
         let dup = (flags & 0x08) != 0;
         let retain = (flags & 0x01) != 0;
 
@@ -531,6 +685,11 @@ impl PacketMeta for Publish {
             qos => return Err(super::DecodeError::UnrecognizedQoS(qos)),
         };
 
+        let properties = match version {
+            ProtocolVersion::V311 => Properties::default(),
+            ProtocolVersion::V5 => Properties::decode(&mut src)?,
+        };
+
         let payload = src.freeze();
 
         Ok(Publish {
@@ -538,10 +697,11 @@ impl PacketMeta for Publish {
             retain,
             topic_name,
             payload,
+            properties,
         })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, version: ProtocolVersion) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
@@ -551,6 +711,7 @@ impl PacketMeta for Publish {
             retain: _,
             topic_name,
             payload,
+            properties,
         } = self;
 
         super::encode_utf8_str(topic_name, dst)?;
@@ -563,6 +724,10 @@ impl PacketMeta for Publish {
             }
         }
 
+        if version == ProtocolVersion::V5 {
+            properties.encode(dst)?;
+        }
+
         dst.put_slice_bytes(&payload);
 
         Ok(())
@@ -574,32 +739,38 @@ impl PacketMeta for Publish {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct PubRec {
     pub packet_identifier: super::PacketIdentifier,
+    pub reason_code: ReasonCode,
+    pub properties: Properties,
 }
 
 impl PacketMeta for PubRec {
     const PACKET_TYPE: u8 = 0x50;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
-        if flags != 0 || src.len() != std::mem::size_of::<u16>() {
-            return Err(super::DecodeError::UnrecognizedPacket {
-                packet_type: Self::PACKET_TYPE,
-                flags,
-                remaining_length: src.len(),
-            });
-        }
-
-        let packet_identifier = src.get_packet_identifier()?;
+    fn decode(
+        flags: u8,
+        src: bytes::BytesMut,
+        version: ProtocolVersion,
+    ) -> Result<Self, super::DecodeError> {
+        let (packet_identifier, reason_code, properties) =
+            decode_ack(Self::PACKET_TYPE, 0, flags, src, version)?;
 
-        Ok(PubRec { packet_identifier })
+        Ok(PubRec {
+            packet_identifier,
+            reason_code,
+            properties,
+        })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, version: ProtocolVersion) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
-        let PubRec { packet_identifier } = self;
-        dst.put_packet_identifier_bytes(*packet_identifier);
-        Ok(())
+        let PubRec {
+            packet_identifier,
+            reason_code,
+            properties,
+        } = self;
+        encode_ack(*packet_identifier, *reason_code, properties, dst, version)
     }
 }
 
@@ -608,32 +779,38 @@ impl PacketMeta for PubRec {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct PubRel {
     pub packet_identifier: super::PacketIdentifier,
+    pub reason_code: ReasonCode,
+    pub properties: Properties,
 }
 
 impl PacketMeta for PubRel {
     const PACKET_TYPE: u8 = 0x60;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
-        if flags != 2 || src.len() != std::mem::size_of::<u16>() {
-            return Err(super::DecodeError::UnrecognizedPacket {
-                packet_type: Self::PACKET_TYPE,
-                flags,
-                remaining_length: src.len(),
-            });
-        }
-
-        let packet_identifier = src.get_packet_identifier()?;
+    fn decode(
+        flags: u8,
+        src: bytes::BytesMut,
+        version: ProtocolVersion,
+    ) -> Result<Self, super::DecodeError> {
+        let (packet_identifier, reason_code, properties) =
+            decode_ack(Self::PACKET_TYPE, 2, flags, src, version)?;
 
-        Ok(PubRel { packet_identifier })
+        Ok(PubRel {
+            packet_identifier,
+            reason_code,
+            properties,
+        })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, version: ProtocolVersion) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
-        let PubRel { packet_identifier } = self;
-        dst.put_packet_identifier_bytes(*packet_identifier);
-        Ok(())
+        let PubRel {
+            packet_identifier,
+            reason_code,
+            properties,
+        } = self;
+        encode_ack(*packet_identifier, *reason_code, properties, dst, version)
     }
 }
 
@@ -647,7 +824,11 @@ pub struct SubAck {
 impl PacketMeta for SubAck {
     const PACKET_TYPE: u8 = 0x90;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
+    fn decode(
+        flags: u8,
+        mut src: bytes::BytesMut,
+        version: ProtocolVersion,
+    ) -> Result<Self, super::DecodeError> {
         if flags != 0 || src.len() < std::mem::size_of::<u16>() {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
@@ -658,14 +839,19 @@ impl PacketMeta for SubAck {
 
         let packet_identifier = src.get_packet_identifier()?;
 
+        if version == ProtocolVersion::V5 {
+            let _ = Properties::decode(&mut src)?;
+        }
+
         let qos: Result<Vec<_>, _> = src
             .iter()
-            .map(|&qos| match qos {
-                0x00 => Ok(SubAckQos::Success(QoS::AtMostOnce)),
-                0x01 => Ok(SubAckQos::Success(QoS::AtLeastOnce)),
-                0x02 => Ok(SubAckQos::Success(QoS::ExactlyOnce)),
-                0x80 => Ok(SubAckQos::Failure),
-                qos => Err(super::DecodeError::UnrecognizedQoS(qos)),
+            .map(|&qos| match (qos, version) {
+                (0x00, _) => Ok(SubAckQos::Success(QoS::AtMostOnce)),
+                (0x01, _) => Ok(SubAckQos::Success(QoS::AtLeastOnce)),
+                (0x02, _) => Ok(SubAckQos::Success(QoS::ExactlyOnce)),
+                (0x80, _) => Ok(SubAckQos::Failure(ReasonCode::UNSPECIFIED_ERROR)),
+                (qos, ProtocolVersion::V5) if qos > 0x80 => Ok(SubAckQos::Failure(ReasonCode(qos))),
+                (qos, _) => Err(super::DecodeError::UnrecognizedQoS(qos)),
             })
             .collect();
         let qos = qos?;
@@ -680,7 +866,7 @@ impl PacketMeta for SubAck {
         })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, version: ProtocolVersion) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
@@ -691,8 +877,16 @@ impl PacketMeta for SubAck {
 
         dst.put_packet_identifier_bytes(*packet_identifier);
 
+        if version == ProtocolVersion::V5 {
+            Properties::default().encode(dst)?;
+        }
+
         for &qos in qos {
-            dst.put_u8_bytes(qos.into());
+            let qos = match (qos, version) {
+                (SubAckQos::Failure(_), ProtocolVersion::V311) => 0x80,
+                (qos, _) => qos.into(),
+            };
+            dst.put_u8_bytes(qos);
         }
 
         Ok(())
@@ -700,16 +894,23 @@ impl PacketMeta for SubAck {
 }
 
 /// Ref: 3.8 SUBSCRIBE - Subscribe to topics
+///
+/// The properties are only sent on MQTT 5.0 connections.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Subscribe {
     pub packet_identifier: super::PacketIdentifier,
     pub subscribe_to: Vec<SubscribeTo>,
+    pub properties: Properties,
 }
 
 impl PacketMeta for Subscribe {
     const PACKET_TYPE: u8 = 0x80;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
+    fn decode(
+        flags: u8,
+        mut src: bytes::BytesMut,
+        version: ProtocolVersion,
+    ) -> Result<Self, super::DecodeError> {
         if flags != 2 || src.len() < std::mem::size_of::<u16>() {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
@@ -720,19 +921,57 @@ impl PacketMeta for Subscribe {
 
         let packet_identifier = src.get_packet_identifier()?;
 
+        let properties = match version {
+            ProtocolVersion::V311 => Properties::default(),
+            ProtocolVersion::V5 => Properties::decode(&mut src)?,
+        };
+
         let mut subscribe_to = vec![];
 
         while !src.is_empty() {
             let topic_filter = super::Utf8StringDecoder::default()
                 .decode(&mut src)?
                 .ok_or(super::DecodeError::IncompletePacket)?;
-            let qos = match src.try_get_u8()? {
+
+            // MQTT 5.0 extends the requested QoS to a subscription options byte.
+            let options_byte = src.try_get_u8()?;
+            let (qos, options) = match version {
+                ProtocolVersion::V311 => (options_byte, SubscriptionOptions::default()),
+                ProtocolVersion::V5 if options_byte & 0xC0 != 0 => {
+                    return Err(super::DecodeError::SubscriptionOptionsReservedSet(
+                        options_byte,
+                    ))
+                }
+                ProtocolVersion::V5 => {
+                    let retain_handling = match (options_byte >> 4) & 0x03 {
+                        0x00 => RetainHandling::SendAtSubscribe,
+                        0x01 => RetainHandling::SendAtNewSubscribe,
+                        0x02 => RetainHandling::DoNotSend,
+                        retain_handling => {
+                            return Err(super::DecodeError::UnrecognizedRetainHandling(
+                                retain_handling,
+                            ))
+                        }
+                    };
+                    let options = SubscriptionOptions {
+                        no_local: options_byte & 0x04 != 0,
+                        retain_as_published: options_byte & 0x08 != 0,
+                        retain_handling,
+                    };
+                    (options_byte & 0x03, options)
+                }
+            };
+            let qos = match qos {
                 0x00 => QoS::AtMostOnce,
                 0x01 => QoS::AtLeastOnce,
                 0x02 => QoS::ExactlyOnce,
                 qos => return Err(super::DecodeError::UnrecognizedQoS(qos)),
             };
-            subscribe_to.push(SubscribeTo { topic_filter, qos });
+            subscribe_to.push(SubscribeTo {
+                topic_filter,
+                qos,
+                options,
+            });
         }
 
         if subscribe_to.is_empty() {
@@ -742,23 +981,38 @@ impl PacketMeta for Subscribe {
         Ok(Subscribe {
             packet_identifier,
             subscribe_to,
+            properties,
         })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, version: ProtocolVersion) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
         let Subscribe {
             packet_identifier,
             subscribe_to,
+            properties,
         } = self;
 
         dst.put_packet_identifier_bytes(*packet_identifier);
 
-        for SubscribeTo { topic_filter, qos } in subscribe_to {
+        if version == ProtocolVersion::V5 {
+            properties.encode(dst)?;
+        }
+
+        for SubscribeTo {
+            topic_filter,
+            qos,
+            options,
+        } in subscribe_to
+        {
             super::encode_utf8_str(topic_filter, dst)?;
-            dst.put_u8_bytes((*qos).into());
+            let options = match version {
+                ProtocolVersion::V311 => u8::from(*qos),
+                ProtocolVersion::V5 => u8::from(*qos) | u8::from(*options),
+            };
+            dst.put_u8_bytes(options);
         }
 
         Ok(())
@@ -766,16 +1020,27 @@ impl PacketMeta for Subscribe {
 }
 
 /// Ref: 3.11 UNSUBACK – Unsubscribe acknowledgement
+///
+/// The reason codes, one per unsubscribed topic filter, are only sent on MQTT 5.0 connections.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct UnsubAck {
     pub packet_identifier: super::PacketIdentifier,
+    pub reason_codes: Vec<ReasonCode>,
 }
 
 impl PacketMeta for UnsubAck {
     const PACKET_TYPE: u8 = 0xB0;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
-        if flags != 0 || src.len() != std::mem::size_of::<u16>() {
+    fn decode(
+        flags: u8,
+        mut src: bytes::BytesMut,
+        version: ProtocolVersion,
+    ) -> Result<Self, super::DecodeError> {
+        let has_valid_len = match version {
+            ProtocolVersion::V311 => src.len() == std::mem::size_of::<u16>(),
+            ProtocolVersion::V5 => src.len() > std::mem::size_of::<u16>(),
+        };
+        if flags != 0 || !has_valid_len {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
                 flags,
@@ -785,15 +1050,41 @@ impl PacketMeta for UnsubAck {
 
         let packet_identifier = src.get_packet_identifier()?;
 
-        Ok(UnsubAck { packet_identifier })
+        let reason_codes = match version {
+            ProtocolVersion::V311 => vec![],
+            ProtocolVersion::V5 => {
+                let _ = Properties::decode(&mut src)?;
+                src.iter()
+                    .map(|&reason_code| ReasonCode(reason_code))
+                    .collect()
+            }
+        };
+
+        Ok(UnsubAck {
+            packet_identifier,
+            reason_codes,
+        })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, version: ProtocolVersion) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
-        let UnsubAck { packet_identifier } = self;
+        let UnsubAck {
+            packet_identifier,
+            reason_codes,
+        } = self;
+
         dst.put_packet_identifier_bytes(*packet_identifier);
+
+        if version == ProtocolVersion::V5 {
+            Properties::default().encode(dst)?;
+
+            for reason_code in reason_codes {
+                dst.put_u8_bytes(reason_code.0);
+            }
+        }
+
         Ok(())
     }
 }
@@ -808,7 +1099,11 @@ pub struct Unsubscribe {
 impl PacketMeta for Unsubscribe {
     const PACKET_TYPE: u8 = 0xA0;
 
-    fn decode(flags: u8, mut src: bytes::BytesMut) -> Result<Self, super::DecodeError> {
+    fn decode(
+        flags: u8,
+        mut src: bytes::BytesMut,
+        version: ProtocolVersion,
+    ) -> Result<Self, super::DecodeError> {
         if flags != 2 || src.len() < std::mem::size_of::<u16>() {
             return Err(super::DecodeError::UnrecognizedPacket {
                 packet_type: Self::PACKET_TYPE,
@@ -819,6 +1114,10 @@ impl PacketMeta for Unsubscribe {
 
         let packet_identifier = src.get_packet_identifier()?;
 
+        if version == ProtocolVersion::V5 {
+            let _ = Properties::decode(&mut src)?;
+        }
+
         let mut unsubscribe_from = vec![];
 
         while !src.is_empty() {
@@ -839,7 +1138,7 @@ impl PacketMeta for Unsubscribe {
         })
     }
 
-    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    fn encode<B>(&self, dst: &mut B, version: ProtocolVersion) -> Result<(), super::EncodeError>
     where
         B: ByteBuf,
     {
@@ -850,6 +1149,10 @@ impl PacketMeta for Unsubscribe {
 
         dst.put_packet_identifier_bytes(*packet_identifier);
 
+        if version == ProtocolVersion::V5 {
+            Properties::default().encode(dst)?;
+        }
+
         for unsubscribe_from in unsubscribe_from {
             super::encode_utf8_str(unsubscribe_from, dst)?;
         }
@@ -871,9 +1174,61 @@ pub enum PacketIdentifierDupQoS {
 
 /// A subscription request.
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(Deserialize, Serialize))]
 pub struct SubscribeTo {
     pub topic_filter: String,
     pub qos: QoS,
+    pub options: SubscriptionOptions,
+}
+
+/// The MQTT 5.0 options of a subscription request.
+///
+/// The options are always the defaults on MQTT 3.1.1 connections.
+///
+/// Ref: 3.8.3.1 Subscription Options (MQTT 5.0)
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(Deserialize, Serialize))]
+pub struct SubscriptionOptions {
+    /// Publications are not forwarded to the connection which published them.
+    pub no_local: bool,
+
+    /// Forwarded publications keep the RETAIN flag they were published with.
+    pub retain_as_published: bool,
+
+    /// Whether retained messages are sent when the subscription is made.
+    pub retain_handling: RetainHandling,
+}
+
+impl From<SubscriptionOptions> for u8 {
+    fn from(options: SubscriptionOptions) -> Self {
+        let retain_handling = match options.retain_handling {
+            RetainHandling::SendAtSubscribe => 0x00,
+            RetainHandling::SendAtNewSubscribe => 0x10,
+            RetainHandling::DoNotSend => 0x20,
+        };
+        let no_local = if options.no_local { 0x04 } else { 0x00 };
+        let retain_as_published = if options.retain_as_published {
+            0x08
+        } else {
+            0x00
+        };
+        retain_handling | no_local | retain_as_published
+    }
+}
+
+/// Whether retained messages are sent when a subscription is made.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(Deserialize, Serialize))]
+pub enum RetainHandling {
+    SendAtSubscribe,
+    SendAtNewSubscribe,
+    DoNotSend,
+}
+
+impl Default for RetainHandling {
+    fn default() -> Self {
+        RetainHandling::SendAtSubscribe
+    }
 }
 
 /// The level of reliability for a publication
@@ -899,17 +1254,20 @@ impl From<QoS> for u8 {
 
 #[allow(clippy::doc_markdown)]
 /// QoS returned in a SUBACK packet. Either one of the [`QoS`] values, or an error code.
+///
+/// The reason code of a failure is only sent on MQTT 5.0 connections, MQTT 3.1.1 connections
+/// always get `0x80`.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum SubAckQos {
     Success(QoS),
-    Failure,
+    Failure(ReasonCode),
 }
 
 impl From<SubAckQos> for u8 {
     fn from(qos: SubAckQos) -> Self {
         match qos {
             SubAckQos::Success(qos) => qos.into(),
-            SubAckQos::Failure => 0x80,
+            SubAckQos::Failure(reason_code) => reason_code.0,
         }
     }
 }
@@ -924,14 +1282,26 @@ pub struct Publication {
     #[cfg_attr(feature = "serde1", serde(serialize_with = "serialize_bytes"))]
     #[cfg_attr(feature = "serde1", serde(deserialize_with = "deserialize_bytes"))]
     pub payload: bytes::Bytes,
+    pub properties: Properties,
 }
 
 /// A tokio codec that encodes and decodes MQTT packets.
 ///
+/// The codec starts out speaking MQTT 3.1.1. Decoding or encoding a CONNECT packet switches it
+/// to the protocol version requested by that packet for the rest of the connection.
+///
 /// Ref: 2 MQTT Control Packet format
 #[derive(Debug, Default)]
 pub struct PacketCodec {
     decoder_state: PacketDecoderState,
+    protocol_version: ProtocolVersion,
+}
+
+impl PacketCodec {
+    /// The protocol version currently used to encode and decode packets.
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.protocol_version
+    }
 }
 
 #[derive(Debug)]
@@ -1002,25 +1372,46 @@ impl tokio_util::codec::Decoder for PacketCodec {
 
         let packet_type = first_byte & 0xF0;
         let flags = first_byte & 0x0F;
+        let version = self.protocol_version;
         match packet_type {
-            ConnAck::PACKET_TYPE => Ok(Some(Packet::ConnAck(ConnAck::decode(flags, src)?))),
-            Connect::PACKET_TYPE => Ok(Some(Packet::Connect(Connect::decode(flags, src)?))),
-            Disconnect::PACKET_TYPE => {
-                Ok(Some(Packet::Disconnect(Disconnect::decode(flags, src)?)))
+            Auth::PACKET_TYPE => Ok(Some(Packet::Auth(Auth::decode(flags, src, version)?))),
+            ConnAck::PACKET_TYPE => {
+                Ok(Some(Packet::ConnAck(ConnAck::decode(flags, src, version)?)))
+            }
+            Connect::PACKET_TYPE => {
+                let packet = Connect::decode(flags, src, version)?;
+                self.protocol_version =
+                    ProtocolVersion::from_level(packet.protocol_level).unwrap_or_default();
+                Ok(Some(Packet::Connect(packet)))
+            }
+            Disconnect::PACKET_TYPE => Ok(Some(Packet::Disconnect(Disconnect::decode(
+                flags, src, version,
+            )?))),
+            PingReq::PACKET_TYPE => {
+                Ok(Some(Packet::PingReq(PingReq::decode(flags, src, version)?)))
+            }
+            PingResp::PACKET_TYPE => Ok(Some(Packet::PingResp(PingResp::decode(
+                flags, src, version,
+            )?))),
+            PubAck::PACKET_TYPE => Ok(Some(Packet::PubAck(PubAck::decode(flags, src, version)?))),
+            PubComp::PACKET_TYPE => {
+                Ok(Some(Packet::PubComp(PubComp::decode(flags, src, version)?)))
             }
-            PingReq::PACKET_TYPE => Ok(Some(Packet::PingReq(PingReq::decode(flags, src)?))),
-            PingResp::PACKET_TYPE => Ok(Some(Packet::PingResp(PingResp::decode(flags, src)?))),
-            PubAck::PACKET_TYPE => Ok(Some(Packet::PubAck(PubAck::decode(flags, src)?))),
-            PubComp::PACKET_TYPE => Ok(Some(Packet::PubComp(PubComp::decode(flags, src)?))),
-            Publish::PACKET_TYPE => Ok(Some(Packet::Publish(Publish::decode(flags, src)?))),
-            PubRec::PACKET_TYPE => Ok(Some(Packet::PubRec(PubRec::decode(flags, src)?))),
-            PubRel::PACKET_TYPE => Ok(Some(Packet::PubRel(PubRel::decode(flags, src)?))),
-            SubAck::PACKET_TYPE => Ok(Some(Packet::SubAck(SubAck::decode(flags, src)?))),
-            Subscribe::PACKET_TYPE => Ok(Some(Packet::Subscribe(Subscribe::decode(flags, src)?))),
-            UnsubAck::PACKET_TYPE => Ok(Some(Packet::UnsubAck(UnsubAck::decode(flags, src)?))),
-            Unsubscribe::PACKET_TYPE => {
-                Ok(Some(Packet::Unsubscribe(Unsubscribe::decode(flags, src)?)))
+            Publish::PACKET_TYPE => {
+                Ok(Some(Packet::Publish(Publish::decode(flags, src, version)?)))
             }
+            PubRec::PACKET_TYPE => Ok(Some(Packet::PubRec(PubRec::decode(flags, src, version)?))),
+            PubRel::PACKET_TYPE => Ok(Some(Packet::PubRel(PubRel::decode(flags, src, version)?))),
+            SubAck::PACKET_TYPE => Ok(Some(Packet::SubAck(SubAck::decode(flags, src, version)?))),
+            Subscribe::PACKET_TYPE => Ok(Some(Packet::Subscribe(Subscribe::decode(
+                flags, src, version,
+            )?))),
+            UnsubAck::PACKET_TYPE => Ok(Some(Packet::UnsubAck(UnsubAck::decode(
+                flags, src, version,
+            )?))),
+            Unsubscribe::PACKET_TYPE => Ok(Some(Packet::Unsubscribe(Unsubscribe::decode(
+                flags, src, version,
+            )?))),
             packet_type => Err(super::DecodeError::UnrecognizedPacket {
                 packet_type,
                 flags,
@@ -1037,14 +1428,21 @@ impl tokio_util::codec::Encoder for PacketCodec {
     fn encode(&mut self, item: Self::Item, dst: &mut bytes::BytesMut) -> Result<(), Self::Error> {
         dst.reserve(std::mem::size_of::<u8>() + 4 * std::mem::size_of::<u8>());
 
+        if let Packet::Connect(packet) = &item {
+            self.protocol_version =
+                ProtocolVersion::from_level(packet.protocol_level).unwrap_or_default();
+        }
+        let version = self.protocol_version;
+
         match &item {
-            Packet::ConnAck(packet) => encode_packet(packet, 0, dst),
-            Packet::Connect(packet) => encode_packet(packet, 0, dst),
-            Packet::Disconnect(packet) => encode_packet(packet, 0, dst),
-            Packet::PingReq(packet) => encode_packet(packet, 0, dst),
-            Packet::PingResp(packet) => encode_packet(packet, 0, dst),
-            Packet::PubAck(packet) => encode_packet(packet, 0, dst),
-            Packet::PubComp(packet) => encode_packet(packet, 0, dst),
+            Packet::Auth(packet) => encode_packet(packet, 0, dst, version),
+            Packet::ConnAck(packet) => encode_packet(packet, 0, dst, version),
+            Packet::Connect(packet) => encode_packet(packet, 0, dst, version),
+            Packet::Disconnect(packet) => encode_packet(packet, 0, dst, version),
+            Packet::PingReq(packet) => encode_packet(packet, 0, dst, version),
+            Packet::PingResp(packet) => encode_packet(packet, 0, dst, version),
+            Packet::PubAck(packet) => encode_packet(packet, 0, dst, version),
+            Packet::PubComp(packet) => encode_packet(packet, 0, dst, version),
             Packet::Publish(packet) => {
                 let mut flags = match packet.packet_identifier_dup_qos {
                     PacketIdentifierDupQoS::AtMostOnce => 0x00,
@@ -1056,14 +1454,14 @@ impl tokio_util::codec::Encoder for PacketCodec {
                 if packet.retain {
                     flags |= 0x01;
                 };
-                encode_packet(packet, flags, dst)
+                encode_packet(packet, flags, dst, version)
             }
-            Packet::PubRec(packet) => encode_packet(packet, 0, dst),
-            Packet::PubRel(packet) => encode_packet(packet, 0x02, dst),
-            Packet::SubAck(packet) => encode_packet(packet, 0, dst),
-            Packet::Subscribe(packet) => encode_packet(packet, 0x02, dst),
-            Packet::UnsubAck(packet) => encode_packet(packet, 0, dst),
-            Packet::Unsubscribe(packet) => encode_packet(packet, 0x02, dst),
+            Packet::PubRec(packet) => encode_packet(packet, 0, dst, version),
+            Packet::PubRel(packet) => encode_packet(packet, 0x02, dst, version),
+            Packet::SubAck(packet) => encode_packet(packet, 0, dst, version),
+            Packet::Subscribe(packet) => encode_packet(packet, 0x02, dst, version),
+            Packet::UnsubAck(packet) => encode_packet(packet, 0, dst, version),
+            Packet::Unsubscribe(packet) => encode_packet(packet, 0x02, dst, version),
         }
     }
 }
@@ -1072,12 +1470,13 @@ fn encode_packet<P>(
     packet: &P,
     flags: u8,
     dst: &mut bytes::BytesMut,
+    version: ProtocolVersion,
 ) -> Result<(), super::EncodeError>
 where
     P: PacketMeta,
 {
     let mut counter = super::ByteCounter::new();
-    packet.encode(&mut counter)?;
+    packet.encode(&mut counter, version)?;
     let body_len = counter.0;
 
     dst.reserve(
@@ -1088,7 +1487,98 @@ where
 
     dst.put_u8(<P as PacketMeta>::PACKET_TYPE | flags);
     super::encode_remaining_length(body_len, dst)?;
-    packet.encode(dst)?;
+    packet.encode(dst, version)?;
+
+    Ok(())
+}
+
+/// Decodes the body of a PUBACK, PUBREC, PUBREL or PUBCOMP packet.
+///
+/// On MQTT 5.0 connections the reason code is omitted when it is SUCCESS,
+/// and the properties are omitted when there are none.
+fn decode_ack(
+    packet_type: u8,
+    expected_flags: u8,
+    flags: u8,
+    mut src: bytes::BytesMut,
+    version: ProtocolVersion,
+) -> Result<(super::PacketIdentifier, ReasonCode, Properties), super::DecodeError> {
+    let has_valid_len = match version {
+        ProtocolVersion::V311 => src.len() == std::mem::size_of::<u16>(),
+        ProtocolVersion::V5 => src.len() >= std::mem::size_of::<u16>(),
+    };
+    if flags != expected_flags || !has_valid_len {
+        return Err(super::DecodeError::UnrecognizedPacket {
+            packet_type,
+            flags,
+            remaining_length: src.len(),
+        });
+    }
+
+    let packet_identifier = src.get_packet_identifier()?;
+
+    let (reason_code, properties) = decode_reason_code_and_properties(&mut src)?;
+
+    Ok((packet_identifier, reason_code, properties))
+}
+
+/// Encodes the body of a PUBACK, PUBREC, PUBREL or PUBCOMP packet.
+fn encode_ack<B>(
+    packet_identifier: super::PacketIdentifier,
+    reason_code: ReasonCode,
+    properties: &Properties,
+    dst: &mut B,
+    version: ProtocolVersion,
+) -> Result<(), super::EncodeError>
+where
+    B: ByteBuf,
+{
+    dst.put_packet_identifier_bytes(packet_identifier);
+
+    match version {
+        ProtocolVersion::V311 => Ok(()),
+        ProtocolVersion::V5 => encode_reason_code_and_properties(reason_code, properties, dst),
+    }
+}
+
+/// Decodes the optional reason code and properties that end an MQTT 5.0 packet.
+///
+/// A missing reason code means SUCCESS, and missing properties mean there are none.
+fn decode_reason_code_and_properties(
+    src: &mut bytes::BytesMut,
+) -> Result<(ReasonCode, Properties), super::DecodeError> {
+    let reason_code = if src.is_empty() {
+        ReasonCode::SUCCESS
+    } else {
+        ReasonCode(src.get_u8())
+    };
+
+    let properties = if src.is_empty() {
+        Properties::default()
+    } else {
+        Properties::decode(src)?
+    };
+
+    Ok((reason_code, properties))
+}
+
+fn encode_reason_code_and_properties<B>(
+    reason_code: ReasonCode,
+    properties: &Properties,
+    dst: &mut B,
+) -> Result<(), super::EncodeError>
+where
+    B: ByteBuf,
+{
+    if reason_code == ReasonCode::SUCCESS && properties.is_empty() {
+        return Ok(());
+    }
+
+    dst.put_u8_bytes(reason_code.0);
+
+    if !properties.is_empty() {
+        properties.encode(dst)?;
+    }
 
     Ok(())
 }
@@ -1108,3 +1598,328 @@ where
 {
     Vec::<u8>::deserialize(deserializer).map(bytes::Bytes::from)
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio_util::codec::{Decoder, Encoder};
+
+    use super::*;
+    use crate::proto::{ClientId, ConnectReturnCode, ConnectionRefusedReason, Property};
+
+    fn connect(protocol_level: u8) -> Packet {
+        Packet::Connect(Connect {
+            username: Some("username".to_string()),
+            password: Some("password".to_string()),
+            will: Some(Publication {
+                topic_name: "will".to_string(),
+                qos: QoS::AtLeastOnce,
+                retain: true,
+                payload: bytes::Bytes::from("goodbye"),
+                properties: if protocol_level == crate::PROTOCOL_LEVEL_V5 {
+                    vec![Property::WillDelayInterval(10)].into_iter().collect()
+                } else {
+                    Properties::default()
+                },
+            }),
+            client_id: ClientId::IdWithExistingSession("client".to_string()),
+            keep_alive: Duration::from_secs(60),
+            protocol_name: crate::PROTOCOL_NAME.to_string(),
+            protocol_level,
+            properties: if protocol_level == crate::PROTOCOL_LEVEL_V5 {
+                vec![
+                    Property::SessionExpiryInterval(3600),
+                    Property::ReceiveMaximum(10),
+                ]
+                .into_iter()
+                .collect()
+            } else {
+                Properties::default()
+            },
+        })
+    }
+
+    fn roundtrip(packets: Vec<Packet>) -> PacketCodec {
+        let mut encoder = PacketCodec::default();
+        let mut decoder = PacketCodec::default();
+
+        for packet in packets {
+            let mut bytes = bytes::BytesMut::new();
+            encoder.encode(packet.clone(), &mut bytes).unwrap();
+
+            let decoded = decoder.decode(&mut bytes).unwrap().unwrap();
+            assert_eq!(packet, decoded);
+            assert!(bytes.is_empty());
+        }
+
+        assert_eq!(encoder.protocol_version(), decoder.protocol_version());
+        decoder
+    }
+
+    #[test]
+    fn roundtrip_v311() {
+        let codec = roundtrip(vec![
+            connect(crate::PROTOCOL_LEVEL),
+            Packet::PubAck(PubAck {
+                packet_identifier: crate::proto::PacketIdentifier::new(1).unwrap(),
+                reason_code: ReasonCode::SUCCESS,
+                properties: Properties::default(),
+            }),
+            Packet::Disconnect(Disconnect::default()),
+        ]);
+        assert_eq!(ProtocolVersion::V311, codec.protocol_version());
+    }
+
+    #[test]
+    fn roundtrip_v5() {
+        let packet_identifier = crate::proto::PacketIdentifier::new(1).unwrap();
+
+        let codec = roundtrip(vec![
+            connect(crate::PROTOCOL_LEVEL_V5),
+            Packet::ConnAck(ConnAck {
+                session_present: true,
+                return_code: ConnectReturnCode::Refused(ConnectionRefusedReason::NotAuthorized),
+                properties: vec![Property::AssignedClientIdentifier("client".to_string())]
+                    .into_iter()
+                    .collect(),
+            }),
+            Packet::Publish(Publish {
+                packet_identifier_dup_qos: PacketIdentifierDupQoS::AtLeastOnce(
+                    packet_identifier,
+                    false,
+                ),
+                retain: false,
+                topic_name: "topic".to_string(),
+                payload: bytes::Bytes::from("hello"),
+                properties: vec![
+                    Property::MessageExpiryInterval(30),
+                    Property::TopicAlias(1),
+                    Property::UserProperty("key".to_string(), "value".to_string()),
+                ]
+                .into_iter()
+                .collect(),
+            }),
+            Packet::PubAck(PubAck {
+                packet_identifier,
+                reason_code: ReasonCode::SUCCESS,
+                properties: Properties::default(),
+            }),
+            Packet::PubRec(PubRec {
+                packet_identifier,
+                reason_code: ReasonCode::QUOTA_EXCEEDED,
+                properties: vec![Property::ReasonString("quota".to_string())]
+                    .into_iter()
+                    .collect(),
+            }),
+            Packet::PubComp(PubComp {
+                packet_identifier,
+                reason_code: ReasonCode::SUCCESS,
+                properties: vec![Property::UserProperty(
+                    "key".to_string(),
+                    "value".to_string(),
+                )]
+                .into_iter()
+                .collect(),
+            }),
+            Packet::PubRel(PubRel {
+                packet_identifier,
+                reason_code: ReasonCode::PACKET_IDENTIFIER_NOT_FOUND,
+                properties: Properties::default(),
+            }),
+            Packet::SubAck(SubAck {
+                packet_identifier,
+                qos: vec![
+                    SubAckQos::Success(QoS::ExactlyOnce),
+                    SubAckQos::Failure(ReasonCode::QUOTA_EXCEEDED),
+                ],
+            }),
+            Packet::Subscribe(Subscribe {
+                packet_identifier,
+                subscribe_to: vec![
+                    SubscribeTo {
+                        topic_filter: "topic/#".to_string(),
+                        qos: QoS::AtLeastOnce,
+                        options: SubscriptionOptions::default(),
+                    },
+                    SubscribeTo {
+                        topic_filter: "other/#".to_string(),
+                        qos: QoS::ExactlyOnce,
+                        options: SubscriptionOptions {
+                            no_local: true,
+                            retain_as_published: true,
+                            retain_handling: RetainHandling::DoNotSend,
+                        },
+                    },
+                ],
+                properties: vec![Property::SubscriptionIdentifier(7)]
+                    .into_iter()
+                    .collect(),
+            }),
+            Packet::UnsubAck(UnsubAck {
+                packet_identifier,
+                reason_codes: vec![ReasonCode::SUCCESS, ReasonCode::NO_SUBSCRIPTION_EXISTED],
+            }),
+            Packet::Auth(Auth {
+                reason_code: ReasonCode::CONTINUE_AUTHENTICATION,
+                properties: vec![Property::AuthenticationMethod("method".to_string())]
+                    .into_iter()
+                    .collect(),
+            }),
+            Packet::Disconnect(Disconnect {
+                reason_code: ReasonCode::DISCONNECT_WITH_WILL_MESSAGE,
+                properties: Properties::default(),
+            }),
+        ]);
+        assert_eq!(ProtocolVersion::V5, codec.protocol_version());
+    }
+
+    #[test]
+    fn decode_v5_short_acks() {
+        let mut codec = PacketCodec::default();
+
+        let mut bytes = bytes::BytesMut::new();
+        codec
+            .encode(connect(crate::PROTOCOL_LEVEL_V5), &mut bytes)
+            .unwrap();
+        codec.decode(&mut bytes).unwrap().unwrap();
+
+        // PUBACK without a reason code, DISCONNECT without a body
+        let mut bytes = bytes::BytesMut::from(&[0x40, 0x02, 0x00, 0x01, 0xE0, 0x00][..]);
+        assert_eq!(
+            Some(Packet::PubAck(PubAck {
+                packet_identifier: crate::proto::PacketIdentifier::new(1).unwrap(),
+                reason_code: ReasonCode::SUCCESS,
+                properties: Properties::default(),
+            })),
+            codec.decode(&mut bytes).unwrap()
+        );
+        assert_eq!(
+            Some(Packet::Disconnect(Disconnect::default())),
+            codec.decode(&mut bytes).unwrap()
+        );
+    }
+
+    #[test]
+    fn decode_auth_v311_fails() {
+        let mut codec = PacketCodec::default();
+
+        let mut bytes = bytes::BytesMut::from(&[0xF0, 0x00][..]);
+        let err = codec.decode(&mut bytes).unwrap_err();
+        if let crate::proto::DecodeError::UnrecognizedPacket { packet_type, .. } = err {
+            assert_eq!(Auth::PACKET_TYPE, packet_type);
+        } else {
+            panic!("{:?}", err);
+        }
+    }
+
+    #[test]
+    fn encode_v311_subscribe_options_and_suback_reason_codes() {
+        let packet_identifier = crate::proto::PacketIdentifier::new(1).unwrap();
+        let mut codec = PacketCodec::default();
+
+        let mut bytes = bytes::BytesMut::new();
+        codec
+            .encode(
+                Packet::Subscribe(Subscribe {
+                    packet_identifier,
+                    subscribe_to: vec![SubscribeTo {
+                        topic_filter: "a".to_string(),
+                        qos: QoS::AtLeastOnce,
+                        options: SubscriptionOptions {
+                            no_local: true,
+                            retain_as_published: true,
+                            retain_handling: RetainHandling::DoNotSend,
+                        },
+                    }],
+                    properties: vec![Property::SubscriptionIdentifier(7)]
+                        .into_iter()
+                        .collect(),
+                }),
+                &mut bytes,
+            )
+            .unwrap();
+        codec
+            .encode(
+                Packet::SubAck(SubAck {
+                    packet_identifier,
+                    qos: vec![SubAckQos::Failure(ReasonCode::QUOTA_EXCEEDED)],
+                }),
+                &mut bytes,
+            )
+            .unwrap();
+
+        assert_eq!(
+            &[0x82, 0x06, 0x00, 0x01, 0x00, 0x01, b'a', 0x01, 0x90, 0x03, 0x00, 0x01, 0x80][..],
+            &bytes[..]
+        );
+    }
+
+    #[test]
+    fn decode_v5_subscription_options() {
+        let mut codec = PacketCodec::default();
+
+        let mut bytes = bytes::BytesMut::new();
+        codec
+            .encode(connect(crate::PROTOCOL_LEVEL_V5), &mut bytes)
+            .unwrap();
+        codec.decode(&mut bytes).unwrap().unwrap();
+
+        let mut bytes =
+            bytes::BytesMut::from(&[0x82, 0x07, 0x00, 0x01, 0x00, 0x00, 0x01, b'a', 0x1D][..]);
+        assert_eq!(
+            Some(Packet::Subscribe(Subscribe {
+                packet_identifier: crate::proto::PacketIdentifier::new(1).unwrap(),
+                subscribe_to: vec![SubscribeTo {
+                    topic_filter: "a".to_string(),
+                    qos: QoS::AtLeastOnce,
+                    options: SubscriptionOptions {
+                        no_local: true,
+                        retain_as_published: true,
+                        retain_handling: RetainHandling::SendAtNewSubscribe,
+                    },
+                }],
+                properties: Properties::default(),
+            })),
+            codec.decode(&mut bytes).unwrap()
+        );
+    }
+
+    #[test]
+    fn decode_v5_subscription_options_retain_handling_3() {
+        let mut codec = PacketCodec::default();
+
+        let mut bytes = bytes::BytesMut::new();
+        codec
+            .encode(connect(crate::PROTOCOL_LEVEL_V5), &mut bytes)
+            .unwrap();
+        codec.decode(&mut bytes).unwrap().unwrap();
+
+        let mut bytes =
+            bytes::BytesMut::from(&[0x82, 0x07, 0x00, 0x01, 0x00, 0x00, 0x01, b'a', 0x31][..]);
+        let err = codec.decode(&mut bytes).unwrap_err();
+        if let crate::proto::DecodeError::UnrecognizedRetainHandling(retain_handling) = err {
+            assert_eq!(0x03, retain_handling);
+        } else {
+            panic!("{:?}", err);
+        }
+    }
+
+    #[test]
+    fn decode_subscription_options_reserved_set() {
+        let mut codec = PacketCodec::default();
+
+        let mut bytes = bytes::BytesMut::new();
+        codec
+            .encode(connect(crate::PROTOCOL_LEVEL_V5), &mut bytes)
+            .unwrap();
+        codec.decode(&mut bytes).unwrap().unwrap();
+
+        let mut bytes =
+            bytes::BytesMut::from(&[0x82, 0x07, 0x00, 0x01, 0x00, 0x00, 0x01, b'a', 0xC1][..]);
+        let err = codec.decode(&mut bytes).unwrap_err();
+        if let crate::proto::DecodeError::SubscriptionOptionsReservedSet(options) = err {
+            assert_eq!(0xC1, options);
+        } else {
+            panic!("{:?}", err);
+        }
+    }
+}
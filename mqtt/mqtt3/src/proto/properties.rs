@@ -0,0 +1,482 @@
+use std::convert::TryInto;
+
+use bytes::Buf;
+#[cfg(feature = "serde1")]
+use serde::{Deserialize, Serialize};
+use tokio_util::codec::Decoder;
+
+use super::{BufMutExt, ByteBuf};
+
+/// The version of the MQTT protocol spoken on a connection.
+///
+/// The version is negotiated by the CONNECT packet. Packets other than CONNECT
+/// are encoded and decoded according to the version of the connection.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProtocolVersion {
+    /// MQTT 3.1.1 (protocol level 4)
+    V311,
+
+    /// MQTT 5.0 (protocol level 5)
+    V5,
+}
+
+impl ProtocolVersion {
+    /// Returns the version for the given CONNECT protocol level, if it is supported.
+    pub fn from_level(level: u8) -> Option<Self> {
+        match level {
+            crate::PROTOCOL_LEVEL => Some(ProtocolVersion::V311),
+            crate::PROTOCOL_LEVEL_V5 => Some(ProtocolVersion::V5),
+            _ => None,
+        }
+    }
+
+    /// Returns the CONNECT protocol level of this version.
+    pub fn level(self) -> u8 {
+        match self {
+            ProtocolVersion::V311 => crate::PROTOCOL_LEVEL,
+            ProtocolVersion::V5 => crate::PROTOCOL_LEVEL_V5,
+        }
+    }
+}
+
+impl Default for ProtocolVersion {
+    fn default() -> Self {
+        ProtocolVersion::V311
+    }
+}
+
+/// A reason code of an MQTT 5.0 packet.
+///
+/// Values below 0x80 indicate success, values of 0x80 and above indicate failure.
+/// Reason codes are not sent on MQTT 3.1.1 connections.
+///
+/// Ref: 2.4 Reason Code (MQTT 5.0)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ReasonCode(pub u8);
+
+impl ReasonCode {
+    pub const SUCCESS: Self = ReasonCode(0x00);
+    pub const NORMAL_DISCONNECTION: Self = ReasonCode(0x00);
+    pub const DISCONNECT_WITH_WILL_MESSAGE: Self = ReasonCode(0x04);
+    pub const NO_SUBSCRIPTION_EXISTED: Self = ReasonCode(0x11);
+    pub const CONTINUE_AUTHENTICATION: Self = ReasonCode(0x18);
+    pub const RE_AUTHENTICATE: Self = ReasonCode(0x19);
+    pub const UNSPECIFIED_ERROR: Self = ReasonCode(0x80);
+    pub const MALFORMED_PACKET: Self = ReasonCode(0x81);
+    pub const PROTOCOL_ERROR: Self = ReasonCode(0x82);
+    pub const IMPLEMENTATION_SPECIFIC_ERROR: Self = ReasonCode(0x83);
+    pub const NOT_AUTHORIZED: Self = ReasonCode(0x87);
+    pub const SERVER_SHUTTING_DOWN: Self = ReasonCode(0x8B);
+    pub const BAD_AUTHENTICATION_METHOD: Self = ReasonCode(0x8C);
+    pub const SESSION_TAKEN_OVER: Self = ReasonCode(0x8E);
+    pub const TOPIC_FILTER_INVALID: Self = ReasonCode(0x8F);
+    pub const TOPIC_NAME_INVALID: Self = ReasonCode(0x90);
+    pub const PACKET_IDENTIFIER_NOT_FOUND: Self = ReasonCode(0x92);
+    pub const TOPIC_ALIAS_INVALID: Self = ReasonCode(0x94);
+    pub const QUOTA_EXCEEDED: Self = ReasonCode(0x97);
+    pub const SUBSCRIPTION_IDENTIFIERS_NOT_SUPPORTED: Self = ReasonCode(0xA1);
+
+    pub fn is_success(self) -> bool {
+        self.0 < 0x80
+    }
+}
+
+impl Default for ReasonCode {
+    fn default() -> Self {
+        ReasonCode::SUCCESS
+    }
+}
+
+impl std::fmt::Display for ReasonCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "0x{:02X}", self.0)
+    }
+}
+
+/// A single MQTT 5.0 property.
+///
+/// Ref: 2.2.2.2 Property (MQTT 5.0)
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(Deserialize, Serialize))]
+pub enum Property {
+    PayloadFormatIndicator(u8),
+    MessageExpiryInterval(u32),
+    ContentType(String),
+    ResponseTopic(String),
+    CorrelationData(Vec<u8>),
+    SubscriptionIdentifier(u32),
+    SessionExpiryInterval(u32),
+    AssignedClientIdentifier(String),
+    ServerKeepAlive(u16),
+    AuthenticationMethod(String),
+    AuthenticationData(Vec<u8>),
+    RequestProblemInformation(u8),
+    WillDelayInterval(u32),
+    RequestResponseInformation(u8),
+    ResponseInformation(String),
+    ServerReference(String),
+    ReasonString(String),
+    ReceiveMaximum(u16),
+    TopicAliasMaximum(u16),
+    TopicAlias(u16),
+    MaximumQoS(u8),
+    RetainAvailable(u8),
+    UserProperty(String, String),
+    MaximumPacketSize(u32),
+    WildcardSubscriptionAvailable(u8),
+    SubscriptionIdentifierAvailable(u8),
+    SharedSubscriptionAvailable(u8),
+}
+
+impl Property {
+    fn identifier(&self) -> u8 {
+        match self {
+            Property::PayloadFormatIndicator(_) => 0x01,
+            Property::MessageExpiryInterval(_) => 0x02,
+            Property::ContentType(_) => 0x03,
+            Property::ResponseTopic(_) => 0x08,
+            Property::CorrelationData(_) => 0x09,
+            Property::SubscriptionIdentifier(_) => 0x0B,
+            Property::SessionExpiryInterval(_) => 0x11,
+            Property::AssignedClientIdentifier(_) => 0x12,
+            Property::ServerKeepAlive(_) => 0x13,
+            Property::AuthenticationMethod(_) => 0x15,
+            Property::AuthenticationData(_) => 0x16,
+            Property::RequestProblemInformation(_) => 0x17,
+            Property::WillDelayInterval(_) => 0x18,
+            Property::RequestResponseInformation(_) => 0x19,
+            Property::ResponseInformation(_) => 0x1A,
+            Property::ServerReference(_) => 0x1C,
+            Property::ReasonString(_) => 0x1F,
+            Property::ReceiveMaximum(_) => 0x21,
+            Property::TopicAliasMaximum(_) => 0x22,
+            Property::TopicAlias(_) => 0x23,
+            Property::MaximumQoS(_) => 0x24,
+            Property::RetainAvailable(_) => 0x25,
+            Property::UserProperty(_, _) => 0x26,
+            Property::MaximumPacketSize(_) => 0x27,
+            Property::WildcardSubscriptionAvailable(_) => 0x28,
+            Property::SubscriptionIdentifierAvailable(_) => 0x29,
+            Property::SharedSubscriptionAvailable(_) => 0x2A,
+        }
+    }
+
+    fn decode(src: &mut bytes::BytesMut) -> Result<Self, super::DecodeError> {
+        // Property identifiers are variable byte integers, but all identifiers
+        // defined by the specification fit into a single byte.
+        let identifier = src.try_get_u8()?;
+        let property = match identifier {
+            0x01 => Property::PayloadFormatIndicator(src.try_get_u8()?),
+            0x02 => Property::MessageExpiryInterval(src.try_get_u32_be()?),
+            0x03 => Property::ContentType(decode_utf8_str(src)?),
+            0x08 => Property::ResponseTopic(decode_utf8_str(src)?),
+            0x09 => Property::CorrelationData(decode_binary(src)?),
+            0x0B => {
+                let id = decode_variable_byte_integer(src)?;
+                #[allow(clippy::cast_possible_truncation)]
+                let id = id as u32;
+                Property::SubscriptionIdentifier(id)
+            }
+            0x11 => Property::SessionExpiryInterval(src.try_get_u32_be()?),
+            0x12 => Property::AssignedClientIdentifier(decode_utf8_str(src)?),
+            0x13 => Property::ServerKeepAlive(src.try_get_u16_be()?),
+            0x15 => Property::AuthenticationMethod(decode_utf8_str(src)?),
+            0x16 => Property::AuthenticationData(decode_binary(src)?),
+            0x17 => Property::RequestProblemInformation(src.try_get_u8()?),
+            0x18 => Property::WillDelayInterval(src.try_get_u32_be()?),
+            0x19 => Property::RequestResponseInformation(src.try_get_u8()?),
+            0x1A => Property::ResponseInformation(decode_utf8_str(src)?),
+            0x1C => Property::ServerReference(decode_utf8_str(src)?),
+            0x1F => Property::ReasonString(decode_utf8_str(src)?),
+            0x21 => Property::ReceiveMaximum(src.try_get_u16_be()?),
+            0x22 => Property::TopicAliasMaximum(src.try_get_u16_be()?),
+            0x23 => Property::TopicAlias(src.try_get_u16_be()?),
+            0x24 => Property::MaximumQoS(src.try_get_u8()?),
+            0x25 => Property::RetainAvailable(src.try_get_u8()?),
+            0x26 => {
+                let name = decode_utf8_str(src)?;
+                let value = decode_utf8_str(src)?;
+                Property::UserProperty(name, value)
+            }
+            0x27 => Property::MaximumPacketSize(src.try_get_u32_be()?),
+            0x28 => Property::WildcardSubscriptionAvailable(src.try_get_u8()?),
+            0x29 => Property::SubscriptionIdentifierAvailable(src.try_get_u8()?),
+            0x2A => Property::SharedSubscriptionAvailable(src.try_get_u8()?),
+            identifier => return Err(super::DecodeError::UnrecognizedProperty(identifier)),
+        };
+        Ok(property)
+    }
+
+    fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    where
+        B: ByteBuf,
+    {
+        dst.put_u8_bytes(self.identifier());
+
+        match self {
+            Property::PayloadFormatIndicator(value)
+            | Property::RequestProblemInformation(value)
+            | Property::RequestResponseInformation(value)
+            | Property::MaximumQoS(value)
+            | Property::RetainAvailable(value)
+            | Property::WildcardSubscriptionAvailable(value)
+            | Property::SubscriptionIdentifierAvailable(value)
+            | Property::SharedSubscriptionAvailable(value) => dst.put_u8_bytes(*value),
+
+            Property::ServerKeepAlive(value)
+            | Property::ReceiveMaximum(value)
+            | Property::TopicAliasMaximum(value)
+            | Property::TopicAlias(value) => dst.put_u16_bytes(*value),
+
+            Property::MessageExpiryInterval(value)
+            | Property::SessionExpiryInterval(value)
+            | Property::WillDelayInterval(value)
+            | Property::MaximumPacketSize(value) => dst.put_u32_bytes(*value),
+
+            Property::SubscriptionIdentifier(value) => {
+                super::encode_remaining_length(*value as usize, dst)?
+            }
+
+            Property::ContentType(value)
+            | Property::ResponseTopic(value)
+            | Property::AssignedClientIdentifier(value)
+            | Property::AuthenticationMethod(value)
+            | Property::ResponseInformation(value)
+            | Property::ServerReference(value)
+            | Property::ReasonString(value) => super::encode_utf8_str(value, dst)?,
+
+            Property::CorrelationData(value) | Property::AuthenticationData(value) => {
+                encode_binary(value, dst)?
+            }
+
+            Property::UserProperty(name, value) => {
+                super::encode_utf8_str(name, dst)?;
+                super::encode_utf8_str(value, dst)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The properties of an MQTT 5.0 packet.
+///
+/// Properties are always empty on MQTT 3.1.1 connections, and are dropped
+/// when a packet that carries them is sent to an MQTT 3.1.1 peer.
+///
+/// Ref: 2.2.2 Properties (MQTT 5.0)
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(Deserialize, Serialize))]
+pub struct Properties(Vec<Property>);
+
+impl Properties {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Property> {
+        self.0.iter()
+    }
+
+    pub fn push(&mut self, property: Property) {
+        self.0.push(property);
+    }
+
+    /// Keeps only the properties for which `f` returns true.
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&Property) -> bool,
+    {
+        self.0.retain(f);
+    }
+
+    pub fn session_expiry_interval(&self) -> Option<u32> {
+        self.iter().find_map(|p| match p {
+            Property::SessionExpiryInterval(value) => Some(*value),
+            _ => None,
+        })
+    }
+
+    pub fn message_expiry_interval(&self) -> Option<u32> {
+        self.iter().find_map(|p| match p {
+            Property::MessageExpiryInterval(value) => Some(*value),
+            _ => None,
+        })
+    }
+
+    pub fn receive_maximum(&self) -> Option<u16> {
+        self.iter().find_map(|p| match p {
+            Property::ReceiveMaximum(value) => Some(*value),
+            _ => None,
+        })
+    }
+
+    pub fn subscription_identifier(&self) -> Option<u32> {
+        self.iter().find_map(|p| match p {
+            Property::SubscriptionIdentifier(value) => Some(*value),
+            _ => None,
+        })
+    }
+
+    pub fn topic_alias(&self) -> Option<u16> {
+        self.iter().find_map(|p| match p {
+            Property::TopicAlias(value) => Some(*value),
+            _ => None,
+        })
+    }
+
+    pub fn authentication_method(&self) -> Option<&str> {
+        self.iter().find_map(|p| match p {
+            Property::AuthenticationMethod(value) => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn user_properties(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.iter().filter_map(|p| match p {
+            Property::UserProperty(name, value) => Some((name.as_str(), value.as_str())),
+            _ => None,
+        })
+    }
+
+    pub(crate) fn decode(src: &mut bytes::BytesMut) -> Result<Self, super::DecodeError> {
+        let len = decode_variable_byte_integer(src)?;
+        if src.len() < len {
+            return Err(super::DecodeError::IncompletePacket);
+        }
+
+        let mut src = src.split_to(len);
+        let mut properties = vec![];
+        while !src.is_empty() {
+            properties.push(Property::decode(&mut src)?);
+        }
+
+        Ok(Properties(properties))
+    }
+
+    pub(crate) fn encode<B>(&self, dst: &mut B) -> Result<(), super::EncodeError>
+    where
+        B: ByteBuf,
+    {
+        let mut counter = super::ByteCounter::new();
+        for property in &self.0 {
+            property.encode(&mut counter)?;
+        }
+
+        super::encode_remaining_length(counter.0, dst)?;
+        for property in &self.0 {
+            property.encode(dst)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::iter::FromIterator<Property> for Properties {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = Property>,
+    {
+        Properties(iter.into_iter().collect())
+    }
+}
+
+fn decode_variable_byte_integer(src: &mut bytes::BytesMut) -> Result<usize, super::DecodeError> {
+    super::RemainingLengthDecoder::default()
+        .decode(src)?
+        .ok_or(super::DecodeError::IncompletePacket)
+}
+
+fn decode_utf8_str(src: &mut bytes::BytesMut) -> Result<String, super::DecodeError> {
+    super::Utf8StringDecoder::default()
+        .decode(src)?
+        .ok_or(super::DecodeError::IncompletePacket)
+}
+
+/// Ref: 1.5.6 Binary Data (MQTT 5.0)
+fn decode_binary(src: &mut bytes::BytesMut) -> Result<Vec<u8>, super::DecodeError> {
+    let len = usize::from(src.try_get_u16_be()?);
+    if src.len() < len {
+        return Err(super::DecodeError::IncompletePacket);
+    }
+
+    let mut value = vec![0; len];
+    src.copy_to_slice(&mut value);
+    Ok(value)
+}
+
+fn encode_binary<B>(item: &[u8], dst: &mut B) -> Result<(), super::EncodeError>
+where
+    B: ByteBuf,
+{
+    let len = item.len();
+    dst.put_u16_bytes(
+        len.try_into()
+            .map_err(|_| super::EncodeError::BinaryTooLarge(len))?,
+    );
+
+    dst.put_slice_bytes(item);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Properties, Property};
+
+    #[test]
+    fn properties_roundtrip() {
+        let properties: Properties = vec![
+            Property::PayloadFormatIndicator(1),
+            Property::MessageExpiryInterval(60),
+            Property::ContentType("application/json".to_string()),
+            Property::CorrelationData(vec![1, 2, 3]),
+            Property::SubscriptionIdentifier(0x4000),
+            Property::TopicAlias(7),
+            Property::UserProperty("key".to_string(), "value".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut bytes = bytes::BytesMut::new();
+        properties.encode(&mut bytes).unwrap();
+
+        let decoded = Properties::decode(&mut bytes).unwrap();
+        assert_eq!(properties, decoded);
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn properties_empty() {
+        let mut bytes = bytes::BytesMut::new();
+        Properties::new().encode(&mut bytes).unwrap();
+        assert_eq!(&*bytes, &[0x00]);
+
+        let decoded = Properties::decode(&mut bytes).unwrap();
+        assert!(decoded.is_empty());
+    }
+
+    #[test]
+    fn properties_unrecognized() {
+        let mut bytes = bytes::BytesMut::from(&[0x02, 0x7F, 0x00][..]);
+        let err = Properties::decode(&mut bytes).unwrap_err();
+        if let super::super::DecodeError::UnrecognizedProperty(0x7F) = err {
+        } else {
+            panic!("{:?}", err);
+        }
+    }
+
+    #[test]
+    fn properties_truncated() {
+        let mut bytes = bytes::BytesMut::from(&[0x05, 0x02, 0x00][..]);
+        let err = Properties::decode(&mut bytes).unwrap_err();
+        if let super::super::DecodeError::IncompletePacket = err {
+        } else {
+            panic!("{:?}", err);
+        }
+    }
+}
@@ -20,11 +20,13 @@ fn server_publishes_at_most_once() {
                 keep_alive: std::time::Duration::from_secs(4),
                 protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                 protocol_level: mqtt3::PROTOCOL_LEVEL,
+                properties: Default::default(),
             },
         )),
         common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(mqtt3::proto::ConnAck {
             session_present: false,
             return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+            properties: Default::default(),
         })),
         common::TestConnectionStep::Receives(mqtt3::proto::Packet::Subscribe(
             mqtt3::proto::Subscribe {
@@ -32,7 +34,9 @@ fn server_publishes_at_most_once() {
                 subscribe_to: vec![mqtt3::proto::SubscribeTo {
                     topic_filter: "topic1".to_owned(),
                     qos: mqtt3::proto::QoS::AtMostOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }],
+                properties: mqtt3::proto::Properties::default(),
             },
         )),
         common::TestConnectionStep::Sends(mqtt3::proto::Packet::SubAck(mqtt3::proto::SubAck {
@@ -48,6 +52,7 @@ fn server_publishes_at_most_once() {
             retain: false,
             topic_name: "topic1".to_owned(),
             payload: [0x01, 0x02, 0x03][..].into(),
+            properties: Default::default(),
         })),
         common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(mqtt3::proto::PingReq)),
         common::TestConnectionStep::Sends(mqtt3::proto::Packet::PingResp(mqtt3::proto::PingResp)),
@@ -65,6 +70,7 @@ fn server_publishes_at_most_once() {
         .subscribe(mqtt3::proto::SubscribeTo {
             topic_filter: "topic1".to_owned(),
             qos: mqtt3::proto::QoS::AtMostOnce,
+            options: mqtt3::proto::SubscriptionOptions::default(),
         })
         .unwrap();
 
@@ -79,6 +85,7 @@ fn server_publishes_at_most_once() {
                 mqtt3::proto::SubscribeTo {
                     topic_filter: "topic1".to_owned(),
                     qos: mqtt3::proto::QoS::AtMostOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 },
             )]),
             mqtt3::Event::Publication(mqtt3::ReceivedPublication {
@@ -114,11 +121,13 @@ fn server_publishes_at_least_once() {
                 keep_alive: std::time::Duration::from_secs(4),
                 protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                 protocol_level: mqtt3::PROTOCOL_LEVEL,
+                properties: Default::default(),
             },
         )),
         common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(mqtt3::proto::ConnAck {
             session_present: false,
             return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+            properties: Default::default(),
         })),
         common::TestConnectionStep::Receives(mqtt3::proto::Packet::Subscribe(
             mqtt3::proto::Subscribe {
@@ -126,7 +135,9 @@ fn server_publishes_at_least_once() {
                 subscribe_to: vec![mqtt3::proto::SubscribeTo {
                     topic_filter: "topic1".to_owned(),
                     qos: mqtt3::proto::QoS::AtLeastOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }],
+                properties: mqtt3::proto::Properties::default(),
             },
         )),
         common::TestConnectionStep::Sends(mqtt3::proto::Packet::SubAck(mqtt3::proto::SubAck {
@@ -145,9 +156,12 @@ fn server_publishes_at_least_once() {
             retain: false,
             topic_name: "topic1".to_owned(),
             payload: [0x01, 0x02, 0x03][..].into(),
+            properties: Default::default(),
         })),
         common::TestConnectionStep::Receives(mqtt3::proto::Packet::PubAck(mqtt3::proto::PubAck {
             packet_identifier: mqtt3::proto::PacketIdentifier::new(2).unwrap(),
+            reason_code: mqtt3::proto::ReasonCode::SUCCESS,
+            properties: mqtt3::proto::Properties::default(),
         })),
         common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(mqtt3::proto::PingReq)),
         common::TestConnectionStep::Sends(mqtt3::proto::Packet::PingResp(mqtt3::proto::PingResp)),
@@ -165,6 +179,7 @@ fn server_publishes_at_least_once() {
         .subscribe(mqtt3::proto::SubscribeTo {
             topic_filter: "topic1".to_owned(),
             qos: mqtt3::proto::QoS::AtLeastOnce,
+            options: mqtt3::proto::SubscriptionOptions::default(),
         })
         .unwrap();
 
@@ -179,6 +194,7 @@ fn server_publishes_at_least_once() {
                 mqtt3::proto::SubscribeTo {
                     topic_filter: "topic1".to_owned(),
                     qos: mqtt3::proto::QoS::AtLeastOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 },
             )]),
             mqtt3::Event::Publication(mqtt3::ReceivedPublication {
@@ -215,12 +231,14 @@ fn server_publishes_at_least_once_with_reconnect_before_publish() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: false,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: Default::default(),
                 },
             )),
         ],
@@ -236,12 +254,14 @@ fn server_publishes_at_least_once_with_reconnect_before_publish() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: true,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::Subscribe(
@@ -250,7 +270,9 @@ fn server_publishes_at_least_once_with_reconnect_before_publish() {
                     subscribe_to: vec![mqtt3::proto::SubscribeTo {
                         topic_filter: "topic1".to_owned(),
                         qos: mqtt3::proto::QoS::AtLeastOnce,
+                        options: mqtt3::proto::SubscriptionOptions::default(),
                     }],
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::SubAck(mqtt3::proto::SubAck {
@@ -272,12 +294,14 @@ fn server_publishes_at_least_once_with_reconnect_before_publish() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: true,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(
@@ -295,11 +319,14 @@ fn server_publishes_at_least_once_with_reconnect_before_publish() {
                     retain: false,
                     topic_name: "topic1".to_owned(),
                     payload: [0x01, 0x02, 0x03][..].into(),
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::PubAck(
                 mqtt3::proto::PubAck {
                     packet_identifier: mqtt3::proto::PacketIdentifier::new(1).unwrap(),
+                    reason_code: mqtt3::proto::ReasonCode::SUCCESS,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(
@@ -323,6 +350,7 @@ fn server_publishes_at_least_once_with_reconnect_before_publish() {
         .subscribe(mqtt3::proto::SubscribeTo {
             topic_filter: "topic1".to_owned(),
             qos: mqtt3::proto::QoS::AtLeastOnce,
+            options: mqtt3::proto::SubscriptionOptions::default(),
         })
         .unwrap();
 
@@ -340,6 +368,7 @@ fn server_publishes_at_least_once_with_reconnect_before_publish() {
                 mqtt3::proto::SubscribeTo {
                     topic_filter: "topic1".to_owned(),
                     qos: mqtt3::proto::QoS::AtLeastOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 },
             )]),
             mqtt3::Event::NewConnection {
@@ -379,12 +408,14 @@ fn server_publishes_at_least_once_with_reconnect_before_ack() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: false,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: Default::default(),
                 },
             )),
         ],
@@ -400,12 +431,14 @@ fn server_publishes_at_least_once_with_reconnect_before_ack() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: true,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::Subscribe(
@@ -414,7 +447,9 @@ fn server_publishes_at_least_once_with_reconnect_before_ack() {
                     subscribe_to: vec![mqtt3::proto::SubscribeTo {
                         topic_filter: "topic1".to_owned(),
                         qos: mqtt3::proto::QoS::AtLeastOnce,
+                        options: mqtt3::proto::SubscriptionOptions::default(),
                     }],
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::SubAck(mqtt3::proto::SubAck {
@@ -432,6 +467,7 @@ fn server_publishes_at_least_once_with_reconnect_before_ack() {
                     retain: false,
                     topic_name: "topic1".to_owned(),
                     payload: [0x01, 0x02, 0x03][..].into(),
+                    properties: Default::default(),
                 },
             )),
         ],
@@ -447,12 +483,14 @@ fn server_publishes_at_least_once_with_reconnect_before_ack() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: true,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(
@@ -470,11 +508,14 @@ fn server_publishes_at_least_once_with_reconnect_before_ack() {
                     retain: false,
                     topic_name: "topic1".to_owned(),
                     payload: [0x01, 0x02, 0x03][..].into(),
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::PubAck(
                 mqtt3::proto::PubAck {
                     packet_identifier: mqtt3::proto::PacketIdentifier::new(1).unwrap(),
+                    reason_code: mqtt3::proto::ReasonCode::SUCCESS,
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(
@@ -498,6 +539,7 @@ fn server_publishes_at_least_once_with_reconnect_before_ack() {
         .subscribe(mqtt3::proto::SubscribeTo {
             topic_filter: "topic1".to_owned(),
             qos: mqtt3::proto::QoS::AtLeastOnce,
+            options: mqtt3::proto::SubscriptionOptions::default(),
         })
         .unwrap();
 
@@ -515,6 +557,7 @@ fn server_publishes_at_least_once_with_reconnect_before_ack() {
                 mqtt3::proto::SubscribeTo {
                     topic_filter: "topic1".to_owned(),
                     qos: mqtt3::proto::QoS::AtLeastOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 },
             )]),
             mqtt3::Event::Publication(mqtt3::ReceivedPublication {
@@ -560,11 +603,13 @@ fn should_reject_invalid_publications() {
                 keep_alive: std::time::Duration::from_secs(4),
                 protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                 protocol_level: mqtt3::PROTOCOL_LEVEL,
+                properties: Default::default(),
             },
         )),
         common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(mqtt3::proto::ConnAck {
             session_present: false,
             return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+            properties: Default::default(),
         })),
         common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(mqtt3::proto::PingReq)),
         common::TestConnectionStep::Sends(mqtt3::proto::Packet::PingResp(mqtt3::proto::PingResp)),
@@ -588,6 +633,7 @@ fn should_reject_invalid_publications() {
         qos: mqtt3::proto::QoS::AtMostOnce,
         retain: false,
         payload: Default::default(),
+        properties: Default::default(),
     });
 
     common::verify_client_events(
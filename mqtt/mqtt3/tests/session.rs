@@ -0,0 +1,179 @@
+#![allow(clippy::let_unit_value)]
+
+use mqtt3::SessionStore;
+
+mod common;
+
+#[test]
+fn client_resumes_session_from_store() {
+    let store = mqtt3::MemoryStore::new();
+
+    // The first client sends a publication but goes away before the server acks it.
+    let mut runtime = tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .expect("couldn't initialize tokio runtime");
+
+    let (io_source, done) = common::IoSource::new(vec![vec![
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::Connect(
+            mqtt3::proto::Connect {
+                username: None,
+                password: None,
+                will: None,
+                client_id: mqtt3::proto::ClientId::IdWithExistingSession("client_id".to_owned()),
+                keep_alive: std::time::Duration::from_secs(4),
+                protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
+                protocol_level: mqtt3::PROTOCOL_LEVEL,
+                properties: Default::default(),
+            },
+        )),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(mqtt3::proto::ConnAck {
+            session_present: false,
+            return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+            properties: Default::default(),
+        })),
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::Publish(
+            mqtt3::proto::Publish {
+                packet_identifier_dup_qos: mqtt3::proto::PacketIdentifierDupQoS::AtLeastOnce(
+                    mqtt3::proto::PacketIdentifier::new(1).unwrap(),
+                    false,
+                ),
+                retain: false,
+                topic_name: "topic1".to_owned(),
+                payload: [0x01, 0x02, 0x03][..].into(),
+                properties: Default::default(),
+            },
+        )),
+    ]]);
+
+    let mut client = mqtt3::Client::with_session_store(
+        "client_id".to_owned(),
+        None,
+        None,
+        io_source,
+        std::time::Duration::from_secs(0),
+        std::time::Duration::from_secs(4),
+        store.clone(),
+    )
+    .unwrap();
+    let _ = client.publish(mqtt3::proto::Publication {
+        topic_name: "topic1".to_owned(),
+        qos: mqtt3::proto::QoS::AtLeastOnce,
+        retain: false,
+        payload: [0x01, 0x02, 0x03][..].into(),
+        properties: Default::default(),
+    });
+
+    common::verify_client_events(
+        &mut runtime,
+        client,
+        vec![mqtt3::Event::NewConnection {
+            reset_session: true,
+        }],
+    );
+
+    let () = runtime
+        .block_on(done)
+        .expect("connection broken while there were still steps remaining on the server");
+    drop(runtime);
+
+    // A new client created with the same store resends the unacked publication,
+    // and continues with the next packet identifier for new publications.
+    let mut runtime = tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .expect("couldn't initialize tokio runtime");
+
+    let (io_source, done) = common::IoSource::new(vec![vec![
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::Connect(
+            mqtt3::proto::Connect {
+                username: None,
+                password: None,
+                will: None,
+                client_id: mqtt3::proto::ClientId::IdWithExistingSession("client_id".to_owned()),
+                keep_alive: std::time::Duration::from_secs(4),
+                protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
+                protocol_level: mqtt3::PROTOCOL_LEVEL,
+                properties: Default::default(),
+            },
+        )),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(mqtt3::proto::ConnAck {
+            session_present: true,
+            return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+            properties: Default::default(),
+        })),
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::Publish(
+            mqtt3::proto::Publish {
+                packet_identifier_dup_qos: mqtt3::proto::PacketIdentifierDupQoS::AtLeastOnce(
+                    mqtt3::proto::PacketIdentifier::new(1).unwrap(),
+                    true,
+                ),
+                retain: false,
+                topic_name: "topic1".to_owned(),
+                payload: [0x01, 0x02, 0x03][..].into(),
+                properties: Default::default(),
+            },
+        )),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::PubAck(mqtt3::proto::PubAck {
+            packet_identifier: mqtt3::proto::PacketIdentifier::new(1).unwrap(),
+            reason_code: mqtt3::proto::ReasonCode::SUCCESS,
+            properties: mqtt3::proto::Properties::default(),
+        })),
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::Publish(
+            mqtt3::proto::Publish {
+                packet_identifier_dup_qos: mqtt3::proto::PacketIdentifierDupQoS::AtLeastOnce(
+                    mqtt3::proto::PacketIdentifier::new(2).unwrap(),
+                    false,
+                ),
+                retain: false,
+                topic_name: "topic2".to_owned(),
+                payload: [0x04][..].into(),
+                properties: Default::default(),
+            },
+        )),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::PubAck(mqtt3::proto::PubAck {
+            packet_identifier: mqtt3::proto::PacketIdentifier::new(2).unwrap(),
+            reason_code: mqtt3::proto::ReasonCode::SUCCESS,
+            properties: mqtt3::proto::Properties::default(),
+        })),
+    ]]);
+
+    let mut client = mqtt3::Client::with_session_store(
+        "client_id".to_owned(),
+        None,
+        None,
+        io_source,
+        std::time::Duration::from_secs(0),
+        std::time::Duration::from_secs(4),
+        store.clone(),
+    )
+    .unwrap();
+    let publish = client.publish(mqtt3::proto::Publication {
+        topic_name: "topic2".to_owned(),
+        qos: mqtt3::proto::QoS::AtLeastOnce,
+        retain: false,
+        payload: [0x04][..].into(),
+        properties: Default::default(),
+    });
+
+    common::verify_client_events(
+        &mut runtime,
+        client,
+        vec![mqtt3::Event::NewConnection {
+            reset_session: false,
+        }],
+    );
+
+    let () = runtime
+        .block_on(done)
+        .expect("connection broken while there were still steps remaining on the server");
+    let () = runtime.block_on(publish).unwrap();
+    drop(runtime);
+
+    let mut store = store;
+    let session = store.load().unwrap().expect("session was saved");
+    assert_eq!(session.publications, vec![]);
+    assert_eq!(session.waiting_to_be_acked, vec![]);
+}
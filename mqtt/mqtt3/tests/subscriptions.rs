@@ -21,12 +21,14 @@ fn server_generated_id_must_always_resubscribe() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: false,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::Subscribe(
@@ -36,16 +38,20 @@ fn server_generated_id_must_always_resubscribe() {
                         mqtt3::proto::SubscribeTo {
                             topic_filter: "topic1".to_string(),
                             qos: mqtt3::proto::QoS::AtMostOnce,
+                            options: mqtt3::proto::SubscriptionOptions::default(),
                         },
                         mqtt3::proto::SubscribeTo {
                             topic_filter: "topic2".to_string(),
                             qos: mqtt3::proto::QoS::AtLeastOnce,
+                            options: mqtt3::proto::SubscriptionOptions::default(),
                         },
                         mqtt3::proto::SubscribeTo {
                             topic_filter: "topic3".to_string(),
                             qos: mqtt3::proto::QoS::ExactlyOnce,
+                            options: mqtt3::proto::SubscriptionOptions::default(),
                         },
                     ],
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::SubAck(mqtt3::proto::SubAck {
@@ -73,12 +79,14 @@ fn server_generated_id_must_always_resubscribe() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: false,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::Subscribe(
@@ -88,16 +96,20 @@ fn server_generated_id_must_always_resubscribe() {
                         mqtt3::proto::SubscribeTo {
                             topic_filter: "topic1".to_string(),
                             qos: mqtt3::proto::QoS::AtMostOnce,
+                            options: mqtt3::proto::SubscriptionOptions::default(),
                         },
                         mqtt3::proto::SubscribeTo {
                             topic_filter: "topic2".to_string(),
                             qos: mqtt3::proto::QoS::AtLeastOnce,
+                            options: mqtt3::proto::SubscriptionOptions::default(),
                         },
                         mqtt3::proto::SubscribeTo {
                             topic_filter: "topic3".to_string(),
                             qos: mqtt3::proto::QoS::ExactlyOnce,
+                            options: mqtt3::proto::SubscriptionOptions::default(),
                         },
                     ],
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::SubAck(mqtt3::proto::SubAck {
@@ -129,18 +141,21 @@ fn server_generated_id_must_always_resubscribe() {
         .subscribe(mqtt3::proto::SubscribeTo {
             topic_filter: "topic1".to_string(),
             qos: mqtt3::proto::QoS::AtMostOnce,
+            options: mqtt3::proto::SubscriptionOptions::default(),
         })
         .unwrap();
     client
         .subscribe(mqtt3::proto::SubscribeTo {
             topic_filter: "topic2".to_string(),
             qos: mqtt3::proto::QoS::AtLeastOnce,
+            options: mqtt3::proto::SubscriptionOptions::default(),
         })
         .unwrap();
     client
         .subscribe(mqtt3::proto::SubscribeTo {
             topic_filter: "topic3".to_string(),
             qos: mqtt3::proto::QoS::ExactlyOnce,
+            options: mqtt3::proto::SubscriptionOptions::default(),
         })
         .unwrap();
 
@@ -155,14 +170,17 @@ fn server_generated_id_must_always_resubscribe() {
                 mqtt3::SubscriptionUpdateEvent::Subscribe(mqtt3::proto::SubscribeTo {
                     topic_filter: "topic1".to_string(),
                     qos: mqtt3::proto::QoS::AtMostOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }),
                 mqtt3::SubscriptionUpdateEvent::Subscribe(mqtt3::proto::SubscribeTo {
                     topic_filter: "topic2".to_string(),
                     qos: mqtt3::proto::QoS::AtLeastOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }),
                 mqtt3::SubscriptionUpdateEvent::Subscribe(mqtt3::proto::SubscribeTo {
                     topic_filter: "topic3".to_string(),
                     qos: mqtt3::proto::QoS::ExactlyOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }),
             ]),
             mqtt3::Event::NewConnection {
@@ -172,14 +190,17 @@ fn server_generated_id_must_always_resubscribe() {
                 mqtt3::SubscriptionUpdateEvent::Subscribe(mqtt3::proto::SubscribeTo {
                     topic_filter: "topic1".to_string(),
                     qos: mqtt3::proto::QoS::AtMostOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }),
                 mqtt3::SubscriptionUpdateEvent::Subscribe(mqtt3::proto::SubscribeTo {
                     topic_filter: "topic2".to_string(),
                     qos: mqtt3::proto::QoS::AtLeastOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }),
                 mqtt3::SubscriptionUpdateEvent::Subscribe(mqtt3::proto::SubscribeTo {
                     topic_filter: "topic3".to_string(),
                     qos: mqtt3::proto::QoS::ExactlyOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }),
             ]),
             mqtt3::Event::NewConnection {
@@ -189,14 +210,17 @@ fn server_generated_id_must_always_resubscribe() {
                 mqtt3::SubscriptionUpdateEvent::Subscribe(mqtt3::proto::SubscribeTo {
                     topic_filter: "topic1".to_string(),
                     qos: mqtt3::proto::QoS::AtMostOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }),
                 mqtt3::SubscriptionUpdateEvent::Subscribe(mqtt3::proto::SubscribeTo {
                     topic_filter: "topic2".to_string(),
                     qos: mqtt3::proto::QoS::AtLeastOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }),
                 mqtt3::SubscriptionUpdateEvent::Subscribe(mqtt3::proto::SubscribeTo {
                     topic_filter: "topic3".to_string(),
                     qos: mqtt3::proto::QoS::ExactlyOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }),
             ]),
         ],
@@ -228,12 +252,14 @@ fn client_id_should_not_resubscribe_when_session_is_present() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: false,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::Subscribe(
@@ -243,16 +269,20 @@ fn client_id_should_not_resubscribe_when_session_is_present() {
                         mqtt3::proto::SubscribeTo {
                             topic_filter: "topic1".to_string(),
                             qos: mqtt3::proto::QoS::AtMostOnce,
+                            options: mqtt3::proto::SubscriptionOptions::default(),
                         },
                         mqtt3::proto::SubscribeTo {
                             topic_filter: "topic2".to_string(),
                             qos: mqtt3::proto::QoS::AtLeastOnce,
+                            options: mqtt3::proto::SubscriptionOptions::default(),
                         },
                         mqtt3::proto::SubscribeTo {
                             topic_filter: "topic3".to_string(),
                             qos: mqtt3::proto::QoS::ExactlyOnce,
+                            options: mqtt3::proto::SubscriptionOptions::default(),
                         },
                     ],
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::SubAck(mqtt3::proto::SubAck {
@@ -282,6 +312,7 @@ fn client_id_should_not_resubscribe_when_session_is_present() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
@@ -291,6 +322,7 @@ fn client_id_should_not_resubscribe_when_session_is_present() {
                     // So this second session will still have `session_present == false`
                     session_present: false,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::Subscribe(
@@ -300,16 +332,20 @@ fn client_id_should_not_resubscribe_when_session_is_present() {
                         mqtt3::proto::SubscribeTo {
                             topic_filter: "topic1".to_string(),
                             qos: mqtt3::proto::QoS::AtMostOnce,
+                            options: mqtt3::proto::SubscriptionOptions::default(),
                         },
                         mqtt3::proto::SubscribeTo {
                             topic_filter: "topic2".to_string(),
                             qos: mqtt3::proto::QoS::AtLeastOnce,
+                            options: mqtt3::proto::SubscriptionOptions::default(),
                         },
                         mqtt3::proto::SubscribeTo {
                             topic_filter: "topic3".to_string(),
                             qos: mqtt3::proto::QoS::ExactlyOnce,
+                            options: mqtt3::proto::SubscriptionOptions::default(),
                         },
                     ],
+                    properties: mqtt3::proto::Properties::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::SubAck(mqtt3::proto::SubAck {
@@ -339,12 +375,14 @@ fn client_id_should_not_resubscribe_when_session_is_present() {
                     keep_alive: std::time::Duration::from_secs(4),
                     protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                     protocol_level: mqtt3::PROTOCOL_LEVEL,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
                 mqtt3::proto::ConnAck {
                     session_present: true,
                     return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+                    properties: Default::default(),
                 },
             )),
             common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(
@@ -368,18 +406,21 @@ fn client_id_should_not_resubscribe_when_session_is_present() {
         .subscribe(mqtt3::proto::SubscribeTo {
             topic_filter: "topic1".to_string(),
             qos: mqtt3::proto::QoS::AtMostOnce,
+            options: mqtt3::proto::SubscriptionOptions::default(),
         })
         .unwrap();
     client
         .subscribe(mqtt3::proto::SubscribeTo {
             topic_filter: "topic2".to_string(),
             qos: mqtt3::proto::QoS::AtLeastOnce,
+            options: mqtt3::proto::SubscriptionOptions::default(),
         })
         .unwrap();
     client
         .subscribe(mqtt3::proto::SubscribeTo {
             topic_filter: "topic3".to_string(),
             qos: mqtt3::proto::QoS::ExactlyOnce,
+            options: mqtt3::proto::SubscriptionOptions::default(),
         })
         .unwrap();
 
@@ -394,14 +435,17 @@ fn client_id_should_not_resubscribe_when_session_is_present() {
                 mqtt3::SubscriptionUpdateEvent::Subscribe(mqtt3::proto::SubscribeTo {
                     topic_filter: "topic1".to_string(),
                     qos: mqtt3::proto::QoS::AtMostOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }),
                 mqtt3::SubscriptionUpdateEvent::Subscribe(mqtt3::proto::SubscribeTo {
                     topic_filter: "topic2".to_string(),
                     qos: mqtt3::proto::QoS::AtLeastOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }),
                 mqtt3::SubscriptionUpdateEvent::Subscribe(mqtt3::proto::SubscribeTo {
                     topic_filter: "topic3".to_string(),
                     qos: mqtt3::proto::QoS::ExactlyOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }),
             ]),
             mqtt3::Event::NewConnection {
@@ -411,14 +455,17 @@ fn client_id_should_not_resubscribe_when_session_is_present() {
                 mqtt3::SubscriptionUpdateEvent::Subscribe(mqtt3::proto::SubscribeTo {
                     topic_filter: "topic1".to_string(),
                     qos: mqtt3::proto::QoS::AtMostOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }),
                 mqtt3::SubscriptionUpdateEvent::Subscribe(mqtt3::proto::SubscribeTo {
                     topic_filter: "topic2".to_string(),
                     qos: mqtt3::proto::QoS::AtLeastOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }),
                 mqtt3::SubscriptionUpdateEvent::Subscribe(mqtt3::proto::SubscribeTo {
                     topic_filter: "topic3".to_string(),
                     qos: mqtt3::proto::QoS::ExactlyOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }),
             ]),
             mqtt3::Event::NewConnection {
@@ -450,11 +497,13 @@ fn should_combine_pending_subscription_updates() {
                 keep_alive: std::time::Duration::from_secs(4),
                 protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
                 protocol_level: mqtt3::PROTOCOL_LEVEL,
+                properties: Default::default(),
             },
         )),
         common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(mqtt3::proto::ConnAck {
             session_present: false,
             return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+            properties: Default::default(),
         })),
         common::TestConnectionStep::Receives(mqtt3::proto::Packet::Subscribe(
             mqtt3::proto::Subscribe {
@@ -463,12 +512,15 @@ fn should_combine_pending_subscription_updates() {
                     mqtt3::proto::SubscribeTo {
                         topic_filter: "topic1".to_string(),
                         qos: mqtt3::proto::QoS::AtLeastOnce,
+                        options: mqtt3::proto::SubscriptionOptions::default(),
                     },
                     mqtt3::proto::SubscribeTo {
                         topic_filter: "topic3".to_string(),
                         qos: mqtt3::proto::QoS::ExactlyOnce,
+                        options: mqtt3::proto::SubscriptionOptions::default(),
                     },
                 ],
+                properties: mqtt3::proto::Properties::default(),
             },
         )),
         common::TestConnectionStep::Sends(mqtt3::proto::Packet::SubAck(mqtt3::proto::SubAck {
@@ -494,24 +546,28 @@ fn should_combine_pending_subscription_updates() {
         .subscribe(mqtt3::proto::SubscribeTo {
             topic_filter: "topic1".to_string(),
             qos: mqtt3::proto::QoS::AtMostOnce,
+            options: mqtt3::proto::SubscriptionOptions::default(),
         })
         .unwrap();
     client
         .subscribe(mqtt3::proto::SubscribeTo {
             topic_filter: "topic2".to_string(),
             qos: mqtt3::proto::QoS::AtLeastOnce,
+            options: mqtt3::proto::SubscriptionOptions::default(),
         })
         .unwrap();
     client
         .subscribe(mqtt3::proto::SubscribeTo {
             topic_filter: "topic3".to_string(),
             qos: mqtt3::proto::QoS::ExactlyOnce,
+            options: mqtt3::proto::SubscriptionOptions::default(),
         })
         .unwrap();
     client
         .subscribe(mqtt3::proto::SubscribeTo {
             topic_filter: "topic1".to_string(),
             qos: mqtt3::proto::QoS::AtLeastOnce,
+            options: mqtt3::proto::SubscriptionOptions::default(),
         })
         .unwrap();
     client.unsubscribe("topic2".to_string()).unwrap();
@@ -527,10 +583,12 @@ fn should_combine_pending_subscription_updates() {
                 mqtt3::SubscriptionUpdateEvent::Subscribe(mqtt3::proto::SubscribeTo {
                     topic_filter: "topic1".to_string(),
                     qos: mqtt3::proto::QoS::AtLeastOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }),
                 mqtt3::SubscriptionUpdateEvent::Subscribe(mqtt3::proto::SubscribeTo {
                     topic_filter: "topic3".to_string(),
                     qos: mqtt3::proto::QoS::ExactlyOnce,
+                    options: mqtt3::proto::SubscriptionOptions::default(),
                 }),
             ]),
         ],
@@ -556,7 +614,7 @@ fn should_reject_invalid_subscriptions() {
 
     let too_large_topic_filter = "a".repeat(usize::from(u16::max_value()) + 1);
 
-    match client.subscribe(mqtt3::proto::SubscribeTo { topic_filter: too_large_topic_filter.clone(), qos: mqtt3::proto::QoS::AtMostOnce }) {
+    match client.subscribe(mqtt3::proto::SubscribeTo { topic_filter: too_large_topic_filter.clone(), qos: mqtt3::proto::QoS::AtMostOnce, options: mqtt3::proto::SubscriptionOptions::default() }) {
 		Err(mqtt3::UpdateSubscriptionError::EncodePacket(_, mqtt3::proto::EncodeError::StringTooLarge(_))) => (),
 		result => panic!("expected client.subscribe() to fail with EncodePacket(StringTooLarge) but it returned {:?}", result),
 	}
@@ -0,0 +1,195 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use tracing::warn;
+
+use mqtt_broker::{
+    AdminRequest, AdminResponse, BrokerConfig, BrokerHandle, Error, RetainedInfo, SessionInfo,
+};
+
+pub fn command() -> App<'static, 'static> {
+    let client_id = Arg::with_name("client_id")
+        .value_name("CLIENT_ID")
+        .required(true);
+
+    SubCommand::with_name("admin")
+        .about("Inspects and manages a running broker")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("socket")
+                .short("s")
+                .long("socket")
+                .value_name("PATH")
+                .help("Sets the admin socket of the broker, defaults to the one in the config file")
+                .takes_value(true),
+        )
+        .subcommand(SubCommand::with_name("sessions").about("Lists sessions"))
+        .subcommand(SubCommand::with_name("retained").about("Lists retained messages"))
+        .subcommand(
+            SubCommand::with_name("delete-retained")
+                .about("Deletes the retained message of a topic")
+                .arg(Arg::with_name("topic").value_name("TOPIC").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("disconnect")
+                .about("Drops the connection of a client")
+                .arg(client_id.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("purge")
+                .about("Drops the connection of a client and removes its session")
+                .arg(client_id),
+        )
+}
+
+/// Runs an `admin` subcommand against the admin endpoint of a running broker.
+pub async fn run(config: &BrokerConfig, matches: &ArgMatches<'_>) -> Result<(), Error> {
+    let socket = matches
+        .value_of("socket")
+        .map(PathBuf::from)
+        .or_else(|| config.admin().map(|admin| admin.socket().to_path_buf()))
+        .ok_or_else(|| {
+            admin_error(
+                io::ErrorKind::NotFound,
+                "no admin socket configured, use --socket".to_string(),
+            )
+        })?;
+
+    let value = |matches: Option<&ArgMatches<'_>>, name| {
+        matches
+            .and_then(|m| m.value_of(name))
+            .unwrap_or_default()
+            .to_string()
+    };
+    let (request, not_found) = match matches.subcommand() {
+        ("sessions", _) => (AdminRequest::ListSessions, String::new()),
+        ("retained", _) => (AdminRequest::ListRetained, String::new()),
+        ("delete-retained", m) => {
+            let topic_name = value(m, "topic");
+            let not_found = format!("no retained message for topic \"{}\"", topic_name);
+            (AdminRequest::DeleteRetained { topic_name }, not_found)
+        }
+        ("disconnect", m) => {
+            let client_id = value(m, "client_id");
+            let not_found = format!("{} is not connected", client_id);
+            (AdminRequest::DisconnectSession { client_id }, not_found)
+        }
+        ("purge", m) => {
+            let client_id = value(m, "client_id");
+            let not_found = format!("no session for {}", client_id);
+            (AdminRequest::PurgeSession { client_id }, not_found)
+        }
+        (name, _) => unreachable!("unknown admin subcommand {}", name),
+    };
+
+    match send(&socket, &request).await? {
+        AdminResponse::Sessions { sessions } => print_sessions(&sessions),
+        AdminResponse::Retained { retained } => print_retained(&retained),
+        AdminResponse::Done => println!("done."),
+        AdminResponse::NotFound => return Err(admin_error(io::ErrorKind::NotFound, not_found)),
+        AdminResponse::Error { message } => {
+            return Err(admin_error(io::ErrorKind::Other, message));
+        }
+    }
+    Ok(())
+}
+
+/// Serves admin requests if the admin endpoint is configured.
+pub fn serve(config: &BrokerConfig, broker_handle: BrokerHandle) {
+    if let Some(admin) = config.admin() {
+        imp::serve(admin.socket().to_path_buf(), broker_handle);
+    }
+}
+
+fn print_sessions(sessions: &[SessionInfo]) {
+    println!(
+        "{:<32} {:<32} {:<10} {:>8} {:>8}  SUBSCRIPTIONS",
+        "CLIENT ID", "AUTH ID", "STATE", "QUEUED", "INFLIGHT"
+    );
+    for session in sessions {
+        let state = if !session.connected() {
+            "offline"
+        } else if session.persistent() {
+            "persistent"
+        } else {
+            "transient"
+        };
+
+        println!(
+            "{:<32} {:<32} {:<10} {:>8} {:>8}  {}",
+            session.client_id(),
+            session.auth_id().unwrap_or("-"),
+            state,
+            session.queued_messages(),
+            session.inflight_messages(),
+            session.subscriptions().join(", ")
+        );
+    }
+}
+
+fn print_retained(retained: &[RetainedInfo]) {
+    println!("{:<64} {:>3} {:>10}", "TOPIC", "QOS", "SIZE");
+    for publication in retained {
+        println!(
+            "{:<64} {:>3} {:>10}",
+            publication.topic_name(),
+            publication.qos(),
+            publication.payload_size()
+        );
+    }
+}
+
+fn admin_error(kind: io::ErrorKind, message: String) -> Error {
+    Error::Admin(io::Error::new(kind, message))
+}
+
+async fn send(socket: &Path, request: &AdminRequest) -> Result<AdminResponse, Error> {
+    imp::send(socket, request).await
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::path::{Path, PathBuf};
+
+    use super::warn;
+    use mqtt_broker::{AdminRequest, AdminResponse, BrokerHandle, Error};
+
+    pub(super) fn serve(socket: PathBuf, broker_handle: BrokerHandle) {
+        tokio::spawn(async move {
+            if let Err(e) = mqtt_broker::serve_admin(socket, broker_handle).await {
+                warn!(message = "failed to serve admin requests", error=%e);
+            }
+        });
+    }
+
+    pub(super) async fn send(
+        socket: &Path,
+        request: &AdminRequest,
+    ) -> Result<AdminResponse, Error> {
+        mqtt_broker::admin_request(socket, request).await
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    use super::{admin_error, warn};
+    use mqtt_broker::{AdminRequest, AdminResponse, BrokerHandle, Error};
+
+    pub(super) fn serve(_socket: PathBuf, _broker_handle: BrokerHandle) {
+        warn!("the admin endpoint is only supported on unix");
+    }
+
+    pub(super) async fn send(
+        _socket: &Path,
+        _request: &AdminRequest,
+    ) -> Result<AdminResponse, Error> {
+        Err(admin_error(
+            io::ErrorKind::Other,
+            "the admin endpoint is only supported on unix".to_string(),
+        ))
+    }
+}
@@ -2,8 +2,10 @@ use std::fmt;
 
 use mqtt_broker::Error;
 
+pub mod admin;
 pub mod shutdown;
 pub mod snapshot;
+pub mod state;
 
 pub struct Terminate {
     error: Error,
@@ -1,13 +1,17 @@
-use std::{convert::TryInto, env, io};
+use std::{
+    convert::TryInto,
+    io,
+    path::{Component, Path, PathBuf},
+};
 
 use clap::{crate_description, crate_name, crate_version, App, Arg};
-use futures_util::pin_mut;
+use futures_util::{pin_mut, TryFutureExt};
 use mqtt_broker::*;
 use tokio::time::{Duration, Instant};
 use tracing::{info, warn, Level};
 use tracing_subscriber::{fmt, EnvFilter};
 
-use mqttd::{shutdown, snapshot, Terminate};
+use mqttd::{admin, shutdown, snapshot, state, Terminate};
 
 #[tokio::main]
 async fn main() -> Result<(), Terminate> {
@@ -24,47 +28,129 @@ async fn main() -> Result<(), Terminate> {
 }
 
 async fn run() -> Result<(), Error> {
-    let config = create_app()
-        .get_matches()
+    let matches = create_app().get_matches();
+    if let ("state", Some(matches)) = matches.subcommand() {
+        return state::run(matches);
+    }
+
+    let config = matches
         .value_of("config")
         .map_or(BrokerConfig::new(), BrokerConfig::from_file)
         .map_err(InitializeBrokerError::LoadConfiguration)?;
 
+    if let ("admin", Some(matches)) = matches.subcommand() {
+        return admin::run(&config, matches).await;
+    }
+
+    match config.persistence().map(|p| p.file_path().to_string()) {
+        Some(file_path) => {
+            let persistor = FilePersistor::new(file_path, ConsolidatedStateFormat::default());
+            serve(config, persistor).await
+        }
+        None => {
+            info!("Persistence is not configured, state will not be saved");
+            serve(config, NullPersistor).await
+        }
+    }
+}
+
+async fn serve<P>(config: BrokerConfig, mut persistor: P) -> Result<(), Error>
+where
+    P: Persist<Error = PersistError> + Send + 'static,
+{
     // Setup the shutdown handle
     let shutdown = shutdown::shutdown();
     pin_mut!(shutdown);
 
     // Setup the snapshotter
-    let mut persistor = FilePersistor::new(
-        env::current_dir().expect("can't get cwd").join("state"),
-        ConsolidatedStateFormat::default(),
-    );
     info!("Loading state...");
     let state = persistor.load().await?.unwrap_or_else(BrokerState::default);
-    let broker = BrokerBuilder::default()
-        .authenticator(|_| Ok(Some(AuthId::Anonymous)))
-        .authorizer(|_| Ok(true))
-        .state(state)
-        .build();
-    info!("state loaded.");
-
     let snapshotter = Snapshotter::new(persistor);
     let snapshot_handle = snapshotter.snapshot_handle();
     let mut shutdown_handle = snapshotter.shutdown_handle();
     let join_handle = tokio::spawn(snapshotter.run());
 
-    // Tick the snapshotter
-    let tick = tick_snapshot(
-        Duration::from_secs(5 * 60),
-        broker.handle(),
-        snapshot_handle.clone(),
+    let authenticator = CertificateAuthenticator::new(
+        config.authentication().certificate_identity(),
+        config.authentication().allow_anonymous(),
     );
+    let authorizer = match config.authorization() {
+        Some(authorization) => {
+            let authorizer = PolicyAuthorizer::from_file(authorization.policy())
+                .map_err(InitializeBrokerError::LoadPolicy)?;
+            let reload = authorizer.reload_on_change(
+                authorization.policy().to_path_buf(),
+                authorization.reload_interval(),
+            );
+            tokio::spawn(reload);
+            authorizer
+        }
+        None => PolicyAuthorizer::new(Policy::allow_all()),
+    };
+    let broker = BrokerBuilder::default()
+        .authenticator(authenticator)
+        .authorizer(authorizer)
+        .state(state)
+        .config(config.clone())
+        .snapshot_handle(snapshot_handle.clone())
+        .build();
+    info!("state loaded.");
+
+    // Tick the snapshotter
+    if let Some(persistence) = config.persistence() {
+        let tick = tick_snapshot(
+            persistence.time_interval(),
+            broker.handle(),
+            snapshot_handle.clone(),
+        );
+        tokio::spawn(tick);
+    }
+
+    // Tick the broker statistics
+    let tick = tick_stats(config.statistics().interval(), broker.handle());
     tokio::spawn(tick);
 
+    // Expose broker statistics to Prometheus
+    if let Some(prometheus) = config.statistics().prometheus() {
+        let metrics = serve_metrics(prometheus.address().to_string(), broker.stats());
+        tokio::spawn(async move {
+            if let Err(e) = metrics.await {
+                warn!(message = "failed to serve metrics", error=%e);
+            }
+        });
+    }
+
+    // Serve admin requests
+    admin::serve(&config, broker.handle());
+
     // Signal the snapshotter
     let snapshot = snapshot::snapshot(broker.handle(), snapshot_handle.clone());
     tokio::spawn(snapshot);
 
+    // Start configured bridges
+    let mut bridges = Vec::new();
+    for bridge_config in config.bridges() {
+        let bridge = match config.persistence() {
+            Some(persistence) => {
+                let dir = bridge_dir(persistence.file_path(), bridge_config.name())?;
+                let persistor = FilePersistor::new(dir, ConsolidatedStateFormat::default());
+                let bridge = Bridge::new(bridge_config.clone(), persistor)?;
+                (
+                    bridge.shutdown_handle(),
+                    tokio::spawn(bridge.run().map_ok(drop)),
+                )
+            }
+            None => {
+                let bridge = Bridge::new(bridge_config.clone(), NullPersistor)?;
+                (
+                    bridge.shutdown_handle(),
+                    tokio::spawn(bridge.run().map_ok(drop)),
+                )
+            }
+        };
+        bridges.push(bridge);
+    }
+
     // Create configured transports
     let transports = config
         .transports()
@@ -77,6 +163,13 @@ async fn run() -> Result<(), Error> {
         .serve(transports, shutdown)
         .await?;
 
+    // Stop bridges
+    for (mut shutdown_handle, join_handle) in bridges {
+        shutdown_handle.shutdown().await?;
+        join_handle.await??;
+    }
+    info!("bridges shutdown.");
+
     // Stop snapshotting
     shutdown_handle.shutdown().await?;
     let mut persistor = join_handle.await?;
@@ -90,6 +183,15 @@ async fn run() -> Result<(), Error> {
     Ok(())
 }
 
+/// Keeps the state of a bridge in its own directory next to the broker state.
+fn bridge_dir(file_path: &str, name: &str) -> Result<PathBuf, InitializeBrokerError> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(Path::new(file_path).join("bridges").join(name)),
+        _ => Err(InitializeBrokerError::BridgeName(name.to_string())),
+    }
+}
+
 async fn tick_snapshot(
     period: Duration,
     mut broker_handle: BrokerHandle,
@@ -111,6 +213,21 @@ async fn tick_snapshot(
     }
 }
 
+async fn tick_stats(period: Duration, mut broker_handle: BrokerHandle) {
+    info!("Publishing broker statistics every {:?}", period);
+    let start = Instant::now() + period;
+    let mut interval = tokio::time::interval_at(start, period);
+    loop {
+        interval.tick().await;
+        if let Err(e) = broker_handle
+            .send(Message::System(SystemEvent::PublishStats))
+            .await
+        {
+            warn!(message = "failed to tick the broker statistics", error=%e);
+        }
+    }
+}
+
 fn create_app() -> App<'static, 'static> {
     App::new(crate_name!())
         .version(crate_version!())
@@ -123,4 +240,6 @@ fn create_app() -> App<'static, 'static> {
                 .help("Sets a custom config file")
                 .takes_value(true),
         )
+        .subcommand(admin::command())
+        .subcommand(state::command())
 }